@@ -0,0 +1,340 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Parsing of the attributes shared by the derives.
+
+use syn::spanned::Spanned;
+use syn::{Attribute, LitStr};
+
+use crate::case::RenameRule;
+
+/// Values of an attribute, like `#[mapping(endpoint = "/value", param = "id")]`, and the flags
+/// without a value, like `#[astarte_interface(allow_unset)]`.
+#[derive(Default)]
+pub struct AttrArgs {
+    args: Vec<(syn::Path, LitStr)>,
+    flags: Vec<syn::Path>,
+}
+
+impl AttrArgs {
+    pub fn parse(attrs: &[Attribute], name: &str) -> syn::Result<Self> {
+        let mut nested = Vec::new();
+
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident(name)) {
+            let syn::Meta::List(list) = attr.parse_meta()? else {
+                return Err(syn::Error::new(
+                    attr.span(),
+                    format!("expected #[{name}(...)]"),
+                ));
+            };
+
+            nested.extend(list.nested);
+        }
+
+        Self::from_nested(nested, name)
+    }
+
+    /// Parses the arguments of an attribute macro, like the ones of `#[astarte_interface(...)]`.
+    pub fn from_nested(
+        nested: impl IntoIterator<Item = syn::NestedMeta>,
+        name: &str,
+    ) -> syn::Result<Self> {
+        let mut args = Vec::new();
+        let mut flags = Vec::new();
+
+        for nested in nested {
+            match nested {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(lit),
+                    ..
+                })) => args.push((path, lit)),
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) => flags.push(path),
+                nested => {
+                    return Err(syn::Error::new(
+                        nested.span(),
+                        format!("expected a string value in #[{name}(...)]"),
+                    ))
+                }
+            }
+        }
+
+        Ok(Self { args, flags })
+    }
+
+    /// Takes the value of an argument.
+    pub fn take(&mut self, name: &str) -> Option<LitStr> {
+        let idx = self.args.iter().position(|(path, _)| path.is_ident(name))?;
+
+        Some(self.args.remove(idx).1)
+    }
+
+    /// Takes a flag, returns `true` if it's set.
+    pub fn take_flag(&mut self, name: &str) -> bool {
+        let Some(idx) = self.flags.iter().position(|path| path.is_ident(name)) else {
+            return false;
+        };
+
+        self.flags.remove(idx);
+
+        true
+    }
+
+    /// Returns an error on each argument not taken.
+    pub fn finish(self) -> syn::Result<()> {
+        let mut errors = Errors::default();
+
+        for path in self.args.iter().map(|(path, _)| path).chain(&self.flags) {
+            errors.push(syn::Error::new(path.span(), "unrecognized argument"));
+        }
+
+        errors.finish()
+    }
+}
+
+/// Errors of the fields and the attributes, reported together so all of them can be fixed in a
+/// single compilation.
+#[derive(Default)]
+pub struct Errors {
+    error: Option<syn::Error>,
+}
+
+impl Errors {
+    pub fn push(&mut self, err: syn::Error) {
+        match &mut self.error {
+            Some(error) => error.combine(err),
+            None => self.error = Some(err),
+        }
+    }
+
+    /// Returns the value of the result, keeping the error.
+    pub fn collect<T>(&mut self, res: syn::Result<T>) -> Option<T> {
+        res.map_err(|err| self.push(err)).ok()
+    }
+
+    /// Returns the errors collected.
+    pub fn finish(self) -> syn::Result<()> {
+        self.error.map_or(Ok(()), Err)
+    }
+}
+
+/// Direction of the conversion, choosing between the `serialize` and `deserialize` names of a
+/// serde rename like `#[serde(rename(serialize = "...", deserialize = "..."))]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerdeDirection {
+    Serialize,
+    Deserialize,
+}
+
+impl SerdeDirection {
+    fn name(self) -> &'static str {
+        match self {
+            SerdeDirection::Serialize => "serialize",
+            SerdeDirection::Deserialize => "deserialize",
+        }
+    }
+}
+
+/// Returns the value of a serde argument, like `rename` in `#[serde(rename = "...")]`.
+///
+/// The serde attributes that can't be parsed are ignored, they are checked by serde.
+fn serde_arg(attrs: &[Attribute], name: &str, direction: SerdeDirection) -> Option<LitStr> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("serde"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(syn::Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|nested| match nested {
+            syn::NestedMeta::Meta(meta) if meta.path().is_ident(name) => Some(meta),
+            _ => None,
+        })
+        .find_map(|meta| match meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                lit: syn::Lit::Str(lit),
+                ..
+            }) => Some(lit),
+            syn::Meta::List(list) => list.nested.into_iter().find_map(|nested| match nested {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(lit),
+                    ..
+                })) if path.is_ident(direction.name()) => Some(lit),
+                _ => None,
+            }),
+            _ => None,
+        })
+}
+
+/// Returns the rule of `#[serde(rename_all = "...")]` on a struct.
+pub fn serde_rename_all(
+    attrs: &[Attribute],
+    direction: SerdeDirection,
+) -> syn::Result<Option<RenameRule>> {
+    serde_arg(attrs, "rename_all", direction)
+        .map(|rename_all| {
+            RenameRule::from_str(&rename_all.value())
+                .map_err(|err| syn::Error::new(rename_all.span(), err))
+        })
+        .transpose()
+}
+
+/// Returns the name of `#[serde(rename = "...")]` on a field.
+pub fn serde_rename(attrs: &[Attribute], direction: SerdeDirection) -> Option<String> {
+    serde_arg(attrs, "rename", direction).map(|rename| rename.value())
+}
+
+/// Maximum number of levels of an endpoint.
+const MAX_LEVELS: usize = 64;
+
+/// Checks if a level is a letter or an underscore, followed by letters, digits or underscores.
+fn is_valid_level(level: &str) -> bool {
+    let mut chars = level.chars();
+
+    matches!(chars.next(), Some(first) if first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|chr| chr.is_ascii_alphanumeric() || chr == '_')
+}
+
+/// Checks that an endpoint is a valid Astarte path, like `/%{sensor_id}/value`, returning the
+/// names of its parameters.
+pub fn validate_endpoint(endpoint: &LitStr) -> syn::Result<Vec<String>> {
+    let value = endpoint.value();
+    let err = |message: String| syn::Error::new(endpoint.span(), message);
+
+    let Some(levels) = value.strip_prefix('/') else {
+        return Err(err(format!(
+            "the endpoint {value:?} must start with a slash"
+        )));
+    };
+
+    let mut params = Vec::new();
+    for (idx, level) in levels.split('/').enumerate() {
+        if idx == MAX_LEVELS {
+            return Err(err(format!(
+                "the endpoint {value:?} has more than {MAX_LEVELS} levels"
+            )));
+        }
+
+        if level.is_empty() {
+            return Err(err(format!("the endpoint {value:?} has an empty level")));
+        }
+
+        let Some(param) = level.strip_prefix("%{") else {
+            if !is_valid_level(level) {
+                return Err(err(format!(
+                    "invalid level {level:?} of the endpoint {value:?}, it must be a letter or an underscore followed by letters, digits or underscores"
+                )));
+            }
+
+            continue;
+        };
+
+        let Some(name) = param.strip_suffix('}') else {
+            return Err(err(format!(
+                "the parameter {level:?} of the endpoint {value:?} must cover the whole level, like \"%{{name}}\""
+            )));
+        };
+
+        if !is_valid_level(name) {
+            return Err(err(format!(
+                "invalid parameter name {name:?} of the endpoint {value:?}, it must be a letter or an underscore followed by letters, digits or underscores"
+            )));
+        }
+
+        if params.iter().any(|param| param == name) {
+            return Err(err(format!(
+                "the parameter {name:?} is repeated in the endpoint {value:?}"
+            )));
+        }
+
+        params.push(name.to_string());
+    }
+
+    Ok(params)
+}
+
+/// Checks that the name of an object field is a valid level of an endpoint.
+pub fn validate_level(level: &LitStr) -> syn::Result<()> {
+    let value = level.value();
+
+    if is_valid_level(&value) {
+        return Ok(());
+    }
+
+    Err(syn::Error::new(
+        level.span(),
+        format!(
+            "invalid field name {value:?}, it must be a letter or an underscore followed by letters, digits or underscores"
+        ),
+    ))
+}
+
+/// Checks that a parameter is one of the endpoint.
+pub fn validate_param(param: &LitStr, params: &[String]) -> syn::Result<()> {
+    let value = param.value();
+
+    if params.contains(&value) {
+        return Ok(());
+    }
+
+    Err(syn::Error::new(
+        param.span(),
+        format!("the parameter {value:?} is not in the endpoint"),
+    ))
+}
+
+/// Returns the type argument of a generic type with the name, like `T` of `Option<T>`.
+pub fn generic_argument<'a>(ty: &'a syn::Type, name: &str) -> Option<&'a syn::Type> {
+    let syn::Type::Path(type_path) = ty else {
+        return None;
+    };
+
+    if type_path.qself.is_some() {
+        return None;
+    }
+
+    let segment = type_path.path.segments.last()?;
+    if segment.ident != name {
+        return None;
+    }
+
+    let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+
+    arguments.args.iter().find_map(|argument| match argument {
+        syn::GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    })
+}
+
+/// Returns the element type of a `Vec` converted from an array mapping, `Vec<u8>` is a binary
+/// blob and not an array.
+pub fn array_element(ty: &syn::Type) -> Option<&syn::Type> {
+    generic_argument(ty, "Vec")
+        .filter(|item| !matches!(item, syn::Type::Path(path) if path.path.is_ident("u8")))
+}
+
+/// Checks if the type of a field is an `Option`.
+pub fn is_option(ty: &syn::Type) -> bool {
+    generic_argument(ty, "Option").is_some()
+}
@@ -25,9 +25,10 @@
 use std::fmt::{self, Debug, Display};
 
 /// The different possible ways to change case of fields in a struct.
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
 pub enum RenameRule {
     /// Do not rename.
+    #[default]
     None,
     /// Rename to "lowercase" style.
     LowerCase,
@@ -102,6 +103,35 @@ impl RenameRule {
                 .replace('_', "-"),
         }
     }
+
+    /// Apply a renaming rule to an enum variant, returning the version expected in the source.
+    pub fn apply_to_variant(&self, variant: &str) -> String {
+        match *self {
+            RenameRule::None | RenameRule::PascalCase => variant.to_owned(),
+            RenameRule::LowerCase => variant.to_ascii_lowercase(),
+            RenameRule::UpperCase => variant.to_ascii_uppercase(),
+            RenameRule::CamelCase => variant[..1].to_ascii_lowercase() + &variant[1..],
+            RenameRule::SnakeCase => {
+                let mut snake = String::new();
+                for (i, ch) in variant.char_indices() {
+                    if i > 0 && ch.is_uppercase() {
+                        snake.push('_');
+                    }
+                    snake.push(ch.to_ascii_lowercase());
+                }
+                snake
+            }
+            RenameRule::ScreamingSnakeCase => RenameRule::SnakeCase
+                .apply_to_variant(variant)
+                .to_ascii_uppercase(),
+            RenameRule::KebabCase => RenameRule::SnakeCase
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+            RenameRule::ScreamingKebabCase => RenameRule::ScreamingSnakeCase
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -162,4 +192,42 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn rename_variants() {
+        for &(original, lower, upper, camel, snake, screaming, kebab, screaming_kebab) in &[
+            (
+                "Outcome", "outcome", "OUTCOME", "outcome", "outcome", "OUTCOME", "outcome",
+                "OUTCOME",
+            ),
+            (
+                "VeryTasty",
+                "verytasty",
+                "VERYTASTY",
+                "veryTasty",
+                "very_tasty",
+                "VERY_TASTY",
+                "very-tasty",
+                "VERY-TASTY",
+            ),
+            ("A", "a", "A", "a", "a", "A", "a", "A"),
+            ("Z42", "z42", "Z42", "z42", "z42", "Z42", "z42", "Z42"),
+        ] {
+            assert_eq!(RenameRule::None.apply_to_variant(original), original);
+            assert_eq!(RenameRule::LowerCase.apply_to_variant(original), lower);
+            assert_eq!(RenameRule::UpperCase.apply_to_variant(original), upper);
+            assert_eq!(RenameRule::PascalCase.apply_to_variant(original), original);
+            assert_eq!(RenameRule::CamelCase.apply_to_variant(original), camel);
+            assert_eq!(RenameRule::SnakeCase.apply_to_variant(original), snake);
+            assert_eq!(
+                RenameRule::ScreamingSnakeCase.apply_to_variant(original),
+                screaming
+            );
+            assert_eq!(RenameRule::KebabCase.apply_to_variant(original), kebab);
+            assert_eq!(
+                RenameRule::ScreamingKebabCase.apply_to_variant(original),
+                screaming_kebab
+            );
+        }
+    }
 }
@@ -0,0 +1,134 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Derive the conversion of a fieldless enum to and from an Astarte string.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::DeriveInput;
+
+use crate::attr::{AttrArgs, Errors};
+use crate::case::RenameRule;
+
+/// Returns the string of a variant.
+fn variant_name(variant: &syn::Variant, rename_rule: RenameRule) -> syn::Result<String> {
+    let mut args = AttrArgs::parse(&variant.attrs, "astarte_enum")?;
+    let rename = args.take("rename");
+    args.finish()?;
+
+    if !matches!(variant.fields, syn::Fields::Unit) {
+        return Err(syn::Error::new(
+            variant.fields.span(),
+            "the variants of an AstarteEnum can't have fields",
+        ));
+    }
+
+    Ok(rename.map_or_else(
+        || rename_rule.apply_to_variant(&variant.ident.to_string()),
+        |rename| rename.value(),
+    ))
+}
+
+pub fn expand(ast: DeriveInput) -> syn::Result<TokenStream> {
+    let mut args = AttrArgs::parse(&ast.attrs, "astarte_enum")?;
+    let rename_all = args.take("rename_all");
+    args.finish()?;
+
+    let rename_rule = match &rename_all {
+        Some(rename_all) => RenameRule::from_str(&rename_all.value())
+            .map_err(|err| syn::Error::new(rename_all.span(), err))?,
+        None => RenameRule::None,
+    };
+
+    let syn::Data::Enum(en) = &ast.data else {
+        return Err(syn::Error::new(
+            ast.ident.span(),
+            "AstarteEnum can only be derived for an enum",
+        ));
+    };
+
+    let enum_name = &ast.ident;
+    let mut errors = Errors::default();
+    let mut names: Vec<String> = Vec::new();
+    let mut to_string = Vec::new();
+    let mut from_string = Vec::new();
+    for variant in &en.variants {
+        let Some(name) = errors.collect(variant_name(variant, rename_rule)) else {
+            continue;
+        };
+
+        if names.contains(&name) {
+            errors.push(syn::Error::new(
+                variant.span(),
+                format!("the string \"{name}\" is already used by another variant"),
+            ));
+
+            continue;
+        }
+
+        let ident = &variant.ident;
+        to_string.push(quote! { #enum_name::#ident => #name, });
+        from_string.push(quote! { #name => Ok(#enum_name::#ident), });
+        names.push(name);
+    }
+    errors.finish()?;
+
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics From<&#enum_name #ty_generics> for astarte_device_sdk::types::AstarteType
+            #where_clause
+        {
+            fn from(value: &#enum_name #ty_generics) -> Self {
+                let value = match value {
+                    #(#to_string)*
+                };
+
+                astarte_device_sdk::types::AstarteType::String(value.to_string())
+            }
+        }
+
+        impl #impl_generics From<#enum_name #ty_generics> for astarte_device_sdk::types::AstarteType
+            #where_clause
+        {
+            fn from(value: #enum_name #ty_generics) -> Self {
+                Self::from(&value)
+            }
+        }
+
+        impl #impl_generics std::convert::TryFrom<astarte_device_sdk::types::AstarteType>
+            for #enum_name #ty_generics #where_clause
+        {
+            type Error = astarte_device_sdk::types::TypeError;
+
+            fn try_from(value: astarte_device_sdk::types::AstarteType) -> Result<Self, Self::Error> {
+                let astarte_device_sdk::types::AstarteType::String(value) = value else {
+                    return Err(astarte_device_sdk::types::TypeError::Conversion);
+                };
+
+                match value.as_str() {
+                    #(#from_string)*
+                    _ => Err(astarte_device_sdk::types::TypeError::Conversion),
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,817 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Derive the `FromEvent` trait for structs and enums, and the `IntoEvent` trait for enums.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{DeriveInput, LitStr};
+
+use crate::attr::{
+    array_element, generic_argument, is_option, serde_rename, serde_rename_all, validate_endpoint,
+    validate_level, validate_param, AttrArgs, Errors, SerdeDirection,
+};
+use crate::case::RenameRule;
+
+/// Parses the path of a conversion function, set with `try_from_with` or `try_into_with`.
+pub fn conversion(args: &mut AttrArgs, name: &str) -> syn::Result<Option<syn::Path>> {
+    args.take(name).map(|function| function.parse()).transpose()
+}
+
+/// Parses the value of a missing field, set with `default` or `default = "expr"`.
+pub fn default_value(args: &mut AttrArgs) -> syn::Result<Option<TokenStream>> {
+    let flag = args.take_flag("default");
+
+    match args.take("default") {
+        Some(expr) if flag => Err(syn::Error::new(
+            expr.span(),
+            "the default is set more than once",
+        )),
+        Some(expr) => {
+            let expr: syn::Expr = expr.parse()?;
+
+            Ok(Some(quote! { #expr }))
+        }
+        None => Ok(flag.then(|| quote! { std::default::Default::default() })),
+    }
+}
+
+/// Returns the function converting a value of the type, the arrays are converted element by
+/// element.
+pub fn try_from(ty: &syn::Type, try_from_with: Option<syn::Path>) -> TokenStream {
+    if let Some(try_from_with) = try_from_with {
+        return quote! { #try_from_with };
+    }
+
+    if array_element(ty).is_some() {
+        return quote! {
+            |value| astarte_device_sdk::event::array_with(value, std::convert::TryFrom::try_from)
+        };
+    }
+
+    quote! { std::convert::TryFrom::try_from }
+}
+
+/// Parses the `interface_type`, returns `true` for a property interface.
+fn is_properties(interface_type: &Option<LitStr>) -> syn::Result<bool> {
+    match interface_type {
+        Some(ty) if ty.value() == "properties" => Ok(true),
+        Some(ty) if ty.value() == "datastream" => Ok(false),
+        Some(ty) => Err(syn::Error::new(
+            ty.span(),
+            "the interface type must be \"datastream\" or \"properties\"",
+        )),
+        None => Ok(false),
+    }
+}
+
+pub fn expand(ast: DeriveInput) -> syn::Result<TokenStream> {
+    let mut args = AttrArgs::parse(&ast.attrs, "from_event")?;
+
+    if args.take_flag("dispatch") {
+        if let Some(interface) = args.take("interface") {
+            return Err(syn::Error::new(
+                interface.span(),
+                "the interfaces of a dispatch enum are the ones of its variants",
+            ));
+        }
+        args.finish()?;
+
+        return expand_dispatch(&ast);
+    }
+
+    let interface = args.take("interface").ok_or_else(|| {
+        syn::Error::new(
+            ast.ident.span(),
+            "missing #[from_event(interface = \"...\")]",
+        )
+    })?;
+    let path = args.take("path");
+    let aggregation = args.take("aggregation");
+    let interface_type = args.take("interface_type");
+    let rename_all = args.take("rename_all");
+    let use_serde_attrs = args.take_flag("use_serde_attrs");
+    args.finish()?;
+
+    let properties = is_properties(&interface_type)?;
+
+    let body = match &ast.data {
+        syn::Data::Struct(st) => {
+            if let Some(aggregation) = aggregation.filter(|a| a.value() != "object") {
+                return Err(syn::Error::new(
+                    aggregation.span(),
+                    "a struct is converted from an object aggregate",
+                ));
+            }
+
+            if let Some(interface_type) = interface_type.filter(|_| properties) {
+                return Err(syn::Error::new(
+                    interface_type.span(),
+                    "a property interface can only be converted to an enum",
+                ));
+            }
+
+            let path = path.ok_or_else(|| {
+                syn::Error::new(ast.ident.span(), "missing #[from_event(path = \"...\")]")
+            })?;
+            let params = validate_endpoint(&path)?;
+
+            // the explicit rule takes precedence over the serde one
+            let rename_rule = match &rename_all {
+                Some(rename_all) => RenameRule::from_str(&rename_all.value())
+                    .map_err(|err| syn::Error::new(rename_all.span(), err))?,
+                None if use_serde_attrs => {
+                    serde_rename_all(&ast.attrs, SerdeDirection::Deserialize)?.unwrap_or_default()
+                }
+                None => RenameRule::None,
+            };
+
+            expand_object(&path, &params, &st.fields, rename_rule, use_serde_attrs)?
+        }
+        syn::Data::Enum(en) => {
+            if let Some(aggregation) = aggregation.filter(|a| a.value() != "individual") {
+                return Err(syn::Error::new(
+                    aggregation.span(),
+                    "an enum is converted from an individual value",
+                ));
+            }
+
+            if let Some(rename_all) = rename_all {
+                return Err(syn::Error::new(
+                    rename_all.span(),
+                    "the endpoints of an enum are set on each variant with #[mapping(endpoint = \"...\")]",
+                ));
+            }
+
+            if let Some(path) = path {
+                return Err(syn::Error::new(
+                    path.span(),
+                    "the path of an enum is set on each variant with #[mapping(endpoint = \"...\")]",
+                ));
+            }
+
+            if use_serde_attrs {
+                return Err(syn::Error::new(
+                    ast.ident.span(),
+                    "the serde attributes are only used for the fields of a struct",
+                ));
+            }
+
+            expand_individual(&interface, en, properties)?
+        }
+        syn::Data::Union(_) => {
+            return Err(syn::Error::new(
+                ast.ident.span(),
+                "FromEvent can't be derived for an union",
+            ))
+        }
+    };
+
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics astarte_device_sdk::event::FromEvent for #name #ty_generics #where_clause {
+            type Err = astarte_device_sdk::event::FromEventError;
+
+            fn from_event(
+                event: astarte_device_sdk::AstarteDeviceDataEvent,
+            ) -> Result<Self, Self::Err> {
+                astarte_device_sdk::event::check_interface(&event, #interface)?;
+
+                #body
+            }
+        }
+
+        impl #impl_generics astarte_device_sdk::event::FromInterface for #name #ty_generics #where_clause {
+            const INTERFACE: &'static str = #interface;
+        }
+    })
+}
+
+/// Routes the events to the variants of an enum, by the interface of their field.
+fn expand_dispatch(ast: &DeriveInput) -> syn::Result<TokenStream> {
+    let syn::Data::Enum(en) = &ast.data else {
+        return Err(syn::Error::new(
+            ast.ident.span(),
+            "only an enum can dispatch the events to its variants",
+        ));
+    };
+
+    let mut errors = Errors::default();
+    let mut routes = Vec::new();
+    for variant in &en.variants {
+        if let Some(route) = errors.collect(dispatch_variant(variant)) {
+            routes.push(route);
+        }
+    }
+    errors.finish()?;
+
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics astarte_device_sdk::event::FromEvent for #name #ty_generics #where_clause {
+            type Err = astarte_device_sdk::event::FromEventError;
+
+            fn from_event(
+                event: astarte_device_sdk::AstarteDeviceDataEvent,
+            ) -> Result<Self, Self::Err> {
+                #(#routes)*
+
+                Err(astarte_device_sdk::event::FromEventError::UnknownInterface(
+                    event.interface,
+                ))
+            }
+        }
+    })
+}
+
+/// Returns the conversion of the events on the interface of the field of a variant.
+fn dispatch_variant(variant: &syn::Variant) -> syn::Result<TokenStream> {
+    if let Some(attr) = variant
+        .attrs
+        .iter()
+        .find(|attr| attr.path.is_ident("mapping"))
+    {
+        return Err(syn::Error::new(
+            attr.span(),
+            "the variants of a dispatch enum are converted by their field",
+        ));
+    }
+
+    let ty = match &variant.fields {
+        syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => &fields.unnamed[0].ty,
+        fields => {
+            return Err(syn::Error::new(
+                fields.span(),
+                "the variant must have as only field a type deriving FromEvent",
+            ))
+        }
+    };
+
+    let ident = &variant.ident;
+
+    Ok(quote! {
+        if event.interface == <#ty as astarte_device_sdk::event::FromInterface>::INTERFACE {
+            return <#ty as astarte_device_sdk::event::FromEvent>::from_event(event)
+                .map(Self::#ident)
+                .map_err(std::convert::From::from);
+        }
+    })
+}
+
+fn expand_object(
+    path: &LitStr,
+    params: &[String],
+    fields: &syn::Fields,
+    rename_rule: RenameRule,
+    use_serde_attrs: bool,
+) -> syn::Result<TokenStream> {
+    let syn::Fields::Named(fields) = fields else {
+        return Err(syn::Error::new(
+            fields.span(),
+            "FromEvent can only be derived for a struct with named fields",
+        ));
+    };
+
+    let mut errors = Errors::default();
+    let mut values = Vec::new();
+    let mut has_params = false;
+    for field in &fields.named {
+        if let Some((value, is_param)) =
+            errors.collect(object_field(field, params, rename_rule, use_serde_attrs))
+        {
+            values.push(value);
+            has_params |= is_param;
+        }
+    }
+    errors.finish()?;
+
+    let params = if has_params {
+        quote! { params }
+    } else {
+        quote! { _ }
+    };
+
+    Ok(quote! {
+        let endpoint = astarte_device_sdk::endpoint::EndpointPattern::new(#path)?;
+
+        let #params = endpoint.matches(&event.path).ok_or_else(|| {
+            astarte_device_sdk::event::FromEventError::Path {
+                interface: event.interface.clone(),
+                path: event.path.clone(),
+            }
+        })?;
+
+        let mut object = match event.data {
+            astarte_device_sdk::Aggregation::Object(object) => object,
+            _ => {
+                return Err(astarte_device_sdk::event::FromEventError::Object {
+                    interface: event.interface,
+                    path: event.path,
+                });
+            }
+        };
+
+        Ok(Self {
+            #(#values,)*
+        })
+    })
+}
+
+/// Returns the value of a field of the struct, and if it's a parameter of the path.
+fn object_field(
+    field: &syn::Field,
+    params: &[String],
+    rename_rule: RenameRule,
+    use_serde_attrs: bool,
+) -> syn::Result<(TokenStream, bool)> {
+    let mut args = AttrArgs::parse(&field.attrs, "mapping")?;
+    let param = args.take("param");
+    let endpoint = args.take("endpoint");
+    let timestamp = args.take_flag("timestamp");
+    let try_from_with = conversion(&mut args, "try_from_with")?;
+    let default = default_value(&mut args)?;
+    // the mapping is shared with IntoEvent
+    args.take("try_into_with");
+    args.finish()?;
+
+    let ident = field.ident.as_ref().expect("named field");
+
+    if default.is_some() && (param.is_some() || timestamp) {
+        return Err(syn::Error::new(
+            ident.span(),
+            "only the fields of the object can have a default",
+        ));
+    }
+
+    if timestamp {
+        if let Some(arg) = param.or(endpoint) {
+            return Err(syn::Error::new(
+                arg.span(),
+                "the timestamp can't be also a parameter or an endpoint",
+            ));
+        }
+
+        if let Some(try_from_with) = try_from_with {
+            return Err(syn::Error::new(
+                try_from_with.span(),
+                "the timestamp is not converted",
+            ));
+        }
+
+        // the explicit timestamp, or the reception time if it's not optional
+        let value = if is_option(&field.ty) {
+            quote! { event.metadata.timestamp }
+        } else {
+            quote! { event.metadata.timestamp.unwrap_or(event.metadata.received_at) }
+        };
+
+        return Ok((quote! { #ident: #value }, false));
+    }
+
+    let is_param = param.is_some();
+    let value = match (param, endpoint) {
+        (Some(param), None) => {
+            if let Some(try_from_with) = try_from_with {
+                return Err(syn::Error::new(
+                    try_from_with.span(),
+                    "a parameter is parsed with FromStr",
+                ));
+            }
+
+            validate_param(&param, params)?;
+
+            quote! { astarte_device_sdk::event::param(&params, #param)? }
+        }
+        (None, endpoint) => {
+            if let Some(endpoint) = &endpoint {
+                validate_level(endpoint)?;
+            }
+
+            // an explicit endpoint takes precedence over the serde rename and the rename rule
+            let key = endpoint
+                .map(|endpoint| endpoint.value())
+                .or_else(|| {
+                    serde_rename(&field.attrs, SerdeDirection::Deserialize)
+                        .filter(|_| use_serde_attrs)
+                })
+                .unwrap_or_else(|| rename_rule.apply_to_field(&ident.to_string()));
+
+            let ty = generic_argument(&field.ty, "Option").unwrap_or(&field.ty);
+            let convert = try_from(ty, try_from_with);
+
+            let optional = quote! {
+                astarte_device_sdk::event::optional_field_with(&mut object, #key, #convert)?
+            };
+
+            // the default of an `Option` field is the whole `Option`
+            match default {
+                Some(default) if is_option(&field.ty) => {
+                    quote! { #optional.map_or_else(|| #default, Some) }
+                }
+                Some(default) => quote! { #optional.unwrap_or_else(|| #default) },
+                None if is_option(&field.ty) => optional,
+                None => quote! {
+                    astarte_device_sdk::event::field_with(&mut object, #key, #convert)?
+                },
+            }
+        }
+        (Some(param), Some(_)) => {
+            return Err(syn::Error::new(
+                param.span(),
+                "a parameter can't be also an endpoint",
+            ))
+        }
+    };
+
+    Ok((quote! { #ident: #value }, is_param))
+}
+
+fn expand_individual(
+    interface: &LitStr,
+    en: &syn::DataEnum,
+    properties: bool,
+) -> syn::Result<TokenStream> {
+    // the value of a property is converted to a `Property`, since it can be unset
+    let (convert_with, unset) = if properties {
+        (
+            quote! { astarte_device_sdk::event::property_with },
+            Some(quote! { astarte_device_sdk::Aggregation::Unset => None, }),
+        )
+    } else {
+        (quote! { astarte_device_sdk::event::convert_with }, None)
+    };
+    let individual = if properties {
+        quote! { Some(value) }
+    } else {
+        quote! { value }
+    };
+
+    let mut errors = Errors::default();
+    let mut variants = Vec::new();
+    for variant in &en.variants {
+        if let Some(variant) =
+            errors.collect(individual_variant(variant, properties, &convert_with))
+        {
+            variants.push(variant);
+        }
+    }
+    errors.finish()?;
+
+    Ok(quote! {
+        let value = match event.data {
+            astarte_device_sdk::Aggregation::Individual(value) => #individual,
+            #unset
+            _ => {
+                return Err(astarte_device_sdk::event::FromEventError::Individual {
+                    interface: event.interface,
+                    path: event.path,
+                });
+            }
+        };
+
+        #(#variants)*
+
+        Err(astarte_device_sdk::event::FromEventError::Path {
+            interface: #interface.to_string(),
+            path: event.path,
+        })
+    })
+}
+
+/// Returns the conversion of the value of a variant, if the path matches its endpoint.
+fn individual_variant(
+    variant: &syn::Variant,
+    properties: bool,
+    convert_with: &TokenStream,
+) -> syn::Result<TokenStream> {
+    let mut args = AttrArgs::parse(&variant.attrs, "mapping")?;
+    let endpoint = args.take("endpoint").ok_or_else(|| {
+        syn::Error::new(
+            variant.ident.span(),
+            "missing #[mapping(endpoint = \"...\")]",
+        )
+    })?;
+    let param = args.take("param");
+    let try_from_with = conversion(&mut args, "try_from_with")?;
+    // the mapping is shared with IntoEvent
+    args.take("try_into_with");
+    args.finish()?;
+
+    let params = validate_endpoint(&endpoint)?;
+    if let Some(param) = &param {
+        validate_param(param, &params)?;
+    }
+
+    let ident = &variant.ident;
+
+    let syn::Fields::Unnamed(fields) = &variant.fields else {
+        return Err(syn::Error::new(
+            variant.span(),
+            "the variant must have the value as unnamed field",
+        ));
+    };
+
+    let expected = if param.is_some() { 2 } else { 1 };
+    if fields.unnamed.len() != expected {
+        return Err(syn::Error::new(
+            fields.span(),
+            if param.is_some() {
+                "the variant must have the parameter and the value as fields"
+            } else {
+                "the variant must have only the value as field"
+            },
+        ));
+    }
+
+    // the value is the last field, wrapped in a `Property` for the properties
+    let ty = &fields
+        .unnamed
+        .last()
+        .expect("checked the number of fields")
+        .ty;
+    let ty = generic_argument(ty, "Property")
+        .filter(|_| properties)
+        .unwrap_or(ty);
+    let convert = try_from(ty, try_from_with);
+
+    let (params, param) = match param {
+        Some(param) => (
+            quote! { params },
+            Some(quote! { astarte_device_sdk::event::param(&params, #param)?, }),
+        ),
+        None => (quote! { _ }, None),
+    };
+
+    Ok(quote! {
+        let endpoint = astarte_device_sdk::endpoint::EndpointPattern::new(#endpoint)?;
+        if let Some(#params) = endpoint.matches(&event.path) {
+            return Ok(Self::#ident(
+                #param
+                #convert_with(value, #endpoint, #convert)?,
+            ));
+        }
+    })
+}
+
+pub fn expand_into(ast: DeriveInput) -> syn::Result<TokenStream> {
+    let mut args = AttrArgs::parse(&ast.attrs, "into_event")?;
+
+    let interface = args.take("interface").ok_or_else(|| {
+        syn::Error::new(
+            ast.ident.span(),
+            "missing #[into_event(interface = \"...\")]",
+        )
+    })?;
+    let interface_type = args.take("interface_type");
+    args.finish()?;
+
+    let properties = is_properties(&interface_type)?;
+
+    let syn::Data::Enum(en) = &ast.data else {
+        return Err(syn::Error::new(
+            ast.ident.span(),
+            "IntoEvent can only be derived for an enum, the objects are sent with AstarteAggregate",
+        ));
+    };
+
+    let mut errors = Errors::default();
+    let mut variants = Vec::new();
+    for variant in &en.variants {
+        if let Some(variant) = errors.collect(into_variant(variant, properties)) {
+            variants.push(variant);
+        }
+    }
+    errors.finish()?;
+
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics astarte_device_sdk::event::IntoEvent for #name #ty_generics #where_clause {
+            fn into_event(
+                self,
+            ) -> Result<astarte_device_sdk::event::OutgoingEvent, astarte_device_sdk::error::Error>
+            {
+                let (path, data): (String, astarte_device_sdk::types::AstarteType) = match self {
+                    #(#variants)*
+                };
+
+                Ok(astarte_device_sdk::event::OutgoingEvent {
+                    interface: #interface.to_string(),
+                    path,
+                    data,
+                })
+            }
+        }
+    })
+}
+
+/// Returns the match arm of a variant, with the path and the value to send.
+fn into_variant(variant: &syn::Variant, properties: bool) -> syn::Result<TokenStream> {
+    let mut args = AttrArgs::parse(&variant.attrs, "mapping")?;
+    let endpoint = args.take("endpoint").ok_or_else(|| {
+        syn::Error::new(
+            variant.ident.span(),
+            "missing #[mapping(endpoint = \"...\")]",
+        )
+    })?;
+    let param = args.take("param");
+    let try_into_with = conversion(&mut args, "try_into_with")?;
+    // the mapping is shared with FromEvent
+    args.take("try_from_with");
+    args.finish()?;
+
+    let syn::Fields::Unnamed(fields) = &variant.fields else {
+        return Err(syn::Error::new(
+            variant.span(),
+            "the variant must have the value as unnamed field",
+        ));
+    };
+
+    let expected = if param.is_some() { 2 } else { 1 };
+    if fields.unnamed.len() != expected {
+        return Err(syn::Error::new(
+            fields.span(),
+            if param.is_some() {
+                "the variant must have the parameter and the value as fields"
+            } else {
+                "the variant must have only the value as field"
+            },
+        ));
+    }
+
+    validate_endpoint(&endpoint)?;
+    let path = format_path(&endpoint, param.as_ref())?;
+    let convert = try_into_with.map_or_else(
+        || quote! { std::convert::TryInto::try_into },
+        |try_into_with| quote! { #try_into_with },
+    );
+    let data = if properties {
+        quote! { astarte_device_sdk::event::unset_or_with(value, #convert)? }
+    } else {
+        quote! { #convert(value)? }
+    };
+
+    let ident = &variant.ident;
+    let variant = match param {
+        Some(_) => quote! {
+            Self::#ident(param, value) => (format!(#path, param), #data),
+        },
+        None => quote! {
+            Self::#ident(value) => (#path.to_string(), #data),
+        },
+    };
+
+    Ok(variant)
+}
+
+/// Returns the format string of the path of an endpoint, with the parameter as argument.
+fn format_path(endpoint: &LitStr, param: Option<&LitStr>) -> syn::Result<LitStr> {
+    let value = endpoint.value();
+    let mut found = false;
+
+    let levels = value.split('/').map(|level| {
+        let Some(name) = level
+            .strip_prefix("%{")
+            .and_then(|level| level.strip_suffix('}'))
+        else {
+            return Ok(level.to_string());
+        };
+
+        match param {
+            Some(param) if param.value() == name && !found => {
+                found = true;
+
+                Ok("{}".to_string())
+            }
+            _ => Err(syn::Error::new(
+                endpoint.span(),
+                format!(
+                    "the parameter {name} must be the one set with #[mapping(param = \"...\")]"
+                ),
+            )),
+        }
+    });
+
+    let path = levels.collect::<syn::Result<Vec<_>>>()?.join("/");
+
+    if let Some(param) = param.filter(|_| !found) {
+        return Err(syn::Error::new(
+            param.span(),
+            "the parameter is missing in the endpoint",
+        ));
+    }
+
+    Ok(LitStr::new(&path, endpoint.span()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_all_errors() {
+        let ast: DeriveInput = syn::parse_quote! {
+            #[from_event(interface = "com.example.Sensors", path = "/%{id}")]
+            struct Reading {
+                #[mapping(param = "id", endpoint = "id")]
+                id: String,
+                #[mapping(unknown, rename = "value")]
+                value: f64,
+                unit: String,
+            }
+        };
+
+        let err = expand(ast).unwrap_err();
+        let messages: Vec<String> = err.into_iter().map(|err| err.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            [
+                "a parameter can't be also an endpoint",
+                "unrecognized argument",
+                "unrecognized argument",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_endpoint_errors() {
+        let ast: DeriveInput = syn::parse_quote! {
+            #[from_event(interface = "com.example.Sensors", aggregation = "individual")]
+            enum Sensor {
+                #[mapping(endpoint = "value")]
+                NoSlash(f64),
+                #[mapping(endpoint = "/%{id/value", param = "id")]
+                Unclosed(String, f64),
+                #[mapping(endpoint = "/%{id}/%{id}")]
+                Repeated(f64),
+                #[mapping(endpoint = "/sensor//value")]
+                Empty(f64),
+                #[mapping(endpoint = "/sensor-1/value")]
+                InvalidLevel(f64),
+                #[mapping(endpoint = "/%{sensor}/value", param = "id")]
+                MissingParam(String, f64),
+                #[mapping(endpoint = "/%{sensor_id}/value", param = "sensor_id")]
+                Valid(String, f64),
+            }
+        };
+
+        let err = expand(ast).unwrap_err();
+        let messages: Vec<String> = err.into_iter().map(|err| err.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            [
+                "the endpoint \"value\" must start with a slash",
+                "the parameter \"%{id\" of the endpoint \"/%{id/value\" must cover the whole level, like \"%{name}\"",
+                "the parameter \"id\" is repeated in the endpoint \"/%{id}/%{id}\"",
+                "the endpoint \"/sensor//value\" has an empty level",
+                "invalid level \"sensor-1\" of the endpoint \"/sensor-1/value\", it must be a letter or an underscore followed by letters, digits or underscores",
+                "the parameter \"id\" is not in the endpoint",
+            ]
+        );
+
+        let ast: DeriveInput = syn::parse_quote! {
+            #[from_event(interface = "com.example.Sensors", path = "/%{id}")]
+            struct Reading {
+                #[mapping(param = "sensor_id")]
+                id: String,
+                #[mapping(endpoint = "/value")]
+                value: f64,
+            }
+        };
+
+        let err = expand(ast).unwrap_err();
+        let messages: Vec<String> = err.into_iter().map(|err| err.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            [
+                "the parameter \"sensor_id\" is not in the endpoint",
+                "invalid field name \"/value\", it must be a letter or an underscore followed by letters, digits or underscores",
+            ]
+        );
+    }
+}
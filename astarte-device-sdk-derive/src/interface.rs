@@ -0,0 +1,479 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Generate a typed module from the JSON of an interface.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use serde_json::Value;
+use syn::parse::{Parse, ParseStream};
+use syn::{Ident, LitStr, Token};
+
+/// Arguments of the macro, an optional name of the module and the path of the interface.
+pub struct InterfaceArgs {
+    name: Option<Ident>,
+    path: LitStr,
+}
+
+impl Parse for InterfaceArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = if input.peek(Ident) {
+            let name = input.parse()?;
+            input.parse::<Token![,]>()?;
+
+            Some(name)
+        } else {
+            None
+        };
+
+        let path = input.parse()?;
+        // Allow a trailing comma
+        let _ = input.parse::<Option<Token![,]>>()?;
+
+        Ok(Self { name, path })
+    }
+}
+
+/// Segment of an endpoint.
+enum Segment<'a> {
+    Static(&'a str),
+    Param(&'a str),
+}
+
+fn segments(endpoint: &str) -> impl Iterator<Item = Segment<'_>> {
+    endpoint
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            match segment
+                .strip_prefix("%{")
+                .and_then(|param| param.strip_suffix('}'))
+            {
+                Some(param) => Segment::Param(param),
+                None => Segment::Static(segment),
+            }
+        })
+}
+
+/// Converts a name like `DeviceDatastream` or `individual-datastream` to snake case.
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len());
+    let mut prev_lower = false;
+
+    for ch in name.chars() {
+        if ch.is_ascii_uppercase() {
+            if prev_lower {
+                snake.push('_');
+            }
+            snake.push(ch.to_ascii_lowercase());
+            prev_lower = false;
+        } else if ch.is_ascii_alphanumeric() {
+            snake.push(ch);
+            prev_lower = true;
+        } else {
+            if !snake.is_empty() && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            prev_lower = false;
+        }
+    }
+
+    let snake = snake.trim_end_matches('_');
+
+    if snake.starts_with(|ch: char| ch.is_ascii_digit()) {
+        format!("_{snake}")
+    } else {
+        snake.to_string()
+    }
+}
+
+/// Returns an identifier for the name, appending an underscore to the keywords.
+fn ident(name: &str) -> Ident {
+    syn::parse_str::<Ident>(name).unwrap_or_else(|_| format_ident!("{}_", name))
+}
+
+/// Rust type accepted by a mapping of the given type.
+fn mapping_type(mapping_type: &str) -> Option<TokenStream> {
+    let date_time = quote!(astarte_device_sdk::chrono::DateTime<astarte_device_sdk::chrono::Utc>);
+
+    let ty = match mapping_type {
+        "double" => quote!(f64),
+        "integer" => quote!(i32),
+        "boolean" => quote!(bool),
+        "longinteger" => quote!(i64),
+        "string" => quote!(&str),
+        "binaryblob" => quote!(std::vec::Vec<u8>),
+        "datetime" => date_time,
+        "doublearray" => quote!(std::vec::Vec<f64>),
+        "integerarray" => quote!(std::vec::Vec<i32>),
+        "booleanarray" => quote!(std::vec::Vec<bool>),
+        "longintegerarray" => quote!(std::vec::Vec<i64>),
+        "stringarray" => quote!(std::vec::Vec<std::string::String>),
+        "binaryblobarray" => quote!(std::vec::Vec<std::vec::Vec<u8>>),
+        "datetimearray" => quote!(std::vec::Vec<#date_time>),
+        _ => return None,
+    };
+
+    Some(ty)
+}
+
+/// Path of the data, with the parameters of the endpoint as arguments.
+struct PathArgs {
+    params: Vec<Ident>,
+    path: TokenStream,
+}
+
+impl PathArgs {
+    /// The `constant` is used as the path if the endpoint has no parameters.
+    fn new(endpoint: &str, constant: TokenStream) -> Self {
+        let mut params = Vec::new();
+        let mut format = String::new();
+
+        for segment in segments(endpoint) {
+            format.push('/');
+
+            match segment {
+                Segment::Static(segment) => format.push_str(segment),
+                Segment::Param(param) => {
+                    params.push(ident(&to_snake_case(param)));
+                    format.push_str("{}");
+                }
+            }
+        }
+
+        let path = if params.is_empty() {
+            constant
+        } else {
+            quote!(&std::format!(#format, #(#params),*))
+        };
+
+        Self { params, path }
+    }
+}
+
+struct Mapping<'a> {
+    endpoint: &'a str,
+    mapping_type: &'a str,
+    explicit_timestamp: bool,
+    allow_unset: bool,
+    description: Option<&'a str>,
+}
+
+impl<'a> Mapping<'a> {
+    fn new(value: &'a Value) -> Result<Self, String> {
+        let endpoint = value
+            .get("endpoint")
+            .and_then(Value::as_str)
+            .ok_or("mapping without an endpoint")?;
+        let mapping_type = value
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("mapping {endpoint} without a type"))?;
+
+        let flag = |key| value.get(key).and_then(Value::as_bool).unwrap_or(false);
+
+        Ok(Self {
+            endpoint,
+            mapping_type,
+            explicit_timestamp: flag("explicit_timestamp"),
+            allow_unset: flag("allow_unset"),
+            description: value.get("description").and_then(Value::as_str),
+        })
+    }
+
+    /// Name of the mapping, from the non parametric segments of the endpoint.
+    fn name(&self) -> String {
+        let name = segments(self.endpoint)
+            .filter_map(|segment| match segment {
+                Segment::Static(segment) => Some(to_snake_case(segment)),
+                Segment::Param(_) => None,
+            })
+            .collect::<Vec<_>>()
+            .join("_");
+
+        if name.is_empty() {
+            "value".to_string()
+        } else {
+            name
+        }
+    }
+}
+
+pub fn expand(args: InterfaceArgs) -> syn::Result<TokenStream> {
+    let span = args.path.span();
+    let err = |msg: String| syn::Error::new(span, msg);
+
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let path = PathBuf::from(manifest_dir).join(args.path.value());
+
+    let json = std::fs::read_to_string(&path).map_err(|e| {
+        err(format!(
+            "couldn't read the interface {}: {e}",
+            path.display()
+        ))
+    })?;
+    let interface: Value = serde_json::from_str(&json)
+        .map_err(|e| err(format!("invalid interface {}: {e}", path.display())))?;
+
+    let get_str = |key: &str| {
+        interface
+            .get(key)
+            .and_then(Value::as_str)
+            .ok_or_else(|| err(format!("missing {key} in the interface")))
+    };
+
+    let interface_name = get_str("interface_name")?;
+    let interface_type = get_str("type")?;
+    let ownership = get_str("ownership")?;
+    let object = interface.get("aggregation").and_then(Value::as_str) == Some("object");
+
+    let mappings = interface
+        .get("mappings")
+        .and_then(Value::as_array)
+        .filter(|mappings| !mappings.is_empty())
+        .ok_or_else(|| err("the interface has no mappings".to_string()))?
+        .iter()
+        .map(Mapping::new)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(err)?;
+
+    let mod_name = args
+        .name
+        .unwrap_or_else(|| ident(&to_snake_case(interface_name)));
+
+    let mut names = HashSet::new();
+    let mut constants = Vec::with_capacity(mappings.len());
+    for mapping in &mappings {
+        let name = mapping.name();
+        if !names.insert(name.clone()) {
+            return Err(err(format!(
+                "the endpoint {} has the same name of another mapping",
+                mapping.endpoint
+            )));
+        }
+
+        let constant = format_ident!("{}", name.to_ascii_uppercase());
+        let endpoint = mapping.endpoint;
+        let doc = mapping
+            .description
+            .map(|description| quote!(#[doc = #description]));
+
+        constants.push((
+            constant.clone(),
+            quote! {
+                #doc
+                pub const #constant: &str = #endpoint;
+            },
+        ));
+    }
+
+    let functions = if ownership != "device" {
+        // Server owned interfaces can only be received
+        Vec::new()
+    } else if object {
+        vec![object_functions(&mappings)]
+    } else {
+        mappings
+            .iter()
+            .zip(&constants)
+            .map(|(mapping, (constant, _))| {
+                individual_functions(mapping, constant, interface_type == "properties").ok_or_else(
+                    || {
+                        err(format!(
+                            "unsupported type {} of the endpoint {}",
+                            mapping.mapping_type, mapping.endpoint
+                        ))
+                    },
+                )
+            })
+            .collect::<syn::Result<_>>()?
+    };
+
+    let constants = constants.iter().map(|(_, constant)| constant);
+    let path = path.to_string_lossy();
+    let mod_doc = format!("Endpoints and send functions of the interface `{interface_name}`.");
+
+    Ok(quote! {
+        #[doc = #mod_doc]
+        pub mod #mod_name {
+            // Rebuild when the interface changes
+            const _: &str = include_str!(#path);
+
+            /// Name of the interface.
+            pub const INTERFACE: &str = #interface_name;
+
+            /// Endpoints of the interface mappings.
+            pub mod endpoints {
+                #(#constants)*
+            }
+
+            #(#functions)*
+        }
+    })
+}
+
+fn individual_functions(
+    mapping: &Mapping,
+    constant: &Ident,
+    property: bool,
+) -> Option<TokenStream> {
+    let ty = mapping_type(mapping.mapping_type)?;
+    let name = mapping.name();
+    let endpoint = mapping.endpoint;
+
+    let PathArgs { params, path } = PathArgs::new(endpoint, quote!(endpoints::#constant));
+    let params = &params;
+
+    let send = format_ident!("send_{}", name);
+    let send_doc = format!("Send the data on `{endpoint}`.");
+
+    let mut functions = quote! {
+        #[doc = #send_doc]
+        pub async fn #send<S>(
+            client: &astarte_device_sdk::AstarteDeviceSdk<S>,
+            #(#params: &str,)*
+            data: #ty,
+        ) -> Result<(), astarte_device_sdk::error::Error>
+        where
+            S: astarte_device_sdk::database::AstarteDatabase + Sync + Send + ?Sized + 'static,
+        {
+            client.send(INTERFACE, #path, data).await
+        }
+    };
+
+    if !property && mapping.explicit_timestamp {
+        let send_with_timestamp = format_ident!("send_{}_with_timestamp", name);
+        let doc = format!("Send the data on `{endpoint}` with an explicit timestamp.");
+
+        functions.extend(quote! {
+            #[doc = #doc]
+            pub async fn #send_with_timestamp<S>(
+                client: &astarte_device_sdk::AstarteDeviceSdk<S>,
+                #(#params: &str,)*
+                data: #ty,
+                timestamp: astarte_device_sdk::chrono::DateTime<astarte_device_sdk::chrono::Utc>,
+            ) -> Result<(), astarte_device_sdk::error::Error>
+            where
+                S: astarte_device_sdk::database::AstarteDatabase + Sync + Send + ?Sized + 'static,
+            {
+                client.send_with_timestamp(INTERFACE, #path, data, timestamp).await
+            }
+        });
+    }
+
+    if property && mapping.allow_unset {
+        let unset = format_ident!("unset_{}", name);
+        let doc = format!("Unset the property `{endpoint}`.");
+
+        functions.extend(quote! {
+            #[doc = #doc]
+            pub async fn #unset<S>(
+                client: &astarte_device_sdk::AstarteDeviceSdk<S>,
+                #(#params: &str,)*
+            ) -> Result<(), astarte_device_sdk::error::Error>
+            where
+                S: astarte_device_sdk::database::AstarteDatabase + Sync + Send + ?Sized + 'static,
+            {
+                client.unset(INTERFACE, #path).await
+            }
+        });
+    }
+
+    Some(functions)
+}
+
+fn object_functions(mappings: &[Mapping]) -> TokenStream {
+    // The mappings of an object share the path, without the last segment
+    let endpoint = mappings[0].endpoint;
+    let base = endpoint.rsplit_once('/').map_or("", |(base, _)| base);
+
+    let PathArgs { params, path } = PathArgs::new(base, quote!(#base));
+    let params = &params;
+
+    let doc = format!("Send the object on `{base}`.");
+    let mut functions = quote! {
+        #[doc = #doc]
+        pub async fn send<S, T>(
+            client: &astarte_device_sdk::AstarteDeviceSdk<S>,
+            #(#params: &str,)*
+            data: T,
+        ) -> Result<(), astarte_device_sdk::error::Error>
+        where
+            S: astarte_device_sdk::database::AstarteDatabase + Sync + Send + ?Sized + 'static,
+            T: astarte_device_sdk::AstarteAggregate,
+        {
+            client.send_object(INTERFACE, #path, data).await
+        }
+    };
+
+    if mappings.iter().any(|mapping| mapping.explicit_timestamp) {
+        let doc = format!("Send the object on `{base}` with an explicit timestamp.");
+
+        functions.extend(quote! {
+            #[doc = #doc]
+            pub async fn send_with_timestamp<S, T>(
+                client: &astarte_device_sdk::AstarteDeviceSdk<S>,
+                #(#params: &str,)*
+                data: T,
+                timestamp: astarte_device_sdk::chrono::DateTime<astarte_device_sdk::chrono::Utc>,
+            ) -> Result<(), astarte_device_sdk::error::Error>
+            where
+                S: astarte_device_sdk::database::AstarteDatabase + Sync + Send + ?Sized + 'static,
+                T: astarte_device_sdk::AstarteAggregate,
+            {
+                client
+                    .send_object_with_timestamp(INTERFACE, #path, data, timestamp)
+                    .await
+            }
+        });
+    }
+
+    functions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            to_snake_case("org.astarte-platform.rust.DeviceDatastream"),
+            "org_astarte_platform_rust_device_datastream"
+        );
+        assert_eq!(to_snake_case("com.example.Sensors"), "com_example_sensors");
+        assert_eq!(to_snake_case("sensor_id"), "sensor_id");
+        assert_eq!(to_snake_case("1st"), "_1st");
+
+        let mapping = Mapping {
+            endpoint: "/%{sensor_id}/rawValue",
+            mapping_type: "double",
+            explicit_timestamp: false,
+            allow_unset: false,
+            description: None,
+        };
+        assert_eq!(mapping.name(), "raw_value");
+
+        assert_eq!(ident("type").to_string(), "type_");
+    }
+}
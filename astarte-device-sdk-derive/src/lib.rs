@@ -18,15 +18,285 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 
+mod attr;
 mod case;
+mod enums;
+mod event;
+mod interface;
+mod newtype;
+mod properties;
+mod schema;
 
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::parse_macro_input;
 use syn::Attribute;
 
+use attr::{is_option, serde_rename, serde_rename_all, Errors, SerdeDirection};
 use case::RenameRule;
 
+/// Derive the `FromEvent` trait, converting a received event.
+///
+/// A struct is converted from the object aggregate sent on a path, its fields are the fields of
+/// the object. An enum is converted from an individual value, each variant has the endpoint of
+/// a mapping and the value as field.
+///
+/// ```ignore
+/// #[derive(FromEvent)]
+/// #[from_event(interface = "com.example.Sensors", path = "/%{sensor_id}")]
+/// struct Reading {
+///     #[mapping(param = "sensor_id")]
+///     sensor: String,
+///     #[mapping(endpoint = "value")]
+///     temperature: f64,
+/// }
+///
+/// #[derive(FromEvent)]
+/// #[from_event(interface = "com.example.Status", aggregation = "individual")]
+/// enum Status {
+///     #[mapping(endpoint = "/%{id}/temperature", param = "id")]
+///     Temperature(u32, f64),
+///     #[mapping(endpoint = "/enabled")]
+///     Enabled(bool),
+/// }
+/// ```
+///
+/// The parameters of the path are captured with `#[mapping(param = "...")]`, on a field of the
+/// struct or on a variant with the parameter before the value, and parsed with `FromStr`. The
+/// `Option` fields of a struct are `None` if they are missing in the object.
+///
+/// The paths and the endpoints are checked when the macro is expanded: each level must be a
+/// letter or an underscore followed by letters, digits or underscores, or a parameter like
+/// `%{sensor_id}`, and the captured parameters must be in the path.
+///
+/// A field missing in the object is set with `#[mapping(default)]` to its `Default`, or with
+/// `#[mapping(default = "expr")]` to the value of the expression, instead of returning an error.
+/// The default of an `Option` field is an `Option`.
+///
+/// A field of a struct with `#[mapping(timestamp)]` gets the explicit timestamp of the event,
+/// or the time it was received if the mapping doesn't have one. The field is a
+/// `DateTime<Utc>`, or an `Option<DateTime<Utc>>` with only the explicit timestamp.
+///
+/// The fields of a struct can be renamed with `#[from_event(rename_all = "...")]`, with the same
+/// rules of `AstarteAggregate`, while `#[mapping(endpoint = "...")]` takes precedence over the
+/// rule.
+///
+/// With `#[from_event(use_serde_attrs)]` the fields of a struct are renamed with
+/// `#[serde(rename_all = "...")]` and `#[serde(rename = "...")]`, or with the `deserialize` names
+/// if they are different for each direction, unless they are set with the Astarte attributes.
+///
+/// A value whose type doesn't implement `TryFrom<AstarteType>` can be converted with
+/// `#[mapping(try_from_with = "path::to::fn")]`, on a field or on a variant, a function taking the
+/// `AstarteType` and returning a `Result<T, TypeError>`.
+///
+/// The events of multiple interfaces are routed with `#[from_event(dispatch)]` on an enum, whose
+/// variants have as only field a type deriving `FromEvent`. The event is converted to the variant
+/// with the interface of the event, or returns `FromEventError::UnknownInterface`.
+///
+/// ```ignore
+/// #[derive(FromEvent)]
+/// #[from_event(dispatch)]
+/// enum DeviceEvent {
+///     Reading(Reading),
+///     Status(Status),
+/// }
+/// ```
+#[proc_macro_derive(FromEvent, attributes(from_event, mapping))]
+pub fn from_event_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as syn::DeriveInput);
+
+    event::expand(ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive the `IntoEvent` trait, converting an enum to an individual value to send.
+///
+/// It's the reverse of `FromEvent` for the enums: each variant has the endpoint of a mapping
+/// set with `#[mapping(...)]`, which can be shared by the two derives, and the value as field.
+/// The parameter of the endpoint, set with `param`, is the first field of the variant and it's
+/// formatted in the path with `Display`.
+///
+/// ```ignore
+/// #[derive(FromEvent, IntoEvent)]
+/// #[from_event(interface = "com.example.Status", aggregation = "individual")]
+/// #[into_event(interface = "com.example.Status")]
+/// enum Status {
+///     #[mapping(endpoint = "/%{id}/temperature", param = "id")]
+///     Temperature(u32, f64),
+///     #[mapping(endpoint = "/enabled")]
+///     Enabled(bool),
+/// }
+///
+/// device.send_event(Status::Temperature(3, 21.5)).await?;
+/// ```
+///
+/// The variants of a property interface, with `#[into_event(interface_type = "properties")]`,
+/// have a `Property` as value, `Property::Unset` unsets the property. A value whose type doesn't
+/// implement `TryInto<AstarteType>` can be converted with `#[mapping(try_into_with = "...")]`, a
+/// function taking the value and returning a `Result<AstarteType, E>`, with an error convertible
+/// into the SDK `Error`.
+#[proc_macro_derive(IntoEvent, attributes(into_event, mapping))]
+pub fn into_event_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as syn::DeriveInput);
+
+    event::expand_into(ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive the `AstarteProperties` trait, loading a whole property interface into a struct.
+///
+/// Each field is the value of a mapping of the interface set with
+/// `#[astarte_properties(interface = "...")]`, on the endpoint `/<field>` changed with the
+/// `rename_all` rule of the struct, or on the one set with `#[mapping(endpoint = "...")]`.
+///
+/// ```ignore
+/// #[derive(AstarteProperties)]
+/// #[astarte_properties(interface = "com.example.Settings", rename_all = "camelCase")]
+/// struct Settings {
+///     sample_rate: i32,
+///     #[mapping(endpoint = "/display/name")]
+///     name: Option<String>,
+///     #[mapping(default = "1.0")]
+///     gain: f64,
+/// }
+///
+/// let mut settings = Settings::load(&device).await?;
+/// settings.sample_rate = 10;
+/// settings.store(&device).await?;
+/// ```
+///
+/// The `Option` fields are `None` if the property is unset and they are unset when stored. The
+/// other fields must be stored, unless they have a `default` like the fields of `FromEvent`. The
+/// values are converted like the ones of `FromEvent` and `IntoEvent`, also with
+/// `try_from_with` and `try_into_with`, so the fields must implement `Clone`.
+#[proc_macro_derive(AstarteProperties, attributes(astarte_properties, mapping))]
+pub fn astarte_properties_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as syn::DeriveInput);
+
+    properties::expand(ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generate the JSON of an interface from a struct, as the `INTERFACE` associated constant.
+///
+/// Each field is a mapping, with the endpoint given by the path of the interface and the field
+/// name, and the type inferred from the Rust type with the same conversions of `AstarteType`.
+///
+/// ```ignore
+/// #[astarte_interface(name = "com.example.Sensor", version = "1.0", aggregation = "object", path = "/%{id}")]
+/// struct Sensor {
+///     temperature: f64,
+///     #[astarte_interface(rename = "unit", type = "string")]
+///     unit_name: Unit,
+/// }
+///
+/// device.add_interface_from_str(Sensor::INTERFACE).await?;
+/// ```
+///
+/// The arguments of the attribute on the struct are the `name` and the `version` of the
+/// interface, and optionally the `interface_type` ("datastream" or "properties"), the
+/// `ownership` ("device" or "server"), the `aggregation` ("individual" or "object"), the `path`
+/// prefix of the endpoints, the `rename_all` rule of the fields, the `reliability`, the
+/// `explicit_timestamp` flag, the `description` and the `doc`.
+///
+/// On a field, `#[astarte_interface(...)]` can set the endpoint with `rename`, the Astarte
+/// `type`, the `reliability`, the `explicit_timestamp` and `allow_unset` flags, the
+/// `description` and the `doc`, or `skip` the field. The `Option` fields of a property interface
+/// can be unset.
+///
+/// The opposite direction, generating the Rust code from the JSON of an interface, is done by
+/// `include_interface!`.
+#[proc_macro_attribute]
+pub fn astarte_interface(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as syn::AttributeArgs);
+    let ast = parse_macro_input!(item as syn::DeriveInput);
+
+    schema::expand(args, ast).into()
+}
+
+/// Derive the conversion of a fieldless enum to and from an `AstarteType::String`.
+///
+/// The enum can be a field of an `AstarteAggregate` or a `FromEvent`, sent on a `string`
+/// mapping. The string of each variant is the variant name, changed with the
+/// `#[astarte_enum(rename_all = "...")]` rule of the enum, or the one set with
+/// `#[astarte_enum(rename = "...")]` on the variant.
+///
+/// ```ignore
+/// #[derive(AstarteEnum)]
+/// #[astarte_enum(rename_all = "snake_case")]
+/// enum State {
+///     Idle,
+///     // sent as "running_fast"
+///     RunningFast,
+///     #[astarte_enum(rename = "KO")]
+///     Failed,
+/// }
+/// ```
+///
+/// Converting a string not matching any variant returns a `TypeError::Conversion`.
+#[proc_macro_derive(AstarteEnum, attributes(astarte_enum))]
+pub fn astarte_enum_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as syn::DeriveInput);
+
+    enums::expand(ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive the conversions to and from `AstarteType` of a newtype wrapper, forwarding them to
+/// the wrapped type.
+///
+/// ```ignore
+/// #[derive(AstarteNewtype)]
+/// struct DeviceId(String);
+///
+/// #[derive(AstarteAggregate)]
+/// struct Registration {
+///     device: DeviceId,
+/// }
+/// ```
+///
+/// The struct must have a single field, named or unnamed. The errors of the conversions are the
+/// ones of the wrapped type, so the wrapper can be a field of a `FromEvent` if the wrapped type
+/// can.
+#[proc_macro_derive(AstarteNewtype)]
+pub fn astarte_newtype_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as syn::DeriveInput);
+
+    newtype::expand(ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generate a module with the endpoints and typed send functions of an interface.
+///
+/// The path of the interface JSON is relative to the manifest of the crate. The name of the
+/// module is the snake case interface name, unless it's passed before the path:
+///
+/// ```ignore
+/// include_interface!("interfaces/com.example.Sensors.json");
+/// include_interface!(sensors, "interfaces/com.example.Sensors.json");
+///
+/// com_example_sensors::send_temperature(&device, 21.5).await?;
+/// ```
+///
+/// For each mapping of a device owned interface a `send_<endpoint>` function is generated,
+/// taking the parameters of the endpoint and the data with the type of the mapping. A
+/// `send_<endpoint>_with_timestamp` is also generated if the mapping has an explicit timestamp,
+/// and an `unset_<endpoint>` for the properties that can be unset. The object interfaces have
+/// a single `send` function for the whole object.
+#[proc_macro]
+pub fn include_interface(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as interface::InterfaceArgs);
+
+    interface::expand(args)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
 #[proc_macro_attribute]
 pub fn astarte_aggregate(attr: TokenStream, item: TokenStream) -> TokenStream {
     // Construct a representation of Rust code as a syntax tree
@@ -47,7 +317,37 @@ fn impl_astarte_aggregate_attr(
     TokenStream::from(quote!(#ast_item))
 }
 
-#[proc_macro_derive(AstarteAggregate)]
+/// Derive the `AstarteAggregate` trait, converting each field of the struct to an endpoint.
+///
+/// A field can be another `AstarteAggregate` with `#[astarte_aggregate(nested)]`, its endpoints
+/// are added with the name of the field and an underscore as prefix, or with the one set with
+/// `#[astarte_aggregate(nested, prefix = "...")]`. With `#[astarte_aggregate(flatten)]` the
+/// endpoints of the field are added without a prefix, like the shared sub-objects reused by
+/// multiple interfaces. A nested endpoint with the same name of another field replaces it if the
+/// field comes before.
+///
+/// The name of the endpoint of a single field can be set with
+/// `#[astarte_aggregate(rename = "...")]`, it takes precedence over the `rename_all` rule of the
+/// struct. The fields with `#[astarte_aggregate(skip)]` are not converted, while the `Option`
+/// fields are omitted when they are `None`.
+///
+/// A field whose type doesn't implement `TryInto<AstarteType>` can be converted with
+/// `#[astarte_aggregate(try_into_with = "path::to::fn")]`, a function taking the value of the
+/// field and returning a `Result<AstarteType, E>`, with an error convertible into the SDK `Error`.
+///
+/// With `#[astarte_aggregate(use_serde_attrs)]` the names set with `#[serde(rename_all = "...")]`
+/// and `#[serde(rename = "...")]` are used, or the `serialize` ones if they are different for
+/// each direction. The `astarte_aggregate` renames take precedence over the serde ones.
+///
+/// A tuple struct with a single field, like a wrapper of a generated type, has the endpoints of
+/// the field, which must be an `AstarteAggregate` like another struct or a
+/// map like `HashMap<String, f64>`.
+///
+/// Each generic type parameter of the struct is bounded by `TryInto<AstarteType>`, or by
+/// `AstarteAggregate` for a tuple struct. The bounds can be removed with
+/// `#[astarte_aggregate(no_bound)]`, for the marker parameters, or replaced with
+/// `#[astarte_aggregate(bound = "T: AstarteAggregate")]`, like for a nested generic field.
+#[proc_macro_derive(AstarteAggregate, attributes(astarte_aggregate))]
 pub fn astarte_aggregate_derive(input: TokenStream) -> TokenStream {
     // Construct a representation of Rust code as a syntax tree
     // that we can manipulate
@@ -55,60 +355,366 @@ pub fn astarte_aggregate_derive(input: TokenStream) -> TokenStream {
 
     // Build the trait implementation
     impl_astarte_aggregate_derive(ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
 }
 
-fn impl_astarte_aggregate_derive(ast: syn::DeriveInput) -> TokenStream {
-    let mut rename_rule = RenameRule::None;
-    if let Some(astarte_aggregate_attr) =
-        find_astarte_aggregate_in_attributes_list(&ast.attrs).unwrap()
-    {
-        rename_rule = parse_astarte_aggregate_attribute(astarte_aggregate_attr).unwrap();
-    }
+fn impl_astarte_aggregate_derive(ast: syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = match &ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => AggregateFields::Named(fields),
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Unnamed(fields),
+            ..
+        }) if fields.unnamed.len() == 1 => AggregateFields::Newtype(&fields.unnamed[0]),
+        _ => {
+            return Err(syn::Error::new(
+                ast.ident.span(),
+                "AstarteAggregate is only implementable over a struct with named fields or with a single unnamed field",
+            ))
+        }
+    };
 
-    if let syn::Data::Struct(st) = ast.data {
-        if let syn::Fields::Named(fields) = st.fields {
-            let mut fields_names_renamed = Vec::new();
-            let mut fields_names_ident = Vec::new();
-            for field in fields.named {
-                let ident = field
-                    .ident
-                    .expect("AstarteAggregate is not implementable over this struct");
-                fields_names_renamed.push(rename_rule.apply_to_field(&ident.to_string()));
-                fields_names_ident.push(ident);
+    let mut errors = Errors::default();
+
+    let struct_attrs = match find_astarte_aggregate_in_attributes_list(&ast.attrs) {
+        Ok(Some(attr)) => errors
+            .collect(parse_astarte_aggregate_attribute(attr))
+            .unwrap_or_default(),
+        Ok(None) => StructAttributes::default(),
+        Err(err) => {
+            errors.push(err);
+
+            StructAttributes::default()
+        }
+    };
+    let use_serde_attrs = struct_attrs.use_serde_attrs;
+    // the explicit rule takes precedence over the serde one
+    let rename_rule = match struct_attrs.rename_rule {
+        RenameRule::None if use_serde_attrs => errors
+            .collect(serde_rename_all(&ast.attrs, SerdeDirection::Serialize))
+            .flatten()
+            .unwrap_or_default(),
+        rename_rule => rename_rule,
+    };
+
+    let mut generics = ast.generics.clone();
+    let newtype = matches!(fields, AggregateFields::Newtype(_));
+    add_trait_bounds(
+        &mut generics,
+        newtype,
+        struct_attrs.no_bound,
+        struct_attrs.bound,
+    );
+
+    let fields = match fields {
+        AggregateFields::Named(fields) => fields,
+        AggregateFields::Newtype(field) => {
+            // the endpoints are the ones of the wrapped type
+            if let Some(attr) = field
+                .attrs
+                .iter()
+                .find(|attr| attr.path.is_ident("astarte_aggregate"))
+            {
+                errors.push(syn::Error::new_spanned(
+                    attr,
+                    "The field of a tuple struct can't have astarte_aggregate attributes.",
+                ));
             }
+            errors.finish()?;
+
             let name = &ast.ident;
-            let fields_names_renamed_iter = fields_names_renamed.iter();
-            let fields_names_ident_iter = fields_names_ident.iter();
-            let gen = quote! {
-                impl AstarteAggregate for #name {
+            let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+            return Ok(quote! {
+                impl #impl_generics AstarteAggregate for #name #ty_generics #where_clause {
                     fn astarte_aggregate(
                         self,
                     ) -> Result<
                         std::collections::HashMap<String, astarte_device_sdk::types::AstarteType>,
                         astarte_device_sdk::error::Error,
                     > {
-                        let mut result = std::collections::HashMap::new();
-                        #(
-                            let astype: astarte_device_sdk::types::AstarteType =
-                                std::convert::TryInto::try_into(self.#fields_names_ident_iter)?;
-                            result.insert(#fields_names_renamed_iter.to_string(), astype);
-                        )*
-                        Ok(result)
+                        astarte_device_sdk::AstarteAggregate::astarte_aggregate(self.0)
                     }
                 }
-            };
-            gen.into()
+            });
+        }
+    };
+    let mut fields_inserts = Vec::new();
+    for field in &fields.named {
+        let Some(field_attrs) = errors.collect(parse_field_attributes(&field.attrs)) else {
+            continue;
+        };
+
+        if field_attrs.skip {
+            continue;
+        }
+
+        let ident = field.ident.as_ref().expect("named field");
+        let renamed = field_attrs
+            .rename
+            .or_else(|| {
+                serde_rename(&field.attrs, SerdeDirection::Serialize).filter(|_| use_serde_attrs)
+            })
+            .unwrap_or_else(|| rename_rule.apply_to_field(&ident.to_string()));
+
+        let insert = if field_attrs.flatten {
+            quote! {
+                let nested = astarte_device_sdk::AstarteAggregate::astarte_aggregate(
+                    value,
+                )?;
+                result.extend(nested);
+            }
+        } else if field_attrs.nested {
+            let prefix = field_attrs.prefix.unwrap_or_else(|| format!("{renamed}_"));
+
+            quote! {
+                let nested = astarte_device_sdk::AstarteAggregate::astarte_aggregate(
+                    value,
+                )?;
+                for (key, astype) in nested {
+                    result.insert(format!("{}{}", #prefix, key), astype);
+                }
+            }
+        } else if let Some(try_into_with) = field_attrs.try_into_with {
+            quote! {
+                let astype: astarte_device_sdk::types::AstarteType =
+                    #try_into_with(value)?;
+                result.insert(#renamed.to_string(), astype);
+            }
         } else {
-            panic!("AstarteAggregate is only implementable over a named struct.")
+            quote! {
+                let astype: astarte_device_sdk::types::AstarteType =
+                    std::convert::TryInto::try_into(value)?;
+                result.insert(#renamed.to_string(), astype);
+            }
+        };
+
+        // the optional fields are omitted when they are None
+        let insert = if is_option(&field.ty) {
+            quote! {
+                if let Some(value) = self.#ident {
+                    #insert
+                }
+            }
+        } else {
+            quote! {
+                let value = self.#ident;
+                #insert
+            }
+        };
+
+        fields_inserts.push(insert);
+    }
+    errors.finish()?;
+
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics AstarteAggregate for #name #ty_generics #where_clause {
+            fn astarte_aggregate(
+                self,
+            ) -> Result<
+                std::collections::HashMap<String, astarte_device_sdk::types::AstarteType>,
+                astarte_device_sdk::error::Error,
+            > {
+                let mut result = std::collections::HashMap::new();
+                #(#fields_inserts)*
+                Ok(result)
+            }
+        }
+    })
+}
+
+/// Fields of a struct deriving `AstarteAggregate`.
+enum AggregateFields<'a> {
+    /// Each field is converted to an endpoint.
+    Named(&'a syn::FieldsNamed),
+    /// The single field is an `AstarteAggregate` with the endpoints of the struct.
+    Newtype(&'a syn::Field),
+}
+
+/// Options of the struct set with the `astarte_aggregate` attribute.
+#[derive(Default)]
+struct StructAttributes {
+    /// Rule to rename the fields to the endpoints.
+    rename_rule: RenameRule,
+    /// The generic parameters are not bounded by `TryInto<AstarteType>`.
+    no_bound: bool,
+    /// The renames of the serde attributes are used, if not set with the Astarte ones.
+    use_serde_attrs: bool,
+    /// Bounds of the implementation, instead of the ones on the generic parameters.
+    bound: Option<Vec<syn::WherePredicate>>,
+}
+
+/// Bounds each type parameter with the conversion into an `AstarteType`, or with
+/// `AstarteAggregate` for a tuple struct, unless the bounds are set on the struct.
+fn add_trait_bounds(
+    generics: &mut syn::Generics,
+    newtype: bool,
+    no_bound: bool,
+    bound: Option<Vec<syn::WherePredicate>>,
+) {
+    let predicates: Vec<syn::WherePredicate> = match bound {
+        Some(bound) => bound,
+        None if no_bound => return,
+        None if newtype => generics
+            .type_params()
+            .map(|param| {
+                let ident = &param.ident;
+
+                syn::parse_quote! { #ident: astarte_device_sdk::AstarteAggregate }
+            })
+            .collect(),
+        None => generics
+            .type_params()
+            .flat_map(|param| {
+                let ident = &param.ident;
+
+                [
+                    syn::parse_quote! {
+                        #ident: std::convert::TryInto<astarte_device_sdk::types::AstarteType>
+                    },
+                    syn::parse_quote! {
+                        astarte_device_sdk::error::Error: std::convert::From<
+                            <#ident as std::convert::TryInto<
+                                astarte_device_sdk::types::AstarteType,
+                            >>::Error,
+                        >
+                    },
+                ]
+            })
+            .collect(),
+    };
+
+    generics.make_where_clause().predicates.extend(predicates);
+}
+
+/// Options of a field set with the `astarte_aggregate` attribute.
+#[derive(Default)]
+struct FieldAttributes {
+    /// The field is an `AstarteAggregate` whose endpoints are inserted with a prefix.
+    nested: bool,
+    /// The field is an `AstarteAggregate` whose endpoints are inserted without a prefix.
+    flatten: bool,
+    /// Prefix of the nested endpoints, the field name followed by an underscore by default.
+    prefix: Option<String>,
+    /// Name of the endpoint, instead of the one given by the rename rule of the struct.
+    rename: Option<String>,
+    /// The field is not converted to an endpoint.
+    skip: bool,
+    /// Function converting the field, instead of `TryInto<AstarteType>`.
+    try_into_with: Option<syn::Path>,
+}
+
+fn parse_field_attributes(attrs: &[Attribute]) -> syn::Result<FieldAttributes> {
+    let mut errors = Errors::default();
+    let mut field_attrs = FieldAttributes::default();
+
+    for attr in attrs {
+        if !attr.path.is_ident("astarte_aggregate") {
+            continue;
         }
+
+        let Ok(syn::Meta::List(meta_list)) = attr.parse_meta() else {
+            errors.push(syn::Error::new_spanned(
+                attr,
+                "Incorrectly formatted astarte_aggregate field attribute.",
+            ));
+
+            continue;
+        };
+
+        for nested in meta_list.nested {
+            match nested {
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("nested") => {
+                    field_attrs.nested = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("flatten") => {
+                    field_attrs.flatten = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("skip") => {
+                    field_attrs.skip = true;
+                }
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(lit_str),
+                    ..
+                })) if path.is_ident("prefix") => {
+                    field_attrs.prefix = Some(lit_str.value());
+                }
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(lit_str),
+                    ..
+                })) if path.is_ident("rename") => {
+                    field_attrs.rename = Some(lit_str.value());
+                }
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(lit_str),
+                    ..
+                })) if path.is_ident("try_into_with") => match lit_str.parse() {
+                    Ok(function) => field_attrs.try_into_with = Some(function),
+                    Err(_) => errors.push(syn::Error::new(
+                        lit_str.span(),
+                        format!(
+                            "Invalid path of the conversion function {}.",
+                            lit_str.value()
+                        ),
+                    )),
+                },
+                nested => errors.push(syn::Error::new_spanned(
+                    nested,
+                    "Unrecognized astarte_aggregate field attribute.",
+                )),
+            }
+        }
+    }
+
+    errors.finish()?;
+
+    let invalid = if field_attrs.prefix.is_some() && !field_attrs.nested {
+        Some("The prefix can only be set on a nested field.")
+    } else if field_attrs.nested && field_attrs.try_into_with.is_some() {
+        Some("A nested field can't have a conversion function.")
+    } else if field_attrs.flatten
+        && (field_attrs.nested
+            || field_attrs.prefix.is_some()
+            || field_attrs.rename.is_some()
+            || field_attrs.try_into_with.is_some())
+    {
+        Some("A flattened field can't have other attributes.")
+    } else if field_attrs.skip
+        && (field_attrs.nested
+            || field_attrs.flatten
+            || field_attrs.prefix.is_some()
+            || field_attrs.rename.is_some()
+            || field_attrs.try_into_with.is_some())
+    {
+        Some("A skipped field can't have other attributes.")
     } else {
-        panic!("AstarteAggregate is only implementable over a struct.")
+        None
+    };
+
+    if let Some(message) = invalid {
+        let attr = attrs
+            .iter()
+            .find(|attr| attr.path.is_ident("astarte_aggregate"))
+            .expect("the options are set by an attribute");
+
+        return Err(syn::Error::new_spanned(attr, message));
     }
+
+    Ok(field_attrs)
 }
 
 fn find_astarte_aggregate_in_attributes_list(
     attrs: &[Attribute],
-) -> Result<Option<&Attribute>, String> {
+) -> syn::Result<Option<&Attribute>> {
     let astarte_aggregate_list = attrs
         .iter()
         .filter(|e| {
@@ -121,28 +727,79 @@ fn find_astarte_aggregate_in_attributes_list(
         })
         .collect::<Vec<_>>();
 
-    if let [astarte_aggregate_attr] = astarte_aggregate_list[..] {
-        Ok(Some(astarte_aggregate_attr))
-    } else if astarte_aggregate_list.is_empty() {
-        Ok(None)
-    } else {
-        Err("Duplicated astarte_aggregate attribute.".to_string())
+    match astarte_aggregate_list[..] {
+        [] => Ok(None),
+        [astarte_aggregate_attr] => Ok(Some(astarte_aggregate_attr)),
+        [_, duplicated, ..] => Err(syn::Error::new_spanned(
+            duplicated,
+            "Duplicated astarte_aggregate attribute.",
+        )),
     }
 }
 
-fn parse_astarte_aggregate_attribute(attr: &Attribute) -> Result<RenameRule, String> {
-    if let Ok(syn::Meta::List(meta_list)) = &attr.parse_meta() {
-        let meta_list_nested = &meta_list.nested;
-        if meta_list_nested.len() == 1 {
-            if let Some(syn::NestedMeta::Meta(syn::Meta::NameValue(meta_name_value))) =
-                meta_list_nested.first()
-            {
-                if let syn::Lit::Str(lit_str) = &meta_name_value.lit {
-                    return RenameRule::from_str(&lit_str.value())
-                        .map_err(|_| format!("Unrecognize syntax rule {}", lit_str.value()));
+fn parse_astarte_aggregate_attribute(attr: &Attribute) -> syn::Result<StructAttributes> {
+    let Ok(syn::Meta::List(meta_list)) = attr.parse_meta() else {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "Incorrectly formatted astarte_aggregate attribute.",
+        ));
+    };
+
+    let mut errors = Errors::default();
+    let mut struct_attrs = StructAttributes::default();
+
+    for nested in meta_list.nested {
+        match nested {
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("no_bound") => {
+                struct_attrs.no_bound = true;
+            }
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("use_serde_attrs") => {
+                struct_attrs.use_serde_attrs = true;
+            }
+            syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                lit: syn::Lit::Str(lit_str),
+                ..
+            })) if path.is_ident("rename_all") => match RenameRule::from_str(&lit_str.value()) {
+                Ok(rename_rule) => struct_attrs.rename_rule = rename_rule,
+                Err(_) => errors.push(syn::Error::new(
+                    lit_str.span(),
+                    format!("Unrecognize syntax rule {}", lit_str.value()),
+                )),
+            },
+            syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                lit: syn::Lit::Str(lit_str),
+                ..
+            })) if path.is_ident("bound") => {
+                let bound = lit_str.parse_with(syn::punctuated::Punctuated::<
+                    syn::WherePredicate,
+                    syn::Token![,],
+                >::parse_terminated);
+
+                match bound {
+                    Ok(bound) => struct_attrs.bound = Some(bound.into_iter().collect()),
+                    Err(_) => errors.push(syn::Error::new(
+                        lit_str.span(),
+                        format!("Invalid bound {}.", lit_str.value()),
+                    )),
                 }
             }
+            nested => errors.push(syn::Error::new_spanned(
+                nested,
+                "Incorrectly formatted astarte_aggregate attribute.",
+            )),
         }
     }
-    Err("Incorrectly formatted astarte_aggregate attribute.".to_string())
+
+    errors.finish()?;
+
+    if struct_attrs.no_bound && struct_attrs.bound.is_some() {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "The bound can't be set with no_bound.",
+        ));
+    }
+
+    Ok(struct_attrs)
 }
@@ -0,0 +1,96 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Derive the conversions of a newtype wrapper forwarding them to the wrapped type.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::DeriveInput;
+
+pub fn expand(mut ast: DeriveInput) -> syn::Result<TokenStream> {
+    let syn::Data::Struct(st) = &ast.data else {
+        return Err(syn::Error::new(
+            ast.ident.span(),
+            "AstarteNewtype can only be derived for a struct",
+        ));
+    };
+
+    let mut fields = st.fields.iter();
+    let (Some(field), None) = (fields.next(), fields.next()) else {
+        return Err(syn::Error::new(
+            st.fields.span(),
+            "AstarteNewtype can only be derived for a struct with a single field",
+        ));
+    };
+
+    let inner = field.ty.clone();
+    let (member, construct) = match &field.ident {
+        Some(ident) => (quote! { #ident }, quote! { |value| Self { #ident: value } }),
+        None => (quote! { 0 }, quote! { Self }),
+    };
+
+    // the wrapped type must be convertible, for the generic wrappers
+    let predicates = &mut ast.generics.make_where_clause().predicates;
+    predicates.push(syn::parse_quote! {
+        #inner: std::convert::TryInto<astarte_device_sdk::types::AstarteType>
+    });
+    predicates.push(syn::parse_quote! {
+        #inner: std::convert::TryFrom<astarte_device_sdk::types::AstarteType>
+    });
+
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    Ok(quote! {
+        #[automatically_derived]
+        // the lint is unknown to the older toolchains
+        #[allow(unknown_lints)]
+        #[allow(clippy::infallible_try_from)]
+        impl #impl_generics std::convert::TryFrom<#name #ty_generics>
+            for astarte_device_sdk::types::AstarteType #where_clause
+        {
+            type Error =
+                <#inner as std::convert::TryInto<astarte_device_sdk::types::AstarteType>>::Error;
+
+            fn try_from(value: #name #ty_generics) -> Result<Self, Self::Error> {
+                std::convert::TryInto::try_into(value.#member)
+            }
+        }
+
+        #[automatically_derived]
+        // the lint is unknown to the older toolchains
+        #[allow(unknown_lints)]
+        #[allow(clippy::infallible_try_from)]
+        impl #impl_generics std::convert::TryFrom<astarte_device_sdk::types::AstarteType>
+            for #name #ty_generics #where_clause
+        {
+            type Error =
+                <#inner as std::convert::TryFrom<astarte_device_sdk::types::AstarteType>>::Error;
+
+            fn try_from(value: astarte_device_sdk::types::AstarteType) -> Result<Self, Self::Error> {
+                <#inner as std::convert::TryFrom<astarte_device_sdk::types::AstarteType>>::try_from(
+                    value,
+                )
+                .map(#construct)
+            }
+        }
+    })
+}
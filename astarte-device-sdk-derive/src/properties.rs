@@ -0,0 +1,174 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Derive the `AstarteProperties` trait for a struct with a field for each mapping.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::DeriveInput;
+
+use crate::attr::{generic_argument, is_option, validate_endpoint, AttrArgs, Errors};
+use crate::case::RenameRule;
+use crate::event::{conversion, default_value, try_from};
+
+/// Returns the conversion of a field from the stored values and to the values to send.
+fn property_field(
+    field: &syn::Field,
+    rename_rule: RenameRule,
+) -> syn::Result<(TokenStream, TokenStream)> {
+    let mut args = AttrArgs::parse(&field.attrs, "mapping")?;
+    let endpoint = args.take("endpoint");
+    let try_from_with = conversion(&mut args, "try_from_with")?;
+    let try_into_with = conversion(&mut args, "try_into_with")?;
+    let default = default_value(&mut args)?;
+    args.finish()?;
+
+    let ident = field.ident.as_ref().expect("named field");
+
+    // an explicit endpoint takes precedence over the rename rule
+    let path = match endpoint {
+        Some(endpoint) => {
+            if !validate_endpoint(&endpoint)?.is_empty() {
+                return Err(syn::Error::new(
+                    endpoint.span(),
+                    "the endpoint must be a path without parameters, like \"/value\"",
+                ));
+            }
+
+            endpoint.value()
+        }
+        None => format!("/{}", rename_rule.apply_to_field(&ident.to_string())),
+    };
+
+    let option = is_option(&field.ty);
+    let ty = generic_argument(&field.ty, "Option").unwrap_or(&field.ty);
+    let convert = try_from(ty, try_from_with);
+
+    let optional = quote! {
+        astarte_device_sdk::event::optional_field_with(&mut values, #path, #convert)?
+    };
+
+    // the default of an `Option` field is the whole `Option`
+    let value = match default {
+        Some(default) if option => quote! { #optional.map_or_else(|| #default, Some) },
+        Some(default) => quote! { #optional.unwrap_or_else(|| #default) },
+        None if option => optional,
+        None => quote! {
+            astarte_device_sdk::event::field_with(&mut values, #path, #convert)?
+        },
+    };
+
+    let value = quote! { #ident: #value };
+
+    let convert = try_into_with.map_or_else(
+        || quote! { std::convert::TryInto::try_into },
+        |try_into_with| quote! { #try_into_with },
+    );
+
+    // the `None` fields are unset
+    let data = if option {
+        quote! { std::clone::Clone::clone(&self.#ident).map(#convert).transpose()? }
+    } else {
+        quote! { Some(#convert(std::clone::Clone::clone(&self.#ident))?) }
+    };
+
+    let property = quote! {
+        let data: Option<astarte_device_sdk::types::AstarteType> = #data;
+        properties.push((#path.to_string(), data));
+    };
+
+    Ok((value, property))
+}
+
+pub fn expand(ast: DeriveInput) -> syn::Result<TokenStream> {
+    let mut args = AttrArgs::parse(&ast.attrs, "astarte_properties")?;
+
+    let interface = args.take("interface").ok_or_else(|| {
+        syn::Error::new(
+            ast.ident.span(),
+            "missing #[astarte_properties(interface = \"...\")]",
+        )
+    })?;
+    let rename_all = args.take("rename_all");
+    args.finish()?;
+
+    let rename_rule = match &rename_all {
+        Some(rename_all) => RenameRule::from_str(&rename_all.value())
+            .map_err(|err| syn::Error::new(rename_all.span(), err))?,
+        None => RenameRule::None,
+    };
+
+    let fields = match &ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => fields,
+        _ => {
+            return Err(syn::Error::new(
+                ast.ident.span(),
+                "AstarteProperties can only be derived for a struct with named fields",
+            ))
+        }
+    };
+
+    let mut errors = Errors::default();
+    let mut values = Vec::new();
+    let mut properties = Vec::new();
+    for field in &fields.named {
+        if let Some((value, property)) = errors.collect(property_field(field, rename_rule)) {
+            values.push(value);
+            properties.push(property);
+        }
+    }
+    errors.finish()?;
+
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics astarte_device_sdk::settings::AstarteProperties for #name #ty_generics #where_clause {
+            const INTERFACE: &'static str = #interface;
+
+            fn from_properties(
+                mut values: std::collections::HashMap<
+                    String,
+                    astarte_device_sdk::types::AstarteType,
+                >,
+            ) -> Result<Self, astarte_device_sdk::event::FromEventError> {
+                Ok(Self {
+                    #(#values,)*
+                })
+            }
+
+            fn to_properties(
+                &self,
+            ) -> Result<
+                Vec<(String, Option<astarte_device_sdk::types::AstarteType>)>,
+                astarte_device_sdk::error::Error,
+            > {
+                let mut properties = Vec::new();
+
+                #(#properties)*
+
+                Ok(properties)
+            }
+        }
+    })
+}
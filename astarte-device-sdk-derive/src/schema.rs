@@ -0,0 +1,342 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Generate the JSON of an interface from the fields of a struct.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use serde_json::{Map, Value};
+use syn::spanned::Spanned;
+use syn::{AttributeArgs, DeriveInput, LitStr};
+
+use crate::attr::{generic_argument, AttrArgs, Errors};
+use crate::case::RenameRule;
+
+/// Returns the value of an argument, checking it's one of the allowed ones.
+fn one_of(arg: &Option<LitStr>, allowed: &[&'static str]) -> syn::Result<&'static str> {
+    let Some(arg) = arg else {
+        return Ok(allowed[0]);
+    };
+
+    let value = arg.value();
+
+    allowed
+        .iter()
+        .find(|allowed| **allowed == value)
+        .copied()
+        .ok_or_else(|| {
+            syn::Error::new(
+                arg.span(),
+                format!("expected one of {}", allowed.join(", ")),
+            )
+        })
+}
+
+/// Parses a version like `1.0` in the major and minor.
+fn parse_version(version: &LitStr) -> syn::Result<(i32, i32)> {
+    version
+        .value()
+        .split_once('.')
+        .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)))
+        .ok_or_else(|| {
+            syn::Error::new(
+                version.span(),
+                "the version must be the major and minor, like \"1.0\"",
+            )
+        })
+}
+
+/// Name of the Astarte type with the same representation of a scalar Rust type.
+fn scalar_type(ty: &syn::Type) -> Option<&'static str> {
+    let syn::Type::Path(type_path) = ty else {
+        return None;
+    };
+
+    let segment = type_path.path.segments.last()?;
+    let name = match segment.ident.to_string().as_str() {
+        "f64" | "f32" => "double",
+        "i32" => "integer",
+        "i64" => "longinteger",
+        "bool" => "boolean",
+        "String" => "string",
+        "DateTime" => "datetime",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// Name of the Astarte type of a field, following the conversions of `AstarteType`.
+fn astarte_type(ty: &syn::Type) -> Option<String> {
+    let Some(item) = generic_argument(ty, "Vec") else {
+        return scalar_type(ty).map(str::to_string);
+    };
+
+    let is_bytes = |ty: &syn::Type| matches!(ty, syn::Type::Path(path) if path.path.is_ident("u8"));
+
+    if is_bytes(item) {
+        return Some("binaryblob".to_string());
+    }
+
+    if matches!(generic_argument(item, "Vec"), Some(item) if is_bytes(item)) {
+        return Some("binaryblobarray".to_string());
+    }
+
+    scalar_type(item).map(|scalar| format!("{scalar}array"))
+}
+
+/// Name of the attribute, on the struct and on the fields.
+const ATTR: &str = "astarte_interface";
+
+/// Expands to the struct, without the attributes of the fields, and the constant with the JSON.
+///
+/// The struct is kept even if the attributes are invalid, so the errors are only the ones of the
+/// attributes.
+pub fn expand(args: AttributeArgs, mut ast: DeriveInput) -> TokenStream {
+    let interface = interface_const(args, &ast).unwrap_or_else(syn::Error::into_compile_error);
+
+    if let syn::Data::Struct(data) = &mut ast.data {
+        for field in data.fields.iter_mut() {
+            field.attrs.retain(|attr| !attr.path.is_ident(ATTR));
+        }
+    }
+
+    quote! {
+        #ast
+
+        #interface
+    }
+}
+
+fn interface_const(args: AttributeArgs, ast: &DeriveInput) -> syn::Result<TokenStream> {
+    let mut args = AttrArgs::from_nested(args, ATTR)?;
+
+    let name = args.take("name").ok_or_else(|| {
+        syn::Error::new(
+            ast.ident.span(),
+            "missing name = \"...\" in #[astarte_interface(...)]",
+        )
+    })?;
+    let version = args.take("version").ok_or_else(|| {
+        syn::Error::new(
+            ast.ident.span(),
+            "missing version = \"...\" in #[astarte_interface(...)]",
+        )
+    })?;
+    let interface_type = args.take("interface_type");
+    let ownership = args.take("ownership");
+    let aggregation = args.take("aggregation");
+    let path = args.take("path");
+    let rename_all = args.take("rename_all");
+    let reliability = args.take("reliability");
+    let explicit_timestamp = args.take_flag("explicit_timestamp");
+    let description = args.take("description");
+    let doc = args.take("doc");
+    args.finish()?;
+
+    let (major, minor) = parse_version(&version)?;
+    let properties = one_of(&interface_type, &["datastream", "properties"])? == "properties";
+    let ownership = one_of(&ownership, &["device", "server"])?;
+    let object = one_of(&aggregation, &["individual", "object"])? == "object";
+
+    if properties && object {
+        return Err(syn::Error::new(
+            aggregation.span(),
+            "a property interface can't be an object aggregate",
+        ));
+    }
+
+    if properties && (reliability.is_some() || explicit_timestamp) {
+        return Err(syn::Error::new(
+            ast.ident.span(),
+            "the reliability and explicit timestamp can only be set on a datastream",
+        ));
+    }
+
+    let rename_rule = match &rename_all {
+        Some(rename_all) => RenameRule::from_str(&rename_all.value())
+            .map_err(|err| syn::Error::new(rename_all.span(), err))?,
+        None => RenameRule::None,
+    };
+
+    let prefix = match &path {
+        Some(path) if !path.value().starts_with('/') => {
+            return Err(syn::Error::new(path.span(), "the path must start with /"));
+        }
+        Some(path) => path.value().trim_end_matches('/').to_string(),
+        None if object => {
+            return Err(syn::Error::new(
+                ast.ident.span(),
+                "missing the path = \"...\" of the object in #[astarte_interface(...)]",
+            ));
+        }
+        None => String::new(),
+    };
+
+    let syn::Data::Struct(syn::DataStruct {
+        fields: syn::Fields::Named(fields),
+        ..
+    }) = &ast.data
+    else {
+        return Err(syn::Error::new(
+            ast.ident.span(),
+            "#[astarte_interface] can only be used on a struct with named fields",
+        ));
+    };
+
+    let field_mapping = |field: &syn::Field| -> syn::Result<Option<Value>> {
+        let mut args = AttrArgs::parse(&field.attrs, ATTR)?;
+        let skip = args.take_flag("skip");
+        let rename = args.take("rename");
+        let mapping_type = args.take("type");
+        let field_reliability = args.take("reliability");
+        let field_explicit_timestamp = args.take_flag("explicit_timestamp");
+        let allow_unset = args.take_flag("allow_unset");
+        let field_description = args.take("description");
+        let field_doc = args.take("doc");
+        args.finish()?;
+
+        if skip {
+            return Ok(None);
+        }
+
+        let ident = field.ident.as_ref().expect("named field");
+        let endpoint = rename.map_or_else(
+            || rename_rule.apply_to_field(&ident.to_string()),
+            |rename| rename.value(),
+        );
+
+        let optional = generic_argument(&field.ty, "Option");
+        let mapping_type = match mapping_type {
+            Some(mapping_type) => mapping_type.value(),
+            None => astarte_type(optional.unwrap_or(&field.ty)).ok_or_else(|| {
+                syn::Error::new(
+                    field.ty.span(),
+                    "unknown Astarte type of the field, set it with #[astarte_interface(type = \"...\")]",
+                )
+            })?,
+        };
+
+        let mut mapping = Map::new();
+        mapping.insert(
+            "endpoint".to_string(),
+            Value::from(format!("{prefix}/{endpoint}")),
+        );
+        mapping.insert("type".to_string(), Value::from(mapping_type));
+
+        if properties {
+            if field_reliability.is_some() || field_explicit_timestamp {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "the reliability and explicit timestamp can only be set on a datastream",
+                ));
+            }
+
+            // the optional properties can be unset
+            if allow_unset || optional.is_some() {
+                mapping.insert("allow_unset".to_string(), Value::from(true));
+            }
+        } else {
+            if allow_unset {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "only the properties can be unset",
+                ));
+            }
+
+            let reliability = field_reliability.as_ref().or(reliability.as_ref());
+            if let Some(reliability) = reliability {
+                let reliability = one_of(
+                    &Some(reliability.clone()),
+                    &["unreliable", "guaranteed", "unique"],
+                )?;
+
+                mapping.insert("reliability".to_string(), Value::from(reliability));
+            }
+
+            if field_explicit_timestamp || explicit_timestamp {
+                mapping.insert("explicit_timestamp".to_string(), Value::from(true));
+            }
+        }
+
+        if let Some(description) = field_description {
+            mapping.insert("description".to_string(), Value::from(description.value()));
+        }
+
+        if let Some(doc) = field_doc {
+            mapping.insert("doc".to_string(), Value::from(doc.value()));
+        }
+
+        Ok(Some(Value::from(mapping)))
+    };
+
+    let mut errors = Errors::default();
+    let mut mappings = Vec::new();
+    for field in &fields.named {
+        if let Some(Some(mapping)) = errors.collect(field_mapping(field)) {
+            mappings.push(mapping);
+        }
+    }
+    errors.finish()?;
+
+    if mappings.is_empty() {
+        return Err(syn::Error::new(
+            ast.ident.span(),
+            "the interface must have at least one mapping",
+        ));
+    }
+
+    let mut interface = Map::new();
+    interface.insert("interface_name".to_string(), Value::from(name.value()));
+    interface.insert("version_major".to_string(), Value::from(major));
+    interface.insert("version_minor".to_string(), Value::from(minor));
+    interface.insert(
+        "type".to_string(),
+        Value::from(if properties {
+            "properties"
+        } else {
+            "datastream"
+        }),
+    );
+    interface.insert("ownership".to_string(), Value::from(ownership));
+    if object {
+        interface.insert("aggregation".to_string(), Value::from("object"));
+    }
+    if let Some(description) = description {
+        interface.insert("description".to_string(), Value::from(description.value()));
+    }
+    if let Some(doc) = doc {
+        interface.insert("doc".to_string(), Value::from(doc.value()));
+    }
+    interface.insert("mappings".to_string(), Value::from(mappings));
+
+    let json = serde_json::to_string_pretty(&Value::from(interface))
+        .map_err(|err| syn::Error::new(ast.ident.span(), err))?;
+
+    let ident = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// JSON of the interface generated from the struct.
+            pub const INTERFACE: &'static str = #json;
+        }
+    })
+}
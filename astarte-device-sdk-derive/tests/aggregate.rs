@@ -0,0 +1,358 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Tests of the `AstarteAggregate` derive.
+
+use std::collections::HashMap;
+
+use astarte_device_sdk::error::Error;
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::AstarteAggregate;
+
+#[derive(AstarteAggregate)]
+struct Measure {
+    value: f64,
+    unit: String,
+}
+
+#[derive(AstarteAggregate)]
+#[astarte_aggregate(rename_all = "UPPERCASE")]
+struct Reading {
+    #[astarte_aggregate(nested)]
+    temperature: Measure,
+    #[astarte_aggregate(nested, prefix = "h.")]
+    humidity: Measure,
+    sensor: i32,
+}
+
+fn duration_to_millis(
+    duration: std::time::Duration,
+) -> Result<AstarteType, astarte_device_sdk::types::TypeError> {
+    i64::try_from(duration.as_millis())
+        .map(AstarteType::LongInteger)
+        .map_err(|_| astarte_device_sdk::types::TypeError::Conversion)
+}
+
+#[derive(AstarteAggregate)]
+struct Timing {
+    #[astarte_aggregate(try_into_with = "duration_to_millis")]
+    elapsed: std::time::Duration,
+    #[astarte_aggregate(try_into_with = "duration_to_millis", rename = "timeoutMs")]
+    timeout: Option<std::time::Duration>,
+}
+
+#[derive(AstarteAggregate)]
+struct GenericSample<T> {
+    value: T,
+    previous: Option<T>,
+}
+
+/// Unit of measure, only used as a marker.
+struct Kelvin;
+
+#[derive(AstarteAggregate)]
+#[astarte_aggregate(no_bound, rename_all = "UPPERCASE")]
+struct Measured<U> {
+    value: f64,
+    #[astarte_aggregate(skip)]
+    _unit: std::marker::PhantomData<U>,
+}
+
+#[derive(AstarteAggregate)]
+#[astarte_aggregate(bound = "A: AstarteAggregate")]
+struct Tagged<A> {
+    #[astarte_aggregate(nested, prefix = "")]
+    aggregate: A,
+    tag: String,
+}
+
+#[derive(AstarteAggregate)]
+struct MeasureWrapper(Measure);
+
+#[derive(AstarteAggregate)]
+struct RawObject(HashMap<String, AstarteType>);
+
+#[derive(AstarteAggregate)]
+struct Wrapper<A>(A);
+
+#[derive(AstarteAggregate)]
+struct GeoPoint {
+    lat: f64,
+    lon: f64,
+}
+
+#[derive(AstarteAggregate)]
+struct Accuracy {
+    horizontal: f64,
+}
+
+#[derive(AstarteAggregate)]
+struct Position {
+    #[astarte_aggregate(flatten)]
+    point: GeoPoint,
+    #[astarte_aggregate(flatten)]
+    accuracy: Option<Accuracy>,
+    speed: f64,
+}
+
+#[test]
+fn test_astarte_aggregate_nested() {
+    let reading = Reading {
+        temperature: Measure {
+            value: 21.5,
+            unit: "C".to_string(),
+        },
+        humidity: Measure {
+            value: 40.0,
+            unit: "%".to_string(),
+        },
+        sensor: 1,
+    };
+
+    let expected = HashMap::from([
+        ("TEMPERATURE_value".to_string(), AstarteType::Double(21.5)),
+        (
+            "TEMPERATURE_unit".to_string(),
+            AstarteType::String("C".to_string()),
+        ),
+        ("h.value".to_string(), AstarteType::Double(40.0)),
+        ("h.unit".to_string(), AstarteType::String("%".to_string())),
+        ("SENSOR".to_string(), AstarteType::Integer(1)),
+    ]);
+
+    assert_eq!(reading.astarte_aggregate().unwrap(), expected);
+}
+
+#[derive(AstarteAggregate)]
+#[astarte_aggregate(rename_all = "camelCase")]
+struct LegacyReading {
+    sensor_id: i32,
+    #[astarte_aggregate(rename = "Temperature_C")]
+    temperature: f64,
+    #[astarte_aggregate(nested, rename = "hum")]
+    humidity: Measure,
+}
+
+#[test]
+fn test_astarte_aggregate_field_rename() {
+    let reading = LegacyReading {
+        sensor_id: 1,
+        temperature: 21.5,
+        humidity: Measure {
+            value: 40.0,
+            unit: "%".to_string(),
+        },
+    };
+
+    let expected = HashMap::from([
+        ("sensorId".to_string(), AstarteType::Integer(1)),
+        ("Temperature_C".to_string(), AstarteType::Double(21.5)),
+        ("hum_value".to_string(), AstarteType::Double(40.0)),
+        ("hum_unit".to_string(), AstarteType::String("%".to_string())),
+    ]);
+
+    assert_eq!(reading.astarte_aggregate().unwrap(), expected);
+}
+
+#[derive(AstarteAggregate)]
+struct Sample {
+    value: f64,
+    #[astarte_aggregate(skip)]
+    #[allow(dead_code)]
+    retries: std::cell::Cell<u32>,
+}
+
+#[test]
+fn test_astarte_aggregate_skip() {
+    let sample = Sample {
+        value: 1.5,
+        retries: std::cell::Cell::new(3),
+    };
+
+    let expected = HashMap::from([("value".to_string(), AstarteType::Double(1.5))]);
+
+    assert_eq!(sample.astarte_aggregate().unwrap(), expected);
+}
+
+#[derive(AstarteAggregate)]
+struct PartialReading {
+    sensor: i32,
+    note: Option<String>,
+    #[astarte_aggregate(nested)]
+    humidity: Option<Measure>,
+}
+
+#[test]
+fn test_astarte_aggregate_option() {
+    let reading = PartialReading {
+        sensor: 1,
+        note: None,
+        humidity: None,
+    };
+
+    let expected = HashMap::from([("sensor".to_string(), AstarteType::Integer(1))]);
+    assert_eq!(reading.astarte_aggregate().unwrap(), expected);
+
+    let reading = PartialReading {
+        sensor: 1,
+        note: Some("calibrated".to_string()),
+        humidity: Some(Measure {
+            value: 40.0,
+            unit: "%".to_string(),
+        }),
+    };
+
+    let expected = HashMap::from([
+        ("sensor".to_string(), AstarteType::Integer(1)),
+        (
+            "note".to_string(),
+            AstarteType::String("calibrated".to_string()),
+        ),
+        ("humidity_value".to_string(), AstarteType::Double(40.0)),
+        (
+            "humidity_unit".to_string(),
+            AstarteType::String("%".to_string()),
+        ),
+    ]);
+    assert_eq!(reading.astarte_aggregate().unwrap(), expected);
+}
+
+#[test]
+fn test_astarte_aggregate_try_into_with() {
+    let timing = Timing {
+        elapsed: std::time::Duration::from_secs(2),
+        timeout: Some(std::time::Duration::from_millis(500)),
+    };
+
+    let expected = HashMap::from([
+        ("elapsed".to_string(), AstarteType::LongInteger(2000)),
+        ("timeoutMs".to_string(), AstarteType::LongInteger(500)),
+    ]);
+    assert_eq!(timing.astarte_aggregate().unwrap(), expected);
+
+    let timing = Timing {
+        elapsed: std::time::Duration::MAX,
+        timeout: None,
+    };
+    assert!(matches!(
+        timing.astarte_aggregate(),
+        Err(Error::Types(
+            astarte_device_sdk::types::TypeError::Conversion
+        ))
+    ));
+}
+
+#[test]
+fn test_astarte_aggregate_generics() {
+    let sample = GenericSample {
+        value: 1.5,
+        previous: None,
+    };
+    assert_eq!(
+        sample.astarte_aggregate().unwrap(),
+        HashMap::from([("value".to_string(), AstarteType::Double(1.5))])
+    );
+
+    let measured = Measured::<Kelvin> {
+        value: 21.5,
+        _unit: std::marker::PhantomData,
+    };
+    assert_eq!(
+        measured.astarte_aggregate().unwrap(),
+        HashMap::from([("VALUE".to_string(), AstarteType::Double(21.5))])
+    );
+
+    let tagged = Tagged {
+        aggregate: GenericSample {
+            value: 1,
+            previous: Some(0),
+        },
+        tag: "counter".to_string(),
+    };
+    assert_eq!(
+        tagged.astarte_aggregate().unwrap(),
+        HashMap::from([
+            ("value".to_string(), AstarteType::Integer(1)),
+            ("previous".to_string(), AstarteType::Integer(0)),
+            (
+                "tag".to_string(),
+                AstarteType::String("counter".to_string())
+            ),
+        ])
+    );
+}
+
+#[test]
+fn test_astarte_aggregate_newtype() {
+    let measure = || Measure {
+        value: 21.5,
+        unit: "C".to_string(),
+    };
+    let expected = HashMap::from([
+        ("value".to_string(), AstarteType::Double(21.5)),
+        ("unit".to_string(), AstarteType::String("C".to_string())),
+    ]);
+
+    assert_eq!(
+        MeasureWrapper(measure()).astarte_aggregate().unwrap(),
+        expected
+    );
+    assert_eq!(
+        RawObject(expected.clone()).astarte_aggregate().unwrap(),
+        expected
+    );
+    assert_eq!(
+        Wrapper(MeasureWrapper(measure()))
+            .astarte_aggregate()
+            .unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn test_astarte_aggregate_flatten() {
+    let position = Position {
+        point: GeoPoint {
+            lat: 45.0,
+            lon: 7.5,
+        },
+        accuracy: None,
+        speed: 1.0,
+    };
+
+    let expected = HashMap::from([
+        ("lat".to_string(), AstarteType::Double(45.0)),
+        ("lon".to_string(), AstarteType::Double(7.5)),
+        ("speed".to_string(), AstarteType::Double(1.0)),
+    ]);
+    assert_eq!(position.astarte_aggregate().unwrap(), expected);
+
+    let position = Position {
+        point: GeoPoint {
+            lat: 45.0,
+            lon: 7.5,
+        },
+        accuracy: Some(Accuracy { horizontal: 5.0 }),
+        speed: 1.0,
+    };
+    let aggregate = position.astarte_aggregate().unwrap();
+    assert_eq!(aggregate.len(), 4);
+    assert_eq!(aggregate["horizontal"], AstarteType::Double(5.0));
+}
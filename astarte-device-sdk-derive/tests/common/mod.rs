@@ -0,0 +1,42 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Helpers shared by the tests of the derives.
+
+use astarte_device_sdk::chrono;
+use astarte_device_sdk::rumqttc;
+use astarte_device_sdk::{Aggregation, AstarteDeviceDataEvent, EventMetadata};
+
+/// Returns an event received on the path of an interface.
+pub fn data_event(interface: &str, path: &str, data: Aggregation) -> AstarteDeviceDataEvent {
+    AstarteDeviceDataEvent {
+        interface: interface.to_string(),
+        path: path.to_string(),
+        data,
+        stale: false,
+        metadata: EventMetadata {
+            received_at: chrono::Utc::now(),
+            timestamp: None,
+            qos: rumqttc::QoS::AtLeastOnce,
+            retain: false,
+            duplicate: false,
+        },
+    }
+}
@@ -0,0 +1,80 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Tests of the `AstarteEnum` derive.
+
+use std::collections::HashMap;
+
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{AstarteAggregate, AstarteEnum};
+
+#[derive(Debug, PartialEq, AstarteEnum)]
+#[astarte_enum(rename_all = "snake_case")]
+enum MachineState {
+    Idle,
+    RunningFast,
+    #[astarte_enum(rename = "KO")]
+    Failed,
+}
+
+#[derive(AstarteAggregate)]
+struct Machine {
+    state: MachineState,
+    previous: Option<MachineState>,
+}
+
+#[test]
+fn test_astarte_enum() {
+    let machine = Machine {
+        state: MachineState::RunningFast,
+        previous: Some(MachineState::Failed),
+    };
+
+    let expected = HashMap::from([
+        (
+            "state".to_string(),
+            AstarteType::String("running_fast".to_string()),
+        ),
+        (
+            "previous".to_string(),
+            AstarteType::String("KO".to_string()),
+        ),
+    ]);
+    assert_eq!(machine.astarte_aggregate().unwrap(), expected);
+
+    assert_eq!(
+        AstarteType::from(&MachineState::Idle),
+        AstarteType::String("idle".to_string())
+    );
+    assert_eq!(
+        MachineState::try_from(AstarteType::String("KO".to_string())).unwrap(),
+        MachineState::Failed
+    );
+
+    for invalid in [
+        AstarteType::String("Failed".to_string()),
+        AstarteType::Integer(1),
+    ] {
+        assert!(matches!(
+            MachineState::try_from(invalid),
+            Err(astarte_device_sdk::types::TypeError::Conversion)
+        ));
+    }
+}
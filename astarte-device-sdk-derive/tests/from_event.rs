@@ -0,0 +1,551 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Tests of the `FromEvent` derive.
+
+use std::collections::HashMap;
+
+use astarte_device_sdk::chrono;
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{event, Aggregation, AstarteEnum, AstarteNewtype, FromEvent};
+
+use crate::common::data_event;
+
+mod common;
+
+#[derive(Debug, PartialEq, FromEvent)]
+#[from_event(
+    interface = "org.astarte-platform.test.Readings",
+    path = "/%{sensor_id}"
+)]
+struct ReadingEvent {
+    #[mapping(param = "sensor_id")]
+    sensor: u32,
+    value: f64,
+    #[mapping(endpoint = "unit")]
+    unit_name: Option<String>,
+}
+
+#[derive(Debug, PartialEq, FromEvent)]
+#[from_event(
+    interface = "org.astarte-platform.test.Status",
+    aggregation = "individual"
+)]
+enum StatusEvent {
+    #[mapping(endpoint = "/%{id}/temperature", param = "id")]
+    Temperature(String, f64),
+    #[mapping(endpoint = "/enabled")]
+    Enabled(bool),
+}
+
+#[derive(Debug, PartialEq, FromEvent)]
+#[from_event(
+    interface = "org.astarte-platform.test.Config",
+    interface_type = "properties"
+)]
+enum ConfigEvent {
+    #[mapping(endpoint = "/%{sensor_id}/enable", param = "sensor_id")]
+    Enable(u32, event::Property<bool>),
+    #[mapping(endpoint = "/name")]
+    Name(event::Property<String>),
+}
+
+#[derive(Debug, PartialEq, FromEvent)]
+#[from_event(
+    interface = "org.astarte-platform.test.Sensors",
+    path = "/sensor",
+    rename_all = "camelCase"
+)]
+struct SensorEvent {
+    sample_rate: i32,
+    #[mapping(endpoint = "unit")]
+    unit_name: String,
+    last_value: Option<f64>,
+}
+
+fn millis_to_duration(
+    value: AstarteType,
+) -> Result<std::time::Duration, astarte_device_sdk::types::TypeError> {
+    let millis: i64 = value.try_into()?;
+
+    u64::try_from(millis)
+        .map(std::time::Duration::from_millis)
+        .map_err(|_| astarte_device_sdk::types::TypeError::Conversion)
+}
+
+#[derive(Debug, PartialEq, FromEvent)]
+#[from_event(interface = "org.astarte-platform.test.Timings", path = "/timing")]
+struct TimingEvent {
+    #[mapping(try_from_with = "millis_to_duration")]
+    elapsed: std::time::Duration,
+    #[mapping(endpoint = "timeoutMs", try_from_with = "millis_to_duration")]
+    timeout: Option<std::time::Duration>,
+}
+
+#[derive(Debug, PartialEq, AstarteEnum)]
+#[astarte_enum(rename_all = "snake_case")]
+enum MachineState {
+    Idle,
+    #[astarte_enum(rename = "KO")]
+    Failed,
+}
+
+#[derive(Debug, PartialEq, AstarteNewtype)]
+struct DeviceId(String);
+
+#[derive(Debug, PartialEq, FromEvent)]
+#[from_event(interface = "org.astarte-platform.test.Arrays", path = "/arrays")]
+struct ArraysEvent {
+    doubles: Vec<f64>,
+    longs: Option<Vec<i64>>,
+    states: Vec<MachineState>,
+    blobs: Vec<Vec<u8>>,
+    blob: Vec<u8>,
+    missing: Option<Vec<bool>>,
+}
+
+#[derive(Debug, PartialEq, FromEvent)]
+#[from_event(
+    interface = "org.astarte-platform.test.Arrays",
+    aggregation = "individual"
+)]
+enum ArrayEvent {
+    #[mapping(endpoint = "/%{id}/names", param = "id")]
+    Names(u32, Vec<DeviceId>),
+}
+
+#[derive(Debug, PartialEq, FromEvent)]
+#[from_event(interface = "org.astarte-platform.test.Samples", path = "/sample")]
+struct SampleEvent {
+    value: f64,
+    #[mapping(timestamp)]
+    at: chrono::DateTime<chrono::Utc>,
+    #[mapping(timestamp)]
+    sampled_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, PartialEq, FromEvent)]
+#[from_event(interface = "org.astarte-platform.test.Defaults", path = "/defaults")]
+struct DefaultsEvent {
+    value: f64,
+    #[mapping(default)]
+    count: i32,
+    #[mapping(default = "String::from(\"unknown\")")]
+    unit: String,
+    #[mapping(default = "Some(1.0)")]
+    gain: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, FromEvent)]
+#[from_event(dispatch)]
+enum DispatchEvent {
+    Status(StatusEvent),
+    Defaults(DefaultsEvent),
+}
+
+#[test]
+fn test_from_event_object() {
+    let interface = "org.astarte-platform.test.Readings";
+    let object = |fields: &[(&str, AstarteType)]| {
+        Aggregation::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+        )
+    };
+
+    let event = data_event(
+        interface,
+        "/42",
+        object(&[
+            ("value", AstarteType::Double(1.5)),
+            ("unit", AstarteType::String("m".to_string())),
+        ]),
+    );
+    assert_eq!(
+        ReadingEvent::from_event(event).unwrap(),
+        ReadingEvent {
+            sensor: 42,
+            value: 1.5,
+            unit_name: Some("m".to_string()),
+        }
+    );
+
+    let event = data_event(
+        interface,
+        "/1",
+        object(&[("value", AstarteType::Double(2.0))]),
+    );
+    assert_eq!(ReadingEvent::from_event(event).unwrap().unit_name, None);
+
+    let event = data_event(interface, "/1", object(&[]));
+    assert!(matches!(
+        ReadingEvent::from_event(event),
+        Err(event::FromEventError::MissingField(field)) if field == "value"
+    ));
+
+    let event = data_event(
+        interface,
+        "/first",
+        object(&[("value", AstarteType::Double(2.0))]),
+    );
+    assert!(matches!(
+        ReadingEvent::from_event(event),
+        Err(event::FromEventError::Param { .. })
+    ));
+
+    let event = data_event(
+        "org.astarte-platform.test.Other",
+        "/1",
+        object(&[("value", AstarteType::Double(2.0))]),
+    );
+    assert!(matches!(
+        ReadingEvent::from_event(event),
+        Err(event::FromEventError::Interface { .. })
+    ));
+}
+
+#[test]
+fn test_from_event_individual() {
+    let interface = "org.astarte-platform.test.Status";
+
+    let event = data_event(
+        interface,
+        "/kitchen/temperature",
+        Aggregation::Individual(AstarteType::Double(21.5)),
+    );
+    assert_eq!(
+        StatusEvent::from_event(event).unwrap(),
+        StatusEvent::Temperature("kitchen".to_string(), 21.5)
+    );
+
+    let event = data_event(
+        interface,
+        "/enabled",
+        Aggregation::Individual(AstarteType::Boolean(true)),
+    );
+    assert_eq!(
+        StatusEvent::from_event(event).unwrap(),
+        StatusEvent::Enabled(true)
+    );
+
+    let event = data_event(
+        interface,
+        "/enabled",
+        Aggregation::Individual(AstarteType::Integer(1)),
+    );
+    assert!(matches!(
+        StatusEvent::from_event(event),
+        Err(event::FromEventError::Conversion { .. })
+    ));
+
+    let event = data_event(
+        interface,
+        "/disabled",
+        Aggregation::Individual(AstarteType::Boolean(true)),
+    );
+    assert!(matches!(
+        StatusEvent::from_event(event),
+        Err(event::FromEventError::Path { .. })
+    ));
+}
+
+#[test]
+fn test_from_event_properties() {
+    let interface = "org.astarte-platform.test.Config";
+
+    let event = data_event(
+        interface,
+        "/3/enable",
+        Aggregation::Individual(AstarteType::Boolean(true)),
+    );
+    assert_eq!(
+        ConfigEvent::from_event(event).unwrap(),
+        ConfigEvent::Enable(3, event::Property::Set(true))
+    );
+
+    let event = data_event(interface, "/3/enable", Aggregation::Unset);
+    assert_eq!(
+        ConfigEvent::from_event(event).unwrap(),
+        ConfigEvent::Enable(3, event::Property::Unset)
+    );
+
+    let event = data_event(interface, "/name", Aggregation::Unset);
+    let ConfigEvent::Name(name) = ConfigEvent::from_event(event).unwrap() else {
+        panic!("expected the name property");
+    };
+    assert_eq!(name.into_option(), None);
+
+    let event = data_event(
+        interface,
+        "/name",
+        Aggregation::Individual(AstarteType::Integer(1)),
+    );
+    assert!(matches!(
+        ConfigEvent::from_event(event),
+        Err(event::FromEventError::Conversion { .. })
+    ));
+}
+
+#[test]
+fn test_from_event_rename_all() {
+    let interface = "org.astarte-platform.test.Sensors";
+    let fields = HashMap::from([
+        ("sampleRate".to_string(), AstarteType::Integer(10)),
+        ("unit".to_string(), AstarteType::String("m".to_string())),
+        ("lastValue".to_string(), AstarteType::Double(1.5)),
+    ]);
+
+    let event = data_event(interface, "/sensor", Aggregation::Object(fields));
+    assert_eq!(
+        SensorEvent::from_event(event).unwrap(),
+        SensorEvent {
+            sample_rate: 10,
+            unit_name: "m".to_string(),
+            last_value: Some(1.5),
+        }
+    );
+
+    let fields = HashMap::from([
+        ("sample_rate".to_string(), AstarteType::Integer(10)),
+        ("unit".to_string(), AstarteType::String("m".to_string())),
+    ]);
+    let event = data_event(interface, "/sensor", Aggregation::Object(fields));
+    assert!(matches!(
+        SensorEvent::from_event(event),
+        Err(event::FromEventError::MissingField(field)) if field == "sampleRate"
+    ));
+}
+
+#[test]
+fn test_from_event_try_from_with() {
+    let interface = "org.astarte-platform.test.Timings";
+
+    let fields = HashMap::from([
+        ("elapsed".to_string(), AstarteType::LongInteger(2000)),
+        ("timeoutMs".to_string(), AstarteType::LongInteger(500)),
+    ]);
+    let event = data_event(interface, "/timing", Aggregation::Object(fields));
+    assert_eq!(
+        TimingEvent::from_event(event).unwrap(),
+        TimingEvent {
+            elapsed: std::time::Duration::from_secs(2),
+            timeout: Some(std::time::Duration::from_millis(500)),
+        }
+    );
+
+    let fields = HashMap::from([("elapsed".to_string(), AstarteType::LongInteger(-1))]);
+    let event = data_event(interface, "/timing", Aggregation::Object(fields));
+    assert!(matches!(
+        TimingEvent::from_event(event),
+        Err(event::FromEventError::Conversion { field, .. }) if field == "elapsed"
+    ));
+}
+
+#[test]
+fn test_from_event_arrays() {
+    let interface = "org.astarte-platform.test.Arrays";
+
+    let fields = HashMap::from([
+        ("doubles".to_string(), AstarteType::IntegerArray(vec![1, 2])),
+        (
+            "longs".to_string(),
+            AstarteType::LongIntegerArray(vec![i64::MAX]),
+        ),
+        (
+            "states".to_string(),
+            AstarteType::StringArray(vec!["idle".to_string(), "KO".to_string()]),
+        ),
+        (
+            "blobs".to_string(),
+            AstarteType::BinaryBlobArray(vec![vec![1], vec![]]),
+        ),
+        ("blob".to_string(), AstarteType::BinaryBlob(vec![2, 3])),
+    ]);
+    let event = data_event(interface, "/arrays", Aggregation::Object(fields.clone()));
+    assert_eq!(
+        ArraysEvent::from_event(event).unwrap(),
+        ArraysEvent {
+            doubles: vec![1.0, 2.0],
+            longs: Some(vec![i64::MAX]),
+            states: vec![MachineState::Idle, MachineState::Failed],
+            blobs: vec![vec![1], vec![]],
+            blob: vec![2, 3],
+            missing: None,
+        }
+    );
+
+    let event = data_event(
+        interface,
+        "/1/names",
+        Aggregation::Individual(AstarteType::StringArray(vec!["device_1".to_string()])),
+    );
+    assert_eq!(
+        ArrayEvent::from_event(event).unwrap(),
+        ArrayEvent::Names(1, vec![DeviceId("device_1".to_string())])
+    );
+
+    // the elements must all be converted
+    let mut fields = fields;
+    fields.insert(
+        "states".to_string(),
+        AstarteType::StringArray(vec!["idle".to_string(), "unknown".to_string()]),
+    );
+    let event = data_event(interface, "/arrays", Aggregation::Object(fields));
+    assert!(matches!(
+        ArraysEvent::from_event(event),
+        Err(event::FromEventError::Conversion { .. })
+    ));
+
+    let event = data_event(
+        interface,
+        "/1/names",
+        Aggregation::Individual(AstarteType::String("device_1".to_string())),
+    );
+    assert!(matches!(
+        ArrayEvent::from_event(event),
+        Err(event::FromEventError::Conversion { .. })
+    ));
+}
+
+#[test]
+fn test_from_event_timestamp() {
+    let interface = "org.astarte-platform.test.Samples";
+    let fields = HashMap::from([("value".to_string(), AstarteType::Double(0.5))]);
+
+    let mut event = data_event(interface, "/sample", Aggregation::Object(fields));
+    let received_at = event.metadata.received_at;
+    assert_eq!(
+        SampleEvent::from_event(event.clone()).unwrap(),
+        SampleEvent {
+            value: 0.5,
+            at: received_at,
+            sampled_at: None,
+        }
+    );
+
+    let timestamp = chrono::TimeZone::timestamp_opt(&chrono::Utc, 1537449422, 0).unwrap();
+    event.metadata.timestamp = Some(timestamp);
+    assert_eq!(
+        SampleEvent::from_event(event).unwrap(),
+        SampleEvent {
+            value: 0.5,
+            at: timestamp,
+            sampled_at: Some(timestamp),
+        }
+    );
+
+    // the timestamp is not a field of the object
+    let fields = HashMap::from([
+        ("value".to_string(), AstarteType::Double(0.5)),
+        ("at".to_string(), AstarteType::DateTime(timestamp)),
+    ]);
+    let event = data_event(interface, "/sample", Aggregation::Object(fields));
+    let received_at = event.metadata.received_at;
+    assert_eq!(SampleEvent::from_event(event).unwrap().at, received_at);
+}
+
+#[test]
+fn test_from_event_default() {
+    let interface = "org.astarte-platform.test.Defaults";
+
+    let fields = HashMap::from([("value".to_string(), AstarteType::Double(0.5))]);
+    let event = data_event(interface, "/defaults", Aggregation::Object(fields));
+    assert_eq!(
+        DefaultsEvent::from_event(event).unwrap(),
+        DefaultsEvent {
+            value: 0.5,
+            count: 0,
+            unit: "unknown".to_string(),
+            gain: Some(1.0),
+        }
+    );
+
+    let fields = HashMap::from([
+        ("value".to_string(), AstarteType::Double(0.5)),
+        ("count".to_string(), AstarteType::Integer(3)),
+        ("unit".to_string(), AstarteType::String("mV".to_string())),
+        ("gain".to_string(), AstarteType::Double(2.0)),
+    ]);
+    let event = data_event(interface, "/defaults", Aggregation::Object(fields));
+    assert_eq!(
+        DefaultsEvent::from_event(event).unwrap(),
+        DefaultsEvent {
+            value: 0.5,
+            count: 3,
+            unit: "mV".to_string(),
+            gain: Some(2.0),
+        }
+    );
+
+    // the fields without a default are still required
+    let event = data_event(interface, "/defaults", Aggregation::Object(HashMap::new()));
+    assert!(matches!(
+        DefaultsEvent::from_event(event),
+        Err(event::FromEventError::MissingField(field)) if field == "value"
+    ));
+}
+
+#[test]
+fn test_from_event_dispatch() {
+    let event = data_event(
+        "org.astarte-platform.test.Status",
+        "/enabled",
+        Aggregation::Individual(AstarteType::Boolean(true)),
+    );
+    assert_eq!(
+        DispatchEvent::from_event(event).unwrap(),
+        DispatchEvent::Status(StatusEvent::Enabled(true))
+    );
+
+    let fields = HashMap::from([("value".to_string(), AstarteType::Double(0.5))]);
+    let event = data_event(
+        "org.astarte-platform.test.Defaults",
+        "/defaults",
+        Aggregation::Object(fields),
+    );
+    assert!(matches!(
+        DispatchEvent::from_event(event).unwrap(),
+        DispatchEvent::Defaults(DefaultsEvent { value, .. }) if value == 0.5
+    ));
+
+    // the errors of the variant are returned
+    let event = data_event(
+        "org.astarte-platform.test.Status",
+        "/disabled",
+        Aggregation::Individual(AstarteType::Boolean(true)),
+    );
+    assert!(matches!(
+        DispatchEvent::from_event(event),
+        Err(event::FromEventError::Path { .. })
+    ));
+
+    let event = data_event(
+        "org.astarte-platform.test.Unknown",
+        "/enabled",
+        Aggregation::Individual(AstarteType::Boolean(true)),
+    );
+    assert!(matches!(
+        DispatchEvent::from_event(event),
+        Err(event::FromEventError::UnknownInterface(interface))
+            if interface == "org.astarte-platform.test.Unknown"
+    ));
+}
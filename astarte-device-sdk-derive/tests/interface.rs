@@ -0,0 +1,126 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Tests of the `astarte_interface` attribute.
+
+use std::str::FromStr;
+
+use astarte_device_sdk::interface::def::Ownership;
+use astarte_device_sdk::{astarte_interface, Interface};
+
+#[astarte_interface(
+    name = "org.astarte-platform.test.Sensor",
+    version = "1.2",
+    aggregation = "object",
+    path = "/%{sensor_id}",
+    rename_all = "camelCase",
+    reliability = "guaranteed",
+    explicit_timestamp
+)]
+#[allow(dead_code)]
+struct SensorInterface {
+    sample_rate: i32,
+    #[astarte_interface(rename = "unit", description = "Unit of the values")]
+    unit_name: String,
+    values: Vec<f64>,
+    raw: Vec<u8>,
+    #[astarte_interface(skip)]
+    cached: std::cell::Cell<u32>,
+}
+
+#[astarte_interface(
+    name = "org.astarte-platform.test.Config",
+    version = "0.1",
+    interface_type = "properties",
+    ownership = "server",
+    path = "/config"
+)]
+#[allow(dead_code)]
+struct ConfigInterface {
+    enable: bool,
+    name: Option<String>,
+    #[astarte_interface(type = "longinteger")]
+    period: std::time::Duration,
+}
+
+#[test]
+fn test_astarte_interface_attribute() {
+    let interface = Interface::from_str(SensorInterface::INTERFACE).unwrap();
+    assert_eq!(
+        interface.interface_name(),
+        "org.astarte-platform.test.Sensor"
+    );
+    assert_eq!(interface.version_major(), 1);
+    assert_eq!(interface.version_minor(), 2);
+
+    let json: serde_json::Value = serde_json::from_str(SensorInterface::INTERFACE).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "interface_name": "org.astarte-platform.test.Sensor",
+            "version_major": 1,
+            "version_minor": 2,
+            "type": "datastream",
+            "ownership": "device",
+            "aggregation": "object",
+            "mappings": [
+                {
+                    "endpoint": "/%{sensor_id}/sampleRate",
+                    "type": "integer",
+                    "reliability": "guaranteed",
+                    "explicit_timestamp": true
+                },
+                {
+                    "endpoint": "/%{sensor_id}/unit",
+                    "type": "string",
+                    "reliability": "guaranteed",
+                    "explicit_timestamp": true,
+                    "description": "Unit of the values"
+                },
+                {
+                    "endpoint": "/%{sensor_id}/values",
+                    "type": "doublearray",
+                    "reliability": "guaranteed",
+                    "explicit_timestamp": true
+                },
+                {
+                    "endpoint": "/%{sensor_id}/raw",
+                    "type": "binaryblob",
+                    "reliability": "guaranteed",
+                    "explicit_timestamp": true
+                }
+            ]
+        })
+    );
+
+    let interface = Interface::from_str(ConfigInterface::INTERFACE).unwrap();
+    assert!(interface.is_property());
+    assert_eq!(interface.ownership(), Ownership::Server);
+
+    let json: serde_json::Value = serde_json::from_str(ConfigInterface::INTERFACE).unwrap();
+    assert_eq!(
+        json["mappings"],
+        serde_json::json!([
+            { "endpoint": "/config/enable", "type": "boolean" },
+            { "endpoint": "/config/name", "type": "string", "allow_unset": true },
+            { "endpoint": "/config/period", "type": "longinteger" }
+        ])
+    );
+}
@@ -0,0 +1,100 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Tests of the `IntoEvent` derive.
+
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{event, Aggregation, FromEvent, IntoEvent};
+
+use crate::common::data_event;
+
+mod common;
+
+#[derive(Debug, PartialEq, FromEvent, IntoEvent)]
+#[from_event(
+    interface = "org.astarte-platform.test.Status",
+    aggregation = "individual"
+)]
+#[into_event(interface = "org.astarte-platform.test.Status")]
+enum StatusEvent {
+    #[mapping(endpoint = "/%{id}/temperature", param = "id")]
+    Temperature(String, f64),
+    #[mapping(endpoint = "/enabled")]
+    Enabled(bool),
+}
+
+#[derive(Debug, PartialEq, FromEvent, IntoEvent)]
+#[from_event(
+    interface = "org.astarte-platform.test.Config",
+    interface_type = "properties"
+)]
+#[into_event(
+    interface = "org.astarte-platform.test.Config",
+    interface_type = "properties"
+)]
+enum ConfigEvent {
+    #[mapping(endpoint = "/%{sensor_id}/enable", param = "sensor_id")]
+    Enable(u32, event::Property<bool>),
+    #[mapping(endpoint = "/name")]
+    Name(event::Property<String>),
+}
+
+#[test]
+fn test_into_event() {
+    let event = StatusEvent::Temperature("kitchen".to_string(), 21.5)
+        .into_event()
+        .unwrap();
+    assert_eq!(
+        event,
+        event::OutgoingEvent {
+            interface: "org.astarte-platform.test.Status".to_string(),
+            path: "/kitchen/temperature".to_string(),
+            data: AstarteType::Double(21.5),
+        }
+    );
+
+    let event = StatusEvent::Enabled(true).into_event().unwrap();
+    assert_eq!(event.path, "/enabled");
+    assert_eq!(event.data, AstarteType::Boolean(true));
+
+    let event = ConfigEvent::Enable(3, event::Property::Unset)
+        .into_event()
+        .unwrap();
+    assert_eq!(event.path, "/3/enable");
+    assert_eq!(event.data, AstarteType::Unset);
+
+    // the conversion is symmetric
+    let configs = || {
+        [
+            ConfigEvent::Enable(1, event::Property::Set(false)),
+            ConfigEvent::Name(event::Property::Set("sensor".to_string())),
+        ]
+    };
+    for (config, expected) in configs().into_iter().zip(configs()) {
+        let event = config.into_event().unwrap();
+        let event = data_event(
+            &event.interface,
+            &event.path,
+            Aggregation::Individual(event.data),
+        );
+
+        assert_eq!(ConfigEvent::from_event(event).unwrap(), expected);
+    }
+}
@@ -0,0 +1,97 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Tests of the `AstarteNewtype` derive.
+
+use std::collections::HashMap;
+
+use astarte_device_sdk::types::{AstarteType, TypeError};
+use astarte_device_sdk::{Aggregation, AstarteAggregate, AstarteNewtype, FromEvent};
+
+use crate::common::data_event;
+
+mod common;
+
+#[derive(Debug, PartialEq, AstarteNewtype)]
+struct DeviceId(String);
+
+#[derive(Debug, PartialEq, AstarteNewtype)]
+struct Celsius {
+    degrees: f64,
+}
+
+#[derive(AstarteAggregate)]
+struct Registration {
+    device: DeviceId,
+    temperature: Celsius,
+}
+
+#[derive(Debug, PartialEq, FromEvent)]
+#[from_event(
+    interface = "org.astarte-platform.test.Registration",
+    path = "/registration"
+)]
+struct RegistrationEvent {
+    device: DeviceId,
+    temperature: Option<Celsius>,
+}
+
+#[test]
+fn test_astarte_newtype() {
+    let registration = Registration {
+        device: DeviceId("device_1".to_string()),
+        temperature: Celsius { degrees: 21.5 },
+    };
+
+    let object = registration.astarte_aggregate().unwrap();
+    assert_eq!(
+        object,
+        HashMap::from([
+            (
+                "device".to_string(),
+                AstarteType::String("device_1".to_string())
+            ),
+            ("temperature".to_string(), AstarteType::Double(21.5)),
+        ])
+    );
+
+    let event = data_event(
+        "org.astarte-platform.test.Registration",
+        "/registration",
+        Aggregation::Object(object),
+    );
+    assert_eq!(
+        RegistrationEvent::from_event(event).unwrap(),
+        RegistrationEvent {
+            device: DeviceId("device_1".to_string()),
+            temperature: Some(Celsius { degrees: 21.5 }),
+        }
+    );
+
+    // the errors are the ones of the wrapped type
+    assert!(matches!(
+        Celsius::try_from(AstarteType::Boolean(true)),
+        Err(TypeError::Conversion)
+    ));
+    assert!(matches!(
+        AstarteType::try_from(Celsius { degrees: f64::NAN }),
+        Err(TypeError::FloatError)
+    ));
+}
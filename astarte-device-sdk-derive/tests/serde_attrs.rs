@@ -0,0 +1,76 @@
+/*
+ * This file is part of Astarte.
+ *
+ * Copyright 2023 SECO Mind Srl
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *    http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Tests of the serde attributes reused by the derives.
+
+use std::collections::HashMap;
+
+use astarte_device_sdk::types::AstarteType;
+use astarte_device_sdk::{Aggregation, AstarteAggregate, FromEvent};
+
+use crate::common::data_event;
+
+mod common;
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize, AstarteAggregate, FromEvent)]
+#[serde(rename_all = "camelCase")]
+#[astarte_aggregate(use_serde_attrs)]
+#[from_event(
+    interface = "org.astarte-platform.test.Serde",
+    path = "/serde",
+    use_serde_attrs
+)]
+struct SerdeReading {
+    sensor_id: i32,
+    #[serde(rename = "temp")]
+    temperature: f64,
+    #[serde(rename(serialize = "out", deserialize = "in"))]
+    direction: String,
+}
+
+#[test]
+fn test_serde_attrs() {
+    let reading = || SerdeReading {
+        sensor_id: 1,
+        temperature: 21.5,
+        direction: "north".to_string(),
+    };
+
+    assert_eq!(
+        reading().astarte_aggregate().unwrap(),
+        HashMap::from([
+            ("sensorId".to_string(), AstarteType::Integer(1)),
+            ("temp".to_string(), AstarteType::Double(21.5)),
+            ("out".to_string(), AstarteType::String("north".to_string())),
+        ])
+    );
+
+    let fields = HashMap::from([
+        ("sensorId".to_string(), AstarteType::Integer(1)),
+        ("temp".to_string(), AstarteType::Double(21.5)),
+        ("in".to_string(), AstarteType::String("north".to_string())),
+    ]);
+    let event = data_event(
+        "org.astarte-platform.test.Serde",
+        "/serde",
+        Aggregation::Object(fields),
+    );
+    assert_eq!(SerdeReading::from_event(event).unwrap(), reading());
+}
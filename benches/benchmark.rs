@@ -16,7 +16,11 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use astarte_device_sdk::crypto::bench;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use astarte_device_sdk::database::{cache::CachedDatabase, AstarteDatabase, AstarteSqliteDatabase};
+use astarte_device_sdk::{crypto::bench, interface, types::AstarteType, Interface};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 pub fn crypto_benchmark(c: &mut Criterion) {
@@ -30,5 +34,81 @@ pub fn crypto_benchmark(c: &mut Criterion) {
     });
 }
 
+fn interface_with_mappings(count: usize) -> Interface {
+    let mappings = (0..count)
+        .map(|i| format!(r#"{{"endpoint": "/%{{sensor_id}}/value{i}", "type": "double"}}"#))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let json = format!(
+        r#"{{
+            "interface_name": "org.astarte-platform.bench.Values",
+            "version_major": 0,
+            "version_minor": 1,
+            "type": "datastream",
+            "ownership": "device",
+            "mappings": [{mappings}]
+        }}"#
+    );
+
+    Interface::from_str(&json).expect("valid interface")
+}
+
+pub fn mapping_benchmark(c: &mut Criterion) {
+    for count in [10, 100, 500] {
+        let interface = interface_with_mappings(count);
+        let path = format!("/sensor/value{}", count - 1);
+
+        c.bench_function(&format!("resolve mapping with {count} mappings"), |b| {
+            b.iter(|| interface::bench::resolve_mapping(black_box(&interface), black_box(&path)))
+        });
+    }
+}
+
+async fn load_props<S>(database: &S)
+where
+    S: AstarteDatabase + Sync + ?Sized,
+{
+    for i in 0..100 {
+        let path = format!("/{i}/value");
+
+        black_box(database.load_prop("com.bench", &path, 0).await.unwrap());
+    }
+}
+
+pub fn store_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("runtime");
+
+    let database = rt.block_on(async {
+        let sqlite = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let database = CachedDatabase::new(sqlite, 100);
+
+        for i in 0..100 {
+            let path = format!("/{i}/value");
+
+            database
+                .store_prop("com.bench", &path, &AstarteType::Integer(i), 0)
+                .await
+                .unwrap();
+        }
+
+        Arc::new(database)
+    });
+
+    c.bench_function("load cached properties static dispatch", |b| {
+        b.iter(|| rt.block_on(load_props(database.as_ref())))
+    });
+
+    let database: Arc<dyn AstarteDatabase + Sync + Send> = database;
+    c.bench_function("load cached properties dynamic dispatch", |b| {
+        b.iter(|| rt.block_on(load_props(database.as_ref())))
+    });
+}
+
 criterion_group!(crypto, crypto_benchmark);
-criterion_main!(crypto);
+criterion_group!(mapping, mapping_benchmark);
+criterion_group!(store, store_benchmark);
+criterion_main!(crypto, mapping, store);
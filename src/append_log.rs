@@ -0,0 +1,199 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Append-only file of the [journal](crate::database::journal) and the
+//! [published log](crate::dedup).
+//!
+//! Each append is synced to disk, and truncated if it fails, so after a crash the file has only
+//! the complete records followed by at most a torn one, discarded by the owner when the file is
+//! opened again. The file is compacted by writing a temporary file and renaming it over the old
+//! one, so a crash leaves either one intact.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+
+/// Append-only file synced on each write.
+#[derive(Debug)]
+pub(crate) struct AppendLog {
+    path: PathBuf,
+    file: File,
+    /// Length of the file, to discard a partially appended record.
+    len: u64,
+}
+
+impl AppendLog {
+    /// Opens the file at the given path, creating it if missing, and returns it with its content.
+    pub(crate) fn open(path: PathBuf) -> io::Result<(Self, Vec<u8>)> {
+        // a temporary file is left by a crash while compacting, the log is still valid
+        match std::fs::remove_file(tmp_path(&path)) {
+            Ok(()) => debug!("removed the temporary file of {}", path.display()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        let content = match std::fs::read(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+
+        let log = Self {
+            file: open_append(&path)?,
+            path,
+            len: content.len() as u64,
+        };
+
+        Ok((log, content))
+    }
+
+    /// Discards the content after the valid records, the next ones are appended after them.
+    pub(crate) fn truncate(&mut self, valid: usize) -> io::Result<()> {
+        let valid = valid as u64;
+        if valid >= self.len {
+            return Ok(());
+        }
+
+        warn!(
+            "discarding {} bytes of a torn record at the end of {}",
+            self.len - valid,
+            self.path.display()
+        );
+
+        self.file.set_len(valid)?;
+        self.len = valid;
+
+        Ok(())
+    }
+
+    /// Returns true if the file is empty.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends the records to the file and syncs it.
+    pub(crate) fn append(&mut self, buf: &[u8]) -> io::Result<()> {
+        let res = self
+            .file
+            .write_all(buf)
+            .and_then(|()| self.file.sync_data());
+
+        if let Err(err) = res {
+            // don't leave a torn record before the next ones
+            if let Err(err) = self.file.set_len(self.len) {
+                warn!(
+                    "couldn't truncate {} after a failed write: {err}",
+                    self.path.display()
+                );
+            }
+
+            return Err(err);
+        }
+
+        self.len += buf.len() as u64;
+
+        Ok(())
+    }
+
+    /// Replaces the content of the file with the records.
+    pub(crate) fn rewrite(&mut self, buf: &[u8]) -> io::Result<()> {
+        let tmp = tmp_path(&self.path);
+        let mut file = File::create(&tmp)?;
+        file.write_all(buf).and_then(|()| file.sync_all())?;
+        drop(file);
+
+        std::fs::rename(&tmp, &self.path)?;
+        sync_dir(&self.path);
+
+        self.file = open_append(&self.path)?;
+        self.len = buf.len() as u64;
+
+        Ok(())
+    }
+}
+
+pub(crate) fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    PathBuf::from(tmp)
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Syncs the directory of the file, so the rename is persisted.
+fn sync_dir(path: &Path) {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    // Opening a directory is not supported on every platform
+    if let Ok(file) = File::open(dir) {
+        if let Err(err) = file.sync_all() {
+            debug!("couldn't sync the directory {}: {err}", dir.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_append_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("records.log");
+
+        let (mut log, content) = AppendLog::open(path.clone()).unwrap();
+        assert!(content.is_empty());
+        assert!(log.is_empty());
+
+        log.append(b"abc").unwrap();
+        log.append(b"def").unwrap();
+        drop(log);
+
+        // the torn record is discarded by the owner
+        let (mut log, content) = AppendLog::open(path.clone()).unwrap();
+        assert_eq!(content, b"abcdef");
+        log.truncate(3).unwrap();
+        log.append(b"ghi").unwrap();
+        drop(log);
+
+        let (log, content) = AppendLog::open(path.clone()).unwrap();
+        assert_eq!(content, b"abcghi");
+
+        // a crash while compacting leaves the old content
+        std::fs::write(tmp_path(&path), b"xyz").unwrap();
+        drop(log);
+        let (mut log, content) = AppendLog::open(path.clone()).unwrap();
+        assert_eq!(content, b"abcghi");
+        assert!(!tmp_path(&path).exists());
+
+        log.rewrite(b"ghi").unwrap();
+        log.append(b"jkl").unwrap();
+        drop(log);
+
+        let (_, content) = AppendLog::open(path).unwrap();
+        assert_eq!(content, b"ghijkl");
+    }
+}
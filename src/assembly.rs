@@ -0,0 +1,400 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Assembly of the object aggregates received split across multiple publishes.
+//!
+//! An [`ObjectAssembler`] merges the fields of the objects received on the same path of an
+//! interface, and converts them with [`FromEvent`] only once all the required fields arrived.
+//! The fields not in the required set are passed to the conversion if received, so they can be
+//! mapped to the `Option` fields of the struct.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{
+//!     assembly::ObjectAssembler, event::FromEventError, AstarteDeviceDataEvent,
+//!     AstarteDeviceSdk, FromEvent,
+//! };
+//!
+//! struct Reading(AstarteDeviceDataEvent);
+//!
+//! impl FromEvent for Reading {
+//!     type Err = FromEventError;
+//!
+//!     fn from_event(event: AstarteDeviceDataEvent) -> Result<Self, Self::Err> {
+//!         Ok(Reading(event))
+//!     }
+//! }
+//!
+//! async fn readings(mut device: AstarteDeviceSdk) {
+//!     let mut assembler =
+//!         ObjectAssembler::<Reading>::new("com.example.Readings", ["temperature", "humidity"]);
+//!
+//!     while let Ok(event) = device.handle_events().await {
+//!         match assembler.push(event) {
+//!             Ok(Some(Reading(event))) => println!("complete reading on {}", event.path),
+//!             Ok(None) => {}
+//!             Err(err) => println!("invalid reading: {err}"),
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use crate::event::FromEventError;
+use crate::types::AstarteType;
+use crate::{Aggregation, AstarteDeviceDataEvent, EventMetadata, FromEvent};
+
+/// Fields received and missing of a partial object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completeness {
+    /// Fields received, sorted by name.
+    pub received: Vec<String>,
+    /// Required fields not received yet, sorted by name.
+    pub missing: Vec<String>,
+}
+
+impl Completeness {
+    /// Returns true if all the required fields were received.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Object being assembled on a path.
+#[derive(Debug)]
+struct Partial {
+    fields: HashMap<String, AstarteType>,
+    metadata: EventMetadata,
+    stale: bool,
+    started: Instant,
+}
+
+/// Merges the object aggregates of an interface until the required fields are present.
+///
+/// See the [module documentation](crate::assembly) for an example.
+#[derive(Debug)]
+pub struct ObjectAssembler<T> {
+    interface: String,
+    required: HashSet<String>,
+    expire_after: Option<Duration>,
+    pending: HashMap<String, Partial>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ObjectAssembler<T>
+where
+    T: FromEvent,
+    T::Err: From<FromEventError>,
+{
+    /// Creates an assembler for the objects of an interface, with the names of the required
+    /// fields.
+    pub fn new<I, S>(interface: &str, required: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            interface: interface.to_string(),
+            required: required.into_iter().map(Into::into).collect(),
+            expire_after: None,
+            pending: HashMap::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Discards the partial objects not completed in the duration, with [`expire`](Self::expire).
+    pub fn expire_after(mut self, duration: Duration) -> Self {
+        self.expire_after = Some(duration);
+
+        self
+    }
+
+    /// Adds the fields of an event, returning the converted object once the required fields are
+    /// present.
+    ///
+    /// The fields received again replace the previous values and the delivery information of the
+    /// last event is kept. An event of another interface or with an individual value is an
+    /// error, and the partial object of the path is left untouched.
+    pub fn push(&mut self, event: AstarteDeviceDataEvent) -> Result<Option<T>, T::Err> {
+        if event.interface != self.interface {
+            return Err(FromEventError::Interface {
+                expected: self.interface.clone(),
+                got: event.interface,
+            }
+            .into());
+        }
+
+        let fields = match event.data {
+            Aggregation::Object(fields) => fields,
+            Aggregation::Individual(_) | Aggregation::Unset => {
+                return Err(FromEventError::Object {
+                    interface: event.interface,
+                    path: event.path,
+                }
+                .into());
+            }
+        };
+
+        let partial = self
+            .pending
+            .entry(event.path.clone())
+            .or_insert_with(|| Partial {
+                fields: HashMap::new(),
+                metadata: event.metadata,
+                stale: event.stale,
+                started: Instant::now(),
+            });
+
+        partial.fields.extend(fields);
+        partial.metadata = event.metadata;
+        partial.stale = event.stale;
+
+        let complete = self
+            .required
+            .iter()
+            .all(|field| partial.fields.contains_key(field));
+        if !complete {
+            return Ok(None);
+        }
+
+        let Some(partial) = self.pending.remove(&event.path) else {
+            return Ok(None);
+        };
+
+        T::from_event(AstarteDeviceDataEvent {
+            interface: event.interface,
+            path: event.path,
+            data: Aggregation::Object(partial.fields),
+            stale: partial.stale,
+            metadata: partial.metadata,
+        })
+        .map(Some)
+    }
+
+    /// Returns the completeness of the partial object on a path, `None` if there is none.
+    pub fn completeness(&self, path: &str) -> Option<Completeness> {
+        self.pending
+            .get(path)
+            .map(|partial| self.partial_completeness(partial))
+    }
+
+    /// Returns the paths with a partial object and their completeness.
+    pub fn pending(&self) -> impl Iterator<Item = (&str, Completeness)> {
+        self.pending
+            .iter()
+            .map(|(path, partial)| (path.as_str(), self.partial_completeness(partial)))
+    }
+
+    /// Removes the partial objects older than the [`expire_after`](Self::expire_after) duration,
+    /// returning their paths and completeness.
+    pub fn expire(&mut self) -> Vec<(String, Completeness)> {
+        let Some(duration) = self.expire_after else {
+            return Vec::new();
+        };
+
+        let expired: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, partial)| partial.started.elapsed() >= duration)
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|path| {
+                let partial = self.pending.remove(&path)?;
+                let completeness = self.partial_completeness(&partial);
+
+                Some((path, completeness))
+            })
+            .collect()
+    }
+
+    /// Discards the partial object on a path, returning true if there was one.
+    pub fn discard(&mut self, path: &str) -> bool {
+        self.pending.remove(path).is_some()
+    }
+
+    fn partial_completeness(&self, partial: &Partial) -> Completeness {
+        let mut received: Vec<String> = partial.fields.keys().cloned().collect();
+        received.sort();
+
+        let mut missing: Vec<String> = self
+            .required
+            .iter()
+            .filter(|field| !partial.fields.contains_key(*field))
+            .cloned()
+            .collect();
+        missing.sort();
+
+        Completeness { received, missing }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INTERFACE: &str = "org.astarte-platform.test.Readings";
+
+    #[derive(Debug)]
+    struct Reading {
+        temperature: f64,
+        humidity: Option<f64>,
+    }
+
+    impl FromEvent for Reading {
+        type Err = FromEventError;
+
+        fn from_event(event: AstarteDeviceDataEvent) -> Result<Self, Self::Err> {
+            let Aggregation::Object(mut fields) = event.data else {
+                unreachable!("the assembler only converts objects");
+            };
+
+            let field = |fields: &mut HashMap<String, AstarteType>, name: &str| {
+                fields
+                    .remove(name)
+                    .map(|value| {
+                        value
+                            .try_into()
+                            .map_err(|source| FromEventError::Conversion {
+                                field: name.to_string(),
+                                source,
+                            })
+                    })
+                    .transpose()
+            };
+
+            Ok(Reading {
+                temperature: field(&mut fields, "temperature")?
+                    .ok_or_else(|| FromEventError::MissingField("temperature".to_string()))?,
+                humidity: field(&mut fields, "humidity")?,
+            })
+        }
+    }
+
+    fn event(interface: &str, path: &str, fields: &[(&str, f64)]) -> AstarteDeviceDataEvent {
+        AstarteDeviceDataEvent {
+            interface: interface.to_string(),
+            path: path.to_string(),
+            data: Aggregation::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), AstarteType::Double(*value)))
+                    .collect(),
+            ),
+            stale: false,
+            metadata: EventMetadata {
+                received_at: chrono::Utc::now(),
+                timestamp: None,
+                qos: rumqttc::QoS::AtLeastOnce,
+                retain: false,
+                duplicate: false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_assemble() {
+        let mut assembler = ObjectAssembler::<Reading>::new(INTERFACE, ["temperature", "pressure"]);
+
+        let res = assembler
+            .push(event(INTERFACE, "/room", &[("temperature", 20.0)]))
+            .unwrap();
+        assert!(res.is_none());
+        assert_eq!(
+            assembler.completeness("/room"),
+            Some(Completeness {
+                received: vec!["temperature".to_string()],
+                missing: vec!["pressure".to_string()],
+            })
+        );
+        assert!(assembler.completeness("/other").is_none());
+
+        // the fields received again are replaced
+        let res = assembler
+            .push(event(
+                INTERFACE,
+                "/room",
+                &[("temperature", 21.0), ("humidity", 0.4)],
+            ))
+            .unwrap();
+        assert!(res.is_none());
+
+        let reading = assembler
+            .push(event(INTERFACE, "/room", &[("pressure", 1.0)]))
+            .unwrap()
+            .expect("complete reading");
+        assert_eq!(reading.temperature, 21.0);
+        assert_eq!(reading.humidity, Some(0.4));
+        assert!(assembler.completeness("/room").is_none());
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let mut assembler = ObjectAssembler::<Reading>::new(INTERFACE, ["pressure"]);
+
+        assembler
+            .push(event(INTERFACE, "/room", &[("temperature", 20.0)]))
+            .unwrap();
+
+        let err = assembler
+            .push(event("com.example.Other", "/room", &[("pressure", 1.0)]))
+            .unwrap_err();
+        assert!(matches!(err, FromEventError::Interface { .. }));
+
+        let mut individual = event(INTERFACE, "/room", &[]);
+        individual.data = Aggregation::Individual(AstarteType::Double(1.0));
+        let err = assembler.push(individual).unwrap_err();
+        assert!(matches!(err, FromEventError::Object { .. }));
+
+        // the partial object is kept after the errors
+        let pending: Vec<_> = assembler.pending().collect();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].0, "/room");
+        assert_eq!(pending[0].1.missing, vec!["pressure".to_string()]);
+
+        assert!(assembler.discard("/room"));
+        assert!(!assembler.discard("/room"));
+
+        // the conversion errors are returned once the required fields are present
+        let err = assembler
+            .push(event(INTERFACE, "/room", &[("pressure", 1.0)]))
+            .unwrap_err();
+        assert!(matches!(err, FromEventError::MissingField(field) if field == "temperature"));
+    }
+
+    #[test]
+    fn test_expire() {
+        let mut assembler = ObjectAssembler::<Reading>::new(INTERFACE, ["temperature", "humidity"]);
+        assembler
+            .push(event(INTERFACE, "/room", &[("temperature", 20.0)]))
+            .unwrap();
+
+        assert!(assembler.expire().is_empty());
+
+        let mut assembler = assembler.expire_after(Duration::ZERO);
+        let expired = assembler.expire();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, "/room");
+        assert!(!expired[0].1.is_complete());
+        assert_eq!(assembler.pending().count(), 0);
+    }
+}
@@ -0,0 +1,522 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Local buffer of individual datastream samples, sent in batches.
+//!
+//! The samples pushed in a [`Buffer`] are serialized right away and kept until they are flushed,
+//! when the [`FlushPolicy`] is due or on demand. A flush sends all the samples in the order they
+//! were pushed, the ones not sent because of an error are kept for the next flush.
+//!
+//! A buffer created with [`Buffer::persistent()`] keeps the samples in the outbox of the
+//! database, so they are not lost if the application crashes and they are sent by the first
+//! flush after the restart. The flush publishes the whole outbox, including the messages the
+//! application committed with
+//! [`AstarteSqliteDatabase::enqueue()`](crate::database::AstarteSqliteDatabase::enqueue).
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use astarte_device_sdk::{
+//!     buffer::{Buffer, FlushPolicy},
+//!     AstarteDeviceSdk,
+//! };
+//!
+//! async fn sample(device: &AstarteDeviceSdk, mut samples: tokio::sync::mpsc::Receiver<f64>) {
+//!     let policy = FlushPolicy::new()
+//!         .max_samples(500)
+//!         .max_age(Duration::from_secs(1));
+//!     let mut buffer = Buffer::new(policy);
+//!
+//!     while let Some(value) = samples.recv().await {
+//!         let timestamp = chrono::Utc::now();
+//!         buffer
+//!             .push("com.example.Vibration", "/x", value, Some(timestamp))
+//!             .await
+//!             .unwrap();
+//!
+//!         buffer.flush_if_due(device).await.unwrap();
+//!     }
+//!
+//!     buffer.flush(device).await.unwrap();
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::debug;
+use tokio::time::Instant;
+
+use crate::database::{AstarteDatabase, AstarteSqliteDatabase};
+use crate::outbox::{AstarteOutbox, OutboxIntent};
+use crate::types::{AstarteType, TypeError};
+use crate::{AstarteDeviceSdk, Error};
+
+/// Thresholds after which the samples of a [`Buffer`] should be flushed.
+///
+/// Without any threshold the buffer is flushed only on demand.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlushPolicy {
+    max_samples: Option<usize>,
+    max_bytes: Option<usize>,
+    max_age: Option<Duration>,
+    max_scale: Option<u32>,
+}
+
+impl FlushPolicy {
+    /// Creates a policy without thresholds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flushes once the buffer has the number of samples.
+    pub fn max_samples(mut self, samples: usize) -> Self {
+        self.max_samples = Some(samples);
+
+        self
+    }
+
+    /// Flushes once the serialized samples reach the size in bytes.
+    pub fn max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = Some(bytes);
+
+        self
+    }
+
+    /// Flushes once the oldest sample has been buffered for the duration.
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+
+        self
+    }
+
+    /// Scales the samples and bytes thresholds up to the given factor as the
+    /// [connection quality](crate::quality) degrades, sending bigger batches on poor links.
+    ///
+    /// Only [`Buffer::flush_if_due`] adapts the thresholds, the age is never scaled.
+    pub fn adapt_to_quality(mut self, max_scale: u32) -> Self {
+        self.max_scale = Some(max_scale.max(1));
+
+        self
+    }
+
+    /// Returns the factor of the thresholds for a quality score.
+    fn scale(&self, score: f64) -> f64 {
+        self.max_scale.map_or(1.0, |max| {
+            1.0 + f64::from(max - 1) * (1.0 - score.clamp(0.0, 1.0))
+        })
+    }
+}
+
+/// Storage of the buffered samples.
+#[derive(Debug)]
+enum Storage {
+    Memory(VecDeque<OutboxIntent>),
+    Outbox(AstarteSqliteDatabase),
+}
+
+/// Buffer of samples, flushed following a [`FlushPolicy`].
+#[derive(Debug)]
+pub struct Buffer {
+    policy: FlushPolicy,
+    storage: Storage,
+    samples: usize,
+    bytes: usize,
+    /// Instant the oldest sample was buffered.
+    oldest: Option<Instant>,
+}
+
+impl Buffer {
+    /// Creates a buffer keeping the samples in memory.
+    pub fn new(policy: FlushPolicy) -> Self {
+        Self {
+            policy,
+            storage: Storage::Memory(VecDeque::new()),
+            samples: 0,
+            bytes: 0,
+            oldest: None,
+        }
+    }
+
+    /// Creates a buffer keeping the samples in the outbox of the database.
+    ///
+    /// The messages already in the outbox, like the samples not flushed before a crash, are
+    /// counted in the buffer and their age starts now.
+    pub async fn persistent(
+        policy: FlushPolicy,
+        database: AstarteSqliteDatabase,
+    ) -> Result<Self, Error> {
+        let mut buffer = Self {
+            policy,
+            storage: Storage::Outbox(database),
+            samples: 0,
+            bytes: 0,
+            oldest: None,
+        };
+
+        buffer.reload().await?;
+
+        Ok(buffer)
+    }
+
+    /// Counts the messages in the outbox.
+    async fn reload(&mut self) -> Result<(), Error> {
+        let Storage::Outbox(database) = &self.storage else {
+            return Ok(());
+        };
+
+        let pending = database.pending().await?;
+
+        self.samples = pending.len();
+        self.bytes = pending.iter().map(|entry| entry.payload.len()).sum();
+        self.oldest = match (self.oldest, self.samples) {
+            (_, 0) => None,
+            (Some(oldest), _) => Some(oldest),
+            (None, _) => Some(Instant::now()),
+        };
+
+        Ok(())
+    }
+
+    /// Adds a sample to the buffer.
+    ///
+    /// The timestamp should be set on the mappings with an explicit timestamp, so the time of
+    /// the sample is kept while it's buffered.
+    pub async fn push<D>(
+        &mut self,
+        interface: &str,
+        path: &str,
+        data: D,
+        timestamp: Option<DateTime<Utc>>,
+    ) -> Result<(), Error>
+    where
+        D: TryInto<AstarteType>,
+    {
+        let data = data.try_into().map_err(|_| TypeError::Conversion)?;
+        let intent = OutboxIntent::individual(interface, path, data, timestamp)?;
+        let bytes = intent.payload.len();
+
+        match &mut self.storage {
+            Storage::Memory(samples) => samples.push_back(intent),
+            Storage::Outbox(database) => {
+                let mut tx = database.begin().await?;
+                AstarteSqliteDatabase::enqueue(&mut tx, &intent).await?;
+                tx.commit().await?;
+            }
+        }
+
+        self.samples += 1;
+        self.bytes += bytes;
+        self.oldest.get_or_insert_with(Instant::now);
+
+        Ok(())
+    }
+
+    /// Returns the number of buffered samples.
+    pub fn len(&self) -> usize {
+        self.samples
+    }
+
+    /// Returns `true` if there are no buffered samples.
+    pub fn is_empty(&self) -> bool {
+        self.samples == 0
+    }
+
+    /// Returns the size in bytes of the serialized samples.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Returns the instant the buffer should be flushed by, given by the age of the oldest
+    /// sample.
+    ///
+    /// It can be used to wait with [`tokio::time::sleep_until()`] the flush of a buffer that
+    /// doesn't receive new samples.
+    pub fn deadline(&self) -> Option<Instant> {
+        let oldest = self.oldest?;
+
+        self.policy.max_age.map(|max_age| oldest + max_age)
+    }
+
+    /// Checks if one of the thresholds of the policy is reached.
+    pub fn is_due(&self) -> bool {
+        self.is_due_scaled(1.0)
+    }
+
+    fn is_due_scaled(&self, scale: f64) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        let threshold = |max: usize| (max as f64 * scale) as usize;
+
+        let samples = self
+            .policy
+            .max_samples
+            .map_or(false, |max| self.samples >= threshold(max));
+        let bytes = self
+            .policy
+            .max_bytes
+            .map_or(false, |max| self.bytes >= threshold(max));
+        let age = self
+            .deadline()
+            .map_or(false, |deadline| deadline <= Instant::now());
+
+        samples || bytes || age
+    }
+
+    /// Sends all the buffered samples, returns the number of samples sent.
+    pub async fn flush<S>(&mut self, device: &AstarteDeviceSdk<S>) -> Result<usize, Error>
+    where
+        S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+    {
+        debug!("flushing {} buffered samples", self.samples);
+
+        let res = match &mut self.storage {
+            Storage::Memory(samples) => {
+                let mut res = Ok(());
+                let mut sent = 0;
+                while let Some(intent) = samples.front() {
+                    res = device
+                        .publish_payload(&intent.interface, &intent.path, &intent.payload)
+                        .await
+                        .map(|_| ());
+                    if res.is_err() {
+                        break;
+                    }
+
+                    self.bytes -= intent.payload.len();
+                    samples.pop_front();
+                    sent += 1;
+                }
+
+                self.samples = samples.len();
+
+                res.map(|()| sent)
+            }
+            Storage::Outbox(database) => device.publish_outbox(database).await,
+        };
+
+        if let Err(err) = self.reload().await {
+            debug!("couldn't count the samples in the outbox: {err}");
+        }
+
+        if self.is_empty() {
+            self.oldest = None;
+        }
+
+        res
+    }
+
+    /// Sends the buffered samples if the policy is due, returns the number of samples sent.
+    ///
+    /// The thresholds are scaled with the quality of the connection of the device, if the policy
+    /// [adapts to it](FlushPolicy::adapt_to_quality).
+    pub async fn flush_if_due<S>(&mut self, device: &AstarteDeviceSdk<S>) -> Result<usize, Error>
+    where
+        S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+    {
+        let scale = self.policy.scale(device.connection_quality().score);
+
+        if !self.is_due_scaled(scale) {
+            return Ok(0);
+        }
+
+        self.flush(device).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use mockall::predicate;
+
+    use super::*;
+    use crate::mock::{MockAsyncClient, MockDevice, MockEventLoop};
+    use crate::test::DIAGNOSTICS;
+    use crate::{payload, Interface};
+
+    #[tokio::test]
+    async fn test_buffer_thresholds() {
+        let mut buffer = Buffer::new(FlushPolicy::new().max_samples(3));
+        assert!(!buffer.is_due());
+        assert_eq!(buffer.deadline(), None);
+
+        for value in 0..2 {
+            buffer
+                .push("com.test", "/value", value, None)
+                .await
+                .unwrap();
+        }
+        assert_eq!(buffer.len(), 2);
+        assert!(!buffer.is_due());
+
+        buffer.push("com.test", "/value", 2, None).await.unwrap();
+        assert!(buffer.is_due());
+
+        let mut buffer = Buffer::new(FlushPolicy::new().max_bytes(64));
+        buffer
+            .push("com.test", "/value", "a".repeat(32), None)
+            .await
+            .unwrap();
+        assert!(!buffer.is_due());
+        buffer
+            .push("com.test", "/value", "a".repeat(32), None)
+            .await
+            .unwrap();
+        assert!(buffer.bytes() >= 64);
+        assert!(buffer.is_due());
+
+        // without thresholds the buffer is flushed only on demand
+        let mut buffer = Buffer::new(FlushPolicy::new());
+        buffer.push("com.test", "/value", 1, None).await.unwrap();
+        assert!(!buffer.is_due());
+    }
+
+    #[tokio::test]
+    async fn test_buffer_adapt_to_quality() {
+        let policy = FlushPolicy::new().max_samples(2).adapt_to_quality(3);
+        assert_eq!(policy.scale(1.0), 1.0);
+        assert_eq!(policy.scale(0.5), 2.0);
+        assert_eq!(policy.scale(0.0), 3.0);
+        assert_eq!(FlushPolicy::new().scale(0.0), 1.0);
+
+        let mut buffer = Buffer::new(policy);
+        for value in 0..2 {
+            buffer
+                .push("com.test", "/value", value, None)
+                .await
+                .unwrap();
+        }
+
+        // bigger batches on a poor link
+        assert!(buffer.is_due_scaled(policy.scale(1.0)));
+        assert!(!buffer.is_due_scaled(policy.scale(0.5)));
+    }
+
+    #[tokio::test]
+    async fn test_buffer_max_age() {
+        let mut buffer = Buffer::new(FlushPolicy::new().max_age(Duration::from_millis(50)));
+        buffer.push("com.test", "/value", 1, None).await.unwrap();
+
+        let deadline = buffer.deadline().unwrap();
+        assert!(!buffer.is_due());
+
+        buffer.push("com.test", "/value", 2, None).await.unwrap();
+        // the age is the one of the oldest sample
+        assert_eq!(buffer.deadline(), Some(deadline));
+
+        tokio::time::sleep_until(deadline).await;
+        assert!(buffer.is_due());
+    }
+
+    #[tokio::test]
+    async fn test_buffer_persistent() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("buffer.sqlite");
+        let database = AstarteSqliteDatabase::new(db_path.to_str().unwrap())
+            .await
+            .unwrap();
+
+        let mut buffer = Buffer::persistent(FlushPolicy::new(), database.clone())
+            .await
+            .unwrap();
+        assert!(buffer.is_empty());
+
+        buffer.push("com.test", "/value", 1, None).await.unwrap();
+        buffer.push("com.test", "/value", 2, None).await.unwrap();
+        let bytes = buffer.bytes();
+        drop(buffer);
+
+        // the samples are restored after a restart
+        let buffer = Buffer::persistent(FlushPolicy::new().max_samples(2), database)
+            .await
+            .unwrap();
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.bytes(), bytes);
+        assert!(buffer.is_due());
+    }
+
+    #[tokio::test]
+    async fn test_buffer_flush() {
+        let topic = "realm/device_id/org.astarte-platform.test.Diagnostics/properties".to_string();
+
+        let mut client = MockAsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq(topic.clone()),
+                predicate::always(),
+                predicate::always(),
+                predicate::eq(payload::serialize_individual(&"first".into(), None).unwrap()),
+            )
+            .returning(|_, _, _, _| Ok(()));
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| {
+                Err(rumqttc::ClientError::Request(rumqttc::Request::Disconnect))
+            });
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .times(2)
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq(topic),
+                predicate::always(),
+                predicate::always(),
+                predicate::always(),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        let astarte = MockDevice::new(client, MockEventLoop::default())
+            .interfaces([Interface::from_str(DIAGNOSTICS).unwrap()])
+            .build();
+
+        let mut buffer = Buffer::new(FlushPolicy::new().max_samples(3));
+        for value in ["first", "second", "third"] {
+            assert_eq!(buffer.flush_if_due(&astarte).await.unwrap(), 0);
+
+            buffer
+                .push(
+                    "org.astarte-platform.test.Diagnostics",
+                    "/properties",
+                    value,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        // the samples not sent are kept for the next flush
+        assert!(buffer.flush_if_due(&astarte).await.is_err());
+        assert_eq!(buffer.len(), 2);
+
+        assert_eq!(buffer.flush(&astarte).await.unwrap(), 2);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.bytes(), 0);
+        assert_eq!(buffer.deadline(), None);
+    }
+}
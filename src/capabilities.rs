@@ -0,0 +1,192 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Features supported by the Astarte cluster, so the device adapts to the older clusters instead
+//! of failing on the unsupported ones.
+//!
+//! The [`Capabilities`] are detected from the version returned by the pairing API when the device
+//! connects, and are returned by
+//! [`AstarteDeviceSdk::capabilities`](crate::AstarteDeviceSdk::capabilities). If the version
+//! can't be parsed all the features are assumed to be supported. The detection can be replaced
+//! with [`AstarteOptions::capabilities`](crate::options::AstarteOptions::capabilities), for
+//! clusters with limits not discoverable from the version.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{capabilities::Capabilities, options::AstarteOptions};
+//!
+//! let mut capabilities = Capabilities::default();
+//! capabilities.max_payload_size = Some(64 * 1024);
+//!
+//! let sdk_options = AstarteOptions::new("_","_","_","_").capabilities(capabilities);
+//! ```
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use log::warn;
+
+/// First version handling the `/control/producer/properties` message.
+const PURGE_PROPERTIES_VERSION: ClusterVersion = ClusterVersion::new(1, 0, 0);
+
+/// Version of the Astarte cluster, without the pre-release and build metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClusterVersion {
+    /// Major version.
+    pub major: u64,
+    /// Minor version.
+    pub minor: u64,
+    /// Patch version.
+    pub patch: u64,
+}
+
+impl ClusterVersion {
+    /// Creates a version.
+    pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+/// Error returned parsing a [`ClusterVersion`].
+#[non_exhaustive]
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid cluster version {0}")]
+pub struct VersionError(String);
+
+impl FromStr for ClusterVersion {
+    type Err = VersionError;
+
+    /// Parses a version like `1.1.0`, `v1.2.0-rc.0` or `1.0`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || VersionError(s.to_string());
+
+        let version = s.trim().trim_start_matches('v');
+        // the pre-release and build metadata are ignored
+        let version = version.split(['-', '+']).next().unwrap_or_default();
+
+        let mut parts = version.split('.').map(u64::from_str);
+        let major = parts.next().ok_or_else(err)?.map_err(|_| err())?;
+        let minor = parts.next().ok_or_else(err)?.map_err(|_| err())?;
+        let patch = parts.next().transpose().map_err(|_| err())?.unwrap_or(0);
+
+        if parts.next().is_some() {
+            return Err(err());
+        }
+
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+impl Display for ClusterVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Features supported by the Astarte cluster.
+///
+/// The default supports all the features, without a limit on the payloads.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Version of the cluster, if known.
+    pub version: Option<ClusterVersion>,
+    /// Maximum size in bytes of the payloads sent, the bigger ones are rejected with an
+    /// [`Error::PayloadTooLarge`](crate::Error::PayloadTooLarge) before being published.
+    pub max_payload_size: Option<usize>,
+    /// The cluster handles the `/control/producer/properties` message, so the device-owned
+    /// properties unset while offline are purged when the device connects.
+    pub purge_properties: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            version: None,
+            max_payload_size: None,
+            purge_properties: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Returns the capabilities of a cluster version.
+    pub fn from_version(version: ClusterVersion) -> Self {
+        Self {
+            version: Some(version),
+            purge_properties: version >= PURGE_PROPERTIES_VERSION,
+            ..Self::default()
+        }
+    }
+
+    /// Detects the capabilities from the version returned by the pairing API, assuming all the
+    /// features are supported if it can't be parsed.
+    pub(crate) fn detect(version: &str) -> Self {
+        match version.parse() {
+            Ok(version) => Self::from_version(version),
+            Err(err) => {
+                warn!("{err}, assuming all the features are supported");
+
+                Self::default()
+            }
+        }
+    }
+
+    /// Checks if a payload fits in the maximum size, returning the maximum if it doesn't.
+    pub(crate) fn exceeds_payload(&self, size: usize) -> Option<usize> {
+        self.max_payload_size.filter(|max| size > *max)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            "1.1.0".parse::<ClusterVersion>(),
+            Ok(ClusterVersion::new(1, 1, 0))
+        );
+        assert_eq!(
+            "v1.2.0-rc.0".parse::<ClusterVersion>(),
+            Ok(ClusterVersion::new(1, 2, 0))
+        );
+        assert_eq!(
+            "0.11".parse::<ClusterVersion>(),
+            Ok(ClusterVersion::new(0, 11, 0))
+        );
+
+        assert!("1".parse::<ClusterVersion>().is_err());
+        assert!("1.0.0.0".parse::<ClusterVersion>().is_err());
+        assert!("latest".parse::<ClusterVersion>().is_err());
+    }
+
+    #[test]
+    fn test_detect() {
+        assert!(Capabilities::detect("1.1.0").purge_properties);
+        assert!(!Capabilities::detect("0.11.5").purge_properties);
+
+        let unknown = Capabilities::detect("snapshot");
+        assert_eq!(unknown, Capabilities::default());
+        assert!(unknown.purge_properties);
+    }
+}
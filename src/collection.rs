@@ -0,0 +1,323 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Collections of items on a parametric endpoint, like `/items/%{idx}`.
+//!
+//! Each item of the collection is sent on the path with the parameter replaced by its key, see
+//! [`send_object_collection()`](crate::AstarteDeviceSdk::send_object_collection). The events
+//! received are reassembled in a [`Collection`], keyed by the value of the parameter: the
+//! objects are merged on the item, while the individual values are stored with the levels after
+//! the parameter as the field name.
+//!
+//! ```
+//! use astarte_device_sdk::{collection::Collection, types::AstarteType, Aggregation};
+//!
+//! let mut items = Collection::new("/items/%{idx}").unwrap();
+//!
+//! assert_eq!(items.path(3), "/items/3");
+//!
+//! let key = items.insert("/items/3/name", Aggregation::Individual("sensor".into()));
+//! assert_eq!(key.as_deref(), Some("3"));
+//!
+//! assert_eq!(
+//!     items.get("3").and_then(|item| item.get("name")),
+//!     Some(&AstarteType::String("sensor".to_string()))
+//! );
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+
+use crate::endpoint::{EndpointError, EndpointPattern};
+use crate::types::AstarteType;
+use crate::Aggregation;
+
+/// Errors creating a [`Collection`].
+#[non_exhaustive]
+#[derive(thiserror::Error, Debug)]
+pub enum CollectionError {
+    #[error("invalid endpoint of the collection")]
+    Endpoint(#[from] EndpointError),
+    #[error("the endpoint {0} must end with the parameter of the collection")]
+    Parameter(String),
+}
+
+/// Fields of an item of the collection.
+pub type Item = HashMap<String, AstarteType>;
+
+/// Items received on a parametric endpoint, keyed by the value of the parameter.
+#[derive(Debug, Clone)]
+pub struct Collection {
+    endpoint: EndpointPattern,
+    levels: usize,
+    items: BTreeMap<String, Item>,
+}
+
+impl Collection {
+    /// Creates an empty collection for the endpoint, which must end with the parameter, like
+    /// `/items/%{idx}`.
+    pub fn new(endpoint: &str) -> Result<Self, CollectionError> {
+        let pattern = EndpointPattern::new(endpoint)?;
+
+        let valid = endpoint
+            .rsplit('/')
+            .next()
+            .map_or(false, |last| last.starts_with("%{"));
+        if !valid || pattern.params().count() != 1 {
+            return Err(CollectionError::Parameter(endpoint.to_string()));
+        }
+
+        Ok(Self {
+            levels: endpoint.matches('/').count(),
+            endpoint: pattern,
+            items: BTreeMap::new(),
+        })
+    }
+
+    /// Returns the path of the item with the key.
+    pub fn path<K>(&self, key: K) -> String
+    where
+        K: Display,
+    {
+        let endpoint = self.endpoint.to_string();
+        let (base, _) = endpoint.rsplit_once('/').unwrap_or_default();
+
+        format!("{base}/{key}")
+    }
+
+    /// Splits the path in the key of the item and the remaining levels.
+    fn split<'a>(&self, path: &'a str) -> Option<(String, &'a str)> {
+        let (item, field) = match path.match_indices('/').nth(self.levels) {
+            Some((idx, _)) => (&path[..idx], &path[idx + 1..]),
+            None => (path, ""),
+        };
+
+        let params = self.endpoint.matches(item)?;
+        let (_, key) = params.iter().next()?;
+
+        Some((key.to_string(), field))
+    }
+
+    /// Stores the data received on a path of the collection, returning the key of the item.
+    ///
+    /// The object data is merged with the fields of the item, while the individual data must
+    /// have a path longer than the endpoint and it's stored with the remaining levels as the
+    /// field name. An unset removes the field, and the item once it has no fields.
+    ///
+    /// Returns `None` if the path isn't part of the collection.
+    pub fn insert(&mut self, path: &str, data: Aggregation) -> Option<String> {
+        let (key, field) = self.split(path)?;
+
+        match data {
+            Aggregation::Object(fields) if field.is_empty() => {
+                self.items.entry(key.clone()).or_default().extend(fields);
+            }
+            Aggregation::Unset if !field.is_empty() => {
+                if let Some(item) = self.items.get_mut(&key) {
+                    item.remove(field);
+
+                    if item.is_empty() {
+                        self.items.remove(&key);
+                    }
+                }
+            }
+            Aggregation::Individual(value) if !field.is_empty() => {
+                self.items
+                    .entry(key.clone())
+                    .or_default()
+                    .insert(field.to_string(), value);
+            }
+            _ => return None,
+        }
+
+        Some(key)
+    }
+
+    /// Returns the fields of an item.
+    pub fn get(&self, key: &str) -> Option<&Item> {
+        self.items.get(key)
+    }
+
+    /// Removes an item from the collection.
+    pub fn remove(&mut self, key: &str) -> Option<Item> {
+        self.items.remove(key)
+    }
+
+    /// Returns the items, ordered by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Item)> {
+        self.items.iter().map(|(key, item)| (key.as_str(), item))
+    }
+
+    /// Returns the number of items.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the collection has no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Converts the items of the collection into structs, keyed by the parameter.
+    pub fn read<K, T>(&self) -> Result<BTreeMap<K, T>, T::Error>
+    where
+        K: From<String> + Ord,
+        T: TryFrom<Item>,
+    {
+        self.items
+            .iter()
+            .map(|(key, item)| Ok((K::from(key.clone()), T::try_from(item.clone())?)))
+            .collect()
+    }
+
+    /// Returns the items, ordered by key.
+    pub fn into_items(self) -> BTreeMap<String, Item> {
+        self.items
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let items = Collection::new("/sensors/%{id}/items/%{idx}").unwrap_err();
+        assert!(matches!(items, CollectionError::Parameter(_)));
+
+        let items = Collection::new("/items/%{idx}/value").unwrap_err();
+        assert!(matches!(items, CollectionError::Parameter(_)));
+
+        let items = Collection::new("/items").unwrap_err();
+        assert!(matches!(items, CollectionError::Parameter(_)));
+
+        let items = Collection::new("/items/%{idx}").unwrap();
+        assert_eq!(items.path(0), "/items/0");
+        assert_eq!(items.path("first"), "/items/first");
+
+        let items = Collection::new("/%{idx}").unwrap();
+        assert_eq!(items.path(1), "/1");
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut items = Collection::new("/device/items/%{idx}").unwrap();
+
+        let key = items.insert(
+            "/device/items/1",
+            Aggregation::Object(HashMap::from([
+                ("name".to_string(), "first".into()),
+                ("value".to_string(), AstarteType::Double(1.0)),
+            ])),
+        );
+        assert_eq!(key.as_deref(), Some("1"));
+
+        let key = items.insert(
+            "/device/items/0/value",
+            Aggregation::Individual(AstarteType::Double(2.0)),
+        );
+        assert_eq!(key.as_deref(), Some("0"));
+        let key = items.insert(
+            "/device/items/0/unit/name",
+            Aggregation::Individual("m".into()),
+        );
+        assert_eq!(key.as_deref(), Some("0"));
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items.get("0"),
+            Some(&HashMap::from([
+                ("value".to_string(), AstarteType::Double(2.0)),
+                ("unit/name".to_string(), "m".into()),
+            ]))
+        );
+        assert_eq!(
+            items.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+            ["0", "1"]
+        );
+
+        // not part of the collection
+        assert_eq!(
+            items.insert(
+                "/device/other/0",
+                Aggregation::Individual(AstarteType::Double(1.0))
+            ),
+            None
+        );
+        assert_eq!(
+            items.insert(
+                "/device/items/0",
+                Aggregation::Individual(AstarteType::Double(1.0))
+            ),
+            None
+        );
+        assert_eq!(
+            items.insert("/device/items/0/value", Aggregation::Object(HashMap::new())),
+            None
+        );
+
+        items.insert("/device/items/0/value", Aggregation::Unset);
+        items.insert("/device/items/0/unit/name", Aggregation::Unset);
+        assert_eq!(items.get("0"), None);
+        assert_eq!(items.len(), 1);
+    }
+
+    #[test]
+    fn test_read() {
+        #[derive(Debug, PartialEq)]
+        struct Item {
+            value: f64,
+        }
+
+        impl TryFrom<super::Item> for Item {
+            type Error = String;
+
+            fn try_from(mut item: super::Item) -> Result<Self, Self::Error> {
+                let value = item.remove("value").ok_or("missing value")?;
+                let value = value.try_into().map_err(|_| "invalid value")?;
+
+                Ok(Item { value })
+            }
+        }
+
+        let mut items = Collection::new("/items/%{idx}").unwrap();
+        items.insert(
+            "/items/a/value",
+            Aggregation::Individual(AstarteType::Double(1.0)),
+        );
+        items.insert(
+            "/items/b/value",
+            Aggregation::Individual(AstarteType::Double(2.0)),
+        );
+
+        let read: BTreeMap<String, Item> = items.read().unwrap();
+        assert_eq!(
+            read,
+            BTreeMap::from([
+                ("a".to_string(), Item { value: 1.0 }),
+                ("b".to_string(), Item { value: 2.0 }),
+            ])
+        );
+
+        items.insert(
+            "/items/c/other",
+            Aggregation::Individual(AstarteType::Double(3.0)),
+        );
+        assert!(items.read::<String, Item>().is_err());
+    }
+}
@@ -0,0 +1,267 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Local constraints on the values received from Astarte.
+//!
+//! The interfaces don't specify the valid ranges of the values, so a constraint can be
+//! registered on the mappings of an interface to reject the values outside of the safe limits.
+//! A rejected value is not stored and [`handle_events()`](crate::AstarteDeviceSdk::handle_events)
+//! returns an [`Error::ConstraintViolation`](crate::Error::ConstraintViolation).
+//!
+//! The fields of an object are checked on the path of the object followed by the field name.
+//! Unsetting a property is always allowed.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{constraint::ValueConstraint, options::AstarteOptions};
+//!
+//! let constraint = ValueConstraint::new("com.example.ServerActuators", "/*/speed")
+//!     .unwrap()
+//!     .range(0.0, 120.0);
+//!
+//! let sdk_options = AstarteOptions::new("_","_","_","_").value_constraint(constraint);
+//! ```
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::{
+    filter::{FilterError, PathGlob},
+    types::AstarteType,
+    Aggregation,
+};
+
+type Validator = Arc<dyn Fn(&AstarteType) -> Result<(), String> + Send + Sync>;
+
+/// Constraint on the values received on the mappings of an interface.
+#[derive(Clone)]
+pub struct ValueConstraint {
+    interface: String,
+    path: PathGlob,
+    range: Option<(f64, f64)>,
+    validator: Option<Validator>,
+}
+
+impl ValueConstraint {
+    /// Create a constraint on the paths matching the glob, see [`PathGlob`].
+    ///
+    /// Without a range or a validator all the values are accepted.
+    pub fn new(interface: &str, path: &str) -> Result<Self, FilterError> {
+        Ok(Self {
+            interface: interface.to_string(),
+            path: PathGlob::new(path)?,
+            range: None,
+            validator: None,
+        })
+    }
+
+    /// Accept only numeric values, or arrays of them, in the inclusive range.
+    pub fn range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+
+        self
+    }
+
+    /// Accept only the values for which the validator returns [`Ok`], the error is the reason
+    /// reported for the rejection.
+    pub fn validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&AstarteType) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.validator = Some(Arc::new(validator));
+
+        self
+    }
+
+    fn check(&self, value: &AstarteType) -> Result<(), String> {
+        if let Some((min, max)) = self.range {
+            check_range(value, min, max)?;
+        }
+
+        match &self.validator {
+            Some(validator) => validator(value),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Debug for ValueConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ValueConstraint")
+            .field("interface", &self.interface)
+            .field("path", &self.path)
+            .field("range", &self.range)
+            .field("validator", &self.validator.is_some())
+            .finish()
+    }
+}
+
+fn check_range(value: &AstarteType, min: f64, max: f64) -> Result<(), String> {
+    let values: Vec<f64> = match value {
+        AstarteType::Double(v) => vec![*v],
+        AstarteType::Integer(v) => vec![f64::from(*v)],
+        AstarteType::LongInteger(v) => vec![*v as f64],
+        AstarteType::DoubleArray(v) => v.clone(),
+        AstarteType::IntegerArray(v) => v.iter().copied().map(f64::from).collect(),
+        AstarteType::LongIntegerArray(v) => v.iter().map(|v| *v as f64).collect(),
+        _ => return Err("the value is not numeric".to_string()),
+    };
+
+    match values.into_iter().find(|v| !(min..=max).contains(v)) {
+        Some(v) => Err(format!("{v} is outside of the range [{min}, {max}]")),
+        None => Ok(()),
+    }
+}
+
+/// A value rejected by a constraint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Violation {
+    pub(crate) path: String,
+    pub(crate) reason: String,
+}
+
+/// Constraints configured on the device.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ValueConstraints {
+    constraints: Vec<ValueConstraint>,
+}
+
+impl ValueConstraints {
+    pub(crate) fn push(&mut self, constraint: ValueConstraint) {
+        self.constraints.push(constraint);
+    }
+
+    fn check_value(&self, interface: &str, path: &str, value: &AstarteType) -> Result<(), String> {
+        self.constraints
+            .iter()
+            .filter(|c| c.interface == interface && c.path.matches(path))
+            .try_for_each(|c| c.check(value))
+    }
+
+    /// Check the data received on the path against all the matching constraints.
+    pub(crate) fn check(
+        &self,
+        interface: &str,
+        path: &str,
+        data: &Aggregation,
+    ) -> Result<(), Violation> {
+        let violation = |path: String| move |reason| Violation { path, reason };
+
+        match data {
+            Aggregation::Individual(value) => self
+                .check_value(interface, path, value)
+                .map_err(violation(path.to_string())),
+            Aggregation::Object(fields) => fields.iter().try_for_each(|(name, value)| {
+                let field_path = format!("{path}/{name}");
+
+                self.check_value(interface, &field_path, value)
+                    .map_err(violation(field_path))
+            }),
+            Aggregation::Unset => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_range() {
+        assert!(check_range(&AstarteType::Integer(5), 0.0, 10.0).is_ok());
+        assert!(check_range(&AstarteType::Double(10.0), 0.0, 10.0).is_ok());
+        assert!(check_range(&AstarteType::LongInteger(-1), 0.0, 10.0).is_err());
+        assert!(check_range(&AstarteType::IntegerArray(vec![1, 2, 3]), 0.0, 10.0).is_ok());
+        assert_eq!(
+            check_range(&AstarteType::DoubleArray(vec![1.0, 11.5]), 0.0, 10.0),
+            Err("11.5 is outside of the range [0, 10]".to_string())
+        );
+        assert!(check_range(&AstarteType::Double(f64::NAN), 0.0, 10.0).is_err());
+        assert!(check_range(&AstarteType::String("5".to_string()), 0.0, 10.0).is_err());
+    }
+
+    #[test]
+    fn test_value_constraints() {
+        let mut constraints = ValueConstraints::default();
+
+        constraints.push(
+            ValueConstraint::new("com.test", "/*/speed")
+                .unwrap()
+                .range(0.0, 120.0),
+        );
+        constraints.push(
+            ValueConstraint::new("com.test", "/*/mode")
+                .unwrap()
+                .validator(|value| match value {
+                    AstarteType::String(mode) if mode == "auto" || mode == "manual" => Ok(()),
+                    _ => Err("invalid mode".to_string()),
+                }),
+        );
+
+        let individual = |v| Aggregation::Individual(v);
+
+        assert!(constraints
+            .check(
+                "com.test",
+                "/1/speed",
+                &individual(AstarteType::Double(80.0))
+            )
+            .is_ok());
+        assert_eq!(
+            constraints.check(
+                "com.test",
+                "/1/speed",
+                &individual(AstarteType::Double(800.0))
+            ),
+            Err(Violation {
+                path: "/1/speed".to_string(),
+                reason: "800 is outside of the range [0, 120]".to_string()
+            })
+        );
+        assert!(constraints
+            .check("com.test", "/1/speed", &Aggregation::Unset)
+            .is_ok());
+        assert!(constraints
+            .check(
+                "com.other",
+                "/1/speed",
+                &individual(AstarteType::Double(800.0))
+            )
+            .is_ok());
+        assert!(constraints
+            .check(
+                "com.test",
+                "/1/mode",
+                &individual(AstarteType::String("off".to_string()))
+            )
+            .is_err());
+
+        let object = Aggregation::Object(HashMap::from([
+            ("speed".to_string(), AstarteType::Integer(200)),
+            ("other".to_string(), AstarteType::Integer(200)),
+        ]));
+        assert_eq!(
+            constraints
+                .check("com.test", "/1", &object)
+                .unwrap_err()
+                .path,
+            "/1/speed"
+        );
+    }
+}
@@ -135,6 +135,7 @@ impl Bundle {
 
 #[cfg(not(taurpaulin_include))]
 #[doc(hidden)]
+#[allow(clippy::expect_used)]
 pub mod bench {
     use rustls::PrivateKey;
 
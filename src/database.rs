@@ -19,22 +19,36 @@
  */
 //! Provides functionality for instantiating an Astarte sqlite database.
 
+pub mod cache;
+#[cfg(any(test, feature = "testing"))]
+pub mod faulty;
+pub mod journal;
+pub mod quota;
+
 use async_trait::async_trait;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
-use log::{debug, trace};
+use log::{debug, error, trace};
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 use sqlx::FromRow;
 
+use crate::outbox::{AstarteOutbox, OutboxEntry, OutboxIntent};
 use crate::payload;
 use crate::{types::AstarteType, Error};
 
 /// Data structure providing an implementation of a sqlite database.
 ///
 /// Can be used by an Astarte device to store permanently properties values.
+///
+/// The stored payloads are saved with a checksum, verified when they are loaded. The corrupted
+/// payloads are removed and never returned, see [`AstarteSqliteDatabase::corrupted`].
 #[derive(Clone, Debug)]
 pub struct AstarteSqliteDatabase {
     db_conn: sqlx::Pool<sqlx::Sqlite>,
+    /// Number of corrupted payloads found.
+    corrupted: Arc<AtomicU64>,
 }
 
 /// Data structure used to return stored properties by a database implementing the AstarteDatabase
@@ -47,6 +61,15 @@ pub struct StoredProp {
     pub interface_major: i32,
 }
 
+/// Property written with [`AstarteDatabase::store_props`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropWrite {
+    pub interface: String,
+    pub path: String,
+    pub value: AstarteType,
+    pub interface_major: i32,
+}
+
 /// Trait providing compatibility with Astarte devices to databases.
 ///
 /// Any database implementing this trait can be used as permanent storage for the properties
@@ -64,6 +87,23 @@ pub trait AstarteDatabase {
         value: &AstarteType,
         interface_major: i32,
     ) -> Result<(), Error>;
+    /// Stores many properties at once.
+    ///
+    /// The default implementation stores them one by one, a database supporting transactions
+    /// should store all of them or none.
+    async fn store_props(&self, props: &[PropWrite]) -> Result<(), Error> {
+        for prop in props {
+            self.store_prop(
+                &prop.interface,
+                &prop.path,
+                &prop.value,
+                prop.interface_major,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
     /// Load a property from the database.
     async fn load_prop(
         &self,
@@ -78,6 +118,86 @@ pub trait AstarteDatabase {
     /// Retrieves all property values in the database, together with their interface name, path
     /// and major version.
     async fn load_all_props(&self) -> Result<Vec<StoredProp>, Error>;
+    /// Delete all the properties of an interface from the database.
+    async fn delete_interface(&self, interface: &str) -> Result<(), Error> {
+        for prop in self.load_all_props().await? {
+            if prop.interface == interface {
+                self.delete_prop(&prop.interface, &prop.path).await?;
+            }
+        }
+
+        Ok(())
+    }
+    /// Load the last [sequence number](crate::sequence) sent on an interface.
+    ///
+    /// The sequence numbers are not persisted by default, so they restart from one.
+    async fn load_sequence(&self, _interface: &str) -> Result<Option<i64>, Error> {
+        Ok(None)
+    }
+    /// Store the last [sequence number](crate::sequence) sent on an interface.
+    async fn store_sequence(&self, _interface: &str, _sequence: i64) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Sqlite extended error code for a full database or disk.
+const SQLITE_FULL: &str = "13";
+
+/// Checksum of a stored payload.
+pub(crate) fn checksum(data: &[u8]) -> i64 {
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+
+    i64::from(crc.sum())
+}
+
+/// Checks the payload against the stored checksum, the rows written before the checksums were
+/// introduced don't have one.
+fn is_corrupted(data: &[u8], stored: Option<i64>) -> bool {
+    stored.map_or(false, |stored| stored != checksum(data))
+}
+
+/// Checks if the error is caused by a full database.
+fn is_full(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(err) => err.code().as_deref() == Some(SQLITE_FULL),
+        _ => false,
+    }
+}
+
+/// Stores or replaces a property in the propcache.
+async fn insert_prop<'e, E>(
+    executor: E,
+    interface: &str,
+    path: &str,
+    value: &AstarteType,
+    interface_major: i32,
+) -> Result<(), Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    let value = payload::serialize_individual(value, None)?;
+    let checksum = checksum(&value);
+
+    let res = sqlx::query(
+            "insert or replace into propcache (interface, path, value, interface_major, checksum) VALUES (?,?,?,?,?)",
+        )
+        .bind(interface)
+        .bind(path)
+        .bind(value)
+        .bind(interface_major)
+        .bind(checksum)
+        .execute(executor)
+        .await;
+
+    match res {
+        Ok(_) => Ok(()),
+        Err(err) if is_full(&err) => Err(Error::StoreFull {
+            interface: interface.to_string(),
+            path: path.to_string(),
+        }),
+        Err(err) => Err(err.into()),
+    }
 }
 
 #[async_trait]
@@ -94,17 +214,26 @@ impl AstarteDatabase for AstarteSqliteDatabase {
             interface, path, value
         );
 
-        let value = payload::serialize_individual(value, None)?;
+        insert_prop(&self.db_conn, interface, path, value, interface_major).await
+    }
+
+    async fn store_props(&self, props: &[PropWrite]) -> Result<(), Error> {
+        debug!("Storing {} properties in db", props.len());
+
+        let mut tx = self.db_conn.begin().await?;
 
-        sqlx::query(
-                "insert or replace into propcache (interface, path, value, interface_major) VALUES (?,?,?,?)",
+        for prop in props {
+            insert_prop(
+                &mut *tx,
+                &prop.interface,
+                &prop.path,
+                &prop.value,
+                prop.interface_major,
             )
-            .bind(interface)
-            .bind(path)
-            .bind(value)
-            .bind(interface_major)
-            .execute(&self.db_conn)
             .await?;
+        }
+
+        tx.commit().await?;
 
         Ok(())
     }
@@ -115,8 +244,8 @@ impl AstarteDatabase for AstarteSqliteDatabase {
         path: &str,
         interface_major: i32,
     ) -> Result<Option<AstarteType>, Error> {
-        let res: Option<(Vec<u8>, i32)> = sqlx::query_as(
-            "select value, interface_major from propcache where interface=? and path=?",
+        let res: Option<(Vec<u8>, i32, Option<i64>)> = sqlx::query_as(
+            "select value, interface_major, checksum from propcache where interface=? and path=?",
         )
         .bind(interface)
         .bind(path)
@@ -126,6 +255,12 @@ impl AstarteDatabase for AstarteSqliteDatabase {
         if let Some(res) = res {
             trace!("Loaded property {} {} in db ({:?})", interface, path, res.0);
 
+            if is_corrupted(&res.0, res.2) {
+                self.report_corrupted("property", interface, path);
+                self.delete_prop(interface, path).await?;
+                return Ok(None);
+            }
+
             //if version mismatch, delete
             if res.1 != interface_major {
                 self.delete_prop(interface, path).await?;
@@ -136,6 +271,7 @@ impl AstarteDatabase for AstarteSqliteDatabase {
 
             match data {
                 crate::Aggregation::Individual(data) => Ok(Some(data)),
+                crate::Aggregation::Unset => Ok(Some(AstarteType::Unset)),
                 crate::Aggregation::Object(_) => Err(Error::Reported(
                     "BUG: extracting an object from the database".into(),
                 )),
@@ -164,11 +300,99 @@ impl AstarteDatabase for AstarteSqliteDatabase {
     }
 
     async fn load_all_props(&self) -> Result<Vec<StoredProp>, Error> {
-        let res: Vec<StoredProp> = sqlx::query_as("select * from propcache")
-            .fetch_all(&self.db_conn)
+        let rows: Vec<(String, String, Vec<u8>, i32, Option<i64>)> = sqlx::query_as(
+            "select interface, path, value, interface_major, checksum from propcache",
+        )
+        .fetch_all(&self.db_conn)
+        .await?;
+
+        let mut res = Vec::with_capacity(rows.len());
+
+        for (interface, path, value, interface_major, checksum) in rows {
+            if is_corrupted(&value, checksum) {
+                self.report_corrupted("property", &interface, &path);
+                self.delete_prop(&interface, &path).await?;
+
+                continue;
+            }
+
+            res.push(StoredProp {
+                interface,
+                path,
+                value,
+                interface_major,
+            });
+        }
+
+        Ok(res)
+    }
+
+    async fn delete_interface(&self, interface: &str) -> Result<(), Error> {
+        sqlx::query("delete from propcache where interface=?")
+            .bind(interface)
+            .execute(&self.db_conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn load_sequence(&self, interface: &str) -> Result<Option<i64>, Error> {
+        let res: Option<(i64,)> =
+            sqlx::query_as("select sequence from sequences where interface=?")
+                .bind(interface)
+                .fetch_optional(&self.db_conn)
+                .await?;
+
+        Ok(res.map(|(sequence,)| sequence))
+    }
+
+    async fn store_sequence(&self, interface: &str, sequence: i64) -> Result<(), Error> {
+        sqlx::query("insert or replace into sequences (interface, sequence) VALUES (?,?)")
+            .bind(interface)
+            .bind(sequence)
+            .execute(&self.db_conn)
             .await?;
 
-        return Ok(res);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AstarteOutbox for AstarteSqliteDatabase {
+    async fn pending(&self) -> Result<Vec<OutboxEntry>, Error> {
+        let rows: Vec<(i64, String, String, Vec<u8>, Option<i64>)> =
+            sqlx::query_as("select id, interface, path, payload, checksum from outbox order by id")
+                .fetch_all(&self.db_conn)
+                .await?;
+
+        let mut res = Vec::with_capacity(rows.len());
+
+        for (id, interface, path, payload, checksum) in rows {
+            if is_corrupted(&payload, checksum) {
+                self.report_corrupted("outbox entry", &interface, &path);
+                self.remove(id).await?;
+
+                continue;
+            }
+
+            res.push(OutboxEntry {
+                id,
+                interface,
+                path,
+                payload,
+            });
+        }
+
+        Ok(res)
+    }
+
+    async fn remove(&self, id: i64) -> Result<(), Error> {
+        sqlx::query("delete from outbox where id=?")
+            .bind(id)
+            .execute(&self.db_conn)
+            .await?;
+
+        Ok(())
     }
 }
 
@@ -192,17 +416,172 @@ impl AstarteSqliteDatabase {
 
         let conn = SqlitePoolOptions::new().connect_with(options).await?;
 
-        sqlx::query("CREATE TABLE if not exists propcache (interface TEXT, path TEXT, value BLOB NOT NULL, interface_major INTEGER NOT NULL, PRIMARY KEY (interface, path))").execute(&conn).await?;
+        Self::from_pool(conn).await.map_err(Into::into)
+    }
+
+    /// Creates the tables, adding the checksum to the tables created by older versions.
+    async fn from_pool(conn: sqlx::Pool<sqlx::Sqlite>) -> Result<Self, sqlx::Error> {
+        sqlx::query("CREATE TABLE if not exists propcache (interface TEXT, path TEXT, value BLOB NOT NULL, interface_major INTEGER NOT NULL, checksum INTEGER, PRIMARY KEY (interface, path))").execute(&conn).await?;
+        sqlx::query("CREATE TABLE if not exists outbox (id INTEGER PRIMARY KEY AUTOINCREMENT, interface TEXT NOT NULL, path TEXT NOT NULL, payload BLOB NOT NULL, checksum INTEGER)").execute(&conn).await?;
+        sqlx::query("CREATE TABLE if not exists appkv (namespace TEXT, key TEXT, value BLOB NOT NULL, PRIMARY KEY (namespace, key))").execute(&conn).await?;
+        sqlx::query("CREATE TABLE if not exists sequences (interface TEXT PRIMARY KEY, sequence INTEGER NOT NULL)").execute(&conn).await?;
+
+        for table in ["propcache", "outbox"] {
+            let (has_checksum,): (bool,) = sqlx::query_as(
+                "select count(*) > 0 from pragma_table_info(?) where name = 'checksum'",
+            )
+            .bind(table)
+            .fetch_one(&conn)
+            .await?;
+
+            if !has_checksum {
+                debug!("adding the checksum to the {table} table");
+
+                sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN checksum INTEGER"))
+                    .execute(&conn)
+                    .await?;
+            }
+        }
+
+        Ok(AstarteSqliteDatabase {
+            db_conn: conn,
+            corrupted: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Returns the number of corrupted payloads found and removed from the database.
+    ///
+    /// A payload is corrupted if it doesn't match the checksum saved with it, for example after
+    /// a failure of the storage.
+    pub fn corrupted(&self) -> u64 {
+        self.corrupted.load(Ordering::Relaxed)
+    }
+
+    fn report_corrupted(&self, kind: &str, interface: &str, path: &str) {
+        error!("corrupted {kind} {interface}{path} in the database, removing it");
+
+        self.corrupted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Begins a transaction on the database.
+    ///
+    /// The application can use it to update its own tables and commit a message in the outbox
+    /// atomically, see [`AstarteSqliteDatabase::enqueue`].
+    pub async fn begin(&self) -> Result<sqlx::Transaction<'static, sqlx::Sqlite>, Error> {
+        let tx = self.db_conn.begin().await?;
+
+        Ok(tx)
+    }
+
+    /// Adds a message to the outbox as part of the transaction.
+    ///
+    /// The message will be available to be published only once the transaction is committed.
+    pub async fn enqueue(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        intent: &OutboxIntent,
+    ) -> Result<(), Error> {
+        trace!(
+            "Enqueuing message on {}{} in the outbox",
+            intent.interface,
+            intent.path
+        );
+
+        sqlx::query("insert into outbox (interface, path, payload, checksum) VALUES (?,?,?,?)")
+            .bind(&intent.interface)
+            .bind(&intent.path)
+            .bind(&intent.payload)
+            .bind(checksum(&intent.payload))
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns the value stored by the application for the key in the namespace.
+    ///
+    /// The application can keep its own lightweight state, like the last processed command, in
+    /// the same database used by the SDK.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::database::AstarteSqliteDatabase;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let database = AstarteSqliteDatabase::new("path/to/database/file.sqlite")
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     database.app_set("commands", "last", b"42").await.unwrap();
+    ///
+    ///     let last = database.app_get("commands", "last").await.unwrap();
+    ///     assert_eq!(last.as_deref(), Some(b"42".as_slice()));
+    /// }
+    /// ```
+    pub async fn app_get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let res: Option<(Vec<u8>,)> =
+            sqlx::query_as("select value from appkv where namespace=? and key=?")
+                .bind(namespace)
+                .bind(key)
+                .fetch_optional(&self.db_conn)
+                .await?;
+
+        Ok(res.map(|(value,)| value))
+    }
+
+    /// Stores a value of the application for the key in the namespace, replacing the previous
+    /// one.
+    pub async fn app_set(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), Error> {
+        let mut tx = self.begin().await?;
+
+        Self::app_set_in(&mut tx, namespace, key, value).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Stores a value of the application as part of the transaction, see
+    /// [`AstarteSqliteDatabase::app_set`].
+    pub async fn app_set_in(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        namespace: &str,
+        key: &str,
+        value: &[u8],
+    ) -> Result<(), Error> {
+        trace!("Storing application key {namespace}/{key}");
+
+        sqlx::query("insert or replace into appkv (namespace, key, value) VALUES (?,?,?)")
+            .bind(namespace)
+            .bind(key)
+            .bind(value)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Removes a value of the application, returns true if the key was stored.
+    pub async fn app_delete(&self, namespace: &str, key: &str) -> Result<bool, Error> {
+        let res = sqlx::query("delete from appkv where namespace=? and key=?")
+            .bind(namespace)
+            .bind(key)
+            .execute(&self.db_conn)
+            .await?;
 
-        Ok(AstarteSqliteDatabase { db_conn: conn })
+        Ok(res.rows_affected() > 0)
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::str::FromStr;
+
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
     use crate::database::AstarteDatabase;
+    use crate::outbox::{AstarteOutbox, OutboxIntent};
     use crate::payload;
-    use crate::{database::AstarteSqliteDatabase, database::StoredProp, types::AstarteType};
+    use crate::{database::AstarteSqliteDatabase, database::StoredProp, types::AstarteType, Error};
 
     #[tokio::test]
     async fn test_db() {
@@ -293,4 +672,213 @@ mod test {
             ]
         );
     }
+
+    #[tokio::test]
+    async fn test_outbox() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("outbox.sqlite");
+        let path = db_path.as_path().to_str().unwrap();
+
+        let db = AstarteSqliteDatabase::new(path).await.unwrap();
+
+        assert!(db.pending().await.unwrap().is_empty());
+
+        let first =
+            OutboxIntent::individual("com.test", "/first", AstarteType::Integer(1), None).unwrap();
+        let second =
+            OutboxIntent::individual("com.test", "/second", AstarteType::Integer(2), None).unwrap();
+
+        // rolled back intents are not committed
+        let mut tx = db.begin().await.unwrap();
+        AstarteSqliteDatabase::enqueue(&mut tx, &first)
+            .await
+            .unwrap();
+        tx.rollback().await.unwrap();
+
+        assert!(db.pending().await.unwrap().is_empty());
+
+        let mut tx = db.begin().await.unwrap();
+        AstarteSqliteDatabase::enqueue(&mut tx, &first)
+            .await
+            .unwrap();
+        AstarteSqliteDatabase::enqueue(&mut tx, &second)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let pending = db.pending().await.unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].path, "/first");
+        assert_eq!(pending[0].payload, first.payload);
+        assert_eq!(pending[1].path, "/second");
+
+        db.remove(pending[0].id).await.unwrap();
+
+        let pending = db.pending().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].path, "/second");
+    }
+
+    #[tokio::test]
+    async fn test_store_full() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("full.sqlite");
+        let options = SqliteConnectOptions::from_str(db_path.to_str().unwrap())
+            .unwrap()
+            .create_if_missing(true);
+
+        // single connection, since the page limit is set per connection
+        let db_conn = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .unwrap();
+
+        let db = AstarteSqliteDatabase::from_pool(db_conn).await.unwrap();
+        sqlx::query("PRAGMA max_page_count = 2")
+            .execute(&db.db_conn)
+            .await
+            .unwrap();
+
+        let value = AstarteType::BinaryBlob(vec![0; 64 * 1024]);
+        let res = db.store_prop("com.test", "/test", &value, 1).await;
+
+        assert!(
+            matches!(res, Err(Error::StoreFull { ref interface, ref path }) if interface == "com.test" && path == "/test"),
+            "expected store full, got {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_interface() {
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+
+        let ty = AstarteType::Integer(23);
+
+        db.store_prop("com.test", "/1/test", &ty, 1).await.unwrap();
+        db.store_prop("com.test", "/2/test", &ty, 1).await.unwrap();
+        db.store_prop("com.test2", "/test", &ty, 1).await.unwrap();
+
+        db.delete_interface("com.test").await.unwrap();
+
+        let props = db.load_all_props().await.unwrap();
+        assert_eq!(props.len(), 1);
+        assert_eq!(props[0].interface, "com.test2");
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_payloads() {
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+
+        let ty = AstarteType::Integer(23);
+        db.store_prop("com.test", "/corrupted", &ty, 1)
+            .await
+            .unwrap();
+        db.store_prop("com.test", "/intact", &ty, 1).await.unwrap();
+
+        let mut tx = db.begin().await.unwrap();
+        for path in ["/corrupted", "/intact"] {
+            let intent = OutboxIntent::individual("com.test", path, ty.clone(), None).unwrap();
+            AstarteSqliteDatabase::enqueue(&mut tx, &intent)
+                .await
+                .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        // flip the last byte of the payloads
+        let corrupt = |table: &str, column: &str| {
+            format!("update {table} set {column} = substr({column}, 1, length({column}) - 1) || x'ff' where path = '/corrupted'")
+        };
+        for (table, column) in [("propcache", "value"), ("outbox", "payload")] {
+            sqlx::query(&corrupt(table, column))
+                .execute(&db.db_conn)
+                .await
+                .unwrap();
+        }
+
+        let props = db.load_all_props().await.unwrap();
+        assert_eq!(props.len(), 1);
+        assert_eq!(props[0].path, "/intact");
+        assert_eq!(
+            db.load_prop("com.test", "/corrupted", 1).await.unwrap(),
+            None
+        );
+
+        let pending = db.pending().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].path, "/intact");
+
+        assert_eq!(db.corrupted(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_checksum_migration() {
+        let db_conn = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        // tables created by the older versions
+        sqlx::query("CREATE TABLE propcache (interface TEXT, path TEXT, value BLOB NOT NULL, interface_major INTEGER NOT NULL, PRIMARY KEY (interface, path))").execute(&db_conn).await.unwrap();
+        sqlx::query("CREATE TABLE outbox (id INTEGER PRIMARY KEY AUTOINCREMENT, interface TEXT NOT NULL, path TEXT NOT NULL, payload BLOB NOT NULL)").execute(&db_conn).await.unwrap();
+
+        let ser = payload::serialize_individual(&AstarteType::Integer(23), None).unwrap();
+        sqlx::query("insert into propcache (interface, path, value, interface_major) VALUES ('com.test', '/test', ?, 1)")
+            .bind(&ser)
+            .execute(&db_conn)
+            .await
+            .unwrap();
+
+        let db = AstarteSqliteDatabase::from_pool(db_conn).await.unwrap();
+
+        // the old rows have no checksum
+        assert_eq!(
+            db.load_prop("com.test", "/test", 1).await.unwrap(),
+            Some(AstarteType::Integer(23))
+        );
+
+        db.store_prop("com.test", "/new", &AstarteType::Integer(1), 1)
+            .await
+            .unwrap();
+        assert_eq!(db.load_all_props().await.unwrap().len(), 2);
+        assert_eq!(db.corrupted(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_app_kv() {
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+
+        assert_eq!(db.app_get("app", "last").await.unwrap(), None);
+
+        db.app_set("app", "last", b"1").await.unwrap();
+        db.app_set("app", "last", b"2").await.unwrap();
+        db.app_set("other", "last", b"3").await.unwrap();
+
+        assert_eq!(
+            db.app_get("app", "last").await.unwrap(),
+            Some(b"2".to_vec())
+        );
+        assert_eq!(
+            db.app_get("other", "last").await.unwrap(),
+            Some(b"3".to_vec())
+        );
+
+        // rolled back with the transaction
+        let mut tx = db.begin().await.unwrap();
+        AstarteSqliteDatabase::app_set_in(&mut tx, "app", "last", b"4")
+            .await
+            .unwrap();
+        tx.rollback().await.unwrap();
+        assert_eq!(
+            db.app_get("app", "last").await.unwrap(),
+            Some(b"2".to_vec())
+        );
+
+        // the SDK state is cleared separately
+        db.clear().await.unwrap();
+        assert!(db.app_delete("app", "last").await.unwrap());
+        assert!(!db.app_delete("app", "last").await.unwrap());
+        assert_eq!(db.app_get("app", "last").await.unwrap(), None);
+    }
 }
@@ -0,0 +1,395 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! In memory write-through cache for the properties stored in a database.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+
+use super::{AstarteDatabase, PropWrite, StoredProp};
+use crate::{types::AstarteType, Error};
+
+/// Cached value of a property, `None` if the property is not stored.
+#[derive(Debug, Clone)]
+struct CachedProp {
+    interface_major: i32,
+    value: Option<AstarteType>,
+}
+
+/// Properties cached for a single interface.
+#[derive(Debug, Default)]
+struct InterfaceCache {
+    props: HashMap<String, CachedProp>,
+    /// Insertion order of the paths, to evict the oldest.
+    order: VecDeque<String>,
+}
+
+impl InterfaceCache {
+    fn get(&self, path: &str, interface_major: i32) -> Option<&CachedProp> {
+        self.props
+            .get(path)
+            .filter(|prop| prop.interface_major == interface_major)
+    }
+
+    fn insert(&mut self, path: &str, prop: CachedProp, capacity: usize) {
+        if self.props.insert(path.to_string(), prop).is_some() {
+            return;
+        }
+
+        self.order.push_back(path.to_string());
+
+        while self.order.len() > capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.props.remove(&evicted);
+            }
+        }
+    }
+
+    fn remove(&mut self, path: &str) {
+        if self.props.remove(path).is_some() {
+            self.order.retain(|p| p != path);
+        }
+    }
+}
+
+/// Database wrapper keeping in memory the most recently stored or loaded properties.
+///
+/// The writes are always forwarded to the wrapped database before updating the cache, so the
+/// values received from Astarte are cached when stored. Each interface keeps at most `capacity`
+/// properties, evicting the oldest ones.
+///
+/// ```no_run
+/// use astarte_device_sdk::{
+///     database::{cache::CachedDatabase, AstarteSqliteDatabase}, options::AstarteOptions,
+/// };
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let database = AstarteSqliteDatabase::new("path/to/database/file.sqlite")
+///         .await
+///         .unwrap();
+///
+///     let sdk_options =
+///         AstarteOptions::new("_","_","_","_").database(CachedDatabase::new(database, 64));
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CachedDatabase<D> {
+    inner: D,
+    capacity: usize,
+    cache: Mutex<HashMap<String, InterfaceCache>>,
+}
+
+impl<D> CachedDatabase<D> {
+    /// Wrap the database, caching at most `capacity` properties per interface.
+    pub fn new(inner: D, capacity: usize) -> Self {
+        Self {
+            inner,
+            capacity,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the wrapped database.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Locks the cache, emptying it if a panic left it poisoned since it could be inconsistent.
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, InterfaceCache>> {
+        self.cache.lock().unwrap_or_else(|err| {
+            let mut cache = err.into_inner();
+            cache.clear();
+
+            cache
+        })
+    }
+
+    fn cached(&self, interface: &str, path: &str, interface_major: i32) -> Option<CachedProp> {
+        self.lock()
+            .get(interface)
+            .and_then(|interface| interface.get(path, interface_major))
+            .cloned()
+    }
+
+    fn update(&self, interface: &str, path: &str, prop: CachedProp) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.lock()
+            .entry(interface.to_string())
+            .or_default()
+            .insert(path, prop, self.capacity);
+    }
+
+    fn invalidate(&self, interface: &str, path: &str) {
+        if let Some(interface) = self.lock().get_mut(interface) {
+            interface.remove(path);
+        }
+    }
+}
+
+#[async_trait]
+impl<D> AstarteDatabase for CachedDatabase<D>
+where
+    D: AstarteDatabase + Send + Sync,
+{
+    async fn store_prop(
+        &self,
+        interface: &str,
+        path: &str,
+        value: &AstarteType,
+        interface_major: i32,
+    ) -> Result<(), Error> {
+        // the cached value could be stale if the write fails
+        self.invalidate(interface, path);
+
+        self.inner
+            .store_prop(interface, path, value, interface_major)
+            .await?;
+
+        self.update(
+            interface,
+            path,
+            CachedProp {
+                interface_major,
+                value: Some(value.clone()),
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn store_props(&self, props: &[PropWrite]) -> Result<(), Error> {
+        for prop in props {
+            self.invalidate(&prop.interface, &prop.path);
+        }
+
+        self.inner.store_props(props).await?;
+
+        for prop in props {
+            self.update(
+                &prop.interface,
+                &prop.path,
+                CachedProp {
+                    interface_major: prop.interface_major,
+                    value: Some(prop.value.clone()),
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn load_prop(
+        &self,
+        interface: &str,
+        path: &str,
+        interface_major: i32,
+    ) -> Result<Option<AstarteType>, Error> {
+        if let Some(prop) = self.cached(interface, path, interface_major) {
+            return Ok(prop.value);
+        }
+
+        let value = self
+            .inner
+            .load_prop(interface, path, interface_major)
+            .await?;
+
+        self.update(
+            interface,
+            path,
+            CachedProp {
+                interface_major,
+                value: value.clone(),
+            },
+        );
+
+        Ok(value)
+    }
+
+    async fn delete_prop(&self, interface: &str, path: &str) -> Result<(), Error> {
+        self.invalidate(interface, path);
+
+        self.inner.delete_prop(interface, path).await
+    }
+
+    async fn clear(&self) -> Result<(), Error> {
+        self.lock().clear();
+
+        self.inner.clear().await
+    }
+
+    async fn load_all_props(&self) -> Result<Vec<StoredProp>, Error> {
+        self.inner.load_all_props().await
+    }
+
+    async fn delete_interface(&self, interface: &str) -> Result<(), Error> {
+        self.lock().remove(interface);
+
+        self.inner.delete_interface(interface).await
+    }
+
+    async fn load_sequence(&self, interface: &str) -> Result<Option<i64>, Error> {
+        self.inner.load_sequence(interface).await
+    }
+
+    async fn store_sequence(&self, interface: &str, sequence: i64) -> Result<(), Error> {
+        self.inner.store_sequence(interface, sequence).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::database::AstarteSqliteDatabase;
+
+    /// Database counting the loads.
+    struct CountingDatabase {
+        loads: AtomicUsize,
+        inner: AstarteSqliteDatabase,
+    }
+
+    #[async_trait]
+    impl AstarteDatabase for CountingDatabase {
+        async fn store_prop(
+            &self,
+            interface: &str,
+            path: &str,
+            value: &AstarteType,
+            interface_major: i32,
+        ) -> Result<(), Error> {
+            self.inner
+                .store_prop(interface, path, value, interface_major)
+                .await
+        }
+
+        async fn load_prop(
+            &self,
+            interface: &str,
+            path: &str,
+            interface_major: i32,
+        ) -> Result<Option<AstarteType>, Error> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+
+            self.inner.load_prop(interface, path, interface_major).await
+        }
+
+        async fn delete_prop(&self, interface: &str, path: &str) -> Result<(), Error> {
+            self.inner.delete_prop(interface, path).await
+        }
+
+        async fn clear(&self) -> Result<(), Error> {
+            self.inner.clear().await
+        }
+
+        async fn load_all_props(&self) -> Result<Vec<StoredProp>, Error> {
+            self.inner.load_all_props().await
+        }
+    }
+
+    async fn cached_database(capacity: usize) -> CachedDatabase<CountingDatabase> {
+        let inner = CountingDatabase {
+            loads: AtomicUsize::new(0),
+            inner: AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap(),
+        };
+
+        CachedDatabase::new(inner, capacity)
+    }
+
+    fn loads(db: &CachedDatabase<CountingDatabase>) -> usize {
+        db.inner.loads.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn test_write_through() {
+        let db = cached_database(8).await;
+
+        db.store_prop("com.test", "/1/period", &AstarteType::Integer(10), 1)
+            .await
+            .unwrap();
+
+        for _ in 0..10 {
+            let value = db.load_prop("com.test", "/1/period", 1).await.unwrap();
+            assert_eq!(value, Some(AstarteType::Integer(10)));
+        }
+        assert_eq!(loads(&db), 0);
+
+        // an update replaces the cached value
+        db.store_prop("com.test", "/1/period", &AstarteType::Integer(20), 1)
+            .await
+            .unwrap();
+        let value = db.load_prop("com.test", "/1/period", 1).await.unwrap();
+        assert_eq!(value, Some(AstarteType::Integer(20)));
+        assert_eq!(loads(&db), 0);
+
+        db.delete_prop("com.test", "/1/period").await.unwrap();
+        let value = db.load_prop("com.test", "/1/period", 1).await.unwrap();
+        assert_eq!(value, None);
+        assert_eq!(loads(&db), 1);
+
+        // missing properties are cached too
+        let value = db.load_prop("com.test", "/1/period", 1).await.unwrap();
+        assert_eq!(value, None);
+        assert_eq!(loads(&db), 1);
+    }
+
+    #[tokio::test]
+    async fn test_major_version() {
+        let db = cached_database(8).await;
+
+        db.store_prop("com.test", "/1/period", &AstarteType::Integer(10), 1)
+            .await
+            .unwrap();
+
+        let value = db.load_prop("com.test", "/1/period", 2).await.unwrap();
+        assert_eq!(value, None);
+        assert_eq!(loads(&db), 1);
+    }
+
+    #[tokio::test]
+    async fn test_capacity() {
+        let db = cached_database(2).await;
+
+        for (i, path) in ["/1", "/2", "/3"].into_iter().enumerate() {
+            db.store_prop("com.test", path, &AstarteType::Integer(i as i32), 1)
+                .await
+                .unwrap();
+        }
+
+        // the other interfaces have a separate capacity
+        db.store_prop("com.other", "/1", &AstarteType::Integer(0), 1)
+            .await
+            .unwrap();
+
+        db.load_prop("com.test", "/3", 1).await.unwrap();
+        db.load_prop("com.test", "/2", 1).await.unwrap();
+        db.load_prop("com.other", "/1", 1).await.unwrap();
+        assert_eq!(loads(&db), 0);
+
+        // the oldest was evicted
+        let value = db.load_prop("com.test", "/1", 1).await.unwrap();
+        assert_eq!(value, Some(AstarteType::Integer(0)));
+        assert_eq!(loads(&db), 1);
+    }
+}
@@ -0,0 +1,432 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Database wrapper injecting failures and latency, to test the behaviour under storage errors.
+//!
+//! Available with `feature = ["testing"]`.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use astarte_device_sdk::database::{
+//!     faulty::{Fault, FaultyStore, StoreOperation},
+//!     AstarteSqliteDatabase,
+//! };
+//! use astarte_device_sdk::options::AstarteOptions;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let database = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+//!
+//!     let store = FaultyStore::new(database);
+//!     store.inject(StoreOperation::StoreProp, Fault::full().times(2));
+//!     store.inject(StoreOperation::LoadProp, Fault::latency(Duration::from_millis(100)));
+//!
+//!     // the clone shares the faults, so they can be changed while the device is running
+//!     let sdk_options = AstarteOptions::new("_","_","_","_").database(store.clone());
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use super::{AstarteDatabase, PropWrite, StoredProp};
+use crate::{types::AstarteType, Error};
+
+/// Operations of the [`AstarteDatabase`] trait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StoreOperation {
+    StoreProp,
+    StoreProps,
+    LoadProp,
+    DeleteProp,
+    Clear,
+    LoadAllProps,
+    DeleteInterface,
+    LoadSequence,
+    StoreSequence,
+}
+
+/// Error returned by a [`Fault`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FaultError {
+    /// [`Error::StoreFull`], like a full disk.
+    Full,
+    /// The timeout of the connection pool.
+    Timeout,
+    /// A generic database error.
+    Other(String),
+}
+
+/// Failure or latency injected on an operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+    error: Option<FaultError>,
+    latency: Duration,
+    /// Remaining number of operations failed, `None` to always fail.
+    times: Option<usize>,
+}
+
+impl Fault {
+    fn new(error: Option<FaultError>) -> Self {
+        Self {
+            error,
+            latency: Duration::ZERO,
+            times: None,
+        }
+    }
+
+    /// Fails with [`Error::StoreFull`], like a full disk.
+    pub fn full() -> Self {
+        Self::new(Some(FaultError::Full))
+    }
+
+    /// Fails with the timeout of the database connection pool.
+    pub fn timeout() -> Self {
+        Self::new(Some(FaultError::Timeout))
+    }
+
+    /// Fails with a generic database error with the message.
+    pub fn error(message: &str) -> Self {
+        Self::new(Some(FaultError::Other(message.to_string())))
+    }
+
+    /// Delays the operation, without failing it.
+    pub fn latency(latency: Duration) -> Self {
+        Self::new(None).delay(latency)
+    }
+
+    /// Delays the operation before the failure.
+    pub fn delay(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+
+        self
+    }
+
+    /// Applies the fault only to the next `times` operations, then they succeed.
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+
+        self
+    }
+
+    fn to_error(&self, interface: &str, path: &str) -> Option<Error> {
+        let error = match self.error.as_ref()? {
+            FaultError::Full => Error::StoreFull {
+                interface: interface.to_string(),
+                path: path.to_string(),
+            },
+            FaultError::Timeout => Error::DbError(sqlx::Error::PoolTimedOut),
+            FaultError::Other(message) => Error::DbError(sqlx::Error::Protocol(message.clone())),
+        };
+
+        Some(error)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Faults {
+    faults: HashMap<StoreOperation, Fault>,
+    calls: HashMap<StoreOperation, usize>,
+}
+
+impl Faults {
+    /// Counts the call and takes the fault to apply, if any.
+    fn take(&mut self, operation: StoreOperation) -> Option<Fault> {
+        *self.calls.entry(operation).or_default() += 1;
+
+        let fault = self.faults.get(&operation)?.clone();
+
+        match fault.times {
+            Some(0) | Some(1) => {
+                self.faults.remove(&operation);
+            }
+            Some(times) => {
+                self.faults.insert(
+                    operation,
+                    Fault {
+                        times: Some(times - 1),
+                        ..fault.clone()
+                    },
+                );
+            }
+            None => {}
+        }
+
+        // a fault applied zero times is removed without failing
+        (fault.times != Some(0)).then_some(fault)
+    }
+}
+
+/// Database wrapper injecting the configured faults on each operation.
+///
+/// The clones share the faults and the wrapped database.
+#[derive(Debug)]
+pub struct FaultyStore<D> {
+    inner: Arc<D>,
+    faults: Arc<Mutex<Faults>>,
+}
+
+impl<D> Clone for FaultyStore<D> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            faults: Arc::clone(&self.faults),
+        }
+    }
+}
+
+impl<D> FaultyStore<D> {
+    /// Wraps the database, without faults.
+    pub fn new(inner: D) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            faults: Arc::new(Mutex::new(Faults::default())),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Faults> {
+        // the faults are always valid, since they are only assigned while locked
+        self.faults.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Injects the fault on the operation, replacing the previous one.
+    pub fn inject(&self, operation: StoreOperation, fault: Fault) {
+        self.lock().faults.insert(operation, fault);
+    }
+
+    /// Removes the fault of the operation.
+    pub fn heal(&self, operation: StoreOperation) {
+        self.lock().faults.remove(&operation);
+    }
+
+    /// Removes all the faults.
+    pub fn heal_all(&self) {
+        self.lock().faults.clear();
+    }
+
+    /// Returns the number of calls of the operation, failed or not.
+    pub fn calls(&self, operation: StoreOperation) -> usize {
+        self.lock().calls.get(&operation).copied().unwrap_or(0)
+    }
+
+    /// Returns the wrapped database.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Applies the fault of the operation.
+    async fn apply(
+        &self,
+        operation: StoreOperation,
+        interface: &str,
+        path: &str,
+    ) -> Result<(), Error> {
+        let Some(fault) = self.lock().take(operation) else {
+            return Ok(());
+        };
+
+        if !fault.latency.is_zero() {
+            tokio::time::sleep(fault.latency).await;
+        }
+
+        match fault.to_error(interface, path) {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<D> AstarteDatabase for FaultyStore<D>
+where
+    D: AstarteDatabase + Send + Sync,
+{
+    async fn store_prop(
+        &self,
+        interface: &str,
+        path: &str,
+        value: &AstarteType,
+        interface_major: i32,
+    ) -> Result<(), Error> {
+        self.apply(StoreOperation::StoreProp, interface, path)
+            .await?;
+
+        self.inner
+            .store_prop(interface, path, value, interface_major)
+            .await
+    }
+
+    async fn store_props(&self, props: &[PropWrite]) -> Result<(), Error> {
+        let (interface, path) = props.first().map_or(("", ""), |prop| {
+            (prop.interface.as_str(), prop.path.as_str())
+        });
+
+        self.apply(StoreOperation::StoreProps, interface, path)
+            .await?;
+
+        self.inner.store_props(props).await
+    }
+
+    async fn load_prop(
+        &self,
+        interface: &str,
+        path: &str,
+        interface_major: i32,
+    ) -> Result<Option<AstarteType>, Error> {
+        self.apply(StoreOperation::LoadProp, interface, path)
+            .await?;
+
+        self.inner.load_prop(interface, path, interface_major).await
+    }
+
+    async fn delete_prop(&self, interface: &str, path: &str) -> Result<(), Error> {
+        self.apply(StoreOperation::DeleteProp, interface, path)
+            .await?;
+
+        self.inner.delete_prop(interface, path).await
+    }
+
+    async fn clear(&self) -> Result<(), Error> {
+        self.apply(StoreOperation::Clear, "", "").await?;
+
+        self.inner.clear().await
+    }
+
+    async fn load_all_props(&self) -> Result<Vec<StoredProp>, Error> {
+        self.apply(StoreOperation::LoadAllProps, "", "").await?;
+
+        self.inner.load_all_props().await
+    }
+
+    async fn delete_interface(&self, interface: &str) -> Result<(), Error> {
+        self.apply(StoreOperation::DeleteInterface, interface, "")
+            .await?;
+
+        self.inner.delete_interface(interface).await
+    }
+
+    async fn load_sequence(&self, interface: &str) -> Result<Option<i64>, Error> {
+        self.apply(StoreOperation::LoadSequence, interface, "")
+            .await?;
+
+        self.inner.load_sequence(interface).await
+    }
+
+    async fn store_sequence(&self, interface: &str, sequence: i64) -> Result<(), Error> {
+        self.apply(StoreOperation::StoreSequence, interface, "")
+            .await?;
+
+        self.inner.store_sequence(interface, sequence).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::AstarteSqliteDatabase;
+
+    async fn faulty_store() -> FaultyStore<AstarteSqliteDatabase> {
+        let inner = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+
+        FaultyStore::new(inner)
+    }
+
+    #[tokio::test]
+    async fn test_fault_times() {
+        let store = faulty_store().await;
+        store.inject(StoreOperation::StoreProp, Fault::full().times(2));
+
+        for _ in 0..2 {
+            let res = store
+                .store_prop("com.test", "/1/enable", &AstarteType::Boolean(true), 1)
+                .await;
+            assert!(
+                matches!(&res, Err(Error::StoreFull { interface, path }) if interface == "com.test" && path == "/1/enable"),
+                "{res:?}"
+            );
+        }
+
+        store
+            .store_prop("com.test", "/1/enable", &AstarteType::Boolean(true), 1)
+            .await
+            .unwrap();
+        assert_eq!(store.calls(StoreOperation::StoreProp), 3);
+
+        // the other operations are not affected
+        let value = store.load_prop("com.test", "/1/enable", 1).await.unwrap();
+        assert_eq!(value, Some(AstarteType::Boolean(true)));
+    }
+
+    #[tokio::test]
+    async fn test_fault_shared_and_healed() {
+        let store = faulty_store().await;
+        let handle = store.clone();
+
+        handle.inject(StoreOperation::LoadAllProps, Fault::timeout());
+        for _ in 0..3 {
+            let res = store.load_all_props().await;
+            assert!(matches!(
+                res,
+                Err(Error::DbError(sqlx::Error::PoolTimedOut))
+            ));
+        }
+
+        handle.heal(StoreOperation::LoadAllProps);
+        assert!(store.load_all_props().await.unwrap().is_empty());
+
+        handle.inject(StoreOperation::Clear, Fault::error("disk I/O error"));
+        assert!(matches!(
+            store.clear().await,
+            Err(Error::DbError(sqlx::Error::Protocol(_)))
+        ));
+
+        handle.heal_all();
+        store.clear().await.unwrap();
+        assert_eq!(handle.calls(StoreOperation::Clear), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fault_latency() {
+        let store = faulty_store().await;
+        store.inject(
+            StoreOperation::LoadProp,
+            Fault::latency(Duration::from_millis(50)),
+        );
+
+        let start = tokio::time::Instant::now();
+        let value = store.load_prop("com.test", "/1/enable", 1).await.unwrap();
+
+        assert_eq!(value, None);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        store.inject(
+            StoreOperation::DeleteProp,
+            Fault::full().delay(Duration::from_millis(50)),
+        );
+
+        let start = tokio::time::Instant::now();
+        let res = store.delete_prop("com.test", "/1/enable").await;
+
+        assert!(matches!(res, Err(Error::StoreFull { .. })));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
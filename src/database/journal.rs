@@ -0,0 +1,915 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Append-only journal deferring the writes of the properties to a database.
+//!
+//! Each write is appended to the journal and synced to disk, then the journal is folded into the
+//! wrapped database when it has enough entries or on [`JournaledDatabase::fold`]. A property
+//! written many times between two folds is written to the database only once, reducing the
+//! writes on flash storage.
+//!
+//! Every entry has its length and checksum, so an entry torn by a crash while appending is
+//! discarded when the journal is replayed. The properties written together with
+//! [`store_props`](super::AstarteDatabase::store_props) are appended as a single entry, so they
+//! are replayed all or none. Folding applies the entries to the database before
+//! removing them from the journal, if the device crashes in between they are applied again on
+//! the next start.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use log::debug;
+
+use super::{checksum, AstarteDatabase, PropWrite, StoredProp};
+use crate::append_log::AppendLog;
+use crate::{payload, types::AstarteType, Aggregation, Error};
+
+/// Default number of entries in the journal before it's folded into the database.
+const DEFAULT_FOLD_THRESHOLD: usize = 256;
+
+/// Size of the length and checksum before each entry.
+const HEADER_SIZE: usize = 8;
+
+const STORE: u8 = 0;
+const DELETE: u8 = 1;
+const BATCH: u8 = 2;
+
+/// Write of a property waiting to be folded in the database.
+#[derive(Debug, Clone)]
+struct Pending {
+    /// Sequence number of the write, to check if it was replaced while folding.
+    seq: u64,
+    /// Major version and value of the property, `None` if it was deleted.
+    prop: Option<(i32, AstarteType)>,
+}
+
+/// Entry of the journal.
+#[derive(Debug, PartialEq)]
+enum Entry {
+    Store {
+        interface: String,
+        path: String,
+        interface_major: i32,
+        value: AstarteType,
+    },
+    Delete {
+        interface: String,
+        path: String,
+    },
+    /// Writes appended in a single entry, a torn batch is discarded as a whole.
+    Batch(Vec<Entry>),
+}
+
+impl Entry {
+    fn new(interface: &str, path: &str, prop: Option<(i32, &AstarteType)>) -> Self {
+        match prop {
+            Some((interface_major, value)) => Entry::Store {
+                interface: interface.to_string(),
+                path: path.to_string(),
+                interface_major,
+                value: value.clone(),
+            },
+            None => Entry::Delete {
+                interface: interface.to_string(),
+                path: path.to_string(),
+            },
+        }
+    }
+
+    /// Number of writes of properties in the entry.
+    fn writes(&self) -> usize {
+        match self {
+            Entry::Batch(entries) => entries.iter().map(Entry::writes).sum(),
+            Entry::Store { .. } | Entry::Delete { .. } => 1,
+        }
+    }
+
+    /// Appends the entry, with its length and checksum, to the buffer.
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        let mut body = Vec::new();
+        self.encode_body(&mut body)?;
+
+        let len = u32::try_from(body.len())
+            .map_err(|_| Error::Reported("journal entry too long".to_string()))?;
+        // the checksum is the crc32, widened to an i64
+        let crc = checksum(&body) as u32;
+
+        buf.extend_from_slice(&len.to_le_bytes());
+        buf.extend_from_slice(&crc.to_le_bytes());
+        buf.extend_from_slice(&body);
+
+        Ok(())
+    }
+
+    fn encode_body(&self, body: &mut Vec<u8>) -> Result<(), Error> {
+        let (interface, path) = match self {
+            Entry::Store {
+                interface, path, ..
+            } => {
+                body.push(STORE);
+                (interface, path)
+            }
+            Entry::Delete { interface, path } => {
+                body.push(DELETE);
+                (interface, path)
+            }
+            Entry::Batch(entries) => {
+                body.push(BATCH);
+
+                return entries.iter().try_for_each(|entry| entry.encode(body));
+            }
+        };
+
+        for s in [interface, path] {
+            let len = u16::try_from(s.len())
+                .map_err(|_| Error::Reported(format!("journal entry too long for {s}")))?;
+
+            body.extend_from_slice(&len.to_le_bytes());
+            body.extend_from_slice(s.as_bytes());
+        }
+
+        if let Entry::Store {
+            interface_major,
+            value,
+            ..
+        } = self
+        {
+            body.extend_from_slice(&interface_major.to_le_bytes());
+            payload::write_individual(body, value, None)?;
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the entries, returning them with the length of the valid part of the journal.
+    ///
+    /// The decoding stops at the first entry that is truncated or has a wrong checksum.
+    fn decode_all(mut journal: &[u8]) -> (Vec<Entry>, usize) {
+        let total = journal.len();
+        let mut entries = Vec::new();
+
+        while let Some((entry, rest)) = Self::decode(journal) {
+            entries.push(entry);
+            journal = rest;
+        }
+
+        (entries, total - journal.len())
+    }
+
+    fn decode(journal: &[u8]) -> Option<(Entry, &[u8])> {
+        let len = u32::from_le_bytes(journal.get(..4)?.try_into().ok()?) as usize;
+        let crc = u32::from_le_bytes(journal.get(4..HEADER_SIZE)?.try_into().ok()?);
+
+        let end = HEADER_SIZE.checked_add(len)?;
+        let body = journal.get(HEADER_SIZE..end)?;
+        if checksum(body) as u32 != crc {
+            return None;
+        }
+
+        let (&kind, rest) = body.split_first()?;
+        let entry = match kind {
+            BATCH => {
+                let (entries, valid) = Self::decode_all(rest);
+                if valid != rest.len() {
+                    return None;
+                }
+
+                Entry::Batch(entries)
+            }
+            kind => Self::decode_prop(kind, rest)?,
+        };
+
+        Some((entry, journal.get(end..)?))
+    }
+
+    fn decode_prop(kind: u8, body: &[u8]) -> Option<Entry> {
+        let (interface, rest) = decode_str(body)?;
+        let (path, rest) = decode_str(rest)?;
+
+        let entry = match kind {
+            STORE => {
+                let major = rest.get(..4)?.try_into().ok()?;
+                let value = rest.get(4..)?;
+                let value = match payload::deserialize(value).ok()? {
+                    Aggregation::Individual(value) => value,
+                    Aggregation::Unset => AstarteType::Unset,
+                    Aggregation::Object(_) => return None,
+                };
+
+                Entry::Store {
+                    interface,
+                    path,
+                    interface_major: i32::from_le_bytes(major),
+                    value,
+                }
+            }
+            DELETE => Entry::Delete { interface, path },
+            _ => return None,
+        };
+
+        Some(entry)
+    }
+}
+
+fn decode_str(buf: &[u8]) -> Option<(String, &[u8])> {
+    let len = u16::from_le_bytes(buf.get(..2)?.try_into().ok()?) as usize;
+    let rest = buf.get(2..)?;
+
+    let s = std::str::from_utf8(rest.get(..len)?).ok()?;
+
+    Some((s.to_string(), rest.get(len..)?))
+}
+
+/// Journal file, written only from the blocking threads.
+#[derive(Debug)]
+struct JournalFile {
+    log: AppendLog,
+    /// Writes in the journal, a property can have more than one.
+    entries: usize,
+    last_fold: Instant,
+}
+
+impl JournalFile {
+    /// Appends the entry to the file and syncs it.
+    fn append(&mut self, entry: &Entry) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        entry.encode(&mut buf)?;
+
+        self.log.append(&buf).map_err(Error::Journal)?;
+        self.entries += entry.writes();
+
+        Ok(())
+    }
+
+    /// Replaces the journal with the given entries.
+    fn rewrite(&mut self, buf: &[u8], entries: usize) -> Result<(), Error> {
+        self.log.rewrite(buf).map_err(Error::Journal)?;
+        self.entries = entries;
+        self.last_fold = Instant::now();
+
+        Ok(())
+    }
+}
+
+/// Writes not yet folded in the database.
+#[derive(Debug, Default)]
+struct Journal {
+    seq: u64,
+    pending: HashMap<(String, String), Pending>,
+}
+
+impl Journal {
+    /// Updates the pending writes with the entry.
+    fn apply(&mut self, entry: Entry) {
+        let (key, prop) = match entry {
+            Entry::Store {
+                interface,
+                path,
+                interface_major,
+                value,
+            } => ((interface, path), Some((interface_major, value))),
+            Entry::Delete { interface, path } => ((interface, path), None),
+            Entry::Batch(entries) => {
+                entries.into_iter().for_each(|entry| self.apply(entry));
+
+                return;
+            }
+        };
+
+        self.seq += 1;
+        self.pending.insert(
+            key,
+            Pending {
+                seq: self.seq,
+                prop,
+            },
+        );
+    }
+
+    /// Encodes the pending writes, in the order they were written.
+    fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut pending: Vec<_> = self.pending.iter().collect();
+        pending.sort_by_key(|(_, pending)| pending.seq);
+
+        let mut buf = Vec::new();
+        for ((interface, path), pending) in pending {
+            let prop = pending.prop.as_ref().map(|(major, value)| (*major, value));
+
+            Entry::new(interface, path, prop).encode(&mut buf)?;
+        }
+
+        Ok(buf)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs the file operations on a blocking thread, to not stall the runtime while syncing.
+async fn unblock<T, F>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| Error::Journal(std::io::Error::new(std::io::ErrorKind::Other, err)))?
+}
+
+/// Database wrapper appending the writes of the properties to a journal, folded in the wrapped
+/// database when the journal reaches a number of entries.
+///
+/// The reads return the values in the journal before querying the wrapped database. The journal
+/// is synced on each write from a blocking thread, the write returns when the data is on disk.
+///
+/// ```no_run
+/// use astarte_device_sdk::{
+///     database::{journal::JournaledDatabase, AstarteSqliteDatabase}, options::AstarteOptions,
+/// };
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let database = AstarteSqliteDatabase::new("path/to/database/file.sqlite")
+///         .await
+///         .unwrap();
+///
+///     let database = JournaledDatabase::open(database, "path/to/database/props.journal")
+///         .await
+///         .unwrap()
+///         .fold_threshold(1024);
+///
+///     let sdk_options = AstarteOptions::new("_","_","_","_").database(database);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct JournaledDatabase<D> {
+    inner: D,
+    fold_threshold: usize,
+    fold_interval: Option<Duration>,
+    /// Locked only from the blocking threads, while writing the file.
+    file: Arc<Mutex<JournalFile>>,
+    /// Locked briefly by the reads, the pending writes are updated after the file is synced.
+    journal: Arc<Mutex<Journal>>,
+    /// Serializes the folds.
+    folding: tokio::sync::Mutex<()>,
+}
+
+impl<D> JournaledDatabase<D>
+where
+    D: AstarteDatabase + Send + Sync,
+{
+    /// Wrap the database with the journal at the given path, it's created if missing.
+    ///
+    /// The entries already in the journal, written before a crash or a restart, are folded in
+    /// the database.
+    pub async fn open(inner: D, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+
+        let (file, entries) = unblock(move || read_journal(path)).await?;
+
+        debug!("replaying {} entries of the journal", entries.len());
+
+        let mut journal = Journal::default();
+        for entry in entries {
+            journal.apply(entry);
+        }
+
+        let db = Self {
+            inner,
+            fold_threshold: DEFAULT_FOLD_THRESHOLD,
+            fold_interval: None,
+            file: Arc::new(Mutex::new(file)),
+            journal: Arc::new(Mutex::new(journal)),
+            folding: tokio::sync::Mutex::new(()),
+        };
+
+        db.fold().await?;
+
+        Ok(db)
+    }
+
+    /// Number of entries in the journal before it's folded in the database, 256 by default.
+    ///
+    /// With zero the journal is folded on every write.
+    pub fn fold_threshold(mut self, entries: usize) -> Self {
+        self.fold_threshold = entries;
+
+        self
+    }
+
+    /// Fold the journal on the first write after the interval from the last fold, even if it
+    /// didn't reach the threshold.
+    pub fn fold_interval(mut self, interval: Duration) -> Self {
+        self.fold_interval = Some(interval);
+
+        self
+    }
+
+    /// Returns the number of properties with a write not yet folded in the database.
+    pub fn pending(&self) -> usize {
+        lock(&self.journal).pending.len()
+    }
+
+    /// Returns the wrapped database.
+    ///
+    /// The pending writes are lost if the journal is not folded first, but they are still in the
+    /// journal file if it's opened again.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Runs a function with the journal file on a blocking thread.
+    async fn with_file<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut JournalFile, &Mutex<Journal>) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let file = Arc::clone(&self.file);
+        let journal = Arc::clone(&self.journal);
+
+        unblock(move || f(&mut lock(&file), &journal)).await
+    }
+
+    /// Apply the journal to the wrapped database and remove the applied entries from it.
+    pub async fn fold(&self) -> Result<(), Error> {
+        let _folding = self.folding.lock().await;
+
+        let pending: Vec<_> = lock(&self.journal)
+            .pending
+            .iter()
+            .map(|(key, pending)| (key.clone(), pending.clone()))
+            .collect();
+
+        if !pending.is_empty() {
+            debug!("folding {} properties of the journal", pending.len());
+        }
+
+        for ((interface, path), pending) in &pending {
+            match &pending.prop {
+                Some((major, value)) => {
+                    self.inner
+                        .store_prop(interface, path, value, *major)
+                        .await?
+                }
+                None => self.inner.delete_prop(interface, path).await?,
+            }
+        }
+
+        self.with_file(move |file, journal| {
+            let (buf, entries) = {
+                let mut journal = lock(journal);
+
+                // keep the writes received while folding
+                for (key, folded) in pending {
+                    if journal.pending.get(&key).map(|pending| pending.seq) == Some(folded.seq) {
+                        journal.pending.remove(&key);
+                    }
+                }
+
+                (journal.encode()?, journal.pending.len())
+            };
+
+            if buf.is_empty() && file.log.is_empty() {
+                return Ok(());
+            }
+
+            file.rewrite(&buf, entries)
+        })
+        .await
+    }
+
+    async fn write(&self, entry: Entry) -> Result<(), Error> {
+        let fold_threshold = self.fold_threshold;
+        let fold_interval = self.fold_interval;
+
+        let fold = self
+            .with_file(move |file, journal| {
+                file.append(&entry)?;
+                lock(journal).apply(entry);
+
+                let interval_elapsed =
+                    fold_interval.map_or(false, |interval| file.last_fold.elapsed() >= interval);
+
+                Ok(file.entries >= fold_threshold || interval_elapsed)
+            })
+            .await?;
+
+        if fold {
+            self.fold().await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Opens the journal file, returning the entries in it.
+fn read_journal(path: PathBuf) -> Result<(JournalFile, Vec<Entry>), Error> {
+    let (mut log, content) = AppendLog::open(path).map_err(Error::Journal)?;
+
+    let (entries, valid) = Entry::decode_all(&content);
+    log.truncate(valid).map_err(Error::Journal)?;
+
+    let file = JournalFile {
+        log,
+        entries: entries.iter().map(Entry::writes).sum(),
+        last_fold: Instant::now(),
+    };
+
+    Ok((file, entries))
+}
+
+#[async_trait]
+impl<D> AstarteDatabase for JournaledDatabase<D>
+where
+    D: AstarteDatabase + Send + Sync,
+{
+    async fn store_prop(
+        &self,
+        interface: &str,
+        path: &str,
+        value: &AstarteType,
+        interface_major: i32,
+    ) -> Result<(), Error> {
+        self.write(Entry::new(interface, path, Some((interface_major, value))))
+            .await
+    }
+
+    async fn store_props(&self, props: &[PropWrite]) -> Result<(), Error> {
+        if props.is_empty() {
+            return Ok(());
+        }
+
+        let entries = props
+            .iter()
+            .map(|prop| {
+                Entry::new(
+                    &prop.interface,
+                    &prop.path,
+                    Some((prop.interface_major, &prop.value)),
+                )
+            })
+            .collect();
+
+        self.write(Entry::Batch(entries)).await
+    }
+
+    async fn load_prop(
+        &self,
+        interface: &str,
+        path: &str,
+        interface_major: i32,
+    ) -> Result<Option<AstarteType>, Error> {
+        let pending = lock(&self.journal)
+            .pending
+            .get(&(interface.to_string(), path.to_string()))
+            .cloned();
+
+        match pending {
+            Some(Pending {
+                prop: Some((major, value)),
+                ..
+            }) if major == interface_major => Ok(Some(value)),
+            Some(_) => Ok(None),
+            None => self.inner.load_prop(interface, path, interface_major).await,
+        }
+    }
+
+    async fn delete_prop(&self, interface: &str, path: &str) -> Result<(), Error> {
+        self.write(Entry::new(interface, path, None)).await
+    }
+
+    async fn clear(&self) -> Result<(), Error> {
+        let _folding = self.folding.lock().await;
+
+        self.with_file(|file, journal| {
+            lock(journal).pending.clear();
+
+            file.rewrite(&[], 0)
+        })
+        .await?;
+
+        self.inner.clear().await
+    }
+
+    async fn load_all_props(&self) -> Result<Vec<StoredProp>, Error> {
+        let stored = self.inner.load_all_props().await?;
+
+        let journal = lock(&self.journal);
+
+        let mut props: Vec<StoredProp> = stored
+            .into_iter()
+            .filter(|prop| {
+                !journal
+                    .pending
+                    .contains_key(&(prop.interface.clone(), prop.path.clone()))
+            })
+            .collect();
+
+        for ((interface, path), pending) in &journal.pending {
+            let Some((interface_major, value)) = &pending.prop else {
+                continue;
+            };
+
+            props.push(StoredProp {
+                interface: interface.clone(),
+                path: path.clone(),
+                value: payload::serialize_individual(value, None)?,
+                interface_major: *interface_major,
+            });
+        }
+
+        Ok(props)
+    }
+
+    async fn delete_interface(&self, interface: &str) -> Result<(), Error> {
+        self.fold().await?;
+
+        self.inner.delete_interface(interface).await
+    }
+
+    async fn load_sequence(&self, interface: &str) -> Result<Option<i64>, Error> {
+        self.inner.load_sequence(interface).await
+    }
+
+    async fn store_sequence(&self, interface: &str, sequence: i64) -> Result<(), Error> {
+        self.inner.store_sequence(interface, sequence).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    use super::*;
+    use crate::append_log::tmp_path;
+    use crate::database::AstarteSqliteDatabase;
+
+    async fn sqlite(dir: &Path) -> AstarteSqliteDatabase {
+        let path = dir.join("props.sqlite");
+
+        AstarteSqliteDatabase::new(path.to_str().unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn journaled(dir: &Path) -> JournaledDatabase<AstarteSqliteDatabase> {
+        JournaledDatabase::open(sqlite(dir).await, dir.join("props.journal"))
+            .await
+            .unwrap()
+            .fold_threshold(100)
+    }
+
+    #[test]
+    fn test_entry_encoding() {
+        let entries = [
+            Entry::new(
+                "com.test",
+                "/1/enable",
+                Some((1, &AstarteType::Boolean(true))),
+            ),
+            Entry::new("com.test", "/1/enable", None),
+            Entry::Batch(vec![
+                Entry::new(
+                    "com.test",
+                    "/2/enable",
+                    Some((1, &AstarteType::Boolean(false))),
+                ),
+                Entry::new("com.test", "/3/enable", None),
+            ]),
+        ];
+
+        let mut buf = Vec::new();
+        for entry in &entries {
+            entry.encode(&mut buf).unwrap();
+        }
+
+        let (decoded, valid) = Entry::decode_all(&buf);
+        assert_eq!(decoded, entries);
+        assert_eq!(valid, buf.len());
+
+        // a torn write at the end
+        let (decoded, valid) = Entry::decode_all(&buf[..buf.len() - 1]);
+        assert_eq!(decoded, entries[..2]);
+        assert!(valid < buf.len());
+
+        // every truncation is decoded without panicking
+        for len in 0..buf.len() {
+            let (decoded, valid) = Entry::decode_all(&buf[..len]);
+            assert!(decoded.len() < entries.len());
+            assert!(valid <= len);
+        }
+
+        // corrupted entry
+        buf[HEADER_SIZE + 2] ^= 0xff;
+        let (decoded, valid) = Entry::decode_all(&buf);
+        assert!(decoded.is_empty());
+        assert_eq!(valid, 0);
+    }
+
+    #[tokio::test]
+    async fn test_deferred_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = journaled(dir.path()).await;
+
+        for i in 0..10 {
+            db.store_prop("com.test", "/1/period", &AstarteType::Integer(i), 1)
+                .await
+                .unwrap();
+        }
+        db.store_prop("com.test", "/2/period", &AstarteType::Integer(5), 1)
+            .await
+            .unwrap();
+        db.delete_prop("com.test", "/2/period").await.unwrap();
+
+        // the reads see the journal
+        let value = db.load_prop("com.test", "/1/period", 1).await.unwrap();
+        assert_eq!(value, Some(AstarteType::Integer(9)));
+        assert_eq!(
+            db.load_prop("com.test", "/1/period", 2).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            db.load_prop("com.test", "/2/period", 1).await.unwrap(),
+            None
+        );
+        assert_eq!(db.load_all_props().await.unwrap().len(), 1);
+
+        let stored = sqlite(dir.path()).await;
+        assert!(stored.load_all_props().await.unwrap().is_empty());
+
+        assert_eq!(db.pending(), 2);
+        db.fold().await.unwrap();
+        assert_eq!(db.pending(), 0);
+
+        let value = stored.load_prop("com.test", "/1/period", 1).await.unwrap();
+        assert_eq!(value, Some(AstarteType::Integer(9)));
+        assert_eq!(stored.load_all_props().await.unwrap().len(), 1);
+
+        let journal = std::fs::metadata(dir.path().join("props.journal")).unwrap();
+        assert_eq!(journal.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fold_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = journaled(dir.path()).await.fold_threshold(3);
+
+        for i in 0..3 {
+            db.store_prop("com.test", "/1/period", &AstarteType::Integer(i), 1)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(db.pending(), 0);
+        let stored = sqlite(dir.path()).await;
+        let value = stored.load_prop("com.test", "/1/period", 1).await.unwrap();
+        assert_eq!(value, Some(AstarteType::Integer(2)));
+    }
+
+    #[tokio::test]
+    async fn test_replay_after_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_path = dir.path().join("props.journal");
+
+        let db = journaled(dir.path()).await;
+        db.store_prop("com.test", "/1/period", &AstarteType::Integer(10), 1)
+            .await
+            .unwrap();
+        db.store_prop("com.test", "/2/period", &AstarteType::Integer(20), 1)
+            .await
+            .unwrap();
+        db.delete_prop("com.test", "/2/period").await.unwrap();
+        // crash before folding
+        drop(db);
+
+        // crash while appending the next entry
+        let mut file = OpenOptions::new().append(true).open(&journal_path).unwrap();
+        file.write_all(&[42, 0, 0, 0, 1, 2]).unwrap();
+        drop(file);
+
+        let db = journaled(dir.path()).await;
+        assert_eq!(db.pending(), 0);
+
+        let stored = sqlite(dir.path()).await;
+        let value = stored.load_prop("com.test", "/1/period", 1).await.unwrap();
+        assert_eq!(value, Some(AstarteType::Integer(10)));
+        assert_eq!(stored.load_all_props().await.unwrap().len(), 1);
+
+        // the torn entry was discarded, the next writes are replayed
+        db.store_prop("com.test", "/3/period", &AstarteType::Integer(30), 1)
+            .await
+            .unwrap();
+        drop(db);
+
+        journaled(dir.path()).await;
+        let value = stored.load_prop("com.test", "/3/period", 1).await.unwrap();
+        assert_eq!(value, Some(AstarteType::Integer(30)));
+    }
+
+    #[tokio::test]
+    async fn test_crash_while_folding() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_path = dir.path().join("props.journal");
+
+        let db = journaled(dir.path()).await;
+        db.store_prop("com.test", "/1/period", &AstarteType::Integer(10), 1)
+            .await
+            .unwrap();
+        db.delete_prop("com.test", "/2/period").await.unwrap();
+
+        let before_fold = std::fs::read(&journal_path).unwrap();
+        db.fold().await.unwrap();
+        drop(db);
+
+        // crash after writing the database, before compacting the journal
+        std::fs::write(&journal_path, &before_fold).unwrap();
+        // and with a partially written compacted journal
+        std::fs::write(tmp_path(&journal_path), [1, 2, 3]).unwrap();
+
+        let db = journaled(dir.path()).await;
+        assert!(!tmp_path(&journal_path).exists());
+
+        let value = db.load_prop("com.test", "/1/period", 1).await.unwrap();
+        assert_eq!(value, Some(AstarteType::Integer(10)));
+        assert_eq!(db.load_all_props().await.unwrap().len(), 1);
+        assert_eq!(std::fs::metadata(&journal_path).unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_store_props() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_path = dir.path().join("props.journal");
+
+        let props: Vec<_> = (1..=3)
+            .map(|i| PropWrite {
+                interface: "com.test".to_string(),
+                path: format!("/{i}/period"),
+                value: AstarteType::Integer(i),
+                interface_major: 1,
+            })
+            .collect();
+
+        let db = journaled(dir.path()).await;
+        db.store_props(&props[..2]).await.unwrap();
+        assert_eq!(db.pending(), 2);
+
+        let before_batch = std::fs::metadata(&journal_path).unwrap().len();
+        db.store_props(&props[2..]).await.unwrap();
+        drop(db);
+
+        // crash while appending the second batch
+        let file = OpenOptions::new().write(true).open(&journal_path).unwrap();
+        file.set_len(before_batch + 4).unwrap();
+        drop(file);
+
+        let db = journaled(dir.path()).await;
+        let stored = db.load_all_props().await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(
+            db.load_prop("com.test", "/3/period", 1).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = journaled(dir.path()).await;
+
+        db.store_prop("com.test", "/1/period", &AstarteType::Integer(10), 1)
+            .await
+            .unwrap();
+        db.fold().await.unwrap();
+        db.store_prop("com.test", "/2/period", &AstarteType::Integer(20), 1)
+            .await
+            .unwrap();
+
+        db.clear().await.unwrap();
+        assert_eq!(db.pending(), 0);
+        assert!(db.load_all_props().await.unwrap().is_empty());
+
+        drop(db);
+        let db = journaled(dir.path()).await;
+        assert!(db.load_all_props().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,529 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Database wrapper enforcing the [quotas](crate::quota) of the stored properties.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use async_trait::async_trait;
+use log::warn;
+
+use super::{AstarteDatabase, PropWrite, StoredProp};
+use crate::quota::{QuotaUsage, StoreQuota};
+use crate::{payload, types::AstarteType, Error};
+
+/// Properties stored for an interface with a quota.
+#[derive(Debug, Clone, Default)]
+struct Ledger {
+    /// Path and size of the properties, from the least recently written.
+    props: VecDeque<(String, usize)>,
+    bytes: usize,
+    rejected: u64,
+    evicted: u64,
+}
+
+impl Ledger {
+    fn usage(&self) -> QuotaUsage {
+        QuotaUsage {
+            rows: self.props.len(),
+            bytes: self.bytes,
+            rejected: self.rejected,
+            evicted: self.evicted,
+        }
+    }
+
+    fn remove(&mut self, path: &str) {
+        if let Some(idx) = self.props.iter().position(|(p, _)| p == path) {
+            if let Some((_, bytes)) = self.props.remove(idx) {
+                self.bytes -= bytes;
+            }
+        }
+    }
+
+    fn insert(&mut self, path: &str, bytes: usize) {
+        self.remove(path);
+
+        self.props.push_back((path.to_string(), bytes));
+        self.bytes += bytes;
+    }
+
+    fn clear(&mut self) {
+        self.props.clear();
+        self.bytes = 0;
+    }
+
+    /// Returns the paths to evict to write the property, or `None` if it's rejected.
+    fn plan(&self, quota: &StoreQuota, path: &str, bytes: usize) -> Option<Vec<String>> {
+        let current = self
+            .props
+            .iter()
+            .find(|(p, _)| p == path)
+            .map(|(_, bytes)| *bytes);
+
+        let mut rows = self.props.len() + usize::from(current.is_none());
+        let mut total = self.bytes - current.unwrap_or(0) + bytes;
+
+        if quota.fits(rows, total) {
+            return Some(Vec::new());
+        }
+
+        if !quota.evicts() {
+            return None;
+        }
+
+        let mut evicted = Vec::new();
+        for (p, size) in self.props.iter().filter(|(p, _)| p != path) {
+            evicted.push(p.clone());
+            rows -= 1;
+            total -= size;
+
+            if quota.fits(rows, total) {
+                return Some(evicted);
+            }
+        }
+
+        None
+    }
+}
+
+/// Database wrapper limiting the properties stored for the interfaces with a [`StoreQuota`].
+///
+/// The size of a property is the one of its serialized value. The usage of the interfaces is
+/// loaded from the wrapped database on the first write, the properties already stored are
+/// considered the oldest ones. A rejected property returns an [`Error::QuotaExceeded`].
+///
+/// See the [`quota`](crate::quota) module for an example.
+#[derive(Debug)]
+pub struct QuotaDatabase<D> {
+    inner: D,
+    quotas: HashMap<String, StoreQuota>,
+    /// Usage of the interfaces with a quota, `None` until it's loaded.
+    ledgers: Mutex<Option<HashMap<String, Ledger>>>,
+    /// Held while writing the interfaces with a quota, so their usage is consistent.
+    writes: tokio::sync::Mutex<()>,
+}
+
+impl<D> QuotaDatabase<D> {
+    /// Wrap the database, without any quota.
+    pub fn new(inner: D) -> Self {
+        Self {
+            inner,
+            quotas: HashMap::new(),
+            ledgers: Mutex::new(None),
+            writes: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Sets the quota of the properties of an interface.
+    pub fn quota(mut self, interface: &str, quota: StoreQuota) -> Self {
+        self.quotas.insert(interface.to_string(), quota);
+
+        self
+    }
+
+    /// Returns the wrapped database.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<HashMap<String, Ledger>>> {
+        // the ledgers are always valid, since they are only assigned while locked
+        self.ledgers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Updates the ledger of an interface, if the usage was loaded.
+    fn update<F>(&self, interface: &str, f: F)
+    where
+        F: FnOnce(&mut Ledger),
+    {
+        if let Some(ledger) = self
+            .lock()
+            .as_mut()
+            .and_then(|ledgers| ledgers.get_mut(interface))
+        {
+            f(ledger);
+        }
+    }
+}
+
+impl<D> QuotaDatabase<D>
+where
+    D: AstarteDatabase + Send + Sync,
+{
+    /// Loads the usage of the interfaces with a quota, if it wasn't already.
+    async fn load(&self) -> Result<(), Error> {
+        if self.lock().is_some() {
+            return Ok(());
+        }
+
+        let mut ledgers: HashMap<String, Ledger> = self
+            .quotas
+            .keys()
+            .map(|interface| (interface.clone(), Ledger::default()))
+            .collect();
+
+        for prop in self.inner.load_all_props().await? {
+            if let Some(ledger) = ledgers.get_mut(&prop.interface) {
+                ledger.insert(&prop.path, prop.value.len());
+            }
+        }
+
+        *self.lock() = Some(ledgers);
+
+        Ok(())
+    }
+
+    /// Returns the usage of an interface with a quota.
+    pub async fn usage(&self, interface: &str) -> Result<Option<QuotaUsage>, Error> {
+        if !self.quotas.contains_key(interface) {
+            return Ok(None);
+        }
+
+        let _writes = self.writes.lock().await;
+        self.load().await?;
+
+        Ok(self
+            .lock()
+            .as_ref()
+            .and_then(|ledgers| ledgers.get(interface))
+            .map(Ledger::usage))
+    }
+
+    /// Plans the writes of the properties on the ledgers, returns the properties to evict.
+    fn reserve(&self, props: &[PropWrite]) -> Result<Vec<(String, String)>, Error> {
+        let mut guard = self.lock();
+        let Some(ledgers) = guard.as_mut() else {
+            return Err(Error::Reported("BUG: quota usage not loaded".into()));
+        };
+
+        let mut planned = ledgers.clone();
+        let mut evictions = Vec::new();
+
+        for prop in props {
+            let (Some(quota), Some(ledger)) = (
+                self.quotas.get(&prop.interface),
+                planned.get_mut(&prop.interface),
+            ) else {
+                continue;
+            };
+
+            let bytes = payload::serialize_individual(&prop.value, None)?.len();
+
+            let Some(evicted) = ledger.plan(quota, &prop.path, bytes) else {
+                if let Some(ledger) = ledgers.get_mut(&prop.interface) {
+                    ledger.rejected += 1;
+                }
+
+                warn!(
+                    "storage quota of {} exceeded, rejecting the property {}",
+                    prop.interface, prop.path
+                );
+
+                return Err(Error::QuotaExceeded {
+                    interface: prop.interface.clone(),
+                    path: prop.path.clone(),
+                });
+            };
+
+            for path in evicted {
+                ledger.remove(&path);
+                evictions.push((prop.interface.clone(), path));
+            }
+
+            ledger.insert(&prop.path, bytes);
+        }
+
+        Ok(evictions)
+    }
+
+    /// Writes the properties, evicting the oldest ones of the interfaces with a quota.
+    async fn write(&self, props: &[PropWrite]) -> Result<(), Error> {
+        if !props
+            .iter()
+            .any(|prop| self.quotas.contains_key(&prop.interface))
+        {
+            return self.inner.store_props(props).await;
+        }
+
+        let _writes = self.writes.lock().await;
+        self.load().await?;
+
+        let evictions = self.reserve(props)?;
+
+        for (interface, path) in evictions {
+            warn!("storage quota of {interface} exceeded, evicting the property {path}");
+
+            self.inner.delete_prop(&interface, &path).await?;
+
+            self.update(&interface, |ledger| {
+                ledger.remove(&path);
+                ledger.evicted += 1;
+            });
+        }
+
+        match props {
+            [prop] => {
+                self.inner
+                    .store_prop(
+                        &prop.interface,
+                        &prop.path,
+                        &prop.value,
+                        prop.interface_major,
+                    )
+                    .await?
+            }
+            props => self.inner.store_props(props).await?,
+        }
+
+        for prop in props {
+            if !self.quotas.contains_key(&prop.interface) {
+                continue;
+            }
+
+            let bytes = payload::serialize_individual(&prop.value, None)?.len();
+            self.update(&prop.interface, |ledger| ledger.insert(&prop.path, bytes));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<D> AstarteDatabase for QuotaDatabase<D>
+where
+    D: AstarteDatabase + Send + Sync,
+{
+    async fn store_prop(
+        &self,
+        interface: &str,
+        path: &str,
+        value: &AstarteType,
+        interface_major: i32,
+    ) -> Result<(), Error> {
+        if !self.quotas.contains_key(interface) {
+            return self
+                .inner
+                .store_prop(interface, path, value, interface_major)
+                .await;
+        }
+
+        self.write(&[PropWrite {
+            interface: interface.to_string(),
+            path: path.to_string(),
+            value: value.clone(),
+            interface_major,
+        }])
+        .await
+    }
+
+    async fn store_props(&self, props: &[PropWrite]) -> Result<(), Error> {
+        self.write(props).await
+    }
+
+    async fn load_prop(
+        &self,
+        interface: &str,
+        path: &str,
+        interface_major: i32,
+    ) -> Result<Option<AstarteType>, Error> {
+        self.inner.load_prop(interface, path, interface_major).await
+    }
+
+    async fn delete_prop(&self, interface: &str, path: &str) -> Result<(), Error> {
+        if !self.quotas.contains_key(interface) {
+            return self.inner.delete_prop(interface, path).await;
+        }
+
+        let _writes = self.writes.lock().await;
+
+        self.inner.delete_prop(interface, path).await?;
+
+        self.update(interface, |ledger| ledger.remove(path));
+
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<(), Error> {
+        let _writes = self.writes.lock().await;
+
+        self.inner.clear().await?;
+
+        if let Some(ledgers) = self.lock().as_mut() {
+            ledgers.values_mut().for_each(Ledger::clear);
+        }
+
+        Ok(())
+    }
+
+    async fn load_all_props(&self) -> Result<Vec<StoredProp>, Error> {
+        self.inner.load_all_props().await
+    }
+
+    async fn delete_interface(&self, interface: &str) -> Result<(), Error> {
+        let _writes = self.writes.lock().await;
+
+        self.inner.delete_interface(interface).await?;
+
+        self.update(interface, Ledger::clear);
+
+        Ok(())
+    }
+
+    async fn load_sequence(&self, interface: &str) -> Result<Option<i64>, Error> {
+        self.inner.load_sequence(interface).await
+    }
+
+    async fn store_sequence(&self, interface: &str, sequence: i64) -> Result<(), Error> {
+        self.inner.store_sequence(interface, sequence).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::AstarteSqliteDatabase;
+    use crate::quota::QuotaPolicy;
+
+    async fn quota_database(quota: StoreQuota) -> QuotaDatabase<AstarteSqliteDatabase> {
+        let inner = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+
+        QuotaDatabase::new(inner).quota("com.test.Labels", quota)
+    }
+
+    async fn paths(db: &QuotaDatabase<AstarteSqliteDatabase>) -> Vec<String> {
+        let mut paths: Vec<String> = db
+            .load_all_props()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|prop| prop.interface == "com.test.Labels")
+            .map(|prop| prop.path)
+            .collect();
+        paths.sort();
+
+        paths
+    }
+
+    fn label(value: &str) -> AstarteType {
+        AstarteType::String(value.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_quota_reject() {
+        let db = quota_database(StoreQuota::new().max_rows(2)).await;
+
+        db.store_prop("com.test.Labels", "/1", &label("a"), 0)
+            .await
+            .unwrap();
+        db.store_prop("com.test.Labels", "/2", &label("b"), 0)
+            .await
+            .unwrap();
+
+        let err = db
+            .store_prop("com.test.Labels", "/3", &label("c"), 0)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::QuotaExceeded { ref path, .. } if path == "/3"));
+
+        // replacing a property doesn't add a row, the other interfaces are not limited
+        db.store_prop("com.test.Labels", "/2", &label("bb"), 0)
+            .await
+            .unwrap();
+        for path in ["/1", "/2", "/3"] {
+            db.store_prop("com.test.Config", path, &label("config"), 0)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(paths(&db).await, ["/1", "/2"]);
+
+        let usage = db.usage("com.test.Labels").await.unwrap().unwrap();
+        assert_eq!(usage.rows, 2);
+        assert_eq!(usage.rejected, 1);
+        assert_eq!(usage.evicted, 0);
+        assert_eq!(db.usage("com.test.Config").await.unwrap(), None);
+
+        // a deleted property frees its row
+        db.delete_prop("com.test.Labels", "/1").await.unwrap();
+        db.store_prop("com.test.Labels", "/3", &label("c"), 0)
+            .await
+            .unwrap();
+        assert_eq!(paths(&db).await, ["/2", "/3"]);
+    }
+
+    #[tokio::test]
+    async fn test_quota_evict_oldest() {
+        let value = label("value");
+        let size = payload::serialize_individual(&value, None).unwrap().len();
+
+        let db = quota_database(
+            StoreQuota::new()
+                .max_bytes(2 * size)
+                .policy(QuotaPolicy::EvictOldest),
+        )
+        .await;
+
+        // already stored before the first write
+        db.inner
+            .store_prop("com.test.Labels", "/1", &value, 0)
+            .await
+            .unwrap();
+
+        db.store_prop("com.test.Labels", "/2", &value, 0)
+            .await
+            .unwrap();
+        db.store_prop("com.test.Labels", "/3", &value, 0)
+            .await
+            .unwrap();
+        assert_eq!(paths(&db).await, ["/2", "/3"]);
+
+        // rewriting a property makes it the most recent
+        db.store_prop("com.test.Labels", "/2", &value, 0)
+            .await
+            .unwrap();
+        db.store_props(&[PropWrite {
+            interface: "com.test.Labels".to_string(),
+            path: "/4".to_string(),
+            value: value.clone(),
+            interface_major: 0,
+        }])
+        .await
+        .unwrap();
+        assert_eq!(paths(&db).await, ["/2", "/4"]);
+
+        // a property bigger than the quota is rejected
+        let err = db
+            .store_prop("com.test.Labels", "/5", &label(&"a".repeat(3 * size)), 0)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::QuotaExceeded { .. }));
+        assert_eq!(paths(&db).await, ["/2", "/4"]);
+
+        let usage = db.usage("com.test.Labels").await.unwrap().unwrap();
+        assert_eq!(
+            usage,
+            QuotaUsage {
+                rows: 2,
+                bytes: 2 * size,
+                rejected: 1,
+                evicted: 2,
+            }
+        );
+    }
+}
@@ -0,0 +1,462 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persistent log of the outbox entries published on the mappings with `unique` reliability.
+//!
+//! An entry of the [outbox](crate::outbox) is removed after the broker acknowledged it, if the
+//! device crashes in between the entry is published again on the next start. With a
+//! [`PublishedLog`] the id of each entry published with `unique` reliability is appended to a file
+//! and synced to disk once the `PUBCOMP` of its publish is received, before the entry is removed,
+//! so on the next start [`publish_outbox()`](crate::AstarteDeviceSdk::publish_outbox) removes the
+//! entries in the log without publishing them again.
+//!
+//! The MQTT session isn't persisted, so a crash after the broker received the publish and before
+//! the id is appended, including while waiting for the `PUBCOMP`, can still cause a duplicate.
+//!
+//! The log keeps the most recent ids, the older ones are discarded when the file is compacted.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{dedup::PublishedLog, options::AstarteOptions};
+//!
+//! let log = PublishedLog::open("path/to/published.log").unwrap();
+//! let sdk_options = AstarteOptions::new("_","_","_","_").published_log(log);
+//! ```
+
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use log::warn;
+
+use crate::append_log::AppendLog;
+use crate::database::{checksum, AstarteDatabase};
+use crate::interface::mapping::path::MappingPath;
+use crate::{AstarteDeviceSdk, Error};
+
+/// Default number of published ids kept in the log.
+pub const DEFAULT_PUBLISHED_CAPACITY: usize = 1024;
+
+/// Size of a record, the id followed by its checksum.
+const RECORD_SIZE: usize = 12;
+
+fn encode(id: i64, buf: &mut Vec<u8>) {
+    let id = id.to_le_bytes();
+
+    buf.extend_from_slice(&id);
+    // the checksum is the crc32, widened to an i64
+    buf.extend_from_slice(&(checksum(&id) as u32).to_le_bytes());
+}
+
+/// Decodes the records, returning the ids with the length of the valid part of the log.
+///
+/// The decoding stops at the first record that is truncated or has a wrong checksum.
+fn decode_all(log: &[u8]) -> (Vec<i64>, usize) {
+    let ids: Vec<i64> = log
+        .chunks_exact(RECORD_SIZE)
+        .map_while(|record| {
+            let (id, sum) = record.split_at(8);
+            let id: [u8; 8] = id.try_into().ok()?;
+            let sum = u32::from_le_bytes(sum.try_into().ok()?);
+
+            (checksum(&id) as u32 == sum).then(|| i64::from_le_bytes(id))
+        })
+        .collect();
+
+    let valid = ids.len() * RECORD_SIZE;
+
+    (ids, valid)
+}
+
+#[derive(Debug)]
+struct State {
+    log: AppendLog,
+    /// Records in the file, including the ones already discarded from the ids.
+    records: usize,
+    ids: VecDeque<i64>,
+    index: HashSet<i64>,
+}
+
+impl State {
+    fn insert(&mut self, id: i64, capacity: usize) {
+        if !self.index.insert(id) {
+            return;
+        }
+
+        self.ids.push_back(id);
+
+        while self.ids.len() > capacity {
+            if let Some(old) = self.ids.pop_front() {
+                self.index.remove(&old);
+            }
+        }
+    }
+}
+
+/// Persistent log of the ids of the published outbox entries.
+///
+/// See the [module documentation](crate::dedup) for an example.
+#[derive(Debug)]
+pub struct PublishedLog {
+    capacity: usize,
+    state: Mutex<State>,
+}
+
+impl PublishedLog {
+    /// Opens the log at the given path, it's created if missing.
+    ///
+    /// A record torn by a crash while appending is discarded.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let (mut log, content) =
+            AppendLog::open(path.as_ref().to_path_buf()).map_err(Error::PublishedLog)?;
+
+        let (ids, valid) = decode_all(&content);
+        log.truncate(valid).map_err(Error::PublishedLog)?;
+
+        let mut state = State {
+            log,
+            records: ids.len(),
+            ids: VecDeque::new(),
+            index: HashSet::new(),
+        };
+
+        for id in ids {
+            state.insert(id, DEFAULT_PUBLISHED_CAPACITY);
+        }
+
+        Ok(Self {
+            capacity: DEFAULT_PUBLISHED_CAPACITY,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Number of published ids kept in the log, [`DEFAULT_PUBLISHED_CAPACITY`] by default.
+    ///
+    /// It should be more than the entries published by each
+    /// [`publish_outbox()`](crate::AstarteDeviceSdk::publish_outbox), or the id of an entry could
+    /// be discarded before the entry is removed.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+
+        while state.ids.len() > capacity {
+            if let Some(old) = state.ids.pop_front() {
+                state.index.remove(&old);
+            }
+        }
+
+        self.capacity = capacity;
+
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // the state is consistent after each operation, even if a thread panicked
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns true if the entry was already published.
+    pub(crate) fn contains(&self, id: i64) -> bool {
+        self.lock().index.contains(&id)
+    }
+
+    /// Appends the id of a published entry to the file and syncs it.
+    pub(crate) fn record(&self, id: i64) -> Result<(), Error> {
+        let mut state = self.lock();
+
+        if state.index.contains(&id) {
+            return Ok(());
+        }
+
+        let mut buf = Vec::with_capacity(RECORD_SIZE);
+        encode(id, &mut buf);
+
+        state.log.append(&buf).map_err(Error::PublishedLog)?;
+        state.records += 1;
+        state.insert(id, self.capacity);
+
+        if state.records >= self.capacity.saturating_mul(2) {
+            if let Err(err) = self.compact(&mut state) {
+                warn!("couldn't compact the published log: {err}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rewrites the log with only the ids kept.
+    fn compact(&self, state: &mut State) -> Result<(), Error> {
+        let mut buf = Vec::with_capacity(state.ids.len() * RECORD_SIZE);
+        for id in &state.ids {
+            encode(*id, &mut buf);
+        }
+
+        state.log.rewrite(&buf).map_err(Error::PublishedLog)?;
+        state.records = state.ids.len();
+
+        Ok(())
+    }
+}
+
+impl<S> AstarteDeviceSdk<S>
+where
+    S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+{
+    /// Returns the published log if the mapping of the path has `unique` reliability.
+    pub(crate) async fn unique_log(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+    ) -> Option<&PublishedLog> {
+        let log = self.published_log.as_deref()?;
+        let path = MappingPath::try_from(interface_path).ok()?;
+
+        let reliability = self
+            .interfaces
+            .read()
+            .await
+            .get_mqtt_reliability(interface_name, &path);
+
+        (reliability == rumqttc::QoS::ExactlyOnce).then_some(log)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use mockall::predicate;
+    use rumqttc::Event;
+
+    use super::*;
+    use crate::database::AstarteSqliteDatabase;
+    use crate::delivery::Deliveries;
+    use crate::mock::{acknowledged, MockAsyncClient, MockDevice, MockEventLoop};
+    use crate::outbox::{AstarteOutbox, OutboxIntent};
+    use crate::types::AstarteType;
+    use crate::{payload, Interface};
+
+    #[test]
+    fn test_published_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("published.log");
+
+        let log = PublishedLog::open(&path).unwrap();
+        assert!(!log.contains(1));
+
+        log.record(1).unwrap();
+        log.record(2).unwrap();
+        log.record(2).unwrap();
+        assert!(log.contains(1));
+        drop(log);
+
+        // a torn record at the end is discarded
+        let mut content = std::fs::read(&path).unwrap();
+        assert_eq!(content.len(), 2 * RECORD_SIZE);
+        content.extend_from_slice(&[3, 0, 0]);
+        std::fs::write(&path, content).unwrap();
+
+        let log = PublishedLog::open(&path).unwrap();
+        assert!(log.contains(1));
+        assert!(log.contains(2));
+        assert!(!log.contains(3));
+
+        log.record(3).unwrap();
+        drop(log);
+
+        let log = PublishedLog::open(&path).unwrap();
+        assert!(log.contains(3));
+    }
+
+    #[test]
+    fn test_published_log_compact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("published.log");
+
+        let log = PublishedLog::open(&path).unwrap().capacity(2);
+        for id in 1..=4 {
+            log.record(id).unwrap();
+        }
+
+        // the log is compacted with only the most recent ids
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            2 * RECORD_SIZE as u64
+        );
+        assert!(!log.contains(2));
+        assert!(log.contains(3));
+        assert!(log.contains(4));
+
+        log.record(5).unwrap();
+        drop(log);
+
+        let log = PublishedLog::open(&path).unwrap().capacity(2);
+        assert!(!log.contains(3));
+        assert!(log.contains(4));
+        assert!(log.contains(5));
+    }
+
+    const UNIQUE_DATASTREAM: &str = r#"
+    {
+        "interface_name": "org.astarte-platform.test.UniqueDatastream",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "ownership": "device",
+        "mappings": [
+            {
+                "endpoint": "/value",
+                "type": "integer",
+                "reliability": "unique"
+            }
+        ]
+    }
+    "#;
+
+    #[tokio::test]
+    async fn test_publish_outbox_dedup() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = AstarteSqliteDatabase::new(dir.path().join("outbox.sqlite").to_str().unwrap())
+            .await
+            .unwrap();
+        let log_path = dir.path().join("published.log");
+
+        let mut tx = db.begin().await.unwrap();
+        for value in [1, 2] {
+            let intent = OutboxIntent::individual(
+                "org.astarte-platform.test.UniqueDatastream",
+                "/value",
+                AstarteType::Integer(value),
+                None,
+            )
+            .unwrap();
+
+            AstarteSqliteDatabase::enqueue(&mut tx, &intent)
+                .await
+                .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        let entries = db.pending().await.unwrap();
+        assert_eq!(entries.len(), 2);
+
+        // the first entry was published before a restart, but not removed
+        PublishedLog::open(&log_path)
+            .unwrap()
+            .record(entries[0].id)
+            .unwrap();
+
+        let deliveries = Arc::new(Deliveries::default());
+        let mut client = MockAsyncClient::default();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .with(
+                predicate::eq(
+                    "realm/device_id/org.astarte-platform.test.UniqueDatastream/value".to_string(),
+                ),
+                predicate::eq(rumqttc::QoS::ExactlyOnce),
+                predicate::always(),
+                predicate::eq(
+                    payload::serialize_individual(&AstarteType::Integer(2), None).unwrap(),
+                ),
+            )
+            .returning(acknowledged(&deliveries));
+
+        let astarte = MockDevice::new(client, MockEventLoop::default())
+            .interfaces([Interface::from_str(UNIQUE_DATASTREAM).unwrap()])
+            .options(|opts| opts.published_log(PublishedLog::open(&log_path).unwrap()))
+            .deliveries(&deliveries)
+            .build();
+
+        assert_eq!(astarte.publish_outbox(&db).await.unwrap(), 1);
+        assert!(db.pending().await.unwrap().is_empty());
+
+        let log = PublishedLog::open(&log_path).unwrap();
+        assert!(log.contains(entries[0].id));
+        assert!(log.contains(entries[1].id));
+    }
+
+    #[tokio::test]
+    async fn test_publish_outbox_dedup_completed() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = AstarteSqliteDatabase::new(dir.path().join("outbox.sqlite").to_str().unwrap())
+            .await
+            .unwrap();
+
+        let intent = OutboxIntent::individual(
+            "org.astarte-platform.test.UniqueDatastream",
+            "/value",
+            AstarteType::Integer(1),
+            None,
+        )
+        .unwrap();
+        let mut tx = db.begin().await.unwrap();
+        AstarteSqliteDatabase::enqueue(&mut tx, &intent)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let id = db.pending().await.unwrap()[0].id;
+
+        let deliveries = Arc::new(Deliveries::default());
+        let received = Arc::new(tokio::sync::Notify::new());
+
+        // the broker received the message, but the exchange isn't complete
+        let sent = Arc::clone(&deliveries);
+        let notify = Arc::clone(&received);
+        let mut client = MockAsyncClient::default();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .returning(move |_, _, _, _| {
+                sent.handle(&Event::Outgoing(rumqttc::Outgoing::Publish(1)));
+                sent.handle(&Event::Incoming(rumqttc::Packet::PubRec(
+                    rumqttc::PubRec::new(1),
+                )));
+                notify.notify_one();
+
+                Ok(())
+            });
+
+        let astarte = MockDevice::new(client, MockEventLoop::default())
+            .interfaces([Interface::from_str(UNIQUE_DATASTREAM).unwrap()])
+            .options(|opts| {
+                opts.published_log(PublishedLog::open(dir.path().join("published.log")).unwrap())
+            })
+            .deliveries(&deliveries)
+            .build();
+
+        let log = astarte.published_log.clone().unwrap();
+        let complete = async {
+            received.notified().await;
+            tokio::task::yield_now().await;
+
+            assert!(!log.contains(id));
+
+            deliveries.handle(&Event::Incoming(rumqttc::Packet::PubComp(
+                rumqttc::PubComp::new(1),
+            )));
+        };
+
+        let (published, ()) = tokio::join!(astarte.publish_outbox(&db), complete);
+        assert_eq!(published.unwrap(), 1);
+        assert!(log.contains(id));
+        assert!(db.pending().await.unwrap().is_empty());
+    }
+}
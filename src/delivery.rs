@@ -0,0 +1,491 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tracking of the messages published until the broker acknowledges them.
+//!
+//! The packet id of a publish is assigned by the event loop when it's sent, so the publishes are
+//! queued in the order they are handed to the client and matched with the publishes sent by the
+//! event loop. A publish with QoS 0 is delivered once it's sent, one with QoS 1 on the `PUBACK`
+//! and one with QoS 2 on the `PUBCOMP`, after the `PUBREC` the packet id can be reused by the next
+//! publishes.
+//!
+//! When the connection is lost the event loop sends again the publishes not acknowledged, with
+//! the same packet id, so they are still tracked. The messages on the mappings with volatile
+//! retention are kept until they are delivered, if the transport they were handed to is
+//! replaced they are moved back to the [volatile retention](crate::retention) and sent again
+//! after the `CONNACK`.
+
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+
+use log::trace;
+use rumqttc::{Event, Outgoing, Packet};
+use tokio::sync::oneshot;
+
+use crate::retention::VolatileItem;
+
+/// Publish handed to the client and not delivered yet.
+struct Tracked {
+    seq: u64,
+    /// Event loop of the client the publish was handed to.
+    transport: Weak<dyn Any + Send + Sync>,
+    /// Copy of the messages with volatile retention.
+    retained: Option<VolatileItem>,
+    delivered: oneshot::Sender<()>,
+}
+
+impl Tracked {
+    fn is_on<T>(&self, transport: &Arc<T>) -> bool {
+        std::ptr::eq(
+            self.transport.as_ptr() as *const (),
+            Arc::as_ptr(transport) as *const (),
+        )
+    }
+
+    fn deliver(self) {
+        // nobody is waiting for most of the publishes
+        let _ = self.delivered.send(());
+    }
+}
+
+#[derive(Default)]
+struct State {
+    next_seq: u64,
+    /// Publishes handed to the client, waiting to be sent by the event loop.
+    queued: VecDeque<Tracked>,
+    /// Publishes sent, waiting for the acknowledgment, by packet id.
+    in_flight: HashMap<u16, Tracked>,
+    /// Publishes with QoS 2 received by the broker, waiting for the `PUBCOMP`.
+    released: HashMap<u16, Tracked>,
+    /// Publishes waiting for the acknowledgment of the one in flight with the same packet id.
+    collisions: HashMap<u16, Tracked>,
+}
+
+/// Publishes handed to the client and not delivered yet.
+#[derive(Default)]
+pub(crate) struct Deliveries {
+    /// Held while a publish is handed to the client, so they are queued in the same order.
+    sending: tokio::sync::Mutex<()>,
+    state: Mutex<State>,
+}
+
+impl Deliveries {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits for the publishes being handed to the client.
+    pub(crate) async fn sending(&self) -> Sending<'_> {
+        Sending {
+            deliveries: self,
+            pending: None,
+            _guard: self.sending.lock().await,
+        }
+    }
+
+    /// Returns `None` if another publish is being handed to the client.
+    pub(crate) fn try_sending(&self) -> Option<Sending<'_>> {
+        let guard = self.sending.try_lock().ok()?;
+
+        Some(Sending {
+            deliveries: self,
+            pending: None,
+            _guard: guard,
+        })
+    }
+
+    /// Tracks the publishes sent and acknowledged in an event of the event loop.
+    pub(crate) fn handle(&self, event: &Event) {
+        match event {
+            Event::Outgoing(Outgoing::Publish(pkid)) => self.sent(*pkid),
+            Event::Outgoing(Outgoing::AwaitAck(pkid)) => self.collided(*pkid),
+            Event::Incoming(Packet::PubAck(ack)) => self.acked(ack.pkid),
+            Event::Incoming(Packet::PubRec(rec)) => self.received(rec.pkid),
+            Event::Incoming(Packet::PubComp(comp)) => self.completed(comp.pkid),
+            _ => {}
+        }
+    }
+
+    fn sent(&self, pkid: u16) {
+        let mut state = self.lock();
+
+        // sent again after a reconnection, or the publish of a collision that is tracked on the
+        // acknowledgment of the previous one
+        if pkid != 0 && state.in_flight.contains_key(&pkid) {
+            trace!("publish {pkid} sent again");
+
+            return;
+        }
+
+        let Some(tracked) = state.queued.pop_front() else {
+            return;
+        };
+
+        if pkid == 0 {
+            tracked.deliver();
+        } else {
+            state.in_flight.insert(pkid, tracked);
+        }
+    }
+
+    /// The packet id of the next publish is still in flight, it's sent after the acknowledgment.
+    fn collided(&self, pkid: u16) {
+        let mut state = self.lock();
+
+        if let Some(tracked) = state.queued.pop_front() {
+            state.collisions.insert(pkid, tracked);
+        }
+    }
+
+    fn acked(&self, pkid: u16) {
+        let mut state = self.lock();
+
+        let Some(tracked) = state.in_flight.remove(&pkid) else {
+            return;
+        };
+
+        if let Some(next) = state.collisions.remove(&pkid) {
+            state.in_flight.insert(pkid, next);
+        }
+
+        tracked.deliver();
+    }
+
+    fn received(&self, pkid: u16) {
+        let mut state = self.lock();
+
+        if let Some(tracked) = state.in_flight.remove(&pkid) {
+            state.released.insert(pkid, tracked);
+        }
+    }
+
+    fn completed(&self, pkid: u16) {
+        let mut state = self.lock();
+
+        // the collision is sent again only if the packet id wasn't reused
+        if !state.in_flight.contains_key(&pkid) {
+            if let Some(next) = state.collisions.remove(&pkid) {
+                state.in_flight.insert(pkid, next);
+            }
+        }
+
+        if let Some(tracked) = state.released.remove(&pkid) {
+            tracked.deliver();
+        }
+    }
+
+    /// Stops tracking the publishes handed to another transport, they won't be sent anymore.
+    ///
+    /// Returns the messages with volatile retention to send again, in the order they were
+    /// published.
+    pub(crate) fn connected<T>(&self, transport: &Arc<T>) -> Vec<VolatileItem> {
+        let mut state = self.lock();
+        let state = &mut *state;
+
+        let (queued, lost): (VecDeque<Tracked>, VecDeque<Tracked>) =
+            std::mem::take(&mut state.queued)
+                .into_iter()
+                .partition(|tracked| tracked.is_on(transport));
+        state.queued = queued;
+
+        let mut lost = Vec::from(lost);
+
+        for tracking in [
+            &mut state.in_flight,
+            &mut state.released,
+            &mut state.collisions,
+        ] {
+            let pkids: Vec<u16> = tracking
+                .iter()
+                .filter(|(_, tracked)| !tracked.is_on(transport))
+                .map(|(pkid, _)| *pkid)
+                .collect();
+
+            lost.extend(pkids.into_iter().filter_map(|pkid| tracking.remove(&pkid)));
+        }
+
+        lost.sort_by_key(|tracked| tracked.seq);
+
+        lost.into_iter()
+            .filter_map(|tracked| tracked.retained)
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for Deliveries {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.lock();
+
+        f.debug_struct("Deliveries")
+            .field("queued", &state.queued.len())
+            .field("in_flight", &(state.in_flight.len() + state.released.len()))
+            .finish_non_exhaustive()
+    }
+}
+
+/// Publish being handed to the client, see [`Deliveries::sending`].
+///
+/// The publish is tracked only if [`sent()`](Sending::sent) is called, so it isn't matched with
+/// the next ones if the client doesn't take it.
+pub(crate) struct Sending<'a> {
+    deliveries: &'a Deliveries,
+    pending: Option<u64>,
+    _guard: tokio::sync::MutexGuard<'a, ()>,
+}
+
+impl<'a> Sending<'a> {
+    /// Tracks the publish handed to the client of the transport, taking the copy of the message
+    /// with volatile retention.
+    pub(crate) fn push<T>(
+        &mut self,
+        transport: &Arc<T>,
+        retained: &mut Option<VolatileItem>,
+    ) -> Delivery
+    where
+        T: Send + Sync + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+
+        let mut state = self.deliveries.lock();
+
+        let seq = state.next_seq;
+        state.next_seq += 1;
+
+        let transport = Arc::downgrade(transport) as Weak<dyn Any + Send + Sync>;
+        state.queued.push_back(Tracked {
+            seq,
+            transport,
+            retained: retained.take(),
+            delivered: tx,
+        });
+
+        self.pending = Some(seq);
+
+        Delivery(rx)
+    }
+
+    /// The client took the publish.
+    pub(crate) fn sent(mut self) {
+        self.pending = None;
+    }
+
+    /// Stops tracking the publish the client couldn't take, giving back the copy of the message.
+    pub(crate) fn cancel(mut self, retained: &mut Option<VolatileItem>) {
+        if let Some(tracked) = self.untrack() {
+            *retained = tracked.retained;
+        }
+    }
+
+    fn untrack(&mut self) -> Option<Tracked> {
+        let seq = self.pending.take()?;
+        let mut state = self.deliveries.lock();
+
+        // the last one pushed, since the publishes are handed to the client one at a time
+        match state.queued.back() {
+            Some(tracked) if tracked.seq == seq => state.queued.pop_back(),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Drop for Sending<'a> {
+    fn drop(&mut self) {
+        self.untrack();
+    }
+}
+
+/// Completes when the publish is delivered.
+#[derive(Debug)]
+pub(crate) struct Delivery(oneshot::Receiver<()>);
+
+impl Delivery {
+    /// Waits for the acknowledgment, returns false if the publish won't be sent anymore.
+    ///
+    /// The events of the event loop must be handled by another task.
+    pub(crate) async fn delivered(self) -> bool {
+        self.0.await.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rumqttc::{PubAck, PubComp, PubRec};
+
+    use super::*;
+
+    async fn push(deliveries: &Deliveries, transport: &Arc<()>, topic: &str) -> Delivery {
+        let mut retained = Some(VolatileItem::new(
+            crate::message::MessageId::new(),
+            "com.test",
+            "/value",
+            topic.to_string(),
+            rumqttc::QoS::AtLeastOnce,
+            Vec::new(),
+            0,
+        ));
+
+        let mut sending = deliveries.sending().await;
+        let delivery = sending.push(transport, &mut retained);
+        sending.sent();
+
+        delivery
+    }
+
+    /// Topics of the publishes not delivered yet.
+    fn tracked(deliveries: &Deliveries) -> Vec<String> {
+        let state = deliveries.lock();
+
+        let mut tracked: Vec<&Tracked> = state
+            .queued
+            .iter()
+            .chain(state.in_flight.values())
+            .chain(state.released.values())
+            .chain(state.collisions.values())
+            .collect();
+        tracked.sort_by_key(|tracked| tracked.seq);
+
+        tracked
+            .into_iter()
+            .filter_map(|tracked| tracked.retained.as_ref())
+            .map(|item| item.topic.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_delivery() {
+        let deliveries = Deliveries::default();
+        let transport = Arc::new(());
+
+        let unreliable = push(&deliveries, &transport, "unreliable").await;
+        let first = push(&deliveries, &transport, "first").await;
+        push(&deliveries, &transport, "second").await;
+
+        deliveries.handle(&Event::Outgoing(Outgoing::Publish(0)));
+        assert_eq!(tracked(&deliveries), ["first", "second"]);
+        assert!(unreliable.delivered().await);
+
+        deliveries.handle(&Event::Outgoing(Outgoing::Publish(1)));
+        deliveries.handle(&Event::Outgoing(Outgoing::Publish(2)));
+        // sent again after a reconnection
+        deliveries.handle(&Event::Outgoing(Outgoing::Publish(1)));
+        assert!(deliveries.connected(&transport).is_empty());
+
+        deliveries.handle(&Event::Incoming(Packet::PubAck(PubAck::new(2))));
+        assert_eq!(tracked(&deliveries), ["first"]);
+
+        deliveries.handle(&Event::Incoming(Packet::PubAck(PubAck::new(1))));
+        assert!(tracked(&deliveries).is_empty());
+        assert!(first.delivered().await);
+    }
+
+    #[tokio::test]
+    async fn test_delivery_exactly_once() {
+        let deliveries = Deliveries::default();
+        let transport = Arc::new(());
+
+        push(&deliveries, &transport, "received").await;
+        push(&deliveries, &transport, "reused").await;
+        push(&deliveries, &transport, "collided").await;
+
+        deliveries.handle(&Event::Outgoing(Outgoing::Publish(1)));
+        deliveries.handle(&Event::Incoming(Packet::PubRec(PubRec::new(1))));
+        // the packet id is reused before the PUBCOMP
+        deliveries.handle(&Event::Outgoing(Outgoing::Publish(1)));
+        assert_eq!(tracked(&deliveries), ["received", "reused", "collided"]);
+
+        deliveries.handle(&Event::Incoming(Packet::PubComp(PubComp::new(1))));
+        assert_eq!(tracked(&deliveries), ["reused", "collided"]);
+
+        deliveries.handle(&Event::Outgoing(Outgoing::AwaitAck(1)));
+
+        // the collided publish is sent before the acknowledgment is returned
+        deliveries.handle(&Event::Outgoing(Outgoing::Publish(1)));
+        deliveries.handle(&Event::Incoming(Packet::PubAck(PubAck::new(1))));
+        assert_eq!(tracked(&deliveries), ["collided"]);
+
+        deliveries.handle(&Event::Incoming(Packet::PubAck(PubAck::new(1))));
+        assert!(tracked(&deliveries).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delivery_transport_replaced() {
+        let deliveries = Deliveries::default();
+        let old = Arc::new(());
+
+        let in_flight = push(&deliveries, &old, "in_flight").await;
+        deliveries.handle(&Event::Outgoing(Outgoing::Publish(1)));
+        let queued = push(&deliveries, &old, "queued").await;
+
+        let new = Arc::new(());
+        push(&deliveries, &new, "new").await;
+
+        let topics: Vec<String> = deliveries
+            .connected(&new)
+            .into_iter()
+            .map(|item| item.topic)
+            .collect();
+        assert_eq!(topics, ["in_flight", "queued"]);
+        assert!(!in_flight.delivered().await);
+        assert!(!queued.delivered().await);
+
+        // the publishes of the new transport are still tracked
+        assert_eq!(tracked(&deliveries), ["new"]);
+        deliveries.handle(&Event::Outgoing(Outgoing::Publish(1)));
+        deliveries.handle(&Event::Incoming(Packet::PubAck(PubAck::new(1))));
+        assert!(tracked(&deliveries).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delivery_cancel() {
+        let deliveries = Deliveries::default();
+        let transport = Arc::new(());
+
+        let mut retained = Some(VolatileItem::new(
+            crate::message::MessageId::new(),
+            "com.test",
+            "/value",
+            "cancelled".to_string(),
+            rumqttc::QoS::AtLeastOnce,
+            Vec::new(),
+            0,
+        ));
+
+        let mut sending = deliveries.sending().await;
+        sending.push(&transport, &mut retained);
+        assert!(retained.is_none());
+        sending.cancel(&mut retained);
+        assert_eq!(retained.unwrap().topic, "cancelled");
+
+        // dropped while the client is taking the publish
+        let mut sending = deliveries.sending().await;
+        sending.push(&transport, &mut None);
+        drop(sending);
+
+        push(&deliveries, &transport, "sent").await;
+        deliveries.handle(&Event::Outgoing(Outgoing::Publish(1)));
+        assert_eq!(
+            deliveries.lock().in_flight[&1]
+                .retained
+                .as_ref()
+                .unwrap()
+                .topic,
+            "sent"
+        );
+    }
+}
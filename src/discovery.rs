@@ -0,0 +1,332 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Discovery of the interfaces installed in the realm.
+//!
+//! Astarte discards the data sent on an interface, or on a version of it, not installed in the
+//! realm. The introspection of the device can be checked against the interfaces installed, using
+//! the [Realm Management API](https://docs.astarte-platform.org/astarte/latest/api/index.html?urls.primaryName=Realm%20Management),
+//! to report the mismatches, see
+//! [`AstarteOptions::realm_discovery`](crate::options::AstarteOptions::realm_discovery) and
+//! [`check_introspection()`](crate::AstarteDeviceSdk::check_introspection).
+
+use std::fmt::{Debug, Display};
+
+use log::debug;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+
+use crate::introspection::{InterfaceVersion, Introspection};
+
+/// Errors querying the interfaces installed in the realm.
+#[non_exhaustive]
+#[derive(thiserror::Error, Debug)]
+pub enum DiscoveryError {
+    #[error("invalid realm management URL")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("error while sending or receiving request")]
+    Request(#[from] reqwest::Error),
+    #[error("the realm management API returned the status {0}")]
+    Status(StatusCode),
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse<T> {
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct InstalledInterface {
+    version_minor: i32,
+}
+
+/// Interface of the introspection that doesn't match the ones installed in the realm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceMismatch {
+    /// The interface is not installed in the realm.
+    Missing {
+        interface: String,
+        version: InterfaceVersion,
+    },
+    /// The major version of the interface is not installed, the installed majors are listed.
+    MissingMajor {
+        interface: String,
+        version: InterfaceVersion,
+        installed: Vec<i32>,
+    },
+    /// The realm has an older minor version, so it doesn't know the newest mappings.
+    OlderMinor {
+        interface: String,
+        version: InterfaceVersion,
+        installed: InterfaceVersion,
+    },
+}
+
+impl InterfaceMismatch {
+    /// Returns the name of the interface.
+    pub fn interface(&self) -> &str {
+        match self {
+            InterfaceMismatch::Missing { interface, .. }
+            | InterfaceMismatch::MissingMajor { interface, .. }
+            | InterfaceMismatch::OlderMinor { interface, .. } => interface,
+        }
+    }
+}
+
+impl Display for InterfaceMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterfaceMismatch::Missing { interface, version } => {
+                write!(f, "{interface}:{version} is not installed in the realm")
+            }
+            InterfaceMismatch::MissingMajor {
+                interface,
+                version,
+                installed,
+            } => write!(
+                f,
+                "{interface}:{version} major version is not installed in the realm, found {installed:?}"
+            ),
+            InterfaceMismatch::OlderMinor {
+                interface,
+                version,
+                installed,
+            } => write!(
+                f,
+                "{interface}:{version} is newer than the version {installed} installed in the realm"
+            ),
+        }
+    }
+}
+
+/// Report of the interfaces of the introspection not matching the ones installed in the realm.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntrospectionMismatch {
+    pub interfaces: Vec<InterfaceMismatch>,
+}
+
+impl IntrospectionMismatch {
+    /// Returns true if all the interfaces are installed in the realm.
+    pub fn is_empty(&self) -> bool {
+        self.interfaces.is_empty()
+    }
+}
+
+impl Display for IntrospectionMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, mismatch) in self.interfaces.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+
+            write!(f, "{mismatch}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compares the version of the device with the majors installed in the realm and the minor of the
+/// same major, if installed.
+fn check_interface(
+    interface: &str,
+    version: InterfaceVersion,
+    installed: &[i32],
+    installed_minor: Option<i32>,
+) -> Option<InterfaceMismatch> {
+    let interface = interface.to_string();
+
+    if installed.is_empty() {
+        return Some(InterfaceMismatch::Missing { interface, version });
+    }
+
+    let Some(minor) = installed_minor.filter(|_| installed.contains(&version.major)) else {
+        return Some(InterfaceMismatch::MissingMajor {
+            interface,
+            version,
+            installed: installed.to_vec(),
+        });
+    };
+
+    (minor < version.minor).then_some(InterfaceMismatch::OlderMinor {
+        interface,
+        version,
+        installed: InterfaceVersion {
+            major: version.major,
+            minor,
+        },
+    })
+}
+
+/// Client of the Realm Management API, to query the interfaces installed in the realm.
+#[derive(Clone)]
+pub struct RealmManagement {
+    url: Url,
+    token: String,
+}
+
+impl RealmManagement {
+    /// Creates a client for the API at the base URL, authenticated with a token with the
+    /// claims to read the interfaces of the realm.
+    pub fn new(api_url: &str, token: &str) -> Result<Self, DiscoveryError> {
+        Ok(Self {
+            url: Url::parse(api_url)?,
+            token: token.to_string(),
+        })
+    }
+
+    fn interface_url(&self, realm: &str, segments: &[&str]) -> Result<Url, DiscoveryError> {
+        let mut url = self.url.clone();
+
+        url.path_segments_mut()
+            .map_err(|_| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+            .pop_if_empty()
+            .push("realmmanagement")
+            .push("v1")
+            .push(realm)
+            .push("interfaces")
+            .extend(segments);
+
+        Ok(url)
+    }
+
+    /// Gets a resource of the API, `None` if it doesn't exist.
+    async fn get<T>(&self, client: &reqwest::Client, url: Url) -> Result<Option<T>, DiscoveryError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let response = client.get(url).bearer_auth(&self.token).send().await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let response: ApiResponse<T> = response.json().await?;
+
+                Ok(Some(response.data))
+            }
+            status => Err(DiscoveryError::Status(status)),
+        }
+    }
+
+    /// Checks the interfaces of the introspection against the ones installed in the realm.
+    pub async fn check(
+        &self,
+        realm: &str,
+        introspection: &Introspection,
+    ) -> Result<IntrospectionMismatch, DiscoveryError> {
+        let client = reqwest::Client::new();
+        let mut report = IntrospectionMismatch::default();
+
+        for (name, version) in introspection.iter() {
+            debug!("checking the interface {name} installed in the realm");
+
+            let url = self.interface_url(realm, &[name])?;
+            let majors: Vec<i32> = self.get(&client, url).await?.unwrap_or_default();
+
+            let mut minor = None;
+            if majors.contains(&version.major) {
+                let url = self.interface_url(realm, &[name, &version.major.to_string()])?;
+
+                minor = self
+                    .get::<InstalledInterface>(&client, url)
+                    .await?
+                    .map(|interface| interface.version_minor);
+            }
+
+            if let Some(mismatch) = check_interface(name, version, &majors, minor) {
+                report.interfaces.push(mismatch);
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+impl Debug for RealmManagement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RealmManagement")
+            .field("url", &self.url)
+            .field("token", &"REDACTED")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn version(major: i32, minor: i32) -> InterfaceVersion {
+        InterfaceVersion { major, minor }
+    }
+
+    #[test]
+    fn test_check_interface() {
+        assert_eq!(
+            check_interface("com.test", version(1, 2), &[], None),
+            Some(InterfaceMismatch::Missing {
+                interface: "com.test".to_string(),
+                version: version(1, 2),
+            })
+        );
+        assert_eq!(
+            check_interface("com.test", version(1, 2), &[0, 2], None),
+            Some(InterfaceMismatch::MissingMajor {
+                interface: "com.test".to_string(),
+                version: version(1, 2),
+                installed: vec![0, 2],
+            })
+        );
+
+        let older = check_interface("com.test", version(1, 2), &[1], Some(1)).unwrap();
+        assert_eq!(
+            older,
+            InterfaceMismatch::OlderMinor {
+                interface: "com.test".to_string(),
+                version: version(1, 2),
+                installed: version(1, 1),
+            }
+        );
+        assert_eq!(
+            older.to_string(),
+            "com.test:1:2 is newer than the version 1:1 installed in the realm"
+        );
+
+        assert_eq!(
+            check_interface("com.test", version(1, 2), &[1], Some(2)),
+            None
+        );
+        assert_eq!(
+            check_interface("com.test", version(1, 2), &[1], Some(3)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_interface_url() {
+        let api = RealmManagement::new("https://api.astarte.example.com/", "secret-jwt").unwrap();
+
+        let url = api
+            .interface_url("test", &["com.example.Sensor", "1"])
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://api.astarte.example.com/realmmanagement/v1/test/interfaces/com.example.Sensor/1"
+        );
+        assert!(!format!("{api:?}").contains("secret-jwt"));
+    }
+}
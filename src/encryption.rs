@@ -0,0 +1,341 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! End to end encryption of the values of the sensitive interfaces.
+//!
+//! The values sent on an interface with a [`PayloadCipher`] are serialized in BSON, encrypted
+//! and sent as a `binaryblob`, so all the mappings of the interface must be binary blobs. The
+//! values received are decrypted before they are filtered, transformed and delivered. The
+//! fields of an object are encrypted one by one, on the path of the object followed by the field
+//! name. A property unset is never encrypted.
+//!
+//! The algorithm and the keys are managed by the cipher, the SDK only sees the plaintext and the
+//! ciphertext. The properties are stored encrypted, so the values returned by
+//! [`get_property()`](crate::AstarteDeviceSdk::get_property) and mirrored by the
+//! [twins](crate::twin) are the binary blobs.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{
+//!     encryption::{CipherError, PayloadCipher},
+//!     options::AstarteOptions,
+//! };
+//!
+//! struct Vault;
+//!
+//! impl PayloadCipher for Vault {
+//!     fn encrypt(&self, interface: &str, path: &str, plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+//!         // encrypt with the current key of the interface
+//!         # Ok(plaintext.to_vec())
+//!     }
+//!
+//!     fn decrypt(&self, interface: &str, path: &str, ciphertext: &[u8]) -> Result<Vec<u8>, CipherError> {
+//!         // decrypt with the key identified in the ciphertext
+//!         # Ok(ciphertext.to_vec())
+//!     }
+//! }
+//!
+//! let sdk_options = AstarteOptions::new("_","_","_","_")
+//!     .payload_encryption("com.example.PatientVitals", Vault);
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::payload::{self, PayloadError};
+use crate::types::AstarteType;
+use crate::Aggregation;
+
+/// Error returned by a [`PayloadCipher`].
+pub type CipherError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Cipher of the values of an interface, managing the algorithm and the keys.
+pub trait PayloadCipher: Send + Sync {
+    /// Encrypts the BSON serialized value sent on the path.
+    fn encrypt(
+        &self,
+        interface: &str,
+        path: &str,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, CipherError>;
+
+    /// Decrypts the value received on the path.
+    fn decrypt(
+        &self,
+        interface: &str,
+        path: &str,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, CipherError>;
+}
+
+/// Errors encrypting or decrypting a value.
+#[non_exhaustive]
+#[derive(thiserror::Error, Debug)]
+pub enum EncryptionError {
+    #[error("cipher error")]
+    Cipher(#[source] CipherError),
+    #[error("couldn't serialize or deserialize the plaintext")]
+    Payload(#[source] Box<PayloadError>),
+    #[error("the encrypted value is not a binary blob")]
+    NotBinary,
+    #[error("the plaintext is not an individual value")]
+    NotIndividual,
+}
+
+impl From<PayloadError> for EncryptionError {
+    fn from(err: PayloadError) -> Self {
+        EncryptionError::Payload(Box::new(err))
+    }
+}
+
+/// Ciphers of the interfaces configured on the device.
+#[derive(Clone, Default)]
+pub(crate) struct PayloadEncryption {
+    ciphers: HashMap<String, Arc<dyn PayloadCipher>>,
+}
+
+impl PayloadEncryption {
+    pub(crate) fn insert(&mut self, interface: &str, cipher: Arc<dyn PayloadCipher>) {
+        self.ciphers.insert(interface.to_string(), cipher);
+    }
+
+    /// Returns true if the interface has a cipher.
+    pub(crate) fn is_encrypted(&self, interface: &str) -> bool {
+        self.ciphers.contains_key(interface)
+    }
+
+    /// Encrypts a value sent on an interface with a cipher.
+    pub(crate) fn encrypt(
+        &self,
+        interface: &str,
+        path: &str,
+        value: AstarteType,
+    ) -> Result<AstarteType, EncryptionError> {
+        let Some(cipher) = self.ciphers.get(interface) else {
+            return Ok(value);
+        };
+
+        if value == AstarteType::Unset {
+            return Ok(value);
+        }
+
+        let plaintext = payload::serialize_individual(&value, None)?;
+        let ciphertext = cipher
+            .encrypt(interface, path, &plaintext)
+            .map_err(EncryptionError::Cipher)?;
+
+        Ok(AstarteType::BinaryBlob(ciphertext))
+    }
+
+    /// Encrypts the fields of an object sent on an interface with a cipher.
+    pub(crate) fn encrypt_object(
+        &self,
+        interface: &str,
+        path: &str,
+        object: HashMap<String, AstarteType>,
+    ) -> Result<HashMap<String, AstarteType>, EncryptionError> {
+        if !self.ciphers.contains_key(interface) {
+            return Ok(object);
+        }
+
+        object
+            .into_iter()
+            .map(|(field, value)| {
+                let value = self.encrypt(interface, &format!("{path}/{field}"), value)?;
+
+                Ok((field, value))
+            })
+            .collect()
+    }
+
+    fn decrypt_value(
+        &self,
+        cipher: &dyn PayloadCipher,
+        interface: &str,
+        path: &str,
+        value: AstarteType,
+    ) -> Result<AstarteType, EncryptionError> {
+        let ciphertext = match value {
+            AstarteType::BinaryBlob(ciphertext) => ciphertext,
+            _ => return Err(EncryptionError::NotBinary),
+        };
+
+        let plaintext = cipher
+            .decrypt(interface, path, &ciphertext)
+            .map_err(EncryptionError::Cipher)?;
+
+        match payload::deserialize(&plaintext)? {
+            Aggregation::Individual(value) => Ok(value),
+            Aggregation::Object(_) | Aggregation::Unset => Err(EncryptionError::NotIndividual),
+        }
+    }
+
+    /// Decrypts the data received on an interface with a cipher, returns the path of the value
+    /// that couldn't be decrypted on error.
+    pub(crate) fn decrypt(
+        &self,
+        interface: &str,
+        path: &str,
+        data: Aggregation,
+    ) -> Result<Aggregation, (String, EncryptionError)> {
+        let Some(cipher) = self.ciphers.get(interface) else {
+            return Ok(data);
+        };
+
+        match data {
+            Aggregation::Unset => Ok(data),
+            Aggregation::Individual(value) => self
+                .decrypt_value(cipher.as_ref(), interface, path, value)
+                .map(Aggregation::Individual)
+                .map_err(|err| (path.to_string(), err)),
+            Aggregation::Object(object) => object
+                .into_iter()
+                .map(|(field, value)| {
+                    let path = format!("{path}/{field}");
+
+                    self.decrypt_value(cipher.as_ref(), interface, &path, value)
+                        .map(|value| (field, value))
+                        .map_err(|err| (path, err))
+                })
+                .collect::<Result<_, _>>()
+                .map(Aggregation::Object),
+        }
+    }
+}
+
+impl Debug for PayloadEncryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PayloadEncryption")
+            .field("interfaces", &self.ciphers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Reverses the bytes of the plaintext.
+    struct Reverse;
+
+    impl PayloadCipher for Reverse {
+        fn encrypt(
+            &self,
+            _interface: &str,
+            _path: &str,
+            plaintext: &[u8],
+        ) -> Result<Vec<u8>, CipherError> {
+            Ok(plaintext.iter().rev().copied().collect())
+        }
+
+        fn decrypt(
+            &self,
+            _interface: &str,
+            path: &str,
+            ciphertext: &[u8],
+        ) -> Result<Vec<u8>, CipherError> {
+            if path.ends_with("revoked") {
+                return Err("revoked key".into());
+            }
+
+            Ok(ciphertext.iter().rev().copied().collect())
+        }
+    }
+
+    fn encryption() -> PayloadEncryption {
+        let mut encryption = PayloadEncryption::default();
+        encryption.insert("com.test.Sensitive", Arc::new(Reverse));
+
+        encryption
+    }
+
+    #[test]
+    fn test_encrypt_roundtrip() {
+        let encryption = encryption();
+
+        let value = AstarteType::String("sensitive".to_string());
+        let encrypted = encryption
+            .encrypt("com.test.Sensitive", "/value", value.clone())
+            .unwrap();
+        assert!(matches!(encrypted, AstarteType::BinaryBlob(_)));
+
+        let decrypted = encryption
+            .decrypt(
+                "com.test.Sensitive",
+                "/value",
+                Aggregation::Individual(encrypted),
+            )
+            .unwrap();
+        assert_eq!(decrypted, Aggregation::Individual(value));
+
+        // the other interfaces and the unset are unchanged
+        assert_eq!(
+            encryption
+                .encrypt("com.test.Other", "/value", AstarteType::Integer(1))
+                .unwrap(),
+            AstarteType::Integer(1)
+        );
+        assert_eq!(
+            encryption
+                .encrypt("com.test.Sensitive", "/value", AstarteType::Unset)
+                .unwrap(),
+            AstarteType::Unset
+        );
+        assert_eq!(
+            encryption
+                .decrypt("com.test.Sensitive", "/value", Aggregation::Unset)
+                .unwrap(),
+            Aggregation::Unset
+        );
+    }
+
+    #[test]
+    fn test_decrypt_object() {
+        let encryption = encryption();
+
+        let object = HashMap::from([
+            ("heart_rate".to_string(), AstarteType::Integer(72)),
+            ("revoked".to_string(), AstarteType::Double(36.6)),
+        ]);
+        let encrypted = encryption
+            .encrypt_object("com.test.Sensitive", "/patient", object)
+            .unwrap();
+        assert!(encrypted
+            .values()
+            .all(|value| matches!(value, AstarteType::BinaryBlob(_))));
+
+        let (path, err) = encryption
+            .decrypt(
+                "com.test.Sensitive",
+                "/patient",
+                Aggregation::Object(encrypted),
+            )
+            .unwrap_err();
+        assert_eq!(path, "/patient/revoked");
+        assert!(matches!(err, EncryptionError::Cipher(_)));
+
+        let (_, err) = encryption
+            .decrypt(
+                "com.test.Sensitive",
+                "/value",
+                Aggregation::Individual(AstarteType::Integer(1)),
+            )
+            .unwrap_err();
+        assert!(matches!(err, EncryptionError::NotBinary));
+    }
+}
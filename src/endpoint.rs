@@ -0,0 +1,213 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Match the paths against the parametric endpoints of the interface mappings.
+//!
+//! The endpoints are parsed with the same rules of the interfaces, so the events can be routed by
+//! the application with the same matching used by the SDK.
+//!
+//! ```
+//! use astarte_device_sdk::endpoint::EndpointPattern;
+//!
+//! let pattern = EndpointPattern::new("/%{sensor_id}/value").unwrap();
+//!
+//! let params = pattern.matches("/temperature/value").unwrap();
+//! assert_eq!(params.get("sensor_id"), Some("temperature"));
+//!
+//! assert!(pattern.matches("/temperature/unit").is_none());
+//! ```
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use itertools::{EitherOrBoth, Itertools};
+
+use crate::interface::mapping::endpoint::{Endpoint, Level};
+
+pub use crate::interface::mapping::endpoint::{EndpointError, LevelError};
+
+/// A parsed mapping endpoint, with simple and parametric levels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointPattern {
+    endpoint: Endpoint<'static>,
+}
+
+impl EndpointPattern {
+    /// Parses an endpoint, like `/%{sensor_id}/value`.
+    pub fn new(endpoint: &str) -> Result<Self, EndpointError> {
+        Endpoint::try_from(endpoint).map(|endpoint| Self {
+            endpoint: endpoint.into_owned(),
+        })
+    }
+
+    /// Returns the names of the parameters, in the order they appear.
+    pub fn params(&self) -> impl Iterator<Item = &str> {
+        self.endpoint.iter().filter_map(|level| match level {
+            Level::Simple(_) => None,
+            Level::Parameter(name) => Some(name.as_ref()),
+        })
+    }
+
+    /// Matches a path against the endpoint, returning the values of the parameters.
+    ///
+    /// The path must start with a slash and have the same number of levels as the endpoint, the
+    /// simple levels must be equal while the parameters match any non empty level.
+    pub fn matches<'a>(&'a self, path: &'a str) -> Option<Params<'a>> {
+        let levels = path.strip_prefix('/')?.split('/');
+
+        let mut params = Vec::new();
+
+        for level in self.endpoint.iter().zip_longest(levels) {
+            let EitherOrBoth::Both(level, value) = level else {
+                return None;
+            };
+
+            if value.is_empty() {
+                return None;
+            }
+
+            match level {
+                Level::Simple(simple) if simple == value => {}
+                Level::Simple(_) => return None,
+                Level::Parameter(name) => params.push((name.as_ref(), value)),
+            }
+        }
+
+        Some(Params { params })
+    }
+}
+
+impl FromStr for EndpointPattern {
+    type Err = EndpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl Display for EndpointPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.endpoint)
+    }
+}
+
+/// Values of the parameters of a matched path.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Params<'a> {
+    params: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Params<'a> {
+    /// Returns the value of a parameter.
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.params
+            .iter()
+            .find_map(|(param, value)| (*param == name).then_some(*value))
+    }
+
+    /// Returns the names and values of the parameters, in the order they appear.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> + '_ {
+        self.params.iter().copied()
+    }
+
+    /// Returns the number of parameters.
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Returns `true` if the endpoint has no parameters.
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_invalid_pattern() {
+        for endpoint in ["", "value", "/", "/a//b", "/a/+", "/a/#", "/%{a}b", "/a/"] {
+            assert!(
+                EndpointPattern::new(endpoint).is_err(),
+                "parsed invalid endpoint {endpoint}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_simple() {
+        let pattern = EndpointPattern::new("/sensors/value").unwrap();
+
+        assert_eq!(pattern.params().count(), 0);
+        assert_eq!(pattern.to_string(), "/sensors/value");
+
+        let params = pattern.matches("/sensors/value").unwrap();
+        assert!(params.is_empty());
+
+        for path in [
+            "",
+            "/",
+            "sensors/value",
+            "/sensors",
+            "/sensors/value/unit",
+            "/sensors/other",
+            "/sensors/value/",
+            "//sensors/value",
+        ] {
+            assert_eq!(pattern.matches(path), None, "matched {path}");
+        }
+    }
+
+    #[test]
+    fn test_parameters() {
+        let pattern: EndpointPattern = "/%{room}/sensors/%{sensor_id}/value".parse().unwrap();
+
+        assert_eq!(pattern.params().collect::<Vec<_>>(), ["room", "sensor_id"]);
+
+        let params = pattern.matches("/kitchen/sensors/42/value").unwrap();
+        assert_eq!(params.len(), 2);
+        assert_eq!(params.get("room"), Some("kitchen"));
+        assert_eq!(params.get("sensor_id"), Some("42"));
+        assert_eq!(params.get("missing"), None);
+        assert_eq!(
+            params.iter().collect::<Vec<_>>(),
+            [("room", "kitchen"), ("sensor_id", "42")]
+        );
+
+        for path in [
+            "/kitchen/sensors/42",
+            "/kitchen/sensors//value",
+            "//sensors/42/value",
+            "/kitchen/other/42/value",
+            "/kitchen/sensors/42/value/unit",
+        ] {
+            assert_eq!(pattern.matches(path), None, "matched {path}");
+        }
+    }
+
+    #[test]
+    fn test_parameter_only() {
+        let pattern = EndpointPattern::new("/%{id}").unwrap();
+
+        assert_eq!(pattern.matches("/1").unwrap().get("id"), Some("1"));
+        // the parameters are matched as simple strings
+        assert_eq!(pattern.matches("/%{id}").unwrap().get("id"), Some("%{id}"));
+        assert_eq!(pattern.matches("/1/2"), None);
+    }
+}
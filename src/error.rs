@@ -20,11 +20,16 @@
 
 use std::convert::Infallible;
 
+use crate::collection::CollectionError;
+use crate::discovery::{DiscoveryError, IntrospectionMismatch};
+use crate::encryption::EncryptionError;
+use crate::event::FromEventError;
 use crate::interface::mapping::path::MappingError;
 use crate::interface::InterfaceError;
 use crate::options::OptionsError;
 use crate::payload::PayloadError;
 use crate::properties::PropertiesError;
+use crate::registry::RegistryError;
 use crate::topic::TopicError;
 use crate::types::TypeError;
 
@@ -38,7 +43,7 @@ pub enum Error {
     BsonClientError(#[from] rumqttc::ClientError),
 
     #[error("mqtt connection error")]
-    ConnectionError(#[from] rumqttc::ConnectionError),
+    ConnectionError(#[source] Box<rumqttc::ConnectionError>),
 
     #[error("send error ({0})")]
     SendError(String),
@@ -78,7 +83,219 @@ pub enum Error {
     #[error("couldn't process payload")]
     Payload(#[from] PayloadError),
 
+    /// Couldn't serialize or deserialize the payload of a path of an interface.
+    #[error("couldn't {operation} the payload on {interface}{path}")]
+    InterfacePayload {
+        interface: String,
+        path: String,
+        operation: PayloadOperation,
+        #[source]
+        source: Box<PayloadError>,
+    },
+
+    /// Couldn't publish the data of a path of an interface.
+    #[error("couldn't publish on {interface}{path}")]
+    Publish {
+        interface: String,
+        path: String,
+        #[source]
+        source: rumqttc::ClientError,
+    },
+
     /// Error while parsing the /control/consumer/properties payload.
     #[error("couldn't handle properties")]
     Properties(#[from] PropertiesError),
+
+    /// The property store is full and the property couldn't be written.
+    #[error("the store is full, couldn't store the property {interface}{path}")]
+    StoreFull { interface: String, path: String },
+
+    /// The property exceeds the [storage quota](crate::quota) of its interface.
+    #[error("the storage quota of {interface} is exceeded, couldn't store the property {path}")]
+    QuotaExceeded { interface: String, path: String },
+
+    /// A received value was rejected by a [`ValueConstraint`](crate::constraint::ValueConstraint).
+    #[error("value rejected on {interface}{path}: {reason}")]
+    ConstraintViolation {
+        interface: String,
+        path: String,
+        reason: String,
+    },
+
+    /// The interface isn't a server-owned property interface.
+    #[error("{0} isn't a server-owned property interface")]
+    NotServerProperty(String),
+
+    /// A received value couldn't be converted by a [`ValueTransform`](crate::transform::ValueTransform).
+    #[error("couldn't transform value on {interface}{path}: {reason}")]
+    Transform {
+        interface: String,
+        path: String,
+        reason: String,
+    },
+
+    /// Couldn't encrypt or decrypt a value of an interface with a
+    /// [`PayloadCipher`](crate::encryption::PayloadCipher).
+    #[error("couldn't encrypt or decrypt the value on {interface}{path}")]
+    Encryption {
+        interface: String,
+        path: String,
+        #[source]
+        source: EncryptionError,
+    },
+
+    /// A received message is bigger than the [maximum
+    /// size](crate::options::AstarteOptions::max_event_size).
+    #[error("the message received on {interface}{path} is {size} bytes, bigger than the maximum of {max}")]
+    EventTooLarge {
+        interface: String,
+        path: String,
+        size: usize,
+        max: usize,
+    },
+
+    /// A payload to send is bigger than the [maximum
+    /// size](crate::capabilities::Capabilities::max_payload_size) supported by the cluster.
+    #[error("the payload on {interface}{path} is {size} bytes, bigger than the maximum of {max}")]
+    PayloadTooLarge {
+        interface: String,
+        path: String,
+        size: usize,
+        max: usize,
+    },
+
+    /// Couldn't read or write the [journal](crate::database::journal) of the properties.
+    #[error("couldn't write the property journal")]
+    Journal(#[source] std::io::Error),
+
+    /// Couldn't read or write the [log](crate::dedup) of the published outbox entries.
+    #[error("couldn't write the log of the published outbox entries")]
+    PublishedLog(#[source] std::io::Error),
+
+    /// Couldn't query the interfaces installed in the realm, see the
+    /// [discovery](crate::discovery) module.
+    #[error("couldn't query the interfaces of the realm")]
+    Discovery(#[from] DiscoveryError),
+
+    /// The introspection references interfaces or versions not installed in the realm.
+    #[error("the introspection doesn't match the realm: {0}")]
+    IntrospectionMismatch(IntrospectionMismatch),
+
+    /// Couldn't download the interfaces from the [schema registry](crate::registry).
+    #[error("schema registry error")]
+    Registry(#[from] RegistryError),
+
+    /// A message was rejected by the rate limit of an [interface
+    /// handle](crate::handle::InterfaceHandle).
+    #[error("rate limit exceeded on interface {0}")]
+    RateLimited(String),
+
+    /// Invalid endpoint of a [collection](crate::collection).
+    #[error("invalid collection")]
+    Collection(#[from] CollectionError),
+
+    /// Couldn't convert the stored values of a property interface into a struct deriving
+    /// [`AstarteProperties`](crate::settings::AstarteProperties).
+    #[error("couldn't load the properties of {interface}")]
+    LoadProperties {
+        interface: String,
+        #[source]
+        source: FromEventError,
+    },
+
+    /// The pending messages couldn't be flushed within the deadline of the
+    /// [shutdown](crate::AstarteDeviceSdk::shutdown), they are lost.
+    #[error("couldn't flush the pending messages in {0:?}")]
+    ShutdownDeadline(std::time::Duration),
+
+    /// The device is in a terminal error state and can't be used anymore.
+    #[error("the device is in a terminal error state: {0}")]
+    Terminated(String),
+}
+
+/// Operation on the payload that caused an [`Error::InterfacePayload`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadOperation {
+    /// Serializing the data sent.
+    Serialize,
+    /// Deserializing the data received.
+    Deserialize,
+}
+
+impl std::fmt::Display for PayloadOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PayloadOperation::Serialize => write!(f, "serialize"),
+            PayloadOperation::Deserialize => write!(f, "deserialize"),
+        }
+    }
+}
+
+// The connection error is boxed to keep the size of the results small
+impl From<rumqttc::ConnectionError> for Error {
+    fn from(err: rumqttc::ConnectionError) -> Self {
+        Error::ConnectionError(Box::new(err))
+    }
+}
+
+impl Error {
+    /// Returns true if the data sent is invalid, sending it again would fail the same way.
+    pub(crate) fn is_invalid_data(&self) -> bool {
+        matches!(
+            self,
+            Error::SendError(_)
+                | Error::Interface(_)
+                | Error::InvalidEndpoint(_)
+                | Error::Types(_)
+                | Error::Payload(_)
+                | Error::InterfacePayload { .. }
+                | Error::Encryption { .. }
+                | Error::PayloadTooLarge { .. }
+        )
+    }
+
+    /// Adds the interface and path to an error handling the payload.
+    pub(crate) fn payload(
+        interface: &str,
+        path: &str,
+        operation: PayloadOperation,
+    ) -> impl FnOnce(PayloadError) -> Self {
+        let interface = interface.to_string();
+        let path = path.to_string();
+
+        move |source| Error::InterfacePayload {
+            interface,
+            path,
+            operation,
+            source: Box::new(source),
+        }
+    }
+
+    /// Adds the interface and path to an error encrypting the data.
+    pub(crate) fn encryption(interface: &str, path: &str) -> impl FnOnce(EncryptionError) -> Self {
+        let interface = interface.to_string();
+        let path = path.to_string();
+
+        move |source| Error::Encryption {
+            interface,
+            path,
+            source,
+        }
+    }
+
+    /// Adds the interface and path to an error publishing the data.
+    pub(crate) fn publish(
+        interface: &str,
+        path: &str,
+    ) -> impl FnOnce(rumqttc::ClientError) -> Self {
+        let interface = interface.to_string();
+        let path = path.to_string();
+
+        move |source| Error::Publish {
+            interface,
+            path,
+            source,
+        }
+    }
 }
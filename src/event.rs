@@ -0,0 +1,305 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conversion of the received events into structs and enums.
+//!
+//! The [`FromEvent`] trait can be derived with `feature = ["derive"]`. A struct is converted from
+//! an object aggregate sent on the path of the `from_event` attribute, while an enum is
+//! converted from an individual value, with a variant for each mapping. The parameters of the
+//! path can be captured in the fields with the `mapping` attribute.
+//!
+//! The `Vec` values are converted from the array mappings element by element, so the elements
+//! can be any type converted from the scalar values, like another derived type or an `f64`
+//! from an `integerarray`. A `Vec<u8>` is a `binaryblob`.
+//!
+//! The enums of the property interfaces, with `interface_type = "properties"`, have a
+//! [`Property`] as value of the variants, so the unset of a property can be decoded.
+//!
+//! The events of multiple interfaces are routed with `#[from_event(dispatch)]` on an enum, whose
+//! variants have as field a type deriving [`FromEvent`] for a single interface. The event is
+//! converted to the variant of its interface, using [`FromInterface::INTERFACE`].
+//!
+//! The reverse conversion is the [`IntoEvent`] trait, which can be derived for the same enums
+//! with the `into_event` attribute. The value is sent on the endpoint of the variant with
+//! [`send_event()`](crate::AstarteDeviceSdk::send_event), the parameter is formatted in the path.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{
+//!     event::Property, AstarteDeviceDataEvent, AstarteDeviceSdk, FromEvent, IntoEvent,
+//! };
+//! #[cfg(not(feature = "derive"))]
+//! use astarte_device_sdk_derive::{FromEvent, IntoEvent};
+//!
+//! #[derive(FromEvent)]
+//! #[from_event(interface = "com.example.Sensors", path = "/%{sensor_id}")]
+//! struct Reading {
+//!     #[mapping(param = "sensor_id")]
+//!     sensor: String,
+//!     value: f64,
+//!     #[mapping(endpoint = "unit")]
+//!     unit_name: Option<String>,
+//! }
+//!
+//! #[derive(FromEvent, IntoEvent)]
+//! #[from_event(interface = "com.example.Status", aggregation = "individual")]
+//! #[into_event(interface = "com.example.Status")]
+//! enum Status {
+//!     #[mapping(endpoint = "/%{id}/temperature", param = "id")]
+//!     Temperature(u32, f64),
+//!     #[mapping(endpoint = "/enabled")]
+//!     Enabled(bool),
+//! }
+//!
+//! #[derive(FromEvent)]
+//! #[from_event(interface = "com.example.Config", interface_type = "properties")]
+//! enum Config {
+//!     #[mapping(endpoint = "/%{sensor_id}/enable", param = "sensor_id")]
+//!     Enable(String, Property<bool>),
+//! }
+//!
+//! #[derive(FromEvent)]
+//! #[from_event(dispatch)]
+//! enum DeviceEvent {
+//!     Reading(Reading),
+//!     Status(Status),
+//!     Config(Config),
+//! }
+//!
+//! fn handle(event: AstarteDeviceDataEvent) {
+//!     match DeviceEvent::from_event(event) {
+//!         Ok(DeviceEvent::Status(Status::Temperature(id, value))) => println!("sensor {id}: {value}"),
+//!         Ok(DeviceEvent::Status(Status::Enabled(enabled))) => println!("enabled: {enabled}"),
+//!         Ok(_) => {}
+//!         Err(err) => println!("invalid event: {err}"),
+//!     }
+//! }
+//!
+//! async fn report(device: &AstarteDeviceSdk) {
+//!     // sent on /3/temperature
+//!     device.send_event(Status::Temperature(3, 21.5)).await.unwrap();
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::endpoint::{EndpointError, Params};
+use crate::error::Error;
+use crate::types::{AstarteType, TypeError};
+use crate::AstarteDeviceDataEvent;
+
+/// Conversion from a received event.
+pub trait FromEvent: Sized {
+    type Err;
+
+    fn from_event(event: AstarteDeviceDataEvent) -> Result<Self, Self::Err>;
+}
+
+/// Type converted from the events of a single interface, implemented by the derived
+/// [`FromEvent`] to route the events of a `dispatch` enum.
+pub trait FromInterface: FromEvent {
+    /// Name of the interface.
+    const INTERFACE: &'static str;
+}
+
+/// Conversion into an individual value to send, on the interface and path given by the value.
+pub trait IntoEvent {
+    fn into_event(self) -> Result<OutgoingEvent, Error>;
+}
+
+/// Individual value to send on an interface, returned by [`IntoEvent`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutgoingEvent {
+    pub interface: String,
+    pub path: String,
+    pub data: AstarteType,
+}
+
+/// Errors converting an event with a derived [`FromEvent`].
+#[non_exhaustive]
+#[derive(thiserror::Error, Debug)]
+pub enum FromEventError {
+    #[error("the event is on the interface {got}, not {expected}")]
+    Interface { expected: String, got: String },
+    #[error("invalid endpoint")]
+    Endpoint(#[from] EndpointError),
+    #[error("no mapping of {interface} matches the path {path}")]
+    Path { interface: String, path: String },
+    #[error("expected an object aggregate on {interface}{path}")]
+    Object { interface: String, path: String },
+    #[error("expected an individual value on {interface}{path}")]
+    Individual { interface: String, path: String },
+    #[error("missing the field {0} of the object")]
+    MissingField(String),
+    #[error("couldn't convert the field {field}")]
+    Conversion {
+        field: String,
+        #[source]
+        source: TypeError,
+    },
+    #[error("invalid value {value} of the parameter {param}")]
+    Param { param: String, value: String },
+    #[error("no variant is converted from the interface {0}")]
+    UnknownInterface(String),
+}
+
+/// Value of a property, set or unset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Property<T> {
+    Set(T),
+    Unset,
+}
+
+impl<T> Property<T> {
+    /// Returns the value, `None` if the property is unset.
+    pub fn into_option(self) -> Option<T> {
+        match self {
+            Property::Set(value) => Some(value),
+            Property::Unset => None,
+        }
+    }
+}
+
+impl<T> From<Property<T>> for Option<T> {
+    fn from(value: Property<T>) -> Self {
+        value.into_option()
+    }
+}
+
+/// Checks the interface of the event.
+#[doc(hidden)]
+pub fn check_interface(
+    event: &AstarteDeviceDataEvent,
+    interface: &str,
+) -> Result<(), FromEventError> {
+    if event.interface != interface {
+        return Err(FromEventError::Interface {
+            expected: interface.to_string(),
+            got: event.interface.clone(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Parses the value of a parameter of the path.
+#[doc(hidden)]
+pub fn param<T>(params: &Params, name: &str) -> Result<T, FromEventError>
+where
+    T: FromStr,
+{
+    let value = params.get(name).unwrap_or_default();
+
+    value.parse().map_err(|_| FromEventError::Param {
+        param: name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Converts an individual value, or a field of an object, with a function.
+#[doc(hidden)]
+pub fn convert_with<T, F>(value: AstarteType, field: &str, f: F) -> Result<T, FromEventError>
+where
+    F: FnOnce(AstarteType) -> Result<T, TypeError>,
+{
+    f(value).map_err(|source| FromEventError::Conversion {
+        field: field.to_string(),
+        source,
+    })
+}
+
+/// Converts the value of a property with a function, [`None`] for an unset.
+#[doc(hidden)]
+pub fn property_with<T, F>(
+    value: Option<AstarteType>,
+    field: &str,
+    f: F,
+) -> Result<Property<T>, FromEventError>
+where
+    F: FnOnce(AstarteType) -> Result<T, TypeError>,
+{
+    match value {
+        Some(value) => convert_with(value, field, f).map(Property::Set),
+        None => Ok(Property::Unset),
+    }
+}
+
+/// Converts each element of an array with a function.
+#[doc(hidden)]
+pub fn array_with<T, F>(value: AstarteType, f: F) -> Result<Vec<T>, TypeError>
+where
+    F: FnMut(AstarteType) -> Result<T, TypeError>,
+{
+    let elements: Vec<AstarteType> = match value {
+        AstarteType::DoubleArray(v) => v.into_iter().map(AstarteType::Double).collect(),
+        AstarteType::IntegerArray(v) => v.into_iter().map(AstarteType::Integer).collect(),
+        AstarteType::BooleanArray(v) => v.into_iter().map(AstarteType::Boolean).collect(),
+        AstarteType::LongIntegerArray(v) => v.into_iter().map(AstarteType::LongInteger).collect(),
+        AstarteType::StringArray(v) => v.into_iter().map(AstarteType::String).collect(),
+        AstarteType::BinaryBlobArray(v) => v.into_iter().map(AstarteType::BinaryBlob).collect(),
+        AstarteType::DateTimeArray(v) => v.into_iter().map(AstarteType::DateTime).collect(),
+        _ => return Err(TypeError::Conversion),
+    };
+
+    elements.into_iter().map(f).collect()
+}
+
+/// Removes and converts a required field of an object with a function.
+#[doc(hidden)]
+pub fn field_with<T, F>(
+    object: &mut HashMap<String, AstarteType>,
+    key: &str,
+    f: F,
+) -> Result<T, FromEventError>
+where
+    F: FnOnce(AstarteType) -> Result<T, TypeError>,
+{
+    let value = object
+        .remove(key)
+        .ok_or_else(|| FromEventError::MissingField(key.to_string()))?;
+
+    convert_with(value, key, f)
+}
+
+/// Removes and converts an optional field of an object with a function.
+#[doc(hidden)]
+pub fn optional_field_with<T, F>(
+    object: &mut HashMap<String, AstarteType>,
+    key: &str,
+    f: F,
+) -> Result<Option<T>, FromEventError>
+where
+    F: FnOnce(AstarteType) -> Result<T, TypeError>,
+{
+    object
+        .remove(key)
+        .map(|value| convert_with(value, key, f))
+        .transpose()
+}
+
+/// Converts the value of a property to send with a function, the unset to [`AstarteType::Unset`].
+#[doc(hidden)]
+pub fn unset_or_with<T, E, F>(value: Property<T>, f: F) -> Result<AstarteType, E>
+where
+    F: FnOnce(T) -> Result<AstarteType, E>,
+{
+    match value {
+        Property::Set(value) => f(value),
+        Property::Unset => Ok(AstarteType::Unset),
+    }
+}
@@ -0,0 +1,348 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Filters applied to the data received from Astarte before it's delivered.
+//!
+//! When at least one filter is configured for an interface, only the events matching one of the
+//! filters are returned by [`handle_events()`](crate::AstarteDeviceSdk::handle_events). The
+//! events of the interfaces without filters are always delivered.
+//!
+//! The interface name of a filter can have `*` wildcards, like `com.example.sensors.*`, to filter
+//! a family of interfaces without listing each name.
+//!
+//! The path of a datastream is checked before the payload is deserialized, so the filtered
+//! messages are discarded cheaply. The properties are always stored even if filtered.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{
+//!     filter::EventFilter, options::AstarteOptions, types::AstarteType, Aggregation,
+//! };
+//!
+//! let filter = EventFilter::new("com.example.ServerSensors")
+//!     .path("/*/temperature")
+//!     .unwrap()
+//!     .value(|data| {
+//!         matches!(data, Aggregation::Individual(AstarteType::Double(t)) if *t > 30.0)
+//!     });
+//!
+//! let sdk_options = AstarteOptions::new("_","_","_","_").event_filter(filter);
+//! ```
+
+use std::fmt::{Debug, Display};
+use std::sync::Arc;
+
+use crate::Aggregation;
+
+/// Errors while building a filter.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FilterError {
+    /// The glob must start with a slash.
+    #[error("path glob missing prefix: {0}")]
+    Prefix(String),
+    /// The glob has an empty level.
+    #[error("path glob has an empty level: {0}")]
+    EmptyLevel(String),
+}
+
+/// Glob on the levels of a path.
+///
+/// - `*` in a level matches any sequence of characters in a single level, e.g. `/sensor_*/value`
+/// - `**` as a level matches any number of levels, e.g. `/sensors/**`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathGlob {
+    glob: String,
+    levels: Vec<GlobLevel>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum GlobLevel {
+    Pattern(String),
+    Any,
+}
+
+impl PathGlob {
+    /// Parse the glob.
+    pub fn new(glob: &str) -> Result<Self, FilterError> {
+        let Some(rest) = glob.strip_prefix('/') else {
+            return Err(FilterError::Prefix(glob.to_string()));
+        };
+
+        let levels = rest
+            .split('/')
+            .map(|level| match level {
+                "" => Err(FilterError::EmptyLevel(glob.to_string())),
+                "**" => Ok(GlobLevel::Any),
+                pattern => Ok(GlobLevel::Pattern(pattern.to_string())),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            glob: glob.to_string(),
+            levels,
+        })
+    }
+
+    /// Check if the path matches the glob.
+    pub fn matches(&self, path: &str) -> bool {
+        let Some(rest) = path.strip_prefix('/') else {
+            return false;
+        };
+
+        let levels: Vec<&str> = rest.split('/').collect();
+
+        matches_levels(&self.levels, &levels)
+    }
+}
+
+impl Display for PathGlob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.glob)
+    }
+}
+
+fn matches_levels(glob: &[GlobLevel], levels: &[&str]) -> bool {
+    match (glob.split_first(), levels.split_first()) {
+        (None, None) => true,
+        (Some((GlobLevel::Any, glob_rest)), _) => (0..=levels.len())
+            .filter_map(|skip| levels.get(skip..))
+            .any(|levels| matches_levels(glob_rest, levels)),
+        (Some((GlobLevel::Pattern(pattern), glob_rest)), Some((level, rest))) => {
+            wildcard_match(pattern, level) && matches_levels(glob_rest, rest)
+        }
+        (Some(_), None) | (None, Some(_)) => false,
+    }
+}
+
+/// Match a string with a pattern where `*` matches any sequence of characters.
+pub(crate) fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+
+    // There is always a first part, which must be a prefix
+    let first = parts.next().unwrap_or_default();
+    let Some(mut value) = value.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts = parts.peekable();
+    while let Some(part) = parts.next() {
+        // The last part must be a suffix
+        if parts.peek().is_none() {
+            return value.ends_with(part);
+        }
+
+        match value.find(part) {
+            Some(idx) => value = &value[idx + part.len()..],
+            None => return false,
+        }
+    }
+
+    // No wildcard in the pattern, it should be equal
+    value.is_empty()
+}
+
+type ValuePredicate = Arc<dyn Fn(&Aggregation) -> bool + Send + Sync>;
+
+/// Filter on the data received on an interface.
+///
+/// The filter matches an event if both the path and value predicate match, the missing ones
+/// match any event.
+#[derive(Clone)]
+pub struct EventFilter {
+    interface: String,
+    path: Option<PathGlob>,
+    value: Option<ValuePredicate>,
+}
+
+impl EventFilter {
+    /// Create a filter on the interface, matching all the events.
+    ///
+    /// A `*` in the name matches any sequence of characters, so `com.example.*` filters all the
+    /// interfaces starting with `com.example.`.
+    pub fn new(interface: &str) -> Self {
+        Self {
+            interface: interface.to_string(),
+            path: None,
+            value: None,
+        }
+    }
+
+    /// Match only the paths matching the glob, see [`PathGlob`].
+    pub fn path(mut self, glob: &str) -> Result<Self, FilterError> {
+        self.path = Some(PathGlob::new(glob)?);
+
+        Ok(self)
+    }
+
+    /// Match only the events with a value satisfying the predicate.
+    pub fn value<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Aggregation) -> bool + Send + Sync + 'static,
+    {
+        self.value = Some(Arc::new(predicate));
+
+        self
+    }
+
+    fn matches_path(&self, path: &str) -> bool {
+        self.path.as_ref().map_or(true, |glob| glob.matches(path))
+    }
+
+    fn matches_value(&self, data: &Aggregation) -> bool {
+        self.value
+            .as_ref()
+            .map_or(true, |predicate| predicate(data))
+    }
+}
+
+impl Debug for EventFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventFilter")
+            .field("interface", &self.interface)
+            .field("path", &self.path)
+            .field("value", &self.value.is_some())
+            .finish()
+    }
+}
+
+/// Filters configured on the device.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EventFilters {
+    filters: Vec<EventFilter>,
+}
+
+impl EventFilters {
+    pub(crate) fn push(&mut self, filter: EventFilter) {
+        self.filters.push(filter);
+    }
+
+    fn for_interface<'a>(&'a self, interface: &'a str) -> impl Iterator<Item = &'a EventFilter> {
+        self.filters
+            .iter()
+            .filter(move |filter| wildcard_match(&filter.interface, interface))
+    }
+
+    /// Check if an event on the path could be delivered, before checking the value.
+    pub(crate) fn accepts_path(&self, interface: &str, path: &str) -> bool {
+        let mut filters = self.for_interface(interface).peekable();
+
+        filters.peek().is_none() || filters.any(|filter| filter.matches_path(path))
+    }
+
+    /// Check if the event should be delivered.
+    pub(crate) fn accepts(&self, interface: &str, path: &str, data: &Aggregation) -> bool {
+        let mut filters = self.for_interface(interface).peekable();
+
+        filters.peek().is_none()
+            || filters.any(|filter| filter.matches_path(path) && filter.matches_value(data))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::AstarteType;
+
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("value", "value"));
+        assert!(!wildcard_match("value", "values"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("sensor_*", "sensor_1"));
+        assert!(wildcard_match("*_temp", "sensor_temp"));
+        assert!(wildcard_match("s*_*_t", "sensor_1_t"));
+        assert!(!wildcard_match("s*_t", "sensor_1"));
+        assert!(!wildcard_match("a*a", "a"));
+    }
+
+    #[test]
+    fn test_path_glob() {
+        let glob = PathGlob::new("/*/value").unwrap();
+        assert!(glob.matches("/1/value"));
+        assert!(!glob.matches("/1/other"));
+        assert!(!glob.matches("/1/2/value"));
+
+        let glob = PathGlob::new("/sensors/**").unwrap();
+        assert!(glob.matches("/sensors"));
+        assert!(glob.matches("/sensors/1/value"));
+        assert!(!glob.matches("/other/1"));
+
+        let glob = PathGlob::new("/**/value").unwrap();
+        assert!(glob.matches("/value"));
+        assert!(glob.matches("/a/b/value"));
+        assert!(!glob.matches("/a/b/other"));
+
+        assert_eq!(
+            PathGlob::new("value"),
+            Err(FilterError::Prefix("value".to_string()))
+        );
+        assert_eq!(
+            PathGlob::new("/a//b"),
+            Err(FilterError::EmptyLevel("/a//b".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_event_filters() {
+        let mut filters = EventFilters::default();
+
+        filters.push(EventFilter::new("com.test").path("/*/enable").unwrap());
+        filters.push(
+            EventFilter::new("com.test")
+                .path("/*/value")
+                .unwrap()
+                .value(|data| {
+                    matches!(data, Aggregation::Individual(AstarteType::Integer(v)) if *v > 10)
+                }),
+        );
+
+        let value = |v| Aggregation::Individual(AstarteType::Integer(v));
+
+        assert!(filters.accepts_path("com.test", "/1/enable"));
+        assert!(filters.accepts_path("com.test", "/1/value"));
+        assert!(!filters.accepts_path("com.test", "/1/other"));
+        assert!(filters.accepts_path("com.other", "/1/other"));
+
+        assert!(filters.accepts("com.test", "/1/value", &value(42)));
+        assert!(!filters.accepts("com.test", "/1/value", &value(1)));
+        assert!(filters.accepts("com.test", "/1/enable", &value(1)));
+        assert!(filters.accepts("com.other", "/1/value", &value(1)));
+    }
+
+    #[test]
+    fn test_event_filters_interface_wildcard() {
+        let mut filters = EventFilters::default();
+
+        filters.push(
+            EventFilter::new("com.test.sensors.*")
+                .path("/*/value")
+                .unwrap(),
+        );
+        filters.push(EventFilter::new("com.test.*.Config").path("/name").unwrap());
+
+        assert!(filters.accepts_path("com.test.sensors.Temperature", "/1/value"));
+        assert!(!filters.accepts_path("com.test.sensors.Temperature", "/1/other"));
+        assert!(filters.accepts_path("com.test.sensors.v2.Humidity", "/1/value"));
+        assert!(filters.accepts_path("com.test.Sensors", "/1/other"));
+
+        assert!(filters.accepts_path("com.test.device.Config", "/name"));
+        assert!(!filters.accepts_path("com.test.device.Config", "/other"));
+        assert!(filters.accepts_path("com.test.ConfigV2", "/other"));
+    }
+}
@@ -0,0 +1,346 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Handles bound to a single interface.
+//!
+//! A gateway can give each data source its own [`InterfaceHandle`], with a rate limit and a quota
+//! of the volatile retention, so a misbehaving source can't consume the buffers shared by the
+//! others. Each handle counts the messages it sent.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let sdk_options = AstarteOptions::new("_","_","_","_");
+//!     let device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+//!
+//!     let sensor = device
+//!         .interface_handle("com.example.Sensor")
+//!         .await
+//!         .unwrap()
+//!         .rate_limit(10.0, 20);
+//!     sensor.set_retention_quota(Some(100)).await;
+//!
+//!     sensor.send("/temperature", 21.5).await.unwrap();
+//!     println!("{:?}", sensor.stats());
+//! }
+//! ```
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::trace;
+
+use crate::{
+    database::AstarteDatabase, types::AstarteType, AstarteAggregate, AstarteDeviceSdk, Error,
+};
+
+/// Counters of the messages sent with an [`InterfaceHandle`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterfaceStats {
+    /// Messages sent successfully.
+    pub sent: u64,
+    /// Messages rejected by the rate limit.
+    pub rate_limited: u64,
+    /// Messages that couldn't be sent.
+    pub failed: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    sent: AtomicU64,
+    rate_limited: AtomicU64,
+    failed: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// Token bucket limiting the messages per second.
+#[derive(Debug)]
+pub(crate) struct RateLimit {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimit {
+    pub(crate) fn new(rate: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+
+        Self {
+            rate,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    pub(crate) fn acquire_at(&self, now: Instant) -> bool {
+        let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let elapsed = now.saturating_duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last = now;
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+
+        bucket.tokens -= 1.0;
+
+        true
+    }
+
+    /// Returns when the next token will be available, `None` if it never will.
+    pub(crate) fn next_token_at(&self) -> Option<Instant> {
+        let bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let missing = (1.0 - bucket.tokens).max(0.0);
+        let wait = Duration::try_from_secs_f64(missing / self.rate).ok()?;
+
+        bucket.last.checked_add(wait)
+    }
+}
+
+/// Lightweight handle to send data on a single interface.
+///
+/// The clones of a handle share the counters and the rate limit.
+pub struct InterfaceHandle<S: ?Sized = dyn AstarteDatabase + Sync + Send> {
+    device: AstarteDeviceSdk<S>,
+    interface: Arc<str>,
+    counters: Arc<Counters>,
+    rate_limit: Option<Arc<RateLimit>>,
+}
+
+// Manual implementation, since deriving it would require `S: Clone`
+impl<S: ?Sized> Clone for InterfaceHandle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            device: self.device.clone(),
+            interface: self.interface.clone(),
+            counters: self.counters.clone(),
+            rate_limit: self.rate_limit.clone(),
+        }
+    }
+}
+
+impl<S: ?Sized> Debug for InterfaceHandle<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InterfaceHandle")
+            .field("interface", &self.interface)
+            .field("counters", &self.counters)
+            .field("rate_limit", &self.rate_limit)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> InterfaceHandle<S>
+where
+    S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+{
+    pub(crate) fn new(device: AstarteDeviceSdk<S>, interface: &str) -> Self {
+        Self {
+            device,
+            interface: interface.into(),
+            counters: Arc::new(Counters::default()),
+            rate_limit: None,
+        }
+    }
+
+    /// Limit the messages sent to `rate` per second, allowing bursts of `burst` messages.
+    ///
+    /// The messages over the limit are rejected with an [`Error::RateLimited`].
+    pub fn rate_limit(mut self, rate: f64, burst: u32) -> Self {
+        self.rate_limit = Some(Arc::new(RateLimit::new(rate, burst)));
+
+        self
+    }
+
+    /// Limit the number of messages of the interface kept in the volatile retention, removing
+    /// the limit if `None`.
+    ///
+    /// When the quota is reached the oldest message of the interface is discarded, the messages
+    /// of the other interfaces are kept. The quota is shared by all the handles of the interface.
+    pub async fn set_retention_quota(&self, quota: Option<usize>) {
+        self.device
+            .volatile
+            .lock()
+            .await
+            .set_rows_quota(&self.interface, quota);
+    }
+
+    /// Returns the name of the interface.
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    /// Returns the counters of the messages sent with the handle and its clones.
+    pub fn stats(&self) -> InterfaceStats {
+        InterfaceStats {
+            sent: self.counters.sent.load(Ordering::Relaxed),
+            rate_limited: self.counters.rate_limited.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Send an individual datastream or property, see [`AstarteDeviceSdk::send`].
+    pub async fn send<D>(&self, interface_path: &str, data: D) -> Result<(), Error>
+    where
+        D: TryInto<AstarteType>,
+    {
+        self.acquire(interface_path)?;
+
+        let res = self
+            .device
+            .send(&self.interface, interface_path, data)
+            .await;
+
+        self.count(res)
+    }
+
+    /// Send an individual datastream with an explicit timestamp, see
+    /// [`AstarteDeviceSdk::send_with_timestamp`].
+    pub async fn send_with_timestamp<D>(
+        &self,
+        interface_path: &str,
+        data: D,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), Error>
+    where
+        D: TryInto<AstarteType>,
+    {
+        self.acquire(interface_path)?;
+
+        let res = self
+            .device
+            .send_with_timestamp(&self.interface, interface_path, data, timestamp)
+            .await;
+
+        self.count(res)
+    }
+
+    /// Send an object datastream, see [`AstarteDeviceSdk::send_object`].
+    pub async fn send_object<T>(&self, interface_path: &str, data: T) -> Result<(), Error>
+    where
+        T: AstarteAggregate,
+    {
+        self.acquire(interface_path)?;
+
+        let res = self
+            .device
+            .send_object(&self.interface, interface_path, data)
+            .await;
+
+        self.count(res)
+    }
+
+    /// Send an object datastream with an explicit timestamp, see
+    /// [`AstarteDeviceSdk::send_object_with_timestamp`].
+    pub async fn send_object_with_timestamp<T>(
+        &self,
+        interface_path: &str,
+        data: T,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), Error>
+    where
+        T: AstarteAggregate,
+    {
+        self.acquire(interface_path)?;
+
+        let res = self
+            .device
+            .send_object_with_timestamp(&self.interface, interface_path, data, timestamp)
+            .await;
+
+        self.count(res)
+    }
+
+    /// Unset a property, see [`AstarteDeviceSdk::unset`].
+    pub async fn unset(&self, interface_path: &str) -> Result<(), Error> {
+        self.acquire(interface_path)?;
+
+        let res = self.device.unset(&self.interface, interface_path).await;
+
+        self.count(res)
+    }
+
+    fn acquire(&self, interface_path: &str) -> Result<(), Error> {
+        let Some(rate_limit) = &self.rate_limit else {
+            return Ok(());
+        };
+
+        if rate_limit.acquire_at(Instant::now()) {
+            return Ok(());
+        }
+
+        trace!("rate limited message on {}{interface_path}", self.interface);
+
+        self.counters.rate_limited.fetch_add(1, Ordering::Relaxed);
+
+        Err(Error::RateLimited(self.interface.to_string()))
+    }
+
+    fn count(&self, res: Result<(), Error>) -> Result<(), Error> {
+        let counter = match res {
+            Ok(()) => &self.counters.sent,
+            Err(_) => &self.counters.failed,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let limit = RateLimit::new(2.0, 3);
+        let start = Instant::now();
+
+        assert!((0..3).all(|_| limit.acquire_at(start)));
+        assert!(!limit.acquire_at(start));
+
+        // a token every half second
+        let later = start + Duration::from_millis(600);
+        assert!(limit.acquire_at(later));
+        assert!(!limit.acquire_at(later));
+
+        // the bucket is never filled over the burst
+        let much_later = later + Duration::from_secs(60);
+        assert!((0..3).all(|_| limit.acquire_at(much_later)));
+        assert!(!limit.acquire_at(much_later));
+        assert_eq!(
+            limit.next_token_at(),
+            Some(much_later + Duration::from_millis(500))
+        );
+    }
+}
@@ -0,0 +1,267 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! History of the recent errors of the device.
+//!
+//! The errors returned while handling the events and sending the data are kept in a bounded
+//! buffer, so they can be reported by a diagnostics interface or a local UI, see
+//! [`recent_errors()`](crate::AstarteDeviceSdk::recent_errors).
+
+use std::collections::VecDeque;
+use std::error::Error as StdError;
+use std::fmt::Display;
+use std::sync::{Mutex, PoisonError};
+
+use chrono::{DateTime, Utc};
+
+use crate::error::PayloadOperation;
+use crate::Error;
+
+/// Default number of errors kept in the history.
+pub(crate) const DEFAULT_ERROR_HISTORY: usize = 20;
+
+/// Category of an error in the history.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Error of the MQTT connection.
+    Connection,
+    /// Error sending the data.
+    Send,
+    /// Error receiving the data, like an invalid payload or a rejected value.
+    Receive,
+    /// Error of the property store.
+    Store,
+    /// Invalid interface or configuration.
+    Config,
+    /// Any other error.
+    Other,
+}
+
+impl Display for ErrorCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let category = match self {
+            ErrorCategory::Connection => "connection",
+            ErrorCategory::Send => "send",
+            ErrorCategory::Receive => "receive",
+            ErrorCategory::Store => "store",
+            ErrorCategory::Config => "config",
+            ErrorCategory::Other => "other",
+        };
+
+        write!(f, "{category}")
+    }
+}
+
+impl ErrorCategory {
+    /// Categorize an error returned while sending or receiving.
+    fn new(error: &Error, sending: bool) -> Self {
+        match error {
+            Error::BsonClientError(_) | Error::ConnectionError(_) => ErrorCategory::Connection,
+            Error::DbError(_)
+            | Error::StoreFull { .. }
+            | Error::Journal(_)
+            | Error::PublishedLog(_) => ErrorCategory::Store,
+            Error::OptionsError(_)
+            | Error::Interface(_)
+            | Error::Registry(_)
+            | Error::Discovery(_)
+            | Error::IntrospectionMismatch(_) => ErrorCategory::Config,
+            Error::SendError(_)
+            | Error::RateLimited(_)
+            | Error::Collection(_)
+            | Error::PayloadTooLarge { .. }
+            | Error::Publish { .. }
+            | Error::InterfacePayload {
+                operation: PayloadOperation::Serialize,
+                ..
+            } => ErrorCategory::Send,
+            Error::ReceiveError(_)
+            | Error::InvalidTopic(_)
+            | Error::Properties(_)
+            | Error::ConstraintViolation { .. }
+            | Error::Transform { .. }
+            | Error::EventTooLarge { .. }
+            | Error::InterfacePayload { .. } => ErrorCategory::Receive,
+            Error::InvalidEndpoint(_) | Error::Types(_) | Error::Payload(_) if sending => {
+                ErrorCategory::Send
+            }
+            Error::InvalidEndpoint(_) | Error::Types(_) | Error::Payload(_) => {
+                ErrorCategory::Receive
+            }
+            _ => ErrorCategory::Other,
+        }
+    }
+}
+
+/// An error in the history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorRecord {
+    /// Time the error occurred.
+    pub timestamp: DateTime<Utc>,
+    pub category: ErrorCategory,
+    /// Message of the error, followed by the messages of its sources.
+    pub message: String,
+}
+
+impl ErrorRecord {
+    pub(crate) fn new(error: &Error, sending: bool) -> Self {
+        let mut message = error.to_string();
+
+        let mut source = error.source();
+        while let Some(err) = source {
+            message.push_str(": ");
+            message.push_str(&err.to_string());
+
+            source = err.source();
+        }
+
+        Self {
+            timestamp: Utc::now(),
+            category: ErrorCategory::new(error, sending),
+            message,
+        }
+    }
+}
+
+/// Bounded buffer of the most recent errors.
+#[derive(Debug)]
+pub(crate) struct ErrorHistory {
+    capacity: usize,
+    records: Mutex<VecDeque<ErrorRecord>>,
+}
+
+impl ErrorHistory {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    fn push(&self, record: ErrorRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if records.len() >= self.capacity {
+            records.pop_front();
+        }
+
+        records.push_back(record);
+    }
+
+    /// Records an error returned while handling the events.
+    pub(crate) fn received(&self, error: &Error) {
+        self.push(ErrorRecord::new(error, false));
+    }
+
+    /// Records an error returned while sending.
+    pub(crate) fn sent(&self, error: &Error) {
+        self.push(ErrorRecord::new(error, true));
+    }
+
+    /// Returns the errors from the oldest to the most recent.
+    pub(crate) fn records(&self) -> Vec<ErrorRecord> {
+        self.records
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for ErrorHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_ERROR_HISTORY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interface::mapping::path::MappingPath;
+
+    use super::*;
+
+    #[test]
+    fn test_error_history() {
+        let history = ErrorHistory::new(2);
+
+        history.sent(&Error::SendError("first".to_string()));
+        history.received(&Error::ConstraintViolation {
+            interface: "com.test".to_string(),
+            path: "/value".to_string(),
+            reason: "too big".to_string(),
+        });
+
+        let err = MappingPath::try_from("invalid").unwrap_err();
+        history.received(&Error::from(err));
+
+        let records = history.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].category, ErrorCategory::Receive);
+        assert_eq!(
+            records[0].message,
+            "value rejected on com.test/value: too big"
+        );
+        assert_eq!(records[1].category, ErrorCategory::Receive);
+        assert!(records[1].timestamp >= records[0].timestamp);
+
+        let history = ErrorHistory::new(0);
+        history.sent(&Error::SendError("dropped".to_string()));
+        assert!(history.records().is_empty());
+    }
+
+    #[test]
+    fn test_source_message() {
+        let err = Error::OptionsError(crate::options::OptionsError::ConfigError(
+            "bad url".to_string(),
+        ));
+
+        let record = ErrorRecord::new(&err, false);
+        assert_eq!(record.category, ErrorCategory::Config);
+        assert!(
+            record.message.starts_with("options error: "),
+            "{}",
+            record.message
+        );
+    }
+
+    #[test]
+    fn test_payload_context() {
+        let err = Error::payload("com.test", "/value", PayloadOperation::Deserialize)(
+            crate::payload::PayloadError::TooLarge(42),
+        );
+
+        let record = ErrorRecord::new(&err, false);
+        assert_eq!(record.category, ErrorCategory::Receive);
+        assert_eq!(
+            record.message,
+            "couldn't deserialize the payload on com.test/value: \
+            the payload of 42 bytes exceeds the maximum size of a BSON document"
+        );
+
+        let err = Error::payload("com.test", "/value", PayloadOperation::Serialize)(
+            crate::payload::PayloadError::TooLarge(42),
+        );
+        assert_eq!(ErrorRecord::new(&err, false).category, ErrorCategory::Send);
+    }
+}
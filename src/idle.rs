@@ -0,0 +1,341 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Idle power mode, closing the MQTT connection when there is no activity.
+//!
+//! The connection is closed after a period without messages sent or received, and opened again
+//! when a message is sent or periodically to receive the server data.
+
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use log::debug;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::database::AstarteDatabase;
+use crate::error::Error;
+use crate::{shutdown, AstarteDeviceSdk};
+
+/// Configuration of the idle mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IdleConfig {
+    /// Period without activity after which the connection is closed.
+    pub(crate) timeout: Duration,
+    /// Interval to reconnect and poll the server data, if any.
+    pub(crate) wakeup: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Connected,
+    /// The disconnection was requested, waiting for the connection to be closed.
+    Disconnecting,
+    Disconnected,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: State,
+    last_activity: Instant,
+}
+
+/// State of the connection in idle mode.
+#[derive(Debug)]
+pub(crate) struct IdleMode {
+    config: IdleConfig,
+    inner: Mutex<Inner>,
+    wakeup: Notify,
+}
+
+impl IdleMode {
+    pub(crate) fn new(config: IdleConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner {
+                state: State::Connected,
+                last_activity: Instant::now(),
+            }),
+            wakeup: Notify::new(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        // the state is always valid, since it's only assigned while locked
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Record a message sent or received, waking up the connection if closed.
+    pub(crate) fn activity(&self) {
+        let mut inner = self.lock();
+
+        inner.last_activity = Instant::now();
+
+        if inner.state != State::Connected {
+            self.wakeup.notify_one();
+        }
+    }
+
+    /// Instant at which the connection will be idle, if connected.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        let inner = self.lock();
+
+        (inner.state == State::Connected).then(|| inner.last_activity + self.config.timeout)
+    }
+
+    /// Check if the connection is idle, in that case the state changes to disconnecting and
+    /// the connection should be closed.
+    pub(crate) fn should_disconnect(&self) -> bool {
+        let mut inner = self.lock();
+
+        if inner.state != State::Connected || inner.last_activity.elapsed() < self.config.timeout {
+            return false;
+        }
+
+        inner.state = State::Disconnecting;
+
+        true
+    }
+
+    /// Returns true if the disconnection was requested, the connection errors are expected.
+    pub(crate) fn is_disconnecting(&self) -> bool {
+        self.lock().state == State::Disconnecting
+    }
+
+    /// The connection was closed.
+    pub(crate) fn disconnected(&self) {
+        self.lock().state = State::Disconnected;
+    }
+
+    /// Wait until a message is sent or the wakeup interval elapsed if the connection is closed.
+    pub(crate) async fn wait_wakeup(&self) {
+        if self.lock().state != State::Disconnected {
+            return;
+        }
+
+        match self.config.wakeup {
+            Some(interval) => {
+                tokio::select! {
+                    _ = self.wakeup.notified() => {},
+                    _ = tokio::time::sleep(interval) => {},
+                }
+            }
+            None => self.wakeup.notified().await,
+        }
+
+        let mut inner = self.lock();
+        inner.state = State::Connected;
+        inner.last_activity = Instant::now();
+    }
+}
+
+impl<S> AstarteDeviceSdk<S>
+where
+    S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+{
+    /// Waits for the wakeup of the idle connection, and closes the connection once it's idle.
+    ///
+    /// Returns the deadline of the shutdown if the signal is received while waiting.
+    pub(crate) async fn check_idle(&self) -> Result<Option<Duration>, Error> {
+        let Some(idle) = &self.idle else {
+            return Ok(None);
+        };
+
+        let woken = shutdown::until_signal(self.shutdown_signal.clone(), idle.wait_wakeup()).await;
+
+        if let Err(deadline) = woken {
+            return Ok(Some(deadline));
+        }
+
+        if idle.should_disconnect() {
+            debug!("connection idle, disconnecting");
+
+            // the connection is opened again without the reconnect delay
+            self.transport.request_reconnect();
+            self.client.disconnect().await?;
+        }
+
+        Ok(None)
+    }
+
+    /// Records the connection closed, returns true if it was closed by the idle mode.
+    pub(crate) fn idle_disconnected(&self) -> bool {
+        match &self.idle {
+            Some(idle) if idle.is_disconnecting() => {
+                idle.disconnected();
+
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the instant the connection becomes idle, if the idle mode is enabled.
+    pub(crate) fn idle_deadline(&self) -> Option<Instant> {
+        self.idle.as_ref().and_then(|idle| idle.deadline())
+    }
+
+    /// Record the activity on the connection for the idle mode.
+    pub(crate) fn idle_activity(&self) {
+        if let Some(idle) = &self.idle {
+            idle.activity();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use rumqttc::Event;
+
+    use super::*;
+    use crate::mock::{MockAsyncClient, MockDevice, MockEventLoop};
+    use crate::test::{INDIVIDUAL_SERVER_DATASTREAM, VOLATILE_DATASTREAM};
+    use crate::Interface;
+
+    fn idle(wakeup: Option<Duration>) -> IdleMode {
+        IdleMode::new(IdleConfig {
+            timeout: Duration::from_millis(10),
+            wakeup,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_idle_disconnect() {
+        let idle = idle(None);
+
+        assert!(!idle.should_disconnect());
+        assert!(idle.deadline().is_some());
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert!(idle.should_disconnect());
+        assert!(idle.is_disconnecting());
+        assert!(idle.deadline().is_none());
+        // only once
+        assert!(!idle.should_disconnect());
+
+        idle.disconnected();
+        idle.activity();
+
+        // woken up by the activity
+        tokio::time::timeout(Duration::from_secs(1), idle.wait_wakeup())
+            .await
+            .unwrap();
+
+        assert!(!idle.should_disconnect());
+        assert!(idle.deadline().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_idle_wakeup_interval() {
+        let idle = idle(Some(Duration::from_millis(10)));
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert!(idle.should_disconnect());
+        idle.disconnected();
+
+        tokio::time::timeout(Duration::from_secs(1), idle.wait_wakeup())
+            .await
+            .unwrap();
+
+        assert!(!idle.is_disconnecting());
+        assert!(idle.deadline().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_device_idle_disconnect() {
+        let mut eventloope = MockEventLoop::default();
+        let mut seq = mockall::Sequence::new();
+
+        eventloope
+            .expect_poll()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|| {
+                std::thread::sleep(Duration::from_millis(30));
+
+                Ok(Event::Outgoing(rumqttc::Outgoing::PingReq))
+            });
+        eventloope
+            .expect_poll()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|| Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)));
+        eventloope
+            .expect_poll()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|| {
+                Err(rumqttc::ConnectionError::Io(std::io::Error::from(
+                    std::io::ErrorKind::ConnectionAborted,
+                )))
+            });
+        eventloope
+            .expect_poll()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|| {
+                Ok(Event::Incoming(rumqttc::Packet::Publish(
+                    rumqttc::Publish::new(
+                        "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/1/intensity",
+                        rumqttc::QoS::AtLeastOnce,
+                        bson::to_vec(&bson::doc! { "v": 4.2 }).unwrap(),
+                    ),
+                )))
+            });
+
+        let mut client = MockAsyncClient::default();
+        client.expect_disconnect().once().returning(|| Ok(()));
+        client.expect_clone().once().returning(|| {
+            let mut client = MockAsyncClient::default();
+            client
+                .expect_publish::<String, Vec<u8>>()
+                .once()
+                .returning(|_, _, _, _| Ok(()));
+
+            client
+        });
+
+        let mut astarte = MockDevice::new(client, eventloope)
+            .interfaces([
+                Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap(),
+                Interface::from_str(VOLATILE_DATASTREAM).unwrap(),
+            ])
+            .options(|opts| opts.idle_disconnect(Duration::from_millis(10), None))
+            .build();
+
+        // sending a message wakes up the idle connection
+        let sender = astarte.clone();
+        let send = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            sender
+                .send("org.astarte-platform.test.VolatileDatastream", "/value", 1)
+                .await
+                .unwrap();
+        });
+
+        let event = astarte.handle_events().await.unwrap();
+        assert_eq!(event.path, "/1/intensity");
+
+        send.await.unwrap();
+    }
+}
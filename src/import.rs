@@ -0,0 +1,277 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bulk import of the device-owned properties, to pre-provision a device with large tables of
+//! values like the calibration data written in the factory.
+//!
+//! [`import_properties()`](crate::AstarteDeviceSdk::import_properties) validates every entry
+//! against the interfaces of the device, the invalid ones are reported in the
+//! [`ImportReport`] and skipped. The valid entries are stored in a single call of
+//! [`AstarteDatabase::store_props()`](crate::database::AstarteDatabase::store_props), with the
+//! sqlite database either all of them are stored or none of them, so a storage error is returned
+//! and nothing is imported.
+//!
+//! The stored properties are then published. The ones not published because of a connection
+//! error are already stored, so they are sent with the other device-owned properties on the next
+//! connection without a session.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{import::ImportProgress, AstarteDeviceSdk};
+//!
+//! async fn provision(device: &AstarteDeviceSdk, calibration: &[(u32, f64)]) {
+//!     let entries = calibration.iter().map(|(point, value)| {
+//!         ("com.example.Calibration", format!("/{point}/offset"), *value)
+//!     });
+//!
+//!     let report = device
+//!         .import_properties_with_progress(entries, |progress| {
+//!             if let ImportProgress::Published { published, total } = progress {
+//!                 println!("published {published}/{total}");
+//!             }
+//!         })
+//!         .await
+//!         .unwrap();
+//!
+//!     for failure in report.failed {
+//!         println!("invalid {}{}: {}", failure.interface, failure.path, failure.error);
+//!     }
+//! }
+//! ```
+
+use crate::database::PropWrite;
+use crate::encryption::{EncryptionError, PayloadEncryption};
+use crate::interface::mapping::path::{MappingError, MappingPath};
+use crate::interface::Ownership;
+use crate::interfaces::Interfaces;
+use crate::types::AstarteType;
+
+/// Reason an entry couldn't be imported.
+#[non_exhaustive]
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("the property interface doesn't exist")]
+    InterfaceNotFound,
+    #[error("the property is owned by the server")]
+    ServerOwned,
+    #[error("invalid path")]
+    InvalidPath(#[from] MappingError),
+    #[error("the mapping doesn't exist")]
+    MappingNotFound,
+    #[error("couldn't convert the value")]
+    Conversion,
+    #[error("the value can't be unset")]
+    Unset,
+    #[error("the value doesn't match the type of the mapping")]
+    WrongType,
+    #[error("couldn't encrypt the value")]
+    Encryption(#[from] EncryptionError),
+}
+
+/// Entry that couldn't be imported.
+#[derive(Debug)]
+pub struct ImportFailure {
+    /// Position of the entry in the imported entries.
+    pub index: usize,
+    pub interface: String,
+    pub path: String,
+    pub error: ImportError,
+}
+
+/// Result of an import, see [`import_properties()`](crate::AstarteDeviceSdk::import_properties).
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// Number of properties stored.
+    pub stored: usize,
+    /// Number of stored properties published, the others are sent on the next connection.
+    pub published: usize,
+    /// Invalid entries, not stored.
+    pub failed: Vec<ImportFailure>,
+}
+
+impl ImportReport {
+    /// Returns `true` if all the entries were stored and published.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.published == self.stored
+    }
+}
+
+/// Progress of an import, reported to the callback of
+/// [`import_properties_with_progress()`](crate::AstarteDeviceSdk::import_properties_with_progress).
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportProgress {
+    /// All the entries were validated.
+    Validated { valid: usize, failed: usize },
+    /// The valid entries were stored.
+    Stored { stored: usize },
+    /// A stored property was published.
+    Published { published: usize, total: usize },
+}
+
+/// Validates an entry against the device-owned properties, returns the property to store.
+pub(crate) fn validate<D>(
+    interfaces: &Interfaces,
+    encryption: &PayloadEncryption,
+    interface_name: &str,
+    path: &str,
+    value: D,
+) -> Result<PropWrite, ImportError>
+where
+    D: TryInto<AstarteType>,
+{
+    let property = interfaces
+        .get_property(interface_name)
+        .ok_or(ImportError::InterfaceNotFound)?;
+
+    if property.ownership() != Ownership::Device {
+        return Err(ImportError::ServerOwned);
+    }
+
+    let mapping_path = MappingPath::try_from(path)?;
+    let mapping = property
+        .mapping(&mapping_path)
+        .ok_or(ImportError::MappingNotFound)?;
+
+    let value = value.try_into().map_err(|_| ImportError::Conversion)?;
+    if value == AstarteType::Unset {
+        return Err(ImportError::Unset);
+    }
+
+    let value = encryption.encrypt(interface_name, path, value)?;
+
+    if value != mapping.mapping_type() || Interfaces::validate_float(&value).is_err() {
+        return Err(ImportError::WrongType);
+    }
+
+    Ok(PropWrite {
+        interface: interface_name.to_string(),
+        path: path.to_string(),
+        value,
+        interface_major: property.version_major(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::Interface;
+
+    const CALIBRATION: &str = r#"
+    {
+        "interface_name": "com.test.Calibration",
+        "version_major": 1,
+        "version_minor": 0,
+        "type": "properties",
+        "ownership": "device",
+        "mappings": [
+            {
+                "endpoint": "/%{point}/offset",
+                "type": "double"
+            }
+        ]
+    }
+    "#;
+
+    const SERVER: &str = r#"
+    {
+        "interface_name": "com.test.Server",
+        "version_major": 1,
+        "version_minor": 0,
+        "type": "properties",
+        "ownership": "server",
+        "mappings": [
+            {
+                "endpoint": "/value",
+                "type": "integer"
+            }
+        ]
+    }
+    "#;
+
+    fn interfaces() -> Interfaces {
+        Interfaces::from([
+            Interface::from_str(CALIBRATION).unwrap(),
+            Interface::from_str(SERVER).unwrap(),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_import() {
+        let interfaces = interfaces();
+        let encryption = PayloadEncryption::default();
+        let validate = |interface, path, value: AstarteType| {
+            validate(&interfaces, &encryption, interface, path, value)
+        };
+
+        let prop = validate(
+            "com.test.Calibration",
+            "/1/offset",
+            AstarteType::Double(0.5),
+        )
+        .unwrap();
+        assert_eq!(
+            prop,
+            PropWrite {
+                interface: "com.test.Calibration".to_string(),
+                path: "/1/offset".to_string(),
+                value: AstarteType::Double(0.5),
+                interface_major: 1,
+            }
+        );
+
+        assert!(matches!(
+            validate("com.test.Missing", "/1/offset", AstarteType::Double(0.5)),
+            Err(ImportError::InterfaceNotFound)
+        ));
+        assert!(matches!(
+            validate("com.test.Server", "/value", AstarteType::Integer(1)),
+            Err(ImportError::ServerOwned)
+        ));
+        assert!(matches!(
+            validate("com.test.Calibration", "offset", AstarteType::Double(0.5)),
+            Err(ImportError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            validate("com.test.Calibration", "/1/gain", AstarteType::Double(0.5)),
+            Err(ImportError::MappingNotFound)
+        ));
+        assert!(matches!(
+            validate("com.test.Calibration", "/1/offset", AstarteType::Unset),
+            Err(ImportError::Unset)
+        ));
+        assert!(matches!(
+            validate(
+                "com.test.Calibration",
+                "/1/offset",
+                AstarteType::String("0.5".to_string())
+            ),
+            Err(ImportError::WrongType)
+        ));
+        assert!(matches!(
+            validate(
+                "com.test.Calibration",
+                "/1/offset",
+                AstarteType::Double(f64::NAN)
+            ),
+            Err(ImportError::WrongType)
+        ));
+    }
+}
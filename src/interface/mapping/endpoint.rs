@@ -24,7 +24,6 @@ use std::{
     fmt::Display,
     ops::Deref,
     slice::Iter as SliceIter,
-    unreachable,
 };
 
 use itertools::{EitherOrBoth, Itertools};
@@ -64,17 +63,6 @@ impl<'a> Endpoint<'a> {
         Endpoint { path, levels }
     }
 
-    /// Clone an owned endpoint, trying to be smart with allocations
-    pub(crate) fn clone_owned(&self) -> Endpoint<'static> {
-        let path = self.path.clone().into_owned();
-        let levels = self.levels.iter().map(Level::clone_owned).collect();
-
-        Endpoint {
-            path: path.into(),
-            levels,
-        }
-    }
-
     pub(crate) fn iter(&self) -> SliceIter<Level> {
         self.levels.iter()
     }
@@ -188,10 +176,6 @@ impl<'a> Level<'a> {
             Self::Parameter(_) => Ordering::Equal,
         }
     }
-
-    pub(crate) fn clone_owned(&self) -> Level<'static> {
-        self.clone().into_owned()
-    }
 }
 
 impl<'a> Display for Level<'a> {
@@ -259,6 +243,8 @@ pub enum LevelError {
     MQTTWildcard(char),
     #[error("the parameter should incapsulate the whole level")]
     Parameter,
+    #[error("levels must not contain the separator '/'")]
+    Separator,
 }
 
 /// Parses an interface endpoint with the following grammar:
@@ -352,7 +338,8 @@ fn parse_simple(input: &str) -> Result<&str, LevelError> {
             '%' if Some('{') == chars.peek().copied() => {
                 return Err(LevelError::Parameter);
             }
-            '/' => unreachable!("level shouldn't contain '/' since it is used as separator"),
+            // the level shouldn't contain '/' since it is used as separator
+            '/' => return Err(LevelError::Separator),
             _ => {
                 trace!("level char: {}", chr)
             }
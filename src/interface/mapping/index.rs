@@ -0,0 +1,305 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Index of the mappings of an interface by endpoint.
+
+use std::{borrow::Borrow, collections::HashMap, slice::Iter as SliceIter};
+
+use super::{
+    endpoint::{Endpoint, Level},
+    path::MappingPath,
+    BaseMapping,
+};
+
+/// Mappings of an interface, indexed by endpoint.
+///
+/// The mappings are stored in insertion order, and indexed with a tree of the endpoint levels.
+/// A parameter level is a single child node which matches any level, so resolving a path costs
+/// a lookup per level instead of comparing the path with the endpoints of the interface.
+///
+/// For example, with the following mappings:
+///
+/// - `/a/b/c`
+/// - `/a/%{p}/d`
+///
+/// The path `/a/b/d` is resolved to the second mapping, since the simple levels are checked
+/// first and the parameters are tried when they don't match.
+#[derive(Debug, Clone)]
+pub(crate) struct MappingMap<T> {
+    mappings: Vec<T>,
+    index: Node,
+}
+
+impl<T> MappingMap<T>
+where
+    T: Borrow<BaseMapping>,
+{
+    pub(crate) fn new() -> Self {
+        Self {
+            mappings: Vec::new(),
+            index: Node::default(),
+        }
+    }
+
+    /// Returns the mapping for the path.
+    ///
+    /// A path received from MQTT is matched against the parameters of the endpoints, while an
+    /// endpoint needs to have the parameters in the same levels.
+    pub(crate) fn get(&self, path: &MappingPath<'_>) -> Option<&T> {
+        let idx = match path {
+            MappingPath::Mapping { levels, .. } => self.index.find(levels),
+            MappingPath::Endpoint(endpoint) => self.index.find_endpoint(&endpoint.levels),
+        }?;
+
+        self.mappings.get(idx)
+    }
+
+    pub(crate) fn contains_key(&self, path: &MappingPath<'_>) -> bool {
+        self.get(path).is_some()
+    }
+
+    /// Returns a mapping that would match the same paths of the endpoint.
+    ///
+    /// Since a parameter is equal to any level, the endpoints `/a/b` and `/%{p}/b` overlap.
+    pub(crate) fn get_overlapping(&self, endpoint: &Endpoint<'_>) -> Option<&T> {
+        self.index
+            .find_overlapping(&endpoint.levels)
+            .and_then(|idx| self.mappings.get(idx))
+    }
+
+    /// Insert a new mapping, replacing the one with the same endpoint.
+    ///
+    /// The caller should check the mapping doesn't overlap with another one with
+    /// [`MappingMap::get_overlapping`].
+    pub(crate) fn insert(&mut self, mapping: T) {
+        let endpoint = &mapping.borrow().endpoint;
+
+        let node = endpoint
+            .levels
+            .iter()
+            .fold(&mut self.index, |node, level| node.child(level));
+
+        match node.value.and_then(|idx| self.mappings.get_mut(idx)) {
+            Some(current) => *current = mapping,
+            None => {
+                node.value = Some(self.mappings.len());
+                self.mappings.push(mapping);
+            }
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.mappings.len()
+    }
+
+    pub(crate) fn values(&self) -> SliceIter<'_, T> {
+        self.mappings.iter()
+    }
+}
+
+impl<T> Default for MappingMap<T>
+where
+    T: Borrow<BaseMapping>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Two maps are equal if they contain the same mappings, regardless of the insertion order.
+impl<T> PartialEq for MappingMap<T>
+where
+    T: Borrow<BaseMapping> + PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.values().all(|mapping| {
+                other
+                    .index
+                    .find_endpoint(&mapping.borrow().endpoint.levels)
+                    .and_then(|idx| other.mappings.get(idx))
+                    == Some(mapping)
+            })
+    }
+}
+
+impl<T> Eq for MappingMap<T> where T: Borrow<BaseMapping> + Eq {}
+
+/// Level of the endpoints tree, the value is the index of the mapping ending in this node.
+#[derive(Debug, Clone, Default)]
+struct Node {
+    simple: HashMap<String, Node>,
+    parameter: Option<Box<Node>>,
+    value: Option<usize>,
+}
+
+impl Node {
+    fn child(&mut self, level: &Level<'_>) -> &mut Node {
+        match level {
+            Level::Simple(level) => self.simple.entry(level.to_string()).or_default(),
+            Level::Parameter(_) => self.parameter.get_or_insert_with(Default::default),
+        }
+    }
+
+    fn find(&self, levels: &[&str]) -> Option<usize> {
+        let Some((level, rest)) = levels.split_first() else {
+            return self.value;
+        };
+
+        self.simple
+            .get(*level)
+            .and_then(|node| node.find(rest))
+            .or_else(|| self.parameter.as_ref().and_then(|node| node.find(rest)))
+    }
+
+    fn find_endpoint(&self, levels: &[Level<'_>]) -> Option<usize> {
+        let Some((level, rest)) = levels.split_first() else {
+            return self.value;
+        };
+
+        let node = match level {
+            Level::Simple(level) => self.simple.get(level.as_ref()),
+            Level::Parameter(_) => self.parameter.as_deref(),
+        }?;
+
+        node.find_endpoint(rest)
+    }
+
+    fn find_overlapping(&self, levels: &[Level<'_>]) -> Option<usize> {
+        let Some((level, rest)) = levels.split_first() else {
+            return self.value;
+        };
+
+        let simple = match level {
+            Level::Simple(level) => self
+                .simple
+                .get(level.as_ref())
+                .and_then(|node| node.find_overlapping(rest)),
+            Level::Parameter(_) => self
+                .simple
+                .values()
+                .find_map(|node| node.find_overlapping(rest)),
+        };
+
+        simple.or_else(|| {
+            self.parameter
+                .as_ref()
+                .and_then(|node| node.find_overlapping(rest))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interface::MappingType;
+
+    use super::*;
+
+    fn base_mapping(endpoint: &str) -> BaseMapping {
+        BaseMapping {
+            endpoint: Endpoint::try_from(endpoint).unwrap().into_owned(),
+            mapping_type: MappingType::Integer,
+            description: None,
+            doc: None,
+        }
+    }
+
+    fn path(path: &str) -> MappingPath<'_> {
+        MappingPath::try_from(path).unwrap()
+    }
+
+    fn mapping_map(endpoints: &[&str]) -> MappingMap<BaseMapping> {
+        let mut map = MappingMap::new();
+
+        for endpoint in endpoints {
+            map.insert(base_mapping(endpoint));
+        }
+
+        map
+    }
+
+    #[test]
+    fn test_get_simple_before_parameter() {
+        let map = mapping_map(&["/a/b/c", "/a/%{p}/d", "/a/%{p}/c"]);
+
+        let endpoint = |p| map.get(&path(p)).unwrap().endpoint().to_string();
+
+        assert_eq!(endpoint("/a/b/c"), "/a/b/c");
+        assert_eq!(endpoint("/a/b/d"), "/a/%{p}/d");
+        assert_eq!(endpoint("/a/x/c"), "/a/%{p}/c");
+        assert!(map.get(&path("/a/b")).is_none());
+        assert!(map.get(&path("/a/b/c/d")).is_none());
+        assert!(map.get(&path("/b/b/c")).is_none());
+    }
+
+    #[test]
+    fn test_get_endpoint() {
+        let map = mapping_map(&["/a/b/c", "/a/%{p}/d"]);
+
+        let endpoint = |e| MappingPath::Endpoint(Endpoint::try_from(e).unwrap());
+
+        assert!(map.contains_key(&endpoint("/a/b/c")));
+        assert!(map.contains_key(&endpoint("/a/%{other}/d")));
+        assert!(!map.contains_key(&endpoint("/a/b/d")));
+        assert!(!map.contains_key(&endpoint("/a/%{p}/c")));
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let map = mapping_map(&["/a/b/c", "/%{p}/%{q}/d"]);
+
+        let overlapping = |e| {
+            map.get_overlapping(&Endpoint::try_from(e).unwrap())
+                .map(|m| m.endpoint().to_string())
+        };
+
+        assert_eq!(overlapping("/a/%{p}/c").as_deref(), Some("/a/b/c"));
+        assert_eq!(overlapping("/x/y/d").as_deref(), Some("/%{p}/%{q}/d"));
+        assert_eq!(overlapping("/a/b/%{p}").as_deref(), Some("/a/b/c"));
+        assert_eq!(overlapping("/a/b/e"), None);
+        assert_eq!(overlapping("/a/b"), None);
+    }
+
+    #[test]
+    fn test_eq_ignores_order() {
+        let first = mapping_map(&["/a/b", "/%{p}/c"]);
+        let second = mapping_map(&["/%{p}/c", "/a/b"]);
+        let other = mapping_map(&["/a/b", "/%{p}/d"]);
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_many_mappings() {
+        let endpoints: Vec<String> = (0..500)
+            .map(|i| format!("/%{{sensor_id}}/value{i}"))
+            .collect();
+        let endpoints: Vec<&str> = endpoints.iter().map(String::as_str).collect();
+
+        let map = mapping_map(&endpoints);
+        assert_eq!(map.len(), 500);
+
+        for (i, endpoint) in endpoints.iter().enumerate() {
+            let p = format!("/sensor/value{i}");
+            let mapping = map.get(&path(&p)).unwrap();
+
+            assert_eq!(*mapping.endpoint(), **endpoint);
+        }
+    }
+}
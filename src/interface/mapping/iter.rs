@@ -18,13 +18,11 @@
 
 //! Iterators over an interface mappings mappings.
 
-use std::{collections::btree_map::Values, iter::FusedIterator};
+use std::{iter::FusedIterator, slice::Iter as ValuesIter};
 
-use crate::interface::{DatastreamObject, InterfaceType, Mapping, MappingMap, MappingPath};
+use crate::interface::{DatastreamObject, InterfaceType, Mapping};
 
-use super::{BaseMapping, DatastreamIndividualMapping, PropertiesMapping};
-
-type ValuesIter<'a, T> = Values<'a, MappingPath<'a>, T>;
+use super::{index::MappingMap, BaseMapping, DatastreamIndividualMapping, PropertiesMapping};
 
 pub(crate) enum MappingIter<'a> {
     Properties(PropertiesMappingIter<'a>),
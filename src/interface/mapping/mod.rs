@@ -23,6 +23,7 @@ use self::endpoint::Endpoint;
 use super::{DatabaseRetention, InterfaceError, Mapping, MappingType, Reliability, Retention};
 
 pub mod endpoint;
+pub mod index;
 pub mod iter;
 pub mod path;
 
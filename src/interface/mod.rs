@@ -28,7 +28,6 @@ pub(crate) mod validation;
 use log::info;
 use serde::{Deserialize, Serialize};
 
-use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::fs;
 use std::path::Path;
@@ -41,6 +40,7 @@ pub use self::error::InterfaceError;
 use self::{
     def::{DatabaseRetentionPolicyDef, InterfaceDef, RetentionDef},
     mapping::{
+        index::MappingMap,
         iter::{IndividualMappingIter, MappingIter, ObjectMappingIter, PropertiesMappingIter},
         path::MappingPath,
         BaseMapping, DatastreamIndividualMapping, PropertiesMapping,
@@ -48,27 +48,12 @@ use self::{
     validation::VersionChange,
 };
 
-/// Mapping between the endpoint and the path
-///
-/// The mappings are stored in a BTree so we can implement a custom compare of the endpoint which
-/// will make a placeholder equal to any level. The [HashSet](std::collections::HashSet) would not
-/// work since we cannot hash the placeholder to the same value as a simple level.
-///
-/// For example, if we have the following mappings:
-///
-/// - `/a/b/c`
-/// - `/a/%{p}/c`
-///
-/// They should be considered the same endpoint, but we cannot hash those to the same HashSet key,
-/// so we use a [`BTreeMap`] and implement a custom [`Ord`] for the [`MappingPath`]. Which is an enum to
-/// compare the parsed endpoint with parameters and the mapping path of a topic received from MQTT.
-///
-/// A mappings can be accessed by passing the endpoint to the [`Interface::mapping`] method.
-pub(crate) type MappingMap<'a, T> = BTreeMap<MappingPath<'a>, T>;
-
 /// Astarte interface implementation.
 ///
 /// Should be used only through its methods, not instantiated directly.
+///
+/// Two interfaces are equal if they have the same definition, regardless of the order of the
+/// mappings and of the fields set to their default value.
 #[derive(Deserialize, Debug, PartialEq, Clone)]
 #[serde(try_from = "InterfaceDef")]
 pub struct Interface {
@@ -105,7 +90,7 @@ impl Interface {
     }
 
     /// Returns the interface version.
-    fn version(&self) -> (i32, i32) {
+    pub(crate) fn version(&self) -> (i32, i32) {
         (self.version_major, self.version_minor)
     }
 
@@ -134,14 +119,57 @@ impl Interface {
         }
     }
 
+    /// Returns the short description of the interface.
     pub fn description(&self) -> Option<&str> {
         self.description.as_deref()
     }
 
+    /// Returns the documentation of the interface.
     pub fn doc(&self) -> Option<&str> {
         self.doc.as_deref()
     }
 
+    /// Returns the description and documentation of the mapping matching the path.
+    ///
+    /// The path is matched against the parametric endpoints of the mappings, so the
+    /// documentation can be shown next to the values received or sent on the path.
+    ///
+    /// ```
+    /// use std::str::FromStr;
+    ///
+    /// use astarte_device_sdk::Interface;
+    ///
+    /// let interface = Interface::from_str(r#"{
+    ///     "interface_name": "com.example.Sensor",
+    ///     "version_major": 0,
+    ///     "version_minor": 1,
+    ///     "type": "datastream",
+    ///     "ownership": "device",
+    ///     "mappings": [{
+    ///         "endpoint": "/%{sensor_id}/value",
+    ///         "type": "double",
+    ///         "description": "Temperature in Celsius"
+    ///     }]
+    /// }"#).unwrap();
+    ///
+    /// let docs = interface.mapping_docs("/kitchen/value").unwrap();
+    /// assert_eq!(docs.endpoint, "/%{sensor_id}/value");
+    /// assert_eq!(docs.description.as_deref(), Some("Temperature in Celsius"));
+    /// assert_eq!(docs.doc, None);
+    /// ```
+    pub fn mapping_docs(&self, path: &str) -> Option<MappingDocs> {
+        let path = MappingPath::try_from(path).ok()?;
+
+        self.mapping(&path)
+            .map(|mapping| MappingDocs::from(&mapping))
+    }
+
+    /// Returns the description and documentation of all the mappings.
+    pub fn iter_mapping_docs(&self) -> impl Iterator<Item = MappingDocs> + '_ {
+        self.iter_mappings()
+            .map(|mapping| MappingDocs::from(&mapping))
+    }
+
     pub(crate) fn iter_mappings(&self) -> MappingIter {
         MappingIter::new(&self.inner)
     }
@@ -238,6 +266,41 @@ impl Interface {
                 self
             })
     }
+
+    /// Returns the JSON of the interface in a normalized form, to diff or hash the interfaces.
+    ///
+    /// The JSON is compact, with the keys of the objects sorted, the mappings sorted by endpoint
+    /// and the fields set to their default value omitted. Two [equal](PartialEq) interfaces have
+    /// the same canonical JSON.
+    pub fn to_json_canonical(&self) -> Result<String, serde_json::Error> {
+        let mut def = InterfaceDef::from(self);
+        def.mappings.sort_by(|a, b| a.endpoint.cmp(b.endpoint));
+
+        let value = serde_json::to_value(def)?;
+
+        Ok(canonical_value(value).to_string())
+    }
+}
+
+/// Sorts the keys of the objects, also with the `preserve_order` feature of `serde_json`.
+fn canonical_value(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, canonical_value(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(canonical_value).collect())
+        }
+        value => value,
+    }
 }
 
 impl Serialize for Interface {
@@ -266,6 +329,27 @@ impl Display for Interface {
     }
 }
 
+/// Description and documentation of a mapping, see [`Interface::mapping_docs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappingDocs {
+    /// Endpoint of the mapping, with the parameters.
+    pub endpoint: String,
+    /// Short description of the mapping.
+    pub description: Option<String>,
+    /// Documentation of the mapping.
+    pub doc: Option<String>,
+}
+
+impl From<&Mapping<'_>> for MappingDocs {
+    fn from(value: &Mapping<'_>) -> Self {
+        Self {
+            endpoint: value.endpoint().to_string(),
+            description: value.description().map(ToString::to_string),
+            doc: value.doc().map(ToString::to_string),
+        }
+    }
+}
+
 /// Enum of all the types of interfaces
 /// This is not a direct representation of only the mapping to permit extensibility of specific
 /// properties present only in some aggregations.
@@ -278,7 +362,7 @@ pub(crate) enum InterfaceType {
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub(crate) struct DatastreamIndividual {
-    mappings: MappingMap<'static, DatastreamIndividualMapping>,
+    mappings: MappingMap<DatastreamIndividualMapping>,
 }
 
 impl DatastreamIndividual {
@@ -293,16 +377,14 @@ impl DatastreamIndividual {
     }
 
     pub fn add(&mut self, mapping: DatastreamIndividualMapping) -> Result<(), InterfaceError> {
-        let path = mapping.endpoint().clone().into_owned().into();
-
-        if let Some(existing) = self.mappings.get(&path) {
+        if let Some(existing) = self.mappings.get_overlapping(mapping.endpoint()) {
             return Err(InterfaceError::DuplicateMapping {
                 endpoint: existing.endpoint().to_string(),
                 duplicate: mapping.endpoint().to_string(),
             });
         }
 
-        self.mappings.insert(path, mapping);
+        self.mappings.insert(mapping);
 
         Ok(())
     }
@@ -328,7 +410,7 @@ impl TryFrom<&InterfaceDef<'_>> for DatastreamIndividual {
 
     fn try_from(value: &InterfaceDef) -> Result<Self, Self::Error> {
         let mut individual = Self {
-            mappings: BTreeMap::new(),
+            mappings: MappingMap::new(),
         };
 
         for mapping in value.mappings.iter() {
@@ -345,7 +427,7 @@ pub(crate) struct DatastreamObject {
     explicit_timestamp: bool,
     retention: Retention,
     database_retention: DatabaseRetention,
-    mappings: MappingMap<'static, BaseMapping>,
+    mappings: MappingMap<BaseMapping>,
 }
 
 impl DatastreamObject {
@@ -397,24 +479,22 @@ impl DatastreamObject {
         }
 
         // Check if the first element exists
-        if let Some((_, entry)) = self.mappings.iter().next() {
+        if let Some(entry) = self.mappings.values().next() {
             // Check that the mapping has the same endpoint as the other mappings
             if !entry.endpoint().eq_till_last(mapping.endpoint()) {
                 return Err(InterfaceError::InconsistentEndpoints);
             }
         }
 
-        let path = MappingPath::from(mapping.endpoint().clone_owned());
-
         // Check that the mapping is not already present
-        if let Some(existing) = self.mappings.get(&path) {
+        if let Some(existing) = self.mappings.get_overlapping(mapping.endpoint()) {
             return Err(InterfaceError::DuplicateMapping {
                 endpoint: existing.endpoint().to_string(),
                 duplicate: mapping.endpoint().to_string(),
             });
         }
 
-        self.mappings.insert(path, mapping);
+        self.mappings.insert(mapping);
 
         Ok(())
     }
@@ -437,13 +517,12 @@ impl TryFrom<&InterfaceDef<'_>> for DatastreamObject {
 
     fn try_from(value: &InterfaceDef) -> Result<Self, Self::Error> {
         let mut mappings_iter = value.mappings.iter();
-        let mut mappings_set = BTreeMap::new();
+        let mut mappings_set = MappingMap::new();
 
         let first = mappings_iter.next().ok_or(InterfaceError::EmptyMappings)?;
         let first_base = BaseMapping::try_from(first)?;
 
-        let path = first_base.endpoint().clone_owned().into();
-        mappings_set.insert(path, first_base);
+        mappings_set.insert(first_base);
 
         // We create the object from the first mapping and then insert the others, checking if
         // compatible
@@ -465,7 +544,7 @@ impl TryFrom<&InterfaceDef<'_>> for DatastreamObject {
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub(crate) struct Properties {
-    mappings: MappingMap<'static, PropertiesMapping>,
+    mappings: MappingMap<PropertiesMapping>,
 }
 
 impl Properties {
@@ -492,16 +571,14 @@ impl Properties {
     }
 
     pub fn add(&mut self, mapping: PropertiesMapping) -> Result<(), InterfaceError> {
-        let path = mapping.endpoint().clone_owned().into();
-
-        if let Some(existing) = self.mappings.get(&path) {
+        if let Some(existing) = self.mappings.get_overlapping(mapping.endpoint()) {
             return Err(InterfaceError::DuplicateMapping {
                 endpoint: existing.endpoint().to_string(),
                 duplicate: mapping.endpoint().to_string(),
             });
         }
 
-        self.mappings.insert(path, mapping);
+        self.mappings.insert(mapping);
 
         Ok(())
     }
@@ -512,7 +589,7 @@ impl TryFrom<&InterfaceDef<'_>> for Properties {
 
     fn try_from(value: &InterfaceDef) -> Result<Self, Self::Error> {
         let mut properties = Self {
-            mappings: BTreeMap::new(),
+            mappings: MappingMap::new(),
         };
 
         for mapping in value.mappings.iter() {
@@ -591,6 +668,18 @@ impl Default for DatabaseRetention {
     }
 }
 
+#[doc(hidden)]
+#[allow(clippy::expect_used)]
+pub mod bench {
+    use super::{mapping::path::MappingPath, Interface};
+
+    pub fn resolve_mapping(interface: &Interface, path: &str) -> bool {
+        let path = MappingPath::try_from(path).expect("invalid mapping path");
+
+        interface.mapping(&path).is_some()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -598,9 +687,11 @@ mod tests {
     use crate::{
         interface::{
             def::{DatabaseRetentionPolicyDef, RetentionDef},
-            mapping::{path::MappingPath, BaseMapping, DatastreamIndividualMapping},
+            mapping::{
+                index::MappingMap, path::MappingPath, BaseMapping, DatastreamIndividualMapping,
+            },
             Aggregation, DatabaseRetention, DatastreamIndividual, InterfaceType, InterfaceTypeDef,
-            Mapping, MappingMap, MappingType, Ownership, Reliability, Retention,
+            Mapping, MappingDocs, MappingType, Ownership, Reliability, Retention,
         },
         Interface,
     };
@@ -824,4 +915,99 @@ mod tests {
         let expected = serde_json::Value::from_str(INTERFACE_JSON).unwrap();
         assert_eq!(value, expected);
     }
+
+    #[test]
+    fn test_json_canonical() {
+        let interface = Interface::from_str(INTERFACE_JSON).unwrap();
+
+        // same interface, with the mappings reordered and the default values explicit
+        let reordered = Interface::from_str(
+            r#"{
+                "mappings": [
+                    {
+                        "type": "double",
+                        "endpoint": "/%{sensor_id}/value",
+                        "reliability": "unreliable",
+                        "explicit_timestamp": true,
+                        "doc": "Mapping doc",
+                        "description": "Mapping description"
+                    },
+                    {
+                        "endpoint": "/%{sensor_id}/otherValue",
+                        "type": "longinteger",
+                        "explicit_timestamp": true,
+                        "description": "Mapping description",
+                        "doc": "Mapping doc"
+                    }
+                ],
+                "version_minor": 0,
+                "version_major": 1,
+                "interface_name": "org.astarte-platform.genericsensors.Values",
+                "aggregation": "individual",
+                "ownership": "device",
+                "type": "datastream",
+                "description": "Interface description",
+                "doc": "Interface doc"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(interface, reordered);
+
+        let canonical = interface.to_json_canonical().unwrap();
+        assert_eq!(canonical, reordered.to_json_canonical().unwrap());
+        assert_eq!(
+            canonical,
+            concat!(
+                r#"{"description":"Interface description","doc":"Interface doc","#,
+                r#""interface_name":"org.astarte-platform.genericsensors.Values","mappings":["#,
+                r#"{"description":"Mapping description","doc":"Mapping doc","#,
+                r#""endpoint":"/%{sensor_id}/otherValue","explicit_timestamp":true,"type":"longinteger"},"#,
+                r#"{"description":"Mapping description","doc":"Mapping doc","#,
+                r#""endpoint":"/%{sensor_id}/value","explicit_timestamp":true,"type":"double"}],"#,
+                r#""ownership":"device","type":"datastream","version_major":1,"version_minor":0}"#
+            )
+        );
+
+        // round trip
+        let deserialized = Interface::from_str(&canonical).unwrap();
+        assert_eq!(deserialized, interface);
+        assert_eq!(deserialized.to_json_canonical().unwrap(), canonical);
+
+        let changed = Interface::from_str(&INTERFACE_JSON.replace("double", "integer")).unwrap();
+        assert_ne!(changed, interface);
+        assert_ne!(changed.to_json_canonical().unwrap(), canonical);
+    }
+
+    #[test]
+    fn test_mapping_docs() {
+        let interface = Interface::from_str(INTERFACE_JSON).unwrap();
+
+        let docs = interface.mapping_docs("/1/value").unwrap();
+        assert_eq!(
+            docs,
+            MappingDocs {
+                endpoint: "/%{sensor_id}/value".to_string(),
+                description: Some("Mapping description".to_string()),
+                doc: Some("Mapping doc".to_string()),
+            }
+        );
+        assert_eq!(interface.mapping_docs("/1/missing"), None);
+        assert_eq!(interface.mapping_docs("invalid"), None);
+
+        let endpoints: Vec<String> = interface
+            .iter_mapping_docs()
+            .map(|docs| docs.endpoint)
+            .collect();
+        assert_eq!(
+            endpoints,
+            ["/%{sensor_id}/otherValue", "/%{sensor_id}/value"]
+        );
+
+        // the mappings without documentation
+        let interface = Interface::from_str(PROPERTIES_JSON).unwrap();
+        let docs = interface.mapping_docs("/1/aaaa").unwrap();
+        assert_eq!(docs.description, None);
+        assert_eq!(docs.doc, None);
+    }
 }
@@ -24,12 +24,13 @@ use std::{
     ops::Deref,
 };
 
-use itertools::Itertools;
 use log::debug;
 
 use crate::{
-    interface::{mapping::path::MappingPath, InterfaceError, Mapping},
-    payload,
+    database::StoredProp,
+    interface::{mapping::path::MappingPath, InterfaceError, Mapping, Retention},
+    introspection::{InterfaceVersion, Introspection},
+    payload::{self, PayloadError},
     types::AstarteType,
     Aggregation, Error, Interface,
 };
@@ -135,18 +136,31 @@ impl Interfaces {
         self.interfaces.remove(interface_name)
     }
 
-    pub(crate) fn get_introspection_string(&self) -> String {
+    /// Restores the previous version of an interface, or removes it if it wasn't present,
+    /// without validating it against the current one.
+    pub(crate) fn restore(&mut self, interface_name: &str, prev: Option<Interface>) {
+        match prev {
+            Some(prev) => {
+                self.interfaces.insert(interface_name.to_string(), prev);
+            }
+            None => {
+                self.interfaces.remove(interface_name);
+            }
+        }
+    }
+
+    pub(crate) fn introspection(&self) -> Introspection {
         self.interfaces
             .iter()
             .map(|(name, interface)| {
-                format!(
-                    "{}:{}:{}",
-                    name,
-                    interface.version_major(),
-                    interface.version_minor()
-                )
+                let version = InterfaceVersion {
+                    major: interface.version_major(),
+                    minor: interface.version_minor(),
+                };
+
+                (name.as_str(), version)
             })
-            .join(";")
+            .collect()
     }
 
     pub(crate) fn get(&self, interface_name: &str) -> Option<&Interface> {
@@ -185,6 +199,16 @@ impl Interfaces {
             .into()
     }
 
+    pub(crate) fn get_retention(
+        &self,
+        interface_name: &str,
+        interface_path: &MappingPath,
+    ) -> Retention {
+        self.get_mapping(interface_name, interface_path)
+            .map(|mapping| mapping.retention())
+            .unwrap_or_default()
+    }
+
     /// returns major version if the property exists, None otherwise
     pub fn get_property_major(&self, interface: &str, path: &MappingPath) -> Option<i32> {
         let interface = self.get(interface)?;
@@ -196,6 +220,42 @@ impl Interfaces {
         Some(interface.version_major())
     }
 
+    /// Returns true if the stored property matches the interface, major version and mapping of
+    /// a current property interface.
+    pub(crate) fn matches_stored_prop(&self, prop: &StoredProp) -> bool {
+        MappingPath::try_from(prop.path.as_str()).map_or(false, |path| {
+            self.get_property_major(&prop.interface, &path) == Some(prop.interface_major)
+        })
+    }
+
+    /// Deserialize a payload sent or received on a mapping, using the types of the mappings for
+    /// the empty arrays.
+    pub(crate) fn deserialize(
+        &self,
+        interface_name: &str,
+        interface_path: &MappingPath,
+        bdata: &[u8],
+    ) -> Result<(Aggregation, Option<chrono::DateTime<chrono::Utc>>), PayloadError> {
+        let interface = self.interfaces.get(interface_name);
+
+        payload::deserialize_typed(bdata, |field| {
+            let interface = interface?;
+
+            match field {
+                Some(field) => {
+                    let path = format!("{interface_path}/{field}");
+
+                    interface
+                        .mapping(&MappingPath::try_from(path.as_str()).ok()?)
+                        .map(|mapping| mapping.mapping_type())
+                }
+                None => interface
+                    .mapping(interface_path)
+                    .map(|mapping| mapping.mapping_type()),
+            }
+        })
+    }
+
     pub(crate) fn validate_float(data: &AstarteType) -> Result<(), Error> {
         match data {
             AstarteType::Double(d) => validate_float(d),
@@ -211,7 +271,7 @@ impl Interfaces {
         data: &[u8],
         timestamp: &Option<chrono::DateTime<chrono::Utc>>,
     ) -> Result<(), Error> {
-        let data_deserialized = payload::deserialize(data)?;
+        let (data_deserialized, _) = self.deserialize(interface_name, interface_path, data)?;
 
         let interface = self
             .interfaces
@@ -224,7 +284,7 @@ impl Interfaces {
                     .mapping(interface_path)
                     .ok_or_else(|| Error::SendError("Mapping doesn't exist".into()))?;
 
-                if individual != AstarteType::Unset && individual != mapping.mapping_type() {
+                if individual != mapping.mapping_type() {
                     return Err(Error::SendError(format!(
                         "You are sending the wrong type for this mapping: got {:?}, expected {:?}",
                         individual,
@@ -239,6 +299,17 @@ impl Interfaces {
                         "Do not send timestamp to a mapping without explicit timestamp".into(),
                     ));
                 }
+            }
+            Aggregation::Unset => {
+                let mapping = interface
+                    .mapping(interface_path)
+                    .ok_or_else(|| Error::SendError("Mapping doesn't exist".into()))?;
+
+                if !mapping.explicit_timestamp() && timestamp.is_some() {
+                    return Err(Error::SendError(
+                        "Do not send timestamp to a mapping without explicit timestamp".into(),
+                    ));
+                }
 
                 if !mapping.allow_unset() && data.is_empty() {
                     return Err(Error::SendError(
@@ -295,7 +366,7 @@ impl Interfaces {
             Error::ReceiveError(format!("Interface '{interface_name}' does not exists"))
         })?;
 
-        let data = payload::deserialize(bdata)?;
+        let (data, _) = self.deserialize(interface_name, path, bdata)?;
 
         match data {
             Aggregation::Individual(individual) => {
@@ -303,16 +374,6 @@ impl Interfaces {
                     Error::ReceiveError(format!("Mapping '{path}' doesn't exist",))
                 })?;
 
-                if individual == AstarteType::Unset {
-                    if !mapping.allow_unset() {
-                        return Err(Error::ReceiveError(
-                            "Do not unset a mapping without allow_unset".into(),
-                        ));
-                    } else {
-                        return Ok(());
-                    }
-                }
-
                 if individual != mapping.mapping_type() {
                     return Err(Error::ReceiveError(
                         "You are receiving the wrong type for this mapping".into(),
@@ -321,6 +382,17 @@ impl Interfaces {
 
                 Interfaces::validate_float(&individual)?;
             }
+            Aggregation::Unset => {
+                let mapping = interface.mapping(path).ok_or_else(|| {
+                    Error::ReceiveError(format!("Mapping '{path}' doesn't exist",))
+                })?;
+
+                if !mapping.allow_unset() {
+                    return Err(Error::ReceiveError(
+                        "Do not unset a mapping without allow_unset".into(),
+                    ));
+                }
+            }
             Aggregation::Object(object) => {
                 for (name, value) in &object {
                     Interfaces::validate_float(value)?;
@@ -918,7 +990,7 @@ mod test {
             "org.astarte-platform.rust.examples.individual-datastream.ServerDatastream:0:1",
         ];
 
-        let intro = ifa.get_introspection_string();
+        let intro = ifa.introspection().to_string();
         let mut res: Vec<&str> = intro.split(';').collect();
 
         res.sort_unstable();
@@ -0,0 +1,194 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Inspection of the device introspection.
+//!
+//! The introspection is the list of interfaces, with their versions, declared by the device to
+//! Astarte. You can find more information in the
+//! [Astarte MQTT v1 Protocol](https://docs.astarte-platform.org/astarte/latest/080-mqtt-v1-protocol.html#introspection).
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use itertools::Itertools;
+
+/// Major and minor version of an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InterfaceVersion {
+    pub major: i32,
+    pub minor: i32,
+}
+
+impl Display for InterfaceVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.major, self.minor)
+    }
+}
+
+/// Introspection of the device, the interfaces names with their version.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Introspection {
+    interfaces: BTreeMap<String, InterfaceVersion>,
+}
+
+impl Introspection {
+    /// Returns the version of an interface in the introspection.
+    pub fn get(&self, interface_name: &str) -> Option<InterfaceVersion> {
+        self.interfaces.get(interface_name).copied()
+    }
+
+    /// Iterate over the interfaces names and versions, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, InterfaceVersion)> {
+        self.interfaces
+            .iter()
+            .map(|(name, version)| (name.as_str(), *version))
+    }
+
+    /// Returns the number of interfaces in the introspection.
+    pub fn len(&self) -> usize {
+        self.interfaces.len()
+    }
+
+    /// Returns true if there are no interfaces in the introspection.
+    pub fn is_empty(&self) -> bool {
+        self.interfaces.is_empty()
+    }
+
+    /// Returns the differences from a previous introspection.
+    pub fn diff(&self, previous: &Introspection) -> IntrospectionDiff {
+        let mut diff = IntrospectionDiff::default();
+
+        for (name, version) in self.iter() {
+            match previous.get(name) {
+                None => diff.added.push((name.to_string(), version)),
+                Some(prev) if prev != version => {
+                    diff.changed.push((name.to_string(), prev, version))
+                }
+                Some(_) => {}
+            }
+        }
+
+        diff.removed = previous
+            .iter()
+            .filter(|(name, _)| !self.interfaces.contains_key(*name))
+            .map(|(name, version)| (name.to_string(), version))
+            .collect();
+
+        diff
+    }
+}
+
+impl Display for Introspection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let introspection = self
+            .iter()
+            .map(|(name, version)| format!("{name}:{version}"))
+            .join(";");
+
+        write!(f, "{introspection}")
+    }
+}
+
+impl<S> FromIterator<(S, InterfaceVersion)> for Introspection
+where
+    S: Into<String>,
+{
+    fn from_iter<T: IntoIterator<Item = (S, InterfaceVersion)>>(iter: T) -> Self {
+        Self {
+            interfaces: iter
+                .into_iter()
+                .map(|(name, version)| (name.into(), version))
+                .collect(),
+        }
+    }
+}
+
+/// Differences between the current introspection and a previous one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntrospectionDiff {
+    /// Interfaces not present in the previous introspection.
+    pub added: Vec<(String, InterfaceVersion)>,
+    /// Interfaces present only in the previous introspection.
+    pub removed: Vec<(String, InterfaceVersion)>,
+    /// Interfaces with a different version, with the previous and current one.
+    pub changed: Vec<(String, InterfaceVersion, InterfaceVersion)>,
+}
+
+impl IntrospectionDiff {
+    /// Returns true if the two introspections are the same.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn version(major: i32, minor: i32) -> InterfaceVersion {
+        InterfaceVersion { major, minor }
+    }
+
+    #[test]
+    fn test_introspection_string() {
+        let introspection: Introspection = [
+            ("com.test.Second", version(1, 0)),
+            ("com.test.First", version(0, 2)),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            introspection.to_string(),
+            "com.test.First:0:2;com.test.Second:1:0"
+        );
+        assert_eq!(Introspection::default().to_string(), "");
+    }
+
+    #[test]
+    fn test_introspection_diff() {
+        let previous: Introspection = [
+            ("com.test.Same", version(0, 1)),
+            ("com.test.Removed", version(0, 1)),
+            ("com.test.Changed", version(0, 1)),
+        ]
+        .into_iter()
+        .collect();
+
+        let current: Introspection = [
+            ("com.test.Same", version(0, 1)),
+            ("com.test.Changed", version(0, 2)),
+            ("com.test.Added", version(1, 0)),
+        ]
+        .into_iter()
+        .collect();
+
+        let diff = current.diff(&previous);
+
+        assert_eq!(diff.added, [("com.test.Added".to_string(), version(1, 0))]);
+        assert_eq!(
+            diff.removed,
+            [("com.test.Removed".to_string(), version(0, 1))]
+        );
+        assert_eq!(
+            diff.changed,
+            [("com.test.Changed".to_string(), version(0, 1), version(0, 2))]
+        );
+
+        assert!(current.diff(&current).is_empty());
+    }
+}
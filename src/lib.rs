@@ -18,20 +18,74 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 #![doc = include_str!("../README.md")]
-
+#![cfg_attr(
+    all(feature = "no-panics", not(test)),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::indexing_slicing,
+        clippy::unreachable,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
+
+mod append_log;
+pub mod assembly;
+pub mod buffer;
+pub mod capabilities;
+pub mod collection;
+pub mod constraint;
 pub mod crypto;
 pub mod database;
+pub mod dedup;
+mod delivery;
+pub mod discovery;
+pub mod encryption;
+pub mod endpoint;
 pub mod error;
+pub mod event;
+pub mod filter;
+pub mod handle;
+pub mod history;
+mod idle;
+pub mod import;
 pub mod interface;
 mod interfaces;
+pub mod introspection;
+pub mod liveness;
+pub mod logging;
+pub mod message;
 #[cfg(test)]
 mod mock;
 pub mod options;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod outbox;
 pub mod pairing;
 pub mod payload;
+pub mod pool;
 pub mod properties;
+pub mod provisioning;
+pub mod quality;
+pub mod queue;
+pub mod quota;
 pub mod registration;
+pub mod registry;
+pub mod replay;
+mod retention;
+pub mod run;
+pub mod selftest;
+pub mod sequence;
+pub mod settings;
+mod shutdown;
+mod store_failure;
+pub mod throttle;
 mod topic;
+pub mod transform;
+pub mod transport;
+pub mod twin;
 pub mod types;
 
 #[cfg(test)]
@@ -39,31 +93,71 @@ use mock::{MockAsyncClient as AsyncClient, MockEventLoop as EventLoop};
 #[cfg(not(test))]
 use rumqttc::{AsyncClient, EventLoop};
 
-use std::collections::HashMap;
+// Resolve the paths generated by the macros in the tests
+#[cfg(test)]
+extern crate self as astarte_device_sdk;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryInto;
 use std::fmt::{self, Debug};
+use std::panic::AssertUnwindSafe;
 use std::path::Path;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError, RwLock, Weak};
 
 // Re-export rumqttc since we return its types in some methods
 pub use chrono;
 pub use rumqttc;
 
+use futures::FutureExt;
 use log::{debug, error, info, trace, warn};
 use rumqttc::Event;
 
 /// Re-exported internal structs
+pub use crate::event::{FromEvent, IntoEvent};
 pub use crate::interface::Interface;
+pub use crate::run::run;
+pub use crate::settings::AstarteProperties;
 
+use crate::capabilities::Capabilities;
+use crate::constraint::ValueConstraints;
 use crate::database::AstarteDatabase;
 use crate::database::StoredProp;
-use crate::error::Error;
+use crate::dedup::PublishedLog;
+use crate::delivery::{Deliveries, Delivery};
+use crate::discovery::{IntrospectionMismatch, RealmManagement};
+use crate::encryption::PayloadEncryption;
+use crate::error::{Error, PayloadOperation};
+use crate::filter::EventFilters;
+use crate::handle::InterfaceHandle;
+use crate::history::{ErrorHistory, ErrorRecord};
+use crate::idle::IdleMode;
+use crate::import::{ImportFailure, ImportProgress, ImportReport};
 use crate::interface::mapping::path::MappingPath;
-use crate::interface::{InterfaceError, Ownership};
-use crate::interfaces::PropertyRef;
-use crate::options::AstarteOptions;
+use crate::interface::{InterfaceError, MappingDocs, Ownership, Retention};
+use crate::introspection::{Introspection, IntrospectionDiff};
+use crate::liveness::{LivenessCheck, LivenessStatus};
+use crate::logging::LogFormat;
+use crate::message::{MessageEvent, MessageHook, MessageId, MessageStage};
+use crate::options::{
+    AstarteOptions, MismatchPolicy, PropertyPublishPolicies, PropertyPublishPolicy,
+    PublishOrdering, PublishOrderings, RetainedPolicy, SendRetry, StalePolicy, StaleWindow,
+};
+use crate::outbox::AstarteOutbox;
+use crate::pool::{BufferPool, PoolStats, PooledBuffer};
+use crate::quality::{ConnectionQuality, QualityEstimator};
+use crate::queue::{QueueSnapshot, Throughput};
+use crate::registry::SchemaRegistry;
+use crate::retention::{VolatileItem, VolatileRetention};
+use crate::selftest::{CheckStatus, ConnectionInfo, SelfTestReport};
+use crate::sequence::Sequences;
+use crate::shutdown::ShutdownSignal;
+use crate::store_failure::StoreFailures;
+use crate::throttle::ReceiveLimits;
 use crate::topic::parse_topic;
+use crate::transform::ValueTransforms;
+use crate::transport::{Reconfigure, Transport, TransportOptions};
+use crate::twin::{Twin, TwinInner};
 use crate::types::{AstarteType, TypeError};
 
 /// A **trait** required by all data to be sent using
@@ -106,9 +200,31 @@ pub trait AstarteAggregate {
     fn astarte_aggregate(self) -> Result<HashMap<String, AstarteType>, Error>;
 }
 
-impl AstarteAggregate for HashMap<String, AstarteType> {
+/// A map is an object with an endpoint for each key, for the objects with the endpoints known
+/// only at runtime.
+impl<T, S> AstarteAggregate for HashMap<String, T, S>
+where
+    T: TryInto<AstarteType>,
+    Error: From<T::Error>,
+{
+    fn astarte_aggregate(self) -> Result<HashMap<String, AstarteType>, Error> {
+        self.into_iter()
+            .map(|(key, value)| Ok((key, value.try_into()?)))
+            .collect()
+    }
+}
+
+/// A map is an object with an endpoint for each key, for the objects with the endpoints known
+/// only at runtime.
+impl<T> AstarteAggregate for BTreeMap<String, T>
+where
+    T: TryInto<AstarteType>,
+    Error: From<T::Error>,
+{
     fn astarte_aggregate(self) -> Result<HashMap<String, AstarteType>, Error> {
-        Ok(self)
+        self.into_iter()
+            .map(|(key, value)| Ok((key, value.try_into()?)))
+            .collect()
     }
 }
 
@@ -126,27 +242,305 @@ extern crate astarte_device_sdk_derive;
 #[cfg(feature = "derive")]
 pub use astarte_device_sdk_derive::AstarteAggregate;
 
+/// Derive macro to implement the `FromEvent` trait with `feature = ["derive"]`.
+#[cfg(feature = "derive")]
+pub use astarte_device_sdk_derive::FromEvent;
+
+/// Derive macro to convert a fieldless enum to and from a string with `feature = ["derive"]`.
+#[cfg(feature = "derive")]
+pub use astarte_device_sdk_derive::AstarteEnum;
+
+/// Derive macro to forward the conversions of a newtype wrapper with `feature = ["derive"]`.
+#[cfg(feature = "derive")]
+pub use astarte_device_sdk_derive::AstarteNewtype;
+
+/// Derive macro to implement the `IntoEvent` trait with `feature = ["derive"]`.
+#[cfg(feature = "derive")]
+pub use astarte_device_sdk_derive::IntoEvent;
+
+/// Attribute macro to generate the JSON of an interface from a struct with `feature = ["derive"]`.
+#[cfg(feature = "derive")]
+pub use astarte_device_sdk_derive::astarte_interface;
+
+/// Derive macro to implement the `AstarteProperties` trait with `feature = ["derive"]`.
+#[cfg(feature = "derive")]
+pub use astarte_device_sdk_derive::AstarteProperties;
+
+/// Macro to generate the typed send functions of an interface with `feature = ["derive"]`.
+#[cfg(feature = "derive")]
+pub use astarte_device_sdk_derive::include_interface;
+
 /// Astarte device implementation.
 ///
 /// Provides functionality to transmit and receive individual and object datastreams as well
 /// as properties.
-#[derive(Clone)]
-pub struct AstarteDeviceSdk {
+///
+/// The property store is a trait object by default, a device created with
+/// [`with_database()`](AstarteDeviceSdk::with_database) uses the concrete type of the store to
+/// avoid the dynamic dispatch.
+pub struct AstarteDeviceSdk<S: ?Sized = dyn AstarteDatabase + Sync + Send> {
     realm: String,
     device_id: String,
     client: AsyncClient,
     eventloop: Arc<tokio::sync::Mutex<EventLoop>>,
     interfaces: Arc<tokio::sync::RwLock<interfaces::Interfaces>>,
-    database: Option<Arc<dyn AstarteDatabase + Sync + Send>>,
+    database: Option<Arc<S>>,
+    property_publish_policies: Arc<PropertyPublishPolicies>,
+    publish_orderings: Arc<PublishOrderings>,
+    /// Locks serializing the publishes of the ordered interfaces.
+    ordered_publishes: Arc<std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+    /// Last introspection successfully sent to Astarte.
+    announced_introspection: Arc<tokio::sync::RwLock<Option<Introspection>>>,
+    /// Messages with volatile retention that couldn't be published.
+    volatile: Arc<tokio::sync::Mutex<VolatileRetention>>,
+    /// Publishes handed to the client and not acknowledged yet.
+    deliveries: Arc<Deliveries>,
+    store_failures: Arc<StoreFailures>,
+    event_filters: Arc<EventFilters>,
+    value_constraints: Arc<ValueConstraints>,
+    value_transforms: Arc<ValueTransforms>,
+    payload_encryption: Arc<PayloadEncryption>,
+    #[cfg(feature = "otel")]
+    tracing: Arc<otel::MessageTracing>,
+    message_hook: Option<MessageHook>,
+    idle: Option<Arc<IdleMode>>,
+    transport: Arc<Transport>,
+    send_retry: Option<SendRetry>,
+    purge_compression: flate2::Compression,
+    stale_window: Option<StaleWindow>,
+    max_event_size: Option<usize>,
+    receive_limits: Arc<ReceiveLimits>,
+    published_log: Option<Arc<PublishedLog>>,
+    quality: Arc<QualityEstimator>,
+    capabilities: Capabilities,
+    /// Broker and certificate used to connect, checked by [`AstarteDeviceSdk::self_test`].
+    connection: Option<Arc<ConnectionInfo>>,
+    retained_policy: RetainedPolicy,
+    liveness: Option<Arc<LivenessCheck>>,
+    shutdown_signal: Option<ShutdownSignal>,
+    error_history: Arc<ErrorHistory>,
+    log_format: LogFormat,
+    buffers: Arc<BufferPool>,
+    sequences: Arc<Sequences>,
+    /// Mirrors of the server properties returned by [`AstarteDeviceSdk::twin`].
+    twins: Arc<std::sync::Mutex<Vec<Weak<TwinInner>>>>,
+    /// Interfaces disabled with [`AstarteDeviceSdk::set_interface_enabled`].
+    disabled_interfaces: Arc<RwLock<HashSet<String>>>,
+    /// Bytes published per second, reported in the [`QueueSnapshot`].
+    throughput: Arc<Throughput>,
+    /// Set by [`AstarteDeviceSdk::shutdown`], so the task polling the event loop releases it.
+    shutdown_requested: Arc<tokio::sync::watch::Sender<bool>>,
+    status: Arc<tokio::sync::watch::Sender<DeviceStatus>>,
+}
+
+// Manual implementation, since deriving it would require `S: Clone`
+impl<S: ?Sized> Clone for AstarteDeviceSdk<S> {
+    fn clone(&self) -> Self {
+        Self {
+            realm: self.realm.clone(),
+            device_id: self.device_id.clone(),
+            client: self.client.clone(),
+            eventloop: self.eventloop.clone(),
+            interfaces: self.interfaces.clone(),
+            database: self.database.clone(),
+            property_publish_policies: self.property_publish_policies.clone(),
+            publish_orderings: self.publish_orderings.clone(),
+            ordered_publishes: self.ordered_publishes.clone(),
+            announced_introspection: self.announced_introspection.clone(),
+            volatile: self.volatile.clone(),
+            deliveries: self.deliveries.clone(),
+            store_failures: self.store_failures.clone(),
+            event_filters: self.event_filters.clone(),
+            value_constraints: self.value_constraints.clone(),
+            value_transforms: self.value_transforms.clone(),
+            payload_encryption: self.payload_encryption.clone(),
+            #[cfg(feature = "otel")]
+            tracing: self.tracing.clone(),
+            message_hook: self.message_hook.clone(),
+            idle: self.idle.clone(),
+            transport: self.transport.clone(),
+            send_retry: self.send_retry,
+            purge_compression: self.purge_compression,
+            stale_window: self.stale_window,
+            max_event_size: self.max_event_size,
+            receive_limits: self.receive_limits.clone(),
+            published_log: self.published_log.clone(),
+            quality: self.quality.clone(),
+            capabilities: self.capabilities,
+            connection: self.connection.clone(),
+            retained_policy: self.retained_policy,
+            liveness: self.liveness.clone(),
+            shutdown_signal: self.shutdown_signal.clone(),
+            error_history: self.error_history.clone(),
+            log_format: self.log_format,
+            buffers: self.buffers.clone(),
+            sequences: self.sequences.clone(),
+            twins: self.twins.clone(),
+            disabled_interfaces: self.disabled_interfaces.clone(),
+            throughput: self.throughput.clone(),
+            shutdown_requested: self.shutdown_requested.clone(),
+            status: self.status.clone(),
+        }
+    }
 }
 
 /// Payload format for an Astarte device event data.
+///
+/// The enum is `#[non_exhaustive]`, so the matches outside the crate need a wildcard arm, for the
+/// variants handled as errors.
+#[non_exhaustive]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Aggregation {
     /// Individual data, can be both from a datastream or property.
     Individual(AstarteType),
     /// Object data, also called aggregate. Can only be from a datastream.
     Object(HashMap<String, AstarteType>),
+    /// Unset of a property, received with an empty payload.
+    Unset,
+}
+
+impl Aggregation {
+    /// Returns true if the data unsets a property.
+    pub fn is_unset(&self) -> bool {
+        matches!(self, Aggregation::Unset)
+    }
+
+    /// Returns the individual value, `None` for an object or an unset property.
+    pub fn as_individual(&self) -> Option<&AstarteType> {
+        match self {
+            Aggregation::Individual(value) => Some(value),
+            Aggregation::Object(_) | Aggregation::Unset => None,
+        }
+    }
+
+    /// Returns the fields of an object, `None` for an individual value or an unset property.
+    pub fn as_object(&self) -> Option<&HashMap<String, AstarteType>> {
+        match self {
+            Aggregation::Object(object) => Some(object),
+            Aggregation::Individual(_) | Aggregation::Unset => None,
+        }
+    }
+}
+
+/// Status of an [`AstarteDeviceSdk`], see [`AstarteDeviceSdk::status`].
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceStatus {
+    /// The device is processing the events.
+    Running,
+    /// The device encountered an unrecoverable failure.
+    Failed { reason: String },
+    /// The device was shut down, see [`AstarteDeviceSdk::shutdown`].
+    Stopped,
+}
+
+/// Event to handle in the loop of [`AstarteDeviceSdk::handle_events`].
+enum Received {
+    /// Polled from the MQTT event loop.
+    Polled(Event),
+    /// Held by the [receive rate limit](crate::throttle) and now allowed.
+    Held(rumqttc::Publish),
+}
+
+/// Data removed by [`AstarteDeviceSdk::prune_store`] since it doesn't match the current
+/// interfaces.
+#[derive(Debug, Default, PartialEq)]
+pub struct PruneReport {
+    /// Stale stored properties.
+    pub properties: Vec<StoredProp>,
+    /// Number of stale messages in the volatile retention.
+    pub volatile: usize,
+}
+
+impl PruneReport {
+    /// Returns true if there is no stale data.
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty() && self.volatile == 0
+    }
+}
+
+/// Change of an interface of the batch passed to [`AstarteDeviceSdk::extend_interfaces`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterfaceChange {
+    /// The interface wasn't present.
+    Added,
+    /// The interface replaced a previous version.
+    Updated,
+    /// The interface was already present.
+    Unchanged,
+}
+
+/// Result of [`AstarteDeviceSdk::extend_interfaces`].
+///
+/// The batch is applied only if all the interfaces are valid and the error is `None`, otherwise
+/// the device interfaces are unchanged.
+#[derive(Debug, Default)]
+#[must_use]
+pub struct ExtendReport {
+    /// Result of the validation of each interface, in the order of the batch.
+    pub interfaces: Vec<(String, Result<InterfaceChange, InterfaceError>)>,
+    /// Error subscribing the interfaces or sending the introspection, the batch was rolled back.
+    pub error: Option<Error>,
+}
+
+impl ExtendReport {
+    /// Returns true if the batch was applied.
+    pub fn is_applied(&self) -> bool {
+        self.error.is_none() && self.interfaces.iter().all(|(_, res)| res.is_ok())
+    }
+
+    /// Returns the names of the interfaces added or updated, or the first error if the batch
+    /// wasn't applied.
+    pub fn into_result(self) -> Result<Vec<String>, Error> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        self.interfaces
+            .into_iter()
+            .filter_map(|(name, res)| match res {
+                Ok(InterfaceChange::Unchanged) => None,
+                Ok(_) => Some(Ok(name)),
+                Err(err) => Some(Err(Error::Interface(err))),
+            })
+            .collect()
+    }
+}
+
+/// Outcome of a message being sent.
+#[derive(Debug)]
+enum Sent {
+    /// Handed to the client, completes when the broker acknowledges it.
+    Queued(Delivery),
+    /// Kept in the volatile retention, since the client couldn't take it.
+    Retained(MessageId),
+    /// Not published, like an unchanged property or a message on a disabled interface.
+    Skipped,
+}
+
+/// Property of a value being sent, copied out of the interfaces to not hold their lock.
+#[derive(Debug, Clone, Copy)]
+struct SentProperty {
+    version_major: i32,
+    /// The path of the value is a mapping of the property.
+    mapped: bool,
+}
+
+/// Returns the message of a panic payload.
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// Returns true if the publish can succeed when retried.
+///
+/// The client returns [`Request`](rumqttc::ClientError::Request) only after the event loop was
+/// dropped, so every retry would fail too.
+fn is_transient(err: &rumqttc::ClientError) -> bool {
+    matches!(err, rumqttc::ClientError::TryRequest(_))
 }
 
 /// Astarte device event data structure.
@@ -160,6 +554,30 @@ pub struct AstarteDeviceDataEvent {
     pub path: String,
     /// Payload of the event
     pub data: Aggregation,
+    /// The event has a timestamp older than the window configured with
+    /// [`AstarteOptions::stale_event_window`].
+    pub stale: bool,
+    /// Reception and delivery information of the event.
+    pub metadata: EventMetadata,
+}
+
+/// Reception and delivery information of an [`AstarteDeviceDataEvent`].
+///
+/// The reception time can be compared with the explicit timestamp to compute the end-to-end
+/// latency, while the MQTT flags can be used to deduplicate the events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventMetadata {
+    /// Local time the message was received by the device.
+    pub received_at: chrono::DateTime<chrono::Utc>,
+    /// Explicit timestamp sent with the value, if any.
+    pub timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    /// QoS the message was delivered with.
+    pub qos: rumqttc::QoS,
+    /// The message was retained by the broker, only reported with the
+    /// [`RetainedPolicy::Mark`](crate::options::RetainedPolicy::Mark).
+    pub retain: bool,
+    /// The message is a redelivery of a message already sent by the broker.
+    pub duplicate: bool,
 }
 
 impl AstarteDeviceSdk {
@@ -179,26 +597,192 @@ impl AstarteDeviceSdk {
     /// }
     /// ```
     pub async fn new(opts: AstarteOptions) -> Result<AstarteDeviceSdk, Error> {
-        let mqtt_options = pairing::get_transport_config(&opts).await?;
+        let database = opts.database.clone();
+
+        Self::connect(opts, database).await
+    }
+}
+
+impl<S> AstarteDeviceSdk<S>
+where
+    S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+{
+    /// Create a new instance of the Astarte Device SDK, storing the properties in the database.
+    ///
+    /// The device uses the concrete type of the database, so the calls to the store are
+    /// statically dispatched. The database set in the options is ignored.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{
+    ///     database::AstarteSqliteDatabase, options::AstarteOptions, AstarteDeviceSdk,
+    /// };
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let database = AstarteSqliteDatabase::new("path/to/database/file.sqlite")
+    ///         .await
+    ///         .unwrap();
+    ///     let sdk_options = AstarteOptions::new("", "", "", "");
+    ///
+    ///     let device: AstarteDeviceSdk<AstarteSqliteDatabase> =
+    ///         AstarteDeviceSdk::with_database(sdk_options, database)
+    ///             .await
+    ///             .unwrap();
+    /// }
+    /// ```
+    pub async fn with_database(opts: AstarteOptions, database: S) -> Result<Self, Error>
+    where
+        S: Sized,
+    {
+        Self::connect(opts, Some(Arc::new(database))).await
+    }
+
+    async fn connect(mut opts: AstarteOptions, database: Option<Arc<S>>) -> Result<Self, Error> {
+        if let Some(registry) = opts.schema_registry.take() {
+            match registry.fetch().await {
+                Ok(interfaces) => {
+                    for interface in interfaces {
+                        let name = interface.to_string();
+
+                        if let Err(err) = opts.interfaces.add(interface) {
+                            warn!("couldn't add the interface {name} from the registry: {err}");
+                        }
+                    }
+                }
+                Err(err) => {
+                    warn!("couldn't fetch the interfaces from the registry, using the configured ones: {err}");
+                }
+            }
+        }
+
+        if let Some((realm, policy)) = &opts.realm_discovery {
+            let introspection = opts.interfaces.introspection();
+
+            match realm.check(&opts.realm, &introspection).await {
+                Ok(report) if report.is_empty() => {}
+                Ok(report) if *policy == MismatchPolicy::Error => {
+                    return Err(Error::IntrospectionMismatch(report));
+                }
+                Ok(report) => {
+                    for mismatch in &report.interfaces {
+                        warn!("{mismatch}, its data will be discarded");
+                    }
+                }
+                Err(err) => {
+                    warn!("couldn't check the interfaces installed in the realm: {err}");
+                }
+            }
+        }
+
+        opts.transport.validate()?;
+
+        let pairing::TransportConfig {
+            mqtt_options,
+            capabilities,
+            connection,
+        } = pairing::get_transport_config(&opts).await?;
+        debug!("cluster capabilities {capabilities:?}");
 
         debug!("{:#?}", mqtt_options);
 
-        let (client, eventloop) = AsyncClient::new(mqtt_options, 50);
+        let (client, mut eventloop) = AsyncClient::new(mqtt_options, 50);
+        eventloop.reconfigure(&opts.transport);
 
-        let mut device = AstarteDeviceSdk {
-            realm: opts.realm,
-            device_id: opts.device_id,
+        let prune_store = opts.prune_store;
+        let mut device = Self::from_options(
+            opts,
+            database,
             client,
-            eventloop: Arc::new(tokio::sync::Mutex::new(eventloop)),
-            interfaces: Arc::new(tokio::sync::RwLock::new(opts.interfaces)),
-            database: opts.database,
-        };
+            eventloop,
+            capabilities,
+            Some(connection),
+        );
+
+        if prune_store {
+            let report = device.prune_store(false).await?;
+
+            if !report.is_empty() {
+                info!(
+                    "pruned {} stale properties from the store",
+                    report.properties.len()
+                );
+            }
+        }
 
         device.wait_for_connack().await?;
 
         Ok(device)
     }
 
+    /// Creates the device on the client and event loop of the transport, with the configured
+    /// options.
+    ///
+    /// The capabilities of the cluster are used if they are not set in the options.
+    fn from_options(
+        opts: AstarteOptions,
+        database: Option<Arc<S>>,
+        client: AsyncClient,
+        eventloop: EventLoop,
+        capabilities: Capabilities,
+        connection: Option<ConnectionInfo>,
+    ) -> Self {
+        let mut volatile = VolatileRetention::new(opts.volatile_retention_capacity);
+        for (interface, quota) in opts.retention_quotas {
+            volatile.set_quota(&interface, Some(quota));
+        }
+
+        AstarteDeviceSdk {
+            realm: opts.realm,
+            device_id: opts.device_id,
+            client,
+            eventloop: Arc::new(tokio::sync::Mutex::new(eventloop)),
+            interfaces: Arc::new(tokio::sync::RwLock::new(opts.interfaces)),
+            database,
+            property_publish_policies: Arc::new(opts.property_publish_policies),
+            publish_orderings: Arc::new(opts.publish_orderings),
+            ordered_publishes: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            announced_introspection: Arc::new(tokio::sync::RwLock::new(None)),
+            volatile: Arc::new(tokio::sync::Mutex::new(volatile)),
+            deliveries: Arc::new(Deliveries::default()),
+            store_failures: Arc::new(StoreFailures::new(
+                opts.store_failure_policy,
+                opts.store_failure_hook,
+            )),
+            event_filters: Arc::new(opts.event_filters),
+            value_constraints: Arc::new(opts.value_constraints),
+            value_transforms: Arc::new(opts.value_transforms),
+            payload_encryption: Arc::new(opts.payload_encryption),
+            #[cfg(feature = "otel")]
+            tracing: Arc::new(otel::MessageTracing::new(opts.trace_fields)),
+            message_hook: opts.message_hook,
+            idle: opts.idle.map(|config| Arc::new(IdleMode::new(config))),
+            transport: Arc::new(Transport::new(&opts.transport)),
+            send_retry: opts.send_retry,
+            purge_compression: opts.purge_compression,
+            stale_window: opts.stale_window,
+            max_event_size: opts.max_event_size,
+            receive_limits: Arc::new(ReceiveLimits::new(opts.receive_limits)),
+            published_log: opts.published_log,
+            quality: Arc::new(QualityEstimator::default()),
+            capabilities: opts.capabilities.unwrap_or(capabilities),
+            connection: connection.map(Arc::new),
+            retained_policy: opts.retained_policy,
+            liveness: opts
+                .liveness
+                .map(|liveness| Arc::new(LivenessCheck::new(liveness))),
+            shutdown_signal: opts.shutdown_signal,
+            error_history: Arc::new(ErrorHistory::new(opts.error_history)),
+            log_format: opts.log_format,
+            buffers: Arc::new(BufferPool::new(opts.buffer_pool)),
+            sequences: Arc::new(opts.sequence_fields.into_iter().collect()),
+            twins: Arc::new(std::sync::Mutex::new(Vec::new())),
+            disabled_interfaces: Arc::new(RwLock::new(HashSet::new())),
+            throughput: Arc::new(Throughput::new()),
+            shutdown_requested: Arc::new(tokio::sync::watch::channel(false).0),
+            status: Arc::new(tokio::sync::watch::channel(DeviceStatus::Running).0),
+        }
+    }
+
     async fn wait_for_connack(&mut self) -> Result<(), Error> {
         loop {
             // keep consuming and processing packets until we have data for the user
@@ -228,9 +812,220 @@ impl AstarteDeviceSdk {
             info!("connack done");
         }
 
+        self.restore_unacknowledged().await;
+        self.send_volatile().await?;
+
         Ok(())
     }
 
+    /// Publish a message, keeping it in the volatile retention if the publish fails and the
+    /// mapping has volatile retention.
+    async fn publish<'a>(
+        &self,
+        interface_name: &str,
+        interface_path: &MappingPath<'a>,
+        buf: &[u8],
+    ) -> Result<Sent, Error> {
+        let topic =
+            self.client_id() + "/" + interface_name.trim_matches('/') + interface_path.as_str();
+
+        let (qos, retention) = {
+            let interfaces = self.interfaces.read().await;
+
+            (
+                interfaces.get_mqtt_reliability(interface_name, interface_path),
+                interfaces.get_retention(interface_name, interface_path),
+            )
+        };
+
+        let id = MessageId::new();
+        let path = interface_path.as_str();
+
+        self.check_payload_size(interface_name, path, buf.len())?;
+
+        // held until the message is handed to the client or retained
+        let _ordered = self.lock_ordered(interface_name).await;
+
+        self.idle_activity();
+
+        // keep a copy of the payload only for the volatile mappings, until it's acknowledged
+        let mut retained = match retention {
+            Retention::Volatile { expiry } => Some(VolatileItem::new(
+                id,
+                interface_name,
+                path,
+                topic.clone(),
+                qos,
+                buf.to_vec(),
+                expiry,
+            )),
+            _ => None,
+        };
+
+        let err = match self.client_publish(&topic, qos, buf, &mut retained).await {
+            Ok(delivery) => {
+                self.message_step(id, interface_name, path, MessageStage::Published);
+
+                return Ok(Sent::Queued(delivery));
+            }
+            Err(err) => err,
+        };
+
+        let Some(item) = retained else {
+            return Err(Error::publish(interface_name, path)(err));
+        };
+
+        warn!(
+            "couldn't publish message {id} on {topic}, keeping it in the volatile retention: {err}"
+        );
+
+        self.keep_volatile(item).await;
+
+        Ok(Sent::Retained(id))
+    }
+
+    /// Waits for the previous publishes of an [ordered](PublishOrdering::Ordered) interface,
+    /// returns `None` for the parallel interfaces.
+    async fn lock_ordered(&self, interface_name: &str) -> Option<tokio::sync::OwnedMutexGuard<()>> {
+        if self.publish_orderings.get(interface_name) == PublishOrdering::Parallel {
+            return None;
+        }
+
+        let lock = self
+            .ordered_publishes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(interface_name.to_string())
+            .or_default()
+            .clone();
+
+        // the tokio mutex is fair, so the publishes are done in the order they are queued
+        Some(lock.lock_owned().await)
+    }
+
+    /// Publish on the client, retrying the failures with the configured [`SendRetry`].
+    async fn client_publish(
+        &self,
+        topic: &str,
+        qos: rumqttc::QoS,
+        buf: &[u8],
+        retained: &mut Option<VolatileItem>,
+    ) -> Result<Delivery, rumqttc::ClientError> {
+        let mut attempt = 0;
+
+        loop {
+            let err = match self
+                .client_send(topic.to_string(), qos, buf.to_vec(), retained)
+                .await
+            {
+                Ok(delivery) => {
+                    self.throughput.record(buf.len());
+
+                    return Ok(delivery);
+                }
+                Err(err) => err,
+            };
+
+            let retry = match self.send_retry {
+                Some(retry) if attempt < retry.attempts && is_transient(&err) => retry,
+                _ => return Err(err),
+            };
+
+            let delay = retry.backoff * 2u32.saturating_pow(attempt);
+            attempt += 1;
+
+            warn!("couldn't publish on {topic}, retrying in {delay:?}: {err}");
+
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Hands a publish to the client, tracking it until the broker acknowledges it.
+    ///
+    /// The copy of a message with volatile retention is kept by the tracking until it's
+    /// delivered, it's left in `retained` if the client couldn't take the publish.
+    async fn client_send<V>(
+        &self,
+        topic: String,
+        qos: rumqttc::QoS,
+        payload: V,
+        retained: &mut Option<VolatileItem>,
+    ) -> Result<Delivery, rumqttc::ClientError>
+    where
+        V: Into<Vec<u8>> + 'static,
+    {
+        let mut sending = self.deliveries.sending().await;
+        let delivery = sending.push(&self.eventloop, retained);
+
+        match self.client.publish(topic, qos, false, payload).await {
+            Ok(()) => {
+                sending.sent();
+
+                Ok(delivery)
+            }
+            Err(err) => {
+                sending.cancel(retained);
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Records an error returned while sending in the history.
+    fn send_failed(&self, err: Error) -> Error {
+        self.error_history.sent(&err);
+
+        if self.log_format == LogFormat::Json {
+            logging::log_error(&self.device_id, &err, true);
+        }
+
+        err
+    }
+
+    /// Returns the most recent errors returned while handling the events and sending, from the
+    /// oldest to the newest.
+    ///
+    /// The number of errors kept is configured with
+    /// [`AstarteOptions::error_history`](crate::options::AstarteOptions::error_history).
+    pub fn recent_errors(&self) -> Vec<ErrorRecord> {
+        self.error_history.records()
+    }
+
+    /// Returns the last [sequence number](crate::sequence) sent on the interface, `None` if the
+    /// interface has no sequence or no object was sent since the device started.
+    pub async fn last_sequence(&self, interface_name: &str) -> Option<i64> {
+        self.sequences.get(interface_name)?.last().await
+    }
+
+    /// Returns the counters of the buffers used to serialize the payloads.
+    ///
+    /// The size of the pool is configured with
+    /// [`AstarteOptions::buffer_pool`](crate::options::AstarteOptions::buffer_pool).
+    pub fn buffer_pool_stats(&self) -> PoolStats {
+        self.buffers.stats()
+    }
+
+    /// Log a step of a message and report it to the hook.
+    fn message_step(&self, id: MessageId, interface: &str, path: &str, stage: MessageStage) {
+        trace!("message {id} on {interface}{path}: {stage:?}");
+
+        if self.log_format == LogFormat::Json {
+            logging::log_message(&self.device_id, id, interface, path, stage);
+        }
+
+        #[cfg(feature = "otel")]
+        self.tracing.step(id, interface, path, stage);
+
+        if let Some(hook) = &self.message_hook {
+            hook(&MessageEvent {
+                id,
+                interface,
+                path,
+                stage,
+            });
+        }
+    }
+
     async fn subscribe(&self) -> Result<(), Error> {
         let ifaces = &self.interfaces.read().await;
         let server_owned_ifaces = ifaces
@@ -245,6 +1040,12 @@ impl AstarteDeviceSdk {
             .await?;
 
         for iface in server_owned_ifaces {
+            if !iface.is_property() && self.is_interface_disabled(iface.interface_name()) {
+                debug!("not subscribing to the disabled interface {iface}");
+
+                continue;
+            }
+
             self.subscribe_server_owned_interface(iface).await?;
         }
 
@@ -303,265 +1104,197 @@ impl AstarteDeviceSdk {
         Ok(())
     }
 
-    async fn add_interface_to_introspection(&self, interface: Interface) -> Result<(), Error> {
-        self.interfaces.write().await.add(interface)?;
+    /// Download the interfaces from the [schema registry](crate::registry) and add them to the
+    /// device, returning the names of the interfaces added or updated.
+    ///
+    /// The bundle is applied only if all its interfaces are new or valid new versions of the
+    /// interfaces of the device. The introspection is sent once, if any interface changed.
+    pub async fn update_interfaces(&self, registry: &SchemaRegistry) -> Result<Vec<String>, Error> {
+        let interfaces = registry.fetch().await?;
 
-        Ok(())
+        self.apply_interfaces(interfaces).await
     }
 
-    /// Remove the interface with the name specified as argument.
-    pub async fn remove_interface(&self, interface_name: &str) -> Result<(), Error> {
-        let interface = self.remove_interface_from_map(interface_name).await?;
-        self.remove_properties_from_store(interface_name).await?;
-        self.send_introspection().await?;
-        if interface.ownership() == interface::Ownership::Server {
-            self.unsubscribe_server_owned_interface(&interface).await?;
+    async fn apply_interfaces(&self, interfaces: Vec<Interface>) -> Result<Vec<String>, Error> {
+        let changed = self.extend_interfaces(interfaces).await.into_result()?;
+
+        if !changed.is_empty() {
+            info!(
+                "interfaces updated from the registry: {}",
+                changed.join(", ")
+            );
         }
-        Ok(())
-    }
 
-    async fn remove_interface_from_map(&self, interface_name: &str) -> Result<Interface, Error> {
-        self.interfaces
-            .write()
-            .await
-            .remove(interface_name)
-            .ok_or_else(|| {
-                Error::Interface(InterfaceError::InterfaceNotFound {
-                    name: interface_name.to_string(),
-                })
-            })
+        Ok(changed)
     }
 
-    /// Poll updates from mqtt, can be placed in a loop to receive data.
+    /// Adds or updates a batch of interfaces atomically, returning the result of each one.
     ///
-    /// This is a blocking function. It should be placed on a dedicated thread/task.
+    /// All the interfaces are validated before changing the device, so a batch with an invalid
+    /// interface, or an invalid new version, is rejected as a whole. The new server-owned
+    /// interfaces are then subscribed and the introspection is sent once. If any of these steps
+    /// fails, the subscriptions made are removed and the previous interfaces are restored.
     ///
     /// ```no_run
-    /// use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions};
+    /// use std::str::FromStr;
+    ///
+    /// use astarte_device_sdk::{interface::Interface, options::AstarteOptions, AstarteDeviceSdk};
     ///
     /// #[tokio::main]
     /// async fn main() {
-    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_");
-    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+    ///     let sdk_options = AstarteOptions::new("_","_","_","_");
+    ///     let device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
     ///
-    ///     loop {
-    ///         match device.handle_events().await {
-    ///             Ok(data) => {
-    ///                 // React to received data
-    ///             }
-    ///             Err(err) => {
-    ///                 // React to reception error
-    ///             }
-    ///         }
+    ///     let interfaces = ["/path/to/first.json", "/path/to/second.json"]
+    ///         .iter()
+    ///         .map(|path| Interface::from_file(std::path::Path::new(path)).unwrap());
+    ///
+    ///     let report = device.extend_interfaces(interfaces).await;
+    ///     for (name, res) in &report.interfaces {
+    ///         println!("{name}: {res:?}");
     ///     }
     /// }
     /// ```
-    pub async fn handle_events(&mut self) -> Result<AstarteDeviceDataEvent, Error> {
-        loop {
-            // keep consuming and processing packets until we have data for the user
-            match self.eventloop.lock().await.poll().await? {
-                Event::Incoming(incoming) => {
-                    trace!("MQTT Incoming = {:?}", incoming);
-
-                    match incoming {
-                        rumqttc::Packet::ConnAck(conn_ack) => {
-                            self.connack(conn_ack).await?;
+    pub async fn extend_interfaces<I>(&self, interfaces: I) -> ExtendReport
+    where
+        I: IntoIterator<Item = Interface>,
+    {
+        let mut report = ExtendReport::default();
+        // previous version of the interfaces changed and the new server-owned ones
+        let mut changed = Vec::new();
+        let mut subscribe = Vec::new();
+
+        {
+            let mut current = self.interfaces.write().await;
+
+            // the batch is validated on a copy, so also the repeated interfaces are checked
+            let mut staged = current.clone();
+
+            for interface in interfaces {
+                let name = interface.interface_name().to_string();
+
+                let res = match staged.get(&name) {
+                    Some(prev) if *prev == interface => Ok(InterfaceChange::Unchanged),
+                    prev => {
+                        let change = if prev.is_some() {
+                            InterfaceChange::Updated
+                        } else {
+                            InterfaceChange::Added
+                        };
+
+                        if change == InterfaceChange::Added
+                            && interface.ownership() == interface::Ownership::Server
+                        {
+                            subscribe.push(interface.clone());
                         }
-                        rumqttc::Packet::Publish(publish) => {
-                            let (_, _, interface, path) = parse_topic(&publish.topic)?;
-
-                            // It can be borrowed as a &[u8]
-                            let bdata = publish.payload;
-
-                            if interface == "control" && path == "/consumer/properties" {
-                                debug!("Purging properties");
-
-                                self.purge_properties(&bdata).await?;
-
-                                continue;
-                            }
-
-                            debug!("Incoming publish = {} {:?}", publish.topic, bdata);
-
-                            let data = payload::deserialize(&bdata)?;
-
-                            self.handle_payload(interface, &path, &data).await?;
 
-                            if cfg!(debug_assertions) {
-                                self.interfaces
-                                    .read()
-                                    .await
-                                    .validate_receive(interface, &path, &bdata)?;
+                        staged.add(interface).map(|prev| {
+                            if !changed.iter().any(|(changed, _)| *changed == name) {
+                                changed.push((name.clone(), prev));
                             }
 
-                            return Ok(AstarteDeviceDataEvent {
-                                interface: interface.to_string(),
-                                path: path.to_string(),
-                                data,
-                            });
-                        }
-                        _ => {}
+                            change
+                        })
                     }
-                }
-                Event::Outgoing(o) => trace!("MQTT Outgoing = {:?}", o),
-            }
-        }
-    }
-
-    /// Handles a payload received from the broker.
-    async fn handle_payload<'a>(
-        &self,
-        interface: &str,
-        path: &MappingPath<'a>,
-        payload: &Aggregation,
-    ) -> Result<(), Error> {
-        match payload {
-            Aggregation::Object(_) => Ok(()),
-            Aggregation::Individual(ref data) => {
-                let r_interfaces = self.interfaces.read().await;
-                let interface = r_interfaces
-                    .get_property(interface)
-                    .filter(|interface| interface.mapping(path).is_some());
-
-                if let Some(property) = interface {
-                    self.store_property(property, path, data).await?;
-                }
+                };
 
-                Ok(())
+                report.interfaces.push((name, res));
             }
-        }
-    }
-
-    /// Store the property.
-    async fn store_property<'a>(
-        &self,
-        interface: PropertyRef<'a>,
-        path: &MappingPath<'a>,
-        data: &AstarteType,
-    ) -> Result<(), Error> {
-        debug_assert!(interface.is_property());
-        debug_assert!(interface.mapping(path).is_some());
 
-        //if database is loaded
-        if let Some(database) = &self.database {
-            let interface_name = interface.interface_name();
-            let version_major = interface.version_major();
+            if !report.is_applied() || changed.is_empty() {
+                return report;
+            }
 
-            database
-                .store_prop(interface_name, path.as_str(), data, version_major)
-                .await?;
+            *current = staged;
+        }
 
-            // database selftest / sanity check for debug builds
-            if cfg!(debug_assertions) {
-                let stored_prop = database
-                    .load_prop(interface_name, path.as_str(), version_major)
-                    .await
-                    .expect("load_prop failed")
-                    .expect("property wasn't correctly saved in the database");
+        if let Err(err) = self.commit_interfaces(&subscribe).await {
+            error!("couldn't extend the interfaces, rolling back: {err}");
 
-                assert_eq!(*data, stored_prop);
-                let prop = self
-                    .property(interface_name, path)
-                    .await?
-                    .expect("property wasn't correctly saved in the database");
-                assert_eq!(*data, prop);
-                trace!("database test ok");
+            let mut current = self.interfaces.write().await;
+            for (name, prev) in changed {
+                current.restore(&name, prev);
             }
-        }
 
-        Ok(())
-    }
+            report.error = Some(err);
+        }
 
-    fn client_id(&self) -> String {
-        format!("{}/{}", self.realm, self.device_id)
+        report
     }
 
-    async fn purge_properties(&self, bdata: &[u8]) -> Result<(), Error> {
-        if let Some(db) = &self.database {
-            let stored_props = db.load_all_props().await?;
+    /// Subscribes the new server-owned interfaces and sends the introspection, removing the
+    /// subscriptions made if it fails.
+    async fn commit_interfaces(&self, subscribe: &[Interface]) -> Result<(), Error> {
+        let mut subscribed = Vec::new();
 
-            let paths = properties::extract_set_properties(bdata)?;
+        let res = async {
+            for interface in subscribe {
+                self.subscribe_server_owned_interface(interface).await?;
+                subscribed.push(interface);
+            }
 
-            for stored_prop in stored_props {
-                if paths.contains(&(stored_prop.interface.clone() + &stored_prop.path)) {
-                    continue;
+            self.send_introspection().await
+        }
+        .await;
+
+        if res.is_err() {
+            for interface in subscribed {
+                if let Err(err) = self.unsubscribe_server_owned_interface(interface).await {
+                    warn!(
+                        "couldn't unsubscribe {} while rolling back: {err}",
+                        interface.interface_name()
+                    );
                 }
-
-                db.delete_prop(&stored_prop.interface, &stored_prop.path)
-                    .await?;
             }
         }
 
-        Ok(())
+        res
     }
 
-    async fn send_emptycache(&self) -> Result<(), Error> {
-        let url = self.client_id() + "/control/emptyCache";
-        debug!("sending emptyCache to {}", url);
-
-        self.client
-            .publish(url, rumqttc::QoS::ExactlyOnce, false, "1")
-            .await?;
+    async fn add_interface_to_introspection(&self, interface: Interface) -> Result<(), Error> {
+        self.interfaces.write().await.add(interface)?;
 
         Ok(())
     }
 
-    async fn send_introspection(&self) -> Result<(), Error> {
-        let interfaces = self.interfaces.read().await;
-        let introspection = interfaces.get_introspection_string();
-
-        debug!("sending introspection = {}", introspection);
-
-        self.client
-            .publish(
-                self.client_id(),
-                rumqttc::QoS::ExactlyOnce,
-                false,
-                introspection,
-            )
-            .await?;
+    /// Remove the interface with the name specified as argument.
+    pub async fn remove_interface(&self, interface_name: &str) -> Result<(), Error> {
+        let interface = self.remove_interface_from_map(interface_name).await?;
+        self.disabled_interfaces
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(interface_name);
+        self.ordered_publishes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(interface_name);
+        self.prune_interface(interface_name).await?;
+        self.send_introspection().await?;
+        if interface.ownership() == interface::Ownership::Server {
+            self.unsubscribe_server_owned_interface(&interface).await?;
+        }
         Ok(())
     }
 
-    async fn send_device_owned_properties(&self) -> Result<(), Error> {
-        if let Some(database) = &self.database {
-            let properties = database.load_all_props().await?;
-            // publish only device-owned properties...
-            let interfaces = self.interfaces.read().await;
-            let device_owned_properties: Vec<StoredProp> = properties
-                .into_iter()
-                .filter(|prop| match interfaces.get_property(&prop.interface) {
-                    Some(interface) => {
-                        interface.ownership() == Ownership::Device
-                            && interface.version_major() == prop.interface_major
-                    }
-                    None => false,
+    async fn remove_interface_from_map(&self, interface_name: &str) -> Result<Interface, Error> {
+        self.interfaces
+            .write()
+            .await
+            .remove(interface_name)
+            .ok_or_else(|| {
+                Error::Interface(InterfaceError::InterfaceNotFound {
+                    name: interface_name.to_string(),
                 })
-                .collect();
-            for prop in device_owned_properties {
-                let topic = format!("{}/{}{}", self.client_id(), prop.interface, prop.path);
-                if let Some(version_major) = self.interfaces.read().await.get_property_major(
-                    &prop.interface,
-                    &MappingPath::try_from(prop.path.as_str())?,
-                ) {
-                    // ..and only if they are up-to-date
-                    if version_major == prop.interface_major {
-                        debug!(
-                            "sending device-owned property = {}{}",
-                            prop.interface, prop.path
-                        );
-                        self.client
-                            .publish(topic, rumqttc::QoS::ExactlyOnce, false, prop.value)
-                            .await?;
-                    }
-                }
-            }
-        }
-
-        Ok(())
+            })
     }
 
-    /// Unset a device property.
+    /// Enable or disable an interface, without changing the introspection.
+    ///
+    /// While an interface is disabled the data sent on it is dropped, instead of being published
+    /// or kept in the retention, and the events received on it are not returned by
+    /// [`handle_events()`](AstarteDeviceSdk::handle_events). The topics of a server-owned
+    /// datastream are also unsubscribed, and subscribed again once it's enabled. The server-owned
+    /// properties are still stored, so the store is up to date when the interface is enabled.
     ///
     /// ```no_run
     /// use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions};
@@ -572,444 +1305,2265 @@ impl AstarteDeviceSdk {
     ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
     ///
     ///     device
-    ///         .unset("my.interface.name", "/endpoint/path",)
+    ///         .set_interface_enabled("com.example.Diagnostics", false)
     ///         .await
     ///         .unwrap();
     /// }
     /// ```
-    pub async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), Error> {
-        trace!("unsetting {} {}", interface_name, interface_path);
+    pub async fn set_interface_enabled(
+        &self,
+        interface_name: &str,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        let interfaces = self.interfaces.read().await;
+        let interface = interfaces.get(interface_name).ok_or_else(|| {
+            Error::Interface(InterfaceError::InterfaceNotFound {
+                name: interface_name.to_string(),
+            })
+        })?;
 
-        let path = MappingPath::try_from(interface_path)?;
+        let changed = {
+            let mut disabled = self
+                .disabled_interfaces
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
 
-        if cfg!(debug_assertions) {
-            self.interfaces
-                .read()
-                .await
-                .validate_send(interface_name, &path, &[], &None)?;
+            if enabled {
+                disabled.remove(interface_name)
+            } else {
+                disabled.insert(interface_name.to_string())
+            }
+        };
+
+        if !changed {
+            return Ok(());
         }
 
-        self.send_with_timestamp_impl(interface_name, &path, AstarteType::Unset, None)
-            .await?;
+        info!(
+            "interface {interface_name} {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+
+        if interface.ownership() == Ownership::Server && !interface.is_property() {
+            if enabled {
+                self.subscribe_server_owned_interface(interface).await?;
+            } else {
+                self.unsubscribe_server_owned_interface(interface).await?;
+            }
+        }
 
         Ok(())
     }
 
-    /// When present get property from the allocated database (if allocated).
-    ///
-    /// ```no_run
-    /// use astarte_device_sdk::{
-    ///     AstarteDeviceSdk, database::AstarteSqliteDatabase, options::AstarteOptions,
-    ///     types::AstarteType
-    /// };
-    ///
-    /// #[tokio::main]
-    /// async fn main() {
-    ///     let database = AstarteSqliteDatabase::new("path/to/database/file.sqlite")
-    ///         .await
-    ///         .unwrap();
-    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_").database(database);
-    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
-    ///
-    ///     let property_value: Option<AstarteType> = device
-    ///         .get_property("my.interface.name", "/endpoint/path",)
-    ///         .await
-    ///         .unwrap();
-    /// }
-    /// ```
-    pub async fn get_property(
-        &self,
-        interface: &str,
-        path: &str,
-    ) -> Result<Option<AstarteType>, Error> {
-        let path_mappings = MappingPath::try_from(path)?;
+    /// Returns `true` if the interface was disabled with
+    /// [`set_interface_enabled()`](AstarteDeviceSdk::set_interface_enabled).
+    pub fn is_interface_disabled(&self, interface_name: &str) -> bool {
+        self.disabled_interfaces
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(interface_name)
+    }
 
-        self.property(interface, &path_mappings).await
+    /// Returns the description and documentation of the mapping of an interface matching the
+    /// path, see [`Interface::mapping_docs`].
+    pub async fn mapping_docs(&self, interface_name: &str, path: &str) -> Option<MappingDocs> {
+        self.interfaces
+            .read()
+            .await
+            .get(interface_name)?
+            .mapping_docs(path)
     }
 
-    /// When present get property from the allocated database (if allocated).
+    /// Returns a handle to send data on a single interface, with its own counters, rate limit and
+    /// retention quota.
     ///
-    /// This will use a [`MappingPath`] to get the property, which is an parsed endpoint.
-    pub(crate) async fn property<'a>(
+    /// See the [`handle`] module for an example.
+    pub async fn interface_handle(
         &self,
-        interface: &str,
-        path: &MappingPath<'a>,
-    ) -> Result<Option<AstarteType>, Error> {
-        let db = match &self.database {
-            Some(db) => db,
-            None => return Ok(None),
-        };
+        interface_name: &str,
+    ) -> Result<InterfaceHandle<S>, Error> {
+        if self.interfaces.read().await.get(interface_name).is_none() {
+            return Err(Error::Interface(InterfaceError::InterfaceNotFound {
+                name: interface_name.to_string(),
+            }));
+        }
 
-        let major = self
-            .interfaces
-            .read()
-            .await
-            .get_property_major(interface, path);
+        Ok(InterfaceHandle::new(self.clone(), interface_name))
+    }
 
-        match major {
-            Some(major) => db.load_prop(interface, path.as_str(), major).await,
-            None => Ok(None),
+    /// Drops a message sent on a disabled interface, returning `true` if it was dropped.
+    fn drop_disabled(&self, interface_name: &str, interface_path: &MappingPath) -> bool {
+        if !self.is_interface_disabled(interface_name) {
+            return false;
         }
-    }
 
-    // ------------------------------------------------------------------------
-    // individual types
-    // ------------------------------------------------------------------------
+        let path = interface_path.as_str();
 
-    /// Send an individual datastream/property on an interface.
-    ///
-    /// The usage is the same of
-    /// [send_with_timestamp()][crate::AstarteDeviceSdk::send_with_timestamp],
-    /// without the timestamp.
-    pub async fn send<D>(
-        &self,
-        interface_name: &str,
-        interface_path: &str,
-        data: D,
-    ) -> Result<(), Error>
-    where
-        D: TryInto<AstarteType>,
-    {
-        let path = MappingPath::try_from(interface_path)?;
+        trace!("dropped message on the disabled interface {interface_name}{path}");
 
-        self.send_with_timestamp_impl(interface_name, &path, data, None)
-            .await
+        self.message_step(
+            MessageId::new(),
+            interface_name,
+            path,
+            MessageStage::Dropped,
+        );
+
+        true
     }
 
-    /// Send an individual datastream/property on an interface, with an explicit timestamp.
+    /// Poll updates from mqtt, can be placed in a loop to receive data.
+    ///
+    /// This is a blocking function. It should be placed on a dedicated thread/task.
     ///
     /// ```no_run
     /// use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions};
-    /// use chrono::{TimeZone, Utc};
     ///
     /// #[tokio::main]
     /// async fn main() {
     ///     let mut sdk_options = AstarteOptions::new("_","_","_","_");
     ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
     ///
-    ///     let value: i32 = 42;
-    ///     let timestamp = Utc.timestamp_opt(1537449422, 0).unwrap();
-    ///     device.send_with_timestamp("my.interface.name", "/endpoint/path", value, timestamp)
-    ///         .await
-    ///         .unwrap();
-    /// }
+    ///     loop {
+    ///         match device.handle_events().await {
+    ///             Ok(data) => {
+    ///                 // React to received data
+    ///             }
+    ///             Err(err) => {
+    ///                 // React to reception error
+    ///             }
+    ///         }
+    ///     }
+    /// }
     /// ```
-    pub async fn send_with_timestamp<D>(
-        &self,
-        interface_name: &str,
-        interface_path: &str,
-        data: D,
-        timestamp: chrono::DateTime<chrono::Utc>,
-    ) -> Result<(), Error>
-    where
-        D: TryInto<AstarteType>,
-    {
-        let mapping = MappingPath::try_from(interface_path)?;
+    pub async fn handle_events(&mut self) -> Result<AstarteDeviceDataEvent, Error> {
+        match &*self.status.borrow() {
+            DeviceStatus::Running => {}
+            DeviceStatus::Failed { reason } => return Err(Error::Terminated(reason.clone())),
+            DeviceStatus::Stopped => {
+                return Err(Error::Terminated("the device was shut down".to_string()))
+            }
+        }
 
-        self.send_with_timestamp_impl(interface_name, &mapping, data, Some(timestamp))
-            .await
+        self.next_event().await.map_err(|err| {
+            self.error_history.received(&err);
+
+            if self.log_format == LogFormat::Json {
+                logging::log_error(&self.device_id, &err, false);
+            }
+
+            err
+        })
     }
 
-    async fn send_with_timestamp_impl<'a, D>(
-        &self,
-        interface_name: &str,
-        interface_path: &MappingPath<'a>,
-        data: D,
-        timestamp: Option<chrono::DateTime<chrono::Utc>>,
-    ) -> Result<(), Error>
-    where
-        D: TryInto<AstarteType>,
-    {
-        debug!("sending {} {}", interface_name, interface_path);
+    async fn next_event(&mut self) -> Result<AstarteDeviceDataEvent, Error> {
+        loop {
+            self.check_liveness().await;
 
-        let data = data.try_into().map_err(|_| TypeError::Conversion)?;
+            if let Some(deadline) = self.check_idle().await? {
+                return self.shutdown_on_signal(deadline).await;
+            }
 
-        let buf = payload::serialize_individual(&data, timestamp)?;
+            // the messages held by the receive rate limit are handled once it allows them
+            let received = match self.receive_limits.take_due() {
+                Some(publish) => Received::Held(publish),
+                None => {
+                    // keep consuming and processing packets until we have data for the user
+                    let polled = AssertUnwindSafe(self.poll()).catch_unwind();
+
+                    let polled = shutdown::until_signal(self.shutdown_signal.clone(), polled);
+
+                    // the event loop is released, so the shutdown can flush it
+                    let polled = tokio::select! {
+                        biased;
+                        _ = self.shutdown_requested() => None,
+                        polled = polled => Some(polled),
+                    };
+
+                    let polled = match polled {
+                        Some(Ok(polled)) => polled,
+                        Some(Err(deadline)) => return self.shutdown_on_signal(deadline).await,
+                        None => {
+                            self.stopped().await;
+
+                            return Err(Error::Terminated("the device was shut down".to_string()));
+                        }
+                    };
 
-        if cfg!(debug_assertions) {
-            self.interfaces.read().await.validate_send(
-                interface_name,
-                interface_path,
-                &buf,
-                &timestamp,
-            )?;
-        }
+                    // the state of the MQTT client is unknown after a panic
+                    let event = match polled {
+                        Ok(Ok(Some(event))) => event,
+                        // the connection became idle while polling
+                        Ok(Ok(None)) => continue,
+                        Ok(Err(err)) => {
+                            let requested = self.transport.disconnected();
 
-        let interfaces = self.interfaces.read().await;
-        let opt_property = interfaces.get_property(interface_name);
-        if let Some(property) = opt_property {
-            let stored = self
-                .check_property_already_stored(property, interface_path, &data)
-                .await?;
+                            if self.idle_disconnected() {
+                                debug!("connection closed while idle: {err}");
 
-            if stored {
-                debug!("property was already sent, no need to send it again");
-                return Ok(());
-            }
-        }
+                                continue;
+                            }
 
-        self.client
-            .publish(
-                self.client_id() + "/" + interface_name.trim_matches('/') + interface_path.as_str(),
-                self.interfaces
-                    .read()
-                    .await
-                    .get_mqtt_reliability(interface_name, interface_path),
-                false,
-                buf,
-            )
-            .await?;
+                            if requested {
+                                debug!("connection closed to reconnect: {err}");
 
-        // we store the property in the database after it has been successfully sent
-        if let Some(property) = opt_property {
-            self.store_property_on_send(property, interface_path, &data)
-                .await?;
-        }
+                                continue;
+                            }
 
-        Ok(())
-    }
+                            self.quality.connection_lost();
 
-    /// Check if a property is already stored in the database with the same value.
-    /// Useful to prevent sending a property twice with the same value.
-    async fn check_property_already_stored<'a>(
-        &self,
-        interface: PropertyRef<'a>,
-        interface_path: &MappingPath<'a>,
-        data: &AstarteType,
-    ) -> Result<bool, Error> {
-        let Some(ref db) = self.database else {
-            return Ok(false);
-        };
+                            return Err(err.into());
+                        }
+                        Err(panic) => {
+                            let reason =
+                                format!("MQTT event loop panicked: {}", panic_message(&*panic));
 
-        // Check the mapping exists
-        interface
-            .mapping(interface_path)
-            .ok_or_else(|| Error::SendError(format!("Mapping {interface_path} doesn't exist")))?;
+                            error!("{reason}");
 
-        // Check if already in db
-        let stored = db
-            .load_prop(interface.interface_name(), interface_path.as_str(), 0)
-            .await?;
+                            self.status.send_replace(DeviceStatus::Failed {
+                                reason: reason.clone(),
+                            });
+
+                            return Err(Error::Terminated(reason));
+                        }
+                    };
+
+                    Received::Polled(event)
+                }
+            };
+
+            #[cfg(feature = "otel")]
+            let received_at = std::time::SystemTime::now();
+
+            // the event can be partially handled after a panic, like a property stored and not
+            // delivered, so the device is stopped instead of polling the next events
+            let processed = match received {
+                Received::Polled(event) => {
+                    AssertUnwindSafe(self.handle_event(event))
+                        .catch_unwind()
+                        .await
+                }
+                Received::Held(publish) => {
+                    AssertUnwindSafe(self.handle_publish(publish, false))
+                        .catch_unwind()
+                        .await
+                }
+            };
+
+            match processed {
+                Ok(Ok(Some(event))) => {
+                    #[cfg(feature = "otel")]
+                    self.tracing.received(&event, received_at);
+
+                    return Ok(event);
+                }
+                Ok(Ok(None)) => {}
+                Ok(Err(err)) => return Err(err),
+                Err(panic) => {
+                    let reason = format!("event processing panicked: {}", panic_message(&*panic));
+
+                    error!("{reason}");
+
+                    self.status.send_replace(DeviceStatus::Failed {
+                        reason: reason.clone(),
+                    });
 
-        match stored {
-            Some(value) => Ok(value.eq(data)),
-            None => Ok(false),
+                    return Err(Error::Terminated(reason));
+                }
+            }
         }
     }
 
-    async fn store_property_on_send<'a>(
-        &self,
-        property: PropertyRef<'a>,
-        interface_path: &MappingPath<'a>,
-        data: &AstarteType,
-    ) -> Result<(), Error> {
-        let Some(ref db) = self.database else {
-            return Ok(());
-        };
-
-        property.mapping(interface_path).ok_or_else(|| {
-            Error::Interface(InterfaceError::MappingNotFound {
-                path: interface_path.to_string(),
-            })
-        })?;
+    /// Changes the options of the MQTT transport, they are applied when the device reconnects.
+    ///
+    /// The options not set keep their current value. The reconnection can be forced with
+    /// [`reconnect()`](AstarteDeviceSdk::reconnect), see the [transport](crate::transport)
+    /// module.
+    pub fn reconfigure(&self, options: TransportOptions) -> Result<(), Error> {
+        options.validate()?;
 
-        db.store_prop(
-            property.interface_name(),
-            interface_path.as_str(),
-            data,
-            property.version_major(),
-        )
-        .await?;
+        debug!("transport reconfigured: {options:?}");
 
-        debug!("Stored new property in database");
+        self.transport.reconfigure(options);
 
         Ok(())
     }
 
-    async fn remove_properties_from_store(&self, interface_name: &str) -> Result<(), Error> {
-        let db = match self.database {
-            Some(ref db) => db,
-            None => return Ok(()),
-        };
+    /// Closes the connection, so it's opened again by
+    /// [`handle_events()`](AstarteDeviceSdk::handle_events) with the options changed by
+    /// [`reconfigure()`](AstarteDeviceSdk::reconfigure).
+    ///
+    /// The messages not yet acknowledged are sent again after the reconnection. Nothing is done
+    /// if the connection is already closed.
+    pub async fn reconnect(&self) -> Result<(), Error> {
+        if !self.transport.request_reconnect() {
+            return Ok(());
+        }
 
-        let interfaces = self.interfaces.read().await;
-        let mappings = interfaces.get_property(interface_name);
+        debug!("closing the connection to reconnect");
 
-        let property = match mappings {
-            Some(property) => property,
-            None => return Ok(()),
-        };
+        if let Err(err) = self.client.disconnect().await {
+            self.transport.cancel_reconnect();
 
-        for mapping in property.iter_mappings() {
-            let path = mapping.endpoint();
-            db.delete_prop(interface_name, path).await?;
-            debug!("Stored property {}{} deleted", interface_name, path);
+            return Err(err.into());
         }
 
         Ok(())
     }
 
-    // ------------------------------------------------------------------------
-    // object types
-    // ------------------------------------------------------------------------
+    /// Poll the MQTT event loop, returns [`None`] if the connection became idle, the
+    /// [liveness check](crate::liveness) needs to run or a message held by the
+    /// [receive rate limit](crate::throttle) can be handled.
+    async fn poll(&self) -> Result<Option<Event>, rumqttc::ConnectionError> {
+        let mut eventloop = self.eventloop.lock().await;
 
-    async fn send_object_with_timestamp_impl<'a, T>(
-        &self,
-        interface_name: &str,
-        interface_path: &MappingPath<'a>,
-        data: T,
-        timestamp: Option<chrono::DateTime<chrono::Utc>>,
-    ) -> Result<(), Error>
-    where
-        T: AstarteAggregate,
-    {
-        let aggregate = data.astarte_aggregate()?;
-        let buf = payload::serialize_object(&aggregate, timestamp)?;
+        if let Some(reconnect_at) = self.transport.reconnect(&mut *eventloop) {
+            debug!("waiting the reconnect delay");
 
-        if cfg!(debug_assertions) {
-            self.interfaces.read().await.validate_send(
-                interface_name,
-                interface_path,
-                &buf,
-                &timestamp,
-            )?;
+            tokio::time::sleep_until(reconnect_at).await;
         }
 
-        let topic =
-            self.client_id() + "/" + interface_name.trim_matches('/') + interface_path.as_str();
-        let qos = self
-            .interfaces
-            .read()
-            .await
-            .get_mqtt_reliability(interface_name, interface_path);
+        let idle = self.idle_deadline();
+        let liveness = self.liveness.as_ref().map(|liveness| liveness.deadline());
+        let held = self
+            .receive_limits
+            .deadline()
+            .map(tokio::time::Instant::from_std);
 
-        self.client.publish(topic, qos, false, buf).await?;
+        let Some(deadline) = idle.into_iter().chain(liveness).chain(held).min() else {
+            return eventloop.poll().await.map(Some);
+        };
 
-        Ok(())
+        tokio::select! {
+            res = eventloop.poll() => res.map(Some),
+            _ = tokio::time::sleep_until(deadline) => Ok(None),
+        }
     }
 
-    /// Send an object datastreamy on an interface, with an explicit timestamp.
-    ///
-    /// ```no_run
-    /// # use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions, AstarteAggregate};
-    /// #[cfg(not(feature = "derive"))]
-    /// use astarte_device_sdk_derive::AstarteAggregate;
-    /// use chrono::{TimeZone, Utc};
-    ///
-    /// #[derive(AstarteAggregate)]
-    /// struct TestObject {
-    ///     endpoint1: f64,
-    ///     endpoint2: bool,
-    /// }
-    ///
-    /// #[tokio::main]
-    /// async fn main() {
-    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_");
-    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
-    ///
-    ///     let data = TestObject {
-    ///         endpoint1: 1.34,
-    ///         endpoint2: false
-    ///     };
-    ///     let timestamp = Utc.timestamp_opt(1537449422, 0).unwrap();
-    ///     device.send_object_with_timestamp("my.interface.name", "/endpoint/path", data, timestamp)
-    ///         .await
-    ///         .unwrap();
-    /// }
-    /// ```
-    pub async fn send_object_with_timestamp<T>(
-        &self,
-        interface_name: &str,
-        interface_path: &str,
-        data: T,
-        timestamp: chrono::DateTime<chrono::Utc>,
-    ) -> Result<(), Error>
-    where
-        T: AstarteAggregate,
-    {
-        let path = MappingPath::try_from(interface_path)?;
+    /// Sends the ping of the liveness check, if it's time for the next one.
+    async fn check_liveness(&self) {
+        let Some(liveness) = &self.liveness else {
+            return;
+        };
 
-        self.send_object_with_timestamp_impl(interface_name, &path, data, Some(timestamp))
+        let Some(ping) = liveness.tick() else {
+            return;
+        };
+
+        let config = &liveness.config;
+
+        debug!(
+            "sending liveness ping {ping} on {}{}",
+            config.ping_interface, config.ping_path
+        );
+
+        // a ping not sent is not echoed, so the check will be broken after the timeout
+        if let Err(err) = self
+            .send(&config.ping_interface, &config.ping_path, ping)
             .await
+        {
+            warn!("couldn't send the liveness ping: {err}");
+        }
     }
 
-    /// Send an object datastream on an interface.
+    /// Returns the status of the end-to-end connectivity, if the
+    /// [liveness check](crate::liveness) is configured.
+    pub fn liveness(&self) -> Option<LivenessStatus> {
+        self.liveness.as_ref().map(|liveness| liveness.status())
+    }
+
+    /// Returns a receiver for the status of the device.
     ///
-    /// The usage is the same of
-    /// [send_object_with_timestamp()][crate::AstarteDeviceSdk::send_object_with_timestamp],
-    /// without the timestamp.
-    pub async fn send_object<T>(
-        &self,
-        interface_name: &str,
-        interface_path: &str,
-        data: T,
-    ) -> Result<(), Error>
-    where
-        T: AstarteAggregate,
-    {
-        let path = MappingPath::try_from(interface_path)?;
+    /// The device transitions to [`DeviceStatus::Failed`] when it can't recover from an
+    /// internal failure, after that [`handle_events()`](AstarteDeviceSdk::handle_events) will
+    /// always return an [`Error::Terminated`].
+    pub fn status(&self) -> tokio::sync::watch::Receiver<DeviceStatus> {
+        self.status.subscribe()
+    }
 
-        self.send_object_with_timestamp_impl(interface_name, &path, data, None)
-            .await
+    /// Returns the features supported by the cluster, see the
+    /// [`capabilities`](crate::capabilities) module.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
     }
-}
 
-impl fmt::Debug for AstarteDeviceSdk {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("AstarteDeviceSdk")
-            .field("realm", &self.realm)
-            .field("device_id", &self.device_id)
-            .field("interfaces", &self.interfaces)
-            .finish_non_exhaustive()
+    /// Runs the non-destructive checks of the readiness of the device, see the
+    /// [`selftest`](crate::selftest) module.
+    pub async fn self_test(&self) -> SelfTestReport {
+        let timestamp = chrono::Utc::now();
+        let connection = self.connection.as_deref();
+
+        let checks = vec![
+            selftest::check_store(self.database.as_deref()).await,
+            selftest::check_certificate(connection, chrono::Utc::now()),
+            selftest::check_broker(connection).await,
+            selftest::check_clock(connection, chrono::Utc::now()),
+            selftest::check_interfaces(&*self.interfaces.read().await),
+        ];
+
+        for result in checks
+            .iter()
+            .filter(|result| result.status == CheckStatus::Failed)
+        {
+            warn!("self test {} failed: {}", result.check, result.message);
+        }
+
+        SelfTestReport { timestamp, checks }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use base64::Engine;
-    use mockall::predicate;
-    use rumqttc::Event;
-    use std::collections::HashMap;
-    use std::str::FromStr;
-    use std::sync::Arc;
-    use tokio::sync::{Mutex, RwLock};
+    /// Process an MQTT event, returning the data for the user if any.
+    async fn handle_event(&self, event: Event) -> Result<Option<AstarteDeviceDataEvent>, Error> {
+        self.deliveries.handle(&event);
 
-    use crate::interfaces::Interfaces;
-    use crate::properties::tests::PROPERTIES_PAYLOAD;
-    use crate::{self as astarte_device_sdk, payload, Interface};
-    use astarte_device_sdk::AstarteAggregate;
-    use astarte_device_sdk::{types::AstarteType, Aggregation, AstarteDeviceSdk};
-    use astarte_device_sdk_derive::astarte_aggregate;
-    #[cfg(not(feature = "derive"))]
-    use astarte_device_sdk_derive::AstarteAggregate;
+        let incoming = match event {
+            Event::Incoming(incoming) => incoming,
+            Event::Outgoing(o) => {
+                trace!("MQTT Outgoing = {:?}", o);
 
-    use super::{AsyncClient, EventLoop};
+                if let rumqttc::Outgoing::Publish(pkid) = o {
+                    self.quality.published(pkid);
+                }
 
-    // Interfaces
-    const OBJECT_DEVICE_DATASTREAM: &str = include_str!("../examples/object_datastream/interfaces/org.astarte-platform.rust.examples.object-datastream.DeviceDatastream.json");
-    const INDIVIDUAL_SERVER_DATASTREAM: &str = include_str!("../examples/individual_datastream/interfaces/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream.json");
-    const DEVICE_PROPERTIES: &str = include_str!("../examples/individual_properties/interfaces/org.astarte-platform.rust.examples.individual-properties.DeviceProperties.json");
-    const SERVER_PROPERTIES: &str = include_str!("../examples/individual_properties/interfaces/org.astarte-platform.rust.examples.individual-properties.ServerProperties.json");
+                return Ok(None);
+            }
+        };
 
-    fn mock_astarte_device<I>(
-        client: AsyncClient,
-        eventloop: EventLoop,
-        interfaces: I,
-    ) -> AstarteDeviceSdk
-    where
-        I: IntoIterator<Item = Interface>,
-    {
-        AstarteDeviceSdk {
-            realm: "realm".to_string(),
-            device_id: "device_id".to_string(),
-            client,
-            database: None,
-            interfaces: Arc::new(RwLock::new(Interfaces::from(interfaces).unwrap())),
-            eventloop: Arc::new(Mutex::new(eventloop)),
-        }
-    }
+        trace!("MQTT Incoming = {:?}", incoming);
 
-    #[derive(AstarteAggregate)]
+        let publish = match incoming {
+            rumqttc::Packet::ConnAck(conn_ack) => {
+                self.connack(conn_ack).await?;
+
+                return Ok(None);
+            }
+            rumqttc::Packet::PubAck(rumqttc::PubAck { pkid })
+            | rumqttc::Packet::PubRec(rumqttc::PubRec { pkid }) => {
+                self.quality.acked(pkid);
+
+                return Ok(None);
+            }
+            rumqttc::Packet::Publish(publish) => publish,
+            _ => return Ok(None),
+        };
+
+        self.idle_activity();
+
+        self.handle_publish(publish, true).await
+    }
+
+    /// Handles a message published by the broker, checking the
+    /// [receive rate limit](crate::throttle) if `throttle` is set.
+    async fn handle_publish(
+        &self,
+        publish: rumqttc::Publish,
+        throttle: bool,
+    ) -> Result<Option<AstarteDeviceDataEvent>, Error> {
+        let received_at = chrono::Utc::now();
+
+        let (_, _, interface, path) = parse_topic(&publish.topic)?;
+
+        if publish.retain && self.retained_policy == RetainedPolicy::Ignore {
+            debug!("ignoring retained message on {interface}{path}");
+
+            return Ok(None);
+        }
+
+        if throttle && !self.receive_limits.admit(interface, &publish) {
+            trace!("message over the receive rate limit on {interface}{path}");
+
+            return Ok(None);
+        }
+
+        // It can be borrowed as a &[u8]
+        let bdata = publish.payload;
+
+        if interface == "control" && path == "/consumer/properties" {
+            debug!("Purging properties");
+
+            self.purge_properties(&bdata)
+                .await
+                .map_err(|err| match err {
+                    Error::Properties(properties::PropertiesError::TooLarge { size, max }) => {
+                        self.event_too_large(interface, &path, size, max)
+                    }
+                    err => err,
+                })?;
+
+            return Ok(None);
+        }
+
+        if let Some(max) = self.max_event_size.filter(|max| bdata.len() > *max) {
+            return Err(self.event_too_large(interface, &path, bdata.len(), max));
+        }
+
+        debug!("Incoming publish = {} {:?}", publish.topic, bdata);
+
+        let path_accepted = self.event_filters.accepts_path(interface, path.as_str());
+
+        // the properties are stored even if the event is filtered
+        if !path_accepted
+            && self
+                .interfaces
+                .read()
+                .await
+                .get_property(interface)
+                .is_none()
+        {
+            trace!("filtered event on {interface}{path}");
+
+            return Ok(None);
+        }
+
+        let (data, timestamp) = self
+            .interfaces
+            .read()
+            .await
+            .deserialize(interface, &path, &bdata)
+            .map_err(Error::payload(
+                interface,
+                path.as_str(),
+                PayloadOperation::Deserialize,
+            ))?;
+
+        // the store keeps the encrypted values
+        let stored = self
+            .payload_encryption
+            .is_encrypted(interface)
+            .then(|| data.clone());
+
+        let data = self
+            .payload_encryption
+            .decrypt(interface, path.as_str(), data)
+            .map_err(|(path, source)| {
+                warn!("couldn't decrypt value on {interface}{path}: {source}");
+
+                Error::Encryption {
+                    interface: interface.to_string(),
+                    path,
+                    source,
+                }
+            })?;
+
+        if let (Some(liveness), Aggregation::Individual(value)) = (&self.liveness, &data) {
+            if liveness.config.echo_interface == interface
+                && liveness.config.echo_path == path.as_str()
+            {
+                liveness.echo(value);
+            }
+        }
+
+        if let Err(violation) = self
+            .value_constraints
+            .check(interface, path.as_str(), &data)
+        {
+            warn!(
+                "rejected value on {interface}{}: {}",
+                violation.path, violation.reason
+            );
+
+            return Err(Error::ConstraintViolation {
+                interface: interface.to_string(),
+                path: violation.path,
+                reason: violation.reason,
+            });
+        }
+
+        let stale = self.is_stale(interface, timestamp).await;
+
+        if stale == Some(StalePolicy::Drop) {
+            warn!("discarding stale event on {interface}{path}");
+
+            return Ok(None);
+        }
+
+        self.handle_payload(interface, &path, stored.as_ref().unwrap_or(&data))
+            .await?;
+
+        if self.is_interface_disabled(interface)
+            || !path_accepted
+            || !self.event_filters.accepts(interface, path.as_str(), &data)
+        {
+            return Ok(None);
+        }
+
+        if cfg!(debug_assertions) {
+            self.interfaces
+                .read()
+                .await
+                .validate_receive(interface, &path, &bdata)?;
+        }
+
+        let data = self
+            .value_transforms
+            .apply(interface, path.as_str(), data)
+            .map_err(|violation| {
+                warn!(
+                    "couldn't transform value on {interface}{}: {}",
+                    violation.path, violation.reason
+                );
+
+                Error::Transform {
+                    interface: interface.to_string(),
+                    path: violation.path,
+                    reason: violation.reason,
+                }
+            })?;
+
+        Ok(Some(AstarteDeviceDataEvent {
+            interface: interface.to_string(),
+            path: path.to_string(),
+            data,
+            stale: stale.is_some(),
+            metadata: EventMetadata {
+                received_at,
+                timestamp,
+                qos: publish.qos,
+                retain: publish.retain && self.retained_policy == RetainedPolicy::Mark,
+                duplicate: publish.dup,
+            },
+        }))
+    }
+
+    fn event_too_large(
+        &self,
+        interface: &str,
+        path: &MappingPath,
+        size: usize,
+        max: usize,
+    ) -> Error {
+        warn!("rejected message on {interface}{path}: {size} bytes, the maximum is {max}");
+
+        Error::EventTooLarge {
+            interface: interface.to_string(),
+            path: path.to_string(),
+            size,
+            max,
+        }
+    }
+
+    /// Checks if an event received on a server-owned datastream is older than the configured
+    /// window.
+    ///
+    /// Returns the policy to apply if the event is stale.
+    async fn is_stale(
+        &self,
+        interface: &str,
+        timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Option<StalePolicy> {
+        let stale_window = self.stale_window?;
+        let timestamp = timestamp?;
+
+        let server_datastream = self
+            .interfaces
+            .read()
+            .await
+            .get(interface)
+            .map_or(false, |interface| {
+                !interface.is_property() && interface.ownership() == Ownership::Server
+            });
+
+        (server_datastream && stale_window.is_stale(timestamp, chrono::Utc::now()))
+            .then_some(stale_window.policy)
+    }
+
+    /// Handles a payload received from the broker.
+    async fn handle_payload<'a>(
+        &self,
+        interface: &str,
+        path: &MappingPath<'a>,
+        payload: &Aggregation,
+    ) -> Result<(), Error> {
+        let data = match payload {
+            Aggregation::Object(_) => return Ok(()),
+            Aggregation::Individual(data) => data,
+            Aggregation::Unset => &AstarteType::Unset,
+        };
+
+        // the lock is released before storing, the retries wait and the store reads the
+        // interfaces again
+        let version_major = self
+            .interfaces
+            .read()
+            .await
+            .get_property(interface)
+            .filter(|property| property.mapping(path).is_some())
+            .map(|property| property.version_major());
+
+        if let Some(version_major) = version_major {
+            self.store_received_property(interface, version_major, path, data)
+                .await?;
+
+            self.update_twins(interface, path.as_str(), payload.as_individual());
+        }
+
+        Ok(())
+    }
+
+    /// Store a property received from the server, applying the configured
+    /// [store failure policy](crate::store_failure) on failure.
+    async fn store_received_property<'a>(
+        &self,
+        interface_name: &str,
+        version_major: i32,
+        path: &MappingPath<'a>,
+        data: &AstarteType,
+    ) -> Result<(), Error> {
+        self.store_failures
+            .store(interface_name, path.as_str(), || {
+                self.store_property(interface_name, version_major, path, data)
+            })
+            .await
+    }
+
+    /// Store the property.
+    async fn store_property<'a>(
+        &self,
+        interface_name: &str,
+        version_major: i32,
+        path: &MappingPath<'a>,
+        data: &AstarteType,
+    ) -> Result<(), Error> {
+        //if database is loaded
+        if let Some(database) = &self.database {
+            database
+                .store_prop(interface_name, path.as_str(), data, version_major)
+                .await?;
+
+            // database selftest / sanity check for debug builds
+            if cfg!(debug_assertions) {
+                let stored_prop = database
+                    .load_prop(interface_name, path.as_str(), version_major)
+                    .await?;
+                debug_assert_eq!(
+                    Some(data),
+                    stored_prop.as_ref(),
+                    "property wasn't correctly saved in the database"
+                );
+
+                let prop = self.property(interface_name, path).await?;
+                debug_assert_eq!(
+                    Some(data),
+                    prop.as_ref(),
+                    "property wasn't correctly saved in the database"
+                );
+                trace!("database test ok");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn client_id(&self) -> String {
+        format!("{}/{}", self.realm, self.device_id)
+    }
+
+    async fn purge_properties(&self, bdata: &[u8]) -> Result<(), Error> {
+        if let Some(db) = &self.database {
+            let stored_props = db.load_all_props().await?;
+
+            let paths = properties::extract_set_properties(bdata, self.max_event_size)?;
+
+            for stored_prop in stored_props {
+                if paths.contains(&(stored_prop.interface.clone() + &stored_prop.path)) {
+                    continue;
+                }
+
+                db.delete_prop(&stored_prop.interface, &stored_prop.path)
+                    .await?;
+
+                self.update_twins(&stored_prop.interface, &stored_prop.path, None);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn send_emptycache(&self) -> Result<(), Error> {
+        let url = self.client_id() + "/control/emptyCache";
+        debug!("sending emptyCache to {}", url);
+
+        self.client_send(url, rumqttc::QoS::ExactlyOnce, "1", &mut None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn send_introspection(&self) -> Result<(), Error> {
+        let introspection = self.interfaces.read().await.introspection();
+
+        debug!("sending introspection = {}", introspection);
+
+        self.client_send(
+            self.client_id(),
+            rumqttc::QoS::ExactlyOnce,
+            introspection.to_string(),
+            &mut None,
+        )
+        .await?;
+
+        *self.announced_introspection.write().await = Some(introspection);
+
+        Ok(())
+    }
+
+    /// Returns the current introspection of the device.
+    ///
+    /// This represents the interfaces added to the device, which could differ from the ones last
+    /// announced to Astarte, see [`AstarteDeviceSdk::introspection_diff`].
+    pub async fn introspection(&self) -> Introspection {
+        self.interfaces.read().await.introspection()
+    }
+
+    /// Checks the current introspection against the interfaces installed in the realm, see the
+    /// [discovery](crate::discovery) module.
+    pub async fn check_introspection(
+        &self,
+        realm: &RealmManagement,
+    ) -> Result<IntrospectionMismatch, Error> {
+        let introspection = self.introspection().await;
+
+        realm
+            .check(&self.realm, &introspection)
+            .await
+            .map_err(Error::from)
+    }
+
+    /// Returns the last introspection sent to Astarte, if any.
+    pub async fn announced_introspection(&self) -> Option<Introspection> {
+        self.announced_introspection.read().await.clone()
+    }
+
+    /// Returns the differences between the current introspection and the last one sent to
+    /// Astarte.
+    ///
+    /// If no introspection was sent, all the interfaces of the device are reported as added.
+    pub async fn introspection_diff(&self) -> IntrospectionDiff {
+        let current = self.introspection().await;
+        let announced = self.announced_introspection.read().await;
+
+        match announced.as_ref() {
+            Some(announced) => current.diff(announced),
+            None => current.diff(&Introspection::default()),
+        }
+    }
+
+    /// Send the purge properties message with the device-owned properties set on the device, so
+    /// Astarte can remove the others.
+    async fn send_purge_device_properties(&self, properties: &[StoredProp]) -> Result<(), Error> {
+        let set = properties
+            .iter()
+            .map(|prop| format!("{}{}", prop.interface, prop.path));
+
+        let payload = properties::encode_set_properties(set, self.purge_compression)?;
+
+        let url = self.client_id() + "/control/producer/properties";
+        debug!("sending purge properties to {url}");
+
+        self.client_send(url, rumqttc::QoS::ExactlyOnce, payload, &mut None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn send_device_owned_properties(&self) -> Result<(), Error> {
+        if let Some(database) = &self.database {
+            let properties = database.load_all_props().await?;
+            // publish only device-owned properties...
+            let interfaces = self.interfaces.read().await;
+            let device_owned_properties: Vec<StoredProp> = properties
+                .into_iter()
+                .filter(|prop| match interfaces.get_property(&prop.interface) {
+                    Some(interface) => {
+                        interface.ownership() == Ownership::Device
+                            && interface.version_major() == prop.interface_major
+                    }
+                    None => false,
+                })
+                .collect();
+
+            if self.capabilities.purge_properties {
+                self.send_purge_device_properties(&device_owned_properties)
+                    .await?;
+            } else {
+                debug!("the cluster doesn't support the purge properties, not sending them");
+            }
+
+            for prop in device_owned_properties {
+                let topic = format!("{}/{}{}", self.client_id(), prop.interface, prop.path);
+                if let Some(version_major) = self.interfaces.read().await.get_property_major(
+                    &prop.interface,
+                    &MappingPath::try_from(prop.path.as_str())?,
+                ) {
+                    // ..and only if they are up-to-date
+                    if version_major == prop.interface_major
+                        && !self.is_interface_disabled(&prop.interface)
+                    {
+                        debug!(
+                            "sending device-owned property = {}{}",
+                            prop.interface, prop.path
+                        );
+                        self.client_send(topic, rumqttc::QoS::ExactlyOnce, prop.value, &mut None)
+                            .await?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Unset a device property.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_");
+    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+    ///
+    ///     device
+    ///         .unset("my.interface.name", "/endpoint/path",)
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn unset(&self, interface_name: &str, interface_path: &str) -> Result<(), Error> {
+        trace!("unsetting {} {}", interface_name, interface_path);
+
+        let path = MappingPath::try_from(interface_path)?;
+
+        if cfg!(debug_assertions) {
+            self.interfaces
+                .read()
+                .await
+                .validate_send(interface_name, &path, &[], &None)?;
+        }
+
+        self.send_with_timestamp_impl(interface_name, &path, AstarteType::Unset, None, false)
+            .await?;
+
+        Ok(())
+    }
+
+    /// When present get property from the allocated database (if allocated).
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{
+    ///     AstarteDeviceSdk, database::AstarteSqliteDatabase, options::AstarteOptions,
+    ///     types::AstarteType
+    /// };
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let database = AstarteSqliteDatabase::new("path/to/database/file.sqlite")
+    ///         .await
+    ///         .unwrap();
+    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_").database(database);
+    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+    ///
+    ///     let property_value: Option<AstarteType> = device
+    ///         .get_property("my.interface.name", "/endpoint/path",)
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn get_property(
+        &self,
+        interface: &str,
+        path: &str,
+    ) -> Result<Option<AstarteType>, Error> {
+        let path_mappings = MappingPath::try_from(path)?;
+
+        self.property(interface, &path_mappings).await
+    }
+
+    /// Get a property from the allocated database, converted to a type.
+    ///
+    /// Returns `None` if the property isn't stored, and an error if it can't be converted.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{
+    ///     AstarteDeviceSdk, database::AstarteSqliteDatabase, options::AstarteOptions,
+    /// };
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let database = AstarteSqliteDatabase::new("path/to/database/file.sqlite")
+    ///         .await
+    ///         .unwrap();
+    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_").database(database);
+    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+    ///
+    ///     let enabled: Option<bool> = device
+    ///         .property_as("my.interface.name", "/endpoint/path")
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn property_as<T>(&self, interface: &str, path: &str) -> Result<Option<T>, Error>
+    where
+        T: TryFrom<AstarteType>,
+        Error: From<T::Error>,
+    {
+        let value = self
+            .get_property(interface, path)
+            .await?
+            .filter(|value| *value != AstarteType::Unset);
+
+        value.map(T::try_from).transpose().map_err(Error::from)
+    }
+
+    /// When present get property from the allocated database (if allocated).
+    ///
+    /// This will use a [`MappingPath`] to get the property, which is an parsed endpoint.
+    pub(crate) async fn property<'a>(
+        &self,
+        interface: &str,
+        path: &MappingPath<'a>,
+    ) -> Result<Option<AstarteType>, Error> {
+        let db = match &self.database {
+            Some(db) => db,
+            None => return Ok(None),
+        };
+
+        let major = self
+            .interfaces
+            .read()
+            .await
+            .get_property_major(interface, path);
+
+        match major {
+            Some(major) => db.load_prop(interface, path.as_str(), major).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the stored values of a property interface by path, without the unset ones.
+    async fn interface_properties(
+        &self,
+        interface_name: &str,
+    ) -> Result<HashMap<String, AstarteType>, Error> {
+        let mut values = HashMap::new();
+
+        let Some(ref db) = self.database else {
+            return Ok(values);
+        };
+
+        let interfaces = self.interfaces.read().await;
+
+        for prop in db.load_all_props().await? {
+            if prop.interface != interface_name || !interfaces.matches_stored_prop(&prop) {
+                continue;
+            }
+
+            match payload::deserialize(&prop.value)? {
+                Aggregation::Unset => {}
+                Aggregation::Individual(value) => {
+                    values.insert(prop.path, value);
+                }
+                Aggregation::Object(_) => {
+                    return Err(Error::Reported(
+                        "BUG: extracting an object from the database".into(),
+                    ))
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Loads all the stored values of a property interface into a struct deriving
+    /// [`AstarteProperties`].
+    pub async fn load_properties<T>(&self) -> Result<T, Error>
+    where
+        T: AstarteProperties,
+    {
+        if self
+            .interfaces
+            .read()
+            .await
+            .get_property(T::INTERFACE)
+            .is_none()
+        {
+            return Err(Error::Interface(InterfaceError::InterfaceNotFound {
+                name: T::INTERFACE.to_string(),
+            }));
+        }
+
+        let values = self.interface_properties(T::INTERFACE).await?;
+
+        T::from_properties(values).map_err(|source| Error::LoadProperties {
+            interface: T::INTERFACE.to_string(),
+            source,
+        })
+    }
+
+    /// Sends the values of a struct deriving [`AstarteProperties`] that are different from the
+    /// stored ones, the `None` values are unset. Returns the number of properties sent.
+    pub async fn store_properties<T>(&self, properties: &T) -> Result<usize, Error>
+    where
+        T: AstarteProperties,
+    {
+        let stored = self.interface_properties(T::INTERFACE).await?;
+
+        let mut sent = 0;
+        for (path, value) in properties.to_properties()? {
+            if stored.get(&path) == value.as_ref() {
+                continue;
+            }
+
+            match value {
+                Some(value) => self.send(T::INTERFACE, &path, value).await?,
+                None => self.unset(T::INTERFACE, &path).await?,
+            }
+
+            sent += 1;
+        }
+
+        Ok(sent)
+    }
+
+    /// Returns a [`Twin`] mirroring the current values of the given server-owned property
+    /// interfaces.
+    ///
+    /// The twin is created from the values in the store and it's updated while the events are
+    /// handled by [`handle_events`](AstarteDeviceSdk::handle_events).
+    pub async fn twin(&self, interfaces: &[&str]) -> Result<Twin, Error> {
+        let r_interfaces = self.interfaces.read().await;
+
+        for interface in interfaces {
+            let server_owned = r_interfaces
+                .get_property(interface)
+                .map_or(false, |property| property.ownership() == Ownership::Server);
+
+            if !server_owned {
+                return Err(Error::NotServerProperty(interface.to_string()));
+            }
+        }
+
+        let twin = Twin::new(interfaces.iter().map(|interface| interface.to_string()));
+        let inner = twin.inner();
+
+        if let Some(ref db) = self.database {
+            for prop in db.load_all_props().await? {
+                if !inner.mirrors(&prop.interface) || !r_interfaces.matches_stored_prop(&prop) {
+                    continue;
+                }
+
+                if let Aggregation::Individual(value) = payload::deserialize(&prop.value)? {
+                    inner.update(&prop.interface, &prop.path, Some(value));
+                }
+            }
+        }
+
+        self.twins
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(twin.downgrade());
+
+        Ok(twin)
+    }
+
+    /// Calls the function on each twin still alive, removing the dropped ones.
+    fn for_each_twin<F>(&self, f: F)
+    where
+        F: Fn(&TwinInner),
+    {
+        self.twins
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|twin| match twin.upgrade() {
+                Some(twin) => {
+                    f(&twin);
+
+                    true
+                }
+                None => false,
+            });
+    }
+
+    /// Updates the value of a property on the twins.
+    fn update_twins(&self, interface: &str, path: &str, value: Option<&AstarteType>) {
+        self.for_each_twin(|twin| twin.update(interface, path, value.cloned()));
+    }
+
+    // ------------------------------------------------------------------------
+    // individual types
+    // ------------------------------------------------------------------------
+
+    /// Send an individual datastream/property on an interface.
+    ///
+    /// The usage is the same of
+    /// [send_with_timestamp()][crate::AstarteDeviceSdk::send_with_timestamp],
+    /// without the timestamp.
+    pub async fn send<D>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: D,
+    ) -> Result<(), Error>
+    where
+        D: TryInto<AstarteType>,
+    {
+        let path = MappingPath::try_from(interface_path)?;
+
+        self.send_with_timestamp_impl(interface_name, &path, data, None, false)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Send an individual datastream/property on an interface, publishing the property even if
+    /// it's set to the value already stored.
+    ///
+    /// The usage is the same of [send()][crate::AstarteDeviceSdk::send], see
+    /// [`PropertyPublishPolicy`] for the properties published only when changed.
+    pub async fn send_forced<D>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: D,
+    ) -> Result<(), Error>
+    where
+        D: TryInto<AstarteType>,
+    {
+        let path = MappingPath::try_from(interface_path)?;
+
+        self.send_with_timestamp_impl(interface_name, &path, data, None, true)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Send an individual datastream/property on an interface, with an explicit timestamp.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions};
+    /// use chrono::{TimeZone, Utc};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_");
+    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+    ///
+    ///     let value: i32 = 42;
+    ///     let timestamp = Utc.timestamp_opt(1537449422, 0).unwrap();
+    ///     device.send_with_timestamp("my.interface.name", "/endpoint/path", value, timestamp)
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn send_with_timestamp<D>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: D,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), Error>
+    where
+        D: TryInto<AstarteType>,
+    {
+        let mapping = MappingPath::try_from(interface_path)?;
+
+        self.send_with_timestamp_impl(interface_name, &mapping, data, Some(timestamp), false)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Send an individual value on the interface and path given by the value, like an enum
+    /// deriving [`IntoEvent`].
+    ///
+    /// An unset property is sent like with [unset()][crate::AstarteDeviceSdk::unset].
+    pub async fn send_event<E>(&self, event: E) -> Result<(), Error>
+    where
+        E: IntoEvent,
+    {
+        let event = event.into_event()?;
+
+        if event.data == AstarteType::Unset {
+            return self.unset(&event.interface, &event.path).await;
+        }
+
+        self.send(&event.interface, &event.path, event.data).await
+    }
+
+    async fn send_with_timestamp_impl<'a, D>(
+        &self,
+        interface_name: &str,
+        interface_path: &MappingPath<'a>,
+        data: D,
+        timestamp: Option<chrono::DateTime<chrono::Utc>>,
+        force: bool,
+    ) -> Result<Sent, Error>
+    where
+        D: TryInto<AstarteType>,
+    {
+        let sent = self.send_individual(interface_name, interface_path, data, timestamp, force);
+
+        #[cfg(feature = "otel")]
+        let sent = self
+            .tracing
+            .send(interface_name, interface_path.as_str(), sent);
+
+        sent.await.map_err(|err| self.send_failed(err))
+    }
+
+    async fn send_individual<'a, D>(
+        &self,
+        interface_name: &str,
+        interface_path: &MappingPath<'a>,
+        data: D,
+        timestamp: Option<chrono::DateTime<chrono::Utc>>,
+        force: bool,
+    ) -> Result<Sent, Error>
+    where
+        D: TryInto<AstarteType>,
+    {
+        debug!("sending {} {}", interface_name, interface_path);
+
+        let data: AstarteType = data.try_into().map_err(|_| TypeError::Conversion)?;
+
+        if self.drop_disabled(interface_name, interface_path) {
+            return Ok(Sent::Skipped);
+        }
+
+        // the lock is released before publishing, which waits for the connection and locks the
+        // interfaces again
+        let opt_property = self
+            .interfaces
+            .read()
+            .await
+            .get_property(interface_name)
+            .map(|property| SentProperty {
+                version_major: property.version_major(),
+                mapped: property.mapping(interface_path).is_some(),
+            });
+
+        if let Some(ref property) = opt_property {
+            let publish_unchanged = force
+                || self.property_publish_policies.get(interface_name)
+                    == PropertyPublishPolicy::Always;
+
+            if !publish_unchanged
+                && self
+                    .check_property_already_stored(interface_name, property, interface_path, &data)
+                    .await?
+            {
+                debug!("property was already sent, no need to send it again");
+                return Ok(Sent::Skipped);
+            }
+        }
+
+        // the unchanged properties are compared before encrypting, the ciphers can be randomized
+        let (data, buf) =
+            self.encode_individual(interface_name, interface_path.as_str(), data, timestamp)?;
+
+        if cfg!(debug_assertions) {
+            self.interfaces.read().await.validate_send(
+                interface_name,
+                interface_path,
+                &buf,
+                &timestamp,
+            )?;
+        }
+
+        let sent = self.publish(interface_name, interface_path, &buf).await?;
+
+        // we store the property in the database after it has been successfully sent
+        if let Some(ref property) = opt_property {
+            self.store_property_on_send(interface_name, property, interface_path, &data)
+                .await?;
+        }
+
+        Ok(sent)
+    }
+
+    /// Converts, encrypts and serializes an individual value, returning the value sent and the
+    /// payload.
+    fn encode_individual<D>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: D,
+        timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<(AstarteType, PooledBuffer<'_>), Error>
+    where
+        D: TryInto<AstarteType>,
+    {
+        let data = data.try_into().map_err(|_| TypeError::Conversion)?;
+        let data = self
+            .payload_encryption
+            .encrypt(interface_name, interface_path, data)
+            .map_err(Error::encryption(interface_name, interface_path))?;
+
+        let mut buf = self.buffers.get();
+        payload::write_individual(&mut buf, &data, timestamp).map_err(Error::payload(
+            interface_name,
+            interface_path,
+            PayloadOperation::Serialize,
+        ))?;
+
+        Ok((data, buf))
+    }
+
+    /// Checks the payload fits in the maximum size supported by the cluster.
+    fn check_payload_size(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        size: usize,
+    ) -> Result<(), Error> {
+        match self.capabilities.exceeds_payload(size) {
+            Some(max) => Err(Error::PayloadTooLarge {
+                interface: interface_name.to_string(),
+                path: interface_path.to_string(),
+                size,
+                max,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Check if a property is already stored in the database with the same value.
+    /// Useful to prevent sending a property twice with the same value.
+    ///
+    /// The stored value is decrypted before comparing it with the plaintext.
+    async fn check_property_already_stored<'a>(
+        &self,
+        interface_name: &str,
+        property: &SentProperty,
+        interface_path: &MappingPath<'a>,
+        data: &AstarteType,
+    ) -> Result<bool, Error> {
+        let Some(ref db) = self.database else {
+            return Ok(false);
+        };
+
+        // Check the mapping exists
+        if !property.mapped {
+            return Err(Error::SendError(format!(
+                "Mapping {interface_path} doesn't exist"
+            )));
+        }
+
+        // Check if already in db
+        let stored = db
+            .load_prop(
+                interface_name,
+                interface_path.as_str(),
+                property.version_major,
+            )
+            .await?;
+
+        let Some(value) = stored else {
+            return Ok(false);
+        };
+
+        let stored = match value {
+            AstarteType::Unset => Aggregation::Unset,
+            value => Aggregation::Individual(value),
+        };
+
+        let path = interface_path.as_str();
+        match self
+            .payload_encryption
+            .decrypt(interface_name, path, stored)
+        {
+            Ok(Aggregation::Individual(value)) => Ok(value.eq(data)),
+            Ok(Aggregation::Unset) => Ok(*data == AstarteType::Unset),
+            Ok(Aggregation::Object(_)) => Ok(false),
+            Err((path, err)) => {
+                // the property is sent again, for example after the key was revoked
+                warn!("couldn't decrypt the stored property {interface_name}{path}: {err}");
+
+                Ok(false)
+            }
+        }
+    }
+
+    async fn store_property_on_send<'a>(
+        &self,
+        interface_name: &str,
+        property: &SentProperty,
+        interface_path: &MappingPath<'a>,
+        data: &AstarteType,
+    ) -> Result<(), Error> {
+        let Some(ref db) = self.database else {
+            return Ok(());
+        };
+
+        if !property.mapped {
+            return Err(Error::Interface(InterfaceError::MappingNotFound {
+                path: interface_path.to_string(),
+            }));
+        }
+
+        db.store_prop(
+            interface_name,
+            interface_path.as_str(),
+            data,
+            property.version_major,
+        )
+        .await?;
+
+        debug!("Stored new property in database");
+
+        Ok(())
+    }
+
+    /// Remove the stored properties and the retained messages of a removed interface.
+    async fn prune_interface(&self, interface_name: &str) -> Result<(), Error> {
+        if let Some(ref db) = self.database {
+            db.delete_interface(interface_name).await?;
+
+            debug!("stored properties of {interface_name} deleted");
+        }
+
+        self.for_each_twin(|twin| twin.unset_interface(interface_name));
+
+        self.volatile
+            .lock()
+            .await
+            .retain(|item| item.interface != interface_name);
+
+        Ok(())
+    }
+
+    /// Remove from the store the properties and from the retention the messages that don't
+    /// match the current interfaces, for example after an interface was removed between two
+    /// releases.
+    ///
+    /// The stored properties are removed if their interface, major version or mapping doesn't
+    /// exist anymore. With `dry_run` nothing is removed, only the report is returned.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_");
+    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+    ///
+    ///     let report = device.prune_store(true).await.unwrap();
+    ///     for prop in report.properties {
+    ///         println!("stale property {}{}", prop.interface, prop.path);
+    ///     }
+    /// }
+    /// ```
+    pub async fn prune_store(&self, dry_run: bool) -> Result<PruneReport, Error> {
+        let interfaces = self.interfaces.read().await;
+
+        let mut report = PruneReport::default();
+
+        if let Some(ref db) = self.database {
+            for prop in db.load_all_props().await? {
+                if interfaces.matches_stored_prop(&prop) {
+                    continue;
+                }
+
+                if !dry_run {
+                    db.delete_prop(&prop.interface, &prop.path).await?;
+                }
+
+                debug!("pruned stale property {}{}", prop.interface, prop.path);
+
+                report.properties.push(prop);
+            }
+        }
+
+        let mut volatile = self.volatile.lock().await;
+        let stale = |item: &VolatileItem| interfaces.get(&item.interface).is_none();
+
+        report.volatile = if dry_run {
+            volatile.iter().filter(|item| stale(item)).count()
+        } else {
+            volatile.retain(|item| !stale(item))
+        };
+
+        Ok(report)
+    }
+
+    /// Imports many device-owned properties at once, like a calibration table preloaded in the
+    /// factory, see the [`import`] module.
+    ///
+    /// The entries are the interface, path and value of each property. The invalid entries are
+    /// skipped and returned in the report, the valid ones are stored together and published. An
+    /// error storing them is returned, since none of them are stored.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_");
+    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+    ///
+    ///     let offsets = (0..4096).map(|point| {
+    ///         ("com.example.Calibration", format!("/{point}/offset"), 0.0)
+    ///     });
+    ///
+    ///     let report = device.import_properties(offsets).await.unwrap();
+    ///     assert!(report.is_complete());
+    /// }
+    /// ```
+    pub async fn import_properties<I, K, P, D>(&self, properties: I) -> Result<ImportReport, Error>
+    where
+        I: IntoIterator<Item = (K, P, D)>,
+        K: AsRef<str>,
+        P: AsRef<str>,
+        D: TryInto<AstarteType>,
+    {
+        self.import_properties_with_progress(properties, |_| {})
+            .await
+    }
+
+    /// Imports many device-owned properties at once like
+    /// [`import_properties()`](AstarteDeviceSdk::import_properties), reporting the progress of
+    /// the import to the callback.
+    pub async fn import_properties_with_progress<I, K, P, D, F>(
+        &self,
+        properties: I,
+        mut progress: F,
+    ) -> Result<ImportReport, Error>
+    where
+        I: IntoIterator<Item = (K, P, D)>,
+        K: AsRef<str>,
+        P: AsRef<str>,
+        D: TryInto<AstarteType>,
+        F: FnMut(ImportProgress),
+    {
+        let Some(ref db) = self.database else {
+            return Err(Error::Reported(
+                "the properties can't be imported without a database".into(),
+            ));
+        };
+
+        let mut report = ImportReport::default();
+
+        let props = {
+            let interfaces = self.interfaces.read().await;
+
+            let mut props = Vec::new();
+            for (index, (interface, path, value)) in properties.into_iter().enumerate() {
+                let (interface, path) = (interface.as_ref(), path.as_ref());
+
+                match import::validate(
+                    &interfaces,
+                    &self.payload_encryption,
+                    interface,
+                    path,
+                    value,
+                ) {
+                    Ok(prop) => props.push(prop),
+                    Err(error) => {
+                        debug!("couldn't import the property {interface}{path}: {error}");
+
+                        report.failed.push(ImportFailure {
+                            index,
+                            interface: interface.to_string(),
+                            path: path.to_string(),
+                            error,
+                        });
+                    }
+                }
+            }
+
+            props
+        };
+
+        progress(ImportProgress::Validated {
+            valid: props.len(),
+            failed: report.failed.len(),
+        });
+
+        db.store_props(&props).await?;
+
+        report.stored = props.len();
+        info!("imported {} properties", report.stored);
+
+        progress(ImportProgress::Stored {
+            stored: report.stored,
+        });
+
+        for prop in &props {
+            if self.is_interface_disabled(&prop.interface) {
+                continue;
+            }
+
+            let topic = format!("{}/{}{}", self.client_id(), prop.interface, prop.path);
+            let payload = payload::serialize_individual(&prop.value, None)?;
+
+            let res = self
+                .client_send(topic, rumqttc::QoS::ExactlyOnce, payload, &mut None)
+                .await;
+
+            if let Err(err) = res {
+                warn!(
+                    "couldn't publish the imported properties, the remaining {} will be sent on the next connection: {err}",
+                    report.stored - report.published
+                );
+
+                break;
+            }
+
+            report.published += 1;
+
+            progress(ImportProgress::Published {
+                published: report.published,
+                total: report.stored,
+            });
+        }
+
+        Ok(report)
+    }
+
+    /// Returns the values of all the stored properties of the current interfaces, both device
+    /// and server owned, by interface and path.
+    pub async fn property_snapshot(
+        &self,
+    ) -> Result<HashMap<String, HashMap<String, AstarteType>>, Error> {
+        let mut snapshot: HashMap<String, HashMap<String, AstarteType>> = HashMap::new();
+
+        let Some(ref db) = self.database else {
+            return Ok(snapshot);
+        };
+
+        let interfaces = self.interfaces.read().await;
+
+        for prop in db.load_all_props().await? {
+            if !interfaces.matches_stored_prop(&prop) {
+                continue;
+            }
+
+            match payload::deserialize(&prop.value)? {
+                Aggregation::Unset => {}
+                Aggregation::Individual(value) => {
+                    snapshot
+                        .entry(prop.interface)
+                        .or_default()
+                        .insert(prop.path, value);
+                }
+                Aggregation::Object(_) => {
+                    return Err(Error::Reported(
+                        "BUG: extracting an object from the database".into(),
+                    ))
+                }
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Publish the [`property_snapshot`](AstarteDeviceSdk::property_snapshot) serialized as a
+    /// JSON object on a device-owned datastream mapping of type string, to inspect the state of
+    /// the device remotely.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{
+    ///     options::AstarteOptions, types::AstarteType, Aggregation, AstarteDeviceSdk,
+    /// };
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut device = AstarteDeviceSdk::new(AstarteOptions::new("_", "_", "_", "_"))
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     loop {
+    ///         let event = device.handle_events().await.unwrap();
+    ///
+    ///         if event.interface == "com.example.Debug"
+    ///             && event.data == Aggregation::Individual(AstarteType::Boolean(true))
+    ///         {
+    ///             device
+    ///                 .send_property_snapshot("com.example.Diagnostics", "/properties")
+    ///                 .await
+    ///                 .unwrap();
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn send_property_snapshot(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+    ) -> Result<(), Error> {
+        let snapshot = self.property_snapshot().await?;
+
+        let json = serde_json::to_string(&snapshot).map_err(|err| {
+            Error::SendError(format!("couldn't serialize the property snapshot: {err}"))
+        })?;
+
+        if let Some(interface) = self.interfaces.read().await.get(interface_name) {
+            if interface.is_property() {
+                return Err(Error::SendError(format!(
+                    "the property snapshot can't be sent on the property {interface_name}"
+                )));
+            }
+        }
+
+        self.send(interface_name, interface_path, json).await
+    }
+
+    /// Send an individual datastream without any delivery guarantee, for high rate and low value
+    /// data where freshness matters more than completeness.
+    ///
+    /// The message is published with QoS 0, is never kept in the volatile retention and never
+    /// waits for the client queue: if the queue is full, or the interfaces are being changed, the
+    /// message is dropped and `false` is returned. Properties can't be sent with this method.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_");
+    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+    ///
+    ///     let sample: f64 = 0.42;
+    ///     if !device.send_unreliable("my.interface.name", "/waveform", sample).await.unwrap() {
+    ///         println!("sample dropped");
+    ///     }
+    /// }
+    /// ```
+    pub async fn send_unreliable<D>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: D,
+    ) -> Result<bool, Error>
+    where
+        D: TryInto<AstarteType>,
+    {
+        let sent = self.send_unreliable_impl(interface_name, interface_path, data);
+
+        #[cfg(feature = "otel")]
+        let sent = self.tracing.send(interface_name, interface_path, sent);
+
+        sent.await.map_err(|err| self.send_failed(err))
+    }
+
+    async fn send_unreliable_impl<D>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: D,
+    ) -> Result<bool, Error>
+    where
+        D: TryInto<AstarteType>,
+    {
+        let interface_path = MappingPath::try_from(interface_path)?;
+
+        debug!("sending unreliable {} {}", interface_name, interface_path);
+
+        let (_, buf) =
+            self.encode_individual(interface_name, interface_path.as_str(), data, None)?;
+
+        let id = MessageId::new();
+        let path = interface_path.as_str();
+
+        {
+            // the interfaces are being changed, drop the message instead of waiting
+            let Ok(interfaces) = self.interfaces.try_read() else {
+                trace!(
+                    "dropped unreliable message {id} on {interface_name}{path}: interfaces locked"
+                );
+
+                self.message_step(id, interface_name, path, MessageStage::Dropped);
+
+                return Ok(false);
+            };
+
+            if interfaces.get_property(interface_name).is_some() {
+                return Err(Error::SendError(format!(
+                    "couldn't send the property {interface_name} without delivery guarantees"
+                )));
+            }
+
+            if self.drop_disabled(interface_name, &interface_path) {
+                return Ok(false);
+            }
+
+            if cfg!(debug_assertions) {
+                interfaces.validate_send(interface_name, &interface_path, &buf, &None)?;
+            }
+        }
+
+        let topic =
+            self.client_id() + "/" + interface_name.trim_matches('/') + interface_path.as_str();
+
+        let size = buf.len();
+        self.check_payload_size(interface_name, path, size)?;
+
+        self.idle_activity();
+
+        // another message is waiting for the client, it's full or the interfaces are being sent
+        let Some(mut sending) = self.deliveries.try_sending() else {
+            trace!(
+                "dropped unreliable message {id} on {interface_name}{path}: publish in progress"
+            );
+
+            self.message_step(id, interface_name, path, MessageStage::Dropped);
+
+            return Ok(false);
+        };
+
+        sending.push(&self.eventloop, &mut None);
+
+        match self
+            .client
+            .try_publish(topic, rumqttc::QoS::AtMostOnce, false, buf.to_vec())
+        {
+            Ok(()) => {
+                sending.sent();
+
+                self.throughput.record(size);
+
+                self.message_step(id, interface_name, path, MessageStage::Published);
+
+                Ok(true)
+            }
+            Err(err) => {
+                sending.cancel(&mut None);
+
+                trace!("dropped unreliable message {id} on {interface_name}{path}: {err}");
+
+                self.message_step(id, interface_name, path, MessageStage::Dropped);
+
+                Ok(false)
+            }
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // object types
+    // ------------------------------------------------------------------------
+
+    async fn send_object_with_timestamp_impl<'a, T>(
+        &self,
+        interface_name: &str,
+        interface_path: &MappingPath<'a>,
+        data: T,
+        timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Sent, Error>
+    where
+        T: AstarteAggregate,
+    {
+        let sent = self.send_aggregate(interface_name, interface_path, data, timestamp);
+
+        #[cfg(feature = "otel")]
+        let sent = self
+            .tracing
+            .send(interface_name, interface_path.as_str(), sent);
+
+        sent.await.map_err(|err| self.send_failed(err))
+    }
+
+    async fn send_aggregate<'a, T>(
+        &self,
+        interface_name: &str,
+        interface_path: &MappingPath<'a>,
+        data: T,
+        timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Sent, Error>
+    where
+        T: AstarteAggregate,
+    {
+        let mut aggregate = data.astarte_aggregate()?;
+
+        if self.drop_disabled(interface_name, interface_path) {
+            return Ok(Sent::Skipped);
+        }
+
+        // held until the object is published, to keep the order of the sequence
+        let mut _sequence = None;
+        if let Some(sequence) = self.sequences.get(interface_name) {
+            let (guard, number) = sequence
+                .next(interface_name, self.database.as_deref())
+                .await?;
+
+            aggregate.insert(sequence.field.clone(), AstarteType::LongInteger(number));
+            _sequence = Some(guard);
+        }
+
+        #[cfg(feature = "otel")]
+        if let Some(field) = self.tracing.field(interface_name) {
+            let cx = opentelemetry_api::Context::current();
+
+            if let Some(traceparent) = otel::traceparent(&cx) {
+                aggregate.insert(field.to_string(), AstarteType::String(traceparent));
+            }
+        }
+
+        let aggregate = self
+            .payload_encryption
+            .encrypt_object(interface_name, interface_path.as_str(), aggregate)
+            .map_err(Error::encryption(interface_name, interface_path.as_str()))?;
+
+        let mut buf = self.buffers.get();
+        payload::write_object(&mut buf, &aggregate, timestamp).map_err(Error::payload(
+            interface_name,
+            interface_path.as_str(),
+            PayloadOperation::Serialize,
+        ))?;
+
+        if cfg!(debug_assertions) {
+            self.interfaces.read().await.validate_send(
+                interface_name,
+                interface_path,
+                &buf,
+                &timestamp,
+            )?;
+        }
+
+        self.publish(interface_name, interface_path, &buf).await
+    }
+
+    /// Send an object datastreamy on an interface, with an explicit timestamp.
+    ///
+    /// ```no_run
+    /// # use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions, AstarteAggregate};
+    /// #[cfg(not(feature = "derive"))]
+    /// use astarte_device_sdk_derive::AstarteAggregate;
+    /// use chrono::{TimeZone, Utc};
+    ///
+    /// #[derive(AstarteAggregate)]
+    /// struct TestObject {
+    ///     endpoint1: f64,
+    ///     endpoint2: bool,
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_");
+    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+    ///
+    ///     let data = TestObject {
+    ///         endpoint1: 1.34,
+    ///         endpoint2: false
+    ///     };
+    ///     let timestamp = Utc.timestamp_opt(1537449422, 0).unwrap();
+    ///     device.send_object_with_timestamp("my.interface.name", "/endpoint/path", data, timestamp)
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn send_object_with_timestamp<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), Error>
+    where
+        T: AstarteAggregate,
+    {
+        let path = MappingPath::try_from(interface_path)?;
+
+        self.send_object_with_timestamp_impl(interface_name, &path, data, Some(timestamp))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Send an object datastream on an interface.
+    ///
+    /// The usage is the same of
+    /// [send_object_with_timestamp()][crate::AstarteDeviceSdk::send_object_with_timestamp],
+    /// without the timestamp.
+    pub async fn send_object<T>(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        data: T,
+    ) -> Result<(), Error>
+    where
+        T: AstarteAggregate,
+    {
+        let path = MappingPath::try_from(interface_path)?;
+
+        self.send_object_with_timestamp_impl(interface_name, &path, data, None)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Send the objects of a [collection](crate::collection) on an interface.
+    ///
+    /// The endpoint must end with the parameter of the collection, each object is sent on the
+    /// path with the parameter replaced by its key. The objects are sent in order, stopping at
+    /// the first error.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{AstarteDeviceSdk, AstarteAggregate};
+    /// #[cfg(not(feature = "derive"))]
+    /// use astarte_device_sdk_derive::AstarteAggregate;
+    ///
+    /// #[derive(AstarteAggregate)]
+    /// struct Item {
+    ///     name: String,
+    ///     value: f64,
+    /// }
+    ///
+    /// async fn send_items(device: &AstarteDeviceSdk, items: Vec<Item>) {
+    ///     device
+    ///         .send_object_collection("com.test.Items", "/items/%{idx}", items.into_iter().enumerate())
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn send_object_collection<I, K, T>(
+        &self,
+        interface_name: &str,
+        endpoint: &str,
+        items: I,
+    ) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = (K, T)>,
+        K: std::fmt::Display,
+        T: AstarteAggregate,
+    {
+        let collection = collection::Collection::new(endpoint)?;
+
+        for (key, data) in items {
+            let path = collection.path(key);
+            let path = MappingPath::try_from(path.as_str())?;
+
+            self.send_object_with_timestamp_impl(interface_name, &path, data, None)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the estimated quality of the connection to the broker, see the
+    /// [`quality`](crate::quality) module.
+    pub fn connection_quality(&self) -> ConnectionQuality {
+        self.quality.get()
+    }
+
+    /// Returns a snapshot of the messages in the volatile retention, waiting to be published
+    /// again.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{AstarteDeviceSdk, options::AstarteOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_");
+    ///     let device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+    ///
+    ///     let snapshot = device.queue_snapshot().await;
+    ///     println!(
+    ///         "{} bytes waiting to sync, done in {:?}",
+    ///         snapshot.bytes(),
+    ///         snapshot.estimated_replay()
+    ///     );
+    /// }
+    /// ```
+    pub async fn queue_snapshot(&self) -> QueueSnapshot {
+        let mut snapshot = QueueSnapshot {
+            interfaces: HashMap::new(),
+            throughput: self.throughput.get(),
+        };
+
+        for item in self.volatile.lock().await.iter() {
+            let timestamp = payload::deserialize_with_timestamp(&item.payload)
+                .ok()
+                .and_then(|(_, timestamp)| timestamp)
+                .unwrap_or(item.retained_at);
+
+            snapshot.push(&item.interface, item.payload.len(), Some(timestamp));
+        }
+
+        snapshot
+    }
+
+    /// Returns a snapshot like [`queue_snapshot()`](AstarteDeviceSdk::queue_snapshot),
+    /// including the entries committed in the outbox.
+    pub async fn queue_snapshot_with_outbox<O>(&self, outbox: &O) -> Result<QueueSnapshot, Error>
+    where
+        O: AstarteOutbox + Sync,
+    {
+        let mut snapshot = self.queue_snapshot().await;
+
+        for entry in outbox.pending().await? {
+            let timestamp = payload::deserialize_with_timestamp(&entry.payload)
+                .ok()
+                .and_then(|(_, timestamp)| timestamp);
+
+            snapshot.push(&entry.interface, entry.payload.len(), timestamp);
+        }
+
+        Ok(snapshot)
+    }
+}
+
+impl<S: ?Sized> fmt::Debug for AstarteDeviceSdk<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AstarteDeviceSdk")
+            .field("realm", &self.realm)
+            .field("device_id", &self.device_id)
+            .field("interfaces", &self.interfaces)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base64::Engine;
+    use futures::FutureExt;
+    use mockall::predicate;
+    use rumqttc::Event;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use crate::capabilities::Capabilities;
+    use crate::constraint::{ValueConstraint, ValueConstraints};
+    use crate::database::cache::CachedDatabase;
+    use crate::database::faulty::{Fault, FaultyStore, StoreOperation};
+    use crate::database::{AstarteDatabase, AstarteSqliteDatabase};
+    use crate::error::Error;
+    use crate::event;
+    use crate::filter::{EventFilter, EventFilters};
+    use crate::handle::InterfaceStats;
+    use crate::history::ErrorCategory;
+    use crate::import::{ImportError, ImportProgress};
+    use crate::interface::mapping::path::MappingPath;
+    use crate::interface::InterfaceError;
+    use crate::message::{MessageId, MessageStage};
+    use crate::mock::MockDevice;
+    use crate::options::{
+        PropertyPublishPolicy, PublishOrdering, RetainedPolicy, StalePolicy, StaleWindow,
+    };
+    use crate::properties::tests::PROPERTIES_PAYLOAD;
+    use crate::quality::ConnectionQuality;
+    use crate::retention::VolatileItem;
+    use crate::run::{self, DeviceHandler};
+    use crate::selftest::{CheckStatus, SelfTestCheck, SelfTestReport};
+    use crate::transform::{ValueTransform, ValueTransforms};
+    use crate::transport::TransportOptions;
+    use crate::{self as astarte_device_sdk, payload, Interface};
+    use astarte_device_sdk::{
+        types::AstarteType, Aggregation, AstarteDeviceDataEvent, AstarteDeviceSdk, DeviceStatus,
+        InterfaceChange, PruneReport,
+    };
+    use astarte_device_sdk::{AstarteAggregate, AstarteProperties};
+    #[cfg(not(feature = "derive"))]
+    use astarte_device_sdk_derive::{AstarteAggregate, AstarteProperties};
+
+    use super::{AsyncClient, EventLoop};
+    use async_trait::async_trait;
+
+    // Interfaces
+    pub(crate) const OBJECT_DEVICE_DATASTREAM: &str = include_str!("../examples/object_datastream/interfaces/org.astarte-platform.rust.examples.object-datastream.DeviceDatastream.json");
+    pub(crate) const INDIVIDUAL_SERVER_DATASTREAM: &str = include_str!("../examples/individual_datastream/interfaces/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream.json");
+    pub(crate) const DEVICE_PROPERTIES: &str = include_str!("../examples/individual_properties/interfaces/org.astarte-platform.rust.examples.individual-properties.DeviceProperties.json");
+    pub(crate) const SERVER_PROPERTIES: &str = include_str!("../examples/individual_properties/interfaces/org.astarte-platform.rust.examples.individual-properties.ServerProperties.json");
+
+    #[derive(AstarteAggregate)]
     #[astarte_aggregate(rename_all = "lowercase")]
     struct MyLowerCasedAggregate {
         endpoint01: f64,
@@ -1028,458 +3582,3367 @@ mod test {
         endpoint14: Vec<chrono::DateTime<chrono::Utc>>,
     }
 
-    #[test]
-    fn test_astarte_aggregate_trait_lower_case_attribute() {
-        let my_aggregate = MyLowerCasedAggregate {
-            endpoint01: 4.34,
-            endpoint02: 1,
-            endpoint03: true,
-            endpoint04: 45543543534,
-            endpoint05: "Hello".to_string(),
-            endpoint06: base64::engine::general_purpose::STANDARD
-                .decode("aGVsbG8=")
-                .unwrap(),
-            endpoint07: chrono::offset::Utc::now(),
-            endpoint08: Vec::from([43.5, 10.5, 11.9]),
-            endpoint09: Vec::from([-4, 123, -2222, 30]),
-            endpoint10: Vec::from([true, false]),
-            endpoint11: Vec::from([53267895478, 53267895428, 53267895118]),
-            endpoint12: Vec::from(["Test ".to_string(), "String".to_string()]),
-            endpoint13: Vec::from([
-                base64::engine::general_purpose::STANDARD
-                    .decode("aGVsbG8=")
-                    .unwrap(),
-                base64::engine::general_purpose::STANDARD
-                    .decode("aGVsbG8=")
-                    .unwrap(),
+    #[test]
+    fn test_astarte_aggregate_trait_lower_case_attribute() {
+        let my_aggregate = MyLowerCasedAggregate {
+            endpoint01: 4.34,
+            endpoint02: 1,
+            endpoint03: true,
+            endpoint04: 45543543534,
+            endpoint05: "Hello".to_string(),
+            endpoint06: base64::engine::general_purpose::STANDARD
+                .decode("aGVsbG8=")
+                .unwrap(),
+            endpoint07: chrono::offset::Utc::now(),
+            endpoint08: Vec::from([43.5, 10.5, 11.9]),
+            endpoint09: Vec::from([-4, 123, -2222, 30]),
+            endpoint10: Vec::from([true, false]),
+            endpoint11: Vec::from([53267895478, 53267895428, 53267895118]),
+            endpoint12: Vec::from(["Test ".to_string(), "String".to_string()]),
+            endpoint13: Vec::from([
+                base64::engine::general_purpose::STANDARD
+                    .decode("aGVsbG8=")
+                    .unwrap(),
+                base64::engine::general_purpose::STANDARD
+                    .decode("aGVsbG8=")
+                    .unwrap(),
+            ]),
+            endpoint14: Vec::from([chrono::offset::Utc::now(), chrono::offset::Utc::now()]),
+        };
+        let expected_res = HashMap::from([
+            (
+                "endpoint01".to_string(),
+                AstarteType::Double(my_aggregate.endpoint01),
+            ),
+            (
+                "endpoint02".to_string(),
+                AstarteType::Integer(my_aggregate.endpoint02),
+            ),
+            (
+                "endpoint03".to_string(),
+                AstarteType::Boolean(my_aggregate.endpoint03),
+            ),
+            (
+                "endpoint04".to_string(),
+                AstarteType::LongInteger(my_aggregate.endpoint04),
+            ),
+            (
+                "endpoint05".to_string(),
+                AstarteType::String(my_aggregate.endpoint05.clone()),
+            ),
+            (
+                "endpoint06".to_string(),
+                AstarteType::BinaryBlob(my_aggregate.endpoint06.clone()),
+            ),
+            (
+                "endpoint07".to_string(),
+                AstarteType::DateTime(my_aggregate.endpoint07),
+            ),
+            (
+                "endpoint08".to_string(),
+                AstarteType::DoubleArray(my_aggregate.endpoint08.clone()),
+            ),
+            (
+                "endpoint09".to_string(),
+                AstarteType::IntegerArray(my_aggregate.endpoint09.clone()),
+            ),
+            (
+                "endpoint10".to_string(),
+                AstarteType::BooleanArray(my_aggregate.endpoint10.clone()),
+            ),
+            (
+                "endpoint11".to_string(),
+                AstarteType::LongIntegerArray(my_aggregate.endpoint11.clone()),
+            ),
+            (
+                "endpoint12".to_string(),
+                AstarteType::StringArray(my_aggregate.endpoint12.clone()),
+            ),
+            (
+                "endpoint13".to_string(),
+                AstarteType::BinaryBlobArray(my_aggregate.endpoint13.clone()),
+            ),
+            (
+                "endpoint14".to_string(),
+                AstarteType::DateTimeArray(my_aggregate.endpoint14.clone()),
+            ),
+        ]);
+        assert_eq!(expected_res, my_aggregate.astarte_aggregate().unwrap());
+        println!("{expected_res:?}");
+    }
+
+    #[derive(AstarteAggregate)]
+    #[astarte_aggregate(rename_all = "UPPERCASE")]
+    struct MyUpperCasedAggregate {
+        first_endpoint: f64,
+        second_endpoint: f64,
+    }
+
+    #[test]
+    fn test_astarte_aggregate_trait_upper_case_attribute() {
+        let my_aggregate = MyUpperCasedAggregate {
+            first_endpoint: 4.34,
+            second_endpoint: 23.0,
+        };
+        let expected_res = HashMap::from([
+            (
+                "FIRST_ENDPOINT".to_string(),
+                AstarteType::Double(my_aggregate.first_endpoint),
+            ),
+            (
+                "SECOND_ENDPOINT".to_string(),
+                AstarteType::Double(my_aggregate.second_endpoint),
+            ),
+        ]);
+        assert_eq!(expected_res, my_aggregate.astarte_aggregate().unwrap());
+    }
+
+    #[derive(AstarteAggregate)]
+    #[astarte_aggregate(rename_all = "PascalCase")]
+    struct MyPascalCasedAggregate {
+        first_endpoint: f64,
+        second_endpoint: f64,
+    }
+
+    #[test]
+    fn test_astarte_aggregate_trait_pascal_case_attribute() {
+        let my_aggregate = MyPascalCasedAggregate {
+            first_endpoint: 4.34,
+            second_endpoint: 23.0,
+        };
+        let expected_res = HashMap::from([
+            (
+                "FirstEndpoint".to_string(),
+                AstarteType::Double(my_aggregate.first_endpoint),
+            ),
+            (
+                "SecondEndpoint".to_string(),
+                AstarteType::Double(my_aggregate.second_endpoint),
+            ),
+        ]);
+        assert_eq!(expected_res, my_aggregate.astarte_aggregate().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_connack() {
+        let mut eventloope = EventLoop::default();
+
+        eventloope.expect_poll().once().returning(|| {
+            Ok(Event::Incoming(rumqttc::Packet::ConnAck(
+                rumqttc::ConnAck {
+                    session_present: false,
+                    code: rumqttc::ConnectReturnCode::Success,
+                },
+            )))
+        });
+
+        let mut client = AsyncClient::default();
+
+        client
+            .expect_subscribe()
+            .once()
+            .returning(|_: String, _| Ok(()));
+
+        client
+            .expect_publish::<String, String>()
+            .returning(|topic, _, _, _| {
+                // Client id
+                assert_eq!(topic, "realm/device_id");
+
+                Ok(())
+            });
+
+        client
+            .expect_publish::<String, &str>()
+            .returning(|topic, _, _, payload| {
+                // empty cache
+                assert_eq!(topic, "realm/device_id/control/emptyCache");
+
+                assert_eq!(payload, "1");
+
+                Ok(())
+            });
+
+        client.expect_subscribe::<String>().returning(|topic, _qos| {
+            assert_eq!(topic, "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/#");
+
+            Ok(())
+        });
+
+        let interfaces = [
+            Interface::from_str(OBJECT_DEVICE_DATASTREAM).unwrap(),
+            Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap(),
+        ];
+
+        let mut astarte = MockDevice::new(client, eventloope)
+            .interfaces(interfaces)
+            .build();
+
+        astarte.wait_for_connack().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_add_remove_interface() {
+        let eventloope = EventLoop::default();
+
+        let mut client = AsyncClient::default();
+
+        client
+            .expect_subscribe::<String>()
+            .once()
+            .with(
+                predicate::eq("realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/#".to_string()),
+                predicate::always()
+            )
+            .returning(|_, _| { Ok(()) });
+
+        client
+            .expect_publish::<String, String>()
+            .with(
+                predicate::eq("realm/device_id".to_string()),
+                predicate::always(),
+                predicate::eq(false),
+                predicate::eq(
+                    "org.astarte-platform.rust.examples.individual-datastream.ServerDatastream:0:1"
+                        .to_string(),
+                ),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        client
+            .expect_publish::<String, String>()
+            .with(
+                predicate::eq("realm/device_id".to_string()),
+                predicate::always(),
+                predicate::eq(false),
+                predicate::eq(String::new()),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        client
+            .expect_unsubscribe::<String>()
+            .with(predicate::eq("realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/#".to_string()))
+            .returning(|_| Ok(()));
+
+        let astarte = MockDevice::new(client, eventloope).interfaces([]).build();
+
+        astarte
+            .add_interface_from_str(INDIVIDUAL_SERVER_DATASTREAM)
+            .await
+            .unwrap();
+
+        astarte
+            .remove_interface(
+                "org.astarte-platform.rust.examples.individual-datastream.ServerDatastream",
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_apply_registry_interfaces() {
+        let server = "org.astarte-platform.rust.examples.individual-datastream.ServerDatastream";
+
+        let mut client = AsyncClient::default();
+        client
+            .expect_subscribe::<String>()
+            .once()
+            .with(
+                predicate::eq(format!("realm/device_id/{server}/#")),
+                predicate::always(),
+            )
+            .returning(|_, _| Ok(()));
+        client
+            .expect_publish::<String, String>()
+            .once()
+            .with(
+                predicate::eq("realm/device_id".to_string()),
+                predicate::always(),
+                predicate::eq(false),
+                predicate::function(|introspection: &String| introspection.split(';').count() == 2),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        let astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(DEVICE_PROPERTIES).unwrap()])
+            .build();
+
+        let interfaces: Vec<Interface> = [INDIVIDUAL_SERVER_DATASTREAM, DEVICE_PROPERTIES]
+            .iter()
+            .map(|json| Interface::from_str(json).unwrap())
+            .collect();
+
+        let changed = astarte.apply_interfaces(interfaces.clone()).await.unwrap();
+        assert_eq!(changed, [server]);
+
+        // nothing changed
+        assert!(astarte
+            .apply_interfaces(interfaces)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // the whole bundle is rejected for an interface changed without a new version
+        let changed = INDIVIDUAL_SERVER_DATASTREAM.replace(r#""double""#, r#""integer""#);
+        let err = astarte
+            .apply_interfaces(vec![
+                Interface::from_str(VOLATILE_DATASTREAM).unwrap(),
+                Interface::from_str(&changed).unwrap(),
+            ])
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::Interface(_)),
+            "unexpected error {err:?}"
+        );
+        assert!(astarte
+            .interfaces
+            .read()
+            .await
+            .get("org.astarte-platform.test.VolatileDatastream")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_extend_interfaces_invalid() {
+        let astarte = MockDevice::new(AsyncClient::default(), EventLoop::default())
+            .interfaces([Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap()])
+            .build();
+
+        // changed without a new version
+        let changed = INDIVIDUAL_SERVER_DATASTREAM.replace(r#""double""#, r#""integer""#);
+        let report = astarte
+            .extend_interfaces([
+                Interface::from_str(DEVICE_PROPERTIES).unwrap(),
+                Interface::from_str(&changed).unwrap(),
+            ])
+            .await;
+
+        assert!(!report.is_applied());
+        assert!(report.error.is_none());
+        assert!(matches!(
+            report.interfaces.as_slice(),
+            [(_, Ok(InterfaceChange::Added)), (_, Err(_))]
+        ));
+
+        // nothing was subscribed or published
+        let introspection = astarte.introspection().await;
+        assert_eq!(introspection.to_string().split(';').count(), 1);
+        assert!(matches!(report.into_result(), Err(Error::Interface(_))));
+    }
+
+    #[tokio::test]
+    async fn test_extend_interfaces_rollback() {
+        let server = "org.astarte-platform.rust.examples.individual-datastream.ServerDatastream";
+        let properties =
+            "org.astarte-platform.rust.examples.individual-properties.DeviceProperties";
+
+        let mut seq = mockall::Sequence::new();
+        let mut client = AsyncClient::default();
+        client
+            .expect_subscribe::<String>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq(format!("realm/device_id/{server}/#")),
+                predicate::always(),
+            )
+            .returning(|_, _| Ok(()));
+        client
+            .expect_publish::<String, String>()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| {
+                Err(rumqttc::ClientError::Request(rumqttc::Request::Disconnect))
+            });
+        client
+            .expect_unsubscribe::<String>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(predicate::eq(format!("realm/device_id/{server}/#")))
+            .returning(|_| Ok(()));
+
+        let astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(DEVICE_PROPERTIES).unwrap()])
+            .build();
+
+        let updated = DEVICE_PROPERTIES.replace(r#""version_minor": 1"#, r#""version_minor": 2"#);
+        let report = astarte
+            .extend_interfaces([
+                Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap(),
+                Interface::from_str(&updated).unwrap(),
+            ])
+            .await;
+
+        assert!(!report.is_applied());
+        assert!(
+            matches!(report.error, Some(Error::BsonClientError(_))),
+            "{:?}",
+            report.error
+        );
+        assert_eq!(
+            report
+                .interfaces
+                .iter()
+                .map(|(name, res)| (name.as_str(), *res.as_ref().unwrap()))
+                .collect::<Vec<_>>(),
+            [
+                (server, InterfaceChange::Added),
+                (properties, InterfaceChange::Updated)
+            ]
+        );
+
+        // the previous interfaces are restored
+        let interfaces = astarte.interfaces.read().await;
+        assert!(interfaces.get(server).is_none());
+        assert_eq!(
+            interfaces.get(properties).map(|i| i.version_minor()),
+            Some(1)
+        );
+    }
+
+    pub(crate) const SERVER_PROPERTIES_NAME: &str =
+        "org.astarte-platform.rust.examples.individual-properties.ServerProperties";
+
+    async fn mock_prune_store(client: AsyncClient) -> AstarteDeviceSdk {
+        let mut astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(SERVER_PROPERTIES).unwrap()])
+            .build();
+
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let value = AstarteType::Boolean(true);
+        db.store_prop(SERVER_PROPERTIES_NAME, "/1/enable", &value, 0)
+            .await
+            .unwrap();
+        db.store_prop(SERVER_PROPERTIES_NAME, "/1/unknown", &value, 0)
+            .await
+            .unwrap();
+        db.store_prop(SERVER_PROPERTIES_NAME, "/2/enable", &value, 1)
+            .await
+            .unwrap();
+        db.store_prop("com.removed.Properties", "/1/enable", &value, 0)
+            .await
+            .unwrap();
+        astarte.database = Some(Arc::new(db));
+
+        {
+            let mut volatile = astarte.volatile.lock().await;
+            for interface in [SERVER_PROPERTIES_NAME, "com.removed.Datastream"] {
+                volatile.push(VolatileItem::new(
+                    MessageId::new(),
+                    interface,
+                    "/value",
+                    format!("realm/device_id/{interface}/value"),
+                    rumqttc::QoS::AtLeastOnce,
+                    Vec::new(),
+                    0,
+                ));
+            }
+        }
+
+        astarte
+    }
+
+    pub(crate) const DIAGNOSTICS: &str = r#"
+    {
+        "interface_name": "org.astarte-platform.test.Diagnostics",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "ownership": "device",
+        "mappings": [
+            {
+                "endpoint": "/properties",
+                "type": "string"
+            }
+        ]
+    }
+    "#;
+
+    #[tokio::test]
+    async fn test_send_property_snapshot() {
+        let mut client = AsyncClient::default();
+
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .with(
+                predicate::eq(
+                    "realm/device_id/org.astarte-platform.test.Diagnostics/properties".to_string(),
+                ),
+                predicate::always(),
+                predicate::always(),
+                predicate::function(|buf: &Vec<u8>| {
+                    let Ok(doc) = bson::Document::from_reader(buf.as_slice()) else {
+                        return false;
+                    };
+                    let Ok(json) = doc.get_str("v") else {
+                        return false;
+                    };
+
+                    serde_json::from_str::<serde_json::Value>(json).ok()
+                        == Some(serde_json::json!({
+                            SERVER_PROPERTIES_NAME: { "/1/enable": true }
+                        }))
+                }),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        let astarte = mock_prune_store(client).await;
+        astarte
+            .add_interface_to_introspection(Interface::from_str(DIAGNOSTICS).unwrap())
+            .await
+            .unwrap();
+
+        // only the properties of the current interfaces are included
+        let snapshot = astarte.property_snapshot().await.unwrap();
+        assert_eq!(
+            snapshot,
+            HashMap::from([(
+                SERVER_PROPERTIES_NAME.to_string(),
+                HashMap::from([("/1/enable".to_string(), AstarteType::Boolean(true))])
+            )])
+        );
+
+        astarte
+            .send_property_snapshot("org.astarte-platform.test.Diagnostics", "/properties")
+            .await
+            .unwrap();
+
+        let err = astarte
+            .send_property_snapshot(SERVER_PROPERTIES_NAME, "/1/enable")
+            .await
+            .expect_err("sent the snapshot on a property");
+        assert!(matches!(err, Error::SendError(_)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn test_interface_enabled() {
+        let server_topic = "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/#";
+        let diagnostics = "org.astarte-platform.test.Diagnostics";
+
+        let mut client = AsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+
+        client
+            .expect_unsubscribe::<String>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(predicate::eq(server_topic.to_string()))
+            .returning(|_| Ok(()));
+
+        client
+            .expect_subscribe::<String>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(predicate::eq(server_topic.to_string()), predicate::always())
+            .returning(|_, _| Ok(()));
+
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq(format!("realm/device_id/{diagnostics}/properties")),
+                predicate::always(),
+                predicate::always(),
+                predicate::always(),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        let astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([
+                Interface::from_str(DIAGNOSTICS).unwrap(),
+                Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap(),
+            ])
+            .build();
+
+        let err = astarte
+            .set_interface_enabled("com.missing.Interface", false)
+            .await
+            .expect_err("disabled a missing interface");
+        assert!(
+            matches!(
+                err,
+                Error::Interface(InterfaceError::InterfaceNotFound { .. })
+            ),
+            "{err:?}"
+        );
+
+        for name in [
+            diagnostics,
+            "org.astarte-platform.rust.examples.individual-datastream.ServerDatastream",
+        ] {
+            astarte.set_interface_enabled(name, false).await.unwrap();
+            // already disabled
+            astarte.set_interface_enabled(name, false).await.unwrap();
+            assert!(astarte.is_interface_disabled(name));
+        }
+
+        // dropped
+        astarte
+            .send(diagnostics, "/properties", "dropped")
+            .await
+            .unwrap();
+        assert!(!astarte
+            .send_unreliable(diagnostics, "/properties", "dropped")
+            .await
+            .unwrap());
+
+        for name in [
+            diagnostics,
+            "org.astarte-platform.rust.examples.individual-datastream.ServerDatastream",
+        ] {
+            astarte.set_interface_enabled(name, true).await.unwrap();
+            assert!(!astarte.is_interface_disabled(name));
+        }
+
+        astarte
+            .send(diagnostics, "/properties", "published")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_static_database() {
+        let mut eventloop = EventLoop::default();
+
+        eventloop.expect_poll().once().returning(|| {
+            Ok(Event::Incoming(rumqttc::Packet::Publish(
+                rumqttc::Publish::new(
+                    format!("realm/device_id/{SERVER_PROPERTIES_NAME}/1/enable"),
+                    rumqttc::QoS::AtLeastOnce,
+                    bson::to_vec(&bson::doc! { "v": true }).unwrap(),
+                ),
+            )))
+        });
+
+        let mut astarte: AstarteDeviceSdk<CachedDatabase<AstarteSqliteDatabase>> =
+            MockDevice::new(AsyncClient::default(), eventloop)
+                .interfaces([Interface::from_str(SERVER_PROPERTIES).unwrap()])
+                .build_with(None);
+
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+        astarte.database = Some(Arc::new(CachedDatabase::new(db, 10)));
+
+        astarte.handle_events().await.unwrap();
+
+        let stored = astarte
+            .get_property(SERVER_PROPERTIES_NAME, "/1/enable")
+            .await
+            .unwrap();
+        assert_eq!(stored, Some(AstarteType::Boolean(true)));
+
+        let enabled: Option<bool> = astarte
+            .property_as(SERVER_PROPERTIES_NAME, "/1/enable")
+            .await
+            .unwrap();
+        assert_eq!(enabled, Some(true));
+
+        let missing: Option<bool> = astarte
+            .property_as(SERVER_PROPERTIES_NAME, "/2/enable")
+            .await
+            .unwrap();
+        assert_eq!(missing, None);
+
+        let err = astarte
+            .property_as::<String>(SERVER_PROPERTIES_NAME, "/1/enable")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Types(_)));
+    }
+
+    #[tokio::test]
+    async fn test_prune_store() {
+        let astarte = mock_prune_store(AsyncClient::default()).await;
+
+        let stale = |report: &PruneReport| {
+            let mut props: Vec<String> = report
+                .properties
+                .iter()
+                .map(|prop| format!("{}{}", prop.interface, prop.path))
+                .collect();
+            props.sort();
+
+            props
+        };
+        let expected = [
+            "com.removed.Properties/1/enable".to_string(),
+            format!("{SERVER_PROPERTIES_NAME}/1/unknown"),
+            format!("{SERVER_PROPERTIES_NAME}/2/enable"),
+        ];
+        let db = astarte.database.clone().unwrap();
+
+        let report = astarte.prune_store(true).await.unwrap();
+        assert_eq!(stale(&report), expected);
+        assert_eq!(report.volatile, 1);
+        assert_eq!(db.load_all_props().await.unwrap().len(), 4);
+        assert_eq!(astarte.volatile.lock().await.iter().count(), 2);
+
+        let report = astarte.prune_store(false).await.unwrap();
+        assert_eq!(stale(&report), expected);
+        assert_eq!(report.volatile, 1);
+
+        let props = db.load_all_props().await.unwrap();
+        assert_eq!(props.len(), 1);
+        assert_eq!(props[0].path, "/1/enable");
+        assert_eq!(astarte.volatile.lock().await.iter().count(), 1);
+
+        assert!(astarte.prune_store(false).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_import_properties() {
+        let mut client = AsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|topic, _, _, _| {
+                topic == &format!("realm/device_id/{DEVICE_PROPERTIES_NAME}/1/name")
+            })
+            .returning(|_, _, _, _| Ok(()));
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| {
+                Err(rumqttc::ClientError::Request(rumqttc::Request::Disconnect))
+            });
+
+        let db = FaultyStore::new(AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap());
+        db.inject(StoreOperation::StoreProps, Fault::full().times(1));
+
+        let mut astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([
+                Interface::from_str(DEVICE_PROPERTIES).unwrap(),
+                Interface::from_str(SERVER_PROPERTIES).unwrap(),
+            ])
+            .build();
+        astarte.database = Some(Arc::new(db.clone()));
+
+        let entries = || {
+            [
+                (
+                    DEVICE_PROPERTIES_NAME,
+                    "/1/name",
+                    AstarteType::String("one".to_string()),
+                ),
+                (
+                    SERVER_PROPERTIES_NAME,
+                    "/1/enable",
+                    AstarteType::Boolean(true),
+                ),
+                (
+                    DEVICE_PROPERTIES_NAME,
+                    "/2/name",
+                    AstarteType::String("two".to_string()),
+                ),
+                (DEVICE_PROPERTIES_NAME, "/3/name", AstarteType::Integer(3)),
+                (
+                    DEVICE_PROPERTIES_NAME,
+                    "/4/name",
+                    AstarteType::String("four".to_string()),
+                ),
+            ]
+        };
+
+        // nothing is stored on a storage error
+        let err = astarte.import_properties(entries()).await.unwrap_err();
+        assert!(matches!(err, Error::StoreFull { ref path, .. } if path == "/1/name"));
+        assert!(db.load_all_props().await.unwrap().is_empty());
+
+        let mut steps = Vec::new();
+        let report = astarte
+            .import_properties_with_progress(entries(), |progress| steps.push(progress))
+            .await
+            .unwrap();
+
+        assert_eq!(report.stored, 3);
+        assert_eq!(report.published, 1);
+        assert!(!report.is_complete());
+
+        let failed: Vec<(usize, &str)> = report
+            .failed
+            .iter()
+            .map(|failure| (failure.index, failure.path.as_str()))
+            .collect();
+        assert_eq!(failed, [(1, "/1/enable"), (3, "/3/name")]);
+        assert!(matches!(report.failed[0].error, ImportError::ServerOwned));
+
+        assert_eq!(
+            steps,
+            [
+                ImportProgress::Validated {
+                    valid: 3,
+                    failed: 2
+                },
+                ImportProgress::Stored { stored: 3 },
+                ImportProgress::Published {
+                    published: 1,
+                    total: 3
+                },
+            ]
+        );
+
+        assert_eq!(db.load_all_props().await.unwrap().len(), 3);
+        assert_eq!(
+            astarte
+                .get_property(DEVICE_PROPERTIES_NAME, "/4/name")
+                .await
+                .unwrap(),
+            Some(AstarteType::String("four".to_string()))
+        );
+        assert_eq!(db.calls(StoreOperation::StoreProps), 2);
+        assert_eq!(db.calls(StoreOperation::StoreProp), 0);
+    }
+
+    #[tokio::test]
+    async fn test_remove_interface_prunes_store() {
+        let mut client = AsyncClient::default();
+        client
+            .expect_publish::<String, String>()
+            .returning(|_, _, _, _| Ok(()));
+        client.expect_unsubscribe::<String>().returning(|_| Ok(()));
+
+        let astarte = mock_prune_store(client).await;
+
+        astarte
+            .remove_interface(SERVER_PROPERTIES_NAME)
+            .await
+            .unwrap();
+
+        let props = astarte
+            .database
+            .as_ref()
+            .unwrap()
+            .load_all_props()
+            .await
+            .unwrap();
+        assert_eq!(props.len(), 1);
+        assert_eq!(props[0].interface, "com.removed.Properties");
+
+        let volatile: Vec<String> = astarte
+            .volatile
+            .lock()
+            .await
+            .iter()
+            .map(|item| item.interface.clone())
+            .collect();
+        assert_eq!(volatile, ["com.removed.Datastream"]);
+    }
+
+    pub(crate) const DEVICE_PROPERTIES_NAME: &str =
+        "org.astarte-platform.rust.examples.individual-properties.DeviceProperties";
+
+    async fn mock_property_publish(publishes: usize) -> MockDevice {
+        let mut client = AsyncClient::default();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .times(publishes)
+            .with(
+                predicate::eq(format!("realm/device_id/{DEVICE_PROPERTIES_NAME}/1/name")),
+                predicate::always(),
+                predicate::always(),
+                predicate::always(),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+
+        MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(DEVICE_PROPERTIES).unwrap()])
+            .options(|opts| opts.database(db))
+    }
+
+    #[tokio::test]
+    async fn test_property_publish_policy() {
+        // only the changes are published by default
+        let astarte = mock_property_publish(2).await.build();
+        for value in ["name", "name", "other"] {
+            astarte
+                .send(DEVICE_PROPERTIES_NAME, "/1/name", value)
+                .await
+                .unwrap();
+        }
+
+        let astarte = mock_property_publish(2).await.build();
+        for _ in 0..2 {
+            astarte
+                .send_forced(DEVICE_PROPERTIES_NAME, "/1/name", "name")
+                .await
+                .unwrap();
+        }
+
+        let astarte = mock_property_publish(2)
+            .await
+            .options(|opts| opts.property_publish_policy(PropertyPublishPolicy::Always))
+            .build();
+        for _ in 0..2 {
+            astarte
+                .send(DEVICE_PROPERTIES_NAME, "/1/name", "name")
+                .await
+                .unwrap();
+        }
+
+        // the interface policy overrides the global one
+        let astarte = mock_property_publish(1)
+            .await
+            .options(|opts| {
+                opts.property_publish_policy(PropertyPublishPolicy::Always)
+                    .interface_property_publish_policy(
+                        DEVICE_PROPERTIES_NAME,
+                        PropertyPublishPolicy::OnChange,
+                    )
+            })
+            .build();
+        for _ in 0..2 {
+            astarte
+                .send(DEVICE_PROPERTIES_NAME, "/1/name", "name")
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_device_owned_properties() {
+        let mut client = AsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq("realm/device_id/control/producer/properties".to_string()),
+                predicate::always(),
+                predicate::always(),
+                predicate::function(|buf: &Vec<u8>| {
+                    crate::properties::extract_set_properties(buf, None).map_or(false, |set| {
+                        set == [format!("{DEVICE_PROPERTIES_NAME}/1/name")]
+                    })
+                }),
+            )
+            .returning(|_, _, _, _| Ok(()));
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq(format!("realm/device_id/{DEVICE_PROPERTIES_NAME}/1/name")),
+                predicate::always(),
+                predicate::always(),
+                predicate::always(),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([
+                Interface::from_str(DEVICE_PROPERTIES).unwrap(),
+                Interface::from_str(SERVER_PROPERTIES).unwrap(),
+            ])
+            .options(|opts| opts.purge_properties_compression(9))
+            .build();
+
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+        db.store_prop(
+            DEVICE_PROPERTIES_NAME,
+            "/1/name",
+            &AstarteType::String("name".to_string()),
+            0,
+        )
+        .await
+        .unwrap();
+        db.store_prop(
+            SERVER_PROPERTIES_NAME,
+            "/1/enable",
+            &AstarteType::Boolean(true),
+            0,
+        )
+        .await
+        .unwrap();
+        astarte.database = Some(Arc::new(db));
+
+        astarte.send_device_owned_properties().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_capabilities() {
+        let capabilities = Capabilities {
+            max_payload_size: Some(32),
+            ..Default::default()
+        };
+
+        let astarte = mock_property_publish(1)
+            .await
+            .options(|opts| opts.capabilities(capabilities))
+            .build();
+        assert_eq!(astarte.capabilities(), capabilities);
+
+        let err = astarte
+            .send(
+                DEVICE_PROPERTIES_NAME,
+                "/1/name",
+                "a name longer than the maximum",
+            )
+            .await
+            .expect_err("sent a payload too large");
+        assert!(
+            matches!(err, Error::PayloadTooLarge { max: 32, .. }),
+            "{err:?}"
+        );
+
+        astarte
+            .send(DEVICE_PROPERTIES_NAME, "/1/name", "name")
+            .await
+            .unwrap();
+
+        // the purge properties isn't sent to the older clusters
+        let astarte = mock_property_publish(1)
+            .await
+            .options(|opts| {
+                opts.capabilities(Capabilities::from_version("0.11.5".parse().unwrap()))
+            })
+            .build();
+        astarte
+            .database
+            .as_ref()
+            .unwrap()
+            .store_prop(
+                DEVICE_PROPERTIES_NAME,
+                "/1/name",
+                &AstarteType::String("name".to_string()),
+                0,
+            )
+            .await
+            .unwrap();
+
+        astarte.send_device_owned_properties().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_self_test() {
+        let mut astarte = mock_property_publish(0).await.build();
+
+        let report = astarte.self_test().await;
+        assert!(report.passed(), "{report:?}");
+
+        let status = |report: &SelfTestReport, check| report.get(check).map(|result| result.status);
+        assert_eq!(
+            status(&report, SelfTestCheck::Store),
+            Some(CheckStatus::Passed)
+        );
+        assert_eq!(
+            status(&report, SelfTestCheck::Broker),
+            Some(CheckStatus::Skipped)
+        );
+        assert_eq!(
+            status(&report, SelfTestCheck::Interfaces),
+            Some(CheckStatus::Passed)
+        );
+
+        astarte.database = None;
+        let report = astarte.self_test().await;
+        assert_eq!(
+            status(&report, SelfTestCheck::Store),
+            Some(CheckStatus::Skipped)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_introspection_diff() {
+        let mut client = AsyncClient::default();
+
+        client
+            .expect_publish::<String, String>()
+            .once()
+            .with(
+                predicate::eq("realm/device_id".to_string()),
+                predicate::always(),
+                predicate::eq(false),
+                predicate::always(),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        let astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(DEVICE_PROPERTIES).unwrap()])
+            .build();
+
+        let device_properties =
+            "org.astarte-platform.rust.examples.individual-properties.DeviceProperties";
+        let object_datastream =
+            "org.astarte-platform.rust.examples.object-datastream.DeviceDatastream";
+
+        assert!(astarte.announced_introspection().await.is_none());
+
+        let diff = astarte.introspection_diff().await;
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].0, device_properties);
+        assert!(diff.removed.is_empty());
+
+        astarte
+            .add_interface_from_str(OBJECT_DEVICE_DATASTREAM)
+            .await
+            .unwrap();
+
+        let introspection = astarte.introspection().await;
+        assert_eq!(introspection.len(), 2);
+        assert!(introspection.get(object_datastream).is_some());
+        assert_eq!(astarte.announced_introspection().await, Some(introspection));
+        assert!(astarte.introspection_diff().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_handle_event() {
+        let mut client = AsyncClient::default();
+
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .with(
+                predicate::eq("realm/device_id/org.astarte-platform.rust.examples.individual-properties.DeviceProperties/1/name".to_string()),
+                predicate::always(),
+                predicate::always(),
+                predicate::function(|buf: &Vec<u8>| {
+                    let doc= bson::Document::from_reader(buf.as_slice()).unwrap();
+
+                    let value = doc.get("v").unwrap().as_str().unwrap();
+
+                    value == "name number 1"
+                }),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut eventloope = EventLoop::default();
+
+        let data = bson::doc! {
+            "v": true
+        };
+
+        // Purge properties
+        eventloope.expect_poll().once().returning(|| {
+            Ok(Event::Incoming(rumqttc::Packet::Publish(
+                rumqttc::Publish::new(
+                    "realm/device_id/control/consumer/properties",
+                    rumqttc::QoS::AtLeastOnce,
+                    PROPERTIES_PAYLOAD,
+                ),
+            )))
+        });
+
+        // Send properties
+        eventloope.expect_poll().once().returning(move || {
+            Ok(Event::Incoming(rumqttc::Packet::Publish(
+                rumqttc::Publish::new(
+                    "realm/device_id/org.astarte-platform.rust.examples.individual-properties.ServerProperties/1/enable",
+                    rumqttc::QoS::AtLeastOnce,
+                    bson::to_vec(&data).unwrap()
+                ),
+            )))
+        });
+
+        let mut astarte = MockDevice::new(client, eventloope)
+            .interfaces([
+                Interface::from_str(DEVICE_PROPERTIES).unwrap(),
+                Interface::from_str(SERVER_PROPERTIES).unwrap(),
+            ])
+            .build();
+
+        astarte
+            .send(
+                "org.astarte-platform.rust.examples.individual-properties.DeviceProperties",
+                "/1/name",
+                "name number 1".to_string(),
+            )
+            .await
+            .unwrap();
+
+        let event = astarte.handle_events().await;
+
+        assert!(
+            event.is_ok(),
+            "Error handling event {:?}",
+            event.unwrap_err()
+        );
+
+        let event = event.unwrap();
+
+        assert_eq!("/1/enable", event.path);
+
+        match event.data {
+            Aggregation::Individual(AstarteType::Boolean(val)) => {
+                assert!(val);
+            }
+            _ => panic!("Wrong data type {:?}", event.data),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unset_property() {
+        let mut client = AsyncClient::default();
+
+        let value = AstarteType::String(String::from("name number 1"));
+        let buf = payload::serialize_individual(&value, None).unwrap();
+
+        let unset = payload::serialize_individual(&AstarteType::Unset, None).unwrap();
+
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .with(
+                predicate::eq("realm/device_id/org.astarte-platform.rust.examples.individual-properties.DeviceProperties/1/name".to_string()),
+                predicate::always(),
+                predicate::always(),
+                predicate::eq(buf),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .with(predicate::eq("realm/device_id/org.astarte-platform.rust.examples.individual-properties.DeviceProperties/1/name".to_string()), predicate::always(), predicate::always(), predicate::eq(unset))
+            .returning(|_, _, _, _| Ok(()));
+
+        let eventloope = EventLoop::default();
+
+        let astarte = MockDevice::new(client, eventloope)
+            .interfaces([Interface::from_str(DEVICE_PROPERTIES).unwrap()])
+            .build();
+
+        astarte
+            .send(
+                "org.astarte-platform.rust.examples.individual-properties.DeviceProperties",
+                "/1/name",
+                "name number 1".to_string(),
+            )
+            .await
+            .unwrap();
+
+        astarte
+            .unset(
+                "org.astarte-platform.rust.examples.individual-properties.DeviceProperties",
+                "/1/name",
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_receive_object() {
+        let client = AsyncClient::default();
+
+        let mut eventloope = EventLoop::default();
+
+        let data = bson::doc! {
+            "v": {
+                "endpoint1": 4.2,
+                "endpoint2": "obj",
+                "endpoint3": [true],
+            }
+        };
+
+        // Send object
+        eventloope.expect_poll().returning(move || {
+            Ok(Event::Incoming(rumqttc::Packet::Publish(
+                rumqttc::Publish::new(
+                    "realm/device_id/org.astarte-platform.rust.examples.object-datastream.DeviceDatastream/1",
+                    rumqttc::QoS::AtLeastOnce,
+                    bson::to_vec(&data).unwrap()
+                ),
+            )))
+        });
+
+        let mut astarte = MockDevice::new(client, eventloope)
+            .interfaces([Interface::from_str(OBJECT_DEVICE_DATASTREAM).unwrap()])
+            .build();
+
+        let event = astarte.handle_events().await;
+
+        assert!(
+            event.is_ok(),
+            "Error handling event {:?}",
+            event.unwrap_err()
+        );
+
+        let event = event.unwrap();
+
+        let mut obj = HashMap::new();
+        obj.insert("endpoint1".to_string(), AstarteType::Double(4.2));
+        obj.insert(
+            "endpoint2".to_string(),
+            AstarteType::String("obj".to_string()),
+        );
+        obj.insert(
+            "endpoint3".to_string(),
+            AstarteType::BooleanArray(vec![true]),
+        );
+        let expected = Aggregation::Object(obj);
+
+        assert_eq!(
+            "org.astarte-platform.rust.examples.object-datastream.DeviceDatastream",
+            event.interface
+        );
+        assert_eq!("/1", event.path);
+        assert_eq!(expected, event.data);
+    }
+
+    #[tokio::test]
+    async fn test_connection_quality() {
+        let astarte = MockDevice::new(AsyncClient::default(), EventLoop::default())
+            .interfaces([])
+            .build();
+        assert_eq!(astarte.connection_quality(), ConnectionQuality::default());
+
+        let outgoing = Event::Outgoing(rumqttc::Outgoing::Publish(1));
+        assert!(astarte.handle_event(outgoing).await.unwrap().is_none());
+        let ack = Event::Incoming(rumqttc::Packet::PubAck(rumqttc::PubAck::new(1)));
+        assert!(astarte.handle_event(ack).await.unwrap().is_none());
+
+        let quality = astarte.connection_quality();
+        assert!(quality.latency.is_some());
+        assert_eq!(quality.ack_loss, 0.0);
+        assert_eq!(quality.reconnects, 0);
+    }
+
+    pub(crate) const VOLATILE_DATASTREAM: &str = r#"
+    {
+        "interface_name": "org.astarte-platform.test.VolatileDatastream",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "ownership": "device",
+        "mappings": [
+            {
+                "endpoint": "/value",
+                "type": "integer",
+                "retention": "volatile"
+            }
+        ]
+    }
+    "#;
+
+    #[tokio::test]
+    async fn test_interface_handle() {
+        let datastream = "org.astarte-platform.test.VolatileDatastream";
+
+        let mut client = AsyncClient::default();
+        // the handle sends with a clone of the device
+        client.expect_clone().once().returning(|| {
+            let mut client = AsyncClient::default();
+            client
+                .expect_publish::<String, Vec<u8>>()
+                .times(2)
+                .returning(|_, _, _, _| {
+                    Err(rumqttc::ClientError::Request(rumqttc::Request::Disconnect))
+                });
+
+            client
+        });
+
+        let astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(VOLATILE_DATASTREAM).unwrap()])
+            .build();
+
+        let err = astarte.interface_handle("com.missing").await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::Interface(InterfaceError::InterfaceNotFound { .. })
+            ),
+            "unexpected error {err:?}"
+        );
+
+        let handle = astarte
+            .interface_handle(datastream)
+            .await
+            .unwrap()
+            .rate_limit(0.001, 2);
+        handle.set_retention_quota(Some(1)).await;
+
+        handle.send("/value", 1).await.unwrap();
+        handle.send("/value", 2).await.unwrap();
+
+        let err = handle.send("/value", 3).await.unwrap_err();
+        assert!(
+            matches!(err, Error::RateLimited(ref name) if name == datastream),
+            "unexpected error {err:?}"
+        );
+
+        assert_eq!(
+            handle.stats(),
+            InterfaceStats {
+                sent: 2,
+                rate_limited: 1,
+                failed: 0,
+            }
+        );
+
+        // only the most recent message is kept by the quota
+        let retained = astarte.volatile.lock().await.drain();
+        let expected = payload::serialize_individual(&AstarteType::Integer(2), None).unwrap();
+        assert_eq!(retained.len(), 1);
+        assert_eq!(retained[0].payload, expected);
+    }
+
+    #[tokio::test]
+    async fn test_message_ids() {
+        let mut client = AsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| {
+                Err(rumqttc::ClientError::Request(rumqttc::Request::Disconnect))
+            });
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .times(2)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(VOLATILE_DATASTREAM).unwrap()])
+            .build();
+
+        let steps = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hook_steps = Arc::clone(&steps);
+        astarte.message_hook = Some(Arc::new(move |event| {
+            hook_steps
+                .lock()
+                .unwrap()
+                .push((event.id, event.path.to_string(), event.stage));
+        }));
+
+        astarte
+            .send("org.astarte-platform.test.VolatileDatastream", "/value", 1)
+            .await
+            .unwrap();
+        astarte.send_volatile().await.unwrap();
+        astarte
+            .send("org.astarte-platform.test.VolatileDatastream", "/value", 2)
+            .await
+            .unwrap();
+
+        let steps = steps.lock().unwrap();
+        let stages: Vec<MessageStage> = steps.iter().map(|(_, _, stage)| *stage).collect();
+        assert_eq!(
+            stages,
+            [
+                MessageStage::Retained,
+                MessageStage::Republished,
+                MessageStage::Published
+            ]
+        );
+        assert!(steps.iter().all(|(_, path, _)| path == "/value"));
+
+        // the id is kept in the retention
+        assert_eq!(steps[0].0, steps[1].0);
+        assert_ne!(steps[1].0, steps[2].0);
+    }
+
+    #[tokio::test]
+    async fn test_reconfigure_transport() {
+        let aborted = || {
+            Err(rumqttc::ConnectionError::Io(std::io::Error::from(
+                std::io::ErrorKind::ConnectionAborted,
+            )))
+        };
+        let publish = || {
+            Ok(Event::Incoming(rumqttc::Packet::Publish(
+                rumqttc::Publish::new(
+                    "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/1/intensity",
+                    rumqttc::QoS::AtLeastOnce,
+                    bson::to_vec(&bson::doc! { "v": 4.2 }).unwrap(),
+                ),
+            )))
+        };
+        let options = TransportOptions::new()
+            .keepalive(std::time::Duration::from_secs(60))
+            .reconnect_delay(std::time::Duration::from_millis(50));
+
+        let mut eventloope = EventLoop::default();
+        let mut seq = mockall::Sequence::new();
+
+        // closed by the requested reconnection
+        eventloope
+            .expect_poll()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(aborted);
+        eventloope
+            .expect_reconfigure()
+            .withf(move |applied| *applied == options)
+            .once()
+            .in_sequence(&mut seq)
+            .return_const(());
+        eventloope
+            .expect_poll()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(publish);
+        // the connection errors delay the reconnection
+        eventloope
+            .expect_poll()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(aborted);
+        eventloope
+            .expect_poll()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(publish);
+
+        let mut client = AsyncClient::default();
+        client.expect_disconnect().once().returning(|| Ok(()));
+
+        let mut astarte = MockDevice::new(client, eventloope)
+            .interfaces([Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap()])
+            .build();
+
+        assert!(matches!(
+            astarte
+                .reconfigure(TransportOptions::new().keepalive(std::time::Duration::from_secs(1))),
+            Err(Error::OptionsError(_))
+        ));
+
+        astarte.reconfigure(options).unwrap();
+        astarte.reconnect().await.unwrap();
+
+        let event = astarte.handle_events().await.unwrap();
+        assert_eq!(event.path, "/1/intensity");
+
+        assert!(matches!(
+            astarte.handle_events().await,
+            Err(Error::ConnectionError(_))
+        ));
+
+        let start = tokio::time::Instant::now();
+        astarte.handle_events().await.unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(50));
+    }
+
+    fn mock_send_retry(failures: usize) -> MockDevice {
+        let mut client = AsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .times(failures)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| {
+                Err(rumqttc::ClientError::TryRequest(
+                    rumqttc::Request::Disconnect,
+                ))
+            });
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .returning(|_, _, _, _| Ok(()));
+
+        MockDevice::new(client, EventLoop::default()).interfaces([
+            Interface::from_str(VOLATILE_DATASTREAM).unwrap(),
+            Interface::from_str(OBJECT_DEVICE_DATASTREAM).unwrap(),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_send_retry() {
+        let astarte = mock_send_retry(2)
+            .options(|opts| opts.send_retry(2, std::time::Duration::from_millis(1)))
+            .build();
+
+        astarte
+            .send("org.astarte-platform.test.VolatileDatastream", "/value", 1)
+            .await
+            .unwrap();
+        assert!(astarte.volatile.lock().await.drain().is_empty());
+
+        // kept in the retention after all the attempts failed
+        let astarte = mock_send_retry(2)
+            .options(|opts| opts.send_retry(1, std::time::Duration::from_millis(1)))
+            .build();
+
+        astarte
+            .send("org.astarte-platform.test.VolatileDatastream", "/value", 1)
+            .await
+            .unwrap();
+        assert_eq!(astarte.volatile.lock().await.drain().len(), 1);
+
+        // the event loop was dropped, the publish is not retried
+        let mut client = AsyncClient::default();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .returning(|_, _, _, _| {
+                Err(rumqttc::ClientError::Request(rumqttc::Request::Disconnect))
+            });
+        let astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(VOLATILE_DATASTREAM).unwrap()])
+            .options(|opts| opts.send_retry(2, std::time::Duration::from_secs(60)))
+            .build();
+
+        astarte
+            .send("org.astarte-platform.test.VolatileDatastream", "/value", 1)
+            .await
+            .unwrap();
+        assert_eq!(astarte.volatile.lock().await.drain().len(), 1);
+
+        let astarte = mock_send_retry(2)
+            .options(|opts| opts.send_retry(1, std::time::Duration::from_millis(1)))
+            .build();
+
+        let res = astarte
+            .send_object(
+                "org.astarte-platform.rust.examples.object-datastream.DeviceDatastream",
+                "/1",
+                HashMap::from([
+                    ("endpoint1".to_string(), AstarteType::Double(1.0)),
+                    (
+                        "endpoint2".to_string(),
+                        AstarteType::String("value".to_string()),
+                    ),
+                    (
+                        "endpoint3".to_string(),
+                        AstarteType::BooleanArray(vec![true]),
+                    ),
+                ]),
+            )
+            .await;
+        assert!(
+            matches!(
+                res,
+                Err(Error::Publish { ref interface, ref path, .. })
+                    if interface == "org.astarte-platform.rust.examples.object-datastream.DeviceDatastream"
+                        && path == "/1"
+            ),
+            "{res:?}"
+        );
+    }
+
+    /// Sends two values concurrently while the first publish is retried, returning the values in
+    /// the order they were published.
+    async fn concurrent_publishes(ordering: PublishOrdering) -> Vec<i32> {
+        let published = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let mut client = AsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| {
+                Err(rumqttc::ClientError::TryRequest(
+                    rumqttc::Request::Disconnect,
+                ))
+            });
+        let rec = Arc::clone(&published);
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .times(2)
+            .in_sequence(&mut seq)
+            .returning(move |_, _, _, buf: Vec<u8>| {
+                let value = match payload::deserialize(&buf).unwrap() {
+                    Aggregation::Individual(AstarteType::Integer(value)) => value,
+                    data => panic!("unexpected data {data:?}"),
+                };
+                rec.lock().unwrap().push(value);
+
+                Ok(())
+            });
+
+        let astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(VOLATILE_DATASTREAM).unwrap()])
+            .options(|opts| {
+                opts.send_retry(1, std::time::Duration::from_millis(10))
+                    .publish_ordering(ordering)
+            })
+            .build();
+
+        let interface = "org.astarte-platform.test.VolatileDatastream";
+        let (first, second) = tokio::join!(
+            astarte.send(interface, "/value", 1),
+            astarte.send(interface, "/value", 2)
+        );
+        first.unwrap();
+        second.unwrap();
+
+        let published = published.lock().unwrap().clone();
+        published
+    }
+
+    #[tokio::test]
+    async fn test_publish_ordering() {
+        assert_eq!(concurrent_publishes(PublishOrdering::Ordered).await, [1, 2]);
+        // the second value overtakes the first being retried
+        assert_eq!(
+            concurrent_publishes(PublishOrdering::Parallel).await,
+            [2, 1]
+        );
+    }
+
+    const OBJECT_ARRAYS: &str = r#"
+    {
+        "interface_name": "org.astarte-platform.test.ObjectArrays",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "aggregation": "object",
+        "ownership": "device",
+        "mappings": [
+            {
+                "endpoint": "/%{sensor_id}/blobs",
+                "type": "binaryblobarray"
+            },
+            {
+                "endpoint": "/%{sensor_id}/times",
+                "type": "datetimearray"
+            }
+        ]
+    }
+    "#;
+
+    #[derive(AstarteAggregate)]
+    struct ArraysAggregate {
+        blobs: Vec<Vec<u8>>,
+        times: Vec<chrono::DateTime<chrono::Utc>>,
+    }
+
+    #[tokio::test]
+    async fn test_send_object_arrays() {
+        let time = chrono::TimeZone::timestamp_opt(&chrono::Utc, 1627580808, 0).unwrap();
+
+        let expected = [
+            HashMap::from([
+                (
+                    "blobs".to_string(),
+                    AstarteType::BinaryBlobArray(vec![b"hello".to_vec(), Vec::new()]),
+                ),
+                ("times".to_string(), AstarteType::DateTimeArray(vec![time])),
             ]),
-            endpoint14: Vec::from([chrono::offset::Utc::now(), chrono::offset::Utc::now()]),
+            HashMap::from([
+                (
+                    "blobs".to_string(),
+                    AstarteType::BinaryBlobArray(Vec::new()),
+                ),
+                ("times".to_string(), AstarteType::DateTimeArray(Vec::new())),
+            ]),
+        ];
+
+        let mut client = AsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+        for object in expected.clone() {
+            client
+                .expect_publish::<String, Vec<u8>>()
+                .once()
+                .in_sequence(&mut seq)
+                .with(
+                    predicate::eq(
+                        "realm/device_id/org.astarte-platform.test.ObjectArrays/1".to_string(),
+                    ),
+                    predicate::always(),
+                    predicate::always(),
+                    // the order of the fields is not deterministic
+                    predicate::function(move |buf: &Vec<u8>| {
+                        let object = Aggregation::Object(object.clone());
+
+                        payload::deserialize_typed(buf, |field| match field {
+                            Some("blobs") => Some(crate::interface::MappingType::BinaryBlobArray),
+                            Some("times") => Some(crate::interface::MappingType::DateTimeArray),
+                            _ => None,
+                        })
+                        .map_or(false, |(data, _)| data == object)
+                    }),
+                )
+                .returning(|_, _, _, _| Ok(()));
+        }
+
+        let astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(OBJECT_ARRAYS).unwrap()])
+            .build();
+
+        let objects = [
+            ArraysAggregate {
+                blobs: vec![b"hello".to_vec(), Vec::new()],
+                times: vec![time],
+            },
+            ArraysAggregate {
+                blobs: Vec::new(),
+                times: Vec::new(),
+            },
+        ];
+
+        for object in objects {
+            astarte
+                .send_object("org.astarte-platform.test.ObjectArrays", "/1", object)
+                .await
+                .unwrap();
+        }
+
+        // the empty arrays are typed with the mappings
+        let buf = payload::serialize_object(&expected[1], None).unwrap();
+        let (data, _) = astarte
+            .interfaces
+            .read()
+            .await
+            .deserialize(
+                "org.astarte-platform.test.ObjectArrays",
+                &MappingPath::try_from("/1").unwrap(),
+                &buf,
+            )
+            .unwrap();
+        assert_eq!(data, Aggregation::Object(expected[1].clone()));
+    }
+
+    #[tokio::test]
+    async fn test_send_unreliable() {
+        let topic = "realm/device_id/org.astarte-platform.test.VolatileDatastream/value";
+
+        let mut client = AsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+        client
+            .expect_try_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq(topic.to_string()),
+                predicate::eq(rumqttc::QoS::AtMostOnce),
+                predicate::always(),
+                predicate::always(),
+            )
+            .returning(|_, _, _, _| Ok(()));
+        client
+            .expect_try_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _| {
+                Err(rumqttc::ClientError::TryRequest(
+                    rumqttc::Request::Disconnect,
+                ))
+            });
+        client.expect_publish::<String, Vec<u8>>().never();
+
+        let astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([
+                Interface::from_str(VOLATILE_DATASTREAM).unwrap(),
+                Interface::from_str(DEVICE_PROPERTIES).unwrap(),
+            ])
+            .build();
+
+        let sent = astarte
+            .send_unreliable("org.astarte-platform.test.VolatileDatastream", "/value", 1)
+            .await
+            .unwrap();
+        assert!(sent);
+
+        let sent = astarte
+            .send_unreliable("org.astarte-platform.test.VolatileDatastream", "/value", 2)
+            .await
+            .unwrap();
+        assert!(!sent);
+
+        // dropped messages are not retained
+        assert!(astarte.volatile.lock().await.drain().is_empty());
+
+        // doesn't wait for the interfaces being changed
+        let interfaces = astarte.interfaces.write().await;
+        let sent = astarte
+            .send_unreliable("org.astarte-platform.test.VolatileDatastream", "/value", 3)
+            .await
+            .unwrap();
+        assert!(!sent);
+        drop(interfaces);
+
+        let res = astarte
+            .send_unreliable(
+                "org.astarte-platform.rust.examples.individual-properties.DeviceProperties",
+                "/1/name",
+                "name",
+            )
+            .await;
+        assert!(matches!(res, Err(Error::SendError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_send_unreliable_encrypted() {
+        let interface = "org.astarte-platform.test.VolatileDatastream";
+        let encrypted = |value: &AstarteType| {
+            let plaintext = payload::serialize_individual(value, None).unwrap();
+
+            AstarteType::BinaryBlob(plaintext.iter().map(|b| !b).collect())
         };
-        let expected_res = HashMap::from([
-            (
-                "endpoint01".to_string(),
-                AstarteType::Double(my_aggregate.endpoint01),
-            ),
-            (
-                "endpoint02".to_string(),
-                AstarteType::Integer(my_aggregate.endpoint02),
-            ),
-            (
-                "endpoint03".to_string(),
-                AstarteType::Boolean(my_aggregate.endpoint03),
-            ),
-            (
-                "endpoint04".to_string(),
-                AstarteType::LongInteger(my_aggregate.endpoint04),
-            ),
-            (
-                "endpoint05".to_string(),
-                AstarteType::String(my_aggregate.endpoint05.clone()),
-            ),
-            (
-                "endpoint06".to_string(),
-                AstarteType::BinaryBlob(my_aggregate.endpoint06.clone()),
+
+        let mut client = AsyncClient::default();
+        client
+            .expect_try_publish::<String, Vec<u8>>()
+            .once()
+            .with(
+                predicate::eq(format!("realm/device_id/{interface}/value")),
+                predicate::eq(rumqttc::QoS::AtMostOnce),
+                predicate::always(),
+                predicate::eq(
+                    payload::serialize_individual(&encrypted(&AstarteType::Integer(1)), None)
+                        .unwrap(),
+                ),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        // the encrypted value is sent as a binary blob
+        let mut astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(
+                &VOLATILE_DATASTREAM.replace("integer", "binaryblob"),
+            )
+            .unwrap()])
+            .build();
+
+        let mut encryption = crate::encryption::PayloadEncryption::default();
+        encryption.insert(interface, Arc::new(FlipCipher));
+        astarte.payload_encryption = Arc::new(encryption);
+
+        assert!(astarte
+            .send_unreliable(interface, "/value", 1)
+            .await
+            .unwrap());
+
+        astarte.capabilities = Capabilities {
+            max_payload_size: Some(8),
+            ..Default::default()
+        };
+
+        let err = astarte
+            .send_unreliable(interface, "/value", 2)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::PayloadTooLarge { max: 8, .. }),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stale_event_window() {
+        let mut astarte = MockDevice::new(AsyncClient::default(), EventLoop::default())
+            .interfaces([Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap()])
+            .build();
+
+        let event = |timestamp: Option<chrono::DateTime<chrono::Utc>>| {
+            let payload =
+                payload::serialize_individual(&AstarteType::Double(4.2), timestamp).unwrap();
+
+            Event::Incoming(rumqttc::Packet::Publish(rumqttc::Publish::new(
+                "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/1/intensity",
+                rumqttc::QoS::AtLeastOnce,
+                payload,
+            )))
+        };
+
+        let now = chrono::Utc::now();
+        let old = now - chrono::Duration::hours(2);
+
+        let stale = |event: Option<AstarteDeviceDataEvent>| event.map(|event| event.stale);
+
+        // without a window nothing is stale
+        let received = astarte.handle_event(event(Some(old))).await.unwrap();
+        assert_eq!(stale(received), Some(false));
+
+        astarte.stale_window = Some(StaleWindow {
+            window: std::time::Duration::from_secs(600),
+            policy: StalePolicy::Flag,
+        });
+
+        let received = astarte.handle_event(event(Some(old))).await.unwrap();
+        assert_eq!(stale(received), Some(true));
+        let received = astarte.handle_event(event(Some(now))).await.unwrap();
+        assert_eq!(stale(received), Some(false));
+        let received = astarte.handle_event(event(None)).await.unwrap();
+        assert_eq!(stale(received), Some(false));
+
+        astarte.stale_window = Some(StaleWindow {
+            window: std::time::Duration::from_secs(600),
+            policy: StalePolicy::Drop,
+        });
+
+        let received = astarte.handle_event(event(Some(old))).await.unwrap();
+        assert_eq!(stale(received), None);
+        let received = astarte.handle_event(event(Some(now))).await.unwrap();
+        assert_eq!(stale(received), Some(false));
+    }
+
+    #[tokio::test]
+    async fn test_recent_errors() {
+        let mut astarte = MockDevice::new(AsyncClient::default(), EventLoop::default())
+            .interfaces([
+                Interface::from_str(SERVER_PROPERTIES).unwrap(),
+                Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap(),
+            ])
+            .build();
+
+        assert!(astarte.recent_errors().is_empty());
+
+        astarte
+            .send_unreliable(SERVER_PROPERTIES_NAME, "/1/enable", true)
+            .await
+            .expect_err("sent a property without delivery guarantees");
+
+        astarte.max_event_size = Some(1);
+        let payload = payload::serialize_individual(&AstarteType::Double(4.2), None).unwrap();
+        let event = Event::Incoming(rumqttc::Packet::Publish(rumqttc::Publish::new(
+            "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/1/intensity",
+            rumqttc::QoS::AtLeastOnce,
+            payload,
+        )));
+        astarte
+            .eventloop
+            .lock()
+            .await
+            .expect_poll()
+            .once()
+            .return_once(|| Ok(event));
+        astarte
+            .handle_events()
+            .await
+            .expect_err("received a message too large");
+
+        let categories: Vec<ErrorCategory> = astarte
+            .recent_errors()
+            .into_iter()
+            .map(|record| record.category)
+            .collect();
+        assert_eq!(categories, [ErrorCategory::Send, ErrorCategory::Receive]);
+    }
+
+    #[derive(Default)]
+    struct RecordingHandler {
+        started: bool,
+        events: Vec<String>,
+        errors: usize,
+    }
+
+    #[async_trait]
+    impl DeviceHandler for &mut RecordingHandler {
+        async fn on_start(&mut self, _device: &AstarteDeviceSdk) -> Result<(), Error> {
+            self.started = true;
+
+            Ok(())
+        }
+
+        async fn on_event(
+            &mut self,
+            _device: &AstarteDeviceSdk,
+            event: AstarteDeviceDataEvent,
+        ) -> Result<(), Error> {
+            self.events.push(event.path);
+
+            if self.events.len() == 2 {
+                return Err(Error::Reported("stop".into()));
+            }
+
+            Ok(())
+        }
+
+        async fn on_error(&mut self, _device: &AstarteDeviceSdk, _error: &Error) {
+            self.errors += 1;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_supervise() {
+        let mut client = AsyncClient::default();
+        let mut eventloop = EventLoop::default();
+        let mut seq = mockall::Sequence::new();
+
+        for (sensor, err) in [(1, true), (2, false)] {
+            if err {
+                eventloop
+                    .expect_poll()
+                    .once()
+                    .in_sequence(&mut seq)
+                    .returning(|| {
+                        Err(rumqttc::ConnectionError::Io(std::io::Error::from(
+                            std::io::ErrorKind::ConnectionAborted,
+                        )))
+                    });
+            } else {
+                // received after the backoff
+                eventloop
+                    .expect_poll()
+                    .once()
+                    .in_sequence(&mut seq)
+                    .returning(|| {
+                        Ok(Event::Incoming(rumqttc::Packet::Publish(
+                            rumqttc::Publish::new(
+                                format!("realm/device_id/{SERVER_PROPERTIES_NAME}/1/enable"),
+                                rumqttc::QoS::AtLeastOnce,
+                                b"invalid".to_vec(),
+                            ),
+                        )))
+                    });
+            }
+
+            eventloop
+                .expect_poll()
+                .once()
+                .in_sequence(&mut seq)
+                .returning(move || {
+                    Ok(Event::Incoming(rumqttc::Packet::Publish(
+                        rumqttc::Publish::new(
+                            format!("realm/device_id/{SERVER_PROPERTIES_NAME}/{sensor}/enable"),
+                            rumqttc::QoS::AtLeastOnce,
+                            bson::to_vec(&bson::doc! { "v": true }).unwrap(),
+                        ),
+                    )))
+                });
+        }
+
+        // the device is shut down after the error of the handler
+        client.expect_disconnect().once().returning(|| Ok(()));
+        eventloop
+            .expect_poll()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|| Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)));
+
+        let astarte = MockDevice::new(client, eventloop)
+            .interfaces([Interface::from_str(SERVER_PROPERTIES).unwrap()])
+            .build();
+        let status = astarte.status();
+
+        let mut handler = RecordingHandler::default();
+        let start = tokio::time::Instant::now();
+        let err = run::supervise(astarte, &mut handler).await.unwrap_err();
+        assert!(start.elapsed() >= run::ERROR_BACKOFF);
+
+        assert!(
+            matches!(err, Error::Reported(_)),
+            "unexpected error {err:?}"
+        );
+        assert!(handler.started);
+        assert_eq!(handler.events, ["/1/enable", "/2/enable"]);
+        assert_eq!(handler.errors, 2);
+        assert_eq!(*status.borrow(), DeviceStatus::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_max_event_size() {
+        let mut astarte = MockDevice::new(AsyncClient::default(), EventLoop::default())
+            .interfaces([Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap()])
+            .build();
+
+        let payload = payload::serialize_individual(&AstarteType::Double(4.2), None).unwrap();
+        let size = payload.len();
+        let event = || {
+            Event::Incoming(rumqttc::Packet::Publish(rumqttc::Publish::new(
+                "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/1/intensity",
+                rumqttc::QoS::AtLeastOnce,
+                payload.clone(),
+            )))
+        };
+
+        astarte.max_event_size = Some(size - 1);
+        let err = astarte
+            .handle_event(event())
+            .await
+            .expect_err("accepted a message too large");
+        assert!(
+            matches!(err, Error::EventTooLarge { size: s, max, .. } if s == size && max == size - 1),
+            "{err:?}"
+        );
+
+        astarte.max_event_size = Some(size);
+        assert!(astarte.handle_event(event()).await.unwrap().is_some());
+
+        // the purge properties is checked once decoded
+        let mut astarte = mock_prune_store(AsyncClient::default()).await;
+        astarte.max_event_size = Some(PROPERTIES_PAYLOAD.len());
+
+        let purge = Event::Incoming(rumqttc::Packet::Publish(rumqttc::Publish::new(
+            "realm/device_id/control/consumer/properties",
+            rumqttc::QoS::AtLeastOnce,
+            PROPERTIES_PAYLOAD.to_vec(),
+        )));
+        let err = astarte
+            .handle_event(purge)
+            .await
+            .expect_err("decoded a purge properties too large");
+        assert!(
+            matches!(
+                err,
+                Error::EventTooLarge {
+                    size: 70,
+                    max: 66,
+                    ..
+                }
             ),
+            "{err:?}"
+        );
+        assert!(!astarte.property_snapshot().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_event_metadata() {
+        let astarte = MockDevice::new(AsyncClient::default(), EventLoop::default())
+            .interfaces([Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap()])
+            .build();
+
+        let timestamp = chrono::Utc::now() - chrono::Duration::seconds(5);
+        let payload =
+            payload::serialize_individual(&AstarteType::Double(4.2), Some(timestamp)).unwrap();
+
+        let mut publish = rumqttc::Publish::new(
+            "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/1/intensity",
+            rumqttc::QoS::ExactlyOnce,
+            payload,
+        );
+        publish.dup = true;
+        publish.retain = true;
+
+        let before = chrono::Utc::now();
+        let event = astarte
+            .handle_event(Event::Incoming(rumqttc::Packet::Publish(publish)))
+            .await
+            .unwrap()
+            .expect("event not delivered");
+
+        let metadata = event.metadata;
+        assert!(metadata.received_at >= before && metadata.received_at <= chrono::Utc::now());
+        assert_eq!(metadata.timestamp, Some(timestamp));
+        assert_eq!(metadata.qos, rumqttc::QoS::ExactlyOnce);
+        assert!(metadata.retain);
+        assert!(metadata.duplicate);
+    }
+
+    #[tokio::test]
+    async fn test_retained_policy() {
+        let mut astarte = MockDevice::new(AsyncClient::default(), EventLoop::default())
+            .interfaces([Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap()])
+            .build();
+
+        let retained = || {
+            let payload = payload::serialize_individual(&AstarteType::Double(4.2), None).unwrap();
+
+            let mut publish = rumqttc::Publish::new(
+                "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/1/intensity",
+                rumqttc::QoS::AtLeastOnce,
+                payload,
+            );
+            publish.retain = true;
+
+            Event::Incoming(rumqttc::Packet::Publish(publish))
+        };
+
+        for (policy, retain) in [
+            (RetainedPolicy::Mark, true),
+            (RetainedPolicy::Process, false),
+        ] {
+            astarte.retained_policy = policy;
+
+            let event = astarte
+                .handle_event(retained())
+                .await
+                .unwrap()
+                .expect("event not delivered");
+
+            assert_eq!(event.metadata.retain, retain, "{policy:?}");
+            assert_eq!(
+                event.data,
+                Aggregation::Individual(AstarteType::Double(4.2))
+            );
+        }
+
+        astarte.retained_policy = RetainedPolicy::Ignore;
+        let event = astarte.handle_event(retained()).await.unwrap();
+        assert!(event.is_none(), "{event:?}");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_events_dont_panic() {
+        let astarte = MockDevice::new(AsyncClient::default(), EventLoop::default())
+            .interfaces([
+                Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap(),
+                Interface::from_str(SERVER_PROPERTIES).unwrap(),
+            ])
+            .build();
+
+        let datastream = "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream";
+        let properties = format!("realm/device_id/{SERVER_PROPERTIES_NAME}");
+
+        let events: Vec<(String, Vec<u8>)> = vec![
+            ("realm".to_string(), Vec::new()),
+            ("realm/device_id/".to_string(), Vec::new()),
+            (datastream.to_string(), Vec::new()),
+            (format!("{datastream}/"), Vec::new()),
+            (format!("{datastream}//intensity"), Vec::new()),
+            (format!("{datastream}/1/intensity"), vec![0xff; 16]),
             (
-                "endpoint07".to_string(),
-                AstarteType::DateTime(my_aggregate.endpoint07),
+                format!("{datastream}/1/unknown"),
+                bson::to_vec(&bson::doc! { "v": 1.0 }).unwrap(),
             ),
             (
-                "endpoint08".to_string(),
-                AstarteType::DoubleArray(my_aggregate.endpoint08.clone()),
+                format!("{datastream}/1/intensity"),
+                bson::to_vec(&bson::doc! { "v": [] }).unwrap(),
             ),
             (
-                "endpoint09".to_string(),
-                AstarteType::IntegerArray(my_aggregate.endpoint09.clone()),
+                format!("{datastream}/1/intensity"),
+                bson::to_vec(&bson::doc! { "v": "string" }).unwrap(),
             ),
             (
-                "endpoint10".to_string(),
-                AstarteType::BooleanArray(my_aggregate.endpoint10.clone()),
+                format!("{datastream}/1/intensity"),
+                bson::to_vec(&bson::doc! { "t": 1 }).unwrap(),
             ),
             (
-                "endpoint11".to_string(),
-                AstarteType::LongIntegerArray(my_aggregate.endpoint11.clone()),
+                format!("{properties}/1/enable"),
+                vec![0x05, 0x00, 0x00, 0x00],
             ),
             (
-                "endpoint12".to_string(),
-                AstarteType::StringArray(my_aggregate.endpoint12.clone()),
+                "realm/device_id/control/consumer/properties".to_string(),
+                Vec::new(),
             ),
             (
-                "endpoint13".to_string(),
-                AstarteType::BinaryBlobArray(my_aggregate.endpoint13.clone()),
+                "realm/device_id/control/consumer/properties".to_string(),
+                vec![0, 0, 0, 4, 1, 2],
             ),
-            (
-                "endpoint14".to_string(),
-                AstarteType::DateTimeArray(my_aggregate.endpoint14.clone()),
+        ];
+
+        for (topic, payload) in events {
+            let event = Event::Incoming(rumqttc::Packet::Publish(rumqttc::Publish::new(
+                &topic,
+                rumqttc::QoS::AtLeastOnce,
+                payload,
+            )));
+
+            let res = std::panic::AssertUnwindSafe(astarte.handle_event(event))
+                .catch_unwind()
+                .await;
+
+            assert!(res.is_ok(), "panicked handling the event on {topic}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_filters() {
+        let mut eventloope = EventLoop::default();
+        let mut seq = mockall::Sequence::new();
+
+        let messages = [
+            ("/1/enable", bson::doc! { "v": true }),
+            ("/1/intensity", bson::doc! { "v": 0.5 }),
+            ("/2/intensity", bson::doc! { "v": 4.2 }),
+        ];
+
+        for (path, data) in messages {
+            let topic = format!("realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream{path}");
+
+            eventloope
+                .expect_poll()
+                .once()
+                .in_sequence(&mut seq)
+                .returning(move || {
+                    Ok(Event::Incoming(rumqttc::Packet::Publish(
+                        rumqttc::Publish::new(
+                            &topic,
+                            rumqttc::QoS::AtLeastOnce,
+                            bson::to_vec(&data).unwrap(),
+                        ),
+                    )))
+                });
+        }
+
+        let mut astarte = MockDevice::new(AsyncClient::default(), eventloope)
+            .interfaces([Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap()])
+            .build();
+
+        let mut filters = EventFilters::default();
+        filters.push(
+            EventFilter::new(
+                "org.astarte-platform.rust.examples.individual-datastream.ServerDatastream",
+            )
+            .path("/*/intensity")
+            .unwrap()
+            .value(
+                |data| matches!(data, Aggregation::Individual(AstarteType::Double(v)) if *v > 1.0),
             ),
-        ]);
-        assert_eq!(expected_res, my_aggregate.astarte_aggregate().unwrap());
-        println!("{expected_res:?}");
+        );
+        astarte.event_filters = Arc::new(filters);
+
+        let event = astarte.handle_events().await.unwrap();
+
+        assert_eq!(event.path, "/2/intensity");
+        assert_eq!(
+            event.data,
+            Aggregation::Individual(AstarteType::Double(4.2))
+        );
     }
 
-    #[derive(AstarteAggregate)]
-    #[astarte_aggregate(rename_all = "UPPERCASE")]
-    struct MyUpperCasedAggregate {
-        first_endpoint: f64,
-        second_endpoint: f64,
+    #[tokio::test]
+    async fn test_value_constraint() {
+        let mut eventloope = EventLoop::default();
+        let mut seq = mockall::Sequence::new();
+
+        let messages = [
+            ("/1/samplingPeriod", bson::doc! { "v": 5000 }),
+            ("/1/samplingPeriod", bson::doc! { "v": 10 }),
+        ];
+
+        for (path, data) in messages {
+            let topic = format!("realm/device_id/org.astarte-platform.rust.examples.individual-properties.ServerProperties{path}");
+
+            eventloope
+                .expect_poll()
+                .once()
+                .in_sequence(&mut seq)
+                .returning(move || {
+                    Ok(Event::Incoming(rumqttc::Packet::Publish(
+                        rumqttc::Publish::new(
+                            &topic,
+                            rumqttc::QoS::ExactlyOnce,
+                            bson::to_vec(&data).unwrap(),
+                        ),
+                    )))
+                });
+        }
+
+        let mut astarte = MockDevice::new(AsyncClient::default(), eventloope)
+            .interfaces([Interface::from_str(SERVER_PROPERTIES).unwrap()])
+            .build();
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+        astarte.database = Some(Arc::new(db));
+
+        let mut constraints = ValueConstraints::default();
+        constraints.push(
+            ValueConstraint::new(
+                "org.astarte-platform.rust.examples.individual-properties.ServerProperties",
+                "/*/samplingPeriod",
+            )
+            .unwrap()
+            .range(1.0, 60.0),
+        );
+        astarte.value_constraints = Arc::new(constraints);
+
+        let res = astarte.handle_events().await;
+        assert!(
+            matches!(res, Err(Error::ConstraintViolation { ref path, .. }) if path == "/1/samplingPeriod"),
+            "{res:?}"
+        );
+
+        let stored = astarte
+            .get_property(
+                "org.astarte-platform.rust.examples.individual-properties.ServerProperties",
+                "/1/samplingPeriod",
+            )
+            .await
+            .unwrap();
+        assert_eq!(stored, None);
+
+        let event = astarte.handle_events().await.unwrap();
+        assert_eq!(
+            event.data,
+            Aggregation::Individual(AstarteType::Integer(10))
+        );
     }
 
-    #[test]
-    fn test_astarte_aggregate_trait_upper_case_attribute() {
-        let my_aggregate = MyUpperCasedAggregate {
-            first_endpoint: 4.34,
-            second_endpoint: 23.0,
-        };
-        let expected_res = HashMap::from([
-            (
-                "FIRST_ENDPOINT".to_string(),
-                AstarteType::Double(my_aggregate.first_endpoint),
-            ),
-            (
-                "SECOND_ENDPOINT".to_string(),
-                AstarteType::Double(my_aggregate.second_endpoint),
-            ),
-        ]);
-        assert_eq!(expected_res, my_aggregate.astarte_aggregate().unwrap());
+    #[tokio::test]
+    async fn test_value_transform() {
+        let mut eventloope = EventLoop::default();
+        let mut seq = mockall::Sequence::new();
+
+        for data in [bson::doc! { "v": 5000 }, bson::doc! { "v": 0 }] {
+            eventloope
+                .expect_poll()
+                .once()
+                .in_sequence(&mut seq)
+                .returning(move || {
+                    Ok(Event::Incoming(rumqttc::Packet::Publish(
+                        rumqttc::Publish::new(
+                            format!("realm/device_id/{SERVER_PROPERTIES_NAME}/1/samplingPeriod"),
+                            rumqttc::QoS::ExactlyOnce,
+                            bson::to_vec(&data).unwrap(),
+                        ),
+                    )))
+                });
+        }
+
+        let mut astarte = MockDevice::new(AsyncClient::default(), eventloope)
+            .interfaces([Interface::from_str(SERVER_PROPERTIES).unwrap()])
+            .build();
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+        astarte.database = Some(Arc::new(db));
+
+        let mut transforms = ValueTransforms::default();
+        transforms.push(
+            ValueTransform::new(SERVER_PROPERTIES_NAME, "/*/samplingPeriod")
+                .unwrap()
+                .scale(0.001, 0.0)
+                .map(|value| match value {
+                    AstarteType::Double(v) if v > 0.0 => Ok(AstarteType::Double(v)),
+                    _ => Err("the period must be positive".to_string()),
+                }),
+        );
+        astarte.value_transforms = Arc::new(transforms);
+
+        let event = astarte.handle_events().await.unwrap();
+        assert_eq!(
+            event.data,
+            Aggregation::Individual(AstarteType::Double(5.0))
+        );
+
+        // the store keeps the received value
+        let stored = astarte
+            .get_property(SERVER_PROPERTIES_NAME, "/1/samplingPeriod")
+            .await
+            .unwrap();
+        assert_eq!(stored, Some(AstarteType::Integer(5000)));
+
+        let res = astarte.handle_events().await;
+        assert!(
+            matches!(res, Err(Error::Transform { ref path, .. }) if path == "/1/samplingPeriod"),
+            "{res:?}"
+        );
     }
 
-    #[derive(AstarteAggregate)]
-    #[astarte_aggregate(rename_all = "PascalCase")]
-    struct MyPascalCasedAggregate {
-        first_endpoint: f64,
-        second_endpoint: f64,
+    const SENSITIVE_DATASTREAM: &str = r#"
+    {
+        "interface_name": "org.astarte-platform.test.Sensitive",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "ownership": "server",
+        "mappings": [
+            {
+                "endpoint": "/%{patient}/vitals",
+                "type": "binaryblob"
+            }
+        ]
     }
+    "#;
+
+    /// Flips all the bits of the payload.
+    struct FlipCipher;
+
+    impl crate::encryption::PayloadCipher for FlipCipher {
+        fn encrypt(
+            &self,
+            _interface: &str,
+            _path: &str,
+            plaintext: &[u8],
+        ) -> Result<Vec<u8>, crate::encryption::CipherError> {
+            Ok(plaintext.iter().map(|b| !b).collect())
+        }
 
-    #[test]
-    fn test_astarte_aggregate_trait_pascal_case_attribute() {
-        let my_aggregate = MyPascalCasedAggregate {
-            first_endpoint: 4.34,
-            second_endpoint: 23.0,
+        fn decrypt(
+            &self,
+            _interface: &str,
+            _path: &str,
+            ciphertext: &[u8],
+        ) -> Result<Vec<u8>, crate::encryption::CipherError> {
+            Ok(ciphertext.iter().map(|b| !b).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_payload_encryption() {
+        let interface = "org.astarte-platform.test.Sensitive";
+        let encrypted = |value: &AstarteType| {
+            let plaintext = payload::serialize_individual(value, None).unwrap();
+
+            AstarteType::BinaryBlob(plaintext.iter().map(|b| !b).collect())
         };
-        let expected_res = HashMap::from([
-            (
-                "FirstEndpoint".to_string(),
-                AstarteType::Double(my_aggregate.first_endpoint),
-            ),
-            (
-                "SecondEndpoint".to_string(),
-                AstarteType::Double(my_aggregate.second_endpoint),
+
+        let mut eventloope = EventLoop::default();
+        let mut seq = mockall::Sequence::new();
+        for value in [
+            encrypted(&AstarteType::Double(36.6)),
+            AstarteType::Integer(1),
+        ] {
+            let data = payload::serialize_individual(&value, None).unwrap();
+            eventloope
+                .expect_poll()
+                .once()
+                .in_sequence(&mut seq)
+                .returning(move || {
+                    Ok(Event::Incoming(rumqttc::Packet::Publish(
+                        rumqttc::Publish::new(
+                            format!("realm/device_id/{interface}/patient_1/vitals"),
+                            rumqttc::QoS::ExactlyOnce,
+                            data.clone(),
+                        ),
+                    )))
+                });
+        }
+
+        let mut client = AsyncClient::default();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .with(
+                predicate::eq(format!("realm/device_id/{DEVICE_PROPERTIES_NAME}/1/name")),
+                predicate::always(),
+                predicate::always(),
+                predicate::eq(
+                    payload::serialize_individual(
+                        &encrypted(&AstarteType::String("patient".to_string())),
+                        None,
+                    )
+                    .unwrap(),
+                ),
+            )
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut astarte = MockDevice::new(client, eventloope)
+            .interfaces([
+                Interface::from_str(SENSITIVE_DATASTREAM).unwrap(),
+                // the encrypted name is sent as a binary blob
+                Interface::from_str(&DEVICE_PROPERTIES.replace(r#""string""#, r#""binaryblob""#))
+                    .unwrap(),
+            ])
+            .build();
+
+        let mut encryption = crate::encryption::PayloadEncryption::default();
+        encryption.insert(interface, Arc::new(FlipCipher));
+        encryption.insert(DEVICE_PROPERTIES_NAME, Arc::new(FlipCipher));
+        astarte.payload_encryption = Arc::new(encryption);
+
+        astarte
+            .send(DEVICE_PROPERTIES_NAME, "/1/name", "patient")
+            .await
+            .unwrap();
+
+        let event = astarte.handle_events().await.unwrap();
+        assert_eq!(event.path, "/patient_1/vitals");
+        assert_eq!(
+            event.data,
+            Aggregation::Individual(AstarteType::Double(36.6))
+        );
+
+        let res = astarte.handle_events().await;
+        assert!(
+            matches!(
+                res,
+                Err(Error::Encryption {
+                    source: crate::encryption::EncryptionError::NotBinary,
+                    ..
+                })
             ),
-        ]);
-        assert_eq!(expected_res, my_aggregate.astarte_aggregate().unwrap());
+            "{res:?}"
+        );
     }
 
     #[tokio::test]
-    async fn test_wait_for_connack() {
+    async fn test_encrypted_value_constraint() {
+        let interface = "org.astarte-platform.test.Sensitive";
+
         let mut eventloope = EventLoop::default();
+        let mut seq = mockall::Sequence::new();
+        for value in [AstarteType::Double(36.6), AstarteType::Double(50.0)] {
+            let plaintext = payload::serialize_individual(&value, None).unwrap();
+            let ciphertext = AstarteType::BinaryBlob(plaintext.iter().map(|b| !b).collect());
+            let data = payload::serialize_individual(&ciphertext, None).unwrap();
+
+            eventloope
+                .expect_poll()
+                .once()
+                .in_sequence(&mut seq)
+                .returning(move || {
+                    Ok(Event::Incoming(rumqttc::Packet::Publish(
+                        rumqttc::Publish::new(
+                            format!("realm/device_id/{interface}/patient_1/vitals"),
+                            rumqttc::QoS::ExactlyOnce,
+                            data.clone(),
+                        ),
+                    )))
+                });
+        }
 
-        eventloope.expect_poll().once().returning(|| {
-            Ok(Event::Incoming(rumqttc::Packet::ConnAck(
-                rumqttc::ConnAck {
-                    session_present: false,
-                    code: rumqttc::ConnectReturnCode::Success,
-                },
-            )))
-        });
+        let mut astarte = MockDevice::new(AsyncClient::default(), eventloope)
+            .interfaces([Interface::from_str(SENSITIVE_DATASTREAM).unwrap()])
+            .build();
 
-        let mut client = AsyncClient::default();
+        let mut encryption = crate::encryption::PayloadEncryption::default();
+        encryption.insert(interface, Arc::new(FlipCipher));
+        astarte.payload_encryption = Arc::new(encryption);
 
-        client
-            .expect_subscribe()
-            .once()
-            .returning(|_: String, _| Ok(()));
+        // the constraints are checked on the decrypted values
+        let mut constraints = ValueConstraints::default();
+        constraints.push(
+            ValueConstraint::new(interface, "/*/vitals")
+                .unwrap()
+                .range(30.0, 45.0),
+        );
+        astarte.value_constraints = Arc::new(constraints);
 
-        client
-            .expect_publish::<String, String>()
-            .returning(|topic, _, _, _| {
-                // Client id
-                assert_eq!(topic, "realm/device_id");
+        let event = astarte.handle_events().await.unwrap();
+        assert_eq!(
+            event.data,
+            Aggregation::Individual(AstarteType::Double(36.6))
+        );
 
-                Ok(())
-            });
+        let res = astarte.handle_events().await;
+        assert!(
+            matches!(res, Err(Error::ConstraintViolation { ref path, .. }) if path == "/patient_1/vitals"),
+            "{res:?}"
+        );
+    }
+
+    /// Prefixes the flipped plaintext with a different nonce on each encryption.
+    #[derive(Default)]
+    struct NonceCipher {
+        nonce: std::sync::atomic::AtomicU8,
+    }
 
+    impl crate::encryption::PayloadCipher for NonceCipher {
+        fn encrypt(
+            &self,
+            _interface: &str,
+            _path: &str,
+            plaintext: &[u8],
+        ) -> Result<Vec<u8>, crate::encryption::CipherError> {
+            let nonce = self.nonce.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+            Ok(std::iter::once(nonce)
+                .chain(plaintext.iter().map(|b| !b))
+                .collect())
+        }
+
+        fn decrypt(
+            &self,
+            _interface: &str,
+            _path: &str,
+            ciphertext: &[u8],
+        ) -> Result<Vec<u8>, crate::encryption::CipherError> {
+            Ok(ciphertext.iter().skip(1).map(|b| !b).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_property_unchanged() {
+        let mut client = AsyncClient::default();
         client
-            .expect_publish::<String, &str>()
-            .returning(|topic, _, _, payload| {
-                // empty cache
-                assert_eq!(topic, "realm/device_id/control/emptyCache");
+            .expect_publish::<String, Vec<u8>>()
+            .times(2)
+            .returning(|_, _, _, _| Ok(()));
 
-                assert_eq!(payload, "1");
+        let mut astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(
+                &DEVICE_PROPERTIES.replace(r#""string""#, r#""binaryblob""#),
+            )
+            .unwrap()])
+            .build();
 
-                Ok(())
-            });
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+        astarte.database = Some(Arc::new(db));
 
-        client.expect_subscribe::<String>().returning(|topic, _qos| {
-            assert_eq!(topic, "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/#");
+        let mut encryption = crate::encryption::PayloadEncryption::default();
+        encryption.insert(DEVICE_PROPERTIES_NAME, Arc::new(NonceCipher::default()));
+        astarte.payload_encryption = Arc::new(encryption);
 
-            Ok(())
-        });
+        // the second value is encrypted with another nonce, but it's not sent again
+        for name in ["patient", "patient", "doctor"] {
+            astarte
+                .send(DEVICE_PROPERTIES_NAME, "/1/name", name)
+                .await
+                .unwrap();
+        }
+    }
 
-        let interfaces = [
-            Interface::from_str(OBJECT_DEVICE_DATASTREAM).unwrap(),
-            Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap(),
-        ];
+    #[tokio::test]
+    async fn test_event_loop_panic() {
+        let mut eventloope = EventLoop::default();
 
-        let mut astarte = mock_astarte_device(client, eventloope, interfaces);
+        eventloope
+            .expect_poll()
+            .once()
+            .returning(|| panic!("broken transport"));
 
-        astarte.wait_for_connack().await.unwrap();
+        let mut astarte = MockDevice::new(AsyncClient::default(), eventloope)
+            .interfaces([])
+            .build();
+        let status = astarte.status();
+
+        let res = astarte.handle_events().await;
+        assert!(matches!(res, Err(Error::Terminated(_))), "{res:?}");
+
+        assert_eq!(
+            *status.borrow(),
+            DeviceStatus::Failed {
+                reason: "MQTT event loop panicked: broken transport".to_string()
+            }
+        );
+
+        // the event loop is not polled again
+        let res = astarte.handle_events().await;
+        assert!(matches!(res, Err(Error::Terminated(_))), "{res:?}");
     }
 
     #[tokio::test]
-    async fn test_add_remove_interface() {
-        let eventloope = EventLoop::default();
+    async fn test_event_processing_panic() {
+        let mut eventloope = EventLoop::default();
+
+        eventloope.expect_poll().once().returning(|| {
+            Ok(Event::Incoming(rumqttc::Packet::Publish(
+                rumqttc::Publish::new(
+                    "realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/1/intensity",
+                    rumqttc::QoS::AtLeastOnce,
+                    bson::to_vec(&bson::doc! { "v": 4.2 }).unwrap(),
+                ),
+            )))
+        });
+
+        let mut astarte = MockDevice::new(AsyncClient::default(), eventloope)
+            .interfaces([Interface::from_str(INDIVIDUAL_SERVER_DATASTREAM).unwrap()])
+            .build();
+
+        let mut filters = EventFilters::default();
+        filters.push(
+            EventFilter::new(
+                "org.astarte-platform.rust.examples.individual-datastream.ServerDatastream",
+            )
+            .value(|_| panic!("broken filter")),
+        );
+        astarte.event_filters = Arc::new(filters);
+
+        let res = astarte.handle_events().await;
+        assert!(
+            matches!(res, Err(Error::Terminated(ref reason)) if reason == "event processing panicked: broken filter"),
+            "{res:?}"
+        );
+        assert_eq!(
+            *astarte.status().borrow(),
+            DeviceStatus::Failed {
+                reason: "event processing panicked: broken filter".to_string()
+            }
+        );
+
+        // the next events are not processed
+        let res = astarte.handle_events().await;
+        assert!(matches!(res, Err(Error::Terminated(_))), "{res:?}");
+    }
+
+    astarte_device_sdk_derive::include_interface!(
+        typed_properties,
+        "examples/individual_properties/interfaces/org.astarte-platform.rust.examples.individual-properties.DeviceProperties.json"
+    );
+    astarte_device_sdk_derive::include_interface!(
+        "examples/object_datastream/interfaces/org.astarte-platform.rust.examples.object-datastream.DeviceDatastream.json"
+    );
+
+    #[tokio::test]
+    async fn test_typed_interface() {
+        use org_astarte_platform_rust_examples_object_datastream_device_datastream as typed_object;
+
+        let timestamp = chrono::Utc::now();
+        let object = HashMap::from([
+            ("endpoint1".to_string(), AstarteType::Double(4.5)),
+            (
+                "endpoint2".to_string(),
+                AstarteType::String("on".to_string()),
+            ),
+            (
+                "endpoint3".to_string(),
+                AstarteType::BooleanArray(vec![true]),
+            ),
+        ]);
 
         let mut client = AsyncClient::default();
 
+        let name = payload::serialize_individual(&AstarteType::from("thermometer"), None).unwrap();
+        let unset = payload::serialize_individual(&AstarteType::Unset, None).unwrap();
         client
-            .expect_subscribe::<String>()
+            .expect_publish::<String, Vec<u8>>()
             .once()
             .with(
-                predicate::eq("realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/#".to_string()),
-                predicate::always()
+                predicate::eq(format!(
+                    "realm/device_id/{}/1/name",
+                    typed_properties::INTERFACE
+                )),
+                predicate::always(),
+                predicate::always(),
+                predicate::eq(name),
             )
-            .returning(|_, _| { Ok(()) });
-
+            .returning(|_, _, _, _| Ok(()));
         client
-            .expect_publish::<String, String>()
+            .expect_publish::<String, Vec<u8>>()
+            .once()
             .with(
-                predicate::eq("realm/device_id".to_string()),
+                predicate::eq(format!(
+                    "realm/device_id/{}/1/name",
+                    typed_properties::INTERFACE
+                )),
                 predicate::always(),
-                predicate::eq(false),
-                predicate::eq(
-                    "org.astarte-platform.rust.examples.individual-datastream.ServerDatastream:0:1"
-                        .to_string(),
-                ),
+                predicate::always(),
+                predicate::eq(unset),
             )
             .returning(|_, _, _, _| Ok(()));
 
+        let expected = payload::serialize_object(&object, Some(timestamp)).unwrap();
+        let expected = bson::Document::from_reader(expected.as_slice()).unwrap();
         client
-            .expect_publish::<String, String>()
+            .expect_publish::<String, Vec<u8>>()
+            .once()
             .with(
-                predicate::eq("realm/device_id".to_string()),
+                predicate::eq(format!("realm/device_id/{}/2", typed_object::INTERFACE)),
                 predicate::always(),
-                predicate::eq(false),
-                predicate::eq(String::new()),
+                predicate::always(),
+                // the order of the object fields can change
+                predicate::function(move |buf: &Vec<u8>| {
+                    bson::Document::from_reader(buf.as_slice()).ok() == Some(expected.clone())
+                }),
             )
             .returning(|_, _, _, _| Ok(()));
 
-        client
-            .expect_unsubscribe::<String>()
-            .with(predicate::eq("realm/device_id/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream/#".to_string()))
-            .returning(|_| Ok(()));
+        let astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([
+                Interface::from_str(DEVICE_PROPERTIES).unwrap(),
+                Interface::from_str(OBJECT_DEVICE_DATASTREAM).unwrap(),
+            ])
+            .build();
 
-        let astarte = mock_astarte_device(client, eventloope, []);
+        assert_eq!(typed_properties::endpoints::NAME, "/%{sensor_id}/name");
 
-        astarte
-            .add_interface_from_str(INDIVIDUAL_SERVER_DATASTREAM)
+        typed_properties::send_name(&astarte, "1", "thermometer")
             .await
             .unwrap();
+        typed_properties::unset_name(&astarte, "1").await.unwrap();
 
-        astarte
-            .remove_interface(
-                "org.astarte-platform.rust.examples.individual-datastream.ServerDatastream",
-            )
+        typed_object::send_with_timestamp(&astarte, "2", object, timestamp)
             .await
             .unwrap();
     }
 
+    const SEQUENCED_OBJECT: &str = r#"{
+        "interface_name": "org.astarte-platform.test.Sequenced",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "aggregation": "object",
+        "ownership": "device",
+        "mappings": [
+            { "endpoint": "/%{sensor_id}/value", "type": "double" },
+            { "endpoint": "/%{sensor_id}/seq", "type": "longinteger" }
+        ]
+    }"#;
+
     #[tokio::test]
-    async fn test_handle_event() {
+    async fn test_sequence_numbers() {
+        let interface = "org.astarte-platform.test.Sequenced";
+
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("sequences.sqlite");
+        let db = AstarteSqliteDatabase::new(db_path.to_str().unwrap())
+            .await
+            .unwrap();
+
+        let sequenced = |seq: i64| {
+            move |buf: &Vec<u8>| {
+                let Ok(Aggregation::Object(object)) = payload::deserialize(buf) else {
+                    return false;
+                };
+
+                object.get("seq") == Some(&AstarteType::LongInteger(seq))
+            }
+        };
+
         let mut client = AsyncClient::default();
+        for seq in [1, 2, 3] {
+            client
+                .expect_publish::<String, Vec<u8>>()
+                .once()
+                .with(
+                    predicate::eq(format!("realm/device_id/{interface}/1")),
+                    predicate::always(),
+                    predicate::always(),
+                    predicate::function(sequenced(seq)),
+                )
+                .returning(|_, _, _, _| Ok(()));
+        }
 
-        client
-            .expect_publish::<String, Vec<u8>>()
-            .once()
-            .with(
-                predicate::eq("realm/device_id/org.astarte-platform.rust.examples.individual-properties.DeviceProperties/1/name".to_string()),
-                predicate::always(),
-                predicate::always(),
-                predicate::function(|buf: &Vec<u8>| {
-                    let doc= bson::Document::from_reader(buf.as_slice()).unwrap();
+        let mut astarte: AstarteDeviceSdk<AstarteSqliteDatabase> =
+            MockDevice::new(client, EventLoop::default())
+                .interfaces([Interface::from_str(SEQUENCED_OBJECT).unwrap()])
+                .build_with(None);
+        astarte.database = Some(Arc::new(db.clone()));
+        astarte.sequences = Arc::new(
+            [(interface.to_string(), "seq".to_string())]
+                .into_iter()
+                .collect(),
+        );
 
-                    let value = doc.get("v").unwrap().as_str().unwrap();
+        assert_eq!(astarte.last_sequence(interface).await, None);
 
-                    value == "name number 1"
-                }),
-            )
-            .returning(|_, _, _, _| Ok(()));
+        let value = || HashMap::from([("value".to_string(), AstarteType::Double(1.0))]);
+        astarte.send_object(interface, "/1", value()).await.unwrap();
+        astarte.send_object(interface, "/1", value()).await.unwrap();
+        assert_eq!(astarte.last_sequence(interface).await, Some(2));
 
-        let mut eventloope = EventLoop::default();
+        // the sequence continues after a restart
+        astarte.sequences = Arc::new(
+            [(interface.to_string(), "seq".to_string())]
+                .into_iter()
+                .collect(),
+        );
+        astarte.send_object(interface, "/1", value()).await.unwrap();
+        assert_eq!(astarte.last_sequence(interface).await, Some(3));
+        assert_eq!(db.load_sequence(interface).await.unwrap(), Some(3));
+    }
 
-        let data = bson::doc! {
-            "v": true
-        };
+    #[cfg(feature = "otel")]
+    const TRACED_DEVICE_OBJECT: &str = r#"{
+        "interface_name": "org.astarte-platform.test.TracedDevice",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "aggregation": "object",
+        "ownership": "device",
+        "mappings": [
+            { "endpoint": "/%{sensor_id}/value", "type": "double" },
+            { "endpoint": "/%{sensor_id}/traceparent", "type": "string" }
+        ]
+    }"#;
+
+    #[cfg(feature = "otel")]
+    const TRACED_SERVER_OBJECT: &str = r#"{
+        "interface_name": "org.astarte-platform.test.TracedServer",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "aggregation": "object",
+        "ownership": "server",
+        "mappings": [
+            { "endpoint": "/%{sensor_id}/value", "type": "double" },
+            { "endpoint": "/%{sensor_id}/traceparent", "type": "string" }
+        ]
+    }"#;
+
+    #[cfg(feature = "otel")]
+    #[tokio::test]
+    async fn test_otel_spans() {
+        use opentelemetry_api::trace::{FutureExt, SpanId, TraceContextExt, TraceId, Tracer};
+        use opentelemetry_api::{global, Context};
+
+        let (exporter, spans, _) = opentelemetry_sdk::testing::trace::new_test_exporter();
+        global::set_tracer_provider(
+            opentelemetry_sdk::trace::TracerProvider::builder()
+                .with_simple_exporter(exporter)
+                .build(),
+        );
 
-        // Purge properties
-        eventloope.expect_poll().once().returning(|| {
-            Ok(Event::Incoming(rumqttc::Packet::Publish(
-                rumqttc::Publish::new(
-                    "realm/device_id/control/consumer/properties",
-                    rumqttc::QoS::AtLeastOnce,
-                    PROPERTIES_PAYLOAD,
-                ),
-            )))
-        });
+        let app = Context::current_with_span(global::tracer("app").start("app"));
+        let app_span = app.span().span_context().clone();
 
-        // Send properties
+        let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+        let mut client = AsyncClient::default();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .withf(move |_, _, _, payload| {
+                let Ok(Aggregation::Object(object)) = payload::deserialize(payload) else {
+                    return false;
+                };
+
+                matches!(
+                    object.get("traceparent"),
+                    Some(AstarteType::String(traceparent))
+                        if traceparent.contains(&app_span.trace_id().to_string())
+                )
+            })
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut eventloope = EventLoop::default();
         eventloope.expect_poll().once().returning(move || {
+            let object = bson::doc! {
+                "v": {
+                    "value": 4.2,
+                    "traceparent": traceparent,
+                }
+            };
+
             Ok(Event::Incoming(rumqttc::Packet::Publish(
                 rumqttc::Publish::new(
-                    "realm/device_id/org.astarte-platform.rust.examples.individual-properties.ServerProperties/1/enable",
+                    "realm/device_id/org.astarte-platform.test.TracedServer/1",
                     rumqttc::QoS::AtLeastOnce,
-                    bson::to_vec(&data).unwrap()
+                    bson::to_vec(&object).unwrap(),
                 ),
             )))
         });
 
-        let mut astarte = mock_astarte_device(
-            client,
-            eventloope,
-            [
-                Interface::from_str(DEVICE_PROPERTIES).unwrap(),
-                Interface::from_str(SERVER_PROPERTIES).unwrap(),
-            ],
-        );
+        let mut astarte = MockDevice::new(client, eventloope)
+            .interfaces([
+                Interface::from_str(TRACED_DEVICE_OBJECT).unwrap(),
+                Interface::from_str(TRACED_SERVER_OBJECT).unwrap(),
+            ])
+            .build();
+        astarte.tracing = Arc::new(crate::otel::MessageTracing::new(HashMap::from([
+            (
+                "org.astarte-platform.test.TracedDevice".to_string(),
+                "traceparent".to_string(),
+            ),
+            (
+                "org.astarte-platform.test.TracedServer".to_string(),
+                "traceparent".to_string(),
+            ),
+        ])));
 
+        let value = HashMap::from([("value".to_string(), AstarteType::Double(1.0))]);
         astarte
-            .send(
-                "org.astarte-platform.rust.examples.individual-properties.DeviceProperties",
-                "/1/name",
-                "name number 1".to_string(),
-            )
+            .send_object("org.astarte-platform.test.TracedDevice", "/1", value)
+            .with_context(app.clone())
             .await
             .unwrap();
 
-        let event = astarte.handle_events().await;
+        let event = astarte.handle_events().await.unwrap();
+        assert_eq!(event.interface, "org.astarte-platform.test.TracedServer");
 
-        assert!(
-            event.is_ok(),
-            "Error handling event {:?}",
-            event.unwrap_err()
+        // the spans of the other tests are exported too
+        let mut exported = HashMap::new();
+        while exported.len() < 3 {
+            let span = spans
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .unwrap();
+
+            let traced = span.attributes.iter().any(|(key, value)| {
+                key.as_str() == "astarte.interface" && value.as_str().contains("Traced")
+            });
+            if traced {
+                exported.insert(span.name.to_string(), span);
+            }
+        }
+
+        let send = &exported["send org.astarte-platform.test.TracedDevice"];
+        assert_eq!(send.parent_span_id, app.span().span_context().span_id());
+        assert_eq!(
+            send.span_context.trace_id(),
+            app.span().span_context().trace_id()
         );
 
-        let event = event.unwrap();
+        let publish = &exported["publish"];
+        assert_eq!(publish.parent_span_id, send.span_context.span_id());
 
-        assert_eq!("/1/enable", event.path);
+        let receive = &exported["receive org.astarte-platform.test.TracedServer"];
+        assert_eq!(
+            receive.span_context.trace_id(),
+            TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap()
+        );
+        assert_eq!(
+            receive.parent_span_id,
+            SpanId::from_hex("b7ad6b7169203331").unwrap()
+        );
+    }
 
-        match event.data {
-            Aggregation::Individual(AstarteType::Boolean(val)) => {
-                assert!(val);
-            }
-            _ => panic!("Wrong data type {:?}", event.data),
-        }
+    #[derive(AstarteAggregate)]
+    struct Measure {
+        value: f64,
+        unit: String,
     }
 
-    #[tokio::test]
-    async fn test_unset_property() {
-        let mut client = AsyncClient::default();
+    #[test]
+    fn test_aggregation_helpers() {
+        let unset = Aggregation::Unset;
+        assert!(unset.is_unset());
+        assert_eq!(unset.as_individual(), None);
+        assert_eq!(unset.as_object(), None);
+
+        let individual = Aggregation::Individual(AstarteType::Integer(4));
+        assert!(!individual.is_unset());
+        assert_eq!(individual.as_individual(), Some(&AstarteType::Integer(4)));
+        assert_eq!(individual.as_object(), None);
+
+        let fields = HashMap::from([("value".to_string(), AstarteType::Boolean(true))]);
+        let object = Aggregation::Object(fields.clone());
+        assert!(!object.is_unset());
+        assert_eq!(object.as_individual(), None);
+        assert_eq!(object.as_object(), Some(&fields));
+    }
 
-        let value = AstarteType::String(String::from("name number 1"));
-        let buf = payload::serialize_individual(&value, None).unwrap();
+    #[test]
+    fn test_astarte_aggregate_map() {
+        let values = HashMap::from([("a".to_string(), 1.5), ("b".to_string(), 2.0)]);
+        assert_eq!(
+            values.astarte_aggregate().unwrap(),
+            HashMap::from([
+                ("a".to_string(), AstarteType::Double(1.5)),
+                ("b".to_string(), AstarteType::Double(2.0)),
+            ])
+        );
 
-        let unset = payload::serialize_individual(&AstarteType::Unset, None).unwrap();
+        let values = std::collections::BTreeMap::from([("count".to_string(), 3)]);
+        assert_eq!(
+            values.astarte_aggregate().unwrap(),
+            HashMap::from([("count".to_string(), AstarteType::Integer(3))])
+        );
 
-        client
-            .expect_publish::<String, Vec<u8>>()
-            .once()
-            .with(
-                predicate::eq("realm/device_id/org.astarte-platform.rust.examples.individual-properties.DeviceProperties/1/name".to_string()),
-                predicate::always(),
-                predicate::always(),
-                predicate::eq(buf),
-            )
-            .returning(|_, _, _, _| Ok(()));
+        let invalid = HashMap::from([("a".to_string(), f64::NAN)]);
+        assert!(matches!(
+            invalid.astarte_aggregate(),
+            Err(Error::Types(crate::types::TypeError::FloatError))
+        ));
+    }
 
-        client
-            .expect_publish::<String, Vec<u8>>()
-            .once()
-            .with(predicate::eq("realm/device_id/org.astarte-platform.rust.examples.individual-properties.DeviceProperties/1/name".to_string()), predicate::always(), predicate::always(), predicate::eq(unset))
-            .returning(|_, _, _, _| Ok(()));
+    const COLLECTION_OBJECT: &str = r#"{
+        "interface_name": "org.astarte-platform.test.Collection",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "aggregation": "object",
+        "ownership": "device",
+        "mappings": [
+            { "endpoint": "/items/%{idx}/value", "type": "double" },
+            { "endpoint": "/items/%{idx}/unit", "type": "string" }
+        ]
+    }"#;
 
-        let eventloope = EventLoop::default();
+    #[tokio::test]
+    async fn test_send_object_collection() {
+        let interface = "org.astarte-platform.test.Collection";
+
+        let items = vec![
+            Measure {
+                value: 21.5,
+                unit: "C".to_string(),
+            },
+            Measure {
+                value: 40.0,
+                unit: "%".to_string(),
+            },
+        ];
 
-        let astarte = mock_astarte_device(
-            client,
-            eventloope,
-            [Interface::from_str(DEVICE_PROPERTIES).unwrap()],
-        );
+        let mut client = AsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+        for (idx, (value, unit)) in [(21.5, "C"), (40.0, "%")].into_iter().enumerate() {
+            let expected = bson::doc! { "v": { "value": value, "unit": unit } };
+
+            client
+                .expect_publish::<String, Vec<u8>>()
+                .once()
+                .in_sequence(&mut seq)
+                .with(
+                    predicate::eq(format!("realm/device_id/{interface}/items/{idx}")),
+                    predicate::always(),
+                    predicate::always(),
+                    predicate::function(move |buf: &Vec<u8>| {
+                        bson::from_slice::<bson::Document>(buf).ok().as_ref() == Some(&expected)
+                    }),
+                )
+                .returning(|_, _, _, _| Ok(()));
+        }
+
+        let astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(COLLECTION_OBJECT).unwrap()])
+            .build();
 
         astarte
-            .send(
-                "org.astarte-platform.rust.examples.individual-properties.DeviceProperties",
-                "/1/name",
-                "name number 1".to_string(),
-            )
+            .send_object_collection(interface, "/items/%{idx}", items.into_iter().enumerate())
             .await
             .unwrap();
 
-        astarte
-            .unset(
-                "org.astarte-platform.rust.examples.individual-properties.DeviceProperties",
-                "/1/name",
+        let err = astarte
+            .send_object_collection(
+                interface,
+                "/items/%{idx}/value",
+                std::iter::empty::<(usize, Measure)>(),
             )
             .await
-            .unwrap()
+            .unwrap_err();
+        assert!(matches!(err, Error::Collection(_)));
     }
 
-    #[tokio::test]
-    async fn test_receive_object() {
-        let client = AsyncClient::default();
-
-        let mut eventloope = EventLoop::default();
-
-        let data = bson::doc! {
-            "v": {
-                "endpoint1": 4.2,
-                "endpoint2": "obj",
-                "endpoint3": [true],
+    const SETTINGS: &str = r#"
+    {
+        "interface_name": "org.astarte-platform.test.Settings",
+        "version_major": 1,
+        "version_minor": 0,
+        "type": "properties",
+        "ownership": "device",
+        "mappings": [
+            {
+                "endpoint": "/name",
+                "type": "string"
+            },
+            {
+                "endpoint": "/sampleRate",
+                "type": "integer"
+            },
+            {
+                "endpoint": "/gain/value",
+                "type": "double",
+                "allow_unset": true
             }
-        };
+        ]
+    }
+    "#;
+
+    #[derive(Debug, Clone, PartialEq, AstarteProperties)]
+    #[astarte_properties(
+        interface = "org.astarte-platform.test.Settings",
+        rename_all = "camelCase"
+    )]
+    struct Settings {
+        name: String,
+        #[mapping(default = "10")]
+        sample_rate: i32,
+        #[mapping(endpoint = "/gain/value")]
+        gain: Option<f64>,
+    }
 
-        // Send object
-        eventloope.expect_poll().returning(move || {
-            Ok(Event::Incoming(rumqttc::Packet::Publish(
-                rumqttc::Publish::new(
-                    "realm/device_id/org.astarte-platform.rust.examples.object-datastream.DeviceDatastream/1",
-                    rumqttc::QoS::AtLeastOnce,
-                    bson::to_vec(&data).unwrap()
-                ),
-            )))
-        });
+    #[tokio::test]
+    async fn test_astarte_properties() {
+        let interface = "org.astarte-platform.test.Settings";
 
-        let mut astarte = mock_astarte_device(
-            client,
-            eventloope,
-            [Interface::from_str(OBJECT_DEVICE_DATASTREAM).unwrap()],
-        );
+        let mut client = AsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+        for path in ["/name", "/sampleRate", "/gain/value"] {
+            client
+                .expect_publish::<String, Vec<u8>>()
+                .once()
+                .in_sequence(&mut seq)
+                .withf(move |topic, _, _, _| topic == &format!("realm/device_id/{interface}{path}"))
+                .returning(|_, _, _, _| Ok(()));
+        }
 
-        let event = astarte.handle_events().await;
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+        db.store_prop(
+            interface,
+            "/name",
+            &AstarteType::String("dev".to_string()),
+            1,
+        )
+        .await
+        .unwrap();
+        db.store_prop(interface, "/gain/value", &AstarteType::Double(2.0), 1)
+            .await
+            .unwrap();
 
-        assert!(
-            event.is_ok(),
-            "Error handling event {:?}",
-            event.unwrap_err()
+        let mut astarte = MockDevice::new(client, EventLoop::default())
+            .interfaces([Interface::from_str(SETTINGS).unwrap()])
+            .build();
+        astarte.database = Some(Arc::new(db));
+
+        let mut settings = Settings::load(&astarte).await.unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                name: "dev".to_string(),
+                sample_rate: 10,
+                gain: Some(2.0),
+            }
         );
 
-        let event = event.unwrap();
+        // the default is sent, since it's not stored
+        settings.name = "sensor".to_string();
+        settings.gain = None;
+        assert_eq!(settings.store(&astarte).await.unwrap(), 3);
 
-        let mut obj = HashMap::new();
-        obj.insert("endpoint1".to_string(), AstarteType::Double(4.2));
-        obj.insert(
-            "endpoint2".to_string(),
-            AstarteType::String("obj".to_string()),
-        );
-        obj.insert(
-            "endpoint3".to_string(),
-            AstarteType::BooleanArray(vec![true]),
-        );
-        let expected = Aggregation::Object(obj);
+        // nothing changed
+        assert_eq!(settings.store(&astarte).await.unwrap(), 0);
+        assert_eq!(Settings::load(&astarte).await.unwrap(), settings);
 
-        assert_eq!(
-            "org.astarte-platform.rust.examples.object-datastream.DeviceDatastream",
-            event.interface
+        astarte.database.as_ref().unwrap().clear().await.unwrap();
+        let err = Settings::load(&astarte).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::LoadProperties {
+                    source: event::FromEventError::MissingField(ref path),
+                    ..
+                } if path == "/name"
+            ),
+            "unexpected error {err:?}"
         );
-        assert_eq!("/1", event.path);
-        assert_eq!(expected, event.data);
     }
 }
@@ -0,0 +1,416 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! End-to-end liveness check, over the data interfaces.
+//!
+//! The MQTT keep alive only checks the connection with the broker, while the data could still be
+//! lost in the pipeline of the cluster. The device periodically publishes a ping, a counter as a
+//! `longinteger`, on a device-owned mapping and the server must echo the same value on a
+//! server-owned mapping, for example with a trigger. If the echo doesn't arrive within the
+//! timeout the check is broken, and it's alive again on the next echo received.
+//!
+//! The pings are sent while the events are handled with
+//! [`handle_events()`](crate::AstarteDeviceSdk::handle_events), and the echo is delivered as any
+//! other event.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use astarte_device_sdk::{liveness::{Liveness, LivenessStatus}, options::AstarteOptions};
+//!
+//! let liveness = Liveness::new("com.example.Ping", "/ping", "com.example.Pong", "/pong")
+//!     .interval(Duration::from_secs(300))
+//!     .timeout(Duration::from_secs(60))
+//!     .on_change(|status| {
+//!         if status == LivenessStatus::Broken {
+//!             println!("the data doesn't reach Astarte");
+//!         }
+//!     });
+//!
+//! let sdk_options = AstarteOptions::new("_","_","_","_").liveness(liveness);
+//! ```
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::types::AstarteType;
+
+/// Default interval between the pings.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+/// Default time to wait for the echo of a ping.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Status of the end-to-end connectivity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LivenessStatus {
+    /// The last ping was echoed by the server.
+    Alive,
+    /// The echo of the last ping didn't arrive within the timeout.
+    Broken,
+}
+
+/// Hook called when the liveness status changes.
+type LivenessHook = Arc<dyn Fn(LivenessStatus) + Send + Sync>;
+
+/// Configuration of the liveness check.
+#[derive(Clone)]
+pub struct Liveness {
+    pub(crate) ping_interface: String,
+    pub(crate) ping_path: String,
+    pub(crate) echo_interface: String,
+    pub(crate) echo_path: String,
+    interval: Duration,
+    timeout: Duration,
+    hook: Option<LivenessHook>,
+}
+
+impl Liveness {
+    /// Creates a check sending the pings on the path of a device-owned interface, expecting
+    /// the echo on the path of a server-owned interface.
+    pub fn new(
+        ping_interface: &str,
+        ping_path: &str,
+        echo_interface: &str,
+        echo_path: &str,
+    ) -> Self {
+        Self {
+            ping_interface: ping_interface.to_string(),
+            ping_path: ping_path.to_string(),
+            echo_interface: echo_interface.to_string(),
+            echo_path: echo_path.to_string(),
+            interval: DEFAULT_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            hook: None,
+        }
+    }
+
+    /// Interval between an echo, or a timeout, and the next ping, 60 seconds by default.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+
+        self
+    }
+
+    /// Time to wait for the echo of a ping, 30 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+
+        self
+    }
+
+    /// Set a hook called each time the status changes.
+    pub fn on_change<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(LivenessStatus) + Send + Sync + 'static,
+    {
+        self.hook = Some(Arc::new(hook));
+
+        self
+    }
+}
+
+impl Debug for Liveness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Liveness")
+            .field("ping_interface", &self.ping_interface)
+            .field("ping_path", &self.ping_path)
+            .field("echo_interface", &self.echo_interface)
+            .field("echo_path", &self.echo_path)
+            .field("interval", &self.interval)
+            .field("timeout", &self.timeout)
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    status: LivenessStatus,
+    counter: i64,
+    /// Value and deadline of the ping waiting for the echo.
+    pending: Option<(i64, Instant)>,
+    next_ping: Instant,
+}
+
+/// State of the liveness check.
+#[derive(Debug)]
+pub(crate) struct LivenessCheck {
+    pub(crate) config: Liveness,
+    state: Mutex<State>,
+}
+
+impl LivenessCheck {
+    pub(crate) fn new(config: Liveness) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State {
+                status: LivenessStatus::Alive,
+                counter: 0,
+                pending: None,
+                next_ping: Instant::now(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // the state is always valid, since it's only assigned while locked
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn status(&self) -> LivenessStatus {
+        self.lock().status
+    }
+
+    /// Instant at which the check needs to send a ping or expire the pending one.
+    pub(crate) fn deadline(&self) -> Instant {
+        let state = self.lock();
+
+        state
+            .pending
+            .map_or(state.next_ping, |(_, deadline)| deadline)
+    }
+
+    /// Expires the pending ping after the timeout, returning the value of the ping to send if
+    /// it's time for the next one.
+    pub(crate) fn tick(&self) -> Option<i64> {
+        let now = Instant::now();
+
+        let mut state = self.lock();
+
+        if let Some((_, deadline)) = state.pending {
+            if now < deadline {
+                return None;
+            }
+
+            state.pending = None;
+            state.next_ping = now + self.config.interval;
+
+            let changed = state.status != LivenessStatus::Broken;
+            state.status = LivenessStatus::Broken;
+            drop(state);
+
+            if changed {
+                self.changed(LivenessStatus::Broken);
+            }
+
+            return None;
+        }
+
+        if now < state.next_ping {
+            return None;
+        }
+
+        state.counter += 1;
+        state.pending = Some((state.counter, now + self.config.timeout));
+
+        Some(state.counter)
+    }
+
+    /// Checks a value received on the echo mapping against the pending ping.
+    pub(crate) fn echo(&self, value: &AstarteType) {
+        let mut state = self.lock();
+
+        let Some((ping, _)) = state.pending else {
+            return;
+        };
+
+        if *value != AstarteType::LongInteger(ping) {
+            return;
+        }
+
+        state.pending = None;
+        state.next_ping = Instant::now() + self.config.interval;
+
+        let changed = state.status != LivenessStatus::Alive;
+        state.status = LivenessStatus::Alive;
+        drop(state);
+
+        if changed {
+            self.changed(LivenessStatus::Alive);
+        }
+    }
+
+    fn changed(&self, status: LivenessStatus) {
+        if let Some(hook) = &self.config.hook {
+            hook(status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use mockall::predicate;
+    use rumqttc::Event;
+
+    use super::*;
+    use crate::mock::{MockAsyncClient, MockDevice, MockEventLoop};
+    use crate::{payload, Aggregation, Interface};
+
+    #[tokio::test]
+    async fn test_liveness_check() {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+
+        let liveness = Liveness::new("com.test.Ping", "/ping", "com.test.Pong", "/pong")
+            .interval(Duration::from_millis(10))
+            .timeout(Duration::from_millis(10))
+            .on_change({
+                let changes = Arc::clone(&changes);
+                move |status| changes.lock().unwrap().push(status)
+            });
+        let check = LivenessCheck::new(liveness);
+
+        assert_eq!(check.tick(), Some(1));
+        assert_eq!(check.tick(), None);
+
+        // a different value isn't the echo of the ping
+        check.echo(&AstarteType::LongInteger(0));
+        check.echo(&AstarteType::Integer(1));
+        tokio::time::sleep_until(check.deadline()).await;
+        assert_eq!(check.tick(), None);
+        assert_eq!(check.status(), LivenessStatus::Broken);
+
+        tokio::time::sleep_until(check.deadline()).await;
+        assert_eq!(check.tick(), Some(2));
+        check.echo(&AstarteType::LongInteger(2));
+        assert_eq!(check.status(), LivenessStatus::Alive);
+        assert!(check.deadline() > Instant::now());
+        assert_eq!(check.tick(), None);
+
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [LivenessStatus::Broken, LivenessStatus::Alive]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_liveness_hook_on_change() {
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let liveness = Liveness::new("com.test.Ping", "/ping", "com.test.Pong", "/pong")
+            .interval(Duration::ZERO)
+            .timeout(Duration::ZERO)
+            .on_change({
+                let calls = Arc::clone(&calls);
+                move |_| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        let check = LivenessCheck::new(liveness);
+
+        for _ in 0..3 {
+            assert!(check.tick().is_some());
+            assert_eq!(check.tick(), None);
+        }
+
+        // called only once, when it's broken the first time
+        assert_eq!(check.status(), LivenessStatus::Broken);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    const LIVENESS_PING: &str = r#"{
+        "interface_name": "org.astarte-platform.test.Ping",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "ownership": "device",
+        "mappings": [{ "endpoint": "/ping", "type": "longinteger" }]
+    }"#;
+
+    const LIVENESS_ECHO: &str = r#"{
+        "interface_name": "org.astarte-platform.test.Echo",
+        "version_major": 0,
+        "version_minor": 1,
+        "type": "datastream",
+        "ownership": "server",
+        "mappings": [{ "endpoint": "/echo", "type": "longinteger" }]
+    }"#;
+
+    #[tokio::test]
+    async fn test_liveness() {
+        let mut client = MockAsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+        for ping in [1, 2] {
+            let expected =
+                payload::serialize_individual(&AstarteType::LongInteger(ping), None).unwrap();
+
+            client
+                .expect_publish::<String, Vec<u8>>()
+                .once()
+                .in_sequence(&mut seq)
+                .with(
+                    predicate::eq(
+                        "realm/device_id/org.astarte-platform.test.Ping/ping".to_string(),
+                    ),
+                    predicate::always(),
+                    predicate::always(),
+                    predicate::eq(expected),
+                )
+                .returning(|_, _, _, _| Ok(()));
+        }
+
+        let mut astarte = MockDevice::new(client, MockEventLoop::default())
+            .interfaces([
+                Interface::from_str(LIVENESS_PING).unwrap(),
+                Interface::from_str(LIVENESS_ECHO).unwrap(),
+            ])
+            .build();
+        assert_eq!(astarte.liveness(), None);
+
+        let liveness = Liveness::new(
+            "org.astarte-platform.test.Ping",
+            "/ping",
+            "org.astarte-platform.test.Echo",
+            "/echo",
+        )
+        .interval(std::time::Duration::ZERO)
+        .timeout(std::time::Duration::from_millis(10));
+        astarte.liveness = Some(Arc::new(LivenessCheck::new(liveness)));
+
+        astarte.check_liveness().await;
+
+        let echo = payload::serialize_individual(&AstarteType::LongInteger(1), None).unwrap();
+        let event = astarte
+            .handle_event(Event::Incoming(rumqttc::Packet::Publish(
+                rumqttc::Publish::new(
+                    "realm/device_id/org.astarte-platform.test.Echo/echo",
+                    rumqttc::QoS::ExactlyOnce,
+                    echo,
+                ),
+            )))
+            .await
+            .unwrap()
+            .expect("echo not delivered");
+        assert_eq!(
+            event.data,
+            Aggregation::Individual(AstarteType::LongInteger(1))
+        );
+        assert_eq!(astarte.liveness(), Some(LivenessStatus::Alive));
+
+        // the second ping isn't echoed
+        astarte.check_liveness().await;
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        astarte.check_liveness().await;
+        assert_eq!(astarte.liveness(), Some(LivenessStatus::Broken));
+    }
+}
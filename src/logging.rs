@@ -0,0 +1,246 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Structured log entries, for aggregating the logs of a fleet of devices.
+//!
+//! With [`LogFormat::Json`] each step of the messages and each error returned while sending or
+//! handling the events is logged as a JSON object on the [`LOG_TARGET`] target, with the
+//! device id, the interface and path, the [message id](crate::message::MessageId) and the
+//! [category](crate::history::ErrorCategory) of the error. The other log messages of the SDK are
+//! unchanged.
+//!
+//! ```json
+//! {"device_id":"2TBn-jNESuuHamE2Zo1anA","event":"error","interface":"com.example.Sensor","path":"/value","error_kind":"receive","message":"couldn't deserialize the payload on com.example.Sensor/value: ..."}
+//! ```
+//!
+//! ```no_run
+//! use astarte_device_sdk::{logging::LogFormat, options::AstarteOptions};
+//!
+//! let sdk_options = AstarteOptions::new("_","_","_","_").log_format(LogFormat::Json);
+//! ```
+
+use log::{log, Level};
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::history::ErrorRecord;
+use crate::message::{MessageId, MessageStage};
+
+/// Target of the structured log entries.
+pub const LOG_TARGET: &str = "astarte_device_sdk::structured";
+
+/// Format of the log entries of the messages and of the errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Only the free-form messages.
+    #[default]
+    Text,
+    /// A JSON object for each entry, on the [`LOG_TARGET`] target.
+    Json,
+}
+
+/// Fields of a structured log entry.
+#[derive(Debug, Serialize)]
+struct LogEntry<'a> {
+    device_id: &'a str,
+    event: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interface: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stage: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+}
+
+impl<'a> LogEntry<'a> {
+    fn new(device_id: &'a str, event: &'static str) -> Self {
+        Self {
+            device_id,
+            event,
+            interface: None,
+            path: None,
+            msg_id: None,
+            stage: None,
+            error_kind: None,
+            message: None,
+        }
+    }
+
+    fn log(&self, level: Level) {
+        match serde_json::to_string(self) {
+            Ok(entry) => log!(target: LOG_TARGET, level, "{entry}"),
+            Err(err) => {
+                log!(target: LOG_TARGET, Level::Warn, "couldn't serialize the log entry: {err}")
+            }
+        }
+    }
+}
+
+/// Returns the interface and the path an error refers to, if any.
+fn error_location(error: &Error) -> (Option<&str>, Option<&str>) {
+    match error {
+        Error::InterfacePayload {
+            interface, path, ..
+        }
+        | Error::Publish {
+            interface, path, ..
+        }
+        | Error::StoreFull { interface, path }
+        | Error::QuotaExceeded { interface, path }
+        | Error::ConstraintViolation {
+            interface, path, ..
+        }
+        | Error::Transform {
+            interface, path, ..
+        }
+        | Error::Encryption {
+            interface, path, ..
+        }
+        | Error::EventTooLarge {
+            interface, path, ..
+        }
+        | Error::PayloadTooLarge {
+            interface, path, ..
+        } => (Some(interface), Some(path)),
+        Error::NotServerProperty(interface)
+        | Error::RateLimited(interface)
+        | Error::LoadProperties { interface, .. } => (Some(interface), None),
+        _ => (None, None),
+    }
+}
+
+fn stage_name(stage: MessageStage) -> &'static str {
+    match stage {
+        MessageStage::Published => "published",
+        MessageStage::Retained => "retained",
+        MessageStage::Republished => "republished",
+        MessageStage::Dropped => "dropped",
+    }
+}
+
+fn message_entry<'a>(
+    device_id: &'a str,
+    id: MessageId,
+    interface: &'a str,
+    path: &'a str,
+    stage: MessageStage,
+) -> LogEntry<'a> {
+    LogEntry {
+        interface: Some(interface),
+        path: Some(path),
+        msg_id: Some(id.to_string()),
+        stage: Some(stage_name(stage)),
+        ..LogEntry::new(device_id, "message")
+    }
+}
+
+fn error_entry<'a>(device_id: &'a str, error: &'a Error, record: &'a ErrorRecord) -> LogEntry<'a> {
+    let (interface, path) = error_location(error);
+
+    LogEntry {
+        interface,
+        path,
+        error_kind: Some(record.category.to_string()),
+        message: Some(&record.message),
+        ..LogEntry::new(device_id, "error")
+    }
+}
+
+/// Logs a step of a message.
+pub(crate) fn log_message(
+    device_id: &str,
+    id: MessageId,
+    interface: &str,
+    path: &str,
+    stage: MessageStage,
+) {
+    let level = match stage {
+        MessageStage::Published | MessageStage::Republished => Level::Debug,
+        MessageStage::Retained | MessageStage::Dropped => Level::Warn,
+    };
+
+    message_entry(device_id, id, interface, path, stage).log(level);
+}
+
+/// Logs an error returned while sending or handling the events.
+pub(crate) fn log_error(device_id: &str, error: &Error, sending: bool) {
+    let record = ErrorRecord::new(error, sending);
+
+    error_entry(device_id, error, &record).log(Level::Error);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_entries() {
+        let id = MessageId::new();
+        let entry = message_entry(
+            "device_id",
+            id,
+            "com.example.Sensor",
+            "/value",
+            MessageStage::Retained,
+        );
+        let value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "device_id": "device_id",
+                "event": "message",
+                "interface": "com.example.Sensor",
+                "path": "/value",
+                "msg_id": id.to_string(),
+                "stage": "retained",
+            })
+        );
+
+        let error = Error::EventTooLarge {
+            interface: "com.example.Sensor".to_string(),
+            path: "/value".to_string(),
+            size: 10,
+            max: 5,
+        };
+        let record = ErrorRecord::new(&error, false);
+        let value = serde_json::to_value(error_entry("device_id", &error, &record)).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "device_id": "device_id",
+                "event": "error",
+                "interface": "com.example.Sensor",
+                "path": "/value",
+                "error_kind": "receive",
+                "message": record.message,
+            })
+        );
+
+        let error = Error::Unreported;
+        let record = ErrorRecord::new(&error, true);
+        let value = serde_json::to_value(error_entry("device_id", &error, &record)).unwrap();
+        assert_eq!(value["error_kind"], "other");
+        assert!(value.get("interface").is_none());
+    }
+}
@@ -0,0 +1,100 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Identifiers of the messages sent to Astarte, to trace a single message from the application
+//! call through the retention to the publish.
+//!
+//! Each message gets a [`MessageId`] when it's sent, the same id is kept while the message is
+//! in the volatile retention and is included in the logs of the message. The hook configured
+//! with [`AstarteOptions::on_message`](crate::options::AstarteOptions::on_message) is called on
+//! each step of the message.
+//!
+//! ```no_run
+//! use astarte_device_sdk::options::AstarteOptions;
+//!
+//! let sdk_options = AstarteOptions::new("_","_","_","_").on_message(|event| {
+//!     println!("{} {}{} {:?}", event.id, event.interface, event.path, event.stage);
+//! });
+//! ```
+
+use std::fmt::Display;
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+/// Unique identifier of a message sent to Astarte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MessageId(Uuid);
+
+impl MessageId {
+    /// Generate a new random id.
+    pub(crate) fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    /// Returns the id as an [`Uuid`].
+    pub fn as_uuid(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl Display for MessageId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Step reached by a message.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageStage {
+    /// The message was handed to the MQTT client to be published.
+    Published,
+    /// The message couldn't be published and was kept in the volatile retention.
+    Retained,
+    /// The message was published again from the volatile retention.
+    Republished,
+    /// The message was dropped without being published.
+    Dropped,
+}
+
+/// Step of a message, passed to the hook configured with
+/// [`AstarteOptions::on_message`](crate::options::AstarteOptions::on_message).
+#[derive(Debug, Clone, Copy)]
+pub struct MessageEvent<'a> {
+    pub id: MessageId,
+    pub interface: &'a str,
+    pub path: &'a str,
+    pub stage: MessageStage,
+}
+
+/// Hook called on each step of the messages.
+pub(crate) type MessageHook = Arc<dyn Fn(&MessageEvent) + Send + Sync>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_message_id() {
+        let id = MessageId::new();
+
+        assert_ne!(id, MessageId::new());
+        assert_eq!(id.to_string(), id.as_uuid().to_string());
+    }
+}
@@ -18,14 +18,26 @@
 
 //! Mocks for the Astarte Device SDK.
 
+use std::sync::Arc;
+
 use mockall::mock;
 use rumqttc::{ClientError, ConnectionError, Event, MqttOptions, QoS};
 
+use crate::capabilities::Capabilities;
+use crate::database::AstarteDatabase;
+use crate::delivery::Deliveries;
+use crate::interfaces::Interfaces;
+use crate::options::AstarteOptions;
+use crate::transport::{Reconfigure, TransportOptions};
+use crate::{AstarteDeviceSdk, Interface};
+
 mock!(
     pub AsyncClient {
         pub fn new(options: MqttOptions, cap: usize) -> (MockAsyncClient, MockEventLoop);
         pub async fn subscribe<S: Into<String> + 'static>(&self, topic: S, qos: QoS) -> Result<(), ClientError>;
         pub async fn publish<S, V>(&self, topic: S, qos: QoS, retain: bool, payload: V,) -> Result<(), ClientError> where S: Into<String> + 'static, V: Into<Vec<u8>> + 'static;
+        pub fn try_publish<S, V>(&self, topic: S, qos: QoS, retain: bool, payload: V,) -> Result<(), ClientError> where S: Into<String> + 'static, V: Into<Vec<u8>> + 'static;
+        pub async fn disconnect(&self) -> Result<(), ClientError>;
         pub async fn unsubscribe<S: Into<String> + 'static>(&self, topic: S) -> Result<(), ClientError>;
     }
     impl Clone for AsyncClient {
@@ -37,4 +49,110 @@ mock! {
     pub EventLoop{
         pub async fn poll(&mut self) -> Result<Event, ConnectionError>;
     }
+    impl Reconfigure for EventLoop {
+        fn reconfigure(&mut self, options: &TransportOptions);
+    }
+}
+
+/// Builder of the devices connected to the mocked client and event loop.
+///
+/// The device is created from the [`AstarteOptions`], like a connected one.
+pub(crate) struct MockDevice {
+    client: MockAsyncClient,
+    eventloop: MockEventLoop,
+    options: AstarteOptions,
+    deliveries: Option<Arc<Deliveries>>,
+}
+
+impl MockDevice {
+    pub(crate) fn new(client: MockAsyncClient, eventloop: MockEventLoop) -> Self {
+        Self {
+            client,
+            eventloop,
+            options: AstarteOptions::new("realm", "device_id", "secret", "http://localhost"),
+            deliveries: None,
+        }
+    }
+
+    /// Sets the interfaces of the device.
+    pub(crate) fn interfaces<I>(mut self, interfaces: I) -> Self
+    where
+        I: IntoIterator<Item = Interface>,
+    {
+        self.options.interfaces = Interfaces::from(interfaces).unwrap();
+
+        self
+    }
+
+    /// Changes the options of the device.
+    pub(crate) fn options<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(AstarteOptions) -> AstarteOptions,
+    {
+        self.options = f(self.options);
+
+        self
+    }
+
+    /// Tracks the publishes with the deliveries, to acknowledge them from the mocked client.
+    pub(crate) fn deliveries(mut self, deliveries: &Arc<Deliveries>) -> Self {
+        self.deliveries = Some(Arc::clone(deliveries));
+
+        self
+    }
+
+    /// Creates the device with the database of the options.
+    pub(crate) fn build(self) -> AstarteDeviceSdk {
+        let database = self.options.database.clone();
+
+        self.build_with(database)
+    }
+
+    /// Creates the device with the concrete type of the database.
+    pub(crate) fn build_with<S>(self, database: Option<Arc<S>>) -> AstarteDeviceSdk<S>
+    where
+        S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+    {
+        let mut device = AstarteDeviceSdk::from_options(
+            self.options,
+            database,
+            self.client,
+            self.eventloop,
+            Capabilities::default(),
+            None,
+        );
+
+        if let Some(deliveries) = self.deliveries {
+            device.deliveries = deliveries;
+        }
+
+        device
+    }
+}
+
+/// Returns the mock of publish acknowledged right away by the broker, sending the events of
+/// the acknowledgment to the deliveries.
+pub(crate) fn acknowledged(
+    deliveries: &Arc<Deliveries>,
+) -> impl FnMut(String, QoS, bool, Vec<u8>) -> Result<(), ClientError> {
+    let deliveries = Arc::clone(deliveries);
+
+    move |_, qos, _, _| {
+        let pkid = u16::from(qos != QoS::AtMostOnce);
+        let events = match qos {
+            QoS::AtMostOnce => vec![],
+            QoS::AtLeastOnce => vec![rumqttc::Packet::PubAck(rumqttc::PubAck::new(1))],
+            QoS::ExactlyOnce => vec![
+                rumqttc::Packet::PubRec(rumqttc::PubRec::new(1)),
+                rumqttc::Packet::PubComp(rumqttc::PubComp::new(1)),
+            ],
+        };
+
+        deliveries.handle(&Event::Outgoing(rumqttc::Outgoing::Publish(pkid)));
+        for event in events {
+            deliveries.handle(&Event::Incoming(event));
+        }
+
+        Ok(())
+    }
 }
@@ -19,6 +19,7 @@
  */
 //! Provides functionality to configure an instance of the
 //! [AstarteDeviceSdk][crate::AstarteDeviceSdk].
+use std::collections::{hash_map::Entry, HashMap};
 use std::ffi::OsStr;
 use std::fmt::Debug;
 use std::io;
@@ -29,11 +30,31 @@ use std::sync::Arc;
 use log::debug;
 use pairing::PairingError;
 
+use crate::capabilities::Capabilities;
+use crate::constraint::{ValueConstraint, ValueConstraints};
 use crate::crypto::CryptoError;
 use crate::database::AstarteDatabase;
+use crate::dedup::PublishedLog;
+use crate::discovery::RealmManagement;
+use crate::encryption::{PayloadCipher, PayloadEncryption};
+use crate::error::Error;
+use crate::filter::{EventFilter, EventFilters};
+use crate::history::DEFAULT_ERROR_HISTORY;
+use crate::idle::IdleConfig;
 use crate::interface::{Interface, InterfaceError};
 use crate::interfaces::Interfaces;
+use crate::liveness::Liveness;
+use crate::logging::LogFormat;
+use crate::message::{MessageEvent, MessageHook};
 use crate::pairing;
+use crate::pool::DEFAULT_BUFFER_POOL;
+use crate::quota::StoreQuota;
+use crate::registry::SchemaRegistry;
+use crate::retention::DEFAULT_VOLATILE_CAPACITY;
+use crate::shutdown::ShutdownSignal;
+use crate::throttle::ReceiveLimit;
+use crate::transform::{ValueTransform, ValueTransforms};
+use crate::transport::TransportOptions;
 
 /// Astarte options error.
 ///
@@ -69,6 +90,170 @@ pub enum OptionsError {
     PkiError(#[from] webpki::Error),
 }
 
+/// Handling of the messages received with the MQTT retained flag.
+///
+/// Astarte doesn't retain the messages, but a broker bridged to it could.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RetainedPolicy {
+    /// The message is handled and the flag is reported in the
+    /// [`EventMetadata`](crate::EventMetadata) of the event.
+    #[default]
+    Mark,
+    /// The message is handled like any other, without reporting the flag.
+    Process,
+    /// The message is discarded, without storing the properties or returning an event.
+    Ignore,
+}
+
+/// Whether a device-owned property is published when it's set to the value already stored.
+///
+/// The value can only be compared when a database is configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PropertyPublishPolicy {
+    /// The property is published only if the value changed.
+    #[default]
+    OnChange,
+    /// The property is always published.
+    Always,
+}
+
+/// Publish policies configured globally and for each interface.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PropertyPublishPolicies {
+    default: PropertyPublishPolicy,
+    interfaces: HashMap<String, PropertyPublishPolicy>,
+}
+
+impl PropertyPublishPolicies {
+    pub(crate) fn get(&self, interface: &str) -> PropertyPublishPolicy {
+        self.interfaces
+            .get(interface)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Whether the messages of an interface are published in the order they are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PublishOrdering {
+    /// The messages are published one at a time, in the order they are sent.
+    ///
+    /// A message waits for the previous ones of the interface to be handed to the client,
+    /// including their [retries](AstarteOptions::send_retry).
+    #[default]
+    Ordered,
+    /// The messages are published concurrently, a message can overtake the previous ones being
+    /// retried.
+    Parallel,
+}
+
+/// Publish orderings configured globally and for each interface.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PublishOrderings {
+    default: PublishOrdering,
+    interfaces: HashMap<String, PublishOrdering>,
+}
+
+impl PublishOrderings {
+    pub(crate) fn get(&self, interface: &str) -> PublishOrdering {
+        self.interfaces
+            .get(interface)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Policy applied when a property received from the server can't be written to the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StoreFailurePolicy {
+    /// The error is returned and the event is not delivered.
+    #[default]
+    Fail,
+    /// The event is delivered anyway, logging a warning.
+    DeliverWithWarning,
+    /// Retry to write the property, doubling the backoff after each attempt. The error is
+    /// returned if all the attempts fail.
+    Retry {
+        attempts: u32,
+        backoff: std::time::Duration,
+    },
+}
+
+/// Failed write of a property received from the server, passed to the hook configured with
+/// [`AstarteOptions::on_store_failure`].
+#[derive(Debug)]
+pub struct StoreFailure<'a> {
+    pub interface: &'a str,
+    pub path: &'a str,
+    /// Error returned by the database, is [`Error::StoreFull`] if the store is full.
+    pub error: &'a Error,
+}
+
+/// Hook called on each failed write of a property.
+pub(crate) type StoreFailureHook = Arc<dyn Fn(&StoreFailure) + Send + Sync>;
+
+/// Retries of the failed publishes, configured with [`AstarteOptions::send_retry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SendRetry {
+    pub(crate) attempts: u32,
+    pub(crate) backoff: std::time::Duration,
+}
+
+/// What to do with the stale events received on the server-owned datastreams, see
+/// [`AstarteOptions::stale_event_window`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StalePolicy {
+    /// The event is delivered with the [`stale`](crate::AstarteDeviceDataEvent::stale) flag set.
+    #[default]
+    Flag,
+    /// The event is discarded.
+    Drop,
+}
+
+/// Window after which an event with an explicit timestamp is stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StaleWindow {
+    pub(crate) window: std::time::Duration,
+    pub(crate) policy: StalePolicy,
+}
+
+impl StaleWindow {
+    /// Returns true if the timestamp is older than the window at the given time.
+    pub(crate) fn is_stale(
+        &self,
+        timestamp: chrono::DateTime<chrono::Utc>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> bool {
+        // a window too big to be represented is never exceeded
+        chrono::Duration::from_std(self.window).map_or(false, |window| now - timestamp > window)
+    }
+}
+
+/// How to resolve an interface present in more than one of the directories passed to
+/// [`AstarteOptions::interface_directories`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InterfaceConflictPolicy {
+    /// The interface with the highest version is used, if the versions are equal the one in the
+    /// directory with higher precedence is used.
+    #[default]
+    HighestVersion,
+    /// The interface in the directory with higher precedence is used, even if it has a lower
+    /// version.
+    Precedence,
+}
+
+/// What to do when the introspection doesn't match the interfaces installed in the realm, see
+/// [`AstarteOptions::realm_discovery`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MismatchPolicy {
+    /// A warning is logged for each mismatching interface.
+    #[default]
+    Warn,
+    /// The device doesn't connect, returning an
+    /// [`Error::IntrospectionMismatch`](crate::error::Error::IntrospectionMismatch).
+    Error,
+}
+
 /// Structure used to store the configuration options for an instance of
 /// [AstarteDeviceSdk][crate::AstarteDeviceSdk].
 #[derive(Clone)]
@@ -81,6 +266,38 @@ pub struct AstarteOptions {
     pub(crate) database: Option<Arc<dyn AstarteDatabase + Sync + Send>>,
     pub(crate) ignore_ssl_errors: bool,
     pub(crate) keepalive: std::time::Duration,
+    pub(crate) transport: TransportOptions,
+    pub(crate) property_publish_policies: PropertyPublishPolicies,
+    pub(crate) publish_orderings: PublishOrderings,
+    pub(crate) volatile_retention_capacity: usize,
+    pub(crate) retention_quotas: HashMap<String, StoreQuota>,
+    pub(crate) store_failure_policy: StoreFailurePolicy,
+    pub(crate) store_failure_hook: Option<StoreFailureHook>,
+    pub(crate) event_filters: EventFilters,
+    pub(crate) value_constraints: ValueConstraints,
+    pub(crate) value_transforms: ValueTransforms,
+    pub(crate) payload_encryption: PayloadEncryption,
+    #[cfg(feature = "otel")]
+    pub(crate) trace_fields: HashMap<String, String>,
+    pub(crate) message_hook: Option<MessageHook>,
+    pub(crate) idle: Option<IdleConfig>,
+    pub(crate) send_retry: Option<SendRetry>,
+    pub(crate) prune_store: bool,
+    pub(crate) purge_compression: flate2::Compression,
+    pub(crate) stale_window: Option<StaleWindow>,
+    pub(crate) max_event_size: Option<usize>,
+    pub(crate) receive_limits: HashMap<String, ReceiveLimit>,
+    pub(crate) published_log: Option<Arc<PublishedLog>>,
+    pub(crate) capabilities: Option<Capabilities>,
+    pub(crate) retained_policy: RetainedPolicy,
+    pub(crate) liveness: Option<Liveness>,
+    pub(crate) shutdown_signal: Option<ShutdownSignal>,
+    pub(crate) schema_registry: Option<SchemaRegistry>,
+    pub(crate) realm_discovery: Option<(RealmManagement, MismatchPolicy)>,
+    pub(crate) sequence_fields: HashMap<String, String>,
+    pub(crate) error_history: usize,
+    pub(crate) log_format: LogFormat,
+    pub(crate) buffer_pool: usize,
 }
 
 impl Debug for AstarteOptions {
@@ -93,6 +310,38 @@ impl Debug for AstarteOptions {
             .field("interfaces", &self.interfaces)
             .field("ignore_ssl_errors", &self.ignore_ssl_errors)
             .field("keepalive", &self.keepalive)
+            .field("transport", &self.transport)
+            .field("property_publish_policies", &self.property_publish_policies)
+            .field("publish_orderings", &self.publish_orderings)
+            .field(
+                "volatile_retention_capacity",
+                &self.volatile_retention_capacity,
+            )
+            .field("retention_quotas", &self.retention_quotas)
+            .field("receive_limits", &self.receive_limits)
+            .field("published_log", &self.published_log)
+            .field("store_failure_policy", &self.store_failure_policy)
+            .field("event_filters", &self.event_filters)
+            .field("value_constraints", &self.value_constraints)
+            .field("value_transforms", &self.value_transforms)
+            .field("payload_encryption", &self.payload_encryption)
+            .field("message_hook", &self.message_hook.is_some())
+            .field("idle", &self.idle)
+            .field("send_retry", &self.send_retry)
+            .field("prune_store", &self.prune_store)
+            .field("purge_compression", &self.purge_compression)
+            .field("stale_window", &self.stale_window)
+            .field("max_event_size", &self.max_event_size)
+            .field("capabilities", &self.capabilities)
+            .field("retained_policy", &self.retained_policy)
+            .field("liveness", &self.liveness)
+            .field("shutdown_signal", &self.shutdown_signal)
+            .field("schema_registry", &self.schema_registry)
+            .field("realm_discovery", &self.realm_discovery)
+            .field("sequence_fields", &self.sequence_fields)
+            .field("error_history", &self.error_history)
+            .field("log_format", &self.log_format)
+            .field("buffer_pool", &self.buffer_pool)
             // We manually implement Debug for the database, so we can avoid have a trait bound on
             // [AstarteDatabase] to implement [Display].
             .finish_non_exhaustive()
@@ -129,6 +378,38 @@ impl AstarteOptions {
             database: None,
             ignore_ssl_errors: false,
             keepalive: std::time::Duration::from_secs(30),
+            transport: TransportOptions::default(),
+            property_publish_policies: PropertyPublishPolicies::default(),
+            publish_orderings: PublishOrderings::default(),
+            volatile_retention_capacity: DEFAULT_VOLATILE_CAPACITY,
+            retention_quotas: HashMap::new(),
+            receive_limits: HashMap::new(),
+            published_log: None,
+            store_failure_policy: StoreFailurePolicy::default(),
+            store_failure_hook: None,
+            event_filters: EventFilters::default(),
+            value_constraints: ValueConstraints::default(),
+            value_transforms: ValueTransforms::default(),
+            payload_encryption: PayloadEncryption::default(),
+            #[cfg(feature = "otel")]
+            trace_fields: HashMap::new(),
+            message_hook: None,
+            idle: None,
+            send_retry: None,
+            prune_store: false,
+            purge_compression: flate2::Compression::default(),
+            stale_window: None,
+            max_event_size: None,
+            capabilities: None,
+            retained_policy: RetainedPolicy::default(),
+            liveness: None,
+            shutdown_signal: None,
+            schema_registry: None,
+            realm_discovery: None,
+            sequence_fields: HashMap::new(),
+            error_history: DEFAULT_ERROR_HISTORY,
+            log_format: LogFormat::default(),
+            buffer_pool: DEFAULT_BUFFER_POOL,
         }
     }
 
@@ -148,6 +429,442 @@ impl AstarteOptions {
         self
     }
 
+    /// Configure the options of the MQTT transport, they can be changed while the device is
+    /// running with [`AstarteDeviceSdk::reconfigure()`](crate::AstarteDeviceSdk::reconfigure).
+    ///
+    /// The keep alive set in the options overrides the one of [`AstarteOptions::keepalive()`].
+    pub fn transport(mut self, options: TransportOptions) -> Self {
+        self.transport = options;
+
+        self
+    }
+
+    /// Configure whether the device-owned properties set to the value already stored are
+    /// published, for all the interfaces without a specific policy.
+    ///
+    /// The value can always be published with
+    /// [`send_forced()`](crate::AstarteDeviceSdk::send_forced).
+    pub fn property_publish_policy(mut self, policy: PropertyPublishPolicy) -> Self {
+        self.property_publish_policies.default = policy;
+
+        self
+    }
+
+    /// Configure whether the properties of an interface set to the value already stored are
+    /// published, overriding [`property_publish_policy()`](AstarteOptions::property_publish_policy).
+    pub fn interface_property_publish_policy(
+        mut self,
+        interface: &str,
+        policy: PropertyPublishPolicy,
+    ) -> Self {
+        self.property_publish_policies
+            .interfaces
+            .insert(interface.to_string(), policy);
+
+        self
+    }
+
+    /// Configure whether the messages are published in the order they are sent, for all the
+    /// interfaces without a specific ordering.
+    ///
+    /// The interfaces are [ordered](PublishOrdering::Ordered) by default.
+    pub fn publish_ordering(mut self, ordering: PublishOrdering) -> Self {
+        self.publish_orderings.default = ordering;
+
+        self
+    }
+
+    /// Configure whether the messages of an interface are published in the order they are sent,
+    /// overriding [`publish_ordering()`](AstarteOptions::publish_ordering).
+    ///
+    /// The interfaces without ordering requirements can be published in
+    /// [parallel](PublishOrdering::Parallel), so a message being retried doesn't delay the
+    /// others.
+    pub fn interface_publish_ordering(
+        mut self,
+        interface: &str,
+        ordering: PublishOrdering,
+    ) -> Self {
+        self.publish_orderings
+            .interfaces
+            .insert(interface.to_string(), ordering);
+
+        self
+    }
+
+    /// Attach a [sequence number](crate::sequence) to the objects sent on an interface, in the
+    /// given field.
+    ///
+    /// The field must be a `longinteger` mapping of the object. The sequence starts from one and
+    /// the last number sent is persisted in the database, if configured. The objects of the
+    /// interface are sent one at a time, in the order of their sequence numbers.
+    pub fn sequence_field(mut self, interface: &str, field: &str) -> Self {
+        self.sequence_fields
+            .insert(interface.to_string(), field.to_string());
+
+        self
+    }
+
+    /// Configure the maximum number of messages kept in memory for the mappings with volatile
+    /// retention.
+    ///
+    /// The messages that couldn't be published are sent again when the device reconnects, when
+    /// the limit is reached the oldest message is discarded. A capacity of zero disables the
+    /// volatile retention.
+    pub fn volatile_retention_capacity(mut self, capacity: usize) -> Self {
+        self.volatile_retention_capacity = capacity;
+
+        self
+    }
+
+    /// Limit the messages of an interface kept in the volatile retention, see the
+    /// [`quota`](crate::quota) module.
+    ///
+    /// The size of a message is the one of its payload. The usage of the interface is returned
+    /// by [`AstarteDeviceSdk::retention_usage`](crate::AstarteDeviceSdk::retention_usage).
+    pub fn retention_quota(mut self, interface: &str, quota: StoreQuota) -> Self {
+        self.retention_quotas.insert(interface.to_string(), quota);
+
+        self
+    }
+
+    /// Configure what happens when a property received from the server can't be stored.
+    ///
+    /// See [`StoreFailurePolicy`] for the available policies.
+    pub fn store_failure_policy(mut self, policy: StoreFailurePolicy) -> Self {
+        self.store_failure_policy = policy;
+
+        self
+    }
+
+    /// Set a hook called each time a property received from the server can't be stored.
+    ///
+    /// It can be used to free space when the store is full, the hook is called before applying
+    /// the [`StoreFailurePolicy`] so the space is available for a retry.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{error::Error, options::AstarteOptions};
+    ///
+    /// let sdk_options = AstarteOptions::new("_","_","_","_")
+    ///     .on_store_failure(|failure| {
+    ///         if let Error::StoreFull { .. } = failure.error {
+    ///             // cleanup the disk
+    ///         }
+    ///     });
+    /// ```
+    pub fn on_store_failure<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&StoreFailure) + Send + Sync + 'static,
+    {
+        self.store_failure_hook = Some(Arc::new(hook));
+
+        self
+    }
+
+    /// Add a filter on the data received from Astarte.
+    ///
+    /// See the [`filter`](crate::filter) module for more information.
+    pub fn event_filter(mut self, filter: EventFilter) -> Self {
+        self.event_filters.push(filter);
+
+        self
+    }
+
+    /// Add a constraint on the values received from Astarte.
+    ///
+    /// See the [`constraint`](crate::constraint) module for more information.
+    pub fn value_constraint(mut self, constraint: ValueConstraint) -> Self {
+        self.value_constraints.push(constraint);
+
+        self
+    }
+
+    /// Add a transform of the values received from Astarte.
+    ///
+    /// See the [`transform`](crate::transform) module for more information.
+    pub fn value_transform(mut self, transform: ValueTransform) -> Self {
+        self.value_transforms.push(transform);
+
+        self
+    }
+
+    /// Encrypt the values sent and received on an interface with the cipher.
+    ///
+    /// See the [`encryption`](crate::encryption) module for more information.
+    pub fn payload_encryption<C>(mut self, interface: &str, cipher: C) -> Self
+    where
+        C: PayloadCipher + 'static,
+    {
+        self.payload_encryption.insert(interface, Arc::new(cipher));
+
+        self
+    }
+
+    /// Send the W3C trace context of the messages in the given field of the objects of an
+    /// interface, and use it as the parent of the objects received.
+    ///
+    /// The field must be a `string` mapping of the object. See the [`otel`](crate::otel) module
+    /// for more information.
+    #[cfg(feature = "otel")]
+    pub fn trace_context_field(mut self, interface: &str, field: &str) -> Self {
+        self.trace_fields
+            .insert(interface.to_string(), field.to_string());
+
+        self
+    }
+
+    /// Set a hook called on each step of the messages sent to Astarte, identified by their
+    /// [`MessageId`](crate::message::MessageId).
+    ///
+    /// See the [`message`](crate::message) module for more information.
+    pub fn on_message<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&MessageEvent) + Send + Sync + 'static,
+    {
+        self.message_hook = Some(Arc::new(hook));
+
+        self
+    }
+
+    /// Close the MQTT connection after a period without messages sent or received, to save the
+    /// keep alive wakeups of battery powered devices.
+    ///
+    /// The connection is opened again when a message is sent, or after the `wakeup` interval to
+    /// receive the server data. Without an interval the server data is received only after a
+    /// message is sent.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use astarte_device_sdk::options::AstarteOptions;
+    ///
+    /// let sdk_options = AstarteOptions::new("_","_","_","_")
+    ///     .idle_disconnect(Duration::from_secs(60), Some(Duration::from_secs(3600)));
+    /// ```
+    pub fn idle_disconnect(
+        mut self,
+        timeout: std::time::Duration,
+        wakeup: Option<std::time::Duration>,
+    ) -> Self {
+        self.idle = Some(IdleConfig { timeout, wakeup });
+
+        self
+    }
+
+    /// Retry the publishes failed for a transient client error, doubling the backoff after each
+    /// attempt.
+    ///
+    /// The publishes failed because the event loop was dropped are not retried. The retries of an
+    /// [ordered](PublishOrdering::Ordered) interface delay its next publishes.
+    ///
+    /// The error is returned only if all the attempts fail, for the mappings with volatile
+    /// retention the message is kept in the retention instead.
+    pub fn send_retry(mut self, attempts: u32, backoff: std::time::Duration) -> Self {
+        self.send_retry = Some(SendRetry { attempts, backoff });
+
+        self
+    }
+
+    /// Remove at startup the stored properties and the retained messages that don't match the
+    /// configured interfaces.
+    ///
+    /// See [`AstarteDeviceSdk::prune_store`](crate::AstarteDeviceSdk::prune_store) for more
+    /// information.
+    pub fn prune_store(mut self) -> Self {
+        self.prune_store = true;
+
+        self
+    }
+
+    /// Configure the zlib compression level, from 0 to 9, of the purge properties message sent
+    /// with the device-owned properties set on the device.
+    ///
+    /// Higher levels reduce the size of the message for devices with many properties, at the
+    /// cost of more CPU time. Levels higher than 9 are treated as 9.
+    pub fn purge_properties_compression(mut self, level: u32) -> Self {
+        self.purge_compression = flate2::Compression::new(level.min(9));
+
+        self
+    }
+
+    /// Mark as stale the events received on the server-owned datastreams with an explicit
+    /// timestamp older than the window, for example commands delivered after a long offline
+    /// period.
+    ///
+    /// Depending on the policy the stale events are delivered flagged or discarded, so old
+    /// commands don't get executed on reconnection. Events without a timestamp are never stale.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use astarte_device_sdk::options::{AstarteOptions, StalePolicy};
+    ///
+    /// let sdk_options = AstarteOptions::new("_","_","_","_")
+    ///     .stale_event_window(Duration::from_secs(600), StalePolicy::Drop);
+    /// ```
+    pub fn stale_event_window(mut self, window: std::time::Duration, policy: StalePolicy) -> Self {
+        self.stale_window = Some(StaleWindow { window, policy });
+
+        self
+    }
+
+    /// Reject the received messages bigger than the maximum size in bytes, before they are
+    /// decoded.
+    ///
+    /// The size of the compressed purge properties message is checked once decoded, and the
+    /// decoding stops at the maximum. A rejected message makes
+    /// [`handle_events()`](crate::AstarteDeviceSdk::handle_events) return an
+    /// [`Error::EventTooLarge`](crate::Error::EventTooLarge), without closing the connection.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::options::AstarteOptions;
+    ///
+    /// let sdk_options = AstarteOptions::new("_","_","_","_").max_event_size(64 * 1024);
+    /// ```
+    pub fn max_event_size(mut self, bytes: usize) -> Self {
+        self.max_event_size = Some(bytes);
+
+        self
+    }
+
+    /// Limit the rate of the messages received on an interface, see the
+    /// [`throttle`](crate::throttle) module.
+    ///
+    /// The messages over the limit are coalesced or dropped before being decoded and stored, the
+    /// counters are returned by
+    /// [`AstarteDeviceSdk::receive_limit_stats`](crate::AstarteDeviceSdk::receive_limit_stats).
+    pub fn receive_rate_limit(mut self, interface: &str, limit: ReceiveLimit) -> Self {
+        self.receive_limits.insert(interface.to_string(), limit);
+
+        self
+    }
+
+    /// Log the outbox entries published on the mappings with `unique` reliability, so they are
+    /// not published again after a restart, see the [`dedup`](crate::dedup) module.
+    pub fn published_log(mut self, log: PublishedLog) -> Self {
+        self.published_log = Some(Arc::new(log));
+
+        self
+    }
+
+    /// Use the capabilities of the cluster, instead of detecting them from its version.
+    ///
+    /// See the [`capabilities`](crate::capabilities) module for more information.
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = Some(capabilities);
+
+        self
+    }
+
+    /// Configure how the messages received with the retained flag are handled.
+    ///
+    /// See [`RetainedPolicy`] for the available policies.
+    pub fn retained_policy(mut self, policy: RetainedPolicy) -> Self {
+        self.retained_policy = policy;
+
+        self
+    }
+
+    /// Check the end-to-end connectivity with pings echoed by the server.
+    ///
+    /// See the [`liveness`](crate::liveness) module for more information.
+    pub fn liveness(mut self, liveness: Liveness) -> Self {
+        self.liveness = Some(liveness);
+
+        self
+    }
+
+    /// Shut down the device gracefully when the signal future completes.
+    ///
+    /// The signal is awaited by [`handle_events()`](crate::AstarteDeviceSdk::handle_events),
+    /// that then flushes the pending messages and disconnects within the deadline, see
+    /// [`shutdown()`](crate::AstarteDeviceSdk::shutdown).
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use astarte_device_sdk::options::AstarteOptions;
+    ///
+    /// let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+    ///
+    /// let sdk_options = AstarteOptions::new("_","_","_","_").shutdown_signal(
+    ///     async move {
+    ///         let _ = rx.await;
+    ///     },
+    ///     Duration::from_secs(5),
+    /// );
+    /// ```
+    pub fn shutdown_signal<F>(mut self, signal: F, deadline: std::time::Duration) -> Self
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown_signal = Some(ShutdownSignal::new(signal, deadline));
+
+        self
+    }
+
+    /// Shut down the device gracefully on SIGTERM or SIGINT, like with
+    /// [`shutdown_signal()`](AstarteOptions::shutdown_signal).
+    ///
+    /// The signal handlers are installed the first time the events are handled. Only SIGINT is
+    /// handled on the platforms other than Unix.
+    #[cfg(feature = "signals")]
+    pub fn shutdown_on_signals(self, deadline: std::time::Duration) -> Self {
+        self.shutdown_signal(crate::shutdown::terminate(), deadline)
+    }
+
+    /// Download the interfaces from a [schema registry](crate::registry) when the device
+    /// connects.
+    ///
+    /// The downloaded interfaces are added to the ones of the options, or replace them if they
+    /// are a valid new version. If the download fails the device connects with the interfaces of
+    /// the options.
+    pub fn schema_registry(mut self, registry: SchemaRegistry) -> Self {
+        self.schema_registry = Some(registry);
+
+        self
+    }
+
+    /// Check the introspection against the interfaces installed in the realm when the device
+    /// connects, see the [discovery](crate::discovery) module.
+    ///
+    /// The mismatching interfaces are handled with the policy. If the realm can't be queried a
+    /// warning is logged and the device connects.
+    pub fn realm_discovery(mut self, realm: RealmManagement, policy: MismatchPolicy) -> Self {
+        self.realm_discovery = Some((realm, policy));
+
+        self
+    }
+
+    /// Number of recent errors kept in the history, 20 by default, see
+    /// [`recent_errors()`](crate::AstarteDeviceSdk::recent_errors).
+    ///
+    /// With a capacity of zero the errors are not recorded.
+    pub fn error_history(mut self, capacity: usize) -> Self {
+        self.error_history = capacity;
+
+        self
+    }
+
+    /// Format of the log entries of the messages and of the errors, see the
+    /// [`logging`](crate::logging) module.
+    pub fn log_format(mut self, format: LogFormat) -> Self {
+        self.log_format = format;
+
+        self
+    }
+
+    /// Number of buffers kept to serialize the payloads, 4 by default, see
+    /// [`buffer_pool_stats()`](crate::AstarteDeviceSdk::buffer_pool_stats).
+    ///
+    /// It should be the number of tasks sending concurrently. With zero a new buffer is
+    /// allocated for each payload.
+    pub fn buffer_pool(mut self, size: usize) -> Self {
+        self.buffer_pool = size;
+
+        self
+    }
+
     /// Ignore TLS/SSL certificate errors.
     pub fn ignore_ssl_errors(mut self) -> Self {
         self.ignore_ssl_errors = true;
@@ -176,6 +893,83 @@ impl AstarteOptions {
             .iter()
             .try_fold(self, |acc, path| acc.interface_file(path))
     }
+
+    /// Add the interfaces from multiple directories, ordered from the lowest to the highest
+    /// precedence.
+    ///
+    /// An interface present in more than one directory is resolved with the given
+    /// [`InterfaceConflictPolicy`]. The directories that don't exist are skipped, so an optional
+    /// overlay can always be passed.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::options::{AstarteOptions, InterfaceConflictPolicy};
+    ///
+    /// let sdk_options = AstarteOptions::new("_","_","_","_")
+    ///     .interface_directories(
+    ///         &["/usr/share/interfaces", "/data/ota/interfaces", "/var/lib/interfaces"],
+    ///         InterfaceConflictPolicy::HighestVersion,
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn interface_directories<P>(
+        mut self,
+        directories: &[P],
+        policy: InterfaceConflictPolicy,
+    ) -> Result<Self, OptionsError>
+    where
+        P: AsRef<Path>,
+    {
+        let mut interfaces: HashMap<String, Interface> = HashMap::new();
+
+        for directory in directories {
+            let directory = directory.as_ref();
+
+            let files = match walk_dir_json(directory) {
+                Ok(files) => files,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    debug!(
+                        "Skipping missing interface directory {}",
+                        directory.display()
+                    );
+
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            for file in files {
+                let interface = Interface::from_file(&file)?;
+
+                match interfaces.entry(interface.interface_name().to_string()) {
+                    Entry::Occupied(mut entry) => {
+                        let replace = match policy {
+                            InterfaceConflictPolicy::HighestVersion => {
+                                interface.version() >= entry.get().version()
+                            }
+                            InterfaceConflictPolicy::Precedence => true,
+                        };
+
+                        if replace {
+                            debug!("Interface {} overridden by {}", interface, file.display());
+
+                            entry.insert(interface);
+                        }
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(interface);
+                    }
+                }
+            }
+        }
+
+        for interface in interfaces.into_values() {
+            debug!("Added interface {}", interface);
+
+            self.interfaces.add(interface)?;
+        }
+
+        Ok(self)
+    }
 }
 
 /// Walks a directory returning an array of json files
@@ -204,7 +998,7 @@ fn walk_dir_json<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, io::Error> {
 
 #[cfg(test)]
 mod test {
-    use super::AstarteOptions;
+    use super::{AstarteOptions, InterfaceConflictPolicy};
 
     #[test]
     fn interface_directory() {
@@ -231,4 +1025,52 @@ mod test {
             res
         );
     }
+
+    fn write_interface(dir: &std::path::Path, file: &str, major: i32, minor: i32) {
+        let json = format!(
+            r#"{{
+                "interface_name": "org.astarte-platform.test.Values",
+                "version_major": {major},
+                "version_minor": {minor},
+                "type": "datastream",
+                "ownership": "device",
+                "mappings": [{{ "endpoint": "/value", "type": "double" }}]
+            }}"#
+        );
+
+        std::fs::write(dir.join(file), json).unwrap();
+    }
+
+    fn version(options: &AstarteOptions) -> (i32, i32) {
+        let interface = options
+            .interfaces
+            .get("org.astarte-platform.test.Values")
+            .unwrap();
+
+        (interface.version_major(), interface.version_minor())
+    }
+
+    #[test]
+    fn interface_directories() {
+        let base = tempfile::tempdir().unwrap();
+        let overlay = tempfile::tempdir().unwrap();
+        let missing = base.path().join("missing");
+
+        write_interface(base.path(), "values.json", 0, 2);
+        write_interface(overlay.path(), "values.json", 0, 1);
+
+        let dirs = [base.path(), overlay.path(), missing.as_path()];
+
+        let options =
+            AstarteOptions::new("realm", "device_id", "credentials_secret", "pairing_url")
+                .interface_directories(&dirs, InterfaceConflictPolicy::HighestVersion)
+                .unwrap();
+        assert_eq!(version(&options), (0, 2));
+
+        let options =
+            AstarteOptions::new("realm", "device_id", "credentials_secret", "pairing_url")
+                .interface_directories(&dirs, InterfaceConflictPolicy::Precedence)
+                .unwrap();
+        assert_eq!(version(&options), (0, 1));
+    }
 }
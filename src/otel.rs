@@ -0,0 +1,261 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! OpenTelemetry spans of the messages sent and received.
+//!
+//! Available with `feature = ["otel"]`, the spans are created with the global tracer provider.
+//!
+//! Each message sent gets a `send` span, child of the current context of the application, with
+//! a `publish` span when it's handed to the MQTT client. A message kept in the volatile
+//! retention gets a `retain` span, lasting until the message is published again or dropped. The
+//! client doesn't report when a single message is acknowledged by the broker, so the lifecycle
+//! of a message ends with its publish.
+//!
+//! Each event returned by [`handle_events()`](crate::AstarteDeviceSdk::handle_events) gets a
+//! `receive` span, from the packet received to the event returned.
+//!
+//! The [W3C trace context](https://www.w3.org/TR/trace-context/) of the `send` span can be sent
+//! in a string field of the objects, configured with
+//! [`AstarteOptions::trace_context_field`](crate::options::AstarteOptions::trace_context_field),
+//! to correlate the data with the services consuming it. The field of the objects received is
+//! used as the parent of the `receive` span.
+//!
+//! ```no_run
+//! use astarte_device_sdk::options::AstarteOptions;
+//!
+//! let sdk_options = AstarteOptions::new("_","_","_","_")
+//!     .trace_context_field("com.example.Readings", "traceparent");
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+use opentelemetry_api::trace::{
+    Span, SpanContext, SpanId, SpanKind, Status, TraceContextExt, TraceFlags, TraceId, TraceState,
+    Tracer,
+};
+use opentelemetry_api::{global, Context, KeyValue};
+
+use crate::message::{MessageId, MessageStage};
+use crate::types::AstarteType;
+use crate::{Aggregation, AstarteDeviceDataEvent, Error};
+
+/// Name of the tracer of the SDK.
+const TRACER: &str = "astarte-device-sdk";
+
+fn attributes(interface: &str, path: &str) -> Vec<KeyValue> {
+    vec![
+        KeyValue::new("astarte.interface", interface.to_string()),
+        KeyValue::new("astarte.path", path.to_string()),
+    ]
+}
+
+/// Returns the W3C `traceparent` of the span of the context, if it's valid.
+pub(crate) fn traceparent(cx: &Context) -> Option<String> {
+    let span = cx.span();
+    let span_context = span.span_context();
+
+    span_context.is_valid().then(|| {
+        format!(
+            "00-{}-{}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags().to_u8()
+        )
+    })
+}
+
+/// Parses a W3C `traceparent` in the remote span context.
+pub(crate) fn parse_traceparent(traceparent: &str) -> Option<SpanContext> {
+    let mut parts = traceparent.split('-');
+
+    let (Some("00"), Some(trace_id), Some(span_id), Some(flags), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return None;
+    };
+
+    if trace_id.len() != 32 || span_id.len() != 16 || flags.len() != 2 {
+        return None;
+    }
+
+    let span_context = SpanContext::new(
+        TraceId::from_hex(trace_id).ok()?,
+        SpanId::from_hex(span_id).ok()?,
+        TraceFlags::new(u8::from_str_radix(flags, 16).ok()?),
+        true,
+        TraceState::default(),
+    );
+
+    span_context.is_valid().then_some(span_context)
+}
+
+/// Spans of the messages, with the fields of the trace context.
+#[derive(Debug, Default)]
+pub(crate) struct MessageTracing {
+    fields: HashMap<String, String>,
+    /// Contexts of the `send` and `retain` spans of the retained messages.
+    retained: Mutex<HashMap<MessageId, (Context, Context)>>,
+}
+
+impl MessageTracing {
+    pub(crate) fn new(fields: HashMap<String, String>) -> Self {
+        Self {
+            fields,
+            retained: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<MessageId, (Context, Context)>> {
+        // the contexts are always valid, since they are only inserted while locked
+        self.retained.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the field of the trace context of the interface.
+    pub(crate) fn field(&self, interface: &str) -> Option<&str> {
+        self.fields.get(interface).map(String::as_str)
+    }
+
+    /// Sends a message in its `send` span, child of the current context.
+    pub(crate) async fn send<F, T>(&self, interface: &str, path: &str, send: F) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        let tracer = global::tracer(TRACER);
+        let span = tracer
+            .span_builder(format!("send {interface}"))
+            .with_kind(SpanKind::Producer)
+            .with_attributes(attributes(interface, path))
+            .start(&tracer);
+        let cx = Context::current_with_span(span);
+
+        let res = opentelemetry_api::trace::FutureExt::with_context(send, cx.clone()).await;
+
+        let span = cx.span();
+        if let Err(err) = &res {
+            span.set_status(Status::error(err.to_string()));
+        }
+        span.end();
+
+        res
+    }
+
+    /// Records a step of a message, sent in the current context or retained.
+    pub(crate) fn step(&self, id: MessageId, interface: &str, path: &str, stage: MessageStage) {
+        let tracer = global::tracer(TRACER);
+        let child = |name: &'static str, parent: &Context| {
+            let mut attributes = attributes(interface, path);
+            attributes.push(KeyValue::new("astarte.message_id", id.to_string()));
+
+            tracer
+                .span_builder(name)
+                .with_kind(SpanKind::Producer)
+                .with_attributes(attributes)
+                .start_with_context(&tracer, parent)
+        };
+
+        match stage {
+            MessageStage::Published => {
+                child("publish", &Context::current()).end();
+            }
+            MessageStage::Retained => {
+                let send = Context::current();
+                let retain = send.with_span(child("retain", &send));
+
+                self.lock().insert(id, (send, retain));
+            }
+            MessageStage::Republished | MessageStage::Dropped => {
+                let (send, retain) = self
+                    .lock()
+                    .remove(&id)
+                    .unwrap_or_else(|| (Context::current(), Context::new()));
+
+                let span = retain.span();
+                if stage == MessageStage::Dropped {
+                    span.set_status(Status::error("dropped from the volatile retention"));
+                }
+                span.end();
+
+                if stage == MessageStage::Republished {
+                    child("publish", &send).end();
+                }
+            }
+        }
+    }
+
+    /// Records the `receive` span of an event, from the instant the packet was received.
+    pub(crate) fn received(&self, event: &AstarteDeviceDataEvent, received_at: SystemTime) {
+        let remote = match (&event.data, self.field(&event.interface)) {
+            (Aggregation::Object(object), Some(field)) => match object.get(field) {
+                Some(AstarteType::String(traceparent)) => parse_traceparent(traceparent),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let parent = match remote {
+            Some(span_context) => Context::new().with_remote_span_context(span_context),
+            None => Context::current(),
+        };
+
+        let tracer = global::tracer(TRACER);
+        tracer
+            .span_builder(format!("receive {}", event.interface))
+            .with_kind(SpanKind::Consumer)
+            .with_start_time(received_at)
+            .with_attributes(attributes(&event.interface, &event.path))
+            .start_with_context(&tracer, &parent)
+            .end();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_traceparent() {
+        let header = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+        let span_context = parse_traceparent(header).unwrap();
+        assert!(span_context.is_remote());
+        assert!(span_context.is_sampled());
+
+        let cx = Context::new().with_remote_span_context(span_context);
+        assert_eq!(traceparent(&cx).as_deref(), Some(header));
+
+        // no span in the context
+        assert_eq!(traceparent(&Context::new()), None);
+
+        for invalid in [
+            "01-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331",
+            "00-0af7651916cd43dd-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-00",
+        ] {
+            assert_eq!(parse_traceparent(invalid), None, "{invalid}");
+        }
+    }
+}
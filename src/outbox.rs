@@ -0,0 +1,714 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Outbox of messages to publish, stored together with the application state.
+//!
+//! The application can commit a message intent in the same transaction used to update its own
+//! state, the committed entries are then published with
+//! [`publish_outbox()`](crate::AstarteDeviceSdk::publish_outbox). An entry is removed from the
+//! outbox only after the broker acknowledged it, so the events of the device must be handled by
+//! another task with [`handle_events()`](crate::AstarteDeviceSdk::handle_events). The entries not
+//! acknowledged, because the connection was lost, are published again on the next call.
+//!
+//! An entry that can't be published is either:
+//!
+//! - discarded, if publishing it again would fail the same way, like a message not matching its
+//!   interface or a property exceeding the [storage quota](crate::quota) of its interface;
+//! - kept and published on the next call, stopping at it, if the device can't publish it now, like
+//!   for a full store or a [rate limit](crate::handle::InterfaceHandle);
+//! - kept, returning the error.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{
+//!     database::AstarteSqliteDatabase, outbox::OutboxIntent, types::AstarteType,
+//! };
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let database = AstarteSqliteDatabase::new("path/to/database/file.sqlite")
+//!         .await
+//!         .unwrap();
+//!
+//!     let mut tx = database.begin().await.unwrap();
+//!
+//!     // ... update the application tables using the same transaction
+//!
+//!     let intent =
+//!         OutboxIntent::individual("my.interface.name", "/endpoint/path", AstarteType::Integer(42), None)
+//!             .unwrap();
+//!     AstarteSqliteDatabase::enqueue(&mut tx, &intent).await.unwrap();
+//!
+//!     tx.commit().await.unwrap();
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+use log::{debug, warn};
+
+use crate::database::AstarteDatabase;
+use crate::error::PayloadOperation;
+use crate::interface::mapping::path::MappingPath;
+use crate::{payload, types::AstarteType, Error};
+use crate::{Aggregation, AstarteDeviceSdk, Sent};
+
+/// A message committed to the outbox, waiting to be published.
+#[derive(FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct OutboxEntry {
+    /// Identifier of the entry, entries are published in increasing order.
+    pub id: i64,
+    pub interface: String,
+    pub path: String,
+    /// BSON payload of the message.
+    pub payload: Vec<u8>,
+}
+
+/// Intent of publishing a message, to be committed in the outbox.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxIntent {
+    pub(crate) interface: String,
+    pub(crate) path: String,
+    pub(crate) payload: Vec<u8>,
+}
+
+impl OutboxIntent {
+    /// Create the intent of sending an individual datastream or property.
+    pub fn individual(
+        interface: &str,
+        path: &str,
+        data: AstarteType,
+        timestamp: Option<DateTime<Utc>>,
+    ) -> Result<Self, Error> {
+        let payload = payload::serialize_individual(&data, timestamp)?;
+
+        Ok(Self {
+            interface: interface.to_string(),
+            path: path.to_string(),
+            payload,
+        })
+    }
+
+    /// Create the intent of sending an object datastream.
+    pub fn object<T>(
+        interface: &str,
+        path: &str,
+        data: T,
+        timestamp: Option<DateTime<Utc>>,
+    ) -> Result<Self, Error>
+    where
+        T: crate::AstarteAggregate,
+    {
+        let aggregate: HashMap<String, AstarteType> = data.astarte_aggregate()?;
+        let payload = payload::serialize_object(&aggregate, timestamp)?;
+
+        Ok(Self {
+            interface: interface.to_string(),
+            path: path.to_string(),
+            payload,
+        })
+    }
+
+    /// Returns the interface of the message.
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    /// Returns the path of the message.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// Trait providing the storage of the outbox.
+///
+/// The SDK provides an implementation for the sqlite database, see
+/// [`AstarteSqliteDatabase`](crate::database::AstarteSqliteDatabase).
+#[async_trait]
+pub trait AstarteOutbox {
+    /// Returns the committed entries, in the order they should be published.
+    async fn pending(&self) -> Result<Vec<OutboxEntry>, Error>;
+    /// Removes a published entry from the outbox.
+    async fn remove(&self, id: i64) -> Result<(), Error>;
+}
+
+/// Handling of an outbox entry that couldn't be published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutboxFailure {
+    /// Publishing it again would fail the same way, the entry is removed.
+    Discard,
+    /// The entry is kept and published on the next call.
+    RetryLater,
+    /// The entry is kept and the error returned.
+    Fail,
+}
+
+impl OutboxFailure {
+    pub(crate) fn of(err: &Error) -> Self {
+        match err {
+            err if err.is_invalid_data() => OutboxFailure::Discard,
+            // the property was published, but it can't be stored
+            Error::QuotaExceeded { .. } => OutboxFailure::Discard,
+            // the store can have room on the next call
+            Error::StoreFull { .. } => OutboxFailure::RetryLater,
+            Error::RateLimited(_) => OutboxFailure::RetryLater,
+            _ => OutboxFailure::Fail,
+        }
+    }
+}
+
+impl<S> AstarteDeviceSdk<S>
+where
+    S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+{
+    /// Publish the entries committed to the outbox, returning the number of published messages.
+    ///
+    /// The entries are published in the order they were committed, each one is removed from the
+    /// outbox after the broker acknowledged it, so the events must be handled by another task
+    /// with [`handle_events()`](AstarteDeviceSdk::handle_events). The entries not acknowledged,
+    /// because the connection was lost, are kept for the next call.
+    ///
+    /// An entry that can't be sent, because its interface was removed, its path or payload are
+    /// invalid or its property exceeds the storage quota, is logged and removed. If the store is
+    /// full or the message is rate limited, the entry and the next ones are kept for the next
+    /// call. If sending an entry fails for any other reason, like a connection error, the error
+    /// is returned and the remaining entries are kept in the outbox for the next call, see the
+    /// [`outbox`](crate::outbox) module.
+    ///
+    /// With a [`PublishedLog`](crate::dedup::PublishedLog) the entries on the mappings with `unique` reliability already
+    /// published are removed without being published again, see the [`dedup`](crate::dedup) module.
+    ///
+    /// ```no_run
+    /// use astarte_device_sdk::{
+    ///     database::AstarteSqliteDatabase, options::AstarteOptions, AstarteDeviceSdk,
+    /// };
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let database = AstarteSqliteDatabase::new("path/to/database/file.sqlite")
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let mut sdk_options = AstarteOptions::new("_","_","_","_");
+    ///     let mut device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+    ///
+    ///     let publisher = device.clone();
+    ///     tokio::spawn(async move {
+    ///         loop {
+    ///             publisher.publish_outbox(&database).await.unwrap();
+    ///             tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    ///         }
+    ///     });
+    ///
+    ///     // the acknowledgments are received while handling the events
+    ///     loop {
+    ///         device.handle_events().await.unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub async fn publish_outbox<O>(&self, outbox: &O) -> Result<usize, Error>
+    where
+        O: AstarteOutbox + Sync,
+    {
+        let entries = outbox.pending().await?;
+
+        // the entries handed to the client, removed in order once they are delivered
+        let mut sent = Vec::new();
+        let mut failed = None;
+
+        for entry in entries {
+            let log = self.unique_log(&entry.interface, &entry.path).await;
+
+            // published before a restart, but not removed from the outbox
+            if log.map_or(false, |log| log.contains(entry.id)) {
+                debug!(
+                    "outbox entry {} on {}{} already published, removing it",
+                    entry.id, entry.interface, entry.path
+                );
+
+                outbox.remove(entry.id).await?;
+                continue;
+            }
+
+            debug!(
+                "publishing outbox entry {} on {}{}",
+                entry.id, entry.interface, entry.path
+            );
+
+            match self
+                .publish_payload(&entry.interface, &entry.path, &entry.payload)
+                .await
+            {
+                Ok(Sent::Queued(delivery)) => sent.push((entry, Some(delivery), log)),
+                Ok(Sent::Skipped) => sent.push((entry, None, log)),
+                // the entry is kept in the outbox instead
+                Ok(Sent::Retained(id)) => {
+                    debug!(
+                        "outbox entry {} on {}{} not published, retrying later",
+                        entry.id, entry.interface, entry.path
+                    );
+
+                    self.volatile.lock().await.retain(|item| item.id != id);
+
+                    break;
+                }
+                Err(err) => match OutboxFailure::of(&err) {
+                    OutboxFailure::Discard => {
+                        warn!(
+                            "discarding outbox entry {} on {}{}: {err}",
+                            entry.id, entry.interface, entry.path
+                        );
+
+                        outbox.remove(entry.id).await?;
+                    }
+                    OutboxFailure::RetryLater => {
+                        warn!(
+                            "outbox entry {} on {}{} not published, retrying later: {err}",
+                            entry.id, entry.interface, entry.path
+                        );
+
+                        break;
+                    }
+                    OutboxFailure::Fail => {
+                        failed = Some(err);
+
+                        break;
+                    }
+                },
+            }
+        }
+
+        let mut published = 0;
+
+        for (entry, delivery, log) in sent {
+            if let Some(delivery) = delivery {
+                if !delivery.delivered().await {
+                    debug!(
+                        "outbox entry {} on {}{} was not acknowledged, retrying later",
+                        entry.id, entry.interface, entry.path
+                    );
+
+                    break;
+                }
+            }
+
+            // the exchange of the unique messages is complete, they won't be sent again
+            if let Some(log) = log {
+                if let Err(err) = log.record(entry.id) {
+                    warn!(
+                        "couldn't record the published outbox entry {}: {err}",
+                        entry.id
+                    );
+                }
+            }
+
+            outbox.remove(entry.id).await?;
+            published += 1;
+        }
+
+        match failed {
+            Some(err) => Err(err),
+            None => Ok(published),
+        }
+    }
+
+    /// Sends a payload serialized in advance, like the ones of the outbox.
+    pub(crate) async fn publish_payload(
+        &self,
+        interface_name: &str,
+        interface_path: &str,
+        payload: &[u8],
+    ) -> Result<Sent, Error> {
+        let path = MappingPath::try_from(interface_path)?;
+        let (data, timestamp) = self
+            .interfaces
+            .read()
+            .await
+            .deserialize(interface_name, &path, payload)
+            .map_err(Error::payload(
+                interface_name,
+                interface_path,
+                PayloadOperation::Deserialize,
+            ))?;
+
+        match data {
+            Aggregation::Individual(data) => {
+                self.send_with_timestamp_impl(interface_name, &path, data, timestamp, false)
+                    .await
+            }
+            Aggregation::Object(data) => {
+                self.send_object_with_timestamp_impl(interface_name, &path, data, timestamp)
+                    .await
+            }
+            Aggregation::Unset => {
+                self.send_with_timestamp_impl(
+                    interface_name,
+                    &path,
+                    AstarteType::Unset,
+                    timestamp,
+                    false,
+                )
+                .await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use mockall::predicate;
+    use rumqttc::Event;
+
+    use super::*;
+    use crate::database::faulty::{Fault, FaultyStore, StoreOperation};
+    use crate::database::quota::QuotaDatabase;
+    use crate::database::AstarteSqliteDatabase;
+    use crate::delivery::Deliveries;
+    use crate::mock::{acknowledged, MockAsyncClient, MockDevice, MockEventLoop};
+    use crate::quota::{QuotaPolicy, StoreQuota};
+    use crate::test::{DEVICE_PROPERTIES, DEVICE_PROPERTIES_NAME, OBJECT_DEVICE_DATASTREAM};
+    use crate::Interface;
+
+    #[test]
+    fn test_outbox_failure_rate_limited() {
+        let err = Error::RateLimited("com.test".to_string());
+
+        assert_eq!(OutboxFailure::of(&err), OutboxFailure::RetryLater);
+    }
+
+    #[tokio::test]
+    async fn test_publish_outbox() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("outbox.sqlite");
+        let db = AstarteSqliteDatabase::new(db_path.to_str().unwrap())
+            .await
+            .unwrap();
+
+        let timestamp = chrono::TimeZone::timestamp_opt(&chrono::Utc, 1537449422, 0).unwrap();
+
+        let mut obj = HashMap::new();
+        obj.insert("endpoint1".to_string(), AstarteType::Double(4.2));
+        obj.insert(
+            "endpoint2".to_string(),
+            AstarteType::String("obj".to_string()),
+        );
+        obj.insert(
+            "endpoint3".to_string(),
+            AstarteType::BooleanArray(vec![true]),
+        );
+
+        let object = OutboxIntent::object(
+            "org.astarte-platform.rust.examples.object-datastream.DeviceDatastream",
+            "/1",
+            obj,
+            Some(timestamp),
+        )
+        .unwrap();
+        let property = OutboxIntent::individual(
+            "org.astarte-platform.rust.examples.individual-properties.DeviceProperties",
+            "/1/name",
+            AstarteType::String("name number 1".to_string()),
+            None,
+        )
+        .unwrap();
+
+        let mut tx = db.begin().await.unwrap();
+        AstarteSqliteDatabase::enqueue(&mut tx, &object)
+            .await
+            .unwrap();
+        AstarteSqliteDatabase::enqueue(&mut tx, &property)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let deliveries = Arc::new(Deliveries::default());
+        let mut client = MockAsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq("realm/device_id/org.astarte-platform.rust.examples.object-datastream.DeviceDatastream/1".to_string()),
+                predicate::always(),
+                predicate::always(),
+                predicate::always(),
+            )
+            .returning(acknowledged(&deliveries));
+
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq("realm/device_id/org.astarte-platform.rust.examples.individual-properties.DeviceProperties/1/name".to_string()),
+                predicate::always(),
+                predicate::always(),
+                predicate::always(),
+            )
+            .returning(acknowledged(&deliveries));
+
+        let astarte = MockDevice::new(client, MockEventLoop::default())
+            .interfaces([
+                Interface::from_str(OBJECT_DEVICE_DATASTREAM).unwrap(),
+                Interface::from_str(DEVICE_PROPERTIES).unwrap(),
+            ])
+            .deliveries(&deliveries)
+            .build();
+
+        let published = astarte.publish_outbox(&db).await.unwrap();
+        assert_eq!(published, 2);
+
+        assert!(db.pending().await.unwrap().is_empty());
+        assert_eq!(astarte.publish_outbox(&db).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_publish_outbox_invalid_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("outbox.sqlite");
+        let db = AstarteSqliteDatabase::new(db_path.to_str().unwrap())
+            .await
+            .unwrap();
+
+        let intents = [
+            // the interface was removed
+            OutboxIntent::individual(
+                "org.astarte-platform.rust.examples.individual-properties.Removed",
+                "/1/name",
+                AstarteType::String("removed".to_string()),
+                None,
+            )
+            .unwrap(),
+            OutboxIntent::individual(
+                DEVICE_PROPERTIES_NAME,
+                "/1/name",
+                AstarteType::String("first".to_string()),
+                None,
+            )
+            .unwrap(),
+            OutboxIntent::individual(
+                DEVICE_PROPERTIES_NAME,
+                "/2/name",
+                AstarteType::String("second".to_string()),
+                None,
+            )
+            .unwrap(),
+        ];
+
+        let mut tx = db.begin().await.unwrap();
+        for intent in &intents {
+            AstarteSqliteDatabase::enqueue(&mut tx, intent)
+                .await
+                .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        let deliveries = Arc::new(Deliveries::default());
+        let mut client = MockAsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq(format!("realm/device_id/{DEVICE_PROPERTIES_NAME}/1/name")),
+                predicate::always(),
+                predicate::always(),
+                predicate::always(),
+            )
+            .returning(acknowledged(&deliveries));
+
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq(format!("realm/device_id/{DEVICE_PROPERTIES_NAME}/2/name")),
+                predicate::always(),
+                predicate::always(),
+                predicate::always(),
+            )
+            .returning(|_, _, _, _| {
+                Err(rumqttc::ClientError::Request(rumqttc::Request::Disconnect))
+            });
+
+        let astarte = MockDevice::new(client, MockEventLoop::default())
+            .interfaces([Interface::from_str(DEVICE_PROPERTIES).unwrap()])
+            .deliveries(&deliveries)
+            .build();
+
+        // the invalid entry is discarded, the connection error stops the publishing
+        let res = astarte.publish_outbox(&db).await;
+        assert!(matches!(res, Err(Error::Publish { .. })), "{res:?}");
+
+        let pending = db.pending().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].path, "/2/name");
+    }
+
+    /// Returns an outbox with the names of the device properties committed.
+    async fn mock_outbox(names: &[(&str, &str)]) -> (tempfile::TempDir, AstarteSqliteDatabase) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = AstarteSqliteDatabase::new(dir.path().join("outbox.sqlite").to_str().unwrap())
+            .await
+            .unwrap();
+
+        let mut tx = db.begin().await.unwrap();
+        for (path, name) in names {
+            let intent = OutboxIntent::individual(
+                DEVICE_PROPERTIES_NAME,
+                path,
+                AstarteType::String(name.to_string()),
+                None,
+            )
+            .unwrap();
+
+            AstarteSqliteDatabase::enqueue(&mut tx, &intent)
+                .await
+                .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        (dir, db)
+    }
+
+    #[tokio::test]
+    async fn test_publish_outbox_not_acknowledged() {
+        let (_dir, db) = mock_outbox(&[("/1/name", "first"), ("/2/name", "second")]).await;
+
+        let deliveries = Arc::new(Deliveries::default());
+        let handed = Arc::new(tokio::sync::Notify::new());
+
+        let mut client = MockAsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+
+        // sent, but the connection is lost before the acknowledgments
+        let mut pkid = 0;
+        let sent = Arc::clone(&deliveries);
+        let notify = Arc::clone(&handed);
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .times(2)
+            .in_sequence(&mut seq)
+            .returning(move |_, _, _, _| {
+                pkid += 1;
+                sent.handle(&Event::Outgoing(rumqttc::Outgoing::Publish(pkid)));
+
+                if pkid == 2 {
+                    notify.notify_one();
+                }
+
+                Ok(())
+            });
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .times(2)
+            .in_sequence(&mut seq)
+            .returning(acknowledged(&deliveries));
+
+        let astarte = MockDevice::new(client, MockEventLoop::default())
+            .interfaces([Interface::from_str(DEVICE_PROPERTIES).unwrap()])
+            .deliveries(&deliveries)
+            .build();
+
+        let reconnect = async {
+            handed.notified().await;
+
+            // connected with a new transport
+            let lost = deliveries.connected(&Arc::new(()));
+            assert!(lost.is_empty());
+        };
+
+        let (published, ()) = tokio::join!(astarte.publish_outbox(&db), reconnect);
+        assert_eq!(published.unwrap(), 0);
+        assert_eq!(db.pending().await.unwrap().len(), 2);
+
+        assert_eq!(astarte.publish_outbox(&db).await.unwrap(), 2);
+        assert!(db.pending().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_publish_outbox_store_full() {
+        let (_dir, db) = mock_outbox(&[("/1/name", "first"), ("/2/name", "second")]).await;
+
+        let deliveries = Arc::new(Deliveries::default());
+        let mut client = MockAsyncClient::default();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .times(3)
+            .returning(acknowledged(&deliveries));
+
+        let mut astarte = MockDevice::new(client, MockEventLoop::default())
+            .interfaces([Interface::from_str(DEVICE_PROPERTIES).unwrap()])
+            .deliveries(&deliveries)
+            .build();
+
+        let store = FaultyStore::new(AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap());
+        store.inject(StoreOperation::StoreProp, Fault::full().times(1));
+        astarte.database = Some(Arc::new(store));
+
+        // the entry is kept, and published again once the store has room
+        assert_eq!(astarte.publish_outbox(&db).await.unwrap(), 0);
+        assert_eq!(db.pending().await.unwrap().len(), 2);
+
+        assert_eq!(astarte.publish_outbox(&db).await.unwrap(), 2);
+        assert!(db.pending().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_publish_outbox_quota_exceeded() {
+        let (_dir, db) = mock_outbox(&[
+            ("/1/name", "first"),
+            ("/2/name", "second"),
+            ("/1/name", "third"),
+        ])
+        .await;
+
+        let deliveries = Arc::new(Deliveries::default());
+        let mut client = MockAsyncClient::default();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .times(3)
+            .returning(acknowledged(&deliveries));
+
+        let mut astarte = MockDevice::new(client, MockEventLoop::default())
+            .interfaces([Interface::from_str(DEVICE_PROPERTIES).unwrap()])
+            .deliveries(&deliveries)
+            .build();
+
+        let store =
+            QuotaDatabase::new(AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap()).quota(
+                DEVICE_PROPERTIES_NAME,
+                StoreQuota::new().max_rows(1).policy(QuotaPolicy::Reject),
+            );
+        astarte.database = Some(Arc::new(store));
+
+        // the second property would exceed the quota again, it's discarded
+        assert_eq!(astarte.publish_outbox(&db).await.unwrap(), 2);
+        assert!(db.pending().await.unwrap().is_empty());
+    }
+}
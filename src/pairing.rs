@@ -30,8 +30,10 @@ use serde_json::json;
 use url::ParseError;
 
 use crate::{
+    capabilities::Capabilities,
     crypto::{Bundle, CryptoError},
     options::{AstarteOptions, OptionsError},
+    selftest::{CertificateValidity, ConnectionInfo},
 };
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -128,7 +130,8 @@ async fn fetch_credentials(opts: &AstarteOptions, csr: &str) -> Result<String, P
     }
 }
 
-async fn fetch_broker_url(opts: &AstarteOptions) -> Result<String, PairingError> {
+/// Returns the broker URL and the version of the cluster.
+async fn fetch_broker_url(opts: &AstarteOptions) -> Result<(String, String), PairingError> {
     let mut url = Url::parse(&opts.pairing_url)?;
     // We have to do this this way to avoid unconsistent behaviour depending
     // on the user putting the trailing slash or not
@@ -149,6 +152,7 @@ async fn fetch_broker_url(opts: &AstarteOptions) -> Result<String, PairingError>
     match response.status() {
         StatusCode::OK => {
             if let ResponseContents::StatusInfo {
+                version,
                 protocols:
                     ProtocolsInfo {
                         astarte_mqtt_v1: AstarteMqttV1Info { broker_url },
@@ -156,7 +160,7 @@ async fn fetch_broker_url(opts: &AstarteOptions) -> Result<String, PairingError>
                 ..
             } = response.json::<ApiResponse>().await?.data
             {
-                Ok(broker_url)
+                Ok((broker_url, version))
             } else {
                 Err(PairingError::UnexpectedResponse)
             }
@@ -184,10 +188,10 @@ async fn populate_credentials(
     Ok((certs, private_key))
 }
 
-async fn populate_broker_url(opts: &AstarteOptions) -> Result<Url, PairingError> {
-    let broker_url = fetch_broker_url(opts).await?;
+async fn populate_broker_url(opts: &AstarteOptions) -> Result<(Url, String), PairingError> {
+    let (broker_url, version) = fetch_broker_url(opts).await?;
     let parsed_broker_url = Url::parse(&broker_url)?;
-    Ok(parsed_broker_url)
+    Ok((parsed_broker_url, version))
 }
 
 fn build_mqtt_opts(
@@ -209,7 +213,10 @@ fn build_mqtt_opts(
         .ok_or_else(|| PairingError::ConfigError("bad broker url".into()))?;
 
     let mut root_cert_store = rustls::RootCertStore::empty();
-    for cert in rustls_native_certs::load_native_certs().expect("could not load platform certs") {
+    let native_certs = rustls_native_certs::load_native_certs().map_err(|err| {
+        PairingError::ConfigError(format!("could not load platform certs: {err}"))
+    })?;
+    for cert in native_certs {
         root_cert_store.add(&rustls::Certificate(cert.0))?;
     }
 
@@ -261,15 +268,35 @@ fn build_mqtt_opts(
     Ok(mqtt_opts)
 }
 
-/// Returns a MqttOptions struct that can be used to connect to the broker.
+/// Configuration to connect to the broker.
+pub(crate) struct TransportConfig {
+    pub(crate) mqtt_options: MqttOptions,
+    /// Capabilities detected from the version of the cluster.
+    pub(crate) capabilities: Capabilities,
+    /// Broker and certificate, kept for the self test.
+    pub(crate) connection: ConnectionInfo,
+}
+
+/// Returns a MqttOptions struct that can be used to connect to the broker, and the
+/// [`Capabilities`] detected from the version of the cluster.
 pub(crate) async fn get_transport_config(
     opts: &AstarteOptions,
-) -> Result<MqttOptions, OptionsError> {
+) -> Result<TransportConfig, OptionsError> {
     let (certificate, private_key) = populate_credentials(opts).await?;
-
-    let broker_url = populate_broker_url(opts).await?;
-
-    let mqtt_opts = build_mqtt_opts(opts, certificate, private_key, &broker_url)?;
-
-    Ok(mqtt_opts)
+    let validity = certificate
+        .first()
+        .and_then(|certificate| CertificateValidity::from_der(&certificate.0));
+
+    let (broker_url, version) = populate_broker_url(opts).await?;
+
+    let mqtt_options = build_mqtt_opts(opts, certificate, private_key, &broker_url)?;
+
+    Ok(TransportConfig {
+        connection: ConnectionInfo {
+            broker: mqtt_options.broker_address(),
+            certificate: validity,
+        },
+        mqtt_options,
+        capabilities: Capabilities::detect(&version),
+    })
 }
@@ -21,13 +21,16 @@
 //! You can find more information about the protocol v1 in the [Astarte MQTT v1 Protocol](https://docs.astarte-platform.org/astarte/latest/080-mqtt-v1-protocol.html).
 
 use std::collections::HashMap;
+use std::io::Write;
 
+use bson::spec::{BinarySubtype, ElementType};
 use bson::Bson;
 use chrono::{DateTime, Utc};
 use log::trace;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    interface::MappingType,
     types::{AstarteType, TypeError},
     Aggregation,
 };
@@ -46,6 +49,10 @@ pub enum PayloadError {
     /// Couldn't convert the value to [`AstarteType`]
     #[error("couldn't convert the value to AstarteType")]
     AstarteType(#[from] TypeError),
+
+    /// The serialized payload exceeds the maximum size of a BSON document.
+    #[error("the payload of {0} bytes exceeds the maximum size of a BSON document")]
+    TooLarge(usize),
 }
 
 /// The payload of an MQTT message.
@@ -65,12 +72,6 @@ impl<T> Payload<T>
 where
     T: serde::Serialize,
 {
-    pub(crate) fn to_vec(&self) -> Result<Vec<u8>, PayloadError> {
-        let res = bson::to_vec(self)?;
-
-        Ok(res)
-    }
-
     pub(crate) fn from_slice<'a>(buf: &'a [u8]) -> Result<Payload<T>, PayloadError>
     where
         T: serde::de::Deserialize<'a>,
@@ -86,12 +87,11 @@ pub(crate) fn serialize_individual(
     data: &AstarteType,
     timestamp: Option<DateTime<Utc>>,
 ) -> Result<Vec<u8>, PayloadError> {
-    let payload = Payload {
-        value: data,
-        timestamp,
-    };
+    let mut buf = Vec::new();
+
+    write_individual(&mut buf, data, timestamp)?;
 
-    payload.to_vec()
+    Ok(buf)
 }
 
 /// Serialize an Object passed as an [`HashMap`] of [`AstarteType`] to bson payload.
@@ -99,51 +99,319 @@ pub(crate) fn serialize_object(
     data: &HashMap<String, AstarteType>,
     timestamp: Option<DateTime<Utc>>,
 ) -> Result<Vec<u8>, PayloadError> {
-    let payload = Payload {
-        value: data,
-        timestamp,
-    };
+    let mut buf = Vec::new();
+
+    write_object(&mut buf, data, timestamp)?;
+
+    Ok(buf)
+}
+
+/// Serialize an [`AstarteType`] to bson payload like [`serialize_individual`], appending it to
+/// the buffer.
+///
+/// The BSON is written directly in the buffer, so a buffer can be reused for different payloads
+/// without allocating.
+pub(crate) fn write_individual(
+    buf: &mut Vec<u8>,
+    data: &AstarteType,
+    timestamp: Option<DateTime<Utc>>,
+) -> Result<(), PayloadError> {
+    write_payload(buf, timestamp, |buf| write_element(buf, "v", data))
+}
+
+/// Serialize an Object to bson payload like [`serialize_object`], appending it to the buffer.
+pub(crate) fn write_object(
+    buf: &mut Vec<u8>,
+    data: &HashMap<String, AstarteType>,
+    timestamp: Option<DateTime<Utc>>,
+) -> Result<(), PayloadError> {
+    write_payload(buf, timestamp, |buf| {
+        write_key(buf, ElementType::EmbeddedDocument, "v")?;
+
+        write_document(buf, |buf| {
+            data.iter()
+                .try_for_each(|(name, value)| write_element(buf, name, value))
+        })
+    })
+}
+
+/// Writes the [`Payload`] document, with the value written by the closure.
+fn write_payload<F>(
+    buf: &mut Vec<u8>,
+    timestamp: Option<DateTime<Utc>>,
+    value: F,
+) -> Result<(), PayloadError>
+where
+    F: FnOnce(&mut Vec<u8>) -> Result<(), PayloadError>,
+{
+    write_document(buf, |buf| {
+        value(buf)?;
+
+        if let Some(timestamp) = timestamp {
+            // same format of the serde implementation of chrono
+            write_key(buf, ElementType::String, "t")?;
+            write_sized(buf, |buf| {
+                write!(buf, "{timestamp:?}").map_err(bson::ser::Error::from)?;
+                buf.push(0);
+
+                Ok(())
+            })?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Writes the value with the same encoding of the [`Bson`] conversion of [`AstarteType`].
+fn write_element(buf: &mut Vec<u8>, key: &str, value: &AstarteType) -> Result<(), PayloadError> {
+    match value {
+        AstarteType::Double(value) => {
+            write_key(buf, ElementType::Double, key)?;
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        AstarteType::Integer(value) => {
+            write_key(buf, ElementType::Int32, key)?;
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        AstarteType::Boolean(value) => {
+            write_key(buf, ElementType::Boolean, key)?;
+            buf.push(u8::from(*value));
+        }
+        AstarteType::LongInteger(value) => {
+            write_key(buf, ElementType::Int64, key)?;
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        AstarteType::String(value) => {
+            write_key(buf, ElementType::String, key)?;
+            write_string(buf, value)?;
+        }
+        AstarteType::BinaryBlob(value) => {
+            write_key(buf, ElementType::Binary, key)?;
+            write_binary(buf, value)?;
+        }
+        AstarteType::DateTime(value) => {
+            write_key(buf, ElementType::DateTime, key)?;
+            buf.extend_from_slice(&value.timestamp_millis().to_le_bytes());
+        }
+        AstarteType::DoubleArray(values) => write_array(buf, key, values, |buf, value| {
+            buf.extend_from_slice(&value.to_le_bytes());
+
+            Ok(ElementType::Double)
+        })?,
+        AstarteType::IntegerArray(values) => write_array(buf, key, values, |buf, value| {
+            buf.extend_from_slice(&value.to_le_bytes());
+
+            Ok(ElementType::Int32)
+        })?,
+        AstarteType::BooleanArray(values) => write_array(buf, key, values, |buf, value| {
+            buf.push(u8::from(*value));
+
+            Ok(ElementType::Boolean)
+        })?,
+        AstarteType::LongIntegerArray(values) => write_array(buf, key, values, |buf, value| {
+            buf.extend_from_slice(&value.to_le_bytes());
+
+            Ok(ElementType::Int64)
+        })?,
+        AstarteType::StringArray(values) => write_array(buf, key, values, |buf, value| {
+            write_string(buf, value)?;
+
+            Ok(ElementType::String)
+        })?,
+        AstarteType::BinaryBlobArray(values) => write_array(buf, key, values, |buf, value| {
+            write_binary(buf, value)?;
+
+            Ok(ElementType::Binary)
+        })?,
+        AstarteType::DateTimeArray(values) => write_array(buf, key, values, |buf, value| {
+            buf.extend_from_slice(&value.timestamp_millis().to_le_bytes());
+
+            Ok(ElementType::DateTime)
+        })?,
+        AstarteType::Unset => write_key(buf, ElementType::Null, key)?,
+    }
+
+    Ok(())
+}
+
+/// Writes an array, the closure writes a value and returns its type.
+///
+/// The type of each element precedes its key, so it's written after the value.
+fn write_array<T, F>(
+    buf: &mut Vec<u8>,
+    key: &str,
+    values: &[T],
+    mut value: F,
+) -> Result<(), PayloadError>
+where
+    F: FnMut(&mut Vec<u8>, &T) -> Result<ElementType, PayloadError>,
+{
+    write_key(buf, ElementType::Array, key)?;
+
+    write_document(buf, |buf| {
+        for (i, item) in values.iter().enumerate() {
+            let type_pos = buf.len();
+
+            buf.push(0);
+            write!(buf, "{i}\0").map_err(bson::ser::Error::from)?;
+
+            let element_type = value(buf, item)?;
+
+            let pos = buf
+                .get_mut(type_pos)
+                .ok_or_else(|| out_of_buffer("element type"))?;
+            *pos = element_type as u8;
+        }
+
+        Ok(())
+    })
+}
+
+/// Writes the type and the key of an element.
+fn write_key(buf: &mut Vec<u8>, element_type: ElementType, key: &str) -> Result<(), PayloadError> {
+    if key.contains('\0') {
+        return Err(bson::ser::Error::InvalidCString(key.to_string()).into());
+    }
+
+    buf.push(element_type as u8);
+    buf.extend_from_slice(key.as_bytes());
+    buf.push(0);
+
+    Ok(())
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) -> Result<(), PayloadError> {
+    write_sized(buf, |buf| {
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(0);
+
+        Ok(())
+    })
+}
+
+fn write_binary(buf: &mut Vec<u8>, value: &[u8]) -> Result<(), PayloadError> {
+    let size = bson_size(value.len())?;
+
+    buf.extend_from_slice(&size.to_le_bytes());
+    buf.push(BinarySubtype::Generic.into());
+    buf.extend_from_slice(value);
+
+    Ok(())
+}
+
+/// Writes a document, with the elements written by the closure.
+fn write_document<F>(buf: &mut Vec<u8>, elements: F) -> Result<(), PayloadError>
+where
+    F: FnOnce(&mut Vec<u8>) -> Result<(), PayloadError>,
+{
+    let start = buf.len();
+
+    buf.extend_from_slice(&[0; 4]);
+    elements(buf)?;
+    buf.push(0);
+
+    // the size of a document includes the size itself
+    let size = bson_size(buf.len() - start)?;
+    write_size_at(buf, start, size)
+}
+
+/// Writes the closure output prefixed with its size, as the strings.
+fn write_sized<F>(buf: &mut Vec<u8>, data: F) -> Result<(), PayloadError>
+where
+    F: FnOnce(&mut Vec<u8>) -> Result<(), PayloadError>,
+{
+    let start = buf.len();
 
-    payload.to_vec()
+    buf.extend_from_slice(&[0; 4]);
+    data(buf)?;
+
+    let size = bson_size(buf.len() - start - 4)?;
+    write_size_at(buf, start, size)
+}
+
+/// Writes the size in the prefix reserved at the position.
+fn write_size_at(buf: &mut [u8], start: usize, size: i32) -> Result<(), PayloadError> {
+    let prefix = buf
+        .get_mut(start..start + 4)
+        .ok_or_else(|| out_of_buffer("size prefix"))?;
+    prefix.copy_from_slice(&size.to_le_bytes());
+
+    Ok(())
+}
+
+/// Error for a position reserved in the buffer that is missing, it's a bug.
+fn out_of_buffer(what: &str) -> PayloadError {
+    let err: bson::ser::Error =
+        serde::ser::Error::custom(format!("BUG: the {what} is out of the buffer"));
+
+    err.into()
+}
+
+fn bson_size(size: usize) -> Result<i32, PayloadError> {
+    i32::try_from(size).map_err(|_| PayloadError::TooLarge(size))
 }
 
 /// Deserialize a bson payload to an individual [`AstarteType`] or an object as an [`HashMap`].
 pub(crate) fn deserialize(bdata: &[u8]) -> Result<Aggregation, PayloadError> {
+    deserialize_with_timestamp(bdata).map(|(data, _)| data)
+}
+
+/// Deserialize a bson payload like [`deserialize`], also returning the explicit timestamp if
+/// present.
+pub(crate) fn deserialize_with_timestamp(
+    bdata: &[u8],
+) -> Result<(Aggregation, Option<DateTime<Utc>>), PayloadError> {
+    deserialize_typed(bdata, |_| None)
+}
+
+/// Deserialize a bson payload like [`deserialize_with_timestamp`], using the types of the
+/// mappings for the values that can't be inferred from the bson, like the empty arrays.
+///
+/// The closure is called with the name of the field for the objects and with [`None`] for the
+/// individual values.
+pub(crate) fn deserialize_typed<F>(
+    bdata: &[u8],
+    mapping_type: F,
+) -> Result<(Aggregation, Option<DateTime<Utc>>), PayloadError>
+where
+    F: Fn(Option<&str>) -> Option<MappingType>,
+{
     if bdata.is_empty() {
-        return Ok(Aggregation::Individual(AstarteType::Unset));
+        return Ok((Aggregation::Unset, None));
     }
 
     let payload = Payload::<Bson>::from_slice(bdata)?;
 
     trace!("{:?}", payload);
 
-    match payload.value {
+    let timestamp = payload.timestamp;
+
+    let data = match payload.value {
         Bson::Document(doc) => {
             let hmap = doc
                 .into_iter()
                 .map(|(name, value)| {
-                    let v = AstarteType::try_from(value)?;
+                    let v = AstarteType::try_from_bson_typed(value, mapping_type(Some(&name)))?;
 
                     Ok((name, v))
                 })
                 .collect::<Result<HashMap<String, AstarteType>, PayloadError>>()?;
 
-            Ok(Aggregation::Object(hmap))
+            Aggregation::Object(hmap)
         }
-        value => {
-            let individual = AstarteType::try_from(value)?;
+        value => match AstarteType::try_from_bson_typed(value, mapping_type(None))? {
+            AstarteType::Unset => Aggregation::Unset,
+            individual => Aggregation::Individual(individual),
+        },
+    };
 
-            Ok(Aggregation::Individual(individual))
-        }
-    }
+    Ok((data, timestamp))
 }
 
 #[cfg(test)]
 mod test {
     use chrono::TimeZone;
 
-    use crate::interface::MappingType;
-
     use super::*;
 
     #[test]
@@ -254,6 +522,60 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_deserialize_with_timestamp() {
+        let timestamp = TimeZone::timestamp_opt(&Utc, 1627580808, 0).unwrap();
+
+        let buf = serialize_individual(&AstarteType::Integer(42), Some(timestamp)).unwrap();
+        let (data, t) = deserialize_with_timestamp(&buf).unwrap();
+        assert_eq!(data, Aggregation::Individual(AstarteType::Integer(42)));
+        assert_eq!(t, Some(timestamp));
+
+        let buf = serialize_individual(&AstarteType::Integer(42), None).unwrap();
+        let (_, t) = deserialize_with_timestamp(&buf).unwrap();
+        assert_eq!(t, None);
+
+        let (data, t) = deserialize_with_timestamp(&[]).unwrap();
+        assert_eq!(data, Aggregation::Unset);
+        assert_eq!(t, None);
+
+        let buf = serialize_individual(&AstarteType::Unset, None).unwrap();
+        assert_eq!(deserialize(&buf).unwrap(), Aggregation::Unset);
+    }
+
+    #[test]
+    fn test_deserialize_empty_arrays() {
+        let buf = serialize_individual(&AstarteType::DateTimeArray(Vec::new()), None).unwrap();
+
+        assert!(deserialize(&buf).is_err());
+
+        let (data, _) = deserialize_typed(&buf, |_| Some(MappingType::DateTimeArray)).unwrap();
+        assert_eq!(
+            data,
+            Aggregation::Individual(AstarteType::DateTimeArray(Vec::new()))
+        );
+
+        let object = HashMap::from([
+            (
+                "blobs".to_string(),
+                AstarteType::BinaryBlobArray(Vec::new()),
+            ),
+            ("times".to_string(), AstarteType::DateTimeArray(Vec::new())),
+        ]);
+        let buf = serialize_object(&object, None).unwrap();
+
+        let (data, _) = deserialize_typed(&buf, |field| match field {
+            Some("blobs") => Some(MappingType::BinaryBlobArray),
+            Some("times") => Some(MappingType::DateTimeArray),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(data, Aggregation::Object(object));
+
+        // the mapping is not an array
+        assert!(deserialize_typed(&buf, |_| Some(MappingType::DateTime)).is_err());
+    }
+
     #[test]
     fn test_bson_serialization() {
         let og_value = AstarteType::LongInteger(3600);
@@ -269,4 +591,69 @@ mod test {
             panic!("Deserialization in not individual");
         }
     }
+
+    #[test]
+    fn test_write_same_as_bson() {
+        let time = |secs, nanos| TimeZone::timestamp_opt(&Utc, secs, nanos).unwrap();
+
+        let alltypes = [
+            AstarteType::Double(-4.5),
+            AstarteType::Integer(i32::MIN),
+            AstarteType::Boolean(false),
+            AstarteType::LongInteger(i64::MAX),
+            AstarteType::String("hello\0world".into()),
+            AstarteType::BinaryBlob(Vec::new()),
+            AstarteType::DateTime(time(1627580808, 123_456_789)),
+            AstarteType::DoubleArray(Vec::new()),
+            AstarteType::IntegerArray((0..12).collect()),
+            AstarteType::BooleanArray(vec![true, false]),
+            AstarteType::LongIntegerArray(vec![-1, 45543543534]),
+            AstarteType::StringArray(vec!["".to_owned(), "world".to_owned()]),
+            AstarteType::BinaryBlobArray(vec![b"hello".to_vec(), Vec::new()]),
+            AstarteType::DateTimeArray(vec![time(-1, 0), time(1627580809, 1_000_000)]),
+            AstarteType::Unset,
+        ];
+
+        let timestamps = [None, Some(time(1627580808, 0)), Some(time(1, 120_000))];
+
+        for timestamp in timestamps {
+            for value in &alltypes {
+                let expected = bson::to_vec(&Payload { value, timestamp }).unwrap();
+
+                assert_eq!(
+                    serialize_individual(value, timestamp).unwrap(),
+                    expected,
+                    "different encoding of {value:?} at {timestamp:?}"
+                );
+            }
+
+            let object: HashMap<String, AstarteType> = alltypes
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (format!("field{i}"), value.clone()))
+                .collect();
+
+            let expected = bson::to_vec(&Payload {
+                value: &object,
+                timestamp,
+            })
+            .unwrap();
+            assert_eq!(serialize_object(&object, timestamp).unwrap(), expected);
+        }
+
+        // the buffer is appended to
+        let mut buf = vec![1, 2, 3];
+        write_individual(&mut buf, &AstarteType::Integer(4), None).unwrap();
+        assert_eq!(buf[..3], [1, 2, 3]);
+        assert_eq!(
+            buf[3..],
+            serialize_individual(&AstarteType::Integer(4), None).unwrap()
+        );
+
+        let object = HashMap::from([("in\0valid".to_string(), AstarteType::Integer(1))]);
+        assert!(matches!(
+            serialize_object(&object, None),
+            Err(PayloadError::Serialize(bson::ser::Error::InvalidCString(_)))
+        ));
+    }
 }
@@ -0,0 +1,201 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pool of the buffers used to serialize the payloads.
+//!
+//! The payloads are serialized in a buffer taken from the pool and returned to it after the
+//! publish, so the sends don't allocate a new buffer each time, see
+//! [`buffer_pool_stats()`](crate::AstarteDeviceSdk::buffer_pool_stats).
+
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Default number of buffers kept in the pool.
+pub(crate) const DEFAULT_BUFFER_POOL: usize = 4;
+
+/// Buffers bigger than this are freed instead of being returned to the pool, so a single big
+/// payload doesn't stay allocated.
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+/// Counters of the buffer pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Buffers reused from the pool.
+    pub hits: u64,
+    /// Buffers allocated because the pool was empty.
+    pub misses: u64,
+    /// Buffers currently in the pool.
+    pub pooled: usize,
+}
+
+impl PoolStats {
+    /// Fraction of the buffers reused from the pool, `None` if no buffer was requested.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+
+        (total > 0).then(|| self.hits as f64 / total as f64)
+    }
+}
+
+/// Bounded pool of byte buffers.
+#[derive(Debug)]
+pub(crate) struct BufferPool {
+    size: usize,
+    buffers: Mutex<Vec<Vec<u8>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl BufferPool {
+    /// Creates a pool keeping at most `size` buffers, with zero every buffer is freed after use.
+    pub(crate) fn new(size: usize) -> Self {
+        Self {
+            size,
+            buffers: Mutex::new(Vec::with_capacity(size)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Takes an empty buffer from the pool, it's returned when dropped.
+    pub(crate) fn get(&self) -> PooledBuffer<'_> {
+        let buf = self
+            .buffers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+
+        let buf = match buf {
+            Some(buf) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+
+                buf
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+
+                Vec::new()
+            }
+        };
+
+        PooledBuffer { pool: self, buf }
+    }
+
+    fn put(&self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || buf.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+
+        let mut buffers = self.buffers.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if buffers.len() < self.size {
+            buf.clear();
+            buffers.push(buf);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            pooled: self
+                .buffers
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .len(),
+        }
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUFFER_POOL)
+    }
+}
+
+/// Buffer taken from a [`BufferPool`].
+#[derive(Debug)]
+pub(crate) struct PooledBuffer<'a> {
+    pool: &'a BufferPool,
+    buf: Vec<u8>,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        self.pool.put(std::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::new(1);
+        assert_eq!(pool.stats().hit_rate(), None);
+
+        {
+            let mut first = pool.get();
+            first.extend_from_slice(b"payload");
+
+            // the second buffer doesn't fit in the pool
+            let mut second = pool.get();
+            second.push(1);
+        }
+
+        let buf = pool.get();
+        assert!(buf.is_empty());
+        assert!(buf.capacity() > 0);
+        drop(buf);
+
+        let mut big = pool.get();
+        big.reserve(MAX_POOLED_CAPACITY + 1);
+        drop(big);
+
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                hits: 2,
+                misses: 2,
+                pooled: 0,
+            }
+        );
+        assert_eq!(pool.stats().hit_rate(), Some(0.5));
+
+        let pool = BufferPool::new(0);
+        pool.get().push(1);
+        assert_eq!(pool.get().capacity(), 0);
+        assert_eq!(pool.stats().misses, 2);
+    }
+}
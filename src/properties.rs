@@ -18,7 +18,9 @@
 
 //! Handles the properties for the device.
 
-use flate2::bufread::ZlibDecoder;
+use std::io::Write;
+
+use flate2::{bufread::ZlibDecoder, write::ZlibEncoder, Compression};
 use log::error;
 
 /// Error handling the properties.
@@ -34,12 +36,21 @@ pub enum PropertiesError {
     /// Error decoding the zlib compressed payload.
     #[error("error decoding the zlib compressed payload")]
     Decode(#[from] std::io::Error),
+    /// The decoded payload is bigger than the maximum event size.
+    #[error("the decoded payload is {size} bytes, bigger than the maximum of {max}")]
+    TooLarge { size: usize, max: usize },
 }
 
 /// Extracts the properties from a set payload.
 ///
+/// The payload is rejected without being decoded if the declared size is bigger than the
+/// maximum, and the decoding stops at the maximum if the declared size is wrong.
+///
 /// See https://docs.astarte-platform.org/astarte/latest/080-mqtt-v1-protocol.html#purge-properties
-pub(crate) fn extract_set_properties(bdata: &[u8]) -> Result<Vec<String>, PropertiesError> {
+pub(crate) fn extract_set_properties(
+    bdata: &[u8],
+    max_size: Option<usize>,
+) -> Result<Vec<String>, PropertiesError> {
     use std::io::Read;
 
     if bdata.len() < 4 {
@@ -48,13 +59,30 @@ pub(crate) fn extract_set_properties(bdata: &[u8]) -> Result<Vec<String>, Proper
 
     let (size, data) = bdata.split_at(4);
     // The size is a u32 in big endian, so we need to convert it to usize
-    let size: u32 = u32::from_be_bytes([size[0], size[1], size[2], size[3]]);
+    let size: [u8; 4] = size
+        .try_into()
+        .map_err(|_| PropertiesError::PayloadTooShort(bdata.len()))?;
+    let size: u32 = u32::from_be_bytes(size);
     let size: usize = size.try_into()?;
 
-    let mut d = ZlibDecoder::new(data);
+    let max = max_size.unwrap_or(usize::MAX);
+    if size > max {
+        return Err(PropertiesError::TooLarge { size, max });
+    }
+
+    // read one more byte to detect a payload bigger than the maximum
+    let limit = u64::try_from(max).unwrap_or(u64::MAX).saturating_add(1);
+    let mut d = ZlibDecoder::new(data).take(limit);
     let mut s = String::new();
     let bytes_read = d.read_to_string(&mut s)?;
 
+    if bytes_read > max {
+        return Err(PropertiesError::TooLarge {
+            size: bytes_read,
+            max,
+        });
+    }
+
     debug_assert_eq!(
         bytes_read, size,
         "Byte red and size mismatch: {} != {}",
@@ -68,6 +96,45 @@ pub(crate) fn extract_set_properties(bdata: &[u8]) -> Result<Vec<String>, Proper
     Ok(s.split(';').map(|x| x.to_string()).collect())
 }
 
+/// Encodes the payload of a purge properties message with the properties set on the device.
+///
+/// The properties are compressed while written, so the uncompressed payload is never kept in
+/// memory.
+///
+/// See https://docs.astarte-platform.org/astarte/latest/080-mqtt-v1-protocol.html#purge-properties
+pub(crate) fn encode_set_properties<I, S>(
+    properties: I,
+    compression: Compression,
+) -> Result<Vec<u8>, PropertiesError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    // The size is written after the compression
+    let mut encoder = ZlibEncoder::new(vec![0; 4], compression);
+    let mut size: usize = 0;
+
+    for (i, property) in properties.into_iter().enumerate() {
+        if i > 0 {
+            encoder.write_all(b";")?;
+            size += 1;
+        }
+
+        let property = property.as_ref().as_bytes();
+        encoder.write_all(property)?;
+        size += property.len();
+    }
+
+    let mut buf = encoder.finish()?;
+
+    let size: u32 = size.try_into()?;
+    if let Some(header) = buf.get_mut(..4) {
+        header.copy_from_slice(&size.to_be_bytes());
+    }
+
+    Ok(buf)
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
@@ -84,8 +151,48 @@ pub(crate) mod tests {
     fn test_deflate() {
         let example = b"com.example.MyInterface/some/path;org.example.DraftInterface/otherPath";
 
-        let s = extract_set_properties(&PROPERTIES_PAYLOAD).unwrap();
+        let s = extract_set_properties(&PROPERTIES_PAYLOAD, None).unwrap();
 
         assert_eq!(s.join(";").as_bytes(), example);
     }
+
+    #[test]
+    fn test_extract_too_large() {
+        assert!(matches!(
+            extract_set_properties(&PROPERTIES_PAYLOAD, Some(69)),
+            Err(PropertiesError::TooLarge { size: 70, max: 69 })
+        ));
+        assert!(extract_set_properties(&PROPERTIES_PAYLOAD, Some(70)).is_ok());
+
+        // the declared size is smaller than the compressed data
+        let mut payload = PROPERTIES_PAYLOAD;
+        payload[3] = 0x01;
+        assert!(matches!(
+            extract_set_properties(&payload, Some(10)),
+            Err(PropertiesError::TooLarge { size: 11, max: 10 })
+        ));
+    }
+
+    #[test]
+    fn test_encode_set_properties() {
+        let properties = [
+            "com.example.MyInterface/some/path",
+            "org.example.DraftInterface/otherPath",
+        ];
+
+        for level in [
+            Compression::none(),
+            Compression::fast(),
+            Compression::best(),
+        ] {
+            let buf = encode_set_properties(properties, level).unwrap();
+
+            assert_eq!(buf[..4], PROPERTIES_PAYLOAD[..4]);
+            assert_eq!(extract_set_properties(&buf, None).unwrap(), properties);
+        }
+
+        let buf = encode_set_properties(Vec::<String>::new(), Compression::default()).unwrap();
+        assert_eq!(buf[..4], [0, 0, 0, 0]);
+        assert_eq!(extract_set_properties(&buf, None).unwrap(), [""]);
+    }
 }
@@ -0,0 +1,433 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! First boot provisioning of a device.
+//!
+//! The [`Provisioner`] registers the device, stores the credentials secret, connects to Astarte
+//! and sends the initial values of the device properties. Each step is run only after the
+//! previous one completed, and a failed step can be run again without repeating the others.
+//!
+//! A device with a credentials secret already in the [`CredentialsStore`] is not registered
+//! again, so the same flow can be run on every boot.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{
+//!     options::AstarteOptions,
+//!     provisioning::{FileCredentials, Provisioner},
+//! };
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let sdk_options = AstarteOptions::new("realm", "device_id", "", "https://api.example.com/pairing")
+//!         .interface_directory("path/to/interfaces")
+//!         .unwrap();
+//!
+//!     let mut provisioner = Provisioner::new(
+//!         sdk_options,
+//!         "pairing_jwt",
+//!         FileCredentials::new("/var/lib/device/credentials_secret"),
+//!     )
+//!     .initial_property("com.example.DeviceInfo", "/serial", "SN-0001");
+//!
+//!     while let Err(err) = provisioner.run().await {
+//!         eprintln!("step {} failed: {err}", err.step());
+//!     }
+//!
+//!     let device = provisioner.into_device().unwrap();
+//! }
+//! ```
+
+use std::fmt::{self, Debug, Display};
+use std::io::Write;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use log::{debug, info};
+
+use crate::{
+    error::Error, options::AstarteOptions, pairing::PairingError, registration, types::AstarteType,
+    AstarteDeviceSdk,
+};
+
+/// Storage of the credentials secret of the device.
+#[async_trait]
+pub trait CredentialsStore: Send + Sync {
+    /// Returns the stored credentials secret, if any.
+    async fn load(&self) -> Result<Option<String>, std::io::Error>;
+
+    /// Stores the credentials secret, replacing the previous one.
+    async fn store(&self, credentials_secret: &str) -> Result<(), std::io::Error>;
+}
+
+/// Credentials secret stored in a file.
+///
+/// The file is replaced atomically, so a power loss while storing never leaves a partial secret.
+#[derive(Debug, Clone)]
+pub struct FileCredentials {
+    path: PathBuf,
+}
+
+impl FileCredentials {
+    /// Store the credentials secret in the file at the given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl CredentialsStore for FileCredentials {
+    async fn load(&self) -> Result<Option<String>, std::io::Error> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(secret) if secret.trim().is_empty() => Ok(None),
+            Ok(secret) => Ok(Some(secret.trim().to_string())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn store(&self, credentials_secret: &str) -> Result<(), std::io::Error> {
+        let tmp = self.path.with_extension("tmp");
+
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(credentials_secret.as_bytes())?;
+        file.sync_all()?;
+
+        std::fs::rename(tmp, &self.path)
+    }
+}
+
+/// Steps of the provisioning, in the order they are run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProvisioningStep {
+    /// Register the device, unless a credentials secret is already stored.
+    Register,
+    /// Store the credentials secret obtained with the registration.
+    StoreCredentials,
+    /// Connect to Astarte.
+    Connect,
+    /// Send the initial values of the device properties.
+    SyncProperties,
+    /// The provisioning is completed.
+    Done,
+}
+
+impl Display for ProvisioningStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let step = match self {
+            ProvisioningStep::Register => "register",
+            ProvisioningStep::StoreCredentials => "store credentials",
+            ProvisioningStep::Connect => "connect",
+            ProvisioningStep::SyncProperties => "sync properties",
+            ProvisioningStep::Done => "done",
+        };
+
+        write!(f, "{step}")
+    }
+}
+
+/// Error returned by a step of the provisioning.
+#[non_exhaustive]
+#[derive(thiserror::Error, Debug)]
+pub enum ProvisioningError {
+    /// Couldn't load the stored credentials secret.
+    #[error("couldn't load the credentials secret")]
+    LoadCredentials(#[source] std::io::Error),
+    /// Couldn't register the device.
+    #[error("couldn't register the device")]
+    Register(#[source] PairingError),
+    /// Couldn't store the credentials secret.
+    #[error("couldn't store the credentials secret")]
+    StoreCredentials(#[source] std::io::Error),
+    /// Couldn't connect to Astarte.
+    #[error("couldn't connect to Astarte")]
+    Connect(#[source] Error),
+    /// Couldn't send the initial value of a property.
+    #[error("couldn't send the property {interface}{path}")]
+    SyncProperty {
+        interface: String,
+        path: String,
+        #[source]
+        source: Error,
+    },
+}
+
+impl ProvisioningError {
+    /// Returns the step that failed.
+    pub fn step(&self) -> ProvisioningStep {
+        match self {
+            ProvisioningError::LoadCredentials(_) | ProvisioningError::Register(_) => {
+                ProvisioningStep::Register
+            }
+            ProvisioningError::StoreCredentials(_) => ProvisioningStep::StoreCredentials,
+            ProvisioningError::Connect(_) => ProvisioningStep::Connect,
+            ProvisioningError::SyncProperty { .. } => ProvisioningStep::SyncProperties,
+        }
+    }
+}
+
+/// Resumable first boot provisioning of a device.
+pub struct Provisioner {
+    opts: AstarteOptions,
+    pairing_token: String,
+    credentials: Box<dyn CredentialsStore>,
+    initial_properties: Vec<(String, String, AstarteType)>,
+    step: ProvisioningStep,
+    credentials_secret: Option<String>,
+    /// Number of initial properties already sent.
+    synced: usize,
+    device: Option<AstarteDeviceSdk>,
+}
+
+impl Provisioner {
+    /// Create a provisioner for the device configured by the options.
+    ///
+    /// The credentials secret in the options is ignored, the pairing token is used to register
+    /// the device.
+    pub fn new<C>(opts: AstarteOptions, pairing_token: &str, credentials: C) -> Self
+    where
+        C: CredentialsStore + 'static,
+    {
+        Self {
+            opts,
+            pairing_token: pairing_token.to_string(),
+            credentials: Box::new(credentials),
+            initial_properties: Vec::new(),
+            step: ProvisioningStep::Register,
+            credentials_secret: None,
+            synced: 0,
+            device: None,
+        }
+    }
+
+    /// Value of a device property sent once connected.
+    ///
+    /// A value equal to the stored one is not sent again.
+    pub fn initial_property<D>(mut self, interface: &str, path: &str, value: D) -> Self
+    where
+        D: Into<AstarteType>,
+    {
+        self.initial_properties
+            .push((interface.to_string(), path.to_string(), value.into()));
+
+        self
+    }
+
+    /// Returns the next step to run.
+    pub fn step(&self) -> ProvisioningStep {
+        self.step
+    }
+
+    /// Runs the next step, returning the step that was completed.
+    ///
+    /// If the step fails it's run again on the next call.
+    pub async fn run_step(&mut self) -> Result<ProvisioningStep, ProvisioningError> {
+        let step = self.step;
+
+        self.step = match step {
+            ProvisioningStep::Register => self.register().await?,
+            ProvisioningStep::StoreCredentials => self.store_credentials().await?,
+            ProvisioningStep::Connect => self.connect().await?,
+            ProvisioningStep::SyncProperties => self.sync_properties().await?,
+            ProvisioningStep::Done => ProvisioningStep::Done,
+        };
+
+        Ok(step)
+    }
+
+    /// Runs all the remaining steps, stopping at the first failure.
+    pub async fn run(&mut self) -> Result<(), ProvisioningError> {
+        while self.step != ProvisioningStep::Done {
+            let step = self.run_step().await?;
+
+            info!("provisioning step {step} completed");
+        }
+
+        Ok(())
+    }
+
+    /// Returns the device once connected.
+    pub fn device(&self) -> Option<&AstarteDeviceSdk> {
+        self.device.as_ref()
+    }
+
+    /// Returns the device once connected.
+    pub fn into_device(self) -> Option<AstarteDeviceSdk> {
+        self.device
+    }
+
+    async fn register(&mut self) -> Result<ProvisioningStep, ProvisioningError> {
+        let stored = self
+            .credentials
+            .load()
+            .await
+            .map_err(ProvisioningError::LoadCredentials)?;
+
+        if let Some(secret) = stored {
+            debug!("device already registered");
+
+            self.credentials_secret = Some(secret);
+
+            return Ok(ProvisioningStep::Connect);
+        }
+
+        let secret = registration::register_device(
+            &self.pairing_token,
+            &self.opts.pairing_url,
+            &self.opts.realm,
+            &self.opts.device_id,
+        )
+        .await
+        .map_err(ProvisioningError::Register)?;
+
+        self.credentials_secret = Some(secret);
+
+        Ok(ProvisioningStep::StoreCredentials)
+    }
+
+    async fn store_credentials(&mut self) -> Result<ProvisioningStep, ProvisioningError> {
+        // the secret is always set by the registration
+        if let Some(secret) = &self.credentials_secret {
+            self.credentials
+                .store(secret)
+                .await
+                .map_err(ProvisioningError::StoreCredentials)?;
+        }
+
+        Ok(ProvisioningStep::Connect)
+    }
+
+    async fn connect(&mut self) -> Result<ProvisioningStep, ProvisioningError> {
+        let mut opts = self.opts.clone();
+        opts.credentials_secret = self.credentials_secret.clone().unwrap_or_default();
+
+        let device = AstarteDeviceSdk::new(opts)
+            .await
+            .map_err(ProvisioningError::Connect)?;
+
+        self.device = Some(device);
+
+        Ok(ProvisioningStep::SyncProperties)
+    }
+
+    async fn sync_properties(&mut self) -> Result<ProvisioningStep, ProvisioningError> {
+        let Some(device) = &self.device else {
+            return Ok(ProvisioningStep::Connect);
+        };
+
+        for (interface, path, value) in self.initial_properties.iter().skip(self.synced) {
+            device
+                .send(interface, path, value.clone())
+                .await
+                .map_err(|source| ProvisioningError::SyncProperty {
+                    interface: interface.clone(),
+                    path: path.clone(),
+                    source,
+                })?;
+
+            self.synced += 1;
+        }
+
+        Ok(ProvisioningStep::Done)
+    }
+}
+
+impl Debug for Provisioner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Provisioner")
+            .field("opts", &self.opts)
+            .field("pairing_token", &"REDACTED")
+            .field("initial_properties", &self.initial_properties)
+            .field("step", &self.step)
+            .field("synced", &self.synced)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct MemoryCredentials {
+        secret: Arc<Mutex<Option<String>>>,
+    }
+
+    #[async_trait]
+    impl CredentialsStore for MemoryCredentials {
+        async fn load(&self) -> Result<Option<String>, std::io::Error> {
+            Ok(self.secret.lock().unwrap().clone())
+        }
+
+        async fn store(&self, credentials_secret: &str) -> Result<(), std::io::Error> {
+            *self.secret.lock().unwrap() = Some(credentials_secret.to_string());
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_credentials() {
+        let dir = tempfile::tempdir().unwrap();
+        let credentials = FileCredentials::new(dir.path().join("secret"));
+
+        assert_eq!(credentials.load().await.unwrap(), None);
+
+        credentials.store("secret").await.unwrap();
+        credentials.store("new secret").await.unwrap();
+
+        assert_eq!(
+            credentials.load().await.unwrap(),
+            Some("new secret".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resume_failed_step() {
+        let credentials = MemoryCredentials::default();
+        credentials.store("secret").await.unwrap();
+
+        let opts = AstarteOptions::new("realm", "device_id", "", "not a url");
+        let mut provisioner = Provisioner::new(opts, "token", credentials).initial_property(
+            "com.test.Device",
+            "/serial",
+            "SN-0001",
+        );
+
+        // already registered
+        assert_eq!(
+            provisioner.run_step().await.unwrap(),
+            ProvisioningStep::Register
+        );
+        assert_eq!(provisioner.step(), ProvisioningStep::Connect);
+        assert_eq!(provisioner.credentials_secret.as_deref(), Some("secret"));
+
+        for _ in 0..2 {
+            let err = provisioner
+                .run()
+                .await
+                .expect_err("connected to an invalid url");
+            assert!(matches!(err, ProvisioningError::Connect(_)), "{err:?}");
+            assert_eq!(err.step(), ProvisioningStep::Connect);
+            assert_eq!(provisioner.step(), ProvisioningStep::Connect);
+        }
+
+        assert!(provisioner.device().is_none());
+    }
+}
@@ -0,0 +1,256 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Estimate of the quality of the connection to the broker.
+//!
+//! The device measures the time between each publish with QoS 1 or 2 and its acknowledgment, the
+//! publishes never acknowledged and the connections lost in the last hour. They are combined in
+//! a score returned by
+//! [`AstarteDeviceSdk::connection_quality`](crate::AstarteDeviceSdk::connection_quality), from 1
+//! on a good link down to 0.
+//!
+//! A [`Buffer`](crate::buffer::Buffer) can send bigger batches as the quality degrades, see
+//! [`FlushPolicy::adapt_to_quality`](crate::buffer::FlushPolicy::adapt_to_quality).
+//!
+//! ```no_run
+//! use astarte_device_sdk::AstarteDeviceSdk;
+//!
+//! fn report(device: &AstarteDeviceSdk) {
+//!     let quality = device.connection_quality();
+//!
+//!     println!(
+//!         "quality {:.2}, latency {:?}, {:.0}% lost, {} reconnects",
+//!         quality.score,
+//!         quality.latency,
+//!         quality.ack_loss * 100.0,
+//!         quality.reconnects
+//!     );
+//! }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Weight of the last measure in the moving averages.
+const SMOOTHING: f64 = 0.2;
+
+/// Time after which a publish not acknowledged is considered lost.
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Interval over which the lost connections are counted.
+const RECONNECT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Latency halving the score.
+const REFERENCE_LATENCY: Duration = Duration::from_millis(500);
+
+/// Lost connections in the window halving the score.
+const REFERENCE_RECONNECTS: f64 = 3.0;
+
+/// Quality of the connection to the broker.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionQuality {
+    /// Score from 0 to 1, with 1 for a good link or if nothing was measured yet.
+    pub score: f64,
+    /// Moving average of the time to acknowledge a publish.
+    pub latency: Option<Duration>,
+    /// Moving average of the fraction of publishes not acknowledged, from 0 to 1.
+    pub ack_loss: f64,
+    /// Connections lost in the last hour.
+    pub reconnects: usize,
+}
+
+impl Default for ConnectionQuality {
+    fn default() -> Self {
+        Self {
+            score: 1.0,
+            latency: None,
+            ack_loss: 0.0,
+            reconnects: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Publishes waiting for the acknowledgment, by packet id.
+    in_flight: HashMap<u16, Instant>,
+    latency: Option<f64>,
+    ack_loss: f64,
+    lost_connections: VecDeque<Instant>,
+}
+
+impl State {
+    fn lost(&mut self) {
+        self.ack_loss += SMOOTHING * (1.0 - self.ack_loss);
+    }
+
+    /// Counts as lost the publishes waiting for longer than the timeout.
+    fn expire(&mut self, now: Instant) {
+        let before = self.in_flight.len();
+        self.in_flight
+            .retain(|_, sent| now.saturating_duration_since(*sent) < ACK_TIMEOUT);
+
+        for _ in self.in_flight.len()..before {
+            self.lost();
+        }
+
+        while let Some(lost) = self.lost_connections.front() {
+            if now.saturating_duration_since(*lost) < RECONNECT_WINDOW {
+                break;
+            }
+
+            self.lost_connections.pop_front();
+        }
+    }
+}
+
+/// Measures the quality of the connection from the MQTT events.
+#[derive(Debug, Default)]
+pub(crate) struct QualityEstimator {
+    state: Mutex<State>,
+}
+
+impl QualityEstimator {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Records a publish sent to the broker, the ones with QoS 0 have a packet id of 0.
+    pub(crate) fn published(&self, pkid: u16) {
+        self.published_at(pkid, Instant::now());
+    }
+
+    fn published_at(&self, pkid: u16, now: Instant) {
+        if pkid == 0 {
+            return;
+        }
+
+        let mut state = self.lock();
+
+        state.expire(now);
+        state.in_flight.insert(pkid, now);
+    }
+
+    /// Records the first acknowledgment of a publish, the `PUBACK` or the `PUBREC`.
+    pub(crate) fn acked(&self, pkid: u16) {
+        self.acked_at(pkid, Instant::now());
+    }
+
+    fn acked_at(&self, pkid: u16, now: Instant) {
+        let mut state = self.lock();
+
+        let Some(sent) = state.in_flight.remove(&pkid) else {
+            return;
+        };
+
+        let latency = now.saturating_duration_since(sent).as_secs_f64();
+        state.latency = Some(match state.latency {
+            Some(average) => average + SMOOTHING * (latency - average),
+            None => latency,
+        });
+        state.ack_loss -= SMOOTHING * state.ack_loss;
+    }
+
+    /// Records a connection lost, the publishes waiting for the acknowledgment are lost.
+    pub(crate) fn connection_lost(&self) {
+        self.connection_lost_at(Instant::now());
+    }
+
+    fn connection_lost_at(&self, now: Instant) {
+        let mut state = self.lock();
+
+        for _ in 0..state.in_flight.len() {
+            state.lost();
+        }
+        state.in_flight.clear();
+
+        state.lost_connections.push_back(now);
+        state.expire(now);
+    }
+
+    /// Returns the current estimate.
+    pub(crate) fn get(&self) -> ConnectionQuality {
+        self.get_at(Instant::now())
+    }
+
+    fn get_at(&self, now: Instant) -> ConnectionQuality {
+        let mut state = self.lock();
+
+        state.expire(now);
+
+        let latency = state.latency.map(Duration::from_secs_f64);
+        let reconnects = state.lost_connections.len();
+
+        let reference = REFERENCE_LATENCY.as_secs_f64();
+        let latency_factor = state
+            .latency
+            .map_or(1.0, |latency| reference / (reference + latency));
+        let reconnect_factor = REFERENCE_RECONNECTS / (REFERENCE_RECONNECTS + reconnects as f64);
+        let score = latency_factor * (1.0 - state.ack_loss) * reconnect_factor;
+
+        ConnectionQuality {
+            score: score.clamp(0.0, 1.0),
+            latency,
+            ack_loss: state.ack_loss,
+            reconnects,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quality() {
+        let quality = QualityEstimator::default();
+        let start = Instant::now();
+
+        assert_eq!(quality.get_at(start), ConnectionQuality::default());
+
+        // QoS 0 publishes and unknown acks are ignored
+        quality.published_at(0, start);
+        quality.acked_at(7, start);
+        assert_eq!(quality.get_at(start), ConnectionQuality::default());
+
+        quality.published_at(1, start);
+        quality.acked_at(1, start + REFERENCE_LATENCY);
+
+        let good = quality.get_at(start + REFERENCE_LATENCY);
+        assert_eq!(good.latency, Some(REFERENCE_LATENCY));
+        assert!((good.score - 0.5).abs() < 1e-9, "{}", good.score);
+
+        // a publish not acknowledged in time is lost
+        quality.published_at(2, start);
+        let timeout = quality.get_at(start + ACK_TIMEOUT);
+        assert!((timeout.ack_loss - SMOOTHING).abs() < 1e-9);
+        assert!(timeout.score < good.score);
+
+        quality.published_at(3, start + ACK_TIMEOUT);
+        quality.connection_lost_at(start + ACK_TIMEOUT);
+        let lost = quality.get_at(start + ACK_TIMEOUT);
+        assert_eq!(lost.reconnects, 1);
+        assert!(lost.ack_loss > timeout.ack_loss);
+        assert!(lost.score < timeout.score);
+
+        // the lost connections are forgotten after the window
+        let later = quality.get_at(start + ACK_TIMEOUT + RECONNECT_WINDOW);
+        assert_eq!(later.reconnects, 0);
+    }
+}
@@ -0,0 +1,294 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Snapshot of the messages waiting to be published.
+//!
+//! The snapshot is a read-only copy of the counters, so it can be taken periodically to show the
+//! amount of data waiting to be synchronized, see
+//! [`queue_snapshot()`](crate::AstarteDeviceSdk::queue_snapshot).
+
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+/// Interval over which the throughput is measured.
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Messages of an interface waiting to be published.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterfaceQueue {
+    /// Number of messages.
+    pub count: usize,
+    /// Size of the payloads in bytes.
+    pub bytes: usize,
+    /// Timestamp of the oldest message.
+    ///
+    /// It's the explicit timestamp of the message if present, otherwise the time the message was
+    /// kept in the retention. Messages in the outbox without an explicit timestamp are not
+    /// considered.
+    pub oldest: Option<DateTime<Utc>>,
+}
+
+impl InterfaceQueue {
+    pub(crate) fn push(&mut self, bytes: usize, timestamp: Option<DateTime<Utc>>) {
+        self.count += 1;
+        self.bytes += bytes;
+        self.oldest = match (self.oldest, timestamp) {
+            (Some(oldest), Some(timestamp)) => Some(oldest.min(timestamp)),
+            (oldest, timestamp) => oldest.or(timestamp),
+        };
+    }
+}
+
+/// Snapshot of the messages waiting to be published.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueueSnapshot {
+    /// Pending messages for each interface.
+    pub interfaces: HashMap<String, InterfaceQueue>,
+    /// Bytes published per second, measured over the last seconds.
+    ///
+    /// It's `None` if nothing was published recently.
+    pub throughput: Option<f64>,
+}
+
+impl QueueSnapshot {
+    /// Total number of pending messages.
+    pub fn count(&self) -> usize {
+        self.interfaces.values().map(|queue| queue.count).sum()
+    }
+
+    /// Total size of the pending payloads in bytes.
+    pub fn bytes(&self) -> usize {
+        self.interfaces.values().map(|queue| queue.bytes).sum()
+    }
+
+    /// Timestamp of the oldest pending message.
+    pub fn oldest(&self) -> Option<DateTime<Utc>> {
+        self.interfaces
+            .values()
+            .filter_map(|queue| queue.oldest)
+            .min()
+    }
+
+    /// Estimated time to publish all the pending messages at the current throughput.
+    ///
+    /// It's `None` if the throughput is unknown.
+    pub fn estimated_replay(&self) -> Option<Duration> {
+        let throughput = self.throughput.filter(|throughput| *throughput > 0.0)?;
+
+        Some(Duration::from_secs_f64(self.bytes() as f64 / throughput))
+    }
+
+    pub(crate) fn push(&mut self, interface: &str, bytes: usize, timestamp: Option<DateTime<Utc>>) {
+        self.interfaces
+            .entry(interface.to_string())
+            .or_default()
+            .push(bytes, timestamp);
+    }
+}
+
+#[derive(Debug)]
+struct ThroughputWindow {
+    start: Instant,
+    bytes: usize,
+    /// Throughput of the previous window.
+    last: Option<f64>,
+}
+
+/// Measures the bytes published per second.
+#[derive(Debug)]
+pub(crate) struct Throughput {
+    window: Mutex<ThroughputWindow>,
+}
+
+impl Throughput {
+    pub(crate) fn new() -> Self {
+        Self {
+            window: Mutex::new(ThroughputWindow {
+                start: Instant::now(),
+                bytes: 0,
+                last: None,
+            }),
+        }
+    }
+
+    fn rotate(window: &mut ThroughputWindow, now: Instant) {
+        let elapsed = now.duration_since(window.start);
+
+        if elapsed < THROUGHPUT_WINDOW {
+            return;
+        }
+
+        // the previous window is stale if nothing was published for a whole window
+        window.last = (elapsed < THROUGHPUT_WINDOW * 2 && window.bytes > 0)
+            .then(|| window.bytes as f64 / elapsed.as_secs_f64());
+        window.start = now;
+        window.bytes = 0;
+    }
+
+    /// Records a published payload.
+    pub(crate) fn record(&self, bytes: usize) {
+        self.record_at(bytes, Instant::now());
+    }
+
+    /// Returns the bytes per second published in the last complete window.
+    pub(crate) fn get(&self) -> Option<f64> {
+        self.get_at(Instant::now())
+    }
+
+    fn record_at(&self, bytes: usize, now: Instant) {
+        let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+
+        Self::rotate(&mut window, now);
+
+        window.bytes += bytes;
+    }
+
+    fn get_at(&self, now: Instant) -> Option<f64> {
+        let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+
+        Self::rotate(&mut window, now);
+
+        window.last
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::database::AstarteSqliteDatabase;
+    use crate::message::MessageId;
+    use crate::mock::{MockAsyncClient, MockDevice, MockEventLoop};
+    use crate::outbox::OutboxIntent;
+    use crate::retention::VolatileItem;
+    use crate::types::AstarteType;
+
+    #[test]
+    fn test_snapshot() {
+        let ts = |secs| Utc.timestamp_opt(secs, 0).unwrap();
+
+        let mut snapshot = QueueSnapshot::default();
+        assert_eq!(snapshot.oldest(), None);
+        assert_eq!(snapshot.estimated_replay(), None);
+
+        snapshot.push("com.test.A", 100, None);
+        snapshot.push("com.test.A", 300, Some(ts(20)));
+        snapshot.push("com.test.B", 600, Some(ts(10)));
+
+        assert_eq!(
+            snapshot.interfaces["com.test.A"],
+            InterfaceQueue {
+                count: 2,
+                bytes: 400,
+                oldest: Some(ts(20)),
+            }
+        );
+        assert_eq!(snapshot.count(), 3);
+        assert_eq!(snapshot.bytes(), 1000);
+        assert_eq!(snapshot.oldest(), Some(ts(10)));
+
+        snapshot.throughput = Some(250.0);
+        assert_eq!(snapshot.estimated_replay(), Some(Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn test_throughput() {
+        let throughput = Throughput::new();
+        let start = Instant::now();
+
+        assert_eq!(throughput.get_at(start), None);
+
+        throughput.record_at(500, start);
+        throughput.record_at(500, start);
+        assert_eq!(throughput.get_at(start), None);
+
+        let first = start + THROUGHPUT_WINDOW;
+        let rate = throughput.get_at(first).unwrap();
+        assert!((95.0..=100.0).contains(&rate), "{rate}");
+
+        // nothing published in the last window
+        assert_eq!(throughput.get_at(first + THROUGHPUT_WINDOW), None);
+    }
+
+    #[tokio::test]
+    async fn test_queue_snapshot() {
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+
+        let timestamp = chrono::TimeZone::timestamp_opt(&chrono::Utc, 1537449422, 0).unwrap();
+        let datastream = "org.astarte-platform.test.Diagnostics";
+
+        let intent = OutboxIntent::individual(
+            datastream,
+            "/properties",
+            AstarteType::String("queued".to_string()),
+            Some(timestamp),
+        )
+        .unwrap();
+        let outbox_size = intent.payload.len();
+
+        let mut tx = db.begin().await.unwrap();
+        AstarteSqliteDatabase::enqueue(&mut tx, &intent)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let astarte = MockDevice::new(MockAsyncClient::default(), MockEventLoop::default())
+            .interfaces([])
+            .build();
+
+        let snapshot = astarte.queue_snapshot().await;
+        assert_eq!(snapshot, QueueSnapshot::default());
+
+        let retained_at = {
+            let mut volatile = astarte.volatile.lock().await;
+            let item = VolatileItem::new(
+                MessageId::new(),
+                datastream,
+                "/properties",
+                format!("realm/device_id/{datastream}/properties"),
+                rumqttc::QoS::AtLeastOnce,
+                vec![0; 10],
+                0,
+            );
+            let retained_at = item.retained_at;
+            volatile.push(item);
+
+            retained_at
+        };
+
+        let snapshot = astarte.queue_snapshot().await;
+        assert_eq!(snapshot.count(), 1);
+        assert_eq!(snapshot.bytes(), 10);
+        assert_eq!(snapshot.oldest(), Some(retained_at));
+
+        let snapshot = astarte.queue_snapshot_with_outbox(&db).await.unwrap();
+        assert_eq!(
+            snapshot.interfaces[datastream],
+            InterfaceQueue {
+                count: 2,
+                bytes: 10 + outbox_size,
+                oldest: Some(timestamp),
+            }
+        );
+        assert_eq!(snapshot.estimated_replay(), None);
+    }
+}
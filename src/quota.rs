@@ -0,0 +1,143 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage quotas of the interfaces, so a single chatty interface can't use the storage needed
+//! by the others.
+//!
+//! A [`StoreQuota`] limits the number of entries and the bytes of an interface. The quotas of the
+//! stored properties are enforced by the
+//! [`QuotaDatabase`](crate::database::quota::QuotaDatabase) wrapper, the ones of the messages
+//! kept in the volatile retention are set with
+//! [`AstarteOptions::retention_quota`](crate::options::AstarteOptions::retention_quota).
+//!
+//! When a quota is exceeded the new entry is rejected, or with [`QuotaPolicy::EvictOldest`] the
+//! oldest entries of the same interface are removed to make room for it. The usage of each
+//! interface is reported in a [`QuotaUsage`].
+//!
+//! ```no_run
+//! use astarte_device_sdk::{
+//!     database::{quota::QuotaDatabase, AstarteSqliteDatabase},
+//!     options::AstarteOptions,
+//!     quota::{QuotaPolicy, StoreQuota},
+//! };
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let database = AstarteSqliteDatabase::new("path/to/database/file.sqlite")
+//!         .await
+//!         .unwrap();
+//!
+//!     let database = QuotaDatabase::new(database)
+//!         .quota("com.example.Labels", StoreQuota::new().max_rows(1000).max_bytes(64 * 1024));
+//!
+//!     let sdk_options = AstarteOptions::new("_","_","_","_")
+//!         .database(database)
+//!         .retention_quota(
+//!             "com.example.Telemetry",
+//!             StoreQuota::new()
+//!                 .max_bytes(1024 * 1024)
+//!                 .policy(QuotaPolicy::EvictOldest),
+//!         );
+//! }
+//! ```
+
+/// What happens when an entry exceeds a [`StoreQuota`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuotaPolicy {
+    /// The new entry is rejected.
+    #[default]
+    Reject,
+    /// The oldest entries of the interface are removed, the new entry is rejected only if it
+    /// doesn't fit in the quota by itself.
+    EvictOldest,
+}
+
+/// Limits of the storage used by an interface, without limits if none is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreQuota {
+    max_rows: Option<usize>,
+    max_bytes: Option<usize>,
+    policy: QuotaPolicy,
+}
+
+impl StoreQuota {
+    /// Creates a quota without limits, rejecting the entries when a limit is exceeded.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the number of entries of the interface.
+    pub fn max_rows(mut self, rows: usize) -> Self {
+        self.max_rows = Some(rows);
+
+        self
+    }
+
+    /// Limits the size in bytes of the serialized entries of the interface.
+    pub fn max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = Some(bytes);
+
+        self
+    }
+
+    /// Sets what happens when an entry exceeds the quota.
+    pub fn policy(mut self, policy: QuotaPolicy) -> Self {
+        self.policy = policy;
+
+        self
+    }
+
+    /// Checks if the oldest entries are evicted when the quota is exceeded.
+    pub(crate) fn evicts(&self) -> bool {
+        self.policy == QuotaPolicy::EvictOldest
+    }
+
+    /// Checks if the rows and bytes fit in the quota.
+    pub(crate) fn fits(&self, rows: usize, bytes: usize) -> bool {
+        self.max_rows.map_or(true, |max| rows <= max)
+            && self.max_bytes.map_or(true, |max| bytes <= max)
+    }
+}
+
+/// Storage used by an interface and the entries rejected or evicted by its quota.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// Number of entries stored.
+    pub rows: usize,
+    /// Size in bytes of the entries stored.
+    pub bytes: usize,
+    /// Entries rejected since they exceeded the quota.
+    pub rejected: u64,
+    /// Entries removed to make room for the new ones.
+    pub evicted: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quota_fits() {
+        assert!(StoreQuota::new().fits(usize::MAX, usize::MAX));
+
+        let quota = StoreQuota::new().max_rows(2).max_bytes(10);
+        assert!(quota.fits(2, 10));
+        assert!(!quota.fits(3, 10));
+        assert!(!quota.fits(1, 11));
+    }
+}
@@ -0,0 +1,234 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Download the interfaces from a schema registry.
+//!
+//! The registry is an HTTP endpoint serving a signed bundle of interfaces, so the interfaces can
+//! be updated without a new firmware. The bundle is a JSON object with the interfaces:
+//!
+//! ```json
+//! { "interfaces": [{ "interface_name": "com.example.Sensor", "...": "..." }] }
+//! ```
+//!
+//! The response must have the [`SIGNATURE_HEADER`] with the base64 encoding of the DER ECDSA
+//! P-384 signature, with SHA-384, of the body. The bundle is rejected if the signature can't be
+//! verified with the public key of the registry.
+//!
+//! The interfaces are downloaded when the device connects with
+//! [`AstarteOptions::schema_registry`](crate::options::AstarteOptions::schema_registry), or on
+//! command with [`update_interfaces()`](crate::AstarteDeviceSdk::update_interfaces).
+
+use std::fmt::Debug;
+use std::time::Duration;
+
+use base64::Engine;
+use log::debug;
+use p384::ecdsa::{signature::Verifier, DerSignature, VerifyingKey};
+use p384::pkcs8::DecodePublicKey;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+
+use crate::Interface;
+
+/// Header of the response with the signature of the bundle.
+pub const SIGNATURE_HEADER: &str = "x-astarte-signature";
+
+/// Default timeout of the requests to the registry.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Errors downloading the interfaces from the registry.
+#[non_exhaustive]
+#[derive(thiserror::Error, Debug)]
+pub enum RegistryError {
+    #[error("invalid registry URL")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("invalid public key of the registry")]
+    PublicKey(#[from] p384::pkcs8::spki::Error),
+    #[error("error while sending or receiving request")]
+    Request(#[from] reqwest::Error),
+    #[error("the registry returned the status {0}")]
+    Status(StatusCode),
+    #[error("the bundle has no valid signature")]
+    MissingSignature,
+    #[error("the signature of the bundle doesn't match")]
+    Signature,
+    #[error("invalid interfaces bundle")]
+    Bundle(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Deserialize)]
+struct Bundle {
+    interfaces: Vec<Interface>,
+}
+
+/// Endpoint serving a signed bundle of interfaces.
+#[derive(Clone)]
+pub struct SchemaRegistry {
+    url: Url,
+    key: VerifyingKey,
+    timeout: Duration,
+}
+
+impl SchemaRegistry {
+    /// Creates a registry for the bundle at the URL, signed with the key of the PEM encoded
+    /// public key.
+    pub fn new(url: &str, public_key_pem: &str) -> Result<Self, RegistryError> {
+        Ok(Self {
+            url: Url::parse(url)?,
+            key: VerifyingKey::from_public_key_pem(public_key_pem)?,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Timeout of the request to the registry, 30 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+
+        self
+    }
+
+    /// Downloads the bundle and returns its interfaces, after verifying the signature.
+    pub async fn fetch(&self) -> Result<Vec<Interface>, RegistryError> {
+        debug!("fetching the interfaces from {}", self.url);
+
+        let client = reqwest::Client::builder().timeout(self.timeout).build()?;
+        let response = client.get(self.url.clone()).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(RegistryError::Status(status));
+        }
+
+        let signature = response
+            .headers()
+            .get(SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let body = response.bytes().await?;
+
+        self.verify(&body, signature.as_deref())
+    }
+
+    /// Verifies the signature of the bundle and parses it.
+    pub(crate) fn verify(
+        &self,
+        body: &[u8],
+        signature: Option<&str>,
+    ) -> Result<Vec<Interface>, RegistryError> {
+        let signature = signature
+            .and_then(|signature| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(signature.trim())
+                    .ok()
+            })
+            .and_then(|der| DerSignature::try_from(der.as_slice()).ok())
+            .ok_or(RegistryError::MissingSignature)?;
+
+        self.key
+            .verify(body, &signature)
+            .map_err(|_| RegistryError::Signature)?;
+
+        let bundle: Bundle = serde_json::from_slice(body)?;
+
+        Ok(bundle.interfaces)
+    }
+}
+
+impl Debug for SchemaRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SchemaRegistry")
+            .field("url", &self.url)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use p384::ecdsa::{signature::Signer, SigningKey};
+    use p384::pkcs8::{EncodePublicKey, LineEnding};
+
+    use super::*;
+
+    const BUNDLE: &str = r#"{
+        "interfaces": [{
+            "interface_name": "com.example.Sensor",
+            "version_major": 0,
+            "version_minor": 1,
+            "type": "datastream",
+            "ownership": "device",
+            "mappings": [{ "endpoint": "/value", "type": "double" }]
+        }]
+    }"#;
+
+    /// Returns a registry and the signature of the body.
+    fn signed(body: &[u8]) -> (SchemaRegistry, String) {
+        let key = SigningKey::random(&mut rand_core::OsRng);
+        let pem = key
+            .verifying_key()
+            .to_public_key_pem(LineEnding::LF)
+            .unwrap();
+
+        let signature: DerSignature = key.sign(body);
+        let signature = base64::engine::general_purpose::STANDARD.encode(signature.as_bytes());
+
+        let registry =
+            SchemaRegistry::new("https://registry.example.com/bundle.json", &pem).unwrap();
+
+        (registry, signature)
+    }
+
+    #[test]
+    fn test_verify() {
+        let (registry, signature) = signed(BUNDLE.as_bytes());
+
+        let interfaces = registry
+            .verify(BUNDLE.as_bytes(), Some(&signature))
+            .unwrap();
+        assert_eq!(interfaces.len(), 1);
+        assert_eq!(interfaces[0].interface_name(), "com.example.Sensor");
+
+        let tampered = BUNDLE.replace("double", "string");
+        assert!(matches!(
+            registry.verify(tampered.as_bytes(), Some(&signature)),
+            Err(RegistryError::Signature)
+        ));
+
+        for signature in [None, Some("not base64!"), Some("aGVsbG8=")] {
+            assert!(matches!(
+                registry.verify(BUNDLE.as_bytes(), signature),
+                Err(RegistryError::MissingSignature)
+            ));
+        }
+
+        let (registry, signature) = signed(b"{}");
+        assert!(matches!(
+            registry.verify(b"{}", Some(&signature)),
+            Err(RegistryError::Bundle(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        assert!(matches!(
+            SchemaRegistry::new("https://registry.example.com", "invalid"),
+            Err(RegistryError::PublicKey(_))
+        ));
+    }
+}
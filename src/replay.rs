@@ -0,0 +1,240 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Broadcast of the received events, replaying the last value of each path to new subscribers.
+//!
+//! The events returned by [`handle_events()`](crate::AstarteDeviceSdk::handle_events) are
+//! forwarded to an [`EventReplay`], which keeps the last event received on each interface and
+//! path. A new subscriber first receives the retained events, so the components started later
+//! see the current state of the server data, and then the events forwarded after the
+//! subscription. An unset removes the retained value of the path.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{replay::EventReplay, AstarteDeviceSdk};
+//! use futures::StreamExt;
+//!
+//! async fn run(mut device: AstarteDeviceSdk) {
+//!     let replay = EventReplay::new(64);
+//!
+//!     let mut events = replay.subscribe().into_stream().boxed();
+//!     tokio::spawn(async move {
+//!         while let Some(event) = events.next().await {
+//!             println!("{}{}: {:?}", event.interface, event.path, event.data);
+//!         }
+//!     });
+//!
+//!     while let Ok(event) = device.handle_events().await {
+//!         replay.send(event);
+//!     }
+//! }
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use futures::Stream;
+use log::warn;
+use tokio::sync::broadcast;
+
+use crate::AstarteDeviceDataEvent;
+
+/// Broadcast of the received events, retaining the last one of each path.
+#[derive(Debug)]
+pub struct EventReplay {
+    sender: broadcast::Sender<AstarteDeviceDataEvent>,
+    /// Last event of each interface and path, the lock is held while sending so a subscriber
+    /// doesn't miss or receive twice an event.
+    last: Mutex<BTreeMap<(String, String), AstarteDeviceDataEvent>>,
+}
+
+impl EventReplay {
+    /// Creates the broadcast, with the number of events buffered for the slow subscribers.
+    ///
+    /// A subscriber lagging more than the capacity skips the oldest events.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+
+        Self {
+            sender,
+            last: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<(String, String), AstarteDeviceDataEvent>> {
+        // the events are only inserted or removed while locked
+        self.last.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Retains the event and sends it to the subscribers.
+    pub fn send(&self, event: AstarteDeviceDataEvent) {
+        let mut last = self.lock();
+
+        let key = (event.interface.clone(), event.path.clone());
+        if event.data.is_unset() {
+            last.remove(&key);
+        } else {
+            last.insert(key, event.clone());
+        }
+
+        // there could be no subscribers
+        let _ = self.sender.send(event);
+    }
+
+    /// Returns the last event retained for the interface and path.
+    pub fn last(&self, interface: &str, path: &str) -> Option<AstarteDeviceDataEvent> {
+        self.lock()
+            .get(&(interface.to_string(), path.to_string()))
+            .cloned()
+    }
+
+    /// Subscribes to the events, starting with the ones retained.
+    pub fn subscribe(&self) -> ReplayReceiver {
+        let last = self.lock();
+
+        ReplayReceiver {
+            replay: last.values().cloned().collect(),
+            receiver: self.sender.subscribe(),
+        }
+    }
+}
+
+/// Subscriber of an [`EventReplay`].
+#[derive(Debug)]
+pub struct ReplayReceiver {
+    replay: VecDeque<AstarteDeviceDataEvent>,
+    receiver: broadcast::Receiver<AstarteDeviceDataEvent>,
+}
+
+impl ReplayReceiver {
+    /// Receives the next event, returns `None` once the [`EventReplay`] is dropped.
+    pub async fn recv(&mut self) -> Option<AstarteDeviceDataEvent> {
+        if let Some(event) = self.replay.pop_front() {
+            return Some(event);
+        }
+
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("replay subscriber lagging, skipped {skipped} events");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Converts the receiver into a [`Stream`] of events.
+    pub fn into_stream(self) -> impl Stream<Item = AstarteDeviceDataEvent> {
+        futures::stream::unfold(self, |mut receiver| async move {
+            let event = receiver.recv().await?;
+
+            Some((event, receiver))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use crate::types::AstarteType;
+    use crate::{Aggregation, EventMetadata};
+
+    use super::*;
+
+    fn event(path: &str, value: AstarteType) -> AstarteDeviceDataEvent {
+        AstarteDeviceDataEvent {
+            interface: "com.test.Server".to_string(),
+            path: path.to_string(),
+            data: Aggregation::Individual(value),
+            stale: false,
+            metadata: EventMetadata {
+                received_at: chrono::Utc::now(),
+                timestamp: None,
+                qos: rumqttc::QoS::AtLeastOnce,
+                retain: false,
+                duplicate: false,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_last_value() {
+        let replay = EventReplay::new(8);
+
+        replay.send(event("/a", AstarteType::Integer(1)));
+        replay.send(event("/a", AstarteType::Integer(2)));
+        replay.send(event("/b", AstarteType::Integer(3)));
+        replay.send(event("/c", AstarteType::Integer(4)));
+        replay.send(AstarteDeviceDataEvent {
+            data: Aggregation::Unset,
+            ..event("/c", AstarteType::Integer(4))
+        });
+
+        assert_eq!(
+            replay.last("com.test.Server", "/a").map(|event| event.data),
+            Some(Aggregation::Individual(AstarteType::Integer(2)))
+        );
+        assert!(replay.last("com.test.Server", "/c").is_none());
+
+        let mut receiver = replay.subscribe();
+        replay.send(event("/b", AstarteType::Integer(5)));
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let event = receiver.recv().await.unwrap();
+            received.push((event.path, event.data));
+        }
+
+        assert_eq!(
+            received,
+            [
+                (
+                    "/a".to_string(),
+                    Aggregation::Individual(AstarteType::Integer(2))
+                ),
+                (
+                    "/b".to_string(),
+                    Aggregation::Individual(AstarteType::Integer(3))
+                ),
+                (
+                    "/b".to_string(),
+                    Aggregation::Individual(AstarteType::Integer(5))
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_stream_lagged() {
+        let replay = EventReplay::new(1);
+
+        let receiver = replay.subscribe();
+        replay.send(event("/a", AstarteType::Integer(1)));
+        replay.send(event("/a", AstarteType::Integer(2)));
+        drop(replay);
+
+        // the lagging subscriber skips the oldest event, the stream ends with the broadcast
+        let events: Vec<_> = receiver.into_stream().collect().await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].data,
+            Aggregation::Individual(AstarteType::Integer(2))
+        );
+    }
+}
@@ -0,0 +1,596 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! In memory retention of the messages on mappings with volatile retention.
+//!
+//! The queue is owned by the device and not by the MQTT transport, so the messages are kept when
+//! the transport is dropped or recreated and sent once the device connects again. The messages
+//! handed to the transport are kept by the [delivery tracking](crate::delivery) until they are
+//! acknowledged, and moved back here if the transport is replaced before that.
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+
+use crate::database::AstarteDatabase;
+use crate::error::Error;
+use crate::message::{MessageId, MessageStage};
+use crate::quota::{QuotaPolicy, QuotaUsage, StoreQuota};
+use crate::AstarteDeviceSdk;
+
+/// Default maximum number of messages kept in the volatile retention.
+pub(crate) const DEFAULT_VOLATILE_CAPACITY: usize = 1000;
+
+/// Message that couldn't be published, waiting to be sent again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct VolatileItem {
+    pub(crate) id: MessageId,
+    pub(crate) interface: String,
+    pub(crate) path: String,
+    pub(crate) topic: String,
+    pub(crate) qos: rumqttc::QoS,
+    pub(crate) payload: Vec<u8>,
+    /// Instant after which the message should be discarded.
+    pub(crate) expiry: Option<DateTime<Utc>>,
+    /// Time the message was kept in the retention.
+    pub(crate) retained_at: DateTime<Utc>,
+}
+
+impl VolatileItem {
+    /// Create a new item, the expiry in seconds is given by the mapping and it's disabled if not
+    /// positive.
+    pub(crate) fn new(
+        id: MessageId,
+        interface: &str,
+        path: &str,
+        topic: String,
+        qos: rumqttc::QoS,
+        payload: Vec<u8>,
+        expiry: i32,
+    ) -> Self {
+        let retained_at = Utc::now();
+        let expiry = (expiry > 0).then(|| retained_at + chrono::Duration::seconds(expiry.into()));
+
+        Self {
+            id,
+            interface: interface.to_string(),
+            path: path.to_string(),
+            topic,
+            qos,
+            payload,
+            expiry,
+            retained_at,
+        }
+    }
+
+    fn is_expired(&self, now: &DateTime<Utc>) -> bool {
+        self.expiry.map_or(false, |expiry| expiry <= *now)
+    }
+}
+
+/// Bounded FIFO queue of the volatile messages.
+#[derive(Debug, Clone)]
+pub(crate) struct VolatileRetention {
+    items: VecDeque<VolatileItem>,
+    capacity: usize,
+    quotas: HashMap<String, StoreQuota>,
+    /// Number of rejected and evicted items of each interface.
+    discarded: HashMap<String, (u64, u64)>,
+}
+
+impl VolatileRetention {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            items: VecDeque::new(),
+            capacity,
+            quotas: HashMap::new(),
+            discarded: HashMap::new(),
+        }
+    }
+
+    /// Limit the items of an interface, removing the limit if `None`.
+    ///
+    /// The items already in the queue are kept even if they exceed the new quota.
+    pub(crate) fn set_quota(&mut self, interface: &str, quota: Option<StoreQuota>) {
+        match quota {
+            Some(quota) => {
+                self.quotas.insert(interface.to_string(), quota);
+            }
+            None => {
+                self.quotas.remove(interface);
+            }
+        }
+    }
+
+    /// Limit the number of items of an interface, discarding the oldest ones, or removing the
+    /// limit if `None`.
+    pub(crate) fn set_rows_quota(&mut self, interface: &str, rows: Option<usize>) {
+        let quota = rows.map(|rows| {
+            StoreQuota::new()
+                .max_rows(rows)
+                .policy(QuotaPolicy::EvictOldest)
+        });
+
+        self.set_quota(interface, quota);
+    }
+
+    /// Returns the items of an interface and the ones discarded.
+    pub(crate) fn usage(&self, interface: &str) -> QuotaUsage {
+        let (rows, bytes) = self
+            .items
+            .iter()
+            .filter(|item| item.interface == interface)
+            .fold((0, 0), |(rows, bytes), item| {
+                (rows + 1, bytes + item.payload.len())
+            });
+        let (rejected, evicted) = self.discarded.get(interface).copied().unwrap_or_default();
+
+        QuotaUsage {
+            rows,
+            bytes,
+            rejected,
+            evicted,
+        }
+    }
+
+    fn discard(&mut self, interface: &str, evicted: bool) {
+        let (rejected_count, evicted_count) =
+            self.discarded.entry(interface.to_string()).or_default();
+
+        if evicted {
+            *evicted_count += 1;
+        } else {
+            *rejected_count += 1;
+        }
+    }
+
+    /// Add an item at the end of the queue, the oldest item is discarded if the queue is full.
+    ///
+    /// If the item exceeds the quota of the interface it's rejected, or the oldest items of
+    /// the same interface are discarded instead with [`QuotaPolicy::EvictOldest`].
+    pub(crate) fn push(&mut self, item: VolatileItem) {
+        if self.capacity == 0 {
+            warn!(
+                "volatile retention disabled, discarding message {} on {}",
+                item.id, item.topic
+            );
+
+            return;
+        }
+
+        if let Some(quota) = self.quotas.get(&item.interface).copied() {
+            let usage = self.usage(&item.interface);
+            let mut rows = usage.rows + 1;
+            let mut bytes = usage.bytes + item.payload.len();
+
+            if !quota.fits(rows, bytes) && (!quota.evicts() || !quota.fits(1, item.payload.len())) {
+                warn!(
+                    "volatile retention quota of {} exceeded, discarding message {} on {}",
+                    item.interface, item.id, item.topic
+                );
+
+                self.discard(&item.interface, false);
+
+                return;
+            }
+
+            while !quota.fits(rows, bytes) {
+                let oldest = self
+                    .items
+                    .iter()
+                    .position(|queued| queued.interface == item.interface);
+
+                let Some(discarded) = oldest.and_then(|i| self.items.remove(i)) else {
+                    break;
+                };
+
+                warn!(
+                    "volatile retention quota of {} reached, discarding message {} on {}",
+                    discarded.interface, discarded.id, discarded.topic
+                );
+
+                rows -= 1;
+                bytes -= discarded.payload.len();
+                self.discard(&discarded.interface, true);
+            }
+        }
+
+        if self.items.len() >= self.capacity {
+            if let Some(discarded) = self.items.pop_front() {
+                warn!(
+                    "volatile retention full, discarding message {} on {}",
+                    discarded.id, discarded.topic
+                );
+
+                self.discard(&discarded.interface, true);
+            }
+        }
+
+        self.items.push_back(item);
+    }
+
+    /// Put back at the start of the queue the items that couldn't be sent.
+    pub(crate) fn restore(&mut self, items: impl DoubleEndedIterator<Item = VolatileItem>) {
+        for item in items.rev() {
+            if self.items.len() >= self.capacity {
+                break;
+            }
+
+            self.items.push_front(item);
+        }
+    }
+
+    /// Iterate over the items in the queue.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &VolatileItem> {
+        self.items.iter()
+    }
+
+    /// Keep only the items matching the predicate, returns the number of removed items.
+    pub(crate) fn retain<F>(&mut self, predicate: F) -> usize
+    where
+        F: FnMut(&VolatileItem) -> bool,
+    {
+        let len = self.items.len();
+
+        self.items.retain(predicate);
+
+        len - self.items.len()
+    }
+
+    /// Remove all the items from the queue, discarding the expired ones.
+    pub(crate) fn drain(&mut self) -> Vec<VolatileItem> {
+        let now = Utc::now();
+
+        self.items
+            .drain(..)
+            .filter(|item| !item.is_expired(&now))
+            .collect()
+    }
+}
+
+impl Default for VolatileRetention {
+    fn default() -> Self {
+        Self::new(DEFAULT_VOLATILE_CAPACITY)
+    }
+}
+
+impl<S> AstarteDeviceSdk<S>
+where
+    S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+{
+    /// Moves back to the retention the messages handed to a replaced transport, they won't be
+    /// sent by its event loop.
+    pub(crate) async fn restore_unacknowledged(&self) {
+        let lost = self.deliveries.connected(&self.eventloop);
+        if !lost.is_empty() {
+            debug!("{} volatile messages were not acknowledged", lost.len());
+
+            self.volatile.lock().await.restore(lost.into_iter());
+        }
+    }
+
+    /// Send again the messages kept in the volatile retention.
+    pub(crate) async fn send_volatile(&self) -> Result<(), Error> {
+        let mut volatile = self.volatile.lock().await;
+        let mut items = volatile.drain().into_iter();
+
+        if items.len() > 0 {
+            debug!("sending {} volatile messages", items.len());
+        }
+
+        while let Some(item) = items.next() {
+            // kept until the message is acknowledged
+            let mut retained = Some(item.clone());
+            let size = item.payload.len();
+
+            let res = self
+                .client_send(item.topic, item.qos, item.payload, &mut retained)
+                .await;
+
+            if let Err(err) = res {
+                let err = Error::publish(&item.interface, &item.path)(err);
+
+                volatile.restore(retained.into_iter().chain(items));
+
+                return Err(err);
+            }
+
+            self.throughput.record(size);
+
+            self.message_step(
+                item.id,
+                &item.interface,
+                &item.path,
+                MessageStage::Republished,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Keeps a message that couldn't be published, to send it again once connected.
+    pub(crate) async fn keep_volatile(&self, item: VolatileItem) {
+        let id = item.id;
+        let interface = item.interface.clone();
+        let path = item.path.clone();
+
+        self.volatile.lock().await.push(item);
+
+        self.message_step(id, &interface, &path, MessageStage::Retained);
+    }
+
+    /// Returns the messages of an interface in the volatile retention, and the ones discarded by
+    /// its [quota](crate::quota) or when the retention was full.
+    pub async fn retention_usage(&self, interface_name: &str) -> QuotaUsage {
+        self.volatile.lock().await.usage(interface_name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use mockall::predicate;
+    use rumqttc::Event;
+
+    use super::*;
+    use crate::mock::{MockAsyncClient, MockDevice, MockEventLoop};
+    use crate::test::VOLATILE_DATASTREAM;
+    use crate::types::AstarteType;
+    use crate::{payload, Interface};
+
+    fn item(topic: &str, expiry: i32) -> VolatileItem {
+        interface_item("com.test", topic, expiry)
+    }
+
+    fn interface_item(interface: &str, topic: &str, expiry: i32) -> VolatileItem {
+        VolatileItem::new(
+            MessageId::new(),
+            interface,
+            "/value",
+            topic.to_string(),
+            rumqttc::QoS::AtLeastOnce,
+            Vec::new(),
+            expiry,
+        )
+    }
+
+    #[test]
+    fn test_volatile_capacity() {
+        let mut retention = VolatileRetention::new(2);
+
+        retention.push(item("first", 0));
+        retention.push(item("second", 0));
+        retention.push(item("third", 0));
+
+        let topics: Vec<String> = retention.drain().into_iter().map(|i| i.topic).collect();
+        assert_eq!(topics, ["second", "third"]);
+        assert!(retention.drain().is_empty());
+    }
+
+    #[test]
+    fn test_volatile_quota() {
+        let mut retention = VolatileRetention::new(10);
+        retention.set_rows_quota("com.noisy", Some(2));
+        retention.set_rows_quota("com.disabled", Some(0));
+
+        retention.push(interface_item("com.noisy", "noisy1", 0));
+        retention.push(item("first", 0));
+        retention.push(interface_item("com.noisy", "noisy2", 0));
+        retention.push(interface_item("com.noisy", "noisy3", 0));
+        retention.push(interface_item("com.disabled", "disabled", 0));
+        retention.push(item("second", 0));
+
+        let topics: Vec<String> = retention.drain().into_iter().map(|i| i.topic).collect();
+        assert_eq!(topics, ["first", "noisy2", "noisy3", "second"]);
+
+        retention.set_rows_quota("com.noisy", None);
+        for topic in ["noisy1", "noisy2", "noisy3"] {
+            retention.push(interface_item("com.noisy", topic, 0));
+        }
+        assert_eq!(retention.iter().count(), 3);
+    }
+
+    #[test]
+    fn test_volatile_bytes_quota() {
+        let sized = |topic: &str, size: usize| {
+            let mut item = interface_item("com.noisy", topic, 0);
+            item.payload = vec![0; size];
+
+            item
+        };
+
+        let mut retention = VolatileRetention::new(10);
+        retention.set_quota("com.noisy", Some(StoreQuota::new().max_bytes(10)));
+
+        retention.push(sized("first", 6));
+        retention.push(sized("second", 6));
+        retention.push(item("other", 0));
+
+        let usage = retention.usage("com.noisy");
+        assert_eq!((usage.rows, usage.bytes, usage.rejected), (1, 6, 1));
+
+        retention.set_quota(
+            "com.noisy",
+            Some(
+                StoreQuota::new()
+                    .max_bytes(10)
+                    .policy(QuotaPolicy::EvictOldest),
+            ),
+        );
+        retention.push(sized("third", 4));
+        retention.push(sized("fourth", 5));
+        // bigger than the whole quota
+        retention.push(sized("fifth", 11));
+
+        assert_eq!(
+            retention.usage("com.noisy"),
+            QuotaUsage {
+                rows: 2,
+                bytes: 9,
+                rejected: 2,
+                evicted: 1,
+            }
+        );
+
+        let topics: Vec<String> = retention.drain().into_iter().map(|i| i.topic).collect();
+        assert_eq!(topics, ["other", "third", "fourth"]);
+    }
+
+    #[test]
+    fn test_volatile_expiry() {
+        let mut retention = VolatileRetention::default();
+
+        let mut expired = item("expired", 10);
+        expired.expiry = Some(Utc::now() - chrono::Duration::seconds(1));
+
+        retention.push(expired);
+        retention.push(item("valid", 10));
+        retention.push(item("no_expiry", 0));
+
+        let topics: Vec<String> = retention.drain().into_iter().map(|i| i.topic).collect();
+        assert_eq!(topics, ["valid", "no_expiry"]);
+    }
+
+    #[test]
+    fn test_volatile_restore() {
+        let mut retention = VolatileRetention::default();
+
+        retention.push(item("third", 0));
+        retention.restore([item("first", 0), item("second", 0)].into_iter());
+
+        let topics: Vec<String> = retention.drain().into_iter().map(|i| i.topic).collect();
+        assert_eq!(topics, ["first", "second", "third"]);
+    }
+
+    #[tokio::test]
+    async fn test_retention_usage() {
+        let datastream = "org.astarte-platform.test.VolatileDatastream";
+
+        let mut client = MockAsyncClient::default();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .times(3)
+            .returning(|_, _, _, _| {
+                Err(rumqttc::ClientError::Request(rumqttc::Request::Disconnect))
+            });
+
+        let astarte = MockDevice::new(client, MockEventLoop::default())
+            .interfaces([Interface::from_str(VOLATILE_DATASTREAM).unwrap()])
+            .build();
+
+        astarte.volatile.lock().await.set_quota(
+            datastream,
+            Some(
+                StoreQuota::new()
+                    .max_rows(2)
+                    .policy(QuotaPolicy::EvictOldest),
+            ),
+        );
+
+        for i in 0..3 {
+            astarte.send(datastream, "/value", i).await.unwrap();
+        }
+
+        let usage = astarte.retention_usage(datastream).await;
+        assert_eq!(usage.rows, 2);
+        assert_eq!(usage.evicted, 1);
+        assert_eq!(usage.rejected, 0);
+        assert!(usage.bytes > 0);
+
+        assert_eq!(
+            astarte.retention_usage("com.missing").await,
+            QuotaUsage::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_volatile_retention_connection_lost() {
+        let datastream = "org.astarte-platform.test.VolatileDatastream";
+        let topic = "realm/device_id/org.astarte-platform.test.VolatileDatastream/value";
+
+        let mut client = MockAsyncClient::default();
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .times(3)
+            .returning(|_, _, _, _| Ok(()));
+
+        let astarte = MockDevice::new(client, MockEventLoop::default())
+            .interfaces([Interface::from_str(VOLATILE_DATASTREAM).unwrap()])
+            .build();
+
+        for value in 1..=3 {
+            astarte.send(datastream, "/value", value).await.unwrap();
+        }
+
+        // only the first message is acknowledged, the second is in flight and the third queued
+        for event in [
+            Event::Outgoing(rumqttc::Outgoing::Publish(1)),
+            Event::Incoming(rumqttc::Packet::PubAck(rumqttc::PubAck::new(1))),
+            Event::Outgoing(rumqttc::Outgoing::Publish(2)),
+        ] {
+            assert!(astarte.handle_event(event).await.unwrap().is_none());
+        }
+
+        assert!(astarte.volatile.lock().await.iter().next().is_none());
+
+        // the connection is lost and the transport recreated, keeping the rest of the device state
+        let mut client = MockAsyncClient::default();
+        let mut seq = mockall::Sequence::new();
+        for value in [2, 3] {
+            let buf = payload::serialize_individual(&AstarteType::Integer(value), None).unwrap();
+
+            client
+                .expect_publish::<String, Vec<u8>>()
+                .once()
+                .in_sequence(&mut seq)
+                .with(
+                    predicate::eq(topic.to_string()),
+                    predicate::always(),
+                    predicate::always(),
+                    predicate::eq(buf),
+                )
+                .returning(|_, _, _, _| Ok(()));
+        }
+
+        let astarte = AstarteDeviceSdk {
+            client,
+            eventloop: Arc::new(tokio::sync::Mutex::new(MockEventLoop::default())),
+            ..astarte
+        };
+
+        astarte
+            .connack(rumqttc::ConnAck {
+                session_present: true,
+                code: rumqttc::ConnectReturnCode::Success,
+            })
+            .await
+            .unwrap();
+
+        // the messages are sent only once, while they wait for the acknowledgment
+        astarte
+            .connack(rumqttc::ConnAck {
+                session_present: true,
+                code: rumqttc::ConnectReturnCode::Success,
+            })
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Entry point owning the whole lifecycle of a device, for the applications that only react to
+//! the data received.
+//!
+//! [`run()`] connects the device and dispatches the events received to a [`DeviceHandler`],
+//! until the device is shut down. The connection errors are reported to the handler and the
+//! device reconnects after a delay, [`RECONNECT_DELAY`] if none is set in the
+//! [transport options](crate::options::AstarteOptions::transport). The other errors are reported
+//! too, and the next events are received after [`ERROR_BACKOFF`], doubled on each consecutive
+//! error up to [`RECONNECT_DELAY`]. With `feature = ["signals"]`
+//! the device is shut down on SIGTERM or SIGINT, unless another
+//! [shutdown signal](crate::options::AstarteOptions::shutdown_signal) is set.
+//!
+//! The data is sent with the device passed to the handler, which can be cloned to send from
+//! other tasks.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{
+//!     error::Error, options::AstarteOptions, run::DeviceHandler, AstarteDeviceDataEvent,
+//!     AstarteDeviceSdk,
+//! };
+//!
+//! struct Echo;
+//!
+//! #[async_trait::async_trait]
+//! impl DeviceHandler for Echo {
+//!     async fn on_event(
+//!         &mut self,
+//!         device: &AstarteDeviceSdk,
+//!         event: AstarteDeviceDataEvent,
+//!     ) -> Result<(), Error> {
+//!         println!("received {}{}", event.interface, event.path);
+//!
+//!         device.send("com.example.Echo", "/count", 1).await
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let sdk_options = AstarteOptions::new("_", "_", "_", "_");
+//!
+//!     astarte_device_sdk::run(sdk_options, Echo).await
+//! }
+//! ```
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::{error, info, warn};
+
+use crate::error::Error;
+use crate::options::AstarteOptions;
+use crate::transport::TransportOptions;
+use crate::{AstarteDeviceDataEvent, AstarteDeviceSdk, DeviceStatus};
+
+/// Delay before reconnecting after a connection error, if the options don't set one.
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Delay after an error receiving the events, other than a connection error.
+pub const ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Deadline to flush the pending messages when the device is shut down by [`run()`].
+pub const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(10);
+
+/// Application driven by [`run()`].
+#[async_trait]
+pub trait DeviceHandler: Send {
+    /// Called once the device is connected, before the events are dispatched.
+    ///
+    /// An error stops the device and it's returned by [`run()`].
+    async fn on_start(&mut self, _device: &AstarteDeviceSdk) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called for each event received.
+    ///
+    /// An error stops the device and it's returned by [`run()`].
+    async fn on_event(
+        &mut self,
+        device: &AstarteDeviceSdk,
+        event: AstarteDeviceDataEvent,
+    ) -> Result<(), Error>;
+
+    /// Called for the errors receiving the events, the device keeps running after a
+    /// [backoff](ERROR_BACKOFF).
+    async fn on_error(&mut self, _device: &AstarteDeviceSdk, error: &Error) {
+        warn!("error handling the events: {error}");
+    }
+}
+
+/// Connects the device and dispatches the events to the handler, returns when the device is
+/// shut down or on a fatal error.
+///
+/// See the [module documentation](crate::run) for the defaults applied to the options.
+pub async fn run<H>(mut options: AstarteOptions, handler: H) -> Result<(), Error>
+where
+    H: DeviceHandler,
+{
+    let mut transport = TransportOptions::new().reconnect_delay(RECONNECT_DELAY);
+    transport.merge(options.transport);
+    options.transport = transport;
+
+    #[cfg(feature = "signals")]
+    if options.shutdown_signal.is_none() {
+        options = options.shutdown_on_signals(SHUTDOWN_DEADLINE);
+    }
+
+    let device = AstarteDeviceSdk::new(options).await?;
+
+    supervise(device, handler).await
+}
+
+/// Dispatches the events of a connected device to the handler.
+pub(crate) async fn supervise<H>(mut device: AstarteDeviceSdk, mut handler: H) -> Result<(), Error>
+where
+    H: DeviceHandler,
+{
+    if let Err(err) = handler.on_start(&device).await {
+        return stop(&device, err).await;
+    }
+
+    let mut errors = 0;
+
+    loop {
+        match device.handle_events().await {
+            Ok(event) => {
+                errors = 0;
+
+                if let Err(err) = handler.on_event(&device, event).await {
+                    return stop(&device, err).await;
+                }
+            }
+            Err(Error::Terminated(reason)) => {
+                if *device.status().borrow() == DeviceStatus::Stopped {
+                    info!("device stopped");
+
+                    return Ok(());
+                }
+
+                return Err(Error::Terminated(reason));
+            }
+            Err(err) => {
+                handler.on_error(&device, &err).await;
+
+                // the connection errors already wait the reconnect delay
+                if !matches!(err, Error::ConnectionError(_)) {
+                    let delay = error_backoff(errors);
+                    errors += 1;
+
+                    warn!("receiving the next events in {delay:?}");
+
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the backoff after the consecutive errors, doubling [`ERROR_BACKOFF`] up to
+/// [`RECONNECT_DELAY`].
+fn error_backoff(errors: u32) -> Duration {
+    ERROR_BACKOFF
+        .saturating_mul(2u32.saturating_pow(errors))
+        .min(RECONNECT_DELAY)
+}
+
+/// Shuts down the device after an error of the handler, returning the error.
+async fn stop(device: &AstarteDeviceSdk, err: Error) -> Result<(), Error> {
+    error!("the handler failed, shutting down: {err}");
+
+    if let Err(shutdown) = device.shutdown(SHUTDOWN_DEADLINE).await {
+        warn!("couldn't shut down the device: {shutdown}");
+    }
+
+    Err(err)
+}
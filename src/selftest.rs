@@ -0,0 +1,433 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Non-destructive checks of the readiness of a device, for the manufacturing and field service
+//! tools.
+//!
+//! [`self_test()`](crate::AstarteDeviceSdk::self_test) runs each [`SelfTestCheck`] and returns a
+//! [`SelfTestReport`] with the result of each one. A check that can't run, like the store check
+//! of a device without a database, is skipped and doesn't fail the report.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{options::AstarteOptions, AstarteDeviceSdk};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let sdk_options = AstarteOptions::new("_","_","_","_");
+//!     let device = AstarteDeviceSdk::new(sdk_options).await.unwrap();
+//!
+//!     let report = device.self_test().await;
+//!     for result in &report.checks {
+//!         println!("{}: {:?} {}", result.check, result.status, result.message);
+//!     }
+//!
+//!     assert!(report.passed());
+//! }
+//! ```
+
+use std::fmt::Display;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use x509_cert::der::Decode;
+
+use crate::database::AstarteDatabase;
+use crate::interfaces::Interfaces;
+use crate::types::AstarteType;
+
+/// Interface of the property written and removed by the store check.
+const PROBE_INTERFACE: &str = "io.astarte.sdk.SelfTest";
+/// Path of the property written and removed by the store check.
+const PROBE_PATH: &str = "/probe";
+
+/// Timeout of the connection to the broker.
+pub const BROKER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Timestamp before which the clock is considered not set, 2023-01-01T00:00:00Z.
+const MIN_CLOCK: i64 = 1_672_531_200;
+
+/// A check of the self test.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SelfTestCheck {
+    /// A property can be written, read and removed from the store.
+    Store,
+    /// The client certificate is valid now.
+    Certificate,
+    /// The broker accepts TCP connections.
+    Broker,
+    /// The clock is set and within the validity of the certificate.
+    Clock,
+    /// The interfaces of the device are valid.
+    Interfaces,
+}
+
+impl Display for SelfTestCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let check = match self {
+            SelfTestCheck::Store => "store",
+            SelfTestCheck::Certificate => "certificate",
+            SelfTestCheck::Broker => "broker",
+            SelfTestCheck::Clock => "clock",
+            SelfTestCheck::Interfaces => "interfaces",
+        };
+
+        write!(f, "{check}")
+    }
+}
+
+/// Outcome of a check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Passed,
+    Failed,
+    /// The check couldn't run, like the store check without a database.
+    Skipped,
+}
+
+/// Result of a check, with a message describing the outcome.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    pub check: SelfTestCheck,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl CheckResult {
+    fn passed(check: SelfTestCheck, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: CheckStatus::Passed,
+            message: message.into(),
+        }
+    }
+
+    fn failed(check: SelfTestCheck, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: CheckStatus::Failed,
+            message: message.into(),
+        }
+    }
+
+    fn skipped(check: SelfTestCheck, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: CheckStatus::Skipped,
+            message: message.into(),
+        }
+    }
+}
+
+/// Results of the checks of the self test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// Time the self test started.
+    pub timestamp: DateTime<Utc>,
+    pub checks: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    /// Checks that none of the checks failed, the skipped ones are ignored.
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Returns the checks that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks
+            .iter()
+            .filter(|result| result.status == CheckStatus::Failed)
+    }
+
+    /// Returns the result of a check.
+    pub fn get(&self, check: SelfTestCheck) -> Option<&CheckResult> {
+        self.checks.iter().find(|result| result.check == check)
+    }
+}
+
+/// Validity of the client certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CertificateValidity {
+    pub(crate) not_before: DateTime<Utc>,
+    pub(crate) not_after: DateTime<Utc>,
+}
+
+impl CertificateValidity {
+    /// Reads the validity of a DER encoded certificate.
+    pub(crate) fn from_der(certificate: &[u8]) -> Option<Self> {
+        let certificate = match x509_cert::Certificate::from_der(certificate) {
+            Ok(certificate) => certificate,
+            Err(err) => {
+                warn!("couldn't read the validity of the certificate: {err}");
+
+                return None;
+            }
+        };
+
+        let validity = certificate.tbs_certificate.validity;
+
+        Some(Self {
+            not_before: validity.not_before.to_system_time().into(),
+            not_after: validity.not_after.to_system_time().into(),
+        })
+    }
+}
+
+/// Broker and certificate used by the device to connect, kept for the self test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ConnectionInfo {
+    pub(crate) broker: (String, u16),
+    pub(crate) certificate: Option<CertificateValidity>,
+}
+
+/// Writes, reads and removes a property.
+pub(crate) async fn check_store<S>(database: Option<&S>) -> CheckResult
+where
+    S: AstarteDatabase + Sync + Send + ?Sized,
+{
+    let check = SelfTestCheck::Store;
+
+    let Some(database) = database else {
+        return CheckResult::skipped(check, "the device has no database");
+    };
+
+    let value = AstarteType::LongInteger(Utc::now().timestamp_millis());
+
+    if let Err(err) = database
+        .store_prop(PROBE_INTERFACE, PROBE_PATH, &value, 0)
+        .await
+    {
+        return CheckResult::failed(check, format!("couldn't write the property: {err}"));
+    }
+
+    let read = database.load_prop(PROBE_INTERFACE, PROBE_PATH, 0).await;
+
+    // the probe is removed even if it couldn't be read
+    let deleted = database.delete_prop(PROBE_INTERFACE, PROBE_PATH).await;
+
+    match (read, deleted) {
+        (Err(err), _) => CheckResult::failed(check, format!("couldn't read the property: {err}")),
+        (Ok(read), _) if read.as_ref() != Some(&value) => {
+            CheckResult::failed(check, "the property read is different from the one written")
+        }
+        (Ok(_), Err(err)) => {
+            CheckResult::failed(check, format!("couldn't remove the property: {err}"))
+        }
+        (Ok(_), Ok(())) => CheckResult::passed(check, "the property was written, read and removed"),
+    }
+}
+
+/// Checks that the certificate is valid now.
+pub(crate) fn check_certificate(
+    connection: Option<&ConnectionInfo>,
+    now: DateTime<Utc>,
+) -> CheckResult {
+    let check = SelfTestCheck::Certificate;
+
+    let Some(validity) = connection.and_then(|connection| connection.certificate) else {
+        return CheckResult::skipped(check, "the validity of the certificate is unknown");
+    };
+
+    if now < validity.not_before {
+        CheckResult::failed(
+            check,
+            format!("the certificate is valid from {}", validity.not_before),
+        )
+    } else if now > validity.not_after {
+        CheckResult::failed(
+            check,
+            format!("the certificate expired on {}", validity.not_after),
+        )
+    } else {
+        CheckResult::passed(
+            check,
+            format!("the certificate is valid until {}", validity.not_after),
+        )
+    }
+}
+
+/// Opens and closes a TCP connection to the broker.
+pub(crate) async fn check_broker(connection: Option<&ConnectionInfo>) -> CheckResult {
+    let check = SelfTestCheck::Broker;
+
+    let Some((host, port)) = connection.map(|connection| &connection.broker) else {
+        return CheckResult::skipped(check, "the broker is unknown");
+    };
+
+    let connect = tokio::net::TcpStream::connect((host.as_str(), *port));
+
+    match tokio::time::timeout(BROKER_TIMEOUT, connect).await {
+        Ok(Ok(_)) => CheckResult::passed(check, format!("{host}:{port} is reachable")),
+        Ok(Err(err)) => {
+            CheckResult::failed(check, format!("couldn't connect to {host}:{port}: {err}"))
+        }
+        Err(_) => CheckResult::failed(
+            check,
+            format!("the connection to {host}:{port} timed out after {BROKER_TIMEOUT:?}"),
+        ),
+    }
+}
+
+/// Checks that the clock is set and within the validity of the certificate.
+pub(crate) fn check_clock(connection: Option<&ConnectionInfo>, now: DateTime<Utc>) -> CheckResult {
+    let check = SelfTestCheck::Clock;
+
+    // compared as timestamps, so there is no conversion that could fail
+    if now.timestamp() < MIN_CLOCK {
+        return CheckResult::failed(check, format!("the clock isn't set, it's {now}"));
+    }
+
+    // the certificate is issued by the server, so it starts before the clock if it's right
+    match connection.and_then(|connection| connection.certificate) {
+        Some(validity) if now < validity.not_before => CheckResult::failed(
+            check,
+            format!(
+                "the clock is {now}, before the certificate issued on {}",
+                validity.not_before
+            ),
+        ),
+        _ => CheckResult::passed(check, format!("the clock is {now}")),
+    }
+}
+
+/// Validates the interfaces of the device.
+pub(crate) fn check_interfaces(interfaces: &Interfaces) -> CheckResult {
+    let check = SelfTestCheck::Interfaces;
+
+    let mut count = 0;
+    let mut invalid = Vec::new();
+    for interface in interfaces.iter_interfaces() {
+        count += 1;
+
+        if let Err(err) = interface.validate() {
+            invalid.push(format!("{}: {err}", interface.interface_name()));
+        }
+    }
+
+    if count == 0 {
+        CheckResult::failed(check, "the device has no interfaces")
+    } else if !invalid.is_empty() {
+        CheckResult::failed(check, format!("invalid interfaces {}", invalid.join(", ")))
+    } else {
+        CheckResult::passed(check, format!("{count} interfaces are valid"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chrono::TimeZone;
+
+    use crate::database::AstarteSqliteDatabase;
+    use crate::Interface;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_store() {
+        let result = check_store::<AstarteSqliteDatabase>(None).await;
+        assert_eq!(result.status, CheckStatus::Skipped);
+
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let result = check_store(Some(&db)).await;
+        assert_eq!(result.status, CheckStatus::Passed, "{}", result.message);
+
+        // the probe is removed
+        assert!(db.load_all_props().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_check_certificate_and_clock() {
+        let now = Utc::now();
+        let connection = |not_before, not_after| ConnectionInfo {
+            broker: ("localhost".to_string(), 8883),
+            certificate: Some(CertificateValidity {
+                not_before,
+                not_after,
+            }),
+        };
+        let day = chrono::Duration::days(1);
+
+        let valid = connection(now - day, now + day);
+        assert_eq!(
+            check_certificate(Some(&valid), now).status,
+            CheckStatus::Passed
+        );
+        assert_eq!(check_clock(Some(&valid), now).status, CheckStatus::Passed);
+
+        let expired = connection(now - day * 2, now - day);
+        assert_eq!(
+            check_certificate(Some(&expired), now).status,
+            CheckStatus::Failed
+        );
+
+        // the clock is behind the issuer of the certificate
+        let future = connection(now + day, now + day * 2);
+        assert_eq!(
+            check_certificate(Some(&future), now).status,
+            CheckStatus::Failed
+        );
+        assert_eq!(check_clock(Some(&future), now).status, CheckStatus::Failed);
+
+        let unset = Utc.timestamp_opt(0, 0).unwrap();
+        assert_eq!(check_clock(None, unset).status, CheckStatus::Failed);
+        assert_eq!(check_certificate(None, now).status, CheckStatus::Skipped);
+    }
+
+    #[test]
+    fn test_check_interfaces() {
+        let interfaces = Interfaces::new();
+        assert_eq!(check_interfaces(&interfaces).status, CheckStatus::Failed);
+
+        let interface = Interface::from_str(include_str!("../examples/individual_datastream/interfaces/org.astarte-platform.rust.examples.individual-datastream.ServerDatastream.json")).unwrap();
+        let interfaces = Interfaces::from([interface]).unwrap();
+        assert_eq!(check_interfaces(&interfaces).status, CheckStatus::Passed);
+    }
+
+    #[test]
+    fn test_report() {
+        let report = SelfTestReport {
+            timestamp: Utc::now(),
+            checks: vec![
+                CheckResult::passed(SelfTestCheck::Clock, "ok"),
+                CheckResult::skipped(SelfTestCheck::Store, "no database"),
+            ],
+        };
+        assert!(report.passed());
+
+        let mut report = report;
+        report
+            .checks
+            .push(CheckResult::failed(SelfTestCheck::Broker, "unreachable"));
+        assert!(!report.passed());
+        assert_eq!(
+            report
+                .failures()
+                .map(|result| result.check)
+                .collect::<Vec<_>>(),
+            [SelfTestCheck::Broker]
+        );
+        assert_eq!(
+            report.get(SelfTestCheck::Store).map(|result| result.status),
+            Some(CheckStatus::Skipped)
+        );
+    }
+}
@@ -0,0 +1,107 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sequence numbers attached to the objects sent on an interface.
+//!
+//! The sequence number is incremented for each object and stored in the database before the
+//! object is published, so it's never reused after a restart. The consumers of the data can
+//! detect the lost or reordered messages from the gaps in the sequence, see
+//! [`AstarteOptions::sequence_field`](crate::options::AstarteOptions::sequence_field).
+
+use std::collections::HashMap;
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::{database::AstarteDatabase, Error};
+
+/// Sequence of an interface.
+#[derive(Debug)]
+pub(crate) struct Sequence {
+    /// Field of the object with the sequence number.
+    pub(crate) field: String,
+    /// Last sequence number sent, `None` until it's loaded from the database.
+    last: Mutex<Option<i64>>,
+}
+
+impl Sequence {
+    pub(crate) fn new(field: String) -> Self {
+        Self {
+            field,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Returns the next sequence number, after storing it in the database.
+    ///
+    /// The returned guard must be held until the object is published, so the objects are
+    /// published in the order of their sequence numbers.
+    pub(crate) async fn next<S>(
+        &self,
+        interface: &str,
+        database: Option<&S>,
+    ) -> Result<(MutexGuard<'_, Option<i64>>, i64), Error>
+    where
+        S: AstarteDatabase + Sync + Send + ?Sized,
+    {
+        let mut last = self.last.lock().await;
+
+        let current = match (*last, database) {
+            (Some(last), _) => last,
+            (None, Some(database)) => database.load_sequence(interface).await?.unwrap_or(0),
+            (None, None) => 0,
+        };
+
+        let next = current + 1;
+
+        if let Some(database) = database {
+            database.store_sequence(interface, next).await?;
+        }
+
+        *last = Some(next);
+
+        Ok((last, next))
+    }
+
+    /// Returns the last sequence number sent.
+    pub(crate) async fn last(&self) -> Option<i64> {
+        *self.last.lock().await
+    }
+}
+
+/// Sequences of the interfaces, configured in the options.
+#[derive(Debug, Default)]
+pub(crate) struct Sequences {
+    interfaces: HashMap<String, Sequence>,
+}
+
+impl Sequences {
+    pub(crate) fn get(&self, interface: &str) -> Option<&Sequence> {
+        self.interfaces.get(interface)
+    }
+}
+
+impl FromIterator<(String, String)> for Sequences {
+    fn from_iter<T: IntoIterator<Item = (String, String)>>(iter: T) -> Self {
+        Self {
+            interfaces: iter
+                .into_iter()
+                .map(|(interface, field)| (interface, Sequence::new(field)))
+                .collect(),
+        }
+    }
+}
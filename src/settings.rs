@@ -0,0 +1,94 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Property interfaces loaded into structs, with a field for each mapping.
+//!
+//! The [`AstarteProperties`] trait can be derived with `feature = ["derive"]`. Each field is the
+//! value of a mapping, on the endpoint `/<field>` or on the one set with
+//! `#[mapping(endpoint = "...")]`. An `Option` field is `None` if the property is unset, the
+//! other fields must be stored unless they have a `#[mapping(default)]`.
+//!
+//! [`load()`](AstarteProperties::load) reads all the stored values of the interface, while
+//! [`store()`](AstarteProperties::store) sends only the values that changed from the stored ones,
+//! unsetting the `None` fields.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{AstarteDeviceSdk, AstarteProperties};
+//! #[cfg(not(feature = "derive"))]
+//! use astarte_device_sdk_derive::AstarteProperties;
+//!
+//! #[derive(AstarteProperties)]
+//! #[astarte_properties(interface = "com.example.Settings", rename_all = "camelCase")]
+//! struct Settings {
+//!     sample_rate: i32,
+//!     #[mapping(endpoint = "/display/name")]
+//!     name: Option<String>,
+//!     #[mapping(default = "1.0")]
+//!     gain: f64,
+//! }
+//!
+//! async fn configure(device: &AstarteDeviceSdk) {
+//!     let mut settings = Settings::load(device).await.unwrap();
+//!
+//!     settings.sample_rate *= 2;
+//!
+//!     // only /sampleRate is sent
+//!     settings.store(device).await.unwrap();
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::database::AstarteDatabase;
+use crate::error::Error;
+use crate::event::FromEventError;
+use crate::types::AstarteType;
+use crate::AstarteDeviceSdk;
+
+/// Struct with the values of the mappings of a property interface.
+#[async_trait]
+pub trait AstarteProperties: Sized + Send + Sync {
+    /// Name of the property interface.
+    const INTERFACE: &'static str;
+
+    /// Converts the values stored by path.
+    fn from_properties(values: HashMap<String, AstarteType>) -> Result<Self, FromEventError>;
+
+    /// Returns the values by path, `None` for the unset properties.
+    fn to_properties(&self) -> Result<Vec<(String, Option<AstarteType>)>, Error>;
+
+    /// Loads the stored values, see
+    /// [`load_properties()`](crate::AstarteDeviceSdk::load_properties).
+    async fn load<S>(device: &AstarteDeviceSdk<S>) -> Result<Self, Error>
+    where
+        S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+    {
+        device.load_properties().await
+    }
+
+    /// Sends the values changed, see
+    /// [`store_properties()`](crate::AstarteDeviceSdk::store_properties).
+    async fn store<S>(&self, device: &AstarteDeviceSdk<S>) -> Result<usize, Error>
+    where
+        S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+    {
+        device.store_properties(self).await
+    }
+}
@@ -0,0 +1,407 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Graceful shutdown of the device when a signal is received.
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::time::Duration;
+
+use futures::future::{BoxFuture, Shared};
+use futures::FutureExt;
+use log::{debug, info, trace, warn};
+use rumqttc::Event;
+
+use crate::database::AstarteDatabase;
+use crate::error::Error;
+use crate::{AstarteDeviceDataEvent, AstarteDeviceSdk, DeviceStatus};
+
+/// Future that triggers the shutdown, with the deadline to flush the pending messages.
+#[derive(Clone)]
+pub(crate) struct ShutdownSignal {
+    pub(crate) signal: Shared<BoxFuture<'static, ()>>,
+    pub(crate) deadline: Duration,
+}
+
+impl ShutdownSignal {
+    pub(crate) fn new<F>(signal: F, deadline: Duration) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Self {
+            signal: signal.boxed().shared(),
+            deadline,
+        }
+    }
+}
+
+impl Debug for ShutdownSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShutdownSignal")
+            .field("deadline", &self.deadline)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Waits for the future, returns the deadline instead if the signal is received first.
+pub(crate) async fn until_signal<F>(
+    signal: Option<ShutdownSignal>,
+    fut: F,
+) -> Result<F::Output, Duration>
+where
+    F: Future,
+{
+    let Some(ShutdownSignal { signal, deadline }) = signal else {
+        return Ok(fut.await);
+    };
+
+    tokio::select! {
+        biased;
+        _ = signal => Err(deadline),
+        out = fut => Ok(out),
+    }
+}
+
+impl<S> AstarteDeviceSdk<S>
+where
+    S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+{
+    /// Shuts down the device after the [shutdown signal](crate::options::AstarteOptions::shutdown_signal) was
+    /// received.
+    pub(crate) async fn shutdown_on_signal(
+        &mut self,
+        deadline: std::time::Duration,
+    ) -> Result<AstarteDeviceDataEvent, Error> {
+        info!("shutdown signal received");
+
+        self.shutdown(deadline).await?;
+
+        Err(Error::Terminated("the device was shut down".to_string()))
+    }
+
+    /// Shut down the device, publishing the messages in the volatile retention and closing the
+    /// connection within the deadline.
+    ///
+    /// It can be called while another task is waiting in
+    /// [`handle_events()`](AstarteDeviceSdk::handle_events), which stops polling the event loop
+    /// and returns an [`Error::Terminated`]. The messages still pending when the deadline expires
+    /// are lost and an [`Error::ShutdownDeadline`] is returned. After the shutdown the status of
+    /// the device is [`DeviceStatus::Stopped`] and
+    /// [`handle_events()`](AstarteDeviceSdk::handle_events) will always return an
+    /// [`Error::Terminated`].
+    pub async fn shutdown(&self, deadline: std::time::Duration) -> Result<(), Error> {
+        info!("shutting down, flushing the pending messages");
+
+        self.shutdown_requested.send_replace(true);
+
+        let flushed = tokio::time::timeout(deadline, self.flush()).await;
+
+        self.status.send_replace(DeviceStatus::Stopped);
+
+        match flushed {
+            Ok(res) => res,
+            Err(_) => {
+                warn!("couldn't flush the pending messages in {deadline:?}");
+
+                Err(Error::ShutdownDeadline(deadline))
+            }
+        }
+    }
+
+    /// Waits until a [`shutdown()`](AstarteDeviceSdk::shutdown) is called from another task.
+    pub(crate) async fn shutdown_requested(&self) {
+        // the senders are owned by the device, so the receivers can't be closed
+        let _ = self
+            .shutdown_requested
+            .subscribe()
+            .wait_for(|requested| *requested)
+            .await;
+    }
+
+    /// Waits until the shutdown flushed the event loop and stopped the device.
+    pub(crate) async fn stopped(&self) {
+        let _ = self
+            .status
+            .subscribe()
+            .wait_for(|status| *status != DeviceStatus::Running)
+            .await;
+    }
+
+    /// Publishes the volatile retention and disconnects, polling the event loop until the
+    /// disconnection is sent so all the previous messages are sent too.
+    async fn flush(&self) -> Result<(), Error> {
+        let mut eventloop = self.eventloop.lock().await;
+
+        let requests = async {
+            self.send_volatile().await?;
+            self.client.disconnect().await?;
+
+            Ok::<(), Error>(())
+        };
+
+        let drain = async {
+            loop {
+                let event = eventloop.poll().await?;
+
+                self.deliveries.handle(&event);
+
+                match event {
+                    Event::Outgoing(rumqttc::Outgoing::Disconnect) => {
+                        debug!("disconnected");
+
+                        return Ok::<(), Error>(());
+                    }
+                    event => trace!("MQTT event while flushing = {event:?}"),
+                }
+            }
+        };
+
+        tokio::try_join!(requests, drain).map(|_| ())
+    }
+}
+
+/// Waits for a SIGTERM or SIGINT.
+///
+/// The handlers are installed when the future is first polled, if they can't be installed the
+/// future never completes.
+#[cfg(feature = "signals")]
+pub(crate) async fn terminate() {
+    use log::error;
+
+    let interrupt = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            error!("couldn't listen for SIGINT: {err}");
+
+            futures::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let terminate = async {
+            match signal(SignalKind::terminate()) {
+                Ok(mut terminate) => {
+                    terminate.recv().await;
+                }
+                Err(err) => {
+                    error!("couldn't listen for SIGTERM: {err}");
+
+                    futures::future::pending::<()>().await;
+                }
+            }
+        };
+
+        tokio::select! {
+            _ = interrupt => {}
+            _ = terminate => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    interrupt.await;
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use mockall::predicate;
+    use rumqttc::Event;
+
+    use crate::error::Error;
+    use crate::message::MessageId;
+    use crate::mock::{MockAsyncClient, MockDevice, MockEventLoop};
+    use crate::retention::VolatileItem;
+    use crate::transport::TransportOptions;
+    use crate::DeviceStatus;
+
+    #[tokio::test]
+    async fn test_shutdown_signal() {
+        let mut client = MockAsyncClient::default();
+        let mut eventloop = MockEventLoop::default();
+        let mut seq = mockall::Sequence::new();
+
+        client
+            .expect_publish::<String, Vec<u8>>()
+            .once()
+            .in_sequence(&mut seq)
+            .with(
+                predicate::eq("realm/device_id/com.test/value".to_string()),
+                predicate::always(),
+                predicate::always(),
+                predicate::always(),
+            )
+            .returning(|_, _, _, _| Ok(()));
+        client
+            .expect_disconnect()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|| Ok(()));
+
+        eventloop
+            .expect_poll()
+            .once()
+            .returning(|| Ok(Event::Outgoing(rumqttc::Outgoing::Publish(1))));
+        eventloop
+            .expect_poll()
+            .once()
+            .returning(|| Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)));
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let mut astarte = MockDevice::new(client, eventloop)
+            .interfaces([])
+            .options(|opts| {
+                opts.shutdown_signal(
+                    async move {
+                        let _ = rx.await;
+                    },
+                    Duration::from_secs(5),
+                )
+            })
+            .build();
+
+        astarte.volatile.lock().await.push(VolatileItem::new(
+            MessageId::new(),
+            "com.test",
+            "/value",
+            "realm/device_id/com.test/value".to_string(),
+            rumqttc::QoS::AtLeastOnce,
+            Vec::new(),
+            0,
+        ));
+
+        tx.send(()).unwrap();
+
+        for _ in 0..2 {
+            let err = astarte
+                .handle_events()
+                .await
+                .expect_err("handled events after the shutdown");
+            assert!(matches!(err, Error::Terminated(_)), "{err:?}");
+        }
+
+        assert_eq!(*astarte.status().borrow(), DeviceStatus::Stopped);
+        assert!(astarte.volatile.lock().await.drain().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_signal_idle() {
+        let mut client = MockAsyncClient::default();
+        client.expect_disconnect().once().returning(|| Ok(()));
+
+        let mut eventloop = MockEventLoop::default();
+        eventloop
+            .expect_poll()
+            .once()
+            .returning(|| Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)));
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let mut astarte = MockDevice::new(client, eventloop)
+            .interfaces([])
+            .options(|opts| {
+                opts.idle_disconnect(Duration::from_secs(60), None)
+                    .shutdown_signal(
+                        async move {
+                            let _ = rx.await;
+                        },
+                        Duration::from_secs(5),
+                    )
+            })
+            .build();
+
+        // disconnected without a wakeup interval, waits until a message is sent
+        astarte.idle.as_ref().unwrap().disconnected();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            tx.send(()).unwrap();
+        });
+
+        let res = tokio::time::timeout(Duration::from_secs(5), astarte.handle_events())
+            .await
+            .expect("the signal wasn't handled while idle");
+        assert!(matches!(res, Err(Error::Terminated(_))), "{res:?}");
+        assert_eq!(*astarte.status().borrow(), DeviceStatus::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_while_polling() {
+        let mut client = MockAsyncClient::default();
+        client.expect_disconnect().once().returning(|| Ok(()));
+        client
+            .expect_clone()
+            .once()
+            .returning(MockAsyncClient::default);
+
+        let mut eventloop = MockEventLoop::default();
+        eventloop.expect_reconfigure().once().return_const(());
+        eventloop
+            .expect_poll()
+            .once()
+            .returning(|| Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)));
+
+        let astarte = MockDevice::new(client, eventloop).interfaces([]).build();
+
+        // the polling task holds the event loop while it waits the reconnect delay
+        astarte
+            .reconfigure(TransportOptions::new().reconnect_delay(Duration::from_secs(3600)))
+            .unwrap();
+        astarte.transport.disconnected();
+
+        let mut device = astarte.clone();
+        let polling = tokio::spawn(async move { device.handle_events().await });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            astarte.shutdown(Duration::from_secs(60)),
+        )
+        .await
+        .expect("the shutdown waited the polling task")
+        .unwrap();
+
+        let res = polling.await.unwrap();
+        assert!(matches!(res, Err(Error::Terminated(_))), "{res:?}");
+        assert_eq!(*astarte.status().borrow(), DeviceStatus::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_deadline() {
+        let astarte = MockDevice::new(MockAsyncClient::default(), MockEventLoop::default())
+            .interfaces([])
+            .build();
+
+        // the event loop can't be flushed while it's locked
+        let _eventloop = astarte.eventloop.lock().await;
+
+        let deadline = Duration::from_millis(10);
+        let err = astarte
+            .shutdown(deadline)
+            .await
+            .expect_err("flushed a locked event loop");
+        assert!(
+            matches!(err, Error::ShutdownDeadline(elapsed) if elapsed == deadline),
+            "{err:?}"
+        );
+        assert_eq!(*astarte.status().borrow(), DeviceStatus::Stopped);
+    }
+}
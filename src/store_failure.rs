@@ -0,0 +1,251 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Failed writes of the properties received from the server.
+//!
+//! The hook is called on each failed write, then the [`StoreFailurePolicy`] decides if the
+//! event is delivered anyway, the write is retried or the error is returned.
+
+use std::future::Future;
+
+use log::warn;
+
+use crate::error::Error;
+use crate::options::{StoreFailure, StoreFailureHook, StoreFailurePolicy};
+
+/// Policy and hook applied on the failed writes of the server properties.
+pub(crate) struct StoreFailures {
+    policy: StoreFailurePolicy,
+    hook: Option<StoreFailureHook>,
+}
+
+impl StoreFailures {
+    pub(crate) fn new(policy: StoreFailurePolicy, hook: Option<StoreFailureHook>) -> Self {
+        Self { policy, hook }
+    }
+
+    /// Writes a property received from the server, applying the policy if the write fails.
+    pub(crate) async fn store<F, Fut>(
+        &self,
+        interface: &str,
+        path: &str,
+        mut write: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(), Error>>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            let error = match write().await {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+
+            if let Some(hook) = &self.hook {
+                hook(&StoreFailure {
+                    interface,
+                    path,
+                    error: &error,
+                });
+            }
+
+            match self.policy {
+                StoreFailurePolicy::DeliverWithWarning => {
+                    warn!("couldn't store property {interface}{path}, delivering anyway: {error}");
+
+                    return Ok(());
+                }
+                StoreFailurePolicy::Retry { attempts, backoff } if attempt < attempts => {
+                    let delay = backoff * 2u32.saturating_pow(attempt);
+                    attempt += 1;
+
+                    warn!(
+                        "couldn't store property {interface}{path}, retrying in {delay:?}: {error}"
+                    );
+
+                    tokio::time::sleep(delay).await;
+                }
+                StoreFailurePolicy::Fail | StoreFailurePolicy::Retry { .. } => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use rumqttc::Event;
+
+    use crate::database::faulty::{Fault, FaultyStore, StoreOperation};
+    use crate::database::AstarteSqliteDatabase;
+    use crate::error::Error;
+    use crate::mock::{MockAsyncClient, MockDevice, MockEventLoop};
+    use crate::options::StoreFailurePolicy;
+    use crate::test::SERVER_PROPERTIES;
+    use crate::types::AstarteType;
+    use crate::{Aggregation, Interface};
+
+    async fn mock_store_failure(failures: usize) -> (MockDevice, Arc<AtomicUsize>) {
+        let mut eventloope = MockEventLoop::default();
+
+        let data = bson::doc! { "v": true };
+        eventloope.expect_poll().returning(move || {
+            Ok(Event::Incoming(rumqttc::Packet::Publish(
+                rumqttc::Publish::new(
+                    "realm/device_id/org.astarte-platform.rust.examples.individual-properties.ServerProperties/1/enable",
+                    rumqttc::QoS::AtLeastOnce,
+                    bson::to_vec(&data).unwrap()
+                ),
+            )))
+        });
+
+        let db = FaultyStore::new(AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap());
+        db.inject(StoreOperation::StoreProp, Fault::full().times(failures));
+
+        let hook_calls = Arc::new(AtomicUsize::new(0));
+        let calls = Arc::clone(&hook_calls);
+
+        let device = MockDevice::new(MockAsyncClient::default(), eventloope)
+            .interfaces([Interface::from_str(SERVER_PROPERTIES).unwrap()])
+            .options(|opts| {
+                opts.database(db).on_store_failure(move |failure| {
+                    assert!(matches!(failure.error, Error::StoreFull { .. }));
+                    assert_eq!(failure.path, "/1/enable");
+
+                    calls.fetch_add(1, Ordering::SeqCst);
+                })
+            });
+
+        (device, hook_calls)
+    }
+
+    #[tokio::test]
+    async fn test_store_failure_fail() {
+        let (device, hook_calls) = mock_store_failure(1).await;
+        let mut astarte = device
+            .options(|opts| opts.store_failure_policy(StoreFailurePolicy::Fail))
+            .build();
+
+        let res = astarte.handle_events().await;
+
+        assert!(matches!(res, Err(Error::StoreFull { .. })), "{res:?}");
+        assert_eq!(hook_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_store_failure_deliver_with_warning() {
+        let (device, hook_calls) = mock_store_failure(1).await;
+        let mut astarte = device
+            .options(|opts| opts.store_failure_policy(StoreFailurePolicy::DeliverWithWarning))
+            .build();
+
+        let event = astarte.handle_events().await.unwrap();
+
+        assert_eq!(
+            event.data,
+            Aggregation::Individual(AstarteType::Boolean(true))
+        );
+        assert_eq!(hook_calls.load(Ordering::SeqCst), 1);
+
+        let stored = astarte
+            .get_property(
+                "org.astarte-platform.rust.examples.individual-properties.ServerProperties",
+                "/1/enable",
+            )
+            .await
+            .unwrap();
+        assert_eq!(stored, None);
+    }
+
+    #[tokio::test]
+    async fn test_store_failure_retry() {
+        let policy = StoreFailurePolicy::Retry {
+            attempts: 2,
+            backoff: std::time::Duration::from_millis(1),
+        };
+
+        let (device, hook_calls) = mock_store_failure(2).await;
+        let mut astarte = device
+            .options(|opts| opts.store_failure_policy(policy))
+            .build();
+
+        let event = astarte.handle_events().await.unwrap();
+
+        assert_eq!(
+            event.data,
+            Aggregation::Individual(AstarteType::Boolean(true))
+        );
+        assert_eq!(hook_calls.load(Ordering::SeqCst), 2);
+
+        let stored = astarte
+            .get_property(
+                "org.astarte-platform.rust.examples.individual-properties.ServerProperties",
+                "/1/enable",
+            )
+            .await
+            .unwrap();
+        assert_eq!(stored, Some(AstarteType::Boolean(true)));
+
+        // fails after all the attempts
+        let (device, hook_calls) = mock_store_failure(3).await;
+        let mut astarte = device
+            .options(|opts| opts.store_failure_policy(policy))
+            .build();
+
+        assert!(astarte.handle_events().await.is_err());
+        assert_eq!(hook_calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_store_failure_retry_interfaces_writer() {
+        let policy = StoreFailurePolicy::Retry {
+            attempts: 1,
+            backoff: std::time::Duration::from_millis(50),
+        };
+
+        let (device, _) = mock_store_failure(1).await;
+        let mut astarte = device
+            .options(|opts| opts.store_failure_policy(policy))
+            .build();
+        let interfaces = Arc::clone(&astarte.interfaces);
+
+        // the writer is queued while the store is retried
+        let writer = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+            drop(interfaces.write().await);
+        });
+
+        let event =
+            tokio::time::timeout(std::time::Duration::from_secs(5), astarte.handle_events())
+                .await
+                .expect("the interfaces lock is held while retrying")
+                .unwrap();
+        assert_eq!(
+            event.data,
+            Aggregation::Individual(AstarteType::Boolean(true))
+        );
+
+        writer.await.unwrap();
+    }
+}
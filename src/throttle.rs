@@ -0,0 +1,449 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rate limit of the messages received on an interface, protecting the device from a
+//! misconfigured server, like a trigger loop setting a property thousands of times per second.
+//!
+//! The messages over a [`ReceiveLimit`] are handled with its [`ExcessPolicy`], before being
+//! decoded and stored:
+//!
+//! - [`ExcessPolicy::Coalesce`] holds the last message of each path, a newer one replaces it, and
+//!   handles them once the rate allows it. The last value of each property is always applied.
+//! - [`ExcessPolicy::Drop`] discards the messages, for the datastreams where only the recent
+//!   values matter.
+//!
+//! A warning is logged when an interface goes over the limit, and the updates coalesced and
+//! dropped are counted in the [`ThrottleStats`] returned by
+//! [`AstarteDeviceSdk::receive_limit_stats`](crate::AstarteDeviceSdk::receive_limit_stats).
+//!
+//! ```no_run
+//! use astarte_device_sdk::{
+//!     options::AstarteOptions,
+//!     throttle::{ExcessPolicy, ReceiveLimit},
+//! };
+//!
+//! let sdk_options = AstarteOptions::new("_","_","_","_")
+//!     .receive_rate_limit("com.example.Config", ReceiveLimit::new(5.0, 20))
+//!     .receive_rate_limit(
+//!         "com.example.Commands",
+//!         ReceiveLimit::new(10.0, 10).policy(ExcessPolicy::Drop),
+//!     );
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+use log::{info, warn};
+use rumqttc::Publish;
+
+use crate::database::AstarteDatabase;
+use crate::handle::RateLimit;
+use crate::AstarteDeviceSdk;
+
+/// Maximum number of paths held for each interface, the messages on other paths are dropped.
+pub const MAX_HELD_PATHS: usize = 1024;
+
+/// What happens to the messages received over a [`ReceiveLimit`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExcessPolicy {
+    /// The last message of each path is held and handled once the rate allows it.
+    #[default]
+    Coalesce,
+    /// The messages are discarded.
+    Drop,
+}
+
+impl Display for ExcessPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExcessPolicy::Coalesce => write!(f, "coalescing"),
+            ExcessPolicy::Drop => write!(f, "dropping"),
+        }
+    }
+}
+
+/// Messages per second received on an interface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReceiveLimit {
+    rate: f64,
+    burst: u32,
+    policy: ExcessPolicy,
+}
+
+impl ReceiveLimit {
+    /// Creates a limit of messages per second, allowing bursts of the given size, coalescing the
+    /// excess messages.
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self {
+            rate,
+            burst,
+            policy: ExcessPolicy::default(),
+        }
+    }
+
+    /// Sets what happens to the messages over the limit.
+    pub fn policy(mut self, policy: ExcessPolicy) -> Self {
+        self.policy = policy;
+
+        self
+    }
+}
+
+/// Messages received over the [`ReceiveLimit`] of an interface.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThrottleStats {
+    /// Messages replaced by a newer one on the same path, before being handled.
+    pub coalesced: u64,
+    /// Messages discarded.
+    pub dropped: u64,
+    /// Messages held, waiting to be handled.
+    pub held: usize,
+    /// The interface is over the limit.
+    pub limited: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Last message of each path over the limit, in order of arrival.
+    held: Vec<Publish>,
+    coalesced: u64,
+    dropped: u64,
+    limited: bool,
+}
+
+#[derive(Debug)]
+struct Throttle {
+    policy: ExcessPolicy,
+    bucket: RateLimit,
+    state: Mutex<State>,
+}
+
+/// Receive limits of the interfaces.
+#[derive(Debug, Default)]
+pub(crate) struct ReceiveLimits {
+    limits: HashMap<String, Throttle>,
+}
+
+impl ReceiveLimits {
+    pub(crate) fn new(limits: HashMap<String, ReceiveLimit>) -> Self {
+        let limits = limits
+            .into_iter()
+            .map(|(interface, limit)| {
+                let throttle = Throttle {
+                    policy: limit.policy,
+                    bucket: RateLimit::new(limit.rate, limit.burst),
+                    state: Mutex::new(State::default()),
+                };
+
+                (interface, throttle)
+            })
+            .collect();
+
+        Self { limits }
+    }
+
+    /// Checks if a message can be handled now, otherwise it's held or dropped.
+    pub(crate) fn admit(&self, interface: &str, publish: &Publish) -> bool {
+        self.admit_at(interface, publish, Instant::now())
+    }
+
+    fn admit_at(&self, interface: &str, publish: &Publish, now: Instant) -> bool {
+        let Some(throttle) = self.limits.get(interface) else {
+            return true;
+        };
+
+        let mut state = throttle
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        // a newer message replaces the held one, so the older is never applied after it
+        if let Some(held) = state
+            .held
+            .iter_mut()
+            .find(|held| held.topic == publish.topic)
+        {
+            *held = publish.clone();
+            state.coalesced += 1;
+
+            return false;
+        }
+
+        if throttle.bucket.acquire_at(now) {
+            if state.limited && state.held.is_empty() {
+                info!(
+                    "receive rate of {interface} back under the limit, {} coalesced and {} dropped messages",
+                    state.coalesced, state.dropped
+                );
+
+                state.limited = false;
+            }
+
+            return true;
+        }
+
+        if !state.limited {
+            warn!(
+                "receive rate of {interface} over the limit, {} the excess messages",
+                throttle.policy
+            );
+
+            state.limited = true;
+        }
+
+        match throttle.policy {
+            ExcessPolicy::Coalesce if state.held.len() < MAX_HELD_PATHS => {
+                state.held.push(publish.clone());
+            }
+            ExcessPolicy::Coalesce | ExcessPolicy::Drop => {
+                state.dropped += 1;
+            }
+        }
+
+        false
+    }
+
+    /// Returns the first held message the rate allows to handle.
+    pub(crate) fn take_due(&self) -> Option<Publish> {
+        self.take_due_at(Instant::now())
+    }
+
+    fn take_due_at(&self, now: Instant) -> Option<Publish> {
+        self.limits.values().find_map(|throttle| {
+            let mut state = throttle
+                .state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+
+            if state.held.is_empty() || !throttle.bucket.acquire_at(now) {
+                return None;
+            }
+
+            Some(state.held.remove(0))
+        })
+    }
+
+    /// Returns when the next held message can be handled.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.limits
+            .values()
+            .filter(|throttle| {
+                !throttle
+                    .state
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .held
+                    .is_empty()
+            })
+            .filter_map(|throttle| throttle.bucket.next_token_at())
+            .min()
+    }
+
+    /// Returns the statistics of an interface, if it has a limit.
+    pub(crate) fn stats(&self, interface: &str) -> Option<ThrottleStats> {
+        let throttle = self.limits.get(interface)?;
+        let state = throttle
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        Some(ThrottleStats {
+            coalesced: state.coalesced,
+            dropped: state.dropped,
+            held: state.held.len(),
+            limited: state.limited,
+        })
+    }
+}
+
+impl<S> AstarteDeviceSdk<S>
+where
+    S: AstarteDatabase + Sync + Send + ?Sized + 'static,
+{
+    /// Returns the messages held, coalesced and dropped by the
+    /// [receive rate limit](crate::throttle) of an interface, `None` if it has no limit.
+    pub fn receive_limit_stats(&self, interface_name: &str) -> Option<ThrottleStats> {
+        self.receive_limits.stats(interface_name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use rumqttc::{Event, QoS};
+
+    use super::*;
+    use crate::database::AstarteSqliteDatabase;
+    use crate::mock::{MockAsyncClient, MockDevice, MockEventLoop};
+    use crate::test::{DEVICE_PROPERTIES_NAME, SERVER_PROPERTIES, SERVER_PROPERTIES_NAME};
+    use crate::types::AstarteType;
+    use crate::{Aggregation, Interface};
+
+    const INTERFACE: &str = "com.example.Config";
+
+    fn publish(path: &str, value: u8) -> Publish {
+        Publish::new(
+            format!("realm/device_id/{INTERFACE}{path}"),
+            QoS::ExactlyOnce,
+            vec![value],
+        )
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let limits = ReceiveLimits::new(HashMap::from([(
+            INTERFACE.to_string(),
+            ReceiveLimit::new(1.0, 1),
+        )]));
+        let start = Instant::now();
+
+        assert!(limits.admit_at("com.example.Other", &publish("/a", 0), start));
+        assert!(limits.admit_at(INTERFACE, &publish("/a", 0), start));
+        assert!(limits.deadline().is_none());
+
+        // only the last message of each path is kept
+        assert!(!limits.admit_at(INTERFACE, &publish("/a", 1), start));
+        assert!(!limits.admit_at(INTERFACE, &publish("/b", 1), start));
+        assert!(!limits.admit_at(INTERFACE, &publish("/a", 2), start));
+        assert_eq!(
+            limits.stats(INTERFACE),
+            Some(ThrottleStats {
+                coalesced: 1,
+                dropped: 0,
+                held: 2,
+                limited: true,
+            })
+        );
+        assert!(limits.stats("com.example.Other").is_none());
+
+        assert!(limits.take_due_at(start).is_none());
+        assert!(limits.deadline().is_some());
+
+        let later = start + Duration::from_secs(1);
+        let held = limits.take_due_at(later).unwrap();
+        assert_eq!(held.topic, publish("/a", 2).topic);
+        assert_eq!(held.payload.as_ref(), [2]);
+        assert!(limits.take_due_at(later).is_none());
+
+        // a new message on a held path replaces it, even with the rate available
+        let much_later = later + Duration::from_secs(5);
+        assert!(!limits.admit_at(INTERFACE, &publish("/b", 3), much_later));
+
+        let held = limits.take_due_at(much_later).unwrap();
+        assert_eq!(held.payload.as_ref(), [3]);
+        assert!(limits.deadline().is_none());
+
+        assert!(limits.admit_at(
+            INTERFACE,
+            &publish("/b", 4),
+            much_later + Duration::from_secs(1)
+        ));
+        assert!(!limits.stats(INTERFACE).unwrap().limited);
+    }
+
+    #[test]
+    fn test_drop() {
+        let limits = ReceiveLimits::new(HashMap::from([(
+            INTERFACE.to_string(),
+            ReceiveLimit::new(1.0, 2).policy(ExcessPolicy::Drop),
+        )]));
+        let start = Instant::now();
+
+        assert!(limits.admit_at(INTERFACE, &publish("/a", 0), start));
+        assert!(limits.admit_at(INTERFACE, &publish("/a", 1), start));
+        assert!(!limits.admit_at(INTERFACE, &publish("/a", 2), start));
+        assert!(!limits.admit_at(INTERFACE, &publish("/b", 2), start));
+
+        let stats = limits.stats(INTERFACE).unwrap();
+        assert_eq!(stats.dropped, 2);
+        assert_eq!(stats.held, 0);
+        assert!(stats.limited);
+        assert!(limits.take_due_at(start + Duration::from_secs(1)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_receive_rate_limit() {
+        let publish = |value: bool| {
+            Event::Incoming(rumqttc::Packet::Publish(rumqttc::Publish::new(
+                format!("realm/device_id/{SERVER_PROPERTIES_NAME}/1/enable"),
+                rumqttc::QoS::ExactlyOnce,
+                bson::to_vec(&bson::doc! { "v": value }).unwrap(),
+            )))
+        };
+
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let mut astarte = MockDevice::new(MockAsyncClient::default(), MockEventLoop::default())
+            .interfaces([Interface::from_str(SERVER_PROPERTIES).unwrap()])
+            .options(|opts| {
+                opts.database(db)
+                    .receive_rate_limit(SERVER_PROPERTIES_NAME, ReceiveLimit::new(10.0, 1))
+            })
+            .build();
+
+        let received = astarte.handle_event(publish(true)).await.unwrap();
+        assert!(received.is_some());
+
+        // the excess messages are not stored
+        for value in [false, true, false] {
+            let received = astarte.handle_event(publish(value)).await.unwrap();
+            assert!(received.is_none());
+        }
+        let stored = astarte
+            .get_property(SERVER_PROPERTIES_NAME, "/1/enable")
+            .await
+            .unwrap();
+        assert_eq!(stored, Some(AstarteType::Boolean(true)));
+        assert_eq!(
+            astarte.receive_limit_stats(SERVER_PROPERTIES_NAME),
+            Some(ThrottleStats {
+                coalesced: 2,
+                dropped: 0,
+                held: 1,
+                limited: true,
+            })
+        );
+
+        // the last value is handled without polling once the rate allows it
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        let event = astarte.handle_events().await.unwrap();
+        assert_eq!(
+            event.data,
+            Aggregation::Individual(AstarteType::Boolean(false))
+        );
+        let stored = astarte
+            .get_property(SERVER_PROPERTIES_NAME, "/1/enable")
+            .await
+            .unwrap();
+        assert_eq!(stored, Some(AstarteType::Boolean(false)));
+        assert_eq!(
+            astarte
+                .receive_limit_stats(SERVER_PROPERTIES_NAME)
+                .unwrap()
+                .held,
+            0
+        );
+        assert!(astarte
+            .receive_limit_stats(DEVICE_PROPERTIES_NAME)
+            .is_none());
+    }
+}
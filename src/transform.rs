@@ -0,0 +1,303 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Transformations of the values received from Astarte.
+//!
+//! A transform is registered on the mappings of an interface to decode the values before they
+//! are delivered, for example to convert a scaled integer into a float or a string into an enum
+//! value. The steps of a transform are applied in the order they are added.
+//!
+//! The values are transformed after the [constraints](crate::constraint) are checked and the
+//! properties are stored, so the store keeps the values sent by Astarte. A value that can't be
+//! transformed is not delivered and [`handle_events()`](crate::AstarteDeviceSdk::handle_events)
+//! returns an [`Error::Transform`](crate::Error::Transform).
+//!
+//! The fields of an object are transformed on the path of the object followed by the field name.
+//! Unsetting a property is never transformed.
+//!
+//! ```no_run
+//! use astarte_device_sdk::{
+//!     options::AstarteOptions, transform::ValueTransform, types::AstarteType,
+//! };
+//!
+//! // tenths of degree
+//! let temperature = ValueTransform::new("com.example.ServerSetpoints", "/*/temperature")
+//!     .unwrap()
+//!     .scale(0.1, 0.0);
+//!
+//! let mode = ValueTransform::new("com.example.ServerSetpoints", "/*/mode")
+//!     .unwrap()
+//!     .enumeration([
+//!         ("off", AstarteType::Integer(0)),
+//!         ("heat", AstarteType::Integer(1)),
+//!         ("cool", AstarteType::Integer(2)),
+//!     ]);
+//!
+//! let sdk_options = AstarteOptions::new("_","_","_","_")
+//!     .value_transform(temperature)
+//!     .value_transform(mode);
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::{
+    constraint::Violation,
+    filter::{FilterError, PathGlob},
+    types::AstarteType,
+    Aggregation,
+};
+
+type Step = Arc<dyn Fn(AstarteType) -> Result<AstarteType, String> + Send + Sync>;
+
+/// Transform of the values received on the mappings of an interface.
+#[derive(Clone)]
+pub struct ValueTransform {
+    interface: String,
+    path: PathGlob,
+    steps: Vec<Step>,
+}
+
+impl ValueTransform {
+    /// Create a transform on the paths matching the glob, see [`PathGlob`].
+    ///
+    /// Without any step the values are delivered unchanged.
+    pub fn new(interface: &str, path: &str) -> Result<Self, FilterError> {
+        Ok(Self {
+            interface: interface.to_string(),
+            path: PathGlob::new(path)?,
+            steps: Vec::new(),
+        })
+    }
+
+    /// Convert a numeric value, or an array of them, into a double multiplied by the factor
+    /// and increased by the offset.
+    pub fn scale(self, factor: f64, offset: f64) -> Self {
+        self.map(move |value| scale(value, factor, offset))
+    }
+
+    /// Convert a string into the value of the matching variant, rejecting the unknown strings.
+    pub fn enumeration<I, S>(self, variants: I) -> Self
+    where
+        I: IntoIterator<Item = (S, AstarteType)>,
+        S: Into<String>,
+    {
+        let variants: HashMap<String, AstarteType> = variants
+            .into_iter()
+            .map(|(name, value)| (name.into(), value))
+            .collect();
+
+        self.map(move |value| match value {
+            AstarteType::String(name) => variants
+                .get(&name)
+                .cloned()
+                .ok_or_else(|| format!("unknown variant {name}")),
+            _ => Err("the value is not a string".to_string()),
+        })
+    }
+
+    /// Convert the value with a custom function, the error is the reason reported for the
+    /// failure.
+    pub fn map<F>(mut self, step: F) -> Self
+    where
+        F: Fn(AstarteType) -> Result<AstarteType, String> + Send + Sync + 'static,
+    {
+        self.steps.push(Arc::new(step));
+
+        self
+    }
+
+    fn apply(&self, value: AstarteType) -> Result<AstarteType, String> {
+        self.steps.iter().try_fold(value, |value, step| step(value))
+    }
+}
+
+impl Debug for ValueTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ValueTransform")
+            .field("interface", &self.interface)
+            .field("path", &self.path)
+            .field("steps", &self.steps.len())
+            .finish()
+    }
+}
+
+fn scale(value: AstarteType, factor: f64, offset: f64) -> Result<AstarteType, String> {
+    let scale = |v: f64| v * factor + offset;
+
+    let value = match value {
+        AstarteType::Double(v) => AstarteType::Double(scale(v)),
+        AstarteType::Integer(v) => AstarteType::Double(scale(f64::from(v))),
+        AstarteType::LongInteger(v) => AstarteType::Double(scale(v as f64)),
+        AstarteType::DoubleArray(v) => AstarteType::DoubleArray(v.into_iter().map(scale).collect()),
+        AstarteType::IntegerArray(v) => {
+            AstarteType::DoubleArray(v.into_iter().map(|v| scale(f64::from(v))).collect())
+        }
+        AstarteType::LongIntegerArray(v) => {
+            AstarteType::DoubleArray(v.into_iter().map(|v| scale(v as f64)).collect())
+        }
+        _ => return Err("the value is not numeric".to_string()),
+    };
+
+    Ok(value)
+}
+
+/// Transforms configured on the device.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ValueTransforms {
+    transforms: Vec<ValueTransform>,
+}
+
+impl ValueTransforms {
+    pub(crate) fn push(&mut self, transform: ValueTransform) {
+        self.transforms.push(transform);
+    }
+
+    fn apply_value(
+        &self,
+        interface: &str,
+        path: &str,
+        value: AstarteType,
+    ) -> Result<AstarteType, String> {
+        self.transforms
+            .iter()
+            .filter(|t| t.interface == interface && t.path.matches(path))
+            .try_fold(value, |value, t| t.apply(value))
+    }
+
+    /// Apply to the data received on the path all the matching transforms.
+    pub(crate) fn apply(
+        &self,
+        interface: &str,
+        path: &str,
+        data: Aggregation,
+    ) -> Result<Aggregation, Violation> {
+        let violation = |path: String| move |reason| Violation { path, reason };
+
+        match data {
+            Aggregation::Individual(value) => self
+                .apply_value(interface, path, value)
+                .map(Aggregation::Individual)
+                .map_err(violation(path.to_string())),
+            Aggregation::Object(fields) => fields
+                .into_iter()
+                .map(|(name, value)| {
+                    let field_path = format!("{path}/{name}");
+
+                    self.apply_value(interface, &field_path, value)
+                        .map(|value| (name, value))
+                        .map_err(violation(field_path))
+                })
+                .collect::<Result<_, _>>()
+                .map(Aggregation::Object),
+            Aggregation::Unset => Ok(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scale() {
+        assert_eq!(
+            scale(AstarteType::Integer(215), 0.1, 0.0),
+            Ok(AstarteType::Double(21.5))
+        );
+        assert_eq!(
+            scale(AstarteType::LongIntegerArray(vec![0, 10]), 2.0, 1.0),
+            Ok(AstarteType::DoubleArray(vec![1.0, 21.0]))
+        );
+        assert!(scale(AstarteType::Boolean(true), 1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_value_transforms() {
+        let mut transforms = ValueTransforms::default();
+
+        transforms.push(
+            ValueTransform::new("com.test", "/*/temperature")
+                .unwrap()
+                .scale(0.5, 0.0)
+                .map(|value| match value {
+                    AstarteType::Double(v) => Ok(AstarteType::Double(v.round())),
+                    _ => Err("not a double".to_string()),
+                }),
+        );
+        transforms.push(
+            ValueTransform::new("com.test", "/*/mode")
+                .unwrap()
+                .enumeration([
+                    ("off", AstarteType::Integer(0)),
+                    ("on", AstarteType::Integer(1)),
+                ]),
+        );
+
+        let individual = |v| Aggregation::Individual(v);
+
+        assert_eq!(
+            transforms.apply(
+                "com.test",
+                "/1/temperature",
+                individual(AstarteType::Integer(43))
+            ),
+            Ok(individual(AstarteType::Double(22.0)))
+        );
+        assert_eq!(
+            transforms.apply(
+                "com.test",
+                "/1/mode",
+                individual(AstarteType::String("on".to_string()))
+            ),
+            Ok(individual(AstarteType::Integer(1)))
+        );
+        assert_eq!(
+            transforms.apply(
+                "com.test",
+                "/1/mode",
+                individual(AstarteType::String("auto".to_string()))
+            ),
+            Err(Violation {
+                path: "/1/mode".to_string(),
+                reason: "unknown variant auto".to_string()
+            })
+        );
+        assert_eq!(
+            transforms.apply("com.test", "/1/mode", Aggregation::Unset),
+            Ok(Aggregation::Unset)
+        );
+        assert_eq!(
+            transforms.apply("com.other", "/1/mode", individual(AstarteType::Integer(3))),
+            Ok(individual(AstarteType::Integer(3)))
+        );
+
+        let object = Aggregation::Object(HashMap::from([
+            ("temperature".to_string(), AstarteType::Integer(40)),
+            ("other".to_string(), AstarteType::Integer(40)),
+        ]));
+        assert_eq!(
+            transforms.apply("com.test", "/1", object),
+            Ok(Aggregation::Object(HashMap::from([
+                ("temperature".to_string(), AstarteType::Double(20.0)),
+                ("other".to_string(), AstarteType::Integer(40)),
+            ])))
+        );
+    }
+}
@@ -0,0 +1,327 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Options of the MQTT transport that can be changed while the device is running.
+//!
+//! The options passed to [`reconfigure()`](crate::AstarteDeviceSdk::reconfigure) are applied
+//! when the device reconnects, after a connection error or when forced with
+//! [`reconnect()`](crate::AstarteDeviceSdk::reconnect). Only the options set are changed, the
+//! others keep their current value.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use astarte_device_sdk::{transport::TransportOptions, AstarteDeviceSdk};
+//!
+//! async fn tune(device: &AstarteDeviceSdk) {
+//!     let options = TransportOptions::new()
+//!         .keepalive(Duration::from_secs(300))
+//!         .reconnect_delay(Duration::from_secs(10));
+//!
+//!     device.reconfigure(options).unwrap();
+//!     device.reconnect().await.unwrap();
+//! }
+//! ```
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::options::OptionsError;
+
+/// Options of the MQTT transport, the ones not set are not changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransportOptions {
+    keepalive: Option<Duration>,
+    pending_throttle: Option<Duration>,
+    connection_timeout: Option<Duration>,
+    reconnect_delay: Option<Duration>,
+}
+
+impl TransportOptions {
+    /// Creates the options without changing any of them.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the keep alive of the connection, it must be at least 5 seconds.
+    pub fn keepalive(mut self, keepalive: Duration) -> Self {
+        self.keepalive = Some(keepalive);
+
+        self
+    }
+
+    /// Sets the delay between the messages sent again after a reconnection, to limit the rate
+    /// of the publishes when a lot of messages are pending.
+    pub fn pending_throttle(mut self, throttle: Duration) -> Self {
+        self.pending_throttle = Some(throttle);
+
+        self
+    }
+
+    /// Sets the timeout to connect to the broker, rounded to the seconds.
+    pub fn connection_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_timeout = Some(timeout);
+
+        self
+    }
+
+    /// Sets the delay before reconnecting after a connection error, [`Duration::ZERO`] to
+    /// reconnect right away.
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_delay = Some(delay);
+
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), OptionsError> {
+        if self
+            .keepalive
+            .map_or(false, |keepalive| keepalive.as_secs() < 5)
+        {
+            return Err(OptionsError::ConfigError(
+                "Keepalive should be >= 5 secs".into(),
+            ));
+        }
+
+        if self
+            .connection_timeout
+            .map_or(false, |timeout| timeout.as_secs() == 0)
+        {
+            return Err(OptionsError::ConfigError(
+                "Connection timeout should be >= 1 sec".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Overrides the options with the ones set in `other`.
+    pub(crate) fn merge(&mut self, other: TransportOptions) {
+        self.keepalive = other.keepalive.or(self.keepalive);
+        self.pending_throttle = other.pending_throttle.or(self.pending_throttle);
+        self.connection_timeout = other.connection_timeout.or(self.connection_timeout);
+        self.reconnect_delay = other.reconnect_delay.or(self.reconnect_delay);
+    }
+}
+
+/// Event loop whose options can be changed before the next connection.
+pub(crate) trait Reconfigure {
+    fn reconfigure(&mut self, options: &TransportOptions);
+}
+
+impl Reconfigure for rumqttc::EventLoop {
+    fn reconfigure(&mut self, options: &TransportOptions) {
+        if let Some(keepalive) = options.keepalive {
+            self.mqtt_options.set_keep_alive(keepalive);
+        }
+
+        if let Some(throttle) = options.pending_throttle {
+            self.mqtt_options.set_pending_throttle(throttle);
+        }
+
+        if let Some(timeout) = options.connection_timeout {
+            self.network_options
+                .set_connection_timeout(timeout.as_secs());
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Options to apply on the next connection.
+    pending: Option<TransportOptions>,
+    reconnect_delay: Option<Duration>,
+    /// The reconnection was requested, the connection errors are expected.
+    reconnecting: bool,
+    /// The connection was closed and not opened again yet.
+    disconnected: bool,
+    /// Instant the reconnect delay started, if the reconnection wasn't requested.
+    delayed_since: Option<Instant>,
+}
+
+/// State of the reconnections of the MQTT transport.
+#[derive(Debug, Default)]
+pub(crate) struct Transport {
+    inner: Mutex<Inner>,
+}
+
+impl Transport {
+    pub(crate) fn new(options: &TransportOptions) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                reconnect_delay: options.reconnect_delay,
+                ..Inner::default()
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // the state is always valid, since it's only assigned while locked
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Stores the options to apply on the next connection.
+    pub(crate) fn reconfigure(&self, options: TransportOptions) {
+        self.lock()
+            .pending
+            .get_or_insert_with(TransportOptions::default)
+            .merge(options);
+    }
+
+    /// Records the reconnection requested, returns `false` if the connection is already closed.
+    pub(crate) fn request_reconnect(&self) -> bool {
+        let mut inner = self.lock();
+
+        if inner.disconnected {
+            return false;
+        }
+
+        inner.reconnecting = true;
+
+        true
+    }
+
+    /// The reconnection couldn't be requested.
+    pub(crate) fn cancel_reconnect(&self) {
+        self.lock().reconnecting = false;
+    }
+
+    /// Records the connection closed, returns `true` if the reconnection was requested.
+    ///
+    /// The next connection is delayed by the reconnect delay, unless it was requested.
+    pub(crate) fn disconnected(&self) -> bool {
+        let mut inner = self.lock();
+
+        let requested = std::mem::take(&mut inner.reconnecting);
+
+        inner.disconnected = true;
+        inner.delayed_since = (!requested).then(Instant::now);
+
+        requested
+    }
+
+    /// Applies the pending options before connecting again, returns the instant to wait before
+    /// connecting.
+    pub(crate) fn reconnect<E: Reconfigure>(&self, eventloop: &mut E) -> Option<Instant> {
+        let mut inner = self.lock();
+
+        if !std::mem::take(&mut inner.disconnected) {
+            return None;
+        }
+
+        if let Some(options) = inner.pending.take() {
+            eventloop.reconfigure(&options);
+
+            if options.reconnect_delay.is_some() {
+                inner.reconnect_delay = options.reconnect_delay;
+            }
+        }
+
+        let since = inner.delayed_since.take()?;
+
+        inner
+            .reconnect_delay
+            .filter(|delay| !delay.is_zero())
+            .map(|delay| since + delay)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Default)]
+    struct Options(Vec<TransportOptions>);
+
+    impl Reconfigure for Options {
+        fn reconfigure(&mut self, options: &TransportOptions) {
+            self.0.push(*options);
+        }
+    }
+
+    #[test]
+    fn test_reconfigure_on_reconnect() {
+        let transport = Transport::new(&TransportOptions::new());
+        let mut applied = Options::default();
+
+        transport.reconfigure(TransportOptions::new().keepalive(Duration::from_secs(60)));
+        transport.reconfigure(
+            TransportOptions::new()
+                .keepalive(Duration::from_secs(90))
+                .pending_throttle(Duration::from_millis(10)),
+        );
+
+        // applied only when connecting again
+        assert_eq!(transport.reconnect(&mut applied), None);
+        assert!(applied.0.is_empty());
+
+        assert!(!transport.disconnected());
+        assert_eq!(transport.reconnect(&mut applied), None);
+        assert_eq!(
+            applied.0,
+            [TransportOptions::new()
+                .keepalive(Duration::from_secs(90))
+                .pending_throttle(Duration::from_millis(10))]
+        );
+
+        // only once
+        transport.disconnected();
+        transport.reconnect(&mut applied);
+        assert_eq!(applied.0.len(), 1);
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        let transport =
+            Transport::new(&TransportOptions::new().reconnect_delay(Duration::from_secs(5)));
+        let mut applied = Options::default();
+
+        transport.disconnected();
+        let reconnect_at = transport.reconnect(&mut applied).unwrap();
+        assert!(reconnect_at > Instant::now());
+
+        // the requested reconnections are not delayed
+        assert!(transport.request_reconnect());
+        assert!(transport.disconnected());
+        assert_eq!(transport.reconnect(&mut applied), None);
+
+        // the delay changed is applied to the next reconnection
+        transport.reconfigure(TransportOptions::new().reconnect_delay(Duration::ZERO));
+        transport.disconnected();
+        assert_eq!(transport.reconnect(&mut applied), None);
+
+        // nothing to reconnect while the connection is closed
+        transport.disconnected();
+        assert!(!transport.request_reconnect());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(TransportOptions::new().validate().is_ok());
+        assert!(TransportOptions::new()
+            .keepalive(Duration::from_secs(1))
+            .validate()
+            .is_err());
+        assert!(TransportOptions::new()
+            .connection_timeout(Duration::from_millis(500))
+            .validate()
+            .is_err());
+    }
+}
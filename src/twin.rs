@@ -0,0 +1,353 @@
+// This file is part of Astarte.
+//
+// Copyright 2023 SECO Mind Srl
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Local mirror of the server-owned properties.
+//!
+//! A [`Twin`] is created with [`AstarteDeviceSdk::twin`](crate::AstarteDeviceSdk::twin) from the
+//! values in the store, and it's kept up to date while the events are handled. The values can be
+//! read directly, converted into a struct or watched for changes.
+//!
+//! ```no_run
+//! use std::collections::HashMap;
+//!
+//! use astarte_device_sdk::{types::AstarteType, AstarteDeviceSdk};
+//!
+//! struct Config {
+//!     enable: bool,
+//! }
+//!
+//! impl TryFrom<HashMap<String, AstarteType>> for Config {
+//!     type Error = String;
+//!
+//!     fn try_from(mut props: HashMap<String, AstarteType>) -> Result<Self, Self::Error> {
+//!         let enable = props.remove("/enable").ok_or("missing /enable")?;
+//!         let enable = enable.try_into().map_err(|_| "invalid /enable")?;
+//!
+//!         Ok(Config { enable })
+//!     }
+//! }
+//!
+//! async fn config(device: &AstarteDeviceSdk) {
+//!     let twin = device.twin(&["com.test.Config"]).await.unwrap();
+//!
+//!     let config: Config = twin.read("com.test.Config").unwrap();
+//!     println!("enabled: {}", config.enable);
+//!
+//!     let mut enable = twin.watch("com.test.Config", "/enable");
+//!     while enable.changed().await.is_ok() {
+//!         println!("enable changed to {:?}", *enable.borrow());
+//!     }
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+
+use tokio::sync::watch;
+
+use crate::types::AstarteType;
+
+/// Value of a field, `None` if the property is unset.
+type Field = watch::Sender<Option<AstarteType>>;
+
+/// Always current view of a set of server-owned property interfaces.
+///
+/// The twin is updated by the SDK until all of its clones are dropped.
+#[derive(Debug, Clone)]
+pub struct Twin {
+    inner: Arc<TwinInner>,
+}
+
+#[derive(Debug)]
+pub(crate) struct TwinInner {
+    interfaces: HashSet<String>,
+    fields: Mutex<HashMap<(String, String), Field>>,
+}
+
+impl Twin {
+    pub(crate) fn new<I>(interfaces: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        Self {
+            inner: Arc::new(TwinInner {
+                interfaces: interfaces.into_iter().collect(),
+                fields: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Returns a weak reference used by the SDK to update the twin.
+    pub(crate) fn downgrade(&self) -> Weak<TwinInner> {
+        Arc::downgrade(&self.inner)
+    }
+
+    pub(crate) fn inner(&self) -> &TwinInner {
+        &self.inner
+    }
+
+    /// Returns the interfaces mirrored by the twin.
+    pub fn interfaces(&self) -> impl Iterator<Item = &str> {
+        self.inner.interfaces.iter().map(String::as_str)
+    }
+
+    /// Returns the current value of a property, or `None` if it's unset.
+    pub fn get(&self, interface: &str, path: &str) -> Option<AstarteType> {
+        self.inner
+            .lock()
+            .get(&(interface.to_string(), path.to_string()))
+            .and_then(|field| field.borrow().clone())
+    }
+
+    /// Returns the set properties of an interface, with the paths as keys.
+    pub fn snapshot(&self, interface: &str) -> HashMap<String, AstarteType> {
+        self.inner
+            .lock()
+            .iter()
+            .filter(|((iface, _), _)| iface == interface)
+            .filter_map(|((_, path), field)| {
+                field.borrow().clone().map(|value| (path.clone(), value))
+            })
+            .collect()
+    }
+
+    /// Converts the [`snapshot`](Twin::snapshot) of an interface into a struct.
+    pub fn read<T>(&self, interface: &str) -> Result<T, T::Error>
+    where
+        T: TryFrom<HashMap<String, AstarteType>>,
+    {
+        T::try_from(self.snapshot(interface))
+    }
+
+    /// Returns a receiver notified each time the value of a property changes.
+    ///
+    /// The property doesn't need to be set, the receiver will hold `None` until it is.
+    pub fn watch(&self, interface: &str, path: &str) -> watch::Receiver<Option<AstarteType>> {
+        self.inner
+            .lock()
+            .entry((interface.to_string(), path.to_string()))
+            .or_insert_with(|| watch::channel(None).0)
+            .subscribe()
+    }
+}
+
+impl TwinInner {
+    // the values are always valid, since the watch channels are only updated while locked
+    fn lock(&self) -> MutexGuard<'_, HashMap<(String, String), Field>> {
+        self.fields.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn mirrors(&self, interface: &str) -> bool {
+        self.interfaces.contains(interface)
+    }
+
+    /// Sets the value of a property, notifying the watchers only if it changed.
+    ///
+    /// A `None` value unsets the property.
+    pub(crate) fn update(&self, interface: &str, path: &str, value: Option<AstarteType>) {
+        if !self.mirrors(interface) {
+            return;
+        }
+
+        self.lock()
+            .entry((interface.to_string(), path.to_string()))
+            .or_insert_with(|| watch::channel(None).0)
+            .send_if_modified(|current| {
+                if *current == value {
+                    return false;
+                }
+
+                *current = value;
+
+                true
+            });
+    }
+
+    /// Unsets all the properties of an interface.
+    pub(crate) fn unset_interface(&self, interface: &str) {
+        self.lock()
+            .iter()
+            .filter(|((iface, _), _)| iface == interface)
+            .for_each(|(_, field)| {
+                field.send_if_modified(|current| current.take().is_some());
+            });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use rumqttc::Event;
+
+    use super::*;
+    use crate::database::{AstarteDatabase, AstarteSqliteDatabase};
+    use crate::mock::{MockAsyncClient, MockDevice, MockEventLoop};
+    use crate::test::{
+        DEVICE_PROPERTIES, DEVICE_PROPERTIES_NAME, SERVER_PROPERTIES, SERVER_PROPERTIES_NAME,
+    };
+    use crate::{Error, Interface};
+
+    #[test]
+    fn test_update_and_watch() {
+        let twin = Twin::new(["com.test.Config".to_string()]);
+        let inner = twin.inner();
+
+        let mut enable = twin.watch("com.test.Config", "/enable");
+        assert_eq!(*enable.borrow_and_update(), None);
+
+        inner.update(
+            "com.test.Config",
+            "/enable",
+            Some(AstarteType::Boolean(true)),
+        );
+        inner.update("com.test.Config", "/name", Some(AstarteType::from("dev")));
+        inner.update(
+            "com.test.Other",
+            "/enable",
+            Some(AstarteType::Boolean(true)),
+        );
+
+        assert!(enable.has_changed().unwrap());
+        assert_eq!(
+            *enable.borrow_and_update(),
+            Some(AstarteType::Boolean(true))
+        );
+        assert_eq!(
+            twin.get("com.test.Config", "/name"),
+            Some(AstarteType::from("dev"))
+        );
+        assert_eq!(twin.get("com.test.Other", "/enable"), None);
+
+        // same value, no notification
+        inner.update(
+            "com.test.Config",
+            "/enable",
+            Some(AstarteType::Boolean(true)),
+        );
+        assert!(!enable.has_changed().unwrap());
+
+        inner.update("com.test.Config", "/enable", None);
+        assert!(enable.has_changed().unwrap());
+        assert_eq!(*enable.borrow_and_update(), None);
+
+        inner.unset_interface("com.test.Config");
+        assert!(twin.snapshot("com.test.Config").is_empty());
+    }
+
+    #[test]
+    fn test_read() {
+        struct Config {
+            enable: bool,
+        }
+
+        impl TryFrom<HashMap<String, AstarteType>> for Config {
+            type Error = ();
+
+            fn try_from(mut props: HashMap<String, AstarteType>) -> Result<Self, Self::Error> {
+                let enable = props.remove("/enable").ok_or(())?;
+
+                Ok(Config {
+                    enable: enable.try_into().map_err(|_| ())?,
+                })
+            }
+        }
+
+        let twin = Twin::new(["com.test.Config".to_string()]);
+        let inner = twin.inner();
+
+        assert!(twin.read::<Config>("com.test.Config").is_err());
+
+        inner.update(
+            "com.test.Config",
+            "/enable",
+            Some(AstarteType::Boolean(true)),
+        );
+
+        let config: Config = twin.read("com.test.Config").unwrap();
+        assert!(config.enable);
+    }
+
+    #[tokio::test]
+    async fn test_twin() {
+        let mut eventloop = MockEventLoop::default();
+
+        eventloop.expect_poll().once().returning(|| {
+            Ok(Event::Incoming(rumqttc::Packet::Publish(
+                rumqttc::Publish::new(
+                    format!("realm/device_id/{SERVER_PROPERTIES_NAME}/1/enable"),
+                    rumqttc::QoS::AtLeastOnce,
+                    bson::to_vec(&bson::doc! { "v": false }).unwrap(),
+                ),
+            )))
+        });
+
+        let mut astarte = MockDevice::new(MockAsyncClient::default(), eventloop)
+            .interfaces([
+                Interface::from_str(DEVICE_PROPERTIES).unwrap(),
+                Interface::from_str(SERVER_PROPERTIES).unwrap(),
+            ])
+            .build();
+
+        let db = AstarteSqliteDatabase::new("sqlite::memory:").await.unwrap();
+        db.store_prop(
+            SERVER_PROPERTIES_NAME,
+            "/1/enable",
+            &AstarteType::Boolean(true),
+            0,
+        )
+        .await
+        .unwrap();
+        db.store_prop(
+            SERVER_PROPERTIES_NAME,
+            "/2/enable",
+            &AstarteType::Boolean(true),
+            1,
+        )
+        .await
+        .unwrap();
+        astarte.database = Some(Arc::new(db));
+
+        let err = astarte
+            .twin(&[DEVICE_PROPERTIES_NAME])
+            .await
+            .expect_err("device properties can't be mirrored");
+        assert!(matches!(err, Error::NotServerProperty(name) if name == DEVICE_PROPERTIES_NAME));
+
+        let twin = astarte.twin(&[SERVER_PROPERTIES_NAME]).await.unwrap();
+
+        // the value stored with another major version isn't loaded
+        assert_eq!(
+            twin.snapshot(SERVER_PROPERTIES_NAME),
+            HashMap::from([("/1/enable".to_string(), AstarteType::Boolean(true))])
+        );
+
+        let mut enable = twin.watch(SERVER_PROPERTIES_NAME, "/1/enable");
+        enable.borrow_and_update();
+
+        astarte.handle_events().await.unwrap();
+
+        assert!(enable.has_changed().unwrap());
+        assert_eq!(*enable.borrow(), Some(AstarteType::Boolean(false)));
+
+        drop(enable);
+        drop(twin);
+        astarte.update_twins(SERVER_PROPERTIES_NAME, "/1/enable", None);
+        assert!(astarte.twins.lock().unwrap().is_empty());
+    }
+}
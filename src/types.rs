@@ -371,16 +371,20 @@ impl TryFrom<Bson> for AstarteType {
         match d {
             Bson::Double(d) => Ok(AstarteType::Double(d)),
             Bson::String(d) => Ok(AstarteType::String(d)),
-            Bson::Array(arr) => match arr[0] {
-                Bson::Double(_) => from_bson_array!(arr, DoubleArray, Double, f64),
-                Bson::Boolean(_) => from_bson_array!(arr, BooleanArray, Boolean, bool),
-                Bson::Int32(_) => from_bson_array!(arr, IntegerArray, Int32, i32),
-                Bson::Int64(_) => from_bson_array!(arr, LongIntegerArray, Int64, i64),
-                Bson::DateTime(_) => {
+            Bson::Array(arr) => match arr.first() {
+                // the type of the elements can't be inferred, see try_from_bson_typed
+                None => Err(TypeError::FromBsonError(
+                    "Can't convert an empty array to astarte without the mapping type".to_string(),
+                )),
+                Some(Bson::Double(_)) => from_bson_array!(arr, DoubleArray, Double, f64),
+                Some(Bson::Boolean(_)) => from_bson_array!(arr, BooleanArray, Boolean, bool),
+                Some(Bson::Int32(_)) => from_bson_array!(arr, IntegerArray, Int32, i32),
+                Some(Bson::Int64(_)) => from_bson_array!(arr, LongIntegerArray, Int64, i64),
+                Some(Bson::DateTime(_)) => {
                     from_bson_array!(arr, DateTimeArray, DateTime, chrono::DateTime<chrono::Utc>)
                 }
-                Bson::String(_) => from_bson_array!(arr, StringArray, String, String),
-                Bson::Binary(_) => from_bson_array!(arr, BinaryBlobArray, Binary, Vec<u8>),
+                Some(Bson::String(_)) => from_bson_array!(arr, StringArray, String, String),
+                Some(Bson::Binary(_)) => from_bson_array!(arr, BinaryBlobArray, Binary, Vec<u8>),
                 _ => Err(TypeError::FromBsonError(format!(
                     "Can't convert array {arr:?} to astarte"
                 ))),
@@ -399,6 +403,42 @@ impl TryFrom<Bson> for AstarteType {
 }
 
 impl AstarteType {
+    /// Convert a bson value, using the type of the mapping for the empty arrays since the type
+    /// of their elements can't be inferred.
+    pub(crate) fn try_from_bson_typed(
+        d: Bson,
+        mapping_type: Option<MappingType>,
+    ) -> Result<Self, TypeError> {
+        match (d, mapping_type) {
+            (Bson::Array(arr), Some(mapping_type)) if arr.is_empty() => {
+                AstarteType::empty_array(mapping_type).ok_or(TypeError::FromBsonArrayError)
+            }
+            (d, _) => AstarteType::try_from(d),
+        }
+    }
+
+    /// Returns an empty array of the mapping type, [`None`] if the type is not an array.
+    fn empty_array(mapping_type: MappingType) -> Option<Self> {
+        let empty = match mapping_type {
+            MappingType::DoubleArray => AstarteType::DoubleArray(Vec::new()),
+            MappingType::IntegerArray => AstarteType::IntegerArray(Vec::new()),
+            MappingType::BooleanArray => AstarteType::BooleanArray(Vec::new()),
+            MappingType::LongIntegerArray => AstarteType::LongIntegerArray(Vec::new()),
+            MappingType::StringArray => AstarteType::StringArray(Vec::new()),
+            MappingType::BinaryBlobArray => AstarteType::BinaryBlobArray(Vec::new()),
+            MappingType::DateTimeArray => AstarteType::DateTimeArray(Vec::new()),
+            MappingType::Double
+            | MappingType::Integer
+            | MappingType::Boolean
+            | MappingType::LongInteger
+            | MappingType::String
+            | MappingType::BinaryBlob
+            | MappingType::DateTime => return None,
+        };
+
+        Some(empty)
+    }
+
     pub fn from_bson_vec(d: Vec<Bson>) -> Result<Vec<Self>, TypeError> {
         d.into_iter().map(AstarteType::try_from).collect()
     }